@@ -0,0 +1,89 @@
+//! Measures `future::Cache::get_or_insert_with` when many async tasks contend on
+//! a single missed key, and only one of them runs the init future.
+//!
+//! Run with `cargo bench --features future --bench future_single_flight`. The
+//! optional argument is the number of worker threads (default: 8).
+
+use moka::future::Cache;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+const NUM_TASKS: u64 = 2_000;
+const NUM_ROUNDS: u32 = 200;
+
+/// Counts the allocations, including the ones made by tokio to spawn the tasks.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Spawns `NUM_TASKS` tasks calling `get_or_insert_with` on the same key of an
+/// empty cache, and returns the elapsed time and the number of allocations.
+async fn run() -> (Duration, u64) {
+    let cache = Cache::new(100);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started_at = Instant::now();
+    let handles = (0..NUM_TASKS)
+        .map(|_| {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_insert_with(0u64, async {
+                        // Let the other tasks pile up on the key.
+                        tokio::task::yield_now().await;
+                        0u64
+                    })
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+    for h in handles {
+        h.await.unwrap();
+    }
+    (
+        started_at.elapsed(),
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+    )
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; take the first numeric argument.
+    let num_threads = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(8);
+    println!("worker threads: {}, tasks: {}", num_threads, NUM_TASKS);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(num_threads)
+        .build()
+        .unwrap();
+    let (mut elapsed, mut allocations) = (Duration::default(), 0);
+    for _ in 0..NUM_ROUNDS {
+        let (e, a) = runtime.block_on(run());
+        elapsed += e;
+        allocations += a;
+    }
+    println!(
+        "{:<20} {:>6} us/round, {:.1} allocations/task",
+        "get_or_insert_with",
+        (elapsed / NUM_ROUNDS).as_micros(),
+        allocations as f64 / (NUM_TASKS * NUM_ROUNDS as u64) as f64
+    );
+}
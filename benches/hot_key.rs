@@ -0,0 +1,71 @@
+//! Compares the read throughput of `Cache` on a single hot key with and without
+//! the access record throttle.
+//!
+//! Run with `cargo bench --bench hot_key`. The optional argument is the number
+//! of threads (default: 1).
+
+use moka::sync::{Cache, ConcurrentCacheExt};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAX_CAPACITY: u64 = 10_000;
+const NUM_READS: u32 = 1_000_000;
+const NUM_ROUNDS: u32 = 5;
+
+/// Reads the hot key `NUM_READS` times split over the threads, and returns the
+/// elapsed time per read.
+fn run(cache: &Cache<u64, u64>, num_threads: u32) -> Duration {
+    let started_at = Instant::now();
+    let handles = (0..num_threads)
+        .map(|_| {
+            let cache = cache.clone();
+            thread::spawn(move || {
+                for _ in 0..(NUM_READS / num_threads) {
+                    cache.get(&0);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for h in handles {
+        h.join().unwrap();
+    }
+    started_at.elapsed() / NUM_READS
+}
+
+fn bench(name: &str, make: impl Fn() -> Cache<u64, u64>, num_threads: u32) {
+    let mut get = Duration::default();
+    for _ in 0..NUM_ROUNDS {
+        let cache = make();
+        cache.insert(0, 0);
+        cache.sync();
+        get += run(&cache, num_threads);
+    }
+    println!(
+        "{:<20} get: {:>6} ns/op",
+        name,
+        (get / NUM_ROUNDS).as_nanos()
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; take the first numeric argument.
+    let num_threads = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(1);
+    println!("threads: {}", num_threads);
+
+    bench("no throttle", || Cache::new(MAX_CAPACITY), num_threads);
+    bench(
+        "throttle 100ms",
+        || {
+            Cache::builder()
+                .max_capacity(MAX_CAPACITY)
+                .access_record_throttle(Duration::from_millis(100))
+                .build()
+        },
+        num_threads,
+    );
+}
@@ -0,0 +1,67 @@
+//! Compares the read throughput of `Cache` with long string keys hashed on every
+//! `get`, and with the same keys wrapped in `PrecomputedHashKey`.
+//!
+//! Run with `cargo bench --bench precomputed_hash`.
+
+use moka::{
+    sync::{Cache, ConcurrentCacheExt},
+    PrecomputedHashKey,
+};
+use std::{
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+const NUM_KEYS: usize = 1_000;
+const KEY_LEN: usize = 1_024;
+const NUM_READS: u32 = 1_000_000;
+const NUM_ROUNDS: u32 = 5;
+
+fn keys() -> Vec<String> {
+    (0..NUM_KEYS)
+        .map(|i| format!("{:0>width$}", i, width = KEY_LEN))
+        .collect()
+}
+
+/// Inserts the keys, reads them `NUM_READS` times in total, and returns the
+/// elapsed time per read.
+fn run<K>(keys: &[K]) -> Duration
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    let cache = Cache::new(NUM_KEYS as u64);
+    for key in keys {
+        cache.insert(key.clone(), 0u64);
+    }
+    cache.sync();
+
+    let started_at = Instant::now();
+    for i in 0..NUM_READS as usize {
+        cache.get(&keys[i % NUM_KEYS]);
+    }
+    started_at.elapsed() / NUM_READS
+}
+
+fn bench<K>(name: &str, keys: &[K])
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    let get = (0..NUM_ROUNDS).map(|_| run(keys)).sum::<Duration>();
+    println!(
+        "{:<20} get: {:>6} ns/op",
+        name,
+        (get / NUM_ROUNDS).as_nanos()
+    );
+}
+
+fn main() {
+    println!("key length: {} bytes", KEY_LEN);
+
+    let keys = keys();
+    bench("String", &keys);
+    let keys = keys
+        .into_iter()
+        .map(PrecomputedHashKey::new)
+        .collect::<Vec<_>>();
+    bench("PrecomputedHashKey", &keys);
+}
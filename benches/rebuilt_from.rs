@@ -0,0 +1,61 @@
+//! Compares filling a new `Cache` with `Cache::rebuilt_from`, and with an
+//! `insert` loop followed by `sync`.
+//!
+//! Run with `cargo bench --bench rebuilt_from`.
+
+use moka::sync::{Cache, ConcurrentCacheExt};
+use std::time::{Duration, Instant};
+
+const MAX_CAPACITY: u64 = 100_000;
+const NUM_ENTRIES: u64 = 100_000;
+const NUM_ROUNDS: u32 = 5;
+
+fn snapshot() -> Vec<(u64, u64)> {
+    (0..NUM_ENTRIES).map(|i| (i, i)).collect()
+}
+
+/// Builds a cache from the snapshot by `rebuilt_from`, and returns the elapsed
+/// time per entry.
+fn run_rebuilt_from(template: &Cache<u64, u64>) -> Duration {
+    let entries = snapshot();
+    let started_at = Instant::now();
+    let cache = template.rebuilt_from(entries);
+    let elapsed = started_at.elapsed();
+    assert_eq!(cache.get(&(NUM_ENTRIES - 1)), Some(NUM_ENTRIES - 1));
+    elapsed / NUM_ENTRIES as u32
+}
+
+/// Builds a cache from the snapshot by inserting the entries one by one, and
+/// returns the elapsed time per entry.
+fn run_insert_loop() -> Duration {
+    let entries = snapshot();
+    let started_at = Instant::now();
+    let cache = Cache::new(MAX_CAPACITY);
+    for (key, value) in entries {
+        cache.insert(key, value);
+    }
+    cache.sync();
+    let elapsed = started_at.elapsed();
+    assert_eq!(cache.get(&(NUM_ENTRIES - 1)), Some(NUM_ENTRIES - 1));
+    elapsed / NUM_ENTRIES as u32
+}
+
+fn main() {
+    println!("entries: {}", NUM_ENTRIES);
+
+    let template = Cache::new(MAX_CAPACITY);
+    let rebuilt_from = (0..NUM_ROUNDS)
+        .map(|_| run_rebuilt_from(&template))
+        .sum::<Duration>();
+    let insert_loop = (0..NUM_ROUNDS).map(|_| run_insert_loop()).sum::<Duration>();
+    println!(
+        "{:<20} {:>6} ns/entry",
+        "rebuilt_from",
+        (rebuilt_from / NUM_ROUNDS).as_nanos()
+    );
+    println!(
+        "{:<20} {:>6} ns/entry",
+        "insert + sync",
+        (insert_loop / NUM_ROUNDS).as_nanos()
+    );
+}
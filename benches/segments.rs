@@ -0,0 +1,131 @@
+//! Compares the throughput of `Cache`, `SegmentedCache` with one segment and
+//! `SegmentedCache` with eight segments. Then compares `Cache` and
+//! `SegmentedCache` with 64 segments under 32 writer threads, where the
+//! segments are maintained in parallel.
+//!
+//! Run with `cargo bench --bench segments`. The optional argument is the number
+//! of threads for the first comparison (default: 1).
+
+use moka::sync::{Cache, ConcurrentCacheExt, SegmentedCache};
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAX_CAPACITY: u64 = 50_000;
+const NUM_KEYS: u64 = 100_000;
+const NUM_ROUNDS: usize = 5;
+const MANY_THREADS: u64 = 32;
+
+trait BenchCache: Clone + Send + Sync + 'static {
+    fn insert(&self, key: u64, value: u64);
+    fn get(&self, key: &u64) -> Option<u64>;
+    fn sync(&self);
+}
+
+impl BenchCache for Cache<u64, u64> {
+    fn insert(&self, key: u64, value: u64) {
+        Cache::insert(self, key, value)
+    }
+
+    fn get(&self, key: &u64) -> Option<u64> {
+        Cache::get(self, key)
+    }
+
+    fn sync(&self) {
+        ConcurrentCacheExt::sync(self)
+    }
+}
+
+impl BenchCache for SegmentedCache<u64, u64> {
+    fn insert(&self, key: u64, value: u64) {
+        SegmentedCache::insert(self, key, value)
+    }
+
+    fn get(&self, key: &u64) -> Option<u64> {
+        SegmentedCache::get(self, key)
+    }
+
+    fn sync(&self) {
+        ConcurrentCacheExt::sync(self)
+    }
+}
+
+/// Scatters the sequential indices over the key space, so that the gets hit and
+/// miss in a mixed order.
+fn key(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15) % NUM_KEYS
+}
+
+/// Runs `op` for `NUM_KEYS` keys split over the threads, and returns the
+/// elapsed time per operation.
+fn run<C: BenchCache>(cache: &C, num_threads: u64, op: fn(&C, u64)) -> Duration {
+    let op = Arc::new(op);
+    let started_at = Instant::now();
+    let handles = (0..num_threads)
+        .map(|t| {
+            let cache = cache.clone();
+            let op = Arc::clone(&op);
+            thread::spawn(move || {
+                let mut i = t;
+                while i < NUM_KEYS {
+                    op(&cache, key(i));
+                    i += num_threads;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for h in handles {
+        h.join().unwrap();
+    }
+    started_at.elapsed() / NUM_KEYS as u32
+}
+
+fn bench<C: BenchCache>(name: &str, make: impl Fn() -> C, num_threads: u64) {
+    let (mut insert, mut get) = (Duration::default(), Duration::default());
+    for _ in 0..NUM_ROUNDS {
+        let cache = make();
+        insert += run(&cache, num_threads, |c, k| c.insert(k, k));
+        cache.sync();
+        get += run(&cache, num_threads, |c, k| {
+            c.get(&k);
+        });
+    }
+    println!(
+        "{:<20} insert: {:>6} ns/op, get: {:>6} ns/op",
+        name,
+        (insert / NUM_ROUNDS as u32).as_nanos(),
+        (get / NUM_ROUNDS as u32).as_nanos()
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; take the first numeric argument.
+    let num_threads = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(1);
+    println!("threads: {}", num_threads);
+
+    bench("Cache", || Cache::new(MAX_CAPACITY), num_threads);
+    bench(
+        "SegmentedCache(1)",
+        || SegmentedCache::new(MAX_CAPACITY, 1),
+        num_threads,
+    );
+    bench(
+        "SegmentedCache(8)",
+        || SegmentedCache::new(MAX_CAPACITY, 8),
+        num_threads,
+    );
+
+    // With a single maintenance lock, the inserts wait for the maintenance.
+    println!("threads: {}", MANY_THREADS);
+    bench("Cache", || Cache::new(MAX_CAPACITY), MANY_THREADS);
+    bench(
+        "SegmentedCache(64)",
+        || SegmentedCache::new(MAX_CAPACITY, 64),
+        MANY_THREADS,
+    );
+}
@@ -2,8 +2,15 @@ use std::convert::TryInto;
 
 pub(crate) mod builder_utils;
 pub(crate) mod deque;
+pub(crate) mod entry;
 pub(crate) mod error;
 pub(crate) mod frequency_sketch;
+#[cfg(feature = "hyperloglog")]
+pub(crate) mod hyperloglog;
+pub(crate) mod jitter;
+pub(crate) mod precomputed_hash;
+pub(crate) mod stats;
+pub(crate) mod system_memory;
 pub(crate) mod thread_pool;
 pub(crate) mod unsafe_weak_pointer;
 
@@ -15,9 +22,23 @@ pub(crate) mod unsafe_weak_pointer;
 #[cfg_attr(not(feature = "atomic64"), path = "common/atomic_time_compat.rs")]
 pub(crate) mod atomic_time;
 
+#[cfg_attr(feature = "atomic64", path = "common/atomic_u64.rs")]
+#[cfg_attr(not(feature = "atomic64"), path = "common/atomic_u64_compat.rs")]
+pub(crate) mod atomic_u64;
+
 pub(crate) mod time;
 
 // Ensures the value fits in a range of `128u32..=u32::MAX`.
 pub(crate) fn sketch_capacity(max_capacity: u64) -> u32 {
     max_capacity.try_into().unwrap_or(u32::MAX).max(128)
 }
+
+// `usize::div_ceil` is not available in our MSRV.
+pub(crate) fn div_ceil(n: usize, divisor: usize) -> usize {
+    let q = n / divisor;
+    if q * divisor < n {
+        q + 1
+    } else {
+        q
+    }
+}
@@ -9,14 +9,14 @@ pub(crate) struct AtomicInstant {
 impl Default for AtomicInstant {
     fn default() -> Self {
         Self {
-            instant: AtomicU64::new(std::u64::MAX),
+            instant: AtomicU64::new(u64::MAX),
         }
     }
 }
 
 impl AtomicInstant {
     pub(crate) fn reset(&self) {
-        self.instant.store(std::u64::MAX, Ordering::Release);
+        self.instant.store(u64::MAX, Ordering::Release);
     }
 
     pub(crate) fn is_set(&self) -> bool {
@@ -28,7 +28,7 @@ impl AtomicInstant {
         if ts == u64::MAX {
             None
         } else {
-            Some(unsafe { std::mem::transmute(ts) })
+            Some(unsafe { std::mem::transmute::<u64, Instant>(ts) })
         }
     }
 
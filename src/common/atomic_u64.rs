@@ -0,0 +1 @@
+pub(crate) use std::sync::atomic::AtomicU64;
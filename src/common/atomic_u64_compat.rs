@@ -0,0 +1,63 @@
+use parking_lot::Mutex;
+use std::sync::atomic::Ordering;
+
+/// A substitute for `AtomicU64` on the platforms without it. It provides the
+/// subset of the `AtomicU64` methods used by this crate, and ignores the memory
+/// orderings as the lock orders the accesses.
+#[derive(Default)]
+pub(crate) struct AtomicU64 {
+    value: Mutex<u64>,
+}
+
+impl AtomicU64 {
+    pub(crate) fn new(v: u64) -> Self {
+        Self {
+            value: Mutex::new(v),
+        }
+    }
+
+    pub(crate) fn load(&self, _order: Ordering) -> u64 {
+        *self.value.lock()
+    }
+
+    pub(crate) fn store(&self, v: u64, _order: Ordering) {
+        *self.value.lock() = v;
+    }
+
+    pub(crate) fn fetch_add(&self, v: u64, _order: Ordering) -> u64 {
+        let mut value = self.value.lock();
+        let prev = *value;
+        *value = prev.wrapping_add(v);
+        prev
+    }
+
+    pub(crate) fn fetch_sub(&self, v: u64, _order: Ordering) -> u64 {
+        let mut value = self.value.lock();
+        let prev = *value;
+        *value = prev.wrapping_sub(v);
+        prev
+    }
+
+    pub(crate) fn fetch_max(&self, v: u64, _order: Ordering) -> u64 {
+        let mut value = self.value.lock();
+        let prev = *value;
+        *value = prev.max(v);
+        prev
+    }
+
+    pub(crate) fn compare_exchange_weak(
+        &self,
+        current: u64,
+        new: u64,
+        _success: Ordering,
+        _failure: Ordering,
+    ) -> Result<u64, u64> {
+        let mut value = self.value.lock();
+        if *value == current {
+            *value = new;
+            Ok(current)
+        } else {
+            Err(*value)
+        }
+    }
+}
@@ -1,16 +1,210 @@
+use super::system_memory;
+use crate::{defaults::TtlCapPolicy, sync::MaxAge, BuildError};
+
 use std::time::Duration;
 
 const YEAR_SECONDS: u64 = 365 * 24 * 3600;
 
+/// The longest `time_to_live` or `time_to_idle` allowed, to protect against
+/// overflow when computing key expiration.
+pub(crate) const MAX_EXPIRATION: Duration = Duration::from_secs(1_000 * YEAR_SECONDS);
+
 pub(crate) fn ensure_expirations_or_panic(
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
 ) {
-    let max_duration = Duration::from_secs(1_000 * YEAR_SECONDS);
     if let Some(d) = time_to_live {
-        assert!(d <= max_duration, "time_to_live is longer than 1000 years");
+        assert!(
+            d <= MAX_EXPIRATION,
+            "time_to_live is longer than 1000 years"
+        );
     }
     if let Some(d) = time_to_idle {
-        assert!(d <= max_duration, "time_to_idle is longer than 1000 years");
+        assert!(
+            d <= MAX_EXPIRATION,
+            "time_to_idle is longer than 1000 years"
+        );
+    }
+    ensure_time_to_idle(time_to_live, time_to_idle).unwrap_or_else(|e| panic!("{}", e));
+}
+
+/// Ensures that the time to idle is not longer than the time to live, as such a
+/// time to idle would never expire an entry. `time_to_live` is the one to build
+/// the cache with, i.e. after applying the process-level defaults.
+pub(crate) fn ensure_time_to_idle(
+    time_to_live: Option<Duration>,
+    time_to_idle: Option<Duration>,
+) -> Result<(), BuildError> {
+    match (time_to_live, time_to_idle) {
+        (Some(ttl), Some(tti)) if tti > ttl => Err(BuildError::TtiExceedsTtl),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the time to live to build the cache with, applying the
+/// `max_allowed_ttl` of the process-level defaults.
+pub(crate) fn time_to_live(
+    time_to_live: Option<Duration>,
+    max_allowed_ttl: Option<(Duration, TtlCapPolicy)>,
+) -> Result<Option<Duration>, BuildError> {
+    match max_allowed_ttl {
+        None => Ok(time_to_live),
+        Some((max, TtlCapPolicy::Clamp)) => Ok(Some(time_to_live.map_or(max, |d| d.min(max)))),
+        Some((max, TtlCapPolicy::Reject)) => match time_to_live {
+            Some(d) if d <= max => Ok(time_to_live),
+            _ => Err(BuildError::TimeToLiveNotAllowed),
+        },
+    }
+}
+
+/// Same as `time_to_live`, but panics instead of returning an error.
+pub(crate) fn time_to_live_or_panic(
+    time_to_live: Option<Duration>,
+    max_allowed_ttl: Option<(Duration, TtlCapPolicy)>,
+) -> Option<Duration> {
+    self::time_to_live(time_to_live, max_allowed_ttl).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Returns the max age to build the cache with.
+///
+/// # Panics
+///
+/// Panics if `max_age` is longer than 1000 years.
+pub(crate) fn max_age(max_age: Option<Duration>, spans_updates: bool) -> Option<MaxAge> {
+    max_age.map(|duration| {
+        assert!(
+            duration <= MAX_EXPIRATION,
+            "max_age is longer than 1000 years"
+        );
+        MaxAge {
+            duration,
+            spans_updates,
+        }
+    })
+}
+
+/// Ensures that the expiration tick is at most half of the time to live and the
+/// time to idle, so that the expirations are not dominated by the tick.
+pub(crate) fn ensure_expiration_tick(
+    expiration_tick: Option<Duration>,
+    time_to_live: Option<Duration>,
+    time_to_idle: Option<Duration>,
+) -> Result<(), BuildError> {
+    if let Some(tick) = expiration_tick {
+        let too_long = |d: Option<Duration>| matches!(d, Some(d) if tick > d / 2);
+        if too_long(time_to_live) || too_long(time_to_idle) {
+            return Err(BuildError::ExpirationTickTooLong);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the capacity to build the cache with. `build` methods accept either
+/// setting, and prefer the one matching the presence of a weigher.
+pub(crate) fn max_capacity(
+    max_capacity: Option<u64>,
+    max_weighted_capacity: Option<u64>,
+    has_weigher: bool,
+) -> Option<u64> {
+    if has_weigher {
+        max_weighted_capacity.or(max_capacity)
+    } else {
+        max_capacity.or(max_weighted_capacity)
+    }
+}
+
+/// Returns the `max_weighted_capacity` to build the cache with. If the
+/// `memory_fraction` is set, this is the fraction of the total memory of the
+/// system, read once now, rather than the given `max_weighted_capacity`.
+pub(crate) fn max_weighted_capacity(
+    max_weighted_capacity: Option<u64>,
+    memory_fraction: Option<f64>,
+    has_weigher: bool,
+) -> Result<Option<u64>, BuildError> {
+    max_weighted_capacity_with(
+        max_weighted_capacity,
+        memory_fraction,
+        has_weigher,
+        system_memory::total_memory,
+    )
+}
+
+pub(crate) fn max_weighted_capacity_or_panic(
+    max_weighted_capacity: Option<u64>,
+    memory_fraction: Option<f64>,
+    has_weigher: bool,
+) -> Option<u64> {
+    self::max_weighted_capacity(max_weighted_capacity, memory_fraction, has_weigher)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn max_weighted_capacity_with(
+    max_weighted_capacity: Option<u64>,
+    memory_fraction: Option<f64>,
+    has_weigher: bool,
+    total_memory: impl FnOnce() -> Option<u64>,
+) -> Result<Option<u64>, BuildError> {
+    match memory_fraction {
+        None => Ok(max_weighted_capacity),
+        Some(_) if !has_weigher => Err(BuildError::MemoryFractionWithoutWeigher),
+        Some(fraction) => match total_memory() {
+            Some(bytes) => Ok(Some((bytes as f64 * fraction) as u64)),
+            None => Err(BuildError::TotalMemoryUnknown),
+        },
+    }
+}
+
+/// Ensures that there is one capacity for each segment, if the capacities of the
+/// segments are set.
+pub(crate) fn ensure_segment_capacities(
+    segment_capacities: Option<&[u64]>,
+    num_segments: usize,
+) -> Result<(), BuildError> {
+    match segment_capacities {
+        Some(caps) if caps.len() != num_segments.next_power_of_two() => {
+            Err(BuildError::SegmentCapacitiesMismatch)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Ensures that the capacity is set by `max_weighted_capacity` if and only if
+/// the cache has a weigher.
+pub(crate) fn ensure_capacity(
+    max_capacity: Option<u64>,
+    max_weighted_capacity: Option<u64>,
+    has_weigher: bool,
+) -> Result<(), BuildError> {
+    if has_weigher && max_capacity.is_some() {
+        Err(BuildError::EntryCapacityWithWeigher)
+    } else if !has_weigher && max_weighted_capacity.is_some() {
+        Err(BuildError::WeightedCapacityWithoutWeigher)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::max_weighted_capacity_with;
+    use crate::BuildError;
+
+    #[test]
+    fn max_weighted_capacity_from_memory_fraction() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        let mocked_memory = || Some(16 * GIB);
+
+        let capacity = max_weighted_capacity_with(None, Some(0.25), true, mocked_memory);
+        assert_eq!(capacity, Ok(Some(4 * GIB)));
+        // The fraction takes precedence over max_weighted_capacity.
+        let capacity = max_weighted_capacity_with(Some(GIB), Some(0.5), true, mocked_memory);
+        assert_eq!(capacity, Ok(Some(8 * GIB)));
+        let capacity = max_weighted_capacity_with(Some(GIB), None, true, || unreachable!());
+        assert_eq!(capacity, Ok(Some(GIB)));
+
+        let capacity = max_weighted_capacity_with(None, Some(0.25), false, mocked_memory);
+        assert_eq!(capacity, Err(BuildError::MemoryFractionWithoutWeigher));
+        let capacity = max_weighted_capacity_with(None, Some(0.25), true, || None);
+        assert_eq!(capacity, Err(BuildError::TotalMemoryUnknown));
     }
 }
@@ -0,0 +1,35 @@
+/// A cached value returned by `get_or_insert_with_info` and its variants,
+/// together with where the value came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry<V> {
+    value: V,
+    is_fresh: bool,
+}
+
+impl<V> Entry<V> {
+    pub(crate) fn new(value: V, is_fresh: bool) -> Self {
+        Self { value, is_fresh }
+    }
+
+    /// Returns a reference to the value.
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// Consumes the `Entry` and returns the value.
+    pub fn into_value(self) -> V {
+        self.value
+    }
+
+    /// Returns `true` if this call has run its `init` closure (or resolved its
+    /// `init` future) to get the value.
+    ///
+    /// Returns `false` if the value was found in the cache, including when this
+    /// call has waited for a concurrent call for the same key to load it. In that
+    /// case, the value is as new as the one of the loading call, but only the
+    /// loading call gets `true`. So exactly one of the concurrent calls sees a
+    /// fresh value per load.
+    pub fn is_fresh(&self) -> bool {
+        self.is_fresh
+    }
+}
@@ -1,3 +1,5 @@
+use std::{convert::Infallible, error::Error, fmt, sync::Arc};
+
 /// The error type for the functionalities around
 /// [`Cache#invalidate_entries_if`][invalidate-if] method.
 ///
@@ -19,3 +21,219 @@ pub enum PredicateError {
     )]
     InvalidationClosuresDisabled,
 }
+
+/// The error returned by `try_build` method of the cache builders when the
+/// capacity settings are ambiguous, or the expiration settings are inconsistent.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// Both a weigher and `max_capacity` are set. `max_capacity` is a number of
+    /// entries, which does not tell the total weight the cache can hold.
+    ///
+    /// Set the capacity by `max_weighted_capacity` instead.
+    #[error(
+        "max_capacity is a number of entries and cannot be used with a weigher. \
+    Please set the capacity by max_weighted_capacity instead"
+    )]
+    EntryCapacityWithWeigher,
+    /// `max_weighted_capacity` is set without a weigher.
+    ///
+    /// Set a weigher, or set the capacity by `max_capacity` instead.
+    #[error(
+        "max_weighted_capacity requires a weigher. \
+    Please set a weigher, or set the capacity by max_capacity instead"
+    )]
+    WeightedCapacityWithoutWeigher,
+    /// The expiration tick is longer than half of the shortest of `time_to_live`
+    /// and `time_to_idle`.
+    ///
+    /// Use a shorter tick, which should be a small fraction of the expiration
+    /// durations.
+    #[error("expiration_tick must not be longer than half of time_to_live or time_to_idle")]
+    ExpirationTickTooLong,
+    /// The `time_to_live` is longer than the `max_allowed_ttl` of the
+    /// [process-level defaults][defaults], or is not set, and the defaults
+    /// reject such a cache.
+    ///
+    /// [defaults]: ./defaults/index.html
+    #[error(
+        "time_to_live must be set and must not be longer than max_allowed_ttl of the defaults"
+    )]
+    TimeToLiveNotAllowed,
+    /// The number of the `segment_capacities` is not the number of the segments
+    /// of the cache, i.e. `num_segments` rounded up to the next power of two.
+    #[error("segment_capacities must have exactly one capacity for each segment")]
+    SegmentCapacitiesMismatch,
+    /// The `time_to_idle` is longer than the `time_to_live`, so it never expires
+    /// an entry. The `time_to_live` here is the one after applying the
+    /// `max_allowed_ttl` of the [process-level defaults][defaults].
+    ///
+    /// Remove the `time_to_idle`, or make it shorter than the `time_to_live`.
+    ///
+    /// [defaults]: ./defaults/index.html
+    #[error("time_to_idle must not be longer than time_to_live")]
+    TtiExceedsTtl,
+    /// `max_capacity_memory_fraction` is set without a weigher. The fraction of
+    /// the memory is a number of bytes, which only a weigher can measure the
+    /// entries against.
+    ///
+    /// Set a weigher returning the approximate size of the entries in bytes.
+    #[error("max_capacity_memory_fraction requires a weigher measuring the entries in bytes")]
+    MemoryFractionWithoutWeigher,
+    /// `max_capacity_memory_fraction` is set, but the total memory of the system
+    /// cannot be determined on this platform.
+    ///
+    /// Set the capacity by `max_weighted_capacity` instead.
+    #[error("the total memory of the system is unknown for max_capacity_memory_fraction")]
+    TotalMemoryUnknown,
+}
+
+/// The error returned by [`defaults::set`][defaults-set].
+///
+/// [defaults-set]: ./defaults/fn.set.html
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultsError {
+    /// The defaults have already been set, or fixed by creating a cache
+    /// builder.
+    #[error("The defaults have already been set, or a cache builder has already been created")]
+    AlreadySet,
+}
+
+/// The error returned by `try_insert` method of the caches when the key is
+/// already present.
+///
+/// It holds the value in the cache and the value that was not inserted.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("The key is already present in the cache")]
+pub struct OccupiedError<V> {
+    /// The value that is present in the cache.
+    pub existing: V,
+    /// The value that was given to `try_insert` and not inserted.
+    pub value: V,
+}
+
+/// The error returned by `get_with_reservation` method of the caches when
+/// reserving the estimated weight of the value would take the cache over its
+/// [reservation high-water mark][high-water-mark]. The `init` closure has not
+/// been called.
+///
+/// [high-water-mark]: ./sync/struct.CacheBuilder.html#method.reservation_high_water_mark
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("The load was shed as the cache would exceed its reservation high-water mark")]
+pub struct LoadSheddingError {
+    /// The total weight of the entries in the cache plus the weight reserved by
+    /// the other loads in progress, when the load was shed.
+    pub committed_weight: u64,
+    /// The estimated weight that the load tried to reserve.
+    pub requested_weight: u64,
+}
+
+/// The error returned by `insert_if_version` method of the caches when the
+/// version of the entry is not the expected one.
+///
+/// It holds the value in the cache with its version, and the value that was not
+/// inserted.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("The version of the entry does not match the expected one")]
+pub struct CasError<V> {
+    /// The value that is present in the cache and its version, or `None` if the
+    /// key is absent.
+    pub current: Option<(V, u64)>,
+    /// The value that was given to `insert_if_version` and not inserted.
+    pub value: V,
+}
+
+/// The error returned by `get_or_try_insert_with_fallback` method of the caches
+/// when both the primary and the fallback loaders have failed.
+///
+/// It holds the errors of both loaders.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Both the primary and the fallback loaders failed")]
+pub struct FallbackError<E1, E2> {
+    /// The error returned by the primary loader.
+    pub primary: E1,
+    /// The error returned by the fallback loader.
+    pub fallback: E2,
+}
+
+/// The error returned by `get_with_deadline` and `insert_with_deadline` methods
+/// of the async cache when the deadline has passed before the operation could
+/// be carried out. The cache has not been modified.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("The deadline passed before the cache operation could be carried out")]
+pub struct DeadlineError;
+
+/// The error returned by the fallible loading methods of the caches, such as
+/// `get_or_try_insert_with` and `get_with_reservation`.
+///
+/// It tells an error returned by the loader apart from the cases where the
+/// cache gave up the load by itself. `E` is the error type of the loader, which
+/// is [`Infallible`] for the methods whose loader cannot fail.
+///
+/// New variants may be added for new ways a load can be given up, so a `match`
+/// needs a wildcard arm.
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoadError<E = Infallible> {
+    /// The loader returned the error. The concurrent calls waiting for the same
+    /// load share the error.
+    Loader(Arc<E>),
+    /// The cache shed the load without calling the loader, as it would have
+    /// taken the cache over its reservation high-water mark.
+    Shed(LoadSheddingError),
+    /// The loader of the concurrent call owning the load of the key kept
+    /// panicking. The call retries the load when the owner panics, and gives up
+    /// after many retries.
+    OwnerPanicked,
+}
+
+impl<E> Clone for LoadError<E> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Loader(e) => Self::Loader(Arc::clone(e)),
+            Self::Shed(e) => Self::Shed(*e),
+            Self::OwnerPanicked => Self::OwnerPanicked,
+        }
+    }
+}
+
+impl<E> LoadError<E> {
+    /// Returns the error of the loader, or `None` if the cache gave up the load.
+    pub fn loader_error(&self) -> Option<&Arc<E>> {
+        match self {
+            Self::Loader(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Converts the error of a load whose loader cannot fail except by being
+    /// shed.
+    pub(crate) fn from_shed_load(e: LoadError<LoadSheddingError>) -> Self {
+        match e {
+            LoadError::Loader(e) => Self::Shed(*e),
+            LoadError::Shed(e) => Self::Shed(e),
+            LoadError::OwnerPanicked => Self::OwnerPanicked,
+        }
+    }
+}
+
+impl<E> fmt::Display for LoadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Loader(_) => f.write_str("The loader failed to load the value"),
+            Self::Shed(_) => f.write_str("The cache shed the load"),
+            Self::OwnerPanicked => {
+                f.write_str("The loader of the concurrent call loading the key kept panicking")
+            }
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for LoadError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Loader(e) => Some(&**e),
+            Self::Shed(e) => Some(e),
+            Self::OwnerPanicked => None,
+        }
+    }
+}
@@ -101,7 +101,7 @@ impl FrequencySketch {
         }
 
         self.table = vec![0; table_size as usize].into_boxed_slice();
-        self.table_mask = 0.max(table_size - 1) as u64;
+        self.table_mask = (table_size - 1) as u64;
         self.sample_size = if cap == 0 {
             10
         } else {
@@ -117,7 +117,7 @@ impl FrequencySketch {
         }
 
         let start = ((hash & 3) << 2) as u8;
-        let mut frequency = std::u8::MAX;
+        let mut frequency = u8::MAX;
         for i in 0..4 {
             let index = self.index_of(hash, i);
             let count = (self.table[index] >> ((start + i) << 2) & 0xF) as u8;
@@ -185,6 +185,43 @@ impl FrequencySketch {
     }
 }
 
+#[cfg(feature = "serde")]
+impl FrequencySketch {
+    /// Returns the counters table, the number of the recorded increments and
+    /// the sample size, from which `restore` recreates this sketch.
+    pub(crate) fn export(&self) -> (Vec<u64>, u32, u32) {
+        (self.table.to_vec(), self.size, self.sample_size)
+    }
+
+    /// Replaces the counters with the ones exported from a sketch of the same
+    /// capacity. Returns `false` without changing this sketch if the table does
+    /// not have the same length as the current one, or, when this sketch has not
+    /// been initialized yet, if the table is not valid.
+    ///
+    /// An empty table is exported by a sketch that has not been initialized yet,
+    /// and is restored to such a sketch by doing nothing.
+    pub(crate) fn restore(&mut self, table: &[u64], size: u32, sample_size: u32) -> bool {
+        if table.is_empty() {
+            return self.table.is_empty();
+        }
+        let is_valid = if self.table.is_empty() {
+            table.len().is_power_of_two() && sample_size > 0
+        } else {
+            table.len() == self.table.len()
+        };
+        if !is_valid {
+            return false;
+        }
+        if self.table.is_empty() {
+            self.table_mask = (table.len() - 1) as u64;
+            self.sample_size = sample_size;
+        }
+        self.table = table.to_vec().into_boxed_slice();
+        self.size = size.min(self.sample_size);
+        true
+    }
+}
+
 // Methods only available for testing.
 #[cfg(test)]
 impl FrequencySketch {
@@ -201,12 +238,12 @@ impl FrequencySketch {
 mod tests {
     use super::FrequencySketch;
     use once_cell::sync::Lazy;
-    use std::hash::{BuildHasher, Hash, Hasher};
+    use std::hash::{BuildHasher, Hash};
 
     static ITEM: Lazy<u32> = Lazy::new(|| {
         let mut buf = [0; 4];
         getrandom::getrandom(&mut buf).unwrap();
-        unsafe { std::mem::transmute::<[u8; 4], u32>(buf) }
+        u32::from_ne_bytes(buf)
     });
 
     // This test was ported from Caffeine.
@@ -252,7 +289,7 @@ mod tests {
         let mut sketch = FrequencySketch::default();
         sketch.ensure_capacity(512);
         let mut indexes = std::collections::HashSet::new();
-        let hashes = vec![std::u64::MAX, 0, 1];
+        let hashes = [u64::MAX, 0, 1];
         for hash in hashes.iter() {
             for depth in 0..4 {
                 indexes.insert(sketch.index_of(*hash, depth));
@@ -314,12 +351,40 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn export_and_restore() {
+        let mut sketch = FrequencySketch::default();
+        sketch.ensure_capacity(512);
+        let hasher = hasher();
+        for _ in 0..3 {
+            sketch.increment(hasher(*ITEM));
+        }
+        let (table, size, sample_size) = sketch.export();
+
+        // An uninitialized sketch takes the table as is.
+        let mut restored = FrequencySketch::default();
+        assert!(restored.restore(&table, size, sample_size));
+        assert_eq!(restored.frequency(hasher(*ITEM)), 3);
+        assert_eq!(restored.size, 3);
+
+        // A sketch of another capacity rejects it.
+        let mut other = FrequencySketch::default();
+        other.ensure_capacity(1024);
+        assert!(!other.restore(&table, size, sample_size));
+        assert_eq!(other.frequency(hasher(*ITEM)), 0);
+
+        // An invalid table is rejected.
+        let mut invalid = FrequencySketch::default();
+        assert!(!invalid.restore(&table[..3], size, sample_size));
+
+        // An empty table is only restored to an uninitialized sketch.
+        assert!(FrequencySketch::default().restore(&[], 0, 0));
+        assert!(!sketch.restore(&[], 0, 0));
+    }
+
     fn hasher<K: Hash>() -> impl Fn(K) -> u64 {
         let build_hasher = std::collections::hash_map::RandomState::default();
-        move |key| {
-            let mut hasher = build_hasher.build_hasher();
-            key.hash(&mut hasher);
-            hasher.finish()
-        }
+        move |key| build_hasher.hash_one(&key)
     }
 }
@@ -0,0 +1,149 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The number of the hash bits used to select a register.
+const PRECISION: u32 = 12;
+const NUM_REGISTERS: usize = 1 << PRECISION;
+
+/// A HyperLogLog estimator of the number of distinct key hashes recorded to it.
+///
+/// It uses 2^12 registers of one byte each, giving the standard error of about
+/// 1.6% (`1.04 / sqrt(4096)`). The registers are atomic, so recording does not
+/// take a lock.
+pub(crate) struct HyperLogLog {
+    registers: Box<[AtomicU8]>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: (0..NUM_REGISTERS).map(|_| AtomicU8::new(0)).collect(),
+        }
+    }
+}
+
+impl HyperLogLog {
+    #[inline]
+    pub(crate) fn record(&self, hash: u64) {
+        let (index, rank) = index_and_rank(hash);
+        let register = &self.registers[index];
+        // Skip the write in the common case where the register is already large
+        // enough.
+        if register.load(Ordering::Relaxed) < rank {
+            register.fetch_max(rank, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> HyperLogLogSnapshot {
+        HyperLogLogSnapshot {
+            registers: self
+                .registers
+                .iter()
+                .map(|r| r.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+}
+
+/// The registers of a `HyperLogLog` at a point in time.
+#[derive(Clone)]
+pub(crate) struct HyperLogLogSnapshot {
+    registers: Box<[u8]>,
+}
+
+impl HyperLogLogSnapshot {
+    /// Merges the other snapshot into this one, so that this estimates the number
+    /// of the distinct hashes recorded to either of them.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (r, o) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *r = (*r).max(*o);
+        }
+    }
+
+    pub(crate) fn estimate(&self) -> u64 {
+        let m = NUM_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let (sum, zeros) = self
+            .registers
+            .iter()
+            .fold((0.0, 0usize), |(sum, zeros), &r| {
+                let zeros = if r == 0 { zeros + 1 } else { zeros };
+                (sum + 2f64.powi(-(r as i32)), zeros)
+            });
+        let raw = alpha * m * m / sum;
+
+        // Use the linear counting for the small cardinalities, where the raw
+        // estimate is biased. The large range correction is not needed with
+        // 64-bit hashes.
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+}
+
+impl fmt::Debug for HyperLogLogSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HyperLogLogSnapshot")
+            .field("estimate", &self.estimate())
+            .finish()
+    }
+}
+
+/// Returns the register index selected by the high bits of the hash, and the
+/// position of the first set bit in the remaining bits.
+#[inline]
+fn index_and_rank(hash: u64) -> (usize, u8) {
+    let index = (hash >> (64 - PRECISION)) as usize;
+    // Set a guard bit so that the rank is at most `64 - PRECISION + 1`.
+    let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+    (index, rest.leading_zeros() as u8 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HyperLogLog;
+    use std::{collections::hash_map::RandomState, hash::BuildHasher};
+
+    fn hash(hasher: &RandomState, n: u64) -> u64 {
+        hasher.hash_one(n)
+    }
+
+    #[test]
+    fn estimate() {
+        let hasher = RandomState::new();
+        for &n in &[0u64, 10, 1_000, 100_000] {
+            let hll = HyperLogLog::default();
+            // Record every hash twice; duplicates must not be counted.
+            for _ in 0..2 {
+                for i in 0..n {
+                    hll.record(hash(&hasher, i));
+                }
+            }
+            let estimate = hll.snapshot().estimate() as f64;
+            let error = (estimate - n as f64).abs() / (n as f64).max(1.0);
+            // Ten times the standard error.
+            assert!(error < 0.16, "n: {}, estimate: {}", n, estimate);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let hasher = RandomState::new();
+        let (a, b) = (HyperLogLog::default(), HyperLogLog::default());
+        for i in 0..20_000 {
+            a.record(hash(&hasher, i));
+        }
+        for i in 10_000..30_000 {
+            b.record(hash(&hasher, i));
+        }
+        let mut merged = a.snapshot();
+        merged.merge(&b.snapshot());
+        let estimate = merged.estimate() as f64;
+        assert!((estimate - 30_000.0).abs() / 30_000.0 < 0.16);
+    }
+}
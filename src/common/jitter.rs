@@ -0,0 +1,91 @@
+use super::builder_utils::MAX_EXPIRATION;
+
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+thread_local! {
+    // The state of a xorshift64* generator, seeded differently on every thread.
+    static RNG_STATE: Cell<u64> = Cell::new(seed());
+}
+
+fn seed() -> u64 {
+    // `RandomState` gives different keys to every instance, so this hashes
+    // nothing into a random number. The state of xorshift must not be zero.
+    RandomState::new().build_hasher().finish() | 1
+}
+
+/// Returns a random number in `0.0..1.0`. It is cheap but not suitable for
+/// anything but spreading the values.
+fn next_f64() -> f64 {
+    RNG_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        // Take the upper 53 bits, the precision of an f64.
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+/// Randomizes the durations by up to a fraction of them, e.g. to spread the
+/// expirations of the entries inserted at the same time.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Jitter {
+    fraction: f64,
+}
+
+impl Jitter {
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not in `0.0..1.0`.
+    pub(crate) fn new(fraction: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&fraction),
+            "expiration_jitter must be at least 0.0 and less than 1.0"
+        );
+        Self { fraction }
+    }
+
+    /// Returns the duration multiplied by a random factor in
+    /// `(1 - fraction)..(1 + fraction)`, capped to 1000 years.
+    pub(crate) fn apply(&self, duration: Duration) -> Duration {
+        let factor = 1.0 + self.fraction * (2.0 * next_f64() - 1.0);
+        // The duration is at most 1000 years and the factor is less than 2, so
+        // this does not overflow.
+        duration.mul_f64(factor).min(MAX_EXPIRATION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Jitter, MAX_EXPIRATION};
+    use std::time::Duration;
+
+    #[test]
+    fn apply() {
+        let jitter = Jitter::new(0.1);
+        let ttl = Duration::from_secs(3600);
+        let durations = (0..1_000).map(|_| jitter.apply(ttl)).collect::<Vec<_>>();
+        assert!(durations
+            .iter()
+            .all(|d| *d >= ttl.mul_f64(0.9) && *d <= ttl.mul_f64(1.1)));
+        // Spread over the range, not stuck at one value.
+        let (min, max) = (durations.iter().min(), durations.iter().max());
+        assert!(*max.unwrap() - *min.unwrap() > Duration::from_secs(600));
+
+        assert_eq!(Jitter::new(0.0).apply(ttl), ttl);
+        let jitter = Jitter::new(0.5);
+        assert!((0..100).all(|_| jitter.apply(MAX_EXPIRATION) <= MAX_EXPIRATION));
+    }
+
+    #[test]
+    #[should_panic(expected = "expiration_jitter must be at least 0.0 and less than 1.0")]
+    fn new_rejects_fraction_of_one() {
+        let _ = Jitter::new(1.0);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+/// A key wrapper that carries the hash of the key, so that a key that is
+/// expensive to hash (e.g. a long string) is hashed only once, when the wrapper
+/// is created.
+///
+/// The `Hash` implementation of `PrecomputedHashKey` writes only the carried
+/// hash, so the cache hashes a `u64` instead of the key on every `insert` and
+/// `get`. Create the wrapper once and reuse it for the repeated lookups of the
+/// same key.
+///
+/// # Consistency of the hashes
+///
+/// Two wrappers are equal only if both their hashes and their keys are equal.
+/// So a wrapper carrying a wrong hash never reads nor replaces the value of
+/// another key: it is only a miss, or a separate entry. To get hits, the same key
+/// must always carry the same hash:
+///
+/// - [`new`](#method.new) hashes the key with a fixed hasher, so it always gives
+///   the same hash to equal keys.
+/// - [`with_hash`](#method.with_hash) trusts the given hash. Use it only with the
+///   hashes computed by the same function for all keys.
+///
+/// As the hasher of `new` is not randomly seeded, it does not protect the cache
+/// from the keys crafted to collide (HashDoS). For keys from untrusted input, use
+/// `with_hash` with a keyed hash function.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::{sync::Cache, PrecomputedHashKey};
+///
+/// let cache = Cache::new(100);
+///
+/// let key = PrecomputedHashKey::new("a long key ".repeat(100));
+/// cache.insert(key.clone(), 1);
+///
+/// // The key is not hashed again.
+/// assert_eq!(cache.get(&key), Some(1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct PrecomputedHashKey<K> {
+    hash: u64,
+    key: K,
+}
+
+impl<K: Hash> PrecomputedHashKey<K> {
+    /// Creates a wrapper of the key, hashing the key with a fixed hasher.
+    pub fn new(key: K) -> Self {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        Self {
+            hash: hasher.finish(),
+            key,
+        }
+    }
+}
+
+impl<K> PrecomputedHashKey<K> {
+    /// Creates a wrapper of the key carrying the given hash. The hash is trusted;
+    /// see [the type-level docs](#consistency-of-the-hashes).
+    pub fn with_hash(key: K, hash: u64) -> Self {
+        Self { hash, key }
+    }
+
+    /// Returns the carried hash.
+    pub fn precomputed_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns a reference to the key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Consumes the wrapper and returns the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+}
+
+impl<K> Hash for PrecomputedHashKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl<K: PartialEq> PartialEq for PrecomputedHashKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        // Compare the hashes first, as they are cheaper to compare than the keys.
+        self.hash == other.hash && self.key == other.key
+    }
+}
+
+impl<K: Eq> Eq for PrecomputedHashKey<K> {}
+
+#[cfg(test)]
+mod tests {
+    use super::PrecomputedHashKey;
+    use crate::sync::Cache;
+
+    #[test]
+    fn hash_consistency() {
+        let (a1, a2) = (
+            PrecomputedHashKey::new("alice".to_string()),
+            PrecomputedHashKey::new("alice".to_string()),
+        );
+        assert_eq!(a1.precomputed_hash(), a2.precomputed_hash());
+        assert_eq!(a1, a2);
+
+        // A wrong hash makes another key, not a wrong hit.
+        let cache = Cache::new(100);
+        cache.insert(a1, 1);
+        let wrong = PrecomputedHashKey::with_hash("alice".to_string(), 42);
+        assert_ne!(wrong, a2);
+        assert_eq!(cache.get(&wrong), None);
+        assert_eq!(cache.get(&a2), Some(1));
+
+        let b = PrecomputedHashKey::with_hash("bob".to_string(), a2.precomputed_hash());
+        assert_eq!(cache.get(&b), None);
+    }
+}
@@ -0,0 +1,892 @@
+use crossbeam_utils::atomic::AtomicCell;
+
+#[cfg(feature = "hyperloglog")]
+use super::hyperloglog::{HyperLogLog, HyperLogLogSnapshot};
+#[cfg(feature = "hdrhistogram")]
+use hdrhistogram::Histogram;
+#[cfg(feature = "hdrhistogram")]
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// Statistics about the operations performed on a cache.
+///
+/// Statistics are recorded only when the cache was built with `record_stats`
+/// method of the `CacheBuilder`. Otherwise, all counts will be zero.
+///
+/// `CacheStats` is a point-in-time snapshot. Call the `stats` method of the cache
+/// again to get the latest values, or `stats_and_reset` to get the counts since
+/// the last reset.
+#[derive(Clone, Debug, Default)]
+pub struct CacheStats {
+    hit_count: u64,
+    miss_count: u64,
+    expired_count: u64,
+    evicted_count: u64,
+    loads_started: u64,
+    loads_completed: u64,
+    loads_failed: u64,
+    loads_deduplicated: u64,
+    total_load_time_nanos: u64,
+    prefetches_issued: u64,
+    prefetch_hits: u64,
+    dropped_eviction_events: u64,
+    lock_contentions: u64,
+    maintenance: MaintenanceStats,
+    #[cfg(feature = "hdrhistogram")]
+    get_latency: Option<Histogram<u64>>,
+    #[cfg(feature = "hdrhistogram")]
+    insert_latency: Option<Histogram<u64>>,
+    #[cfg(feature = "hyperloglog")]
+    distinct_keys: Option<HyperLogLogSnapshot>,
+}
+
+impl CacheStats {
+    /// Returns the number of times `get` and its variants returned a cached value.
+    pub fn hit_count(&self) -> u64 {
+        self.hit_count
+    }
+
+    /// Returns the number of times `get` and its variants did not find a cached
+    /// value. An expired entry counts as a miss.
+    pub fn miss_count(&self) -> u64 {
+        self.miss_count
+    }
+
+    /// Returns the sum of `hit_count` and `miss_count`.
+    pub fn request_count(&self) -> u64 {
+        self.hit_count.saturating_add(self.miss_count)
+    }
+
+    /// Returns the ratio of `hit_count` to `request_count`, or `1.0` if there has
+    /// been no request.
+    pub fn hit_rate(&self) -> f64 {
+        match self.request_count() {
+            0 => 1.0,
+            n => self.hit_count as f64 / n as f64,
+        }
+    }
+
+    /// Returns the number of entries removed by the cache because they expired
+    /// (time-to-live or time-to-idle).
+    pub fn expired_count(&self) -> u64 {
+        self.expired_count
+    }
+
+    /// Returns the number of entries removed by the cache to keep it within its
+    /// max capacity, including the new entries that were not admitted and those
+    /// removed by `evict_now`.
+    ///
+    /// Entries removed by `invalidate` and its variants are counted by neither
+    /// this nor [`expired_count`](#method.expired_count).
+    pub fn evicted_count(&self) -> u64 {
+        self.evicted_count
+    }
+
+    /// Returns the number of times `get_or_insert_with` and its variants have
+    /// called their `init` closure (or resolved their `init` future) to load a
+    /// value. Only the leader of the concurrent calls for a key loads the value,
+    /// so this is not incremented for the calls that found the value in the cache
+    /// or waited for another call.
+    ///
+    /// The loads by `bulk_get_or_insert_with` and `map_stream` of
+    /// `future::Cache` are not counted.
+    pub fn loads_started(&self) -> u64 {
+        self.loads_started
+    }
+
+    /// Returns the number of loads that returned a value, or an `Ok` in the case
+    /// of `get_or_try_insert_with` and its variants.
+    pub fn loads_completed(&self) -> u64 {
+        self.loads_completed
+    }
+
+    /// Returns the number of loads that returned an `Err`, panicked, or were
+    /// dropped before completion (e.g. a cancelled `init` future).
+    ///
+    /// Once no load is in progress, `loads_started` equals the sum of
+    /// `loads_completed` and `loads_failed`.
+    pub fn loads_failed(&self) -> u64 {
+        self.loads_failed
+    }
+
+    /// Returns the number of calls of `get_or_insert_with` and its variants that
+    /// did not load the value themselves but waited for a concurrent call for
+    /// the same key and took its result, including an `Err`.
+    ///
+    /// This counts the waiters, not the leaders. For example, when ten threads
+    /// call `get_or_insert_with` on a missing key at the same time, there are one
+    /// started load and up to nine deduplicated calls. The calls that find the
+    /// value already in the cache are counted by `hit_count` instead.
+    pub fn loads_deduplicated(&self) -> u64 {
+        self.loads_deduplicated
+    }
+
+    /// Returns the number of `prefetch` calls that started a load, i.e. that found
+    /// the key neither in the cache nor being loaded.
+    pub fn prefetches_issued(&self) -> u64 {
+        self.prefetches_issued
+    }
+
+    /// Returns the number of values loaded by `prefetch` that were later returned
+    /// by `get` or its variants. Each value is counted once, on its first hit.
+    ///
+    /// A call that waited for a prefetch still in progress is counted by
+    /// `loads_deduplicated` instead.
+    pub fn prefetch_hits(&self) -> u64 {
+        self.prefetch_hits
+    }
+
+    /// Returns the number of removals dropped by the eviction streams of
+    /// `future::Cache` whose buffers were full, because their consumers did not
+    /// keep up. A removal dropped by several streams is counted once per stream.
+    pub fn dropped_eviction_events(&self) -> u64 {
+        self.dropped_eviction_events
+    }
+
+    /// Returns the number of times an operation had to wait for an internal
+    /// lock held by another thread, i.e. the lock of the cache policy taken by
+    /// the maintenance, or the lock of a key taken by `insert_with` and `swap` of
+    /// `sync::Cache`.
+    ///
+    /// A lock is first tried without blocking, and a contention is counted only
+    /// when that fails, so an uncontended lock costs nothing more to record.
+    pub fn lock_contentions(&self) -> u64 {
+        self.lock_contentions
+    }
+
+    /// Returns the statistics of the maintenance passes, which apply the
+    /// recorded reads and writes to the cache policy and remove the expired and
+    /// evicted entries.
+    pub fn maintenance(&self) -> &MaintenanceStats {
+        &self.maintenance
+    }
+
+    /// Returns the average time spent in a load, including the failed ones, or
+    /// zero if no load has finished. The time is measured from the start of the
+    /// `init` closure (or the first poll of the `init` future) to its end, and
+    /// does not include inserting the value into the cache.
+    pub fn average_load_time(&self) -> Duration {
+        match self.loads_completed.saturating_add(self.loads_failed) {
+            0 => Duration::default(),
+            n => Duration::from_nanos(self.total_load_time_nanos / n),
+        }
+    }
+
+    /// Returns the latency of `get` at the given percentile (`0.0..=100.0`), or
+    /// `None` if no `get` has been sampled yet.
+    ///
+    /// The latency covers the cache operation itself, not the time spent in init
+    /// closures of `get_or_insert_with` and its variants.
+    ///
+    /// Requires the `hdrhistogram` crate feature. See
+    /// [`CacheBuilder::record_stats`][record-stats] for the sampling strategy.
+    ///
+    /// [record-stats]: ./sync/struct.CacheBuilder.html#method.record_stats
+    #[cfg(feature = "hdrhistogram")]
+    pub fn get_latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        Self::latency_percentile(&self.get_latency, percentile)
+    }
+
+    /// Returns the latency of `insert` at the given percentile (`0.0..=100.0`), or
+    /// `None` if no `insert` has been sampled yet.
+    ///
+    /// Requires the `hdrhistogram` crate feature.
+    #[cfg(feature = "hdrhistogram")]
+    pub fn insert_latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        Self::latency_percentile(&self.insert_latency, percentile)
+    }
+
+    /// Returns the approximate number of distinct keys inserted to the cache over
+    /// its lifetime, including the keys that have been evicted, expired or
+    /// invalidated since then. A key inserted again after it was removed is
+    /// counted once.
+    ///
+    /// Comparing this with the max capacity shows the size of the working set
+    /// relative to the cache. A distinct key count much larger than the capacity
+    /// with a low hit rate suggests that the cache is too small.
+    ///
+    /// The count is estimated by a HyperLogLog with 4096 registers, so it takes
+    /// 4 KiB of memory per cache (per segment for a `SegmentedCache`), and its
+    /// standard error is about 1.6%. The inserts are counted when they are applied
+    /// by the maintenance.
+    ///
+    /// Requires the `hyperloglog` crate feature, as it adds work to every insert.
+    /// Returns `0` if the cache does not record the stats.
+    #[cfg(feature = "hyperloglog")]
+    pub fn distinct_keys_estimate(&self) -> u64 {
+        self.distinct_keys
+            .as_ref()
+            .map_or(0, HyperLogLogSnapshot::estimate)
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    fn latency_percentile(hist: &Option<Histogram<u64>>, percentile: f64) -> Option<Duration> {
+        hist.as_ref()
+            .filter(|h| !h.is_empty())
+            .map(|h| Duration::from_nanos(h.value_at_percentile(percentile)))
+    }
+
+    /// Adds the counts of `other` to this `CacheStats`. Used to aggregate the
+    /// statistics of the segments of a `SegmentedCache`.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.hit_count = self.hit_count.saturating_add(other.hit_count);
+        self.miss_count = self.miss_count.saturating_add(other.miss_count);
+        self.expired_count = self.expired_count.saturating_add(other.expired_count);
+        self.evicted_count = self.evicted_count.saturating_add(other.evicted_count);
+        self.loads_started = self.loads_started.saturating_add(other.loads_started);
+        self.loads_completed = self.loads_completed.saturating_add(other.loads_completed);
+        self.loads_failed = self.loads_failed.saturating_add(other.loads_failed);
+        self.loads_deduplicated = self
+            .loads_deduplicated
+            .saturating_add(other.loads_deduplicated);
+        self.total_load_time_nanos = self
+            .total_load_time_nanos
+            .saturating_add(other.total_load_time_nanos);
+        self.prefetches_issued = self
+            .prefetches_issued
+            .saturating_add(other.prefetches_issued);
+        self.prefetch_hits = self.prefetch_hits.saturating_add(other.prefetch_hits);
+        self.dropped_eviction_events = self
+            .dropped_eviction_events
+            .saturating_add(other.dropped_eviction_events);
+        self.lock_contentions = self.lock_contentions.saturating_add(other.lock_contentions);
+        self.maintenance.merge(&other.maintenance);
+        #[cfg(feature = "hdrhistogram")]
+        {
+            Self::merge_histogram(&mut self.get_latency, &other.get_latency);
+            Self::merge_histogram(&mut self.insert_latency, &other.insert_latency);
+        }
+        #[cfg(feature = "hyperloglog")]
+        match (self.distinct_keys.as_mut(), &other.distinct_keys) {
+            (Some(d), Some(o)) => d.merge(o),
+            (None, Some(o)) => self.distinct_keys = Some(o.clone()),
+            (_, None) => (),
+        }
+    }
+
+    /// Combines the statistics of the near and far tiers of a `TieredCache`. Every
+    /// miss in the near tier is looked up in the far tier, so only the misses of
+    /// the far tier are misses of the tiered cache. The entries evicted from the
+    /// near tier are moved to the far tier, so they are not counted either.
+    pub(crate) fn merge_tiers(near: &Self, far: &Self) -> Self {
+        let mut stats = near.clone();
+        stats.merge(far);
+        stats.miss_count = far.miss_count;
+        stats.evicted_count = far.evicted_count;
+        stats
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    fn merge_histogram(this: &mut Option<Histogram<u64>>, other: &Option<Histogram<u64>>) {
+        match (this.as_mut(), other) {
+            (Some(h), Some(o)) => {
+                // Auto-resizing histograms never fail to add.
+                let _ = h.add(o);
+            }
+            (None, Some(o)) => *this = Some(o.clone()),
+            (_, None) => (),
+        }
+    }
+}
+
+/// The number of the buckets of the duration histogram of the maintenance
+/// passes. The last bucket holds the passes of `2^30` microseconds (about 18
+/// minutes) or longer.
+pub(crate) const MAINTENANCE_DURATION_BUCKETS: usize = 32;
+
+/// Statistics about the maintenance passes of a cache, returned by
+/// [`CacheStats::maintenance`].
+///
+/// A maintenance pass applies the reads and writes recorded since the last pass
+/// to the cache policy, and removes the expired and evicted entries. The passes
+/// run on the housekeeper threads, or on the calling thread of `sync`.
+///
+/// Each pass costs two clock reads and a few atomic additions to record, and
+/// only when the cache records its statistics.
+#[derive(Clone, Debug, Default)]
+pub struct MaintenanceStats {
+    pass_count: u64,
+    total_time_nanos: u64,
+    reads_drained: u64,
+    writes_drained: u64,
+    entries_expired: u64,
+    entries_evicted: u64,
+    duration_histogram: [u64; MAINTENANCE_DURATION_BUCKETS],
+}
+
+impl MaintenanceStats {
+    /// Returns the number of maintenance passes.
+    pub fn pass_count(&self) -> u64 {
+        self.pass_count
+    }
+
+    /// Returns the total time spent in the maintenance passes.
+    pub fn total_time(&self) -> Duration {
+        Duration::from_nanos(self.total_time_nanos)
+    }
+
+    /// Returns the number of the recorded reads applied by the passes.
+    pub fn reads_drained(&self) -> u64 {
+        self.reads_drained
+    }
+
+    /// Returns the number of the recorded writes applied by the passes.
+    pub fn writes_drained(&self) -> u64 {
+        self.writes_drained
+    }
+
+    /// Returns the number of entries removed by the passes as they had expired.
+    pub fn entries_expired(&self) -> u64 {
+        self.entries_expired
+    }
+
+    /// Returns the number of entries evicted or rejected by the passes for the
+    /// capacity of the cache.
+    pub fn entries_evicted(&self) -> u64 {
+        self.entries_evicted
+    }
+
+    /// Returns the number of the passes per duration bucket. There are 32
+    /// buckets of powers of two microseconds: the bucket `0` holds the passes
+    /// shorter than 1 microsecond, the bucket `i` the ones from `2^(i - 1)` up to
+    /// `2^i` microseconds, and the last one the passes of `2^30` microseconds
+    /// (about 18 minutes) or longer.
+    pub fn duration_histogram(&self) -> &[u64; MAINTENANCE_DURATION_BUCKETS] {
+        &self.duration_histogram
+    }
+
+    /// Returns the upper bound of the duration bucket that holds the pass at the
+    /// given percentile (`0.0..=100.0`), or `None` if no pass has run. The
+    /// passes in the last bucket have no upper bound, and are reported as
+    /// `Duration::MAX`.
+    pub fn duration_percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.pass_count == 0 {
+            return None;
+        }
+        let rank = ((percentile / 100.0) * self.pass_count as f64)
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+        for (i, count) in self.duration_histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(if i == MAINTENANCE_DURATION_BUCKETS - 1 {
+                    Duration::MAX
+                } else {
+                    Duration::from_micros(1 << i)
+                });
+            }
+        }
+        Some(Duration::MAX)
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.pass_count = self.pass_count.saturating_add(other.pass_count);
+        self.total_time_nanos = self.total_time_nanos.saturating_add(other.total_time_nanos);
+        self.reads_drained = self.reads_drained.saturating_add(other.reads_drained);
+        self.writes_drained = self.writes_drained.saturating_add(other.writes_drained);
+        self.entries_expired = self.entries_expired.saturating_add(other.entries_expired);
+        self.entries_evicted = self.entries_evicted.saturating_add(other.entries_evicted);
+        for (this, other) in self
+            .duration_histogram
+            .iter_mut()
+            .zip(other.duration_histogram.iter())
+        {
+            *this = this.saturating_add(*other);
+        }
+    }
+}
+
+/// What a maintenance pass has done, recorded by `StatsCounter::record_maintenance`.
+pub(crate) struct MaintenancePass {
+    pub(crate) elapsed: Duration,
+    pub(crate) reads_drained: u64,
+    pub(crate) writes_drained: u64,
+    pub(crate) entries_expired: u64,
+    pub(crate) entries_evicted: u64,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum OpKind {
+    Get,
+    Insert,
+}
+
+/// Records the statistics of a cache.
+#[derive(Default)]
+pub(crate) struct StatsCounter {
+    hit_count: AtomicCell<u64>,
+    miss_count: AtomicCell<u64>,
+    expired_count: AtomicCell<u64>,
+    evicted_count: AtomicCell<u64>,
+    loads_started: AtomicCell<u64>,
+    loads_completed: AtomicCell<u64>,
+    loads_failed: AtomicCell<u64>,
+    loads_deduplicated: AtomicCell<u64>,
+    total_load_time_nanos: AtomicCell<u64>,
+    prefetches_issued: AtomicCell<u64>,
+    prefetch_hits: AtomicCell<u64>,
+    dropped_eviction_events: AtomicCell<u64>,
+    lock_contentions: AtomicCell<u64>,
+    maintenance: MaintenanceCounter,
+    #[cfg(feature = "hdrhistogram")]
+    latencies: Latencies,
+    #[cfg(feature = "hyperloglog")]
+    distinct_keys: HyperLogLog,
+}
+
+impl StatsCounter {
+    #[inline]
+    pub(crate) fn record_hit(&self) {
+        self.hit_count.fetch_add(1);
+    }
+
+    #[inline]
+    pub(crate) fn record_miss(&self) {
+        self.miss_count.fetch_add(1);
+    }
+
+    #[inline]
+    pub(crate) fn record_expiration(&self) {
+        self.expired_count.fetch_add(1);
+    }
+
+    #[inline]
+    pub(crate) fn record_eviction(&self) {
+        self.evicted_count.fetch_add(1);
+    }
+
+    /// Records the start of a load, and returns a timer to record its end.
+    #[inline]
+    pub(crate) fn start_load(&self) -> LoadTimer<'_> {
+        self.loads_started.fetch_add(1);
+        LoadTimer {
+            counter: self,
+            started_at: Instant::now(),
+            finished: false,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_load_deduplicated(&self) {
+        self.loads_deduplicated.fetch_add(1);
+    }
+
+    #[inline]
+    pub(crate) fn record_prefetch(&self) {
+        self.prefetches_issued.fetch_add(1);
+    }
+
+    #[inline]
+    pub(crate) fn record_prefetch_hit(&self) {
+        self.prefetch_hits.fetch_add(1);
+    }
+
+    #[cfg(feature = "future")]
+    #[inline]
+    pub(crate) fn record_dropped_eviction_events(&self, count: u64) {
+        self.dropped_eviction_events.fetch_add(count);
+    }
+
+    #[inline]
+    pub(crate) fn record_lock_contention(&self) {
+        self.lock_contentions.fetch_add(1);
+    }
+
+    pub(crate) fn lock_contentions(&self) -> u64 {
+        self.lock_contentions.load()
+    }
+
+    pub(crate) fn record_maintenance(&self, pass: MaintenancePass) {
+        self.maintenance.record(pass);
+    }
+
+    #[cfg(feature = "hyperloglog")]
+    #[inline]
+    pub(crate) fn record_key(&self, hash: u64) {
+        self.distinct_keys.record(hash);
+    }
+
+    fn record_load_end(&self, elapsed: Duration, succeeded: bool) {
+        if succeeded {
+            self.loads_completed.fetch_add(1);
+        } else {
+            self.loads_failed.fetch_add(1);
+        }
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.total_load_time_nanos.fetch_add(nanos);
+    }
+
+    /// Returns the current time if the operation should be sampled for the latency
+    /// histogram.
+    #[inline]
+    #[allow(unused_variables)]
+    pub(crate) fn start_timer(&self, kind: OpKind) -> Option<Instant> {
+        #[cfg(feature = "hdrhistogram")]
+        {
+            self.latencies.start_timer(kind)
+        }
+        #[cfg(not(feature = "hdrhistogram"))]
+        {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(unused_variables)]
+    pub(crate) fn record_latency(&self, kind: OpKind, started_at: Instant) {
+        #[cfg(feature = "hdrhistogram")]
+        self.latencies.record(kind, started_at.elapsed());
+    }
+
+    pub(crate) fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            hit_count: self.hit_count.load(),
+            miss_count: self.miss_count.load(),
+            expired_count: self.expired_count.load(),
+            evicted_count: self.evicted_count.load(),
+            loads_started: self.loads_started.load(),
+            loads_completed: self.loads_completed.load(),
+            loads_failed: self.loads_failed.load(),
+            loads_deduplicated: self.loads_deduplicated.load(),
+            total_load_time_nanos: self.total_load_time_nanos.load(),
+            prefetches_issued: self.prefetches_issued.load(),
+            prefetch_hits: self.prefetch_hits.load(),
+            dropped_eviction_events: self.dropped_eviction_events.load(),
+            lock_contentions: self.lock_contentions.load(),
+            maintenance: self.maintenance.snapshot(false),
+            #[cfg(feature = "hdrhistogram")]
+            get_latency: Some(self.latencies.get.histogram.lock().clone()),
+            #[cfg(feature = "hdrhistogram")]
+            insert_latency: Some(self.latencies.insert.histogram.lock().clone()),
+            #[cfg(feature = "hyperloglog")]
+            distinct_keys: Some(self.distinct_keys.snapshot()),
+        }
+    }
+
+    /// Returns a snapshot of the statistics and zeroes the counters. Each counter
+    /// is read and zeroed by a single atomic swap, so no event recorded
+    /// concurrently is lost or counted twice.
+    pub(crate) fn snapshot_and_reset(&self) -> CacheStats {
+        CacheStats {
+            hit_count: self.hit_count.swap(0),
+            miss_count: self.miss_count.swap(0),
+            expired_count: self.expired_count.swap(0),
+            evicted_count: self.evicted_count.swap(0),
+            loads_started: self.loads_started.swap(0),
+            loads_completed: self.loads_completed.swap(0),
+            loads_failed: self.loads_failed.swap(0),
+            loads_deduplicated: self.loads_deduplicated.swap(0),
+            total_load_time_nanos: self.total_load_time_nanos.swap(0),
+            prefetches_issued: self.prefetches_issued.swap(0),
+            prefetch_hits: self.prefetch_hits.swap(0),
+            dropped_eviction_events: self.dropped_eviction_events.swap(0),
+            lock_contentions: self.lock_contentions.swap(0),
+            maintenance: self.maintenance.snapshot(true),
+            #[cfg(feature = "hdrhistogram")]
+            get_latency: Some(self.latencies.get.take_histogram()),
+            #[cfg(feature = "hdrhistogram")]
+            insert_latency: Some(self.latencies.insert.take_histogram()),
+            // The distinct keys are counted over the lifetime of the cache, so
+            // they are not reset.
+            #[cfg(feature = "hyperloglog")]
+            distinct_keys: Some(self.distinct_keys.snapshot()),
+        }
+    }
+}
+
+/// Records the maintenance passes of a cache.
+#[derive(Default)]
+struct MaintenanceCounter {
+    pass_count: AtomicCell<u64>,
+    total_time_nanos: AtomicCell<u64>,
+    reads_drained: AtomicCell<u64>,
+    writes_drained: AtomicCell<u64>,
+    entries_expired: AtomicCell<u64>,
+    entries_evicted: AtomicCell<u64>,
+    duration_histogram: [AtomicCell<u64>; MAINTENANCE_DURATION_BUCKETS],
+}
+
+impl MaintenanceCounter {
+    fn record(&self, pass: MaintenancePass) {
+        let nanos = pass.elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let micros = nanos / 1_000;
+        // The bucket `i` holds the durations below `2^i` microseconds.
+        let bucket = (64 - micros.leading_zeros()) as usize;
+        let bucket = bucket.min(MAINTENANCE_DURATION_BUCKETS - 1);
+
+        self.pass_count.fetch_add(1);
+        self.total_time_nanos.fetch_add(nanos);
+        self.reads_drained.fetch_add(pass.reads_drained);
+        self.writes_drained.fetch_add(pass.writes_drained);
+        self.entries_expired.fetch_add(pass.entries_expired);
+        self.entries_evicted.fetch_add(pass.entries_evicted);
+        self.duration_histogram[bucket].fetch_add(1);
+    }
+
+    fn snapshot(&self, reset: bool) -> MaintenanceStats {
+        let read = |cell: &AtomicCell<u64>| if reset { cell.swap(0) } else { cell.load() };
+        let mut duration_histogram = [0; MAINTENANCE_DURATION_BUCKETS];
+        for (count, cell) in duration_histogram
+            .iter_mut()
+            .zip(self.duration_histogram.iter())
+        {
+            *count = read(cell);
+        }
+        MaintenanceStats {
+            pass_count: read(&self.pass_count),
+            total_time_nanos: read(&self.total_time_nanos),
+            reads_drained: read(&self.reads_drained),
+            writes_drained: read(&self.writes_drained),
+            entries_expired: read(&self.entries_expired),
+            entries_evicted: read(&self.entries_evicted),
+            duration_histogram,
+        }
+    }
+}
+
+/// Measures a load started by `StatsCounter::start_load`. A timer dropped without
+/// calling `finish`, e.g. by a panicking `init` closure, records a failed load.
+pub(crate) struct LoadTimer<'a> {
+    counter: &'a StatsCounter,
+    started_at: Instant,
+    finished: bool,
+}
+
+impl LoadTimer<'_> {
+    pub(crate) fn finish(mut self, succeeded: bool) {
+        self.finished = true;
+        self.counter
+            .record_load_end(self.started_at.elapsed(), succeeded);
+    }
+}
+
+impl Drop for LoadTimer<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.counter
+                .record_load_end(self.started_at.elapsed(), false);
+        }
+    }
+}
+
+/// Take one sample out of this many operations. Must be a power of two.
+#[cfg(feature = "hdrhistogram")]
+const LATENCY_SAMPLING_INTERVAL: u64 = 16;
+
+#[cfg(feature = "hdrhistogram")]
+#[derive(Default)]
+struct Latencies {
+    get: Latency,
+    insert: Latency,
+}
+
+#[cfg(feature = "hdrhistogram")]
+struct Latency {
+    op_count: AtomicCell<u64>,
+    // In nanoseconds.
+    histogram: Mutex<Histogram<u64>>,
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl Latencies {
+    fn get(&self, kind: OpKind) -> &Latency {
+        match kind {
+            OpKind::Get => &self.get,
+            OpKind::Insert => &self.insert,
+        }
+    }
+
+    #[inline]
+    fn start_timer(&self, kind: OpKind) -> Option<Instant> {
+        let count = self.get(kind).op_count.fetch_add(1);
+        if count & (LATENCY_SAMPLING_INTERVAL - 1) == 0 {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    fn record(&self, kind: OpKind, elapsed: Duration) {
+        // Do not wait for other threads recording their samples. Dropping a sample
+        // is better than adding contention to the cache being measured.
+        if let Some(mut hist) = self.get(kind).histogram.try_lock() {
+            let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+            // The histogram is auto-resizing, so this will not fail.
+            let _ = hist.record(nanos);
+        }
+    }
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl Latency {
+    fn take_histogram(&self) -> Histogram<u64> {
+        let mut hist = self.histogram.lock();
+        let taken = hist.clone();
+        hist.reset();
+        taken
+    }
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            op_count: Default::default(),
+            // Three significant digits keeps the error under 0.1%. The histogram
+            // grows its range as larger values are recorded.
+            histogram: Mutex::new(Histogram::new(3).expect("Failed to create a histogram")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheStats, StatsCounter};
+
+    #[test]
+    fn hit_rate() {
+        let counter = StatsCounter::default();
+        assert_eq!(counter.snapshot().hit_rate(), 1.0);
+
+        counter.record_hit();
+        counter.record_hit();
+        counter.record_hit();
+        counter.record_miss();
+
+        let stats = counter.snapshot();
+        assert_eq!(stats.hit_count(), 3);
+        assert_eq!(stats.miss_count(), 1);
+        assert_eq!(stats.request_count(), 4);
+        assert_eq!(stats.hit_rate(), 0.75);
+
+        let mut merged = CacheStats::default();
+        merged.merge(&stats);
+        merged.merge(&stats);
+        assert_eq!(merged.request_count(), 8);
+    }
+
+    #[test]
+    fn load_timer() {
+        use std::time::Duration;
+
+        let counter = StatsCounter::default();
+        assert_eq!(counter.snapshot().average_load_time(), Duration::default());
+
+        counter.start_load().finish(true);
+        counter.start_load().finish(false);
+        // Dropped without finishing, e.g. by a panic.
+        drop(counter.start_load());
+        counter.record_load_deduplicated();
+
+        let stats = counter.snapshot();
+        assert_eq!(stats.loads_started(), 3);
+        assert_eq!(stats.loads_completed(), 1);
+        assert_eq!(stats.loads_failed(), 2);
+        assert_eq!(stats.loads_deduplicated(), 1);
+
+        let mut merged = CacheStats::default();
+        merged.merge(&stats);
+        merged.merge(&stats);
+        assert_eq!(merged.loads_started(), 6);
+        assert_eq!(merged.average_load_time(), stats.average_load_time());
+    }
+
+    #[test]
+    fn snapshot_and_reset() {
+        let counter = StatsCounter::default();
+        counter.record_hit();
+        counter.record_miss();
+        counter.record_eviction();
+        counter.start_load().finish(true);
+
+        let stats = counter.snapshot_and_reset();
+        assert_eq!(stats.request_count(), 2);
+        assert_eq!(stats.evicted_count(), 1);
+        assert_eq!(stats.loads_completed(), 1);
+
+        let stats = counter.snapshot();
+        assert_eq!(stats.request_count(), 0);
+        assert_eq!(stats.evicted_count(), 0);
+        assert_eq!(stats.loads_started(), 0);
+        assert_eq!(stats.average_load_time(), std::time::Duration::default());
+
+        counter.record_hit();
+        assert_eq!(counter.snapshot_and_reset().hit_count(), 1);
+    }
+
+    #[test]
+    fn maintenance_stats() {
+        use super::{MaintenancePass, MAINTENANCE_DURATION_BUCKETS};
+        use std::time::Duration;
+
+        let counter = StatsCounter::default();
+        assert_eq!(
+            counter.snapshot().maintenance().duration_percentile(99.0),
+            None
+        );
+
+        let pass = |micros| MaintenancePass {
+            elapsed: Duration::from_micros(micros),
+            reads_drained: 10,
+            writes_drained: 5,
+            entries_expired: 2,
+            entries_evicted: 1,
+        };
+        for _ in 0..98 {
+            counter.record_maintenance(pass(100));
+        }
+        counter.record_maintenance(pass(0));
+        counter.record_maintenance(pass(u64::MAX / 1_000));
+
+        let stats = counter.snapshot_and_reset();
+        let m = stats.maintenance();
+        assert_eq!(m.pass_count(), 100);
+        assert_eq!(m.reads_drained(), 1000);
+        assert_eq!(m.writes_drained(), 500);
+        assert_eq!(m.entries_expired(), 200);
+        assert_eq!(m.entries_evicted(), 100);
+        // 100 microseconds falls in the bucket of 64..128 microseconds.
+        assert_eq!(m.duration_histogram()[0], 1);
+        assert_eq!(m.duration_histogram()[7], 98);
+        assert_eq!(m.duration_histogram()[MAINTENANCE_DURATION_BUCKETS - 1], 1);
+        assert_eq!(m.duration_percentile(0.0), Some(Duration::from_micros(1)));
+        assert_eq!(
+            m.duration_percentile(50.0),
+            Some(Duration::from_micros(128))
+        );
+        assert_eq!(m.duration_percentile(100.0), Some(Duration::MAX));
+
+        let mut merged = CacheStats::default();
+        merged.merge(&stats);
+        merged.merge(&stats);
+        assert_eq!(merged.maintenance().pass_count(), 200);
+        assert_eq!(merged.maintenance().duration_histogram()[7], 196);
+
+        assert_eq!(counter.snapshot().maintenance().pass_count(), 0);
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn latency_percentile() {
+        use super::OpKind;
+        use std::time::Duration;
+
+        let counter = StatsCounter::default();
+        assert_eq!(counter.snapshot().get_latency_percentile(99.0), None);
+
+        for _ in 0..100 {
+            if let Some(started_at) = counter.start_timer(OpKind::Get) {
+                counter.record_latency(OpKind::Get, started_at - Duration::from_micros(10));
+            }
+        }
+        let stats = counter.snapshot();
+        let p50 = stats.get_latency_percentile(50.0).unwrap();
+        assert!(p50 >= Duration::from_micros(10));
+        assert!(p50 < Duration::from_secs(1));
+        assert_eq!(stats.insert_latency_percentile(50.0), None);
+
+        // 100 operations with the sampling interval of 16.
+        assert_eq!(stats.get_latency.unwrap().len(), 7);
+    }
+}
@@ -0,0 +1,53 @@
+/// Returns the total memory available to this process in bytes, or `None` if it
+/// cannot be determined on this platform.
+///
+/// On Linux, this is the `MemTotal` of `/proc/meminfo`, or the memory limit of
+/// the cgroup of the process if it is lower (e.g. in a container).
+#[cfg(target_os = "linux")]
+pub(crate) fn total_memory() -> Option<u64> {
+    use std::fs::read_to_string;
+
+    let total = read_to_string("/proc/meminfo")
+        .ok()
+        .as_deref()
+        .and_then(parse_mem_total)?;
+    let cgroup_limit = [
+        "/sys/fs/cgroup/memory.max",
+        "/sys/fs/cgroup/memory/memory.limit_in_bytes",
+    ]
+    .iter()
+    .filter_map(|path| read_to_string(path).ok())
+    .find_map(|s| s.trim().parse::<u64>().ok());
+    Some(cgroup_limit.map_or(total, |limit| limit.min(total)))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn total_memory() -> Option<u64> {
+    None
+}
+
+/// Parses the `MemTotal: <n> kB` line of `/proc/meminfo` into bytes.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn parse_mem_total(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let mut fields = line["MemTotal:".len()..].split_whitespace();
+    let value = fields.next()?.parse::<u64>().ok()?;
+    match fields.next() {
+        Some("kB") => value.checked_mul(1024),
+        None => Some(value),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_mem_total;
+
+    #[test]
+    fn parse_mem_total_line() {
+        let meminfo = "MemTotal:       16303868 kB\nMemFree:         1234567 kB\n";
+        assert_eq!(parse_mem_total(meminfo), Some(16_303_868 * 1024));
+        assert_eq!(parse_mem_total("MemFree: 1 kB\n"), None);
+        assert_eq!(parse_mem_total("MemTotal: many kB\n"), None);
+    }
+}
@@ -9,6 +9,7 @@ static REGISTRY: Lazy<ThreadPoolRegistry> = Lazy::new(ThreadPoolRegistry::defaul
 pub(crate) enum PoolName {
     Housekeeper,
     Invalidator,
+    Loader,
 }
 
 impl PoolName {
@@ -16,6 +17,7 @@ impl PoolName {
         match self {
             PoolName::Housekeeper => "moka-housekeeper-{}",
             PoolName::Invalidator => "moka-invalidator-{}",
+            PoolName::Loader => "moka-loader-{}",
         }
     }
 }
@@ -57,8 +59,10 @@ impl ThreadPoolRegistry {
                     // https://github.com/moka-rs/moka/pull/39#issuecomment-916888859
                     // https://github.com/seanmonstar/num_cpus/issues/69
                     let num_threads = num_cpus::get().max(1);
-                    let pool =
-                        ScheduledThreadPool::with_name(name.thread_name_template(), num_threads);
+                    let pool = ScheduledThreadPool::builder()
+                        .num_threads(num_threads)
+                        .thread_name_pattern(name.thread_name_template())
+                        .build();
                     let t_pool = ThreadPool {
                         name,
                         pool,
@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 pub(crate) type Clock = quanta::Clock;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 pub(crate) type Mock = quanta::Mock;
 
 /// a wrapper type over qunta::Instant to force checked additions and prevent
@@ -23,6 +23,36 @@ impl Instant {
     pub(crate) fn now() -> Instant {
         Instant(quanta::Instant::now())
     }
+
+    /// Rounds this instant up to the next multiple of `tick`. Returns the instant
+    /// as is if `tick` is zero or the result overflows.
+    pub(crate) fn round_up_to(self, tick: Duration) -> Instant {
+        let tick = tick.as_nanos() as u64;
+        if tick == 0 {
+            return self;
+        }
+        match self.0.as_u64() % tick {
+            0 => self,
+            rem => self
+                .checked_add(Duration::from_nanos(tick - rem))
+                .unwrap_or(self),
+        }
+    }
+
+    /// Converts this instant into a `std::time::Instant`, using `now` and
+    /// `std_now` taken at (roughly) the same moment as the reference points.
+    pub(crate) fn to_std_instant(
+        self,
+        now: Instant,
+        std_now: std::time::Instant,
+    ) -> std::time::Instant {
+        if self >= now {
+            std_now + self.0.duration_since(now.0)
+        } else {
+            let elapsed = now.0.duration_since(self.0);
+            std_now.checked_sub(elapsed).unwrap_or(std_now)
+        }
+    }
 }
 
 impl CheckedTimeOps for Instant {
@@ -21,12 +21,12 @@ pub(crate) struct UnsafeWeakPointer {
 impl UnsafeWeakPointer {
     pub(crate) fn from_weak_arc<T>(p: Weak<T>) -> Self {
         Self {
-            raw_ptr: unsafe { std::mem::transmute(p) },
+            raw_ptr: unsafe { std::mem::transmute::<Weak<T>, usize>(p) },
         }
     }
 
     pub(crate) unsafe fn as_weak_arc<T>(&self) -> Weak<T> {
-        std::mem::transmute(self.raw_ptr)
+        std::mem::transmute::<usize, Weak<T>>(self.raw_ptr)
     }
 
     pub(crate) fn forget_arc<T>(p: Arc<T>) {
@@ -0,0 +1,195 @@
+//! Provides the process-level defaults of the cache builders.
+//!
+//! The defaults are set once by [`set`](./fn.set.html), before any cache builder
+//! is created, and cannot be changed afterwards. They are read when
+//! `CacheBuilder::new` or `CacheBuilder::default` of `sync` and `future` modules
+//! is called, so the settings made on the builder afterwards take precedence.
+//! Caches created by `Cache::new` and `SegmentedCache::new` do not use the
+//! defaults.
+//!
+//! The applied defaults can be seen by the getters of the built cache, e.g.
+//! `name` and `time_to_live`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use moka::{
+//!     defaults::{self, DefaultsBuilder, TtlCapPolicy},
+//!     sync::CacheBuilder,
+//!     BuildError,
+//! };
+//! use std::time::Duration;
+//!
+//! // Call this at the start of the process.
+//! defaults::set(
+//!     DefaultsBuilder::new()
+//!         .record_stats(true)
+//!         .name_prefix("payments.")
+//!         .max_allowed_ttl(Duration::from_secs(3600), TtlCapPolicy::Clamp),
+//! )
+//! .unwrap();
+//!
+//! let cache = CacheBuilder::new(100).name("users").build();
+//! cache.insert(1, "alice");
+//! cache.get(&1);
+//!
+//! assert_eq!(cache.name(), Some("payments.users"));
+//! assert_eq!(cache.time_to_live(), Some(Duration::from_secs(3600)));
+//! assert_eq!(cache.stats().hit_count(), 1);
+//!
+//! // The defaults cannot be changed once set.
+//! assert!(defaults::set(DefaultsBuilder::new()).is_err());
+//! ```
+
+use crate::DefaultsError;
+
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+
+static DEFAULTS: OnceCell<Defaults> = OnceCell::new();
+
+/// Sets the process-level defaults of the cache builders.
+///
+/// # Errors
+///
+/// Returns [`DefaultsError::AlreadySet`][already-set] if the defaults have
+/// already been set, or a cache builder has already been created, which fixes
+/// the defaults to the empty ones.
+///
+/// [already-set]: ../enum.DefaultsError.html#variant.AlreadySet
+pub fn set(builder: DefaultsBuilder) -> Result<(), DefaultsError> {
+    DEFAULTS
+        .set(builder.defaults)
+        .map_err(|_| DefaultsError::AlreadySet)
+}
+
+/// Returns the process-level defaults, fixing them to the empty ones if they
+/// have not been set yet.
+pub fn get() -> &'static Defaults {
+    DEFAULTS.get_or_init(Defaults::default)
+}
+
+/// What the cache builders do when the time-to-live of a cache exceeds the
+/// `max_allowed_ttl` of the defaults, or is not set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtlCapPolicy {
+    /// `try_build` returns [`BuildError::TimeToLiveNotAllowed`][not-allowed],
+    /// and `build` panics.
+    ///
+    /// [not-allowed]: ../enum.BuildError.html#variant.TimeToLiveNotAllowed
+    Reject,
+    /// The time-to-live is lowered to `max_allowed_ttl`. A cache without a
+    /// time-to-live gets `max_allowed_ttl`.
+    Clamp,
+}
+
+/// The process-level defaults of the cache builders. See the [module
+/// documentation](./index.html) for the details.
+#[derive(Clone, Debug, Default)]
+pub struct Defaults {
+    record_stats: bool,
+    name_prefix: Option<String>,
+    max_allowed_ttl: Option<(Duration, TtlCapPolicy)>,
+}
+
+impl Defaults {
+    /// Returns `true` if the caches record the statistics by default.
+    pub fn record_stats(&self) -> bool {
+        self.record_stats
+    }
+
+    /// Returns the prefix added to the cache names.
+    pub fn name_prefix(&self) -> Option<&str> {
+        self.name_prefix.as_deref()
+    }
+
+    /// Returns the cap of the time-to-live and the policy to enforce it.
+    pub fn max_allowed_ttl(&self) -> Option<(Duration, TtlCapPolicy)> {
+        self.max_allowed_ttl
+    }
+
+    pub(crate) fn prefixed_name(&self, name: &str) -> String {
+        match &self.name_prefix {
+            Some(prefix) => format!("{}{}", prefix, name),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Builds the [`Defaults`](./struct.Defaults.html) given to
+/// [`set`](./fn.set.html).
+#[derive(Default)]
+#[must_use]
+pub struct DefaultsBuilder {
+    defaults: Defaults,
+}
+
+impl DefaultsBuilder {
+    /// Creates a `DefaultsBuilder` with no defaults set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the caches record the statistics, as if `record_stats` of
+    /// the cache builder were called.
+    pub fn record_stats(self, enabled: bool) -> Self {
+        Self {
+            defaults: Defaults {
+                record_stats: enabled,
+                ..self.defaults
+            },
+        }
+    }
+
+    /// Sets the prefix added to the names given by `name` of the cache builder.
+    /// Caches without a name stay unnamed.
+    pub fn name_prefix(self, prefix: &str) -> Self {
+        Self {
+            defaults: Defaults {
+                name_prefix: Some(prefix.to_string()),
+                ..self.defaults
+            },
+        }
+    }
+
+    /// Sets the longest time-to-live allowed for a cache. `policy` decides what
+    /// happens to a cache whose time-to-live is longer or not set.
+    ///
+    /// The cap applies to the time-to-live of the cache only. It does not limit
+    /// the time-to-idle or the `max_age`.
+    pub fn max_allowed_ttl(self, max_ttl: Duration, policy: TtlCapPolicy) -> Self {
+        Self {
+            defaults: Defaults {
+                max_allowed_ttl: Some((max_ttl, policy)),
+                ..self.defaults
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultsBuilder, TtlCapPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn defaults_builder() {
+        let defaults = DefaultsBuilder::new().defaults;
+        assert!(!defaults.record_stats());
+        assert_eq!(defaults.prefixed_name("users"), "users");
+        assert_eq!(defaults.max_allowed_ttl(), None);
+
+        let hour = Duration::from_secs(3600);
+        let defaults = DefaultsBuilder::new()
+            .record_stats(true)
+            .name_prefix("app.")
+            .max_allowed_ttl(hour, TtlCapPolicy::Reject)
+            .defaults;
+        assert!(defaults.record_stats());
+        assert_eq!(defaults.prefixed_name("users"), "app.users");
+        assert_eq!(
+            defaults.max_allowed_ttl(),
+            Some((hour, TtlCapPolicy::Reject))
+        );
+    }
+}
@@ -4,10 +4,21 @@
 
 mod builder;
 mod cache;
+pub(crate) mod eviction_stream;
+mod map_stream;
+mod miss_claim;
 mod value_initializer;
 
+pub use crate::sync::{BorrowedKey, FrozenView, MissResolution, WeightIncreasePolicy};
 pub use builder::CacheBuilder;
 pub use cache::Cache;
+pub use miss_claim::{MissClaim, MissToken, MissWaiter};
+
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+
+pub(crate) type AsyncLoader<K, V> =
+    Arc<dyn Fn(Arc<K>) -> BoxFuture<'static, V> + Send + Sync + 'static>;
 
 /// Provides extra methods that will be useful for testing.
 pub trait ConcurrentCacheExt<K, V> {
@@ -1,11 +1,27 @@
-use super::Cache;
-use crate::{common::builder_utils, sync::Weigher};
+use super::{AsyncLoader, Cache};
+use crate::{
+    common::builder_utils,
+    defaults::{self, TtlCapPolicy},
+    notification::{
+        AccessListener, BatchedListener, DropContext, EvictionListener, RemovalCause, RemovalInfo,
+    },
+    policy::SharedFrequencySketch,
+    sync::{
+        cache_config::CacheConfig, value_dropper::ValueDropper, EvictionFilter, KeyNormalizer,
+        Weigher, WeightIncreasePolicy,
+    },
+    BuildError,
+};
+
+#[cfg(feature = "tracing")]
+use crate::sync::KeyTracer;
 
 use std::{
     collections::hash_map::RandomState,
+    future::Future,
     hash::{BuildHasher, Hash},
     marker::PhantomData,
-    sync::Arc,
+    sync::{Arc, Weak},
     time::Duration,
 };
 
@@ -52,11 +68,39 @@ use std::{
 #[must_use]
 pub struct CacheBuilder<K, V, C> {
     max_capacity: Option<u64>,
+    max_weighted_capacity: Option<u64>,
+    max_capacity_memory_fraction: Option<f64>,
     initial_capacity: Option<usize>,
     weigher: Option<Weigher<K, V>>,
+    key_normalizer: Option<KeyNormalizer<K>>,
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
+    max_allowed_ttl: Option<(Duration, TtlCapPolicy)>,
     invalidator_enabled: bool,
+    stats_enabled: bool,
+    small_cache_hint: bool,
+    promote_after_hits: Option<u8>,
+    weight_increase_policy: WeightIncreasePolicy,
+    name: Option<String>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    invoke_listener_on_drop: bool,
+    shared_frequency_sketch: Option<SharedFrequencySketch>,
+    max_age: Option<Duration>,
+    max_age_spans_updates: bool,
+    expiration_tick: Option<Duration>,
+    expiration_jitter: Option<f64>,
+    eviction_filter: Option<EvictionFilter<K, V>>,
+    maintenance_interval: Option<Duration>,
+    loader: Option<AsyncLoader<K, V>>,
+    access_listener: Option<AccessListener<K, V>>,
+    access_record_throttle: Option<Duration>,
+    reservation_high_water_mark: Option<u64>,
+    collection_check: Option<fn(&V) -> bool>,
+    drop_context: DropContext,
+    #[cfg(feature = "zeroize")]
+    value_zeroizer: Option<fn(&mut V)>,
+    #[cfg(feature = "tracing")]
+    key_tracer: Option<KeyTracer<K>>,
     cache_type: PhantomData<C>,
 }
 
@@ -66,13 +110,42 @@ where
     V: Clone + Send + Sync + 'static,
 {
     fn default() -> Self {
+        let defaults = defaults::get();
         Self {
             max_capacity: None,
+            max_weighted_capacity: None,
+            max_capacity_memory_fraction: None,
             initial_capacity: None,
             weigher: None,
+            key_normalizer: None,
             time_to_live: None,
             time_to_idle: None,
+            max_allowed_ttl: defaults.max_allowed_ttl(),
             invalidator_enabled: false,
+            stats_enabled: defaults.record_stats(),
+            small_cache_hint: false,
+            promote_after_hits: None,
+            weight_increase_policy: WeightIncreasePolicy::Admit,
+            name: None,
+            eviction_listener: None,
+            invoke_listener_on_drop: false,
+            shared_frequency_sketch: None,
+            max_age: None,
+            max_age_spans_updates: false,
+            expiration_tick: None,
+            expiration_jitter: None,
+            eviction_filter: None,
+            maintenance_interval: None,
+            loader: None,
+            access_listener: None,
+            access_record_throttle: None,
+            reservation_high_water_mark: None,
+            collection_check: None,
+            drop_context: DropContext::Inline,
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: None,
+            #[cfg(feature = "tracing")]
+            key_tracer: None,
             cache_type: Default::default(),
         }
     }
@@ -99,18 +172,99 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
+    ///
+    /// Also panics if `max_capacity_memory_fraction` is set without a weigher, or
+    /// the total memory of the system is unknown.
+    ///
+    /// Also panics if the [process-level defaults][defaults] reject the
+    /// `time_to_live`.
+    ///
+    /// [defaults]: ../defaults/index.html
     pub fn build(self) -> Cache<K, V, RandomState> {
-        let build_hasher = RandomState::default();
-        builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        Cache::with_everything(
+        let build_hasher = self
+            .shared_frequency_sketch
+            .as_ref()
+            .map_or_else(RandomState::default, |s| s.build_hasher().clone());
+        let (config, loader) = self.into_parts(build_hasher);
+        Cache::with_config(config, loader)
+    }
+
+    /// Builds a `Cache<K, V>` like `build`, after checking that the capacity
+    /// is given in the unit matching the weigher setting, and that the expiration
+    /// tick is short enough.
+    ///
+    /// # Errors
+    ///
+    /// - [`BuildError::EntryCapacityWithWeigher`][entry-with-weigher] if a
+    ///   weigher is set together with `max_capacity` (including the one given to
+    ///   `CacheBuilder::new`).
+    /// - [`BuildError::WeightedCapacityWithoutWeigher`][weighted-without-weigher]
+    ///   if `max_weighted_capacity` is set without a weigher.
+    /// - [`BuildError::ExpirationTickTooLong`][tick-too-long] if the
+    ///   `expiration_tick` is longer than half of `time_to_live` or
+    ///   `time_to_idle`.
+    /// - [`BuildError::TimeToLiveNotAllowed`][ttl-not-allowed] if the
+    ///   [process-level defaults][defaults] reject the `time_to_live`.
+    /// - [`BuildError::TtiExceedsTtl`][tti-exceeds-ttl] if the `time_to_idle` is
+    ///   longer than the `time_to_live`.
+    /// - [`BuildError::MemoryFractionWithoutWeigher`][memory-without-weigher] if
+    ///   `max_capacity_memory_fraction` is set without a weigher.
+    /// - [`BuildError::TotalMemoryUnknown`][memory-unknown] if
+    ///   `max_capacity_memory_fraction` is set and the total memory of the system
+    ///   is unknown.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `build`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::future::CacheBuilder;
+    ///
+    /// let cache = CacheBuilder::default()
+    ///     .weigher(|_key: &u32, value: &String| value.len() as u32)
+    ///     .max_weighted_capacity(1024 * 1024)
+    ///     .try_build()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [entry-with-weigher]: ../enum.BuildError.html#variant.EntryCapacityWithWeigher
+    /// [weighted-without-weigher]: ../enum.BuildError.html#variant.WeightedCapacityWithoutWeigher
+    /// [tick-too-long]: ../enum.BuildError.html#variant.ExpirationTickTooLong
+    /// [ttl-not-allowed]: ../enum.BuildError.html#variant.TimeToLiveNotAllowed
+    /// [tti-exceeds-ttl]: ../enum.BuildError.html#variant.TtiExceedsTtl
+    /// [memory-without-weigher]: ../enum.BuildError.html#variant.MemoryFractionWithoutWeigher
+    /// [memory-unknown]: ../enum.BuildError.html#variant.TotalMemoryUnknown
+    /// [defaults]: ../defaults/index.html
+    pub fn try_build(self) -> Result<Cache<K, V, RandomState>, BuildError> {
+        builder_utils::ensure_capacity(
             self.max_capacity,
-            self.initial_capacity,
-            build_hasher,
-            self.weigher,
-            self.time_to_live,
+            self.max_weighted_capacity,
+            self.weigher.is_some(),
+        )?;
+        // Read the memory of the system only once.
+        let max_weighted_capacity = builder_utils::max_weighted_capacity(
+            self.max_weighted_capacity,
+            self.max_capacity_memory_fraction,
+            self.weigher.is_some(),
+        )?;
+        let time_to_live = builder_utils::time_to_live(self.time_to_live, self.max_allowed_ttl)?;
+        builder_utils::ensure_time_to_idle(time_to_live, self.time_to_idle)?;
+        builder_utils::ensure_expiration_tick(
+            self.expiration_tick,
+            time_to_live,
             self.time_to_idle,
-            self.invalidator_enabled,
-        )
+        )?;
+        Ok(Self {
+            max_weighted_capacity,
+            max_capacity_memory_fraction: None,
+            ..self
+        }
+        .build())
     }
 
     /// Builds a `Cache<K, V, S>`, with the given `hasher`.
@@ -120,25 +274,92 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
+    ///
+    /// Also panics if `max_capacity_memory_fraction` is set without a weigher, or
+    /// the total memory of the system is unknown.
+    ///
+    /// Also panics if the [process-level defaults][defaults] reject the
+    /// `time_to_live`.
+    ///
+    /// [defaults]: ../defaults/index.html
     pub fn build_with_hasher<S>(self, hasher: S) -> Cache<K, V, S>
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
-        builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        Cache::with_everything(
-            self.max_capacity,
-            self.initial_capacity,
-            hasher,
-            self.weigher,
-            self.time_to_live,
-            self.time_to_idle,
-            self.invalidator_enabled,
-        )
+        let (config, loader) = self.into_parts(hasher);
+        Cache::with_config(config, loader)
+    }
+}
+
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Fills the configuration of the cache to build, and returns it with the
+    /// loader.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the cases described in `build`.
+    fn into_parts<S>(self, build_hasher: S) -> (CacheConfig<K, V, S>, Option<AsyncLoader<K, V>>) {
+        let time_to_live =
+            builder_utils::time_to_live_or_panic(self.time_to_live, self.max_allowed_ttl);
+        builder_utils::ensure_expirations_or_panic(time_to_live, self.time_to_idle);
+        let config = CacheConfig {
+            max_capacity: builder_utils::max_capacity(
+                self.max_capacity,
+                builder_utils::max_weighted_capacity_or_panic(
+                    self.max_weighted_capacity,
+                    self.max_capacity_memory_fraction,
+                    self.weigher.is_some(),
+                ),
+                self.weigher.is_some(),
+            ),
+            initial_capacity: self.initial_capacity,
+            weigher: self.weigher,
+            key_normalizer: self.key_normalizer,
+            time_to_live,
+            time_to_idle: self.time_to_idle,
+            invalidator_enabled: self.invalidator_enabled,
+            stats_enabled: self.stats_enabled,
+            small_cache_hint: self.small_cache_hint,
+            promote_after_hits: self.promote_after_hits,
+            weight_increase_policy: self.weight_increase_policy,
+            name: self.name,
+            eviction_listener: self.eviction_listener,
+            invoke_listener_on_drop: self.invoke_listener_on_drop,
+            shared_frequency_sketch: self.shared_frequency_sketch,
+            max_age: builder_utils::max_age(self.max_age, self.max_age_spans_updates),
+            expiration_tick: self.expiration_tick,
+            expiration_jitter: self.expiration_jitter,
+            eviction_filter: self.eviction_filter,
+            maintenance_interval: self.maintenance_interval,
+            access_listener: self.access_listener,
+            access_record_throttle: self.access_record_throttle,
+            reservation_high_water_mark: self.reservation_high_water_mark,
+            collection_check: self.collection_check,
+            value_dropper: ValueDropper::new(self.drop_context),
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: self.value_zeroizer,
+            #[cfg(feature = "tracing")]
+            key_tracer: self.key_tracer,
+            ..CacheConfig::new(build_hasher)
+        };
+        (config, self.loader)
     }
 }
 
 impl<K, V, C> CacheBuilder<K, V, C> {
-    /// Sets the max capacity of the cache.
+    /// Sets the max capacity of the cache as a number of entries.
+    ///
+    /// For compatibility, the `build` methods interpret it as the total weight of
+    /// the entries when a [`weigher`](#method.weigher) is set. `try_build`
+    /// rejects that combination; use
+    /// [`max_weighted_capacity`](#method.max_weighted_capacity) with a weigher.
     pub fn max_capacity(self, max_capacity: u64) -> Self {
         Self {
             max_capacity: Some(max_capacity),
@@ -146,6 +367,44 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the max capacity of the cache as the total weight of the entries
+    /// computed by the [`weigher`](#method.weigher).
+    ///
+    /// The `build` methods interpret `max_capacity` as the total weight too when
+    /// a weigher is set, while `try_build` requires this method to be used with a
+    /// weigher, to make the interpretation explicit.
+    pub fn max_weighted_capacity(self, max_weighted_capacity: u64) -> Self {
+        Self {
+            max_weighted_capacity: Some(max_weighted_capacity),
+            ..self
+        }
+    }
+
+    /// Sets the max capacity of the cache as a fraction of the total memory of
+    /// the system, e.g. `0.25` for a quarter of it. The cache must have a
+    /// [`weigher`](#method.weigher) returning the approximate size of the entries
+    /// in bytes.
+    ///
+    /// See
+    /// [`sync::CacheBuilder::max_capacity_memory_fraction`][sync-memory-fraction]
+    /// for the details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not greater than `0.0` and less than `1.0`.
+    ///
+    /// [sync-memory-fraction]: ../sync/struct.CacheBuilder.html#method.max_capacity_memory_fraction
+    pub fn max_capacity_memory_fraction(self, fraction: f64) -> Self {
+        assert!(
+            fraction > 0.0 && fraction < 1.0,
+            "max_capacity_memory_fraction must be greater than 0.0 and less than 1.0"
+        );
+        Self {
+            max_capacity_memory_fraction: Some(fraction),
+            ..self
+        }
+    }
+
     /// Sets the initial capacity (number of entries) of the cache.
     pub fn initial_capacity(self, number_of_entries: usize) -> Self {
         Self {
@@ -154,6 +413,16 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the name of the cache. The name is used to tell caches apart in the
+    /// events emitted with the `tracing` crate feature. The `name_prefix` of the
+    /// process-level defaults is added to the name.
+    pub fn name(self, name: &str) -> Self {
+        Self {
+            name: Some(defaults::get().prefixed_name(name)),
+            ..self
+        }
+    }
+
     /// Sets the weigher closure of the cache.
     ///
     /// The closure should take `&K` and `&V` as the arguments and returns a `u32`
@@ -165,6 +434,190 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the eviction listener closure of the cache. It is called with the
+    /// key, the value and the [`RemovalCause`][removal-cause] every time an entry
+    /// is removed from the cache, or its value is replaced by `insert`.
+    ///
+    /// See [`sync::CacheBuilder::eviction_listener`][sync-eviction-listener] for
+    /// the details.
+    ///
+    /// [removal-cause]: ../notification/enum.RemovalCause.html
+    /// [sync-eviction-listener]: ../sync/struct.CacheBuilder.html#method.eviction_listener
+    pub fn eviction_listener(
+        self,
+        listener: impl Fn(Arc<K>, V, RemovalCause) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(
+            !matches!(self.eviction_listener, Some(EvictionListener::Batched(_))),
+            "eviction_listener cannot be used with eviction_listener_batched"
+        );
+        Self {
+            eviction_listener: Some(EvictionListener::PerEntry(Arc::new(listener))),
+            ..self
+        }
+    }
+
+    /// Sets the eviction listener closure of the cache, which receives a
+    /// [`RemovalInfo`][removal-info] instead of a `RemovalCause`.
+    ///
+    /// See
+    /// [`sync::CacheBuilder::eviction_listener_with_info`][sync-method] for the
+    /// details.
+    ///
+    /// [removal-info]: ../notification/struct.RemovalInfo.html
+    /// [sync-method]: ../sync/struct.CacheBuilder.html#method.eviction_listener_with_info
+    pub fn eviction_listener_with_info(
+        self,
+        listener: impl Fn(Arc<K>, V, RemovalInfo) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(
+            !matches!(self.eviction_listener, Some(EvictionListener::Batched(_))),
+            "eviction_listener_with_info cannot be used with eviction_listener_batched"
+        );
+        Self {
+            eviction_listener: Some(EvictionListener::WithInfo(Arc::new(listener))),
+            ..self
+        }
+    }
+
+    /// Sets the eviction listener closure of the cache, which receives the
+    /// removals in batches of up to `max_batch_size` entries.
+    ///
+    /// See [`sync::CacheBuilder::eviction_listener_batched`][sync-method] for the
+    /// details.
+    ///
+    /// [sync-method]: ../sync/struct.CacheBuilder.html#method.eviction_listener_batched
+    pub fn eviction_listener_batched(
+        self,
+        max_batch_size: usize,
+        listener: impl Fn(&[(Arc<K>, V, RemovalCause)]) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(max_batch_size != 0, "max_batch_size must be greater than 0");
+        assert!(
+            !matches!(
+                self.eviction_listener,
+                Some(EvictionListener::PerEntry(_)) | Some(EvictionListener::WithInfo(_))
+            ),
+            "eviction_listener_batched cannot be used with eviction_listener"
+        );
+        let listener = BatchedListener::new(Box::new(listener), max_batch_size);
+        Self {
+            eviction_listener: Some(EvictionListener::Batched(Arc::new(listener))),
+            ..self
+        }
+    }
+
+    /// Sets the access listener closure of the cache. It is called with the key
+    /// and the value every time `get` and its variants find a live entry.
+    ///
+    /// See [`sync::CacheBuilder::access_listener`][sync-access-listener] for the
+    /// details.
+    ///
+    /// [sync-access-listener]: ../sync/struct.CacheBuilder.html#method.access_listener
+    pub fn access_listener(self, listener: impl Fn(&K, &V) + Send + Sync + 'static) -> Self {
+        Self {
+            access_listener: Some(Arc::new(listener)),
+            ..self
+        }
+    }
+
+    /// Sets whether to notify the eviction listener of the entries remaining in
+    /// the cache, with `RemovalCause::Shutdown`, when the last clone of the cache
+    /// is dropped. The default is `false`.
+    ///
+    /// See [`sync::CacheBuilder::invoke_listener_on_drop`][sync-on-drop] for the
+    /// details.
+    ///
+    /// [sync-on-drop]: ../sync/struct.CacheBuilder.html#method.invoke_listener_on_drop
+    pub fn invoke_listener_on_drop(self, enabled: bool) -> Self {
+        Self {
+            invoke_listener_on_drop: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the eviction filter closure of the cache. Returning `false` from the
+    /// closure vetoes evicting the entry to keep the cache within its max
+    /// capacity. The expirations and the explicit invalidations cannot be
+    /// vetoed, and a new entry heavier than the max capacity is always rejected.
+    ///
+    /// See [`sync::CacheBuilder::eviction_filter`][sync-eviction-filter] for the
+    /// details.
+    ///
+    /// [sync-eviction-filter]: ../sync/struct.CacheBuilder.html#method.eviction_filter
+    pub fn eviction_filter(
+        self,
+        filter: impl Fn(&K, &V, RemovalCause) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            eviction_filter: Some(Arc::new(filter)),
+            ..self
+        }
+    }
+
+    /// Sets the loader of the cache. The loader is a closure that receives the
+    /// key and returns a future computing its value, which is used by
+    /// [`Cache::get_loading`][get-loading].
+    ///
+    /// See [`sync::CacheBuilder::loader`][sync-loader] for the details.
+    ///
+    /// [get-loading]: ./struct.Cache.html#method.get_loading
+    /// [sync-loader]: ../sync/struct.CacheBuilder.html#method.loader
+    pub fn loader<F, Fut>(self, loader: F) -> Self
+    where
+        F: Fn(Arc<K>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = V> + Send + 'static,
+    {
+        Self {
+            loader: Some(Arc::new(move |key| Box::pin(loader(key)))),
+            ..self
+        }
+    }
+
+    /// Makes the cache record the access frequencies of the keys into the given
+    /// sketch, which can be shared with other caches.
+    ///
+    /// See [`sync::CacheBuilder::shared_frequency_sketch`][sync-shared-sketch]
+    /// for the details.
+    ///
+    /// [sync-shared-sketch]: ../sync/struct.CacheBuilder.html#method.shared_frequency_sketch
+    pub fn shared_frequency_sketch(self, sketch: SharedFrequencySketch) -> Self {
+        Self {
+            shared_frequency_sketch: Some(sketch),
+            ..self
+        }
+    }
+
+    /// Sets the key normalizer closure of the cache. The cache applies it to
+    /// every key before hashing or storing it. The closure must be idempotent.
+    ///
+    /// See [`sync::CacheBuilder::key_normalizer`][sync-key-normalizer] for the
+    /// details.
+    ///
+    /// [sync-key-normalizer]: ../sync/struct.CacheBuilder.html#method.key_normalizer
+    pub fn key_normalizer(self, normalizer: impl Fn(&K) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            key_normalizer: Some(Arc::new(normalizer)),
+            ..self
+        }
+    }
+
+    /// Sets what happens when an update increases the weight of an entry that
+    /// has been already admitted to the cache. The default is
+    /// [`WeightIncreasePolicy::Admit`][admit].
+    ///
+    /// See [`sync::CacheBuilder::on_weight_increase`][sync-on-weight-increase]
+    /// for the details.
+    ///
+    /// [admit]: ./enum.WeightIncreasePolicy.html#variant.Admit
+    /// [sync-on-weight-increase]: ../sync/struct.CacheBuilder.html#method.on_weight_increase
+    pub fn on_weight_increase(self, policy: WeightIncreasePolicy) -> Self {
+        Self {
+            weight_increase_policy: policy,
+            ..self
+        }
+    }
+
     /// Sets the time to live of the cache.
     ///
     /// A cached entry will be expired after the specified duration past from
@@ -199,6 +652,157 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the max age of the cache, a hard cap on the lifetime of the entries
+    /// measured from their creation, regardless of the accesses to them.
+    ///
+    /// See [`sync::CacheBuilder::max_age`][sync-max-age] for the details.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `duration` is longer
+    /// than 1000 years.
+    ///
+    /// [sync-max-age]: ../sync/struct.CacheBuilder.html#method.max_age
+    pub fn max_age(self, duration: Duration) -> Self {
+        Self {
+            max_age: Some(duration),
+            ..self
+        }
+    }
+
+    /// Sets a sliding time to live of the cache, which every read extends by
+    /// `extend`, up to `max_lifetime` from the creation of the entry.
+    ///
+    /// See [`sync::CacheBuilder::sliding_ttl`][sync-sliding-ttl] for the details.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if either duration is longer
+    /// than 1000 years.
+    ///
+    /// [sync-sliding-ttl]: ../sync/struct.CacheBuilder.html#method.sliding_ttl
+    pub fn sliding_ttl(self, extend: Duration, max_lifetime: Duration) -> Self {
+        Self {
+            time_to_idle: Some(extend),
+            max_age: Some(max_lifetime),
+            ..self
+        }
+    }
+
+    /// Sets whether the max age of an entry is measured from the first `insert`
+    /// of the key, rather than from the last one. The default is `false`.
+    ///
+    /// See [`sync::CacheBuilder::max_age_spans_updates`][sync-spans-updates] for
+    /// the details.
+    ///
+    /// [sync-spans-updates]: ../sync/struct.CacheBuilder.html#method.max_age_spans_updates
+    pub fn max_age_spans_updates(self, enabled: bool) -> Self {
+        Self {
+            max_age_spans_updates: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the resolution of the access times recorded for the time to idle, to
+    /// make reads cheaper at the cost of expiring idle entries up to one tick
+    /// later. The default is zero, which records the exact times.
+    ///
+    /// See [`sync::CacheBuilder::expiration_tick`][sync-tick] for the details.
+    ///
+    /// [sync-tick]: ../sync/struct.CacheBuilder.html#method.expiration_tick
+    pub fn expiration_tick(self, tick: Duration) -> Self {
+        Self {
+            expiration_tick: Some(tick),
+            ..self
+        }
+    }
+
+    /// Randomizes the time to live of every entry by up to `fraction` of it, e.g.
+    /// `0.1` for ±10%, to spread the expirations of the entries inserted at the
+    /// same time. By default, this is disabled.
+    ///
+    /// See [`sync::CacheBuilder::expiration_jitter`][sync-jitter] for the
+    /// details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not at least `0.0` and less than `1.0`.
+    ///
+    /// [sync-jitter]: ../sync/struct.CacheBuilder.html#method.expiration_jitter
+    pub fn expiration_jitter(self, fraction: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&fraction),
+            "expiration_jitter must be at least 0.0 and less than 1.0"
+        );
+        Self {
+            expiration_jitter: Some(fraction),
+            ..self
+        }
+    }
+
+    /// Sets the minimum interval between the recorded accesses of an entry, to
+    /// make the reads of hot keys cheaper at the cost of a coarser access
+    /// tracking. By default, this is disabled.
+    ///
+    /// See [`sync::CacheBuilder::access_record_throttle`][sync-throttle] for the
+    /// details.
+    ///
+    /// [sync-throttle]: ../sync/struct.CacheBuilder.html#method.access_record_throttle
+    pub fn access_record_throttle(self, throttle: Duration) -> Self {
+        Self {
+            access_record_throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Sets the limit of the weighted size of the cache plus the weight reserved
+    /// by the loads in progress, above which
+    /// [`get_with_reservation`][get-with-reservation] sheds new loads. By
+    /// default, there is no limit and loads are never shed.
+    ///
+    /// See
+    /// [`sync::CacheBuilder::reservation_high_water_mark`][sync-high-water-mark]
+    /// for the details.
+    ///
+    /// [get-with-reservation]: ./struct.Cache.html#method.get_with_reservation
+    /// [sync-high-water-mark]: ../sync/struct.CacheBuilder.html#method.reservation_high_water_mark
+    pub fn reservation_high_water_mark(self, high_water_mark: u64) -> Self {
+        Self {
+            reservation_high_water_mark: Some(high_water_mark),
+            ..self
+        }
+    }
+
+    /// Sets where the cache drops the values it removes. The default is
+    /// `DropContext::Inline`, which drops a value wherever the cache releases it.
+    ///
+    /// See [`sync::CacheBuilder::drop_values_on`][sync-drop] for the details and
+    /// the costs of the other contexts.
+    ///
+    /// [sync-drop]: ../sync/struct.CacheBuilder.html#method.drop_values_on
+    pub fn drop_values_on(self, context: DropContext) -> Self {
+        Self {
+            drop_context: context,
+            ..self
+        }
+    }
+
+    /// Sets the minimum interval between the maintenance passes run by the
+    /// housekeeper, to reduce their overhead under a very high access rate at
+    /// the cost of applying the evictions and the expirations up to one interval
+    /// late. The default is zero.
+    ///
+    /// See [`sync::CacheBuilder::maintenance_interval`][sync-interval] for the
+    /// details.
+    ///
+    /// [sync-interval]: ../sync/struct.CacheBuilder.html#method.maintenance_interval
+    pub fn maintenance_interval(self, interval: Duration) -> Self {
+        Self {
+            maintenance_interval: Some(interval),
+            ..self
+        }
+    }
+
     /// Enables support for [Cache::invalidate_entries_if][cache-invalidate-if]
     /// method.
     ///
@@ -212,6 +816,138 @@ impl<K, V, C> CacheBuilder<K, V, C> {
             ..self
         }
     }
+
+    /// Enables recording of the cache statistics, which can be retrieved by
+    /// [`Cache::stats`][cache-stats] method.
+    ///
+    /// The hit and miss counts are always recorded. With the `hdrhistogram` crate
+    /// feature, the latencies of `get` and `insert` are also sampled into
+    /// histograms. See [`sync::CacheBuilder::record_stats`][sync-record-stats] for
+    /// the sampling strategy. The latency of `insert` includes the time waiting
+    /// for the write buffer to have room.
+    ///
+    /// [cache-stats]: ./struct.Cache.html#method.stats
+    /// [sync-record-stats]: ../sync/struct.CacheBuilder.html#method.record_stats
+    pub fn record_stats(self) -> Self {
+        Self {
+            stats_enabled: true,
+            ..self
+        }
+    }
+
+    /// Hints that the cache is small, so that it can use a simpler eviction
+    /// policy.
+    ///
+    /// The hint takes effect only when the max capacity is below 32 entries and
+    /// no weigher is set. See [`sync::CacheBuilder::small`][sync-small] for the
+    /// details and the hit rate tradeoff.
+    ///
+    /// [sync-small]: ../sync/struct.CacheBuilder.html#method.small
+    pub fn small(self) -> Self {
+        Self {
+            small_cache_hint: true,
+            ..self
+        }
+    }
+
+    /// Makes the cache protect the entries that have been read `hits` times,
+    /// so that they are not evicted by a burst of new entries.
+    ///
+    /// See [`sync::CacheBuilder::promote_after_hits`][sync-promote] for the
+    /// details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hits` is 0.
+    ///
+    /// [sync-promote]: ../sync/struct.CacheBuilder.html#method.promote_after_hits
+    pub fn promote_after_hits(self, hits: u8) -> Self {
+        assert!(hits != 0, "promote_after_hits must be greater than 0");
+        Self {
+            promote_after_hits: Some(hits),
+            ..self
+        }
+    }
+}
+
+impl<K, T, C> CacheBuilder<K, Weak<T>, C> {
+    /// Makes the cache a non-owning index of objects held in `Arc`s, by caching
+    /// `Weak` references to them and forgetting the references whose objects have
+    /// been dropped.
+    ///
+    /// See [`sync::CacheBuilder::weak_values`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.CacheBuilder.html#method.weak_values
+    pub fn weak_values(self) -> Self {
+        Self {
+            collection_check: Some(|value| value.strong_count() == 0),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    V: zeroize::Zeroize,
+{
+    /// Makes the cache zeroize values when it drops them.
+    ///
+    /// A value is zeroized when its entry is physically dropped from the cache:
+    /// on eviction, expiration, `invalidate*` and replacement by `insert`, and
+    /// when the cache itself is dropped. Note that the internal concurrent hash
+    /// table defers dropping removed entries until no other thread can be reading
+    /// them, so a removed value may stay in memory for a short while.
+    ///
+    /// Clones of the value returned by `get` are not covered. Wrap the value in
+    /// `zeroize::Zeroizing` or store `Arc`s to a zeroizing type so that they are
+    /// also wiped when they are dropped.
+    ///
+    /// Requires the `zeroize` crate feature.
+    pub fn zeroize_values_on_removal(self) -> Self {
+        Self {
+            value_zeroizer: Some(<V as zeroize::Zeroize>::zeroize),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    K: std::fmt::Debug,
+{
+    /// Makes the cache emit a `tracing` event at the `DEBUG` level for every
+    /// `get`, `insert` and removal of the keys matching the predicate.
+    ///
+    /// See [`sync::CacheBuilder::trace_keys`][sync-trace-keys] for the details.
+    ///
+    /// Requires the `tracing` crate feature.
+    ///
+    /// [sync-trace-keys]: ../sync/struct.CacheBuilder.html#method.trace_keys
+    pub fn trace_keys(self, predicate: impl Fn(&K) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            key_tracer: Some(Arc::new(move |key: &K| {
+                if predicate(key) {
+                    Some(format!("{:?}", key))
+                } else {
+                    None
+                }
+            })),
+            ..self
+        }
+    }
+
+    /// Makes the cache emit `tracing` events for the key. A shorthand for
+    /// [`trace_keys`](#method.trace_keys) with an equality check.
+    ///
+    /// Requires the `tracing` crate feature.
+    pub fn trace_key(self, key: K) -> Self
+    where
+        K: Eq + Send + Sync + 'static,
+    {
+        self.trace_keys(move |k| *k == key)
+    }
 }
 
 #[cfg(test)]
@@ -247,6 +983,34 @@ mod tests {
         assert_eq!(cache.get(&'a'), Some("Alice"));
     }
 
+    #[tokio::test]
+    async fn try_build_cache() {
+        use crate::BuildError;
+
+        let weigher = |_k: &char, v: &String| v.len() as u32;
+
+        let cache = CacheBuilder::<char, String, _>::new(100)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), Some(100));
+        let cache = CacheBuilder::default()
+            .weigher(weigher)
+            .max_weighted_capacity(1000)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), Some(1000));
+
+        let result = CacheBuilder::new(100).weigher(weigher).try_build();
+        assert_eq!(result.err(), Some(BuildError::EntryCapacityWithWeigher));
+        let result = CacheBuilder::<char, String, _>::default()
+            .max_weighted_capacity(1000)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuildError::WeightedCapacityWithoutWeigher)
+        );
+    }
+
     #[tokio::test]
     #[should_panic(expected = "time_to_live is longer than 1000 years")]
     async fn build_cache_too_long_ttl() {
@@ -1,25 +1,39 @@
 use super::{
+    map_stream,
+    miss_claim::{self, Claim, MissClaim, MissToken},
     value_initializer::{InitResult, ValueInitializer},
-    CacheBuilder, ConcurrentCacheExt,
+    AsyncLoader, CacheBuilder, ConcurrentCacheExt,
 };
 use crate::{
+    common::stats::OpKind,
+    notification::RemovalCause,
     sync::{
-        base_cache::{BaseCache, HouseKeeperArc, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS},
+        base_cache::{BaseCache, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS},
+        cache_config::CacheConfig,
+        frozen_view::{FrozenView, DEFAULT_MAX_FREEZE_DURATION},
         housekeeper::InnerSync,
-        PredicateId, Weigher, WriteOp,
+        miss_claim::{ClaimState, MissClaims},
+        BorrowedKey, KvEntry, PredicateId, WriteOp,
     },
+    CacheStats, CasError, DeadlineError, Entry, FallbackError, LoadError, OccupiedError,
     PredicateError,
 };
 
-use crossbeam_channel::{Sender, TrySendError};
+#[cfg(feature = "serde")]
+use crate::policy::PolicyState;
+
+use crossbeam_channel::TrySendError;
+use futures_util::stream::Stream;
 use std::{
     any::TypeId,
     borrow::Borrow,
-    collections::hash_map::RandomState,
+    collections::{hash_map::RandomState, HashMap, HashSet},
     future::Future,
     hash::{BuildHasher, Hash},
-    sync::Arc,
-    time::Duration,
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::{Arc, Weak},
+    task::Poll,
+    time::{Duration, Instant},
 };
 
 /// A thread-safe, futures-aware concurrent in-memory cache.
@@ -256,6 +270,14 @@ use std::{
 ///
 /// [once-cell-crate]: https://crates.io/crates/once_cell
 ///
+/// # Unwind Safety
+///
+/// `Cache<K, V, S>` implements `UnwindSafe` and `RefUnwindSafe` when `K`, `V` and
+/// `S` implement `RefUnwindSafe`. See [`sync::Cache`][sync-cache-struct] for what
+/// a panic in a cache operation leaves behind.
+///
+/// [sync-cache-struct]: ../sync/struct.Cache.html#unwind-safety
+///
 /// # Hashing Algorithm
 ///
 /// By default, `Cache` uses a hashing algorithm selected to provide resistance
@@ -275,10 +297,26 @@ use std::{
 /// [build-with-hasher-method]: ./struct.CacheBuilder.html#method.build_with_hasher
 /// [ahash-crate]: https://crates.io/crates/ahash
 ///
-#[derive(Clone)]
 pub struct Cache<K, V, S = RandomState> {
     base: BaseCache<K, V, S>,
     value_initializer: Arc<ValueInitializer<K, V, S>>,
+    miss_claims: Arc<MissClaims<K, Claim<V>, S>>,
+    loader: Option<AsyncLoader<K, V>>,
+}
+
+impl<K, V, S> Clone for Cache<K, V, S> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+            value_initializer: Arc::clone(&self.value_initializer),
+            miss_claims: Arc::clone(&self.miss_claims),
+            loader: self.loader.as_ref().map(Arc::clone),
+        }
+    }
 }
 
 // TODO: https://github.com/moka-rs/moka/issues/54
@@ -299,6 +337,23 @@ where
 {
 }
 
+// See the unwind safety impls of `sync::Cache`.
+impl<K, V, S> UnwindSafe for Cache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
+impl<K, V, S> RefUnwindSafe for Cache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
 impl<K, V> Cache<K, V, RandomState>
 where
     K: Hash + Eq + Send + Sync + 'static,
@@ -311,16 +366,11 @@ where
     ///
     /// [builder-struct]: ./struct.CacheBuilder.html
     pub fn new(max_capacity: u64) -> Self {
-        let build_hasher = RandomState::default();
-        Self::with_everything(
-            Some(max_capacity),
-            None,
-            build_hasher,
-            None,
-            None,
-            None,
-            false,
-        )
+        let config = CacheConfig {
+            max_capacity: Some(max_capacity),
+            ..CacheConfig::new(RandomState::default())
+        };
+        Self::with_config(config, None)
     }
 
     /// Returns a [`CacheBuilder`][builder-struct], which can builds a `Cache` with
@@ -338,29 +388,22 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn with_everything(
-        max_capacity: Option<u64>,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
-        weigher: Option<Weigher<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+    pub(crate) fn with_config(
+        config: CacheConfig<K, V, S>,
+        loader: Option<AsyncLoader<K, V>>,
     ) -> Self {
         Self {
-            base: BaseCache::new(
-                max_capacity,
-                initial_capacity,
-                build_hasher.clone(),
-                weigher,
-                time_to_live,
-                time_to_idle,
-                invalidator_enabled,
-            ),
-            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher)),
+            base: BaseCache::new(&config),
+            value_initializer: Arc::new(ValueInitializer::with_hasher(config.build_hasher.clone())),
+            miss_claims: Arc::new(MissClaims::with_hasher(config.build_hasher)),
+            loader,
         }
     }
 
+    pub(crate) fn normalize_key(&self, key: K) -> K {
+        self.base.normalize_key(key)
+    }
+
     /// Returns a _clone_ of the value corresponding to the key.
     ///
     /// If you want to store values that will be expensive to clone, wrap them by
@@ -374,9 +417,136 @@ where
     pub fn get<Q>(&self, key: &Q) -> Option<V>
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let started_at = self.base.start_latency_timer(OpKind::Get);
+        let v = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self
+                .base
+                .get_with_hash::<K>(&key, self.base.hash::<K>(&key)),
+            None => self.base.get_with_hash(key, self.base.hash(key)),
+        };
+        self.base.record_latency(OpKind::Get, started_at);
+        v
+    }
+
+    /// Runs the closure `f` against the value corresponding to the key without
+    /// cloning the value, and returns its result.
+    ///
+    /// `f` is a plain closure, not a future, so the value is never held across
+    /// an `.await`. See
+    /// [`sync::Cache::read_with`](../sync/struct.Cache.html#method.read_with) for
+    /// details.
+    pub fn read_with<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        match self.base.normalize_borrowed_key(key) {
+            Some(key) => self
+                .base
+                .read_with_hash::<K, R>(&key, self.base.hash::<K>(&key), f),
+            None => self.base.read_with_hash(key, self.base.hash(key), f),
+        }
+    }
+
+    /// Returns a copy of the value corresponding to the key. This is the same as
+    /// [`get`](#method.get) for a `Copy` value type. See
+    /// [`sync::Cache::get_copied`](../sync/struct.Cache.html#method.get_copied)
+    /// for details.
+    pub fn get_copied<Q>(&self, key: &Q) -> Option<V>
+    where
+        V: Copy,
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, like `map[key]`
+    /// of a `HashMap`. See
+    /// [`sync::Cache::fetch`](../sync/struct.Cache.html#method.fetch) for
+    /// details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache has no value for the key.
+    #[track_caller]
+    pub fn fetch<Q>(&self, key: &Q) -> V
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key)
+            .expect("Cache::fetch: The cache has no value for the key")
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or `default` if
+    /// the cache has no value for the key. The `default` is not inserted.
+    pub fn fetch_or<Q>(&self, key: &Q, default: V) -> V
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, together with the
+    /// version of the entry. See
+    /// [`sync::Cache::get_versioned`](../sync/struct.Cache.html#method.get_versioned)
+    /// for details.
+    pub fn get_versioned<Q>(&self, key: &Q) -> Option<(V, u64)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let started_at = self.base.start_latency_timer(OpKind::Get);
+        let v = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self
+                .base
+                .get_versioned_with_hash::<K>(&key, self.base.hash::<K>(&key)),
+            None => self.base.get_versioned_with_hash(key, self.base.hash(key)),
+        };
+        self.base.record_latency(OpKind::Get, started_at);
+        v
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or
+    /// `Err(DeadlineError)` without looking up the key if the `deadline` has
+    /// already passed.
+    ///
+    /// This is the same as [`get`](#method.get) otherwise. Use it together with
+    /// [`insert_with_deadline`](#method.insert_with_deadline) to bound every
+    /// cache operation of a request by the budget of the request.
+    ///
+    /// # Which operations can time out
+    ///
+    /// The async cache never holds an internal lock across an `.await`, and the
+    /// user operations never wait for the internal locks: the locks guarding the
+    /// eviction policy are only taken by the maintenance, which a user operation
+    /// merely schedules onto the housekeeping threads. So a lookup is effectively
+    /// instantaneous, and a deadline can only cut it short before it has
+    /// started.
+    ///
+    /// The one place where an operation can wait is the bounded write buffer of
+    /// an insert (see the [backpressure](#backpressure) of `insert`), which is
+    /// what `insert_with_deadline` bounds. Loading methods such as
+    /// [`get_or_insert_with`](#method.get_or_insert_with) also wait for the init
+    /// future of another call; wrap them in the timeout of your runtime to bound
+    /// that wait.
+    pub fn get_with_deadline<Q>(
+        &self,
+        key: &Q,
+        deadline: Instant,
+    ) -> Result<Option<V>, DeadlineError>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        self.base.get_with_hash(key, self.base.hash(key))
+        if Instant::now() >= deadline {
+            return Err(DeadlineError);
+        }
+        Ok(self.get(key))
     }
 
     /// Ensures the value of the key exists by inserting the output of the init
@@ -464,14 +634,351 @@ where
     where
         F: Future<Output = V>,
     {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_hash_and_fun(key, hash, init)
+            .await
+            .into_value()
+    }
+
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but returns
+    /// the value in an `Entry`, which tells whether the value was loaded by this
+    /// call. [`Entry::is_fresh`][entry-is-fresh] returns `true` only for the call
+    /// that has resolved its `init` future.
+    ///
+    /// See [`sync::Cache::get_or_insert_with_info`][sync-method] for the details.
+    ///
+    /// [entry-is-fresh]: ../struct.Entry.html#method.is_fresh
+    /// [sync-method]: ../sync/struct.Cache.html#method.get_or_insert_with_info
+    pub async fn get_or_insert_with_info<F>(&self, key: K, init: F) -> Entry<V>
+    where
+        F: Future<Output = V>,
+    {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
         self.get_or_insert_with_hash_and_fun(key, hash, init).await
     }
 
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but takes a
+    /// closure that receives the key and returns the `init` future. The closure
+    /// is called only when the value needs to be initialized.
+    ///
+    /// The closure receives the key as an `Arc<K>`, so the future can own it
+    /// without cloning the key itself. See
+    /// [`sync::Cache::get_or_insert_with_key`][sync-method] for the details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka::future::Cache;
+    /// use std::sync::Arc;
+    ///
+    /// async fn load(id: Arc<String>) -> usize {
+    ///     id.len()
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     let value = cache.get_or_insert_with_key("user:1".to_string(), load).await;
+    ///     assert_eq!(value, 6);
+    /// }
+    /// ```
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.get_or_insert_with_key
+    pub async fn get_or_insert_with_key<F, Fut>(&self, key: K, init: F) -> V
+    where
+        F: FnOnce(Arc<K>) -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init_key = Arc::clone(&key);
+        self.get_or_insert_with_hash_and_fun(key, hash, async move { init(init_key).await })
+            .await
+            .into_value()
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key. If the key is not
+    /// in the cache, resolves the future returned by the [loader][builder-loader]
+    /// registered to the builder, inserts the value and returns a clone of it.
+    ///
+    /// Concurrent calls for the same key resolve only one loader future. See
+    /// [`sync::Cache::get_loading`][sync-method] for the details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka::future::Cache;
+    /// use std::sync::Arc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::builder()
+    ///         .loader(|id: Arc<String>| async move { id.len() })
+    ///         .build();
+    ///
+    ///     assert_eq!(cache.get_loading("user:1".to_string()).await, 6);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache was built without a loader.
+    ///
+    /// [builder-loader]: ./struct.CacheBuilder.html#method.loader
+    /// [sync-method]: ../sync/struct.Cache.html#method.get_loading
+    pub async fn get_loading(&self, key: K) -> V {
+        let loader = self
+            .loader
+            .as_ref()
+            .map(Arc::clone)
+            .expect("get_loading requires a loader set by CacheBuilder::loader");
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init_key = Arc::clone(&key);
+        self.get_or_insert_with_hash_and_fun(key, hash, async move { loader(init_key).await })
+            .await
+            .into_value()
+    }
+
+    /// Returns a future that loads the value of the key with the
+    /// [loader][builder-loader], if the key is neither in the cache nor being
+    /// loaded. Otherwise, the returned future does nothing.
+    ///
+    /// The cache does not depend on an async runtime, so it cannot run the load
+    /// in the background by itself. Spawn the returned future on your runtime
+    /// (e.g. with `tokio::spawn`) instead of awaiting it, so that the caller does
+    /// not wait for the load. The check for the key is done by this method, not by
+    /// the future, so dropping the future without polling it loses the prefetch.
+    ///
+    /// As with [`sync::Cache::prefetch`][sync-method], a `get_loading` or
+    /// `get_or_insert_with` made while the prefetch is loading waits for it
+    /// instead of loading the value again.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka::future::Cache;
+    /// use std::sync::Arc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::builder()
+    ///         .loader(|id: Arc<String>| async move { id.len() })
+    ///         .build();
+    ///
+    ///     tokio::spawn(cache.prefetch("user:1".to_string()));
+    ///     assert_eq!(cache.get_loading("user:1".to_string()).await, 6);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache was built without a loader.
+    ///
+    /// [builder-loader]: ./struct.CacheBuilder.html#method.loader
+    /// [sync-method]: ../sync/struct.Cache.html#method.prefetch
+    pub fn prefetch(&self, key: K) -> impl Future<Output = ()> + Send + 'static {
+        let loader = self
+            .loader
+            .as_ref()
+            .map(Arc::clone)
+            .expect("prefetch requires a loader set by CacheBuilder::loader");
+        let key = Arc::new(self.base.normalize_key(key));
+        let hash = self.base.hash(&key);
+        let cache = if self.base.contains_live_key(&key) || self.value_initializer.is_loading(&key)
+        {
+            None
+        } else {
+            self.base.record_prefetch();
+            Some(self.clone())
+        };
+        async move {
+            if let Some(cache) = cache {
+                cache.load_prefetched(key, hash, loader).await;
+            }
+        }
+    }
+
+    /// Loads the value for `prefetch`. Unlike `get_loading`, this does not
+    /// record a hit or a miss.
+    async fn load_prefetched(&self, key: Arc<K>, hash: u64, loader: AsyncLoader<K, V>) {
+        // Another call may have inserted the key since the prefetch was issued.
+        if self.base.contains_live_key(&key) {
+            return;
+        }
+        let init_key = Arc::clone(&key);
+        let init = async {
+            let timer = self.base.start_load();
+            let v = loader(init_key).await;
+            if let Some(timer) = timer {
+                timer.finish(true);
+            }
+            v
+        };
+        if let InitResult::Initialized(v) = self
+            .value_initializer
+            .init_or_read(Arc::clone(&key), init)
+            .await
+        {
+            self.insert_with_hash(Arc::clone(&key), hash, v).await;
+            self.base.mark_prefetched(&key);
+            self.value_initializer
+                .remove_waiter(&key, TypeId::of::<()>());
+        }
+    }
+
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but takes a
+    /// synchronous `init` closure instead of a future.
+    ///
+    /// Only one of the concurrent calls on the same key runs its closure, and the
+    /// others wait for its value without blocking their threads. The closure runs
+    /// directly in the calling task, so it blocks the executor thread while it
+    /// runs. This is fine for a short, CPU-bound computation such as deserializing
+    /// bytes already at hand. For a heavy one, consider calling
+    /// `get_or_insert_with` with a future that runs the computation by the
+    /// `spawn_blocking` of your async runtime.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     let blob = br#"{"name":"alice"}"#;
+    ///
+    ///     let value = cache
+    ///         .get_or_insert_with_sync_init("user:1", || String::from_utf8_lossy(blob).into_owned())
+    ///         .await;
+    ///     assert_eq!(value, r#"{"name":"alice"}"#);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same way as `get_or_insert_with` when the `init` closure
+    /// panics.
+    pub async fn get_or_insert_with_sync_init(&self, key: K, init: impl FnOnce() -> V) -> V {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_hash_and_fun(key, hash, async move { init() })
+            .await
+            .into_value()
+    }
+
+    /// Looks up the key, and claims the load of its value if it is absent, so
+    /// that the concurrent misses of the key are collapsed into one load done
+    /// outside of the cache.
+    ///
+    /// The owner of the claim gives the loaded value to
+    /// [`complete`](#method.complete), or gives up by
+    /// [`abandon`](#method.abandon) or by dropping the token (e.g. when its task is
+    /// cancelled). The other callers await the `wait` method of their
+    /// [`MissWaiter`][miss-waiter]. See
+    /// [`sync::Cache::claim_miss`][sync-method] for the details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka::future::{Cache, MissClaim, MissResolution};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache: Cache<u32, String> = Cache::new(100);
+    ///
+    ///     let token = match cache.claim_miss(1) {
+    ///         MissClaim::Owner(token) => token,
+    ///         _ => unreachable!(),
+    ///     };
+    ///     let waiter = match cache.claim_miss(1) {
+    ///         MissClaim::Pending(waiter) => waiter,
+    ///         _ => unreachable!(),
+    ///     };
+    ///     let handle = tokio::spawn(waiter.wait());
+    ///
+    ///     cache.complete(token, "one".to_string()).await;
+    ///
+    ///     assert_eq!(
+    ///         handle.await.unwrap(),
+    ///         MissResolution::Completed("one".to_string())
+    ///     );
+    /// }
+    /// ```
+    ///
+    /// [miss-waiter]: ./struct.MissWaiter.html
+    /// [sync-method]: ../sync/struct.Cache.html#method.claim_miss
+    pub fn claim_miss(&self, key: K) -> MissClaim<K, V, S> {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        match self.base.get_with_hash(&key, hash) {
+            Some(v) => MissClaim::Hit(v),
+            None => miss_claim::claim(&self.miss_claims, key, hash),
+        }
+    }
+
+    /// Inserts the value of the key claimed by [`claim_miss`](#method.claim_miss),
+    /// and wakes up the callers waiting for the claim with clones of the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the token was returned by another cache.
+    pub async fn complete(&self, mut token: MissToken<K, V, S>, value: V) {
+        assert!(
+            token.is_claimed_from(&self.miss_claims),
+            "The miss was claimed from another cache"
+        );
+        let (key, hash) = token.key_and_hash();
+        self.insert_with_hash(Arc::clone(key), hash, value.clone())
+            .await;
+        token.resolve(ClaimState::Completed(value));
+    }
+
+    /// Gives up the claim of the key claimed by
+    /// [`claim_miss`](#method.claim_miss), and wakes up the callers waiting for
+    /// the claim with `MissResolution::Retry`. Same as dropping the token.
+    pub fn abandon(&self, token: MissToken<K, V, S>) {
+        drop(token);
+    }
+
     /// Try to ensure the value of the key exists by inserting an `Ok` output of the
-    /// init future if not exist, and returns a _clone_ of the value or the `Err`
-    /// produced by the future.
+    /// init future if not exist, and returns a _clone_ of the value or a
+    /// [`LoadError::Loader`][load-error] holding the `Err` produced by the future.
     ///
     /// This method prevents to resolve the init future multiple times on the same
     /// key even if the method is concurrently called by many async tasks; only one
@@ -559,53 +1066,597 @@ where
     /// 0, 1 and 3 above), this method will restart and resolve one of the remaining
     /// `init` futures.
     ///
-    pub async fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    /// A waiting call that keeps seeing the `init` futures of the other calls
+    /// panic gives up after a while, and returns `LoadError::OwnerPanicked`
+    /// instead of panicking.
+    ///
+    /// [load-error]: ../enum.LoadError.html
+    pub async fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, LoadError<E>>
     where
         F: Future<Output = Result<V, E>>,
         E: Send + Sync + 'static,
     {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
         self.get_or_try_insert_with_hash_and_fun(key, hash, init)
             .await
     }
 
-    /// Inserts a key-value pair into the cache.
+    /// Same as [`get_or_try_insert_with`](#method.get_or_try_insert_with), but
+    /// resolves the `fallback` future when the `primary` future resolves to an
+    /// `Err`. Only an `Ok` result of either future is inserted into the cache.
     ///
-    /// If the cache has this key present, the value is updated.
-    pub async fn insert(&self, key: K, value: V) {
+    /// See
+    /// [`sync::Cache::get_or_try_insert_with_fallback`][sync-with-fallback] for
+    /// the details.
+    ///
+    /// [sync-with-fallback]: ../sync/struct.Cache.html#method.get_or_try_insert_with_fallback
+    pub async fn get_or_try_insert_with_fallback<F1, F2, E1, E2>(
+        &self,
+        key: K,
+        primary: F1,
+        fallback: F2,
+    ) -> Result<V, LoadError<FallbackError<E1, E2>>>
+    where
+        F1: Future<Output = Result<V, E1>>,
+        F2: Future<Output = Result<V, E2>>,
+        E1: Send + Sync + 'static,
+        E2: Send + Sync + 'static,
+    {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
-        self.insert_with_hash(key, hash, value).await
+        let init = async {
+            match primary.await {
+                Ok(v) => Ok(v),
+                Err(primary) => fallback
+                    .await
+                    .map_err(|fallback| FallbackError { primary, fallback }),
+            }
+        };
+        self.get_or_try_insert_with_hash_and_fun(key, hash, init)
+            .await
     }
 
-    /// Blocking [insert](#method.insert) to call outside of asynchronous contexts.
-    ///
-    /// This method is intended for use cases where you are inserting from
-    /// synchronous code.
-    pub fn blocking_insert(&self, key: K, value: V) {
+    /// Same as [`get_or_try_insert_with`](#method.get_or_try_insert_with), but
+    /// takes a closure that receives the key and returns the `init` future, like
+    /// [`get_or_insert_with_key`](#method.get_or_insert_with_key).
+    pub async fn get_or_try_insert_with_key<F, Fut, E>(
+        &self,
+        key: K,
+        init: F,
+    ) -> Result<V, LoadError<E>>
+    where
+        F: FnOnce(Arc<K>) -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+    {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
-        let op = self.base.do_insert_with_hash(key, hash, value);
-        let hk = self.base.housekeeper.as_ref();
-        Self::blocking_schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to insert");
+        let init_key = Arc::clone(&key);
+        self.get_or_try_insert_with_hash_and_fun(key, hash, async move { init(init_key).await })
+            .await
     }
 
-    /// Discards any cached value for the key.
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but reserves
+    /// the `estimated_weight` of the value before resolving the `init` future, or
+    /// returns a `LoadError::Shed` without resolving it if the reservation would
+    /// take the cache over its reservation high-water mark.
     ///
-    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// The weight is released when the `init` future resolves, panics or is
+    /// dropped. See
+    /// [`sync::Cache::get_with_reservation`][sync-get-with-reservation] for the
+    /// details.
+    ///
+    /// [sync-get-with-reservation]: ../sync/struct.Cache.html#method.get_with_reservation
+    pub async fn get_with_reservation<F>(
+        &self,
+        key: K,
+        estimated_weight: u32,
+        init: F,
+    ) -> Result<V, LoadError>
+    where
+        F: Future<Output = V>,
+    {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init = async {
+            let _reservation = self.base.try_reserve_weight(estimated_weight)?;
+            Ok(init.await)
+        };
+        self.get_or_try_insert_with_hash_and_fun(key, hash, init)
+            .await
+            .map_err(LoadError::from_shed_load)
+    }
+
+    /// Returns the total weight reserved by the calls of
+    /// [`get_with_reservation`](#method.get_with_reservation) loading their
+    /// values.
+    pub fn reserved_weight(&self) -> u64 {
+        self.base.reserved_weight()
+    }
+
+    /// Returns the values of the keys, loading the missed ones by a single call
+    /// of the `loader`.
+    ///
+    /// The keys found in the cache are returned without calling the `loader`.
+    /// The rest are passed to the `loader` at once, and the values it returns are
+    /// inserted into the cache. The `loader` is not called when all keys are hit.
+    ///
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), this method
+    /// prevents to load the same key multiple times even if it is concurrently
+    /// called by many async tasks with overlapping keys. A key that is being
+    /// loaded by another call is not passed to the `loader`; this call waits for
+    /// the other call to finish loading it instead.
+    ///
+    /// The loads are coordinated only among the calls of this method and
+    /// [`map_stream`](#method.map_stream). A key being loaded by
+    /// `get_or_insert_with` or `get_or_try_insert_with` is not waited for, so it
+    /// may be loaded by both.
+    ///
+    /// # Keys not returned by the loader
+    ///
+    /// A key absent from the map returned by the `loader` is omitted from the
+    /// returned map, and nothing is inserted into the cache for it. The same
+    /// applies to a key that this call has waited for, when the other call's
+    /// `loader` has not returned it, has panicked or has been aborted. Keys
+    /// returned by the `loader` but not requested are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka::future::Cache;
+    /// use std::collections::HashMap;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert(2, "two".to_string()).await;
+    ///
+    ///     let loader = |keys: Vec<u32>| async move {
+    ///         // Key 3 does not exist in the data source.
+    ///         keys.into_iter()
+    ///             .filter(|k| *k != 3)
+    ///             .map(|k| (k, k.to_string()))
+    ///             .collect::<HashMap<_, _>>()
+    ///     };
+    ///     let values = cache.bulk_get_or_insert_with(vec![1, 2, 3], loader).await;
+    ///
+    ///     assert_eq!(values.len(), 2);
+    ///     assert_eq!(values.get(&1), Some(&"1".to_string()));
+    ///     assert_eq!(values.get(&2), Some(&"two".to_string()));
+    ///     assert_eq!(cache.get(&3), None);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the future returned by the `loader` has been
+    /// panicked. The keys it was loading are released, so other calls waiting for
+    /// them will omit them.
+    pub async fn bulk_get_or_insert_with<I, F, Fut>(&self, keys: I, loader: F) -> HashMap<K, V>
+    where
+        K: Clone,
+        I: IntoIterator<Item = K>,
+        F: FnOnce(Vec<K>) -> Fut,
+        Fut: Future<Output = HashMap<K, V>>,
+    {
+        let mut values = HashMap::new();
+        let mut misses = Vec::new();
+        let mut seen_misses = HashSet::new();
+        for key in keys {
+            let key = self.base.normalize_key(key);
+            if values.contains_key(&key) || seen_misses.contains(&key) {
+                continue;
+            }
+            let hash = self.base.hash(&key);
+            match self.base.get_with_hash(&key, hash) {
+                Some(v) => {
+                    values.insert(key, v);
+                }
+                None => {
+                    seen_misses.insert(key.clone());
+                    misses.push(key);
+                }
+            }
+        }
+        if misses.is_empty() {
+            return values;
+        }
+
+        let misses = misses.into_iter().map(Arc::new).collect();
+        let loader = |keys: Vec<Arc<K>>| loader(keys.iter().map(|k| K::clone(k)).collect());
+        // The guard removes the waiters of the loaded keys even if this future
+        // is dropped while inserting them.
+        let (results, _loaded_waiters) = self
+            .value_initializer
+            .bulk_init_or_read(misses, loader)
+            .await;
+
+        for (key, value, loaded) in results {
+            if let Some(v) = value {
+                if loaded {
+                    let hash = self.base.hash(&key);
+                    self.insert_with_hash(Arc::clone(&key), hash, v.clone())
+                        .await;
+                }
+                values.insert(K::clone(&key), v);
+            }
+        }
+        values
+    }
+
+    /// Maps a stream of keys to a stream of `(key, value)` pairs, loading the
+    /// values of missed keys in batches.
+    ///
+    /// A key found in the cache is emitted as soon as all preceding keys have been
+    /// emitted. Missed keys are buffered until `max_batch` misses have been
+    /// collected or `max_delay` has passed since the first miss of the batch,
+    /// whichever comes first. Then the `loader` is called once with the (deduped)
+    /// missed keys. The values returned by the `loader` are inserted into the
+    /// cache, and keys absent from the returned map are emitted with `None`.
+    ///
+    /// The output preserves the order of the input keys. Note that a hit
+    /// arriving while a batch is pending is held back until the batch has been
+    /// loaded.
+    ///
+    /// The missed keys are loaded through
+    /// [`bulk_get_or_insert_with`](#method.bulk_get_or_insert_with): a key being
+    /// loaded by another stream or `bulk_get_or_insert_with` call is not passed
+    /// to the `loader`, and the stream waits for the other loading to finish
+    /// instead. Like that method, the stream does not wait for the loads of
+    /// `get_or_insert_with` or `get_or_try_insert_with`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// // futures = "0.3"
+    ///
+    /// use moka::future::Cache;
+    /// use futures_util::stream::{self, StreamExt};
+    /// use std::{collections::HashMap, time::Duration};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert(2, "two".to_string()).await;
+    ///
+    ///     let keys = stream::iter(vec![1, 2, 3]);
+    ///     let loader = |keys: Vec<u32>| async move {
+    ///         keys.into_iter()
+    ///             .map(|k| (k, k.to_string()))
+    ///             .collect::<HashMap<_, _>>()
+    ///     };
+    ///     let results = cache
+    ///         .map_stream(keys, loader, 16, Duration::from_millis(10))
+    ///         .collect::<Vec<_>>()
+    ///         .await;
+    ///
+    ///     assert_eq!(
+    ///         results,
+    ///         vec![
+    ///             (1, Some("1".to_string())),
+    ///             (2, Some("two".to_string())),
+    ///             (3, Some("3".to_string())),
+    ///         ]
+    ///     );
+    /// }
+    /// ```
+    pub fn map_stream<'a, St, L, Fut>(
+        &'a self,
+        keys: St,
+        loader: L,
+        max_batch: usize,
+        max_delay: Duration,
+    ) -> impl Stream<Item = (K, Option<V>)> + 'a
+    where
+        K: Clone,
+        St: Stream<Item = K> + 'a,
+        L: FnMut(Vec<K>) -> Fut + 'a,
+        Fut: Future<Output = HashMap<K, V>> + 'a,
+    {
+        map_stream::map_stream(self, keys, loader, max_batch, max_delay)
+    }
+
+    /// Returns a stream of the entries removed from this cache, with the cause of
+    /// each removal.
+    ///
+    /// The stream receives the same removals as an
+    /// [eviction listener][eviction-listener], from the time it is created: the
+    /// evicted and expired entries, and the invalidated and replaced ones. Unlike
+    /// a listener, it can be polled in an async pipeline, e.g. in a `select!`,
+    /// and does not require a `Send + Sync + 'static` closure. Each call returns
+    /// a new stream receiving every removal. The stream ends after yielding the
+    /// buffered removals once this cache and all of its clones are dropped.
+    ///
+    /// # Dropped removals
+    ///
+    /// Each stream buffers up to 1024 removals. When a consumer does not keep up
+    /// and its buffer is full, the _oldest_ buffered removal is dropped to make
+    /// room for the new one, so the removals never block the cache nor grow the
+    /// memory. The number of dropped removals is counted by
+    /// [`CacheStats::dropped_eviction_events`][dropped-events] when the cache
+    /// records the stats.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.7", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// // futures = "0.3"
+    ///
+    /// use moka::{future::Cache, notification::RemovalCause};
+    /// use futures_util::stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     let mut removals = cache.eviction_stream();
+    ///
+    ///     cache.insert(1, "alice").await;
+    ///     cache.invalidate(&1).await;
+    ///
+    ///     let (key, value, cause) = removals.next().await.unwrap();
+    ///     assert_eq!((*key, value, cause), (1, "alice", RemovalCause::Explicit));
+    /// }
+    /// ```
+    ///
+    /// [eviction-listener]: ./struct.CacheBuilder.html#method.eviction_listener
+    /// [dropped-events]: ../struct.CacheStats.html#method.dropped_eviction_events
+    pub fn eviction_stream(
+        &self,
+    ) -> impl Stream<Item = (Arc<K>, V, RemovalCause)> + Send + Unpin + 'static {
+        self.base.eviction_stream()
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    ///
+    /// # Backpressure
+    ///
+    /// Every insert is recorded into a bounded write buffer, which is drained by
+    /// the maintenance of the cache. When the buffer is full, e.g. because a fast
+    /// producer outpaces the maintenance or the cache is [frozen](#method.freeze),
+    /// the returned future yields to the executor and waits until there is room,
+    /// rather than blocking the executor thread or growing the buffer. So a
+    /// producer awaiting `insert` in a loop is slowed down to the pace of the
+    /// maintenance, and the memory held by the pending writes stays bounded.
+    ///
+    /// Do not drop the future before it completes to skip the wait: the value is
+    /// already visible in the cache, but the pending write will not be applied
+    /// to the eviction policy. [`blocking_insert`](#method.blocking_insert)
+    /// blocks the calling thread instead in the same situation.
+    pub async fn insert(&self, key: K, value: V) {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash(key, hash, value).await
+    }
+
+    /// Blocking [insert](#method.insert) to call outside of asynchronous contexts.
+    ///
+    /// This method is intended for use cases where you are inserting from
+    /// synchronous code.
+    pub fn blocking_insert(&self, key: K, value: V) {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let op = self.base.do_insert_with_hash(key, hash, value);
+        Self::blocking_schedule_write_op(&self.base, op).expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+    }
+
+    /// Inserts a key-value pair into the cache, or returns `Err(DeadlineError)`
+    /// if the `deadline` passes before the insert can be carried out.
+    ///
+    /// The insert waits for room in the write buffer _before_ updating the
+    /// cache, and gives up at the deadline. So a timed-out insert leaves the
+    /// cache as it was, and unlike dropping the future of
+    /// [`insert`](#method.insert) midway, it never leaves a value in the cache
+    /// whose write has not been recorded to the eviction policy. An insert with
+    /// room in the buffer does not wait at all. Like `get_with_deadline`, it
+    /// fails without updating the cache if the deadline has already passed.
+    ///
+    /// See [`get_with_deadline`](#method.get_with_deadline) for which operations
+    /// can time out.
+    pub async fn insert_with_deadline(
+        &self,
+        key: K,
+        value: V,
+        deadline: Instant,
+    ) -> Result<(), DeadlineError> {
+        if Instant::now() >= deadline {
+            return Err(DeadlineError);
+        }
+        Self::wait_for_write_room(&self.base, deadline).await?;
+        self.insert(key, value).await;
+        Ok(())
+    }
+
+    /// Inserts a key-value pair into the cache with its own time-to-idle, which
+    /// overrides the one of the cache. See
+    /// [`sync::Cache::insert_with_tti`](../sync/struct.Cache.html#method.insert_with_tti)
+    /// for details.
+    pub async fn insert_with_tti(&self, key: K, value: V, time_to_idle: Duration) {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let op = self
+            .base
+            .do_insert_with_hash_and_tti(key, hash, value, Some(time_to_idle));
+        Self::schedule_write_op(&self.base, op)
+            .await
+            .expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+    }
+
+    /// Inserts a key-value pair into the cache, and adds the entry to the group
+    /// `group`. See
+    /// [`sync::Cache::insert_in_group`](../sync/struct.Cache.html#method.insert_in_group)
+    /// for details.
+    pub async fn insert_in_group(&self, key: K, value: V, group: impl Into<String>) {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash(Arc::clone(&key), hash, value).await;
+        self.base.add_to_group(group.into(), &key);
+    }
+
+    /// Inserts a key-value pair into the cache only if the key is not present.
+    ///
+    /// If the cache already has an unexpired value for the key, the cache is left
+    /// untouched and an `OccupiedError` holding the existing value and the given
+    /// value is returned. See
+    /// [`sync::Cache::try_insert`](../sync/struct.Cache.html#method.try_insert)
+    /// for details.
+    pub async fn try_insert(&self, key: K, value: V) -> Result<(), OccupiedError<V>> {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let op = self.base.do_insert_if_absent_with_hash(key, hash, value)?;
+        Self::schedule_write_op(&self.base, op)
+            .await
+            .expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+        Ok(())
+    }
+
+    /// Inserts a key-value pair into the cache only if the entry of the key still
+    /// has the expected version, and returns the version of the new entry. See
+    /// [`sync::Cache::insert_if_version`](../sync/struct.Cache.html#method.insert_if_version)
+    /// for details.
+    pub async fn insert_if_version(
+        &self,
+        key: K,
+        value: V,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CasError<V>> {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let (version, op) =
+            self.base
+                .do_insert_if_version_with_hash(key, hash, value, expected_version)?;
+        Self::schedule_write_op(&self.base, op)
+            .await
+            .expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+        Ok(version)
+    }
+
+    /// Marks the entry for the key as used without reading its value. Returns
+    /// `true` if the cache had a live entry for the key.
+    ///
+    /// The time-to-idle of the entry always restarts, and so does its
+    /// time-to-live when `reset_ttl` is `true`. See
+    /// [`sync::Cache::touch`](../sync/struct.Cache.html#method.touch) for details.
+    pub async fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let op = match self.base.normalize_borrowed_key(key) {
+            Some(key) => {
+                let hash = self.base.hash::<K>(&key);
+                if !reset_ttl {
+                    return self.base.touch_with_hash::<K>(&key, hash);
+                }
+                self.base.do_rewrite_with_hash::<K>(&key, hash)
+            }
+            None => {
+                let hash = self.base.hash(key);
+                if !reset_ttl {
+                    return self.base.touch_with_hash(key, hash);
+                }
+                self.base.do_rewrite_with_hash(key, hash)
+            }
+        };
+        match op {
+            Some(op) => {
+                Self::schedule_write_op(&self.base, op)
+                    .await
+                    .expect("Failed to touch");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
     /// on the borrowed form _must_ match those for the key type.
     pub async fn invalidate<Q>(&self, key: &Q)
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(kv) = self.remove_entry(key) {
+            let op = WriteOp::Remove(kv);
+            Self::schedule_write_op(&self.base, op)
+                .await
+                .expect("Failed to remove");
+        }
+    }
+
+    /// Discards any cached value for the key, and returns `true` if the key had a
+    /// value that had neither expired nor been invalidated.
+    ///
+    /// See [`sync::Cache::invalidate_present`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.invalidate_present
+    pub async fn invalidate_present<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        if let Some(kv) = self.base.remove_entry(key) {
+        if let Some(kv) = self.remove_entry(key) {
+            let is_live = self.base.is_live(&kv);
             let op = WriteOp::Remove(kv);
-            let hk = self.base.housekeeper.as_ref();
-            Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+            Self::schedule_write_op(&self.base, op)
                 .await
                 .expect("Failed to remove");
+            is_live
+        } else {
+            false
+        }
+    }
+
+    /// Schedules the removal of the cached value for the key after the `delay`.
+    ///
+    /// See [`sync::Cache::invalidate_after`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.invalidate_after
+    pub fn invalidate_after<Q>(&self, key: &Q, delay: Duration) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.invalidate_after::<K>(&key, delay),
+            None => self.base.invalidate_after(key, delay),
         }
     }
 
@@ -617,13 +1668,11 @@ where
     pub fn blocking_invalidate<Q>(&self, key: &Q)
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        if let Some(kv) = self.base.remove_entry(key) {
+        if let Some(kv) = self.remove_entry(key) {
             let op = WriteOp::Remove(kv);
-            let hk = self.base.housekeeper.as_ref();
-            Self::blocking_schedule_write_op(&self.base.write_op_ch, op, hk)
-                .expect("Failed to remove");
+            Self::blocking_schedule_write_op(&self.base, op).expect("Failed to remove");
         }
     }
 
@@ -637,8 +1686,149 @@ where
     /// Like the `invalidate` method, this method does not clear the historic
     /// popularity estimator of keys so that it retains the client activities of
     /// trying to retrieve an item.
+    ///
+    /// The eviction listener is notified of the discarded entries with
+    /// `RemovalCause::Explicit`. This is the same as
+    /// [`clear_with(true)`](#method.clear_with).
     pub fn invalidate_all(&self) {
-        self.base.invalidate_all();
+        self.clear_with(true);
+    }
+
+    /// Discards all cached values without notifying the eviction listener. This
+    /// is the same as [`clear_with(false)`](#method.clear_with).
+    pub fn clear(&self) {
+        self.clear_with(false);
+    }
+
+    /// Discards all cached values, and notifies the eviction listener of them
+    /// with `RemovalCause::Explicit` only when `notify` is `true`. Not notifying
+    /// is the faster way to reclaim the memory of a large cache.
+    ///
+    /// See [`sync::Cache::clear_with`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.clear_with
+    pub fn clear_with(&self, notify: bool) {
+        self.base.invalidate_all(notify);
+    }
+
+    /// Discards all cached values except the ones for which `keep` returns `true`.
+    ///
+    /// This method applies the pending writes, calls `keep` once for each cached
+    /// entry, and discards the entries for which it returns `false`. When the
+    /// returned future completes, the `get` method will not return the discarded
+    /// values. See [`sync::Cache::invalidate_all_except`][sync-method] for the
+    /// details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.invalidate_all_except
+    pub async fn invalidate_all_except<F>(&self, keep: F)
+    where
+        F: Fn(&K, &V) -> bool,
+    {
+        // Apply the pending writes so that all entries inserted by now are visited.
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+        for kv in self.base.remove_entries_except(keep) {
+            Self::schedule_write_op(&self.base, WriteOp::Remove(kv))
+                .await
+                .expect("Failed to remove");
+        }
+    }
+
+    /// Discards all entries in the group `group`, and notifies the eviction
+    /// listener of them with `RemovalCause::Explicit`.
+    ///
+    /// See [`sync::Cache::invalidate_group`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.invalidate_group
+    pub async fn invalidate_group(&self, group: &str) {
+        for kv in self.base.remove_group(group) {
+            Self::schedule_write_op(&self.base, WriteOp::Remove(kv))
+                .await
+                .expect("Failed to remove");
+        }
+    }
+
+    /// Evicts up to `n` entries right away, coldest first, and returns the number
+    /// of evicted entries. Entries weighted zero are skipped.
+    ///
+    /// See [`sync::Cache::evict_now`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.evict_now
+    pub fn evict_now(&self, n: usize) -> usize {
+        self.base.evict_now(n)
+    }
+
+    /// Catches up on the deferred setup and maintenance of this cache, so that
+    /// the next operations do not pay for them.
+    ///
+    /// This method blocks the calling thread while the maintenance runs. See
+    /// [`sync::Cache::prepare`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.prepare
+    pub fn prepare(&self) {
+        self.base.prepare();
+    }
+
+    /// Returns the number of accesses to the key estimated by the frequency sketch
+    /// of the cache, from `0` to `15`. The estimate is approximate.
+    ///
+    /// See [`sync::Cache::estimated_frequency`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.estimated_frequency
+    pub fn estimated_frequency<Q>(&self, key: &Q) -> u8
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let hash = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.hash::<K>(&key),
+            None => self.base.hash(key),
+        };
+        self.base.estimated_frequency(hash)
+    }
+
+    /// Returns the state learned by the admission policy of the cache, to be
+    /// imported by the cache replacing this one.
+    ///
+    /// See [`sync::Cache::export_policy_state`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.export_policy_state
+    #[cfg(feature = "serde")]
+    pub fn export_policy_state(&self) -> PolicyState {
+        self.base.export_policy_state()
+    }
+
+    /// Replaces the state learned by the admission policy of the cache with the
+    /// one exported by [`export_policy_state`](#method.export_policy_state).
+    /// Returns `true` if the state has been imported.
+    ///
+    /// See [`sync::Cache::import_policy_state`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.import_policy_state
+    #[cfg(feature = "serde")]
+    pub fn import_policy_state(&self, state: &PolicyState) -> bool {
+        self.base.import_policy_state(state)
+    }
+
+    /// Returns the popularity of the given keys as estimated by the cache, most
+    /// popular first.
+    ///
+    /// See [`sync::Cache::top_frequencies`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.top_frequencies
+    pub fn top_frequencies<'a>(&self, sample_keys: impl IntoIterator<Item = &'a K>) -> Vec<(K, u8)>
+    where
+        K: Clone + 'a,
+    {
+        let mut freqs = sample_keys
+            .into_iter()
+            .map(|key| {
+                let key = self.base.normalize_key(key.clone());
+                let freq = self.base.estimated_frequency(self.base.hash(&key));
+                (key, freq)
+            })
+            .collect::<Vec<_>>();
+        freqs.sort_by_key(|(_, freq)| std::cmp::Reverse(*freq));
+        freqs
     }
 
     /// Discards cached values that satisfy a predicate.
@@ -673,11 +1863,32 @@ where
         self.base.invalidate_entries_if(Arc::new(predicate))
     }
 
+    /// Returns the name of this cache, if it has been set by the
+    /// `CacheBuilder::name` method.
+    pub fn name(&self) -> Option<&str> {
+        self.base.name()
+    }
+
     /// Returns the `max_capacity` of this cache.
     pub fn max_capacity(&self) -> Option<usize> {
         self.base.max_capacity()
     }
 
+    /// Returns the weight that the weigher of this cache gives to the key-value
+    /// pair, or `None` if the cache was built without a weigher.
+    ///
+    /// See [`sync::Cache::weigh`][sync-weigh] for the details.
+    ///
+    /// [sync-weigh]: ../sync/struct.Cache.html#method.weigh
+    pub fn weigh(&self, key: &K, value: &V) -> Option<u32> {
+        self.base.weigher().map(|w| w(key, value))
+    }
+
+    /// Returns the `initial_capacity` of this cache.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        self.base.initial_capacity()
+    }
+
     /// Returns the `time_to_live` of this cache.
     pub fn time_to_live(&self) -> Option<Duration> {
         self.base.time_to_live()
@@ -688,6 +1899,187 @@ where
         self.base.time_to_idle()
     }
 
+    /// Returns `true` if any entry of this cache has been given its own
+    /// expiration.
+    ///
+    /// See [`sync::Cache::has_per_entry_expiration`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.has_per_entry_expiration
+    pub fn has_per_entry_expiration(&self) -> bool {
+        self.base.has_per_entry_expiration()
+    }
+
+    /// Returns the `max_age` of this cache.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.base.max_age().map(|ma| ma.duration)
+    }
+
+    /// Returns the keys of this cache with the instants when they will expire,
+    /// sorted from the soonest to the latest.
+    ///
+    /// An entry expires at the earliest of `last insert + time_to_live`, `last
+    /// access + time_to_idle` and `creation + max_age`. If none of them is
+    /// configured, this method returns an empty `Vec`.
+    ///
+    /// This method is intended for diagnostics and is not cheap: it walks the
+    /// internal access-order queues while holding their lock, then sorts the
+    /// result in `O(n log n)`. The result is weakly consistent; it reflects the
+    /// state as of the last time the pending reads and writes were applied to the
+    /// queues, so recently inserted or read entries may be missing or have stale
+    /// instants. Entries that have already expired or been invalidated are
+    /// excluded.
+    pub fn entries_by_expiration(&self) -> Vec<(Arc<K>, std::time::Instant)> {
+        self.base.entries_by_expiration()
+    }
+
+    /// Returns an iterator over the entries of this cache in the order they will
+    /// expire, yielding the key, a _clone_ of the value, and the instant when the
+    /// entry will expire.
+    ///
+    /// If `time_to_live` is configured, the entries are visited in the order of
+    /// their last insert, which is exactly the expiration order. Otherwise, if
+    /// `time_to_idle` is configured, they are visited in the order of their last
+    /// access, which is again exactly the expiration order. If both are configured,
+    /// an entry can expire earlier by `time_to_idle` than the entries before it,
+    /// so the order is approximate. If neither is configured, the iterator yields
+    /// nothing.
+    ///
+    /// The iterator does not hold a lock for the whole iteration. It visits the
+    /// internal queue in chunks, taking the lock only while copying out a chunk.
+    /// Therefore the iteration is weakly consistent: an entry inserted or updated
+    /// during the iteration may or may not be yielded, and an updated entry may be
+    /// yielded twice. Like [`entries_by_expiration`](#method.entries_by_expiration),
+    /// it reflects the queue as of the last time the pending writes were applied.
+    /// Entries that have already expired or been invalidated are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::future::Cache;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let cache = Cache::builder()
+    ///     .time_to_live(Duration::from_secs(600))
+    ///     .build();
+    /// cache.blocking_insert("a", 1);
+    /// # use moka::future::ConcurrentCacheExt;
+    /// # cache.sync();
+    ///
+    /// // What will fall out of the cache in the next 60 seconds?
+    /// let deadline = Instant::now() + Duration::from_secs(60);
+    /// let expiring = cache
+    ///     .iter_by_expiration()
+    ///     .take_while(|(_, _, expires_at)| *expires_at <= deadline)
+    ///     .count();
+    /// assert_eq!(expiring, 0);
+    /// ```
+    pub fn iter_by_expiration(&self) -> impl Iterator<Item = (Arc<K>, V, std::time::Instant)> + '_ {
+        self.base.iter_by_expiration()
+    }
+
+    /// Calls the closure with a reference to the key and the value of every entry
+    /// in this cache, without cloning the values.
+    ///
+    /// The entries are visited in chunks, and the returned future yields to the
+    /// executor between chunks. The closure must be fast and must not call back
+    /// into this cache. See [`sync::Cache::for_each_entry`][sync-for-each-entry]
+    /// for the details.
+    ///
+    /// [sync-for-each-entry]: ../sync/struct.Cache.html#method.for_each_entry
+    pub async fn for_each_entry(&self, mut f: impl FnMut(&K, &V)) {
+        let mut visitor = self.base.entry_visitor();
+        while visitor.visit_chunk(&mut |k, v| f(k, v)) {
+            yield_now().await;
+        }
+    }
+
+    /// Returns the keys of the entries whose values satisfy the predicate.
+    ///
+    /// The entries are scanned in chunks, and the returned future yields to the
+    /// executor between chunks. See
+    /// [`sync::Cache::keys_matching`][sync-keys-matching] for the details.
+    ///
+    /// [sync-keys-matching]: ../sync/struct.Cache.html#method.keys_matching
+    pub async fn keys_matching(&self, predicate: impl Fn(&V) -> bool) -> Vec<Arc<K>> {
+        let mut keys = Vec::new();
+        let mut visitor = self.base.entry_visitor();
+        let mut collect = |k: &Arc<K>, v: &V| {
+            if predicate(v) {
+                keys.push(Arc::clone(k));
+            }
+        };
+        while visitor.visit_chunk(&mut collect) {
+            yield_now().await;
+        }
+        keys
+    }
+
+    /// Transforms the value of every entry in this cache in place.
+    ///
+    /// This method blocks the calling thread until all entries are transformed.
+    /// See [`sync::Cache::transform_values`][sync-transform-values] for the
+    /// details.
+    ///
+    /// [sync-transform-values]: ../sync/struct.Cache.html#method.transform_values
+    pub fn transform_values(&self, f: impl FnMut(&K, &mut V)) {
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+        self.base.transform_values(f);
+    }
+
+    /// Creates a [`FrozenView`][frozen-view] of this cache, whose entry count and
+    /// entries correspond to the same point in time.
+    ///
+    /// The maintenance of the cache is paused until the view is dropped, or five
+    /// seconds have passed. See [`sync::Cache::freeze`][sync-freeze] for the
+    /// details.
+    ///
+    /// [frozen-view]: ./struct.FrozenView.html
+    /// [sync-freeze]: ../sync/struct.Cache.html#method.freeze
+    pub fn freeze(&self) -> FrozenView<'_, K, V, S> {
+        self.freeze_for(DEFAULT_MAX_FREEZE_DURATION)
+    }
+
+    /// Creates a [`FrozenView`][frozen-view] of this cache, which pauses the
+    /// maintenance of the cache for up to `max_duration`.
+    ///
+    /// [frozen-view]: ./struct.FrozenView.html
+    pub fn freeze_for(&self, max_duration: Duration) -> FrozenView<'_, K, V, S> {
+        FrozenView::new(&self.base, max_duration)
+    }
+
+    /// Returns a snapshot of the statistics of this cache.
+    ///
+    /// The statistics are recorded only if the cache was built with
+    /// [`CacheBuilder::record_stats`][record-stats]. Otherwise, this method
+    /// returns a `CacheStats` with all counts set to zero.
+    ///
+    /// [record-stats]: ./struct.CacheBuilder.html#method.record_stats
+    pub fn stats(&self) -> CacheStats {
+        self.base.stats()
+    }
+
+    /// Returns a snapshot of the statistics of this cache, and resets the counts
+    /// to zero.
+    ///
+    /// This is intended for delta-based metric systems that export the counts
+    /// periodically. Reading with `stats` and then resetting separately would lose
+    /// the events recorded between the two calls. Here, each count is read and
+    /// zeroed by a single atomic swap without taking a lock, so every event is
+    /// counted by exactly one call. The counts are swapped one by one, so they
+    /// may not be consistent with each other, e.g. a hit may be in this snapshot
+    /// and its latency sample in the next.
+    ///
+    /// The `distinct_keys_estimate` is counted over the lifetime of the cache and
+    /// is not reset.
+    ///
+    /// [`CacheBuilder::record_stats`][record-stats] must be set for the counts to
+    /// be recorded.
+    ///
+    /// [record-stats]: ./struct.CacheBuilder.html#method.record_stats
+    pub fn stats_and_reset(&self) -> CacheStats {
+        self.base.stats_and_reset()
+    }
+
     /// Returns the number of internal segments of this cache.
     ///
     /// `Cache` always returns `1`.
@@ -706,10 +2098,44 @@ where
     }
 }
 
+impl<K, T, S> Cache<K, Weak<T>, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Returns the object the cached `Weak` for the key points to, or `None` if
+    /// the key has no value or the object has been dropped.
+    ///
+    /// See [`sync::Cache::get_upgraded`][sync-method] for the details.
+    ///
+    /// [sync-method]: ../sync/struct.Cache.html#method.get_upgraded
+    pub async fn get_upgraded<Q>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(value) = self.get(key).and_then(|weak| weak.upgrade()) {
+            return Some(value);
+        }
+        let removed = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.remove_if_collected::<K>(&key),
+            None => self.base.remove_if_collected(key),
+        };
+        if let Some(kv) = removed {
+            let op = WriteOp::Remove(kv);
+            Self::schedule_write_op(&self.base, op)
+                .await
+                .expect("Failed to remove");
+        }
+        None
+    }
+}
+
 impl<K, V, S> ConcurrentCacheExt<K, V> for Cache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     fn sync(&self) {
@@ -724,16 +2150,35 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
+    fn remove_entry<Q>(&self, key: &Q) -> Option<KvEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.remove_entry::<K>(&key),
+            None => self.base.remove_entry(key),
+        }
+    }
+
     async fn get_or_insert_with_hash_and_fun(
         &self,
         key: Arc<K>,
         hash: u64,
         init: impl Future<Output = V>,
-    ) -> V {
+    ) -> Entry<V> {
         if let Some(v) = self.base.get_with_hash(&key, hash) {
-            return v;
+            return Entry::new(v, false);
         }
 
+        let init = async {
+            let timer = self.base.start_load();
+            let v = init.await;
+            if let Some(timer) = timer {
+                timer.finish(true);
+            }
+            v
+        };
         match self
             .value_initializer
             .init_or_read(Arc::clone(&key), init)
@@ -744,10 +2189,13 @@ where
                     .await;
                 self.value_initializer
                     .remove_waiter(&key, TypeId::of::<()>());
-                v
+                Entry::new(v, true)
+            }
+            InitResult::ReadExisting(v) => {
+                self.base.record_load_deduplicated();
+                Entry::new(v, false)
             }
-            InitResult::ReadExisting(v) => v,
-            InitResult::InitErr(_) => unreachable!(),
+            InitResult::InitErr(_) | InitResult::OwnerPanicked => unreachable!(),
         }
     }
 
@@ -756,7 +2204,7 @@ where
         key: Arc<K>,
         hash: u64,
         init: F,
-    ) -> Result<V, Arc<E>>
+    ) -> Result<V, LoadError<E>>
     where
         F: Future<Output = Result<V, E>>,
         E: Send + Sync + 'static,
@@ -765,11 +2213,26 @@ where
             return Ok(v);
         }
 
-        match self
+        // A waiter may also get an `InitErr`, so remember whether this call has
+        // loaded the value.
+        let mut loaded = false;
+        let init = async {
+            loaded = true;
+            let timer = self.base.start_load();
+            let result = init.await;
+            if let Some(timer) = timer {
+                timer.finish(result.is_ok());
+            }
+            result
+        };
+        let result = self
             .value_initializer
             .try_init_or_read(Arc::clone(&key), init)
-            .await
-        {
+            .await;
+        if !loaded {
+            self.base.record_load_deduplicated();
+        }
+        match result {
             InitResult::Initialized(v) => {
                 let hash = self.base.hash(&key);
                 self.insert_with_hash(Arc::clone(&key), hash, v.clone())
@@ -779,34 +2242,37 @@ where
                 Ok(v)
             }
             InitResult::ReadExisting(v) => Ok(v),
-            InitResult::InitErr(e) => Err(e),
+            InitResult::InitErr(e) => Err(LoadError::Loader(e)),
+            InitResult::OwnerPanicked => Err(LoadError::OwnerPanicked),
         }
     }
 
     async fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
         let op = self.base.do_insert_with_hash(key, hash, value);
-        let hk = self.base.housekeeper.as_ref();
-        Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+        Self::schedule_write_op(&self.base, op)
             .await
             .expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
     }
 
     #[inline]
     async fn schedule_write_op(
-        ch: &Sender<WriteOp<K, V>>,
+        base: &BaseCache<K, V, S>,
         op: WriteOp<K, V>,
-        housekeeper: Option<&HouseKeeperArc<K, V, S>>,
     ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let ch = base.write_op_ch();
         let mut op = op;
 
         // TODO: Try to replace the timer with an async event listener to see if it
         // can provide better performance.
         loop {
-            BaseCache::apply_reads_writes_if_needed(ch, housekeeper);
+            BaseCache::apply_reads_writes_if_needed(ch, base.housekeeper.as_ref());
             match ch.try_send(op) {
                 Ok(()) => break,
                 Err(TrySendError::Full(op1)) => {
                     op = op1;
+                    base.try_apply_writes();
                     async_io::Timer::after(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS))
                         .await;
                 }
@@ -816,20 +2282,45 @@ where
         Ok(())
     }
 
+    /// Waits until the write buffer has room for a write op, or the deadline
+    /// passes.
+    async fn wait_for_write_room(
+        base: &BaseCache<K, V, S>,
+        deadline: Instant,
+    ) -> Result<(), DeadlineError> {
+        let ch = base.write_op_ch();
+        loop {
+            BaseCache::apply_reads_writes_if_needed(ch, base.housekeeper.as_ref());
+            if ch.is_full() {
+                base.try_apply_writes();
+            }
+            if !ch.is_full() {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(DeadlineError);
+            }
+            let retry_at = now + Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS);
+            async_io::Timer::at(retry_at.min(deadline)).await;
+        }
+    }
+
     #[inline]
     fn blocking_schedule_write_op(
-        ch: &Sender<WriteOp<K, V>>,
+        base: &BaseCache<K, V, S>,
         op: WriteOp<K, V>,
-        housekeeper: Option<&HouseKeeperArc<K, V, S>>,
     ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let ch = base.write_op_ch();
         let mut op = op;
 
         loop {
-            BaseCache::apply_reads_writes_if_needed(ch, housekeeper);
+            BaseCache::apply_reads_writes_if_needed(ch, base.housekeeper.as_ref());
             match ch.try_send(op) {
                 Ok(()) => break,
                 Err(TrySendError::Full(op1)) => {
                     op = op1;
+                    base.try_apply_writes();
                     std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
                 }
                 Err(e @ TrySendError::Disconnected(_)) => return Err(e),
@@ -865,13 +2356,29 @@ where
 }
 
 // To see the debug prints, run test as `cargo test -- --nocapture`
+/// Returns a future that yields to the executor once, so that other tasks can run
+/// before it completes.
+async fn yield_now() {
+    let mut yielded = false;
+    futures_util::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Cache, ConcurrentCacheExt};
-    use crate::{common::time::Clock, future::CacheBuilder};
+    use crate::{common::time::Clock, future::CacheBuilder, LoadError};
 
     use async_io::Timer;
-    use std::{convert::Infallible, sync::Arc, time::Duration};
+    use std::{sync::Arc, time::Duration};
 
     #[tokio::test]
     async fn basic_single_async_task() {
@@ -1031,80 +2538,279 @@ mod tests {
         // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
         cache.insert("b", bill).await;
         cache.sync();
-        assert_eq!(cache.get(&"b"), Some(bill));
-        assert_eq!(cache.get(&"d"), None);
+        assert_eq!(cache.get(&"b"), Some(bill));
+        assert_eq!(cache.get(&"d"), None);
+
+        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
+        cache.insert("a", alice).await;
+        cache.insert("b", bob).await;
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        assert_eq!(cache.get(&"d"), None);
+
+        // Verify the sizes.
+        assert_eq!(cache.estimated_entry_count(), 2);
+        assert_eq!(cache.weighted_size(), 25);
+    }
+
+    #[tokio::test]
+    async fn basic_multi_async_tasks() {
+        let num_threads = 4;
+        let cache = Cache::new(100);
+
+        let tasks = (0..num_threads)
+            .map(|id| {
+                let cache = cache.clone();
+                if id == 0 {
+                    tokio::spawn(async move {
+                        cache.blocking_insert(10, format!("{}-100", id));
+                        cache.get(&10);
+                        cache.blocking_insert(20, format!("{}-200", id));
+                        cache.blocking_invalidate(&10);
+                    })
+                } else {
+                    tokio::spawn(async move {
+                        cache.insert(10, format!("{}-100", id)).await;
+                        cache.get(&10);
+                        cache.insert(20, format!("{}-200", id)).await;
+                        cache.invalidate(&10).await;
+                    })
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let _ = futures_util::future::join_all(tasks).await;
+
+        assert!(cache.get(&10).is_none());
+        assert!(cache.get(&20).is_some());
+    }
+
+    #[tokio::test]
+    async fn claim_miss() {
+        use crate::future::{MissClaim, MissResolution};
+
+        let cache = Cache::new(100);
+
+        let token = match cache.claim_miss(1) {
+            MissClaim::Owner(token) => token,
+            _ => panic!("The first claimant should own the claim"),
+        };
+        let waiter = match cache.claim_miss(1) {
+            MissClaim::Pending(waiter) => tokio::spawn(waiter.wait()),
+            _ => panic!("The second claimant should wait"),
+        };
+        cache.complete(token, "one").await;
+        assert_eq!(waiter.await.unwrap(), MissResolution::Completed("one"));
+        assert!(matches!(cache.claim_miss(1), MissClaim::Hit("one")));
+
+        // A cancelled owner abandons the claim.
+        let owner = {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                let _token = cache.claim_miss(2);
+                futures_util::future::pending::<()>().await;
+            })
+        };
+        while !matches!(cache.claim_miss(2), MissClaim::Pending(_)) {
+            tokio::task::yield_now().await;
+        }
+        let waiter = match cache.claim_miss(2) {
+            MissClaim::Pending(waiter) => tokio::spawn(waiter.wait()),
+            _ => panic!("The claim should be pending"),
+        };
+        owner.abort();
+        assert_eq!(waiter.await.unwrap(), MissResolution::Retry);
+        assert!(matches!(cache.claim_miss(2), MissClaim::Owner(_)));
+    }
+
+    #[tokio::test]
+    async fn invalidate_all() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.insert("c", "cindy").await;
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        cache.sync();
+
+        cache.invalidate_all();
+        cache.sync();
+
+        cache.insert("d", "david").await;
+        cache.sync();
+
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+        assert_eq!(cache.get(&"d"), Some("david"));
+    }
+
+    #[tokio::test]
+    async fn invalidate_present() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // The insert has not been applied by the maintenance yet.
+        cache.insert("a", "alice").await;
+        assert!(cache.invalidate_present(&"a").await);
+        assert!(!cache.invalidate_present(&"a").await);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+
+        // An expired entry is not present.
+        cache.insert("b", "bob").await;
+        cache.sync();
+        mock.increment(Duration::from_secs(10));
+        assert!(!cache.invalidate_present(&"b").await);
+    }
+
+    #[tokio::test]
+    async fn read_with() {
+        let cache = Cache::new(100);
+        cache.insert('a', Arc::new(vec![1u8, 2, 3])).await;
+
+        assert_eq!(cache.read_with(&'a', Arc::strong_count), Some(1));
+        assert_eq!(cache.read_with(&'a', |v| v[2]), Some(3));
+        assert_eq!(cache.read_with(&'b', |v| v[2]), None);
+    }
+
+    #[tokio::test]
+    async fn weak_values() {
+        let mut cache = CacheBuilder::new(100).weak_values().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let (a, b) = (Arc::new("alice"), Arc::new("bob"));
+        cache.insert('a', Arc::downgrade(&a)).await;
+        cache.insert('b', Arc::downgrade(&b)).await;
+        cache.sync();
+
+        drop(a);
+        assert_eq!(cache.get_upgraded(&'a').await, None);
+        assert_eq!(cache.get_upgraded(&'b').await, Some(Arc::clone(&b)));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn invalidate_after() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.sync();
 
-        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
-        cache.insert("a", alice).await;
-        cache.insert("b", bob).await;
+        assert!(cache.invalidate_after(&"a", Duration::from_secs(5)));
+        assert!(cache.invalidate_after(&"b", Duration::from_secs(5)));
+        assert!(!cache.invalidate_after(&"c", Duration::from_secs(5)));
+        cache.insert("b", "bill").await;
         cache.sync();
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert_eq!(cache.get(&"b"), Some(bob));
-        assert_eq!(cache.get(&"d"), None);
 
-        // Verify the sizes.
-        assert_eq!(cache.estimated_entry_count(), 2);
-        assert_eq!(cache.weighted_size(), 25);
+        mock.increment(Duration::from_secs(5));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bill"));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
     }
 
     #[tokio::test]
-    async fn basic_multi_async_tasks() {
-        let num_threads = 4;
-        let cache = Cache::new(100);
+    async fn for_each_entry() {
+        let mut cache = Cache::new(1000);
+        cache.reconfigure_for_testing();
 
-        let tasks = (0..num_threads)
-            .map(|id| {
-                let cache = cache.clone();
-                if id == 0 {
-                    tokio::spawn(async move {
-                        cache.blocking_insert(10, format!("{}-100", id));
-                        cache.get(&10);
-                        cache.blocking_insert(20, format!("{}-200", id));
-                        cache.blocking_invalidate(&10);
-                    })
-                } else {
-                    tokio::spawn(async move {
-                        cache.insert(10, format!("{}-100", id)).await;
-                        cache.get(&10);
-                        cache.insert(20, format!("{}-200", id)).await;
-                        cache.invalidate(&10).await;
-                    })
-                }
-            })
-            .collect::<Vec<_>>();
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        let _ = futures_util::future::join_all(tasks).await;
+        for i in 0..300 {
+            cache.insert(i, i * 10).await;
+        }
+        cache.sync();
+        cache.invalidate(&0).await;
 
-        assert!(cache.get(&10).is_none());
-        assert!(cache.get(&20).is_some());
+        let mut visited = Vec::new();
+        cache.for_each_entry(|k, v| visited.push((*k, *v))).await;
+        visited.sort_unstable();
+        assert_eq!(visited, (1..300).map(|i| (i, i * 10)).collect::<Vec<_>>());
     }
 
     #[tokio::test]
-    async fn invalidate_all() {
-        let mut cache = Cache::new(100);
+    async fn keys_matching() {
+        let mut cache = Cache::new(1000);
         cache.reconfigure_for_testing();
 
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", "alice").await;
-        cache.insert("b", "bob").await;
-        cache.insert("c", "cindy").await;
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), Some("cindy"));
+        for i in 0..300 {
+            cache.insert(i, i < 10).await;
+        }
         cache.sync();
 
-        cache.invalidate_all();
-        cache.sync();
+        let mut keys = cache
+            .keys_matching(|flag| *flag)
+            .await
+            .into_iter()
+            .map(|k| *k)
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+    }
 
-        cache.insert("d", "david").await;
-        cache.sync();
+    #[tokio::test]
+    async fn transform_values() {
+        let mut cache = Cache::new(1000);
+        cache.reconfigure_for_testing();
 
-        assert!(cache.get(&"a").is_none());
-        assert!(cache.get(&"b").is_none());
-        assert!(cache.get(&"c").is_none());
-        assert_eq!(cache.get(&"d"), Some("david"));
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..300 {
+            cache.insert(i, i * 10).await;
+        }
+        cache.transform_values(|k, v| *v += k);
+        assert!((0..300).all(|i| cache.get(&i) == Some(i * 11)));
+    }
+
+    #[tokio::test]
+    async fn insert_if_version() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let v1 = cache.insert_if_version("a", "alice", None).await.unwrap();
+        let v2 = cache
+            .insert_if_version("a", "anna", Some(v1))
+            .await
+            .unwrap();
+        assert!(v2 > v1);
+        assert!(cache.insert_if_version("a", "amy", Some(v1)).await.is_err());
+        assert_eq!(cache.get_versioned(&"a"), Some(("anna", v2)));
     }
 
     #[tokio::test]
@@ -1361,16 +3067,471 @@ mod tests {
         futures_util::join!(task1, task2, task3, task4, task5);
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn get_or_insert_with_many_waiters() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const NUM_TASKS: usize = 500;
+
+        let cache = Cache::new(100);
+        let init_count = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..NUM_TASKS)
+            .map(|i| {
+                let cache = cache.clone();
+                let init_count = Arc::clone(&init_count);
+                tokio::spawn(async move {
+                    cache
+                        .get_or_insert_with(0u32, async move {
+                            init_count.fetch_add(1, Ordering::AcqRel);
+                            Timer::after(Duration::from_millis(50)).await;
+                            i
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut values = Vec::with_capacity(NUM_TASKS);
+        for task in tasks {
+            values.push(task.await.expect("Failed to join a task"));
+        }
+
+        // Only one init future was resolved, and all tasks got its value.
+        assert_eq!(init_count.load(Ordering::Acquire), 1);
+        assert!(values.iter().all(|v| *v == values[0]));
+        assert_eq!(cache.get(&0), Some(values[0]));
+    }
+
     #[tokio::test]
-    async fn get_or_try_insert_with() {
-        use std::sync::Arc;
+    async fn get_or_insert_with_info() {
+        let cache = Cache::new(100);
+
+        let entry = cache.get_or_insert_with_info(1, async { "one" }).await;
+        assert!(entry.is_fresh());
+        let entry = cache.get_or_insert_with_info(1, async { "uno" }).await;
+        assert!(!entry.is_fresh());
+        assert_eq!(entry.into_value(), "one");
+    }
+
+    #[tokio::test]
+    async fn access_listener() {
+        use std::sync::Mutex;
+
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let hits1 = Arc::clone(&hits);
+        let cache = Cache::builder()
+            .access_listener(move |k: &u32, v: &u32| hits1.lock().unwrap().push((*k, *v)))
+            .build();
+
+        cache.insert(1, 10).await;
+        assert_eq!(cache.get(&1), Some(10));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(
+            cache.get_or_insert_with(1, async { unreachable!() }).await,
+            10
+        );
+        assert_eq!(*hits.lock().unwrap(), vec![(1, 10), (1, 10)]);
+    }
+
+    #[tokio::test]
+    async fn get_or_insert_with_key() {
+        let cache = Cache::new(100);
+
+        let v = cache
+            .get_or_insert_with_key("a".to_string(), |k| async move { format!("{}!", k) })
+            .await;
+        assert_eq!(v, "a!");
+        // The closure is not called when the value exists.
+        let v = cache
+            .get_or_insert_with_key(
+                "a".to_string(),
+                |_| -> futures_util::future::Ready<String> { unreachable!() },
+            )
+            .await;
+        assert_eq!(v, "a!");
+
+        let v: Result<_, LoadError<std::num::ParseIntError>> = cache
+            .get_or_try_insert_with_key("42".to_string(), |k| async move {
+                k.parse::<u32>().map(|n| n.to_string())
+            })
+            .await;
+        assert_eq!(v.unwrap(), "42");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn get_loading() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const NUM_TASKS: usize = 16;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls1 = Arc::clone(&calls);
+        let cache = Cache::builder()
+            .loader(move |k: Arc<u32>| {
+                let calls = Arc::clone(&calls1);
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Timer::after(Duration::from_millis(50)).await;
+                    *k * 10
+                }
+            })
+            .build();
+
+        // Concurrent calls for the same key resolve only one loader future.
+        let tasks = (0..NUM_TASKS)
+            .map(|_| {
+                let cache = cache.clone();
+                tokio::spawn(async move { cache.get_loading(1).await })
+            })
+            .collect::<Vec<_>>();
+        for v in futures_util::future::join_all(tasks).await {
+            assert_eq!(v.unwrap(), 10);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(cache.get_loading(1).await, 10);
+        assert_eq!(cache.get_loading(2).await, 20);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn prefetch() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls1 = Arc::clone(&calls);
+        let cache = Cache::builder()
+            .record_stats()
+            .loader(move |k: Arc<u32>| {
+                calls1.fetch_add(1, Ordering::SeqCst);
+                async move { *k * 10 }
+            })
+            .build();
 
+        tokio::spawn(cache.prefetch(1)).await.unwrap();
+        // Does nothing for a key in the cache.
+        tokio::spawn(cache.prefetch(1)).await.unwrap();
+        assert_eq!(cache.get(&1), Some(10));
+        assert_eq!(cache.get_loading(1).await, 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let stats = cache.stats();
+        assert_eq!(stats.prefetches_issued(), 1);
+        assert_eq!(stats.prefetch_hits(), 1);
+        assert_eq!(stats.hit_count(), 2);
+        assert_eq!(stats.miss_count(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn get_or_insert_with_sync_init() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const NUM_TASKS: usize = 100;
+
+        let cache = Cache::new(100);
+        let init_count = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..NUM_TASKS)
+            .map(|i| {
+                let cache = cache.clone();
+                let init_count = Arc::clone(&init_count);
+                tokio::spawn(async move {
+                    cache
+                        .get_or_insert_with_sync_init(0u32, || {
+                            init_count.fetch_add(1, Ordering::AcqRel);
+                            std::thread::sleep(Duration::from_millis(50));
+                            i
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut values = Vec::with_capacity(NUM_TASKS);
+        for task in tasks {
+            values.push(task.await.expect("Failed to join a task"));
+        }
+
+        assert_eq!(init_count.load(Ordering::Acquire), 1);
+        assert!(values.iter().all(|v| *v == values[0]));
+        assert_eq!(cache.get(&0), Some(values[0]));
+
+        // The closure can borrow locals of the caller.
+        let local = String::from("borrowed");
+        let v = cache.get_or_insert_with_sync_init(1, || local.len()).await;
+        assert_eq!(v, local.len());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn insert_waits_while_write_buffer_is_full() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let cache: Cache<usize, usize> = Cache::new(100_000);
+        // Create the write buffer.
+        cache.insert(0, 0).await;
+        let buffer_size = cache.base.write_op_ch().capacity().unwrap();
+
+        // Pause the maintenance so that nothing drains the write buffer.
+        let frozen = cache.freeze();
+
+        let inserted = Arc::new(AtomicUsize::new(0));
+        let producer = {
+            let cache = cache.clone();
+            let inserted = Arc::clone(&inserted);
+            tokio::spawn(async move {
+                for i in 1..=(buffer_size * 2) {
+                    cache.insert(i, i).await;
+                    inserted.fetch_add(1, Ordering::AcqRel);
+                }
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // The producer is parked on the full buffer, which has not grown.
+        assert!(inserted.load(Ordering::Acquire) <= buffer_size);
+        assert_eq!(cache.base.write_op_ch().len(), buffer_size);
+
+        // Unfreezing lets the maintenance drain the buffer, and the producer
+        // resumes.
+        drop(frozen);
+        producer.await.unwrap();
+        assert_eq!(inserted.load(Ordering::Acquire), buffer_size * 2);
+        cache.sync();
+        assert_eq!(cache.get(&(buffer_size * 2)), Some(buffer_size * 2));
+    }
+
+    #[tokio::test]
+    async fn eviction_stream() {
+        use crate::notification::RemovalCause;
+        use futures_util::{Stream, StreamExt};
+
+        async fn next(
+            removals: &mut (impl Stream<Item = (Arc<i32>, i32, RemovalCause)> + Unpin),
+        ) -> (i32, i32, RemovalCause) {
+            let (k, v, cause) = removals.next().await.unwrap();
+            (*k, v, cause)
+        }
+
+        let cache = Cache::builder()
+            .max_capacity(3)
+            .time_to_live(Duration::from_millis(500))
+            .build();
+        let mut removals = cache.eviction_stream();
+        for i in 0..3 {
+            cache.insert(i, i).await;
+        }
+        cache.insert(2, 20).await;
+        cache.invalidate(&2).await;
+        assert_eq!(next(&mut removals).await, (2, 2, RemovalCause::Replaced));
+        assert_eq!(next(&mut removals).await, (2, 20, RemovalCause::Explicit));
+
+        // Two of the new entries do not fit in the cache.
+        for i in 3..6 {
+            cache.insert(i, i).await;
+        }
+        cache.sync();
+        for _ in 0..2 {
+            let (_, _, cause) = next(&mut removals).await;
+            assert!(matches!(
+                cause,
+                RemovalCause::Size | RemovalCause::NotAdmitted
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        cache.sync();
+        for _ in 0..3 {
+            assert_eq!(next(&mut removals).await.2, RemovalCause::Expired);
+        }
+
+        // The stream ends once the cache is dropped.
+        drop(cache);
+        assert!(removals.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn eviction_stream_drops_oldest_when_full() {
+        use super::super::eviction_stream::EVICTION_STREAM_CAPACITY;
+        use futures_util::StreamExt;
+
+        let cache = Cache::builder().record_stats().build();
+        let mut removals = cache.eviction_stream();
+        // A dropped stream is forgotten.
+        drop(cache.eviction_stream());
+
+        let n = EVICTION_STREAM_CAPACITY + 10;
+        for i in 0..n {
+            cache.insert(i, i).await;
+            cache.invalidate(&i).await;
+        }
+        assert_eq!(cache.stats().dropped_eviction_events(), 10);
+
+        // The oldest removals were dropped.
+        let (k, _, _) = removals.next().await.unwrap();
+        assert_eq!(*k, 10);
+        drop(cache);
+        assert_eq!(removals.count().await, EVICTION_STREAM_CAPACITY - 1);
+    }
+
+    #[tokio::test]
+    async fn get_and_insert_with_deadline() {
+        use crate::DeadlineError;
+        use std::time::Instant;
+
+        let cache: Cache<usize, usize> = Cache::new(100_000);
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(cache.insert_with_deadline(0, 0, later).await, Ok(()));
+        assert_eq!(cache.get_with_deadline(&0, later), Ok(Some(0)));
+
+        // A passed deadline fails without touching the cache.
+        let passed = Instant::now();
+        assert_eq!(cache.get_with_deadline(&0, passed), Err(DeadlineError));
+        assert_eq!(
+            cache.insert_with_deadline(1, 1, passed).await,
+            Err(DeadlineError)
+        );
+        assert_eq!(cache.get(&1), None);
+
+        // Pause the maintenance and fill the write buffer.
+        let buffer_size = cache.base.write_op_ch().capacity().unwrap();
+        let frozen = cache.freeze();
+        while !cache.base.write_op_ch().is_full() {
+            cache.insert(0, 0).await;
+        }
+
+        // The insert gives up at the deadline, and the value is not inserted.
+        let started_at = Instant::now();
+        let deadline = started_at + Duration::from_millis(50);
+        assert_eq!(
+            cache.insert_with_deadline(2, 2, deadline).await,
+            Err(DeadlineError)
+        );
+        assert!(Instant::now() >= deadline);
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.base.write_op_ch().len(), buffer_size);
+
+        drop(frozen);
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(cache.insert_with_deadline(2, 2, later).await, Ok(()));
+        assert_eq!(cache.get(&2), Some(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn bulk_get_or_insert_with() {
+        use std::{
+            collections::HashMap,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        const NUM_TASKS: u32 = 16;
+
+        let cache = Cache::new(100);
+        cache.insert(0, 0).await;
+
+        // Every key passed to the loaders, to count how many times each of them
+        // has been loaded.
+        let load_counts = Arc::new(
+            (0..=NUM_TASKS + 2)
+                .map(|_| AtomicUsize::new(0))
+                .collect::<Vec<_>>(),
+        );
+
+        // Task i requests keys i..i+3, so the keys overlap between tasks.
+        let tasks = (0..NUM_TASKS)
+            .map(|i| {
+                let cache = cache.clone();
+                let load_counts = Arc::clone(&load_counts);
+                tokio::spawn(async move {
+                    let loader = |keys: Vec<u32>| async move {
+                        for k in &keys {
+                            load_counts[*k as usize].fetch_add(1, Ordering::AcqRel);
+                        }
+                        Timer::after(Duration::from_millis(50)).await;
+                        // Key 7 does not exist.
+                        keys.into_iter()
+                            .filter(|k| *k != 7)
+                            .map(|k| (k, k * 10))
+                            .collect::<HashMap<_, _>>()
+                    };
+                    cache.bulk_get_or_insert_with(i..i + 3, loader).await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (i, task) in (0..NUM_TASKS).zip(tasks) {
+            let values = task.await.expect("Failed to join a task");
+            for k in i..i + 3 {
+                match k {
+                    7 => assert!(!values.contains_key(&k)),
+                    _ => assert_eq!(values.get(&k), Some(&(k * 10))),
+                }
+            }
+        }
+
+        // Key 0 was a hit, and every other key has been loaded only once. (Key 7
+        // is not cached, so a task coming after its load may try it again.)
+        assert_eq!(load_counts[0].load(Ordering::Acquire), 0);
+        for (k, count) in load_counts
+            .iter()
+            .enumerate()
+            .take(NUM_TASKS as usize + 2)
+            .skip(1)
+        {
+            match k {
+                7 => assert!(count.load(Ordering::Acquire) >= 1),
+                _ => assert_eq!(count.load(Ordering::Acquire), 1),
+            }
+        }
+        assert_eq!(cache.get(&5), Some(50));
+        assert_eq!(cache.get(&7), None);
+
+        // The loader is not called when all keys are hit.
+        let values = cache
+            .bulk_get_or_insert_with(vec![1, 2, 2], |_| async { unreachable!() })
+            .await;
+        assert_eq!(values.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn bulk_get_or_insert_with_aborted_before_insert() {
+        use std::collections::HashMap;
+
+        let cache: Cache<u32, u32> = Cache::new(100);
+
+        // Load key 1, and drop the guard without inserting the value as an
+        // aborted `bulk_get_or_insert_with` would.
+        let (results, loaded_waiters) = cache
+            .value_initializer
+            .bulk_init_or_read(vec![Arc::new(1)], |keys| async move {
+                keys.into_iter()
+                    .map(|k| (*k, 10))
+                    .collect::<HashMap<_, _>>()
+            })
+            .await;
+        assert_eq!(results, vec![(Arc::new(1), Some(10), true)]);
+        std::mem::drop(loaded_waiters);
+
+        // The waiter has been removed, so the key is loaded again instead of
+        // being read from the stale waiter.
+        let values = cache
+            .bulk_get_or_insert_with(vec![1], |keys| async move {
+                keys.into_iter().map(|k| (k, 20)).collect()
+            })
+            .await;
+        assert_eq!(values.get(&1), Some(&20));
+        assert_eq!(cache.get(&1), Some(20));
+    }
+
+    #[tokio::test]
+    async fn get_or_try_insert_with() {
         // Note that MyError does not implement std::error::Error trait
         // like anyhow::Error.
         #[derive(Debug)]
-        pub struct MyError(String);
+        pub struct MyError(#[allow(dead_code)] String);
 
-        type MyResult<T> = Result<T, Arc<MyError>>;
+        type MyResult<T> = Result<T, LoadError<MyError>>;
 
         let cache = Cache::new(100);
         const KEY: u32 = 0;
@@ -1503,6 +3664,191 @@ mod tests {
         futures_util::join!(task1, task2, task3, task4, task5, task6, task7, task8);
     }
 
+    #[tokio::test]
+    async fn map_stream() {
+        use futures_util::stream::{self, StreamExt};
+        use std::{
+            collections::HashMap,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        let cache = Cache::new(100);
+        cache.insert(2, 20).await;
+
+        let calls = AtomicUsize::new(0);
+        let loader = |keys: Vec<u32>| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                // Key 4 does not exist.
+                keys.into_iter()
+                    .filter(|k| *k != 4)
+                    .map(|k| (k, k * 10))
+                    .collect::<HashMap<_, _>>()
+            }
+        };
+
+        // All misses fit in one batch. Key 1 appears twice.
+        let keys = stream::iter(vec![1, 2, 3, 1, 4]);
+        let results = cache
+            .map_stream(keys, loader, 10, Duration::from_secs(60))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            results,
+            vec![
+                (1, Some(10)),
+                (2, Some(20)),
+                (3, Some(30)),
+                (1, Some(10)),
+                (4, None)
+            ]
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.get(&3), Some(30));
+
+        // Batches are limited by max_batch.
+        let calls = AtomicUsize::new(0);
+        let loader = |keys: Vec<u32>| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            async move { keys.into_iter().map(|k| (k, k)).collect::<HashMap<_, _>>() }
+        };
+        let keys = stream::iter(vec![5, 6, 7, 8, 9]);
+        let results = cache
+            .map_stream(keys, loader, 2, Duration::from_secs(60))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 5);
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        // A batch is flushed after max_delay even if the input is not finished.
+        let calls = AtomicUsize::new(0);
+        let loader = |keys: Vec<u32>| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            async move { keys.into_iter().map(|k| (k, k)).collect::<HashMap<_, _>>() }
+        };
+        let keys = stream::iter(vec![10]).chain(stream::pending());
+        let mut results = Box::pin(cache.map_stream(keys, loader, 10, Duration::from_millis(10)));
+        assert_eq!(results.next().await, Some((10, Some(10))));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn map_stream_loads_each_key_once() {
+        use futures_util::stream::{self, StreamExt};
+        use std::{
+            collections::HashMap,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Mutex,
+            },
+        };
+
+        let cache = Cache::new(100);
+        let loads = Mutex::new(HashMap::<u32, usize>::new());
+        let loader = |keys: Vec<u32>| {
+            let loads = &loads;
+            async move {
+                {
+                    let mut loads = loads.lock().unwrap();
+                    for k in &keys {
+                        *loads.entry(*k).or_default() += 1;
+                    }
+                }
+                // Give the other stream time to request the same keys.
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                keys.into_iter()
+                    .map(|k| (k, k * 10))
+                    .collect::<HashMap<_, _>>()
+            }
+        };
+        let calls = AtomicUsize::new(0);
+        let loader1 = |keys| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            loader(keys)
+        };
+        let loader2 = |keys| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            loader(keys)
+        };
+
+        // The streams overlap on keys 3 to 6.
+        let stream1 = cache
+            .map_stream(stream::iter(0..7), loader1, 3, Duration::from_millis(10))
+            .collect::<Vec<_>>();
+        let stream2 = cache
+            .map_stream(stream::iter(3..10), loader2, 3, Duration::from_millis(10))
+            .collect::<Vec<_>>();
+        let (results1, results2) = futures_util::join!(stream1, stream2);
+
+        assert_eq!(
+            results1,
+            (0..7).map(|k| (k, Some(k * 10))).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            results2,
+            (3..10).map(|k| (k, Some(k * 10))).collect::<Vec<_>>()
+        );
+        let loads = loads.into_inner().unwrap();
+        assert_eq!(loads.len(), 10);
+        assert!(loads.values().all(|count| *count == 1), "{:?}", loads);
+        assert!(calls.load(Ordering::Relaxed) >= 2);
+    }
+
+    #[tokio::test]
+    async fn map_stream_coordinates_with_bulk_loads_only() {
+        use futures_util::stream::{self, StreamExt};
+        use std::{
+            collections::HashMap,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        let cache = Cache::new(100);
+        let stream_loads = AtomicUsize::new(0);
+        let stream_loader = |keys: Vec<u32>| {
+            stream_loads.fetch_add(keys.len(), Ordering::Relaxed);
+            async move {
+                keys.into_iter()
+                    .map(|k| (k, k * 20))
+                    .collect::<HashMap<_, _>>()
+            }
+        };
+        let (cache_ref, stream_loader) = (&cache, &stream_loader);
+        let delayed_stream = |key| async move {
+            // Let the other call start loading the key first.
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            cache_ref
+                .map_stream(
+                    stream::iter(vec![key]),
+                    stream_loader,
+                    1,
+                    Duration::from_millis(10),
+                )
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        // The stream waits for key 1 being loaded by `bulk_get_or_insert_with`.
+        let bulk = cache.bulk_get_or_insert_with(vec![1], |keys| async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            keys.into_iter().map(|k| (k, k * 10)).collect()
+        });
+        let (values, results) = futures_util::join!(bulk, delayed_stream(1));
+        assert_eq!(values.get(&1), Some(&10));
+        assert_eq!(results, vec![(1, Some(10))]);
+        assert_eq!(stream_loads.load(Ordering::Relaxed), 0);
+
+        // The stream does not wait for key 2 being loaded by `get_or_insert_with`,
+        // so both load it.
+        let single = cache.get_or_insert_with(2, async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            20
+        });
+        let (value, results) = futures_util::join!(single, delayed_stream(2));
+        assert_eq!(value, 20);
+        assert_eq!(results, vec![(2, Some(40))]);
+        assert_eq!(stream_loads.load(Ordering::Relaxed), 1);
+    }
+
     #[tokio::test]
     // https://github.com/moka-rs/moka/issues/43
     async fn handle_panic_in_get_or_insert_with() {
@@ -1544,12 +3890,12 @@ mod tests {
                         sleep(Duration::from_millis(50)).await;
                         panic!("Panic during get_or_try_insert_with");
                     })
-                    .await as Result<_, Arc<Infallible>>;
+                    .await as Result<_, LoadError>;
             });
         }
         let _ = semaphore.acquire().await.expect("semaphore acquire failed");
         assert_eq!(
-            cache.get_or_try_insert_with(1, async { Ok(5) }).await as Result<_, Arc<Infallible>>,
+            cache.get_or_try_insert_with(1, async { Ok(5) }).await as Result<_, LoadError>,
             Ok(5)
         );
     }
@@ -1604,7 +3950,7 @@ mod tests {
                         sleep(Duration::from_millis(50)).await;
                         unreachable!();
                     })
-                    .await as Result<_, Arc<Infallible>>;
+                    .await as Result<_, LoadError>;
             });
         }
 
@@ -1612,7 +3958,7 @@ mod tests {
         handle.abort();
 
         assert_eq!(
-            cache.get_or_try_insert_with(1, async { Ok(5) }).await as Result<_, Arc<Infallible>>,
+            cache.get_or_try_insert_with(1, async { Ok(5) }).await as Result<_, LoadError>,
             Ok(5)
         );
     }
@@ -0,0 +1,135 @@
+use crate::notification::RemovalCause;
+
+use futures_util::{stream::Stream, task::AtomicWaker};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+/// The number of removals an eviction stream buffers before it starts dropping
+/// the oldest ones.
+pub(crate) const EVICTION_STREAM_CAPACITY: usize = 1024;
+
+type Event<K, V> = (Arc<K>, V, RemovalCause);
+
+/// The buffer of an eviction stream.
+struct Queue<K, V> {
+    events: Mutex<VecDeque<Event<K, V>>>,
+    waker: AtomicWaker,
+    // Set when the cache is dropped.
+    closed: AtomicBool,
+}
+
+impl<K, V> Queue<K, V> {
+    /// Pushes the event and wakes up the stream. Returns true if the oldest event
+    /// has been dropped to make room for it.
+    fn push(&self, event: Event<K, V>) -> bool {
+        let dropped = {
+            let mut events = self.events.lock();
+            let dropped = events.len() >= EVICTION_STREAM_CAPACITY;
+            if dropped {
+                events.pop_front();
+            }
+            events.push_back(event);
+            dropped
+        };
+        self.waker.wake();
+        dropped
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+/// The eviction streams of a cache, which receive the same removals as the
+/// eviction listener.
+pub(crate) struct EvictionStreams<K, V> {
+    queues: Mutex<Vec<Arc<Queue<K, V>>>>,
+    // Lets the removals skip cloning the value when there is no stream.
+    has_streams: AtomicBool,
+}
+
+impl<K, V> Default for EvictionStreams<K, V> {
+    fn default() -> Self {
+        Self {
+            queues: Mutex::new(Vec::new()),
+            has_streams: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<K, V> Drop for EvictionStreams<K, V> {
+    fn drop(&mut self) {
+        // End the streams after they have yielded the buffered events.
+        for queue in self.queues.get_mut().iter() {
+            queue.close();
+        }
+    }
+}
+
+impl<K, V: Clone> EvictionStreams<K, V> {
+    pub(crate) fn subscribe(&self) -> EvictionStream<K, V> {
+        let queue = Arc::new(Queue {
+            events: Mutex::new(VecDeque::new()),
+            waker: AtomicWaker::new(),
+            closed: AtomicBool::new(false),
+        });
+        self.queues.lock().push(Arc::clone(&queue));
+        self.has_streams.store(true, Ordering::Release);
+        EvictionStream { queue }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.has_streams.load(Ordering::Acquire)
+    }
+
+    /// Sends the removal to every stream, and forgets the dropped streams.
+    /// Returns the number of the events dropped by the full streams.
+    pub(crate) fn push(&self, key: &Arc<K>, value: &V, cause: RemovalCause) -> u64 {
+        let mut queues = self.queues.lock();
+        // A queue only referred to by this is one of a dropped stream.
+        queues.retain(|q| Arc::strong_count(q) > 1);
+        if queues.is_empty() {
+            self.has_streams.store(false, Ordering::Release);
+        }
+        queues
+            .iter()
+            .filter(|q| q.push((Arc::clone(key), value.clone(), cause)))
+            .count() as u64
+    }
+}
+
+/// The stream returned by `eviction_stream` of the async cache.
+pub(crate) struct EvictionStream<K, V> {
+    queue: Arc<Queue<K, V>>,
+}
+
+impl<K, V> Stream for EvictionStream<K, V> {
+    type Item = Event<K, V>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let queue = &self.queue;
+        if let Some(event) = queue.events.lock().pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        queue.waker.register(cx.waker());
+        // Read the flag before checking the events again, so that the events
+        // pushed before the cache was dropped are not lost, and so is an event
+        // pushed before the waker was registered.
+        let closed = queue.closed.load(Ordering::Acquire);
+        match queue.events.lock().pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None if closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
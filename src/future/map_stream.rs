@@ -0,0 +1,145 @@
+use super::Cache;
+
+use futures_util::{
+    future::{self, Either},
+    stream::{self, Fuse, Stream, StreamExt},
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    hash::{BuildHasher, Hash},
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+struct State<'a, K, V, S, St, L> {
+    cache: &'a Cache<K, V, S>,
+    keys: Pin<Box<Fuse<St>>>,
+    loader: L,
+    max_batch: usize,
+    max_delay: Duration,
+    // Keys waiting for the current batch to be loaded, in the input order. The
+    // `bool` is true for a miss.
+    pending: Vec<(K, Option<V>, bool)>,
+    miss_count: usize,
+    deadline: Option<Instant>,
+    // Resolved keys to emit.
+    ready: VecDeque<(K, Option<V>)>,
+}
+
+pub(crate) fn map_stream<'a, K, V, S, St, L, Fut>(
+    cache: &'a Cache<K, V, S>,
+    keys: St,
+    loader: L,
+    max_batch: usize,
+    max_delay: Duration,
+) -> impl Stream<Item = (K, Option<V>)> + 'a
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+    St: Stream<Item = K> + 'a,
+    L: FnMut(Vec<K>) -> Fut + 'a,
+    Fut: Future<Output = HashMap<K, V>> + 'a,
+{
+    let state = State {
+        cache,
+        keys: Box::pin(keys.fuse()),
+        loader,
+        max_batch: max_batch.max(1),
+        max_delay,
+        pending: Vec::default(),
+        miss_count: 0,
+        deadline: None,
+        ready: VecDeque::default(),
+    };
+
+    stream::unfold(state, |mut st| async move {
+        loop {
+            if let Some(item) = st.ready.pop_front() {
+                return Some((item, st));
+            }
+
+            let next_key = if let Some(deadline) = st.deadline {
+                let timer = async_io::Timer::at(deadline);
+                match future::select(st.keys.next(), timer).await {
+                    Either::Left((key, _)) => key.map(Some),
+                    // The deadline has passed.
+                    Either::Right(_) => Some(None),
+                }
+            } else {
+                st.keys.next().await.map(Some)
+            };
+
+            match next_key {
+                Some(Some(key)) => {
+                    let value = st.cache.get(&key);
+                    if st.pending.is_empty() && value.is_some() {
+                        // A hit with nothing to wait for. Emit it immediately.
+                        return Some(((key, value), st));
+                    }
+                    let is_miss = value.is_none();
+                    st.pending.push((key, value, is_miss));
+                    if is_miss {
+                        st.miss_count += 1;
+                        if st.deadline.is_none() {
+                            st.deadline = Some(Instant::now() + st.max_delay);
+                        }
+                        if st.miss_count >= st.max_batch {
+                            st.load_pending().await;
+                        }
+                    }
+                }
+                // The deadline has passed.
+                Some(None) => st.load_pending().await,
+                // No more keys.
+                None if st.pending.is_empty() => return None,
+                None => st.load_pending().await,
+            }
+        }
+    })
+}
+
+impl<'a, K, V, S, St, L, Fut> State<'a, K, V, S, St, L>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+    L: FnMut(Vec<K>) -> Fut,
+    Fut: Future<Output = HashMap<K, V>>,
+{
+    /// Loads the missed keys in the pending batch through the value initializer,
+    /// and moves the whole batch to the ready queue.
+    async fn load_pending(&mut self) {
+        let mut seen = HashSet::with_capacity(self.miss_count);
+        let misses = self
+            .pending
+            .iter()
+            .filter(|(_, _, is_miss)| *is_miss)
+            .filter(|(k, _, _)| seen.insert(k.clone()))
+            .map(|(k, _, _)| k.clone())
+            .collect::<Vec<_>>();
+
+        // Keys being loaded by other streams or `bulk_get_or_insert_with` calls
+        // are not passed to the loader; they are waited for instead.
+        let loaded = if misses.is_empty() {
+            HashMap::default()
+        } else {
+            self.cache
+                .bulk_get_or_insert_with(misses, &mut self.loader)
+                .await
+        };
+
+        for (key, value, is_miss) in self.pending.drain(..) {
+            let value = if is_miss {
+                // The loaded map is keyed by the normalized keys.
+                loaded.get(&self.cache.normalize_key(key.clone())).cloned()
+            } else {
+                value
+            };
+            self.ready.push_back((key, value));
+        }
+        self.miss_count = 0;
+        self.deadline = None;
+    }
+}
@@ -0,0 +1,166 @@
+use crate::sync::{
+    miss_claim::{ClaimState, MissClaims},
+    MissResolution,
+};
+
+use async_lock::{RwLock, RwLockWriteGuardArc};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::Arc,
+};
+
+/// A claimed miss of the async cache. The owner holds its write lock until it
+/// resolves the claim, and the waiters wait for a read lock.
+pub(crate) type Claim<V> = RwLock<ClaimState<V>>;
+
+/// The result of [`Cache::claim_miss`][claim-miss].
+///
+/// [claim-miss]: ./struct.Cache.html#method.claim_miss
+pub enum MissClaim<K, V, S = RandomState>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// The key is in the cache. Holds a clone of the value.
+    Hit(V),
+    /// The key was absent and not claimed. The caller owns the claim, and is
+    /// expected to load the value and give it to the cache with the token.
+    Owner(MissToken<K, V, S>),
+    /// Another caller owns the claim of the key. The caller can wait for it to
+    /// be resolved.
+    Pending(MissWaiter<V>),
+}
+
+/// The token of a claimed miss, returned to the owner of the claim by
+/// [`Cache::claim_miss`][claim-miss].
+///
+/// Give it back to [`Cache::complete`][complete] with the loaded value, or to
+/// [`Cache::abandon`][abandon]. Dropping the token without doing so, e.g. when
+/// the task of the owner is cancelled, abandons the claim, so that the waiters
+/// do not wait forever.
+///
+/// [claim-miss]: ./struct.Cache.html#method.claim_miss
+/// [complete]: ./struct.Cache.html#method.complete
+/// [abandon]: ./struct.Cache.html#method.abandon
+pub struct MissToken<K, V, S = RandomState>
+// NOTE: We usually do not attach trait bounds to here at the struct definition, but
+// the Drop trait requires these bounds here.
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    key: Arc<K>,
+    hash: u64,
+    claim: Arc<Claim<V>>,
+    // Taken when the claim is resolved.
+    guard: Option<RwLockWriteGuardArc<ClaimState<V>>>,
+    claims: Arc<MissClaims<K, Claim<V>, S>>,
+}
+
+impl<K, V, S> MissToken<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the claimed key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub(crate) fn key_and_hash(&self) -> (&Arc<K>, u64) {
+        (&self.key, self.hash)
+    }
+
+    pub(crate) fn is_claimed_from(&self, claims: &Arc<MissClaims<K, Claim<V>, S>>) -> bool {
+        Arc::ptr_eq(&self.claims, claims)
+    }
+
+    /// Resolves the claim unless it has been resolved, wakes up the waiters by
+    /// releasing the write lock, and removes the claim so that the key can be
+    /// claimed again.
+    pub(crate) fn resolve(&mut self, resolution: ClaimState<V>) {
+        if let Some(mut guard) = self.guard.take() {
+            *guard = resolution;
+            self.claims.remove(&self.key, &self.claim);
+        }
+    }
+}
+
+impl<K, V, S> Drop for MissToken<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        self.resolve(ClaimState::Abandoned);
+    }
+}
+
+// See the unwind safety impls of `sync::MissToken`.
+impl<K, V, S> UnwindSafe for MissToken<K, V, S>
+where
+    K: Hash + Eq + RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: BuildHasher + RefUnwindSafe,
+{
+}
+
+impl<K, V, S> RefUnwindSafe for MissToken<K, V, S>
+where
+    K: Hash + Eq + RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: BuildHasher + RefUnwindSafe,
+{
+}
+
+/// A handle to wait for a miss claimed by another caller of
+/// [`Cache::claim_miss`][claim-miss].
+///
+/// [claim-miss]: ./struct.Cache.html#method.claim_miss
+pub struct MissWaiter<V> {
+    claim: Arc<Claim<V>>,
+}
+
+impl<V: Clone> MissWaiter<V> {
+    /// Waits until the owner of the claim completes or abandons it.
+    pub async fn wait(self) -> MissResolution<V> {
+        match &*self.claim.read().await {
+            ClaimState::Completed(v) => MissResolution::Completed(v.clone()),
+            // The owner always resolves the claim before releasing the lock.
+            ClaimState::Pending | ClaimState::Abandoned => MissResolution::Retry,
+        }
+    }
+}
+
+impl<V> UnwindSafe for MissWaiter<V> where V: RefUnwindSafe {}
+
+impl<V> RefUnwindSafe for MissWaiter<V> where V: RefUnwindSafe {}
+
+/// Claims the miss of the key unless it has been claimed.
+pub(crate) fn claim<K, V, S>(
+    claims: &Arc<MissClaims<K, Claim<V>, S>>,
+    key: Arc<K>,
+    hash: u64,
+) -> MissClaim<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    let claim = Arc::new(RwLock::new(ClaimState::Pending));
+    // Lock the claim before publishing it, so that the waiters wait for it.
+    let guard = claim
+        .try_write_arc()
+        .expect("A new claim should not be locked");
+    match claims.try_insert(&key, &claim) {
+        None => MissClaim::Owner(MissToken {
+            key,
+            hash,
+            claim,
+            guard: Some(guard),
+            claims: Arc::clone(claims),
+        }),
+        Some(claim) => MissClaim::Pending(MissWaiter { claim }),
+    }
+}
@@ -1,6 +1,7 @@
 use async_lock::RwLock;
 use std::{
     any::{Any, TypeId},
+    collections::HashMap,
     future::Future,
     hash::{BuildHasher, Hash},
     sync::Arc,
@@ -12,6 +13,8 @@ pub(crate) enum InitResult<V, E> {
     Initialized(V),
     ReadExisting(V),
     InitErr(Arc<E>),
+    // The init futures of the other calls have kept panicking.
+    OwnerPanicked,
 }
 
 enum WaiterValue<V> {
@@ -25,6 +28,17 @@ enum WaiterValue<V> {
 
 type Waiter<V> = Arc<RwLock<WaiterValue<V>>>;
 
+/// The number of times a call retries the load after the init future of another
+/// call has panicked or been aborted.
+const MAX_RETRIES: usize = 200;
+
+/// The type ID of the waiters of `bulk_init_or_read`. It keeps them apart from
+/// the waiters of `init_or_read`, as a bulk waiter may hold `NotLoaded`.
+struct BulkLoad;
+
+/// The error set to the waiter of a key that the bulk loader has not returned.
+struct NotLoaded;
+
 struct WaiterGuard<'a, K, V, S>
 // NOTE: We usually do not attach trait bounds to here at the struct definition, but
 // the Drop trait requires these bounds here.
@@ -85,6 +99,35 @@ where
     }
 }
 
+/// Removes the waiters of the keys loaded by `bulk_init_or_read` when dropped.
+///
+/// The caller holds it while inserting the loaded values into the cache. If the
+/// enclosing future is aborted in the middle, the waiters are still removed, so
+/// the later calls will load the keys again instead of reading stale waiters.
+pub(crate) struct BulkWaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher,
+{
+    keys: Vec<Arc<K>>,
+    value_initializer: &'a ValueInitializer<K, V, S>,
+}
+
+impl<'a, K, V, S> Drop for BulkWaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        let type_id = TypeId::of::<BulkLoad>();
+        for key in &self.keys {
+            self.value_initializer.remove_waiter(key, type_id);
+        }
+    }
+}
+
 pub(crate) struct ValueInitializer<K, V, S> {
     // TypeId is the type ID of the concrete error type of generic type E in
     // try_init_or_read(). We use the type ID as a part of the key to ensure that
@@ -119,7 +162,10 @@ where
         };
 
         let type_id = TypeId::of::<()>();
-        self.do_try_init(&key, type_id, init, post_init).await
+        match self.do_try_init(&key, type_id, init, post_init).await {
+            InitResult::OwnerPanicked => panic_for_retries_exhausted_for_panicking(MAX_RETRIES),
+            result => result,
+        }
     }
 
     /// # Panics
@@ -150,6 +196,109 @@ where
         self.do_try_init(&key, type_id, init, post_init).await
     }
 
+    /// Loads the values of the keys by calling `init` once, unless other calls of
+    /// this method are already loading some of them. In that case, waits for
+    /// them instead of loading them again.
+    ///
+    /// Returns the values of the keys, together with `true` for the keys loaded
+    /// by this call. The waiters of the loaded keys are removed when the returned
+    /// guard is dropped, which must be after the values are inserted into the
+    /// cache.
+    ///
+    /// # Panics
+    /// Panics if the `init` future has been panicked.
+    pub(crate) async fn bulk_init_or_read<F, Fut>(
+        &self,
+        keys: Vec<Arc<K>>,
+        init: F,
+    ) -> (Vec<(Arc<K>, Option<V>, bool)>, BulkWaiterGuard<'_, K, V, S>)
+    where
+        K: Eq + Hash,
+        F: FnOnce(Vec<Arc<K>>) -> Fut,
+        Fut: Future<Output = HashMap<K, V>>,
+    {
+        use futures_util::FutureExt;
+        use std::panic::{resume_unwind, AssertUnwindSafe};
+
+        let type_id = TypeId::of::<BulkLoad>();
+        let new_waiters = keys
+            .iter()
+            .map(|_| Arc::new(RwLock::new(WaiterValue::Computing)))
+            .collect::<Vec<_>>();
+        // Nobody else knows the new waiters yet, so we can take the write locks
+        // without waiting.
+        let mut locks = new_waiters
+            .iter()
+            .map(|w| {
+                w.try_write()
+                    .expect("Failed to lock a new waiter. This might be a bug in Moka")
+            })
+            .collect::<Vec<_>>();
+
+        // Claim the keys that nobody else is loading.
+        let mut others_waiters = Vec::new();
+        let mut guards = Vec::new();
+        for ((key, waiter), lock) in keys.iter().zip(&new_waiters).zip(locks.iter_mut()) {
+            match self.try_insert_waiter(key, type_id, waiter) {
+                None => guards.push(WaiterGuard::new(key, type_id, self, &mut **lock)),
+                Some(w) => others_waiters.push((key, w)),
+            }
+        }
+
+        let mut results = Vec::with_capacity(keys.len());
+        let mut loaded_waiters = BulkWaiterGuard {
+            keys: Vec::with_capacity(guards.len()),
+            value_initializer: self,
+        };
+        if !guards.is_empty() {
+            let claimed = guards.iter().map(|g| Arc::clone(g.key)).collect();
+            // Catching panic is safe here as we do not try to resolve the future again.
+            match AssertUnwindSafe(init(claimed)).catch_unwind().await {
+                Ok(mut loaded) => {
+                    for guard in &mut guards {
+                        let key = guard.key;
+                        match loaded.remove(&**key) {
+                            Some(value) => {
+                                guard.set_waiter_value(WaiterValue::Ready(Ok(value.clone())));
+                                loaded_waiters.keys.push(Arc::clone(key));
+                                results.push((Arc::clone(key), Some(value), true));
+                            }
+                            None => {
+                                let err: ErrorObject = Arc::new(NotLoaded);
+                                guard.set_waiter_value(WaiterValue::Ready(Err(err)));
+                                self.remove_waiter(key, type_id);
+                                results.push((Arc::clone(key), None, false));
+                            }
+                        }
+                    }
+                }
+                Err(payload) => {
+                    for guard in &mut guards {
+                        guard.set_waiter_value(WaiterValue::InitFuturePanicked);
+                        self.remove_waiter(guard.key, type_id);
+                    }
+                    resume_unwind(payload);
+                }
+            }
+        }
+
+        // Unlock our waiters before waiting for the others, so that two calls
+        // waiting for each other's keys will not deadlock.
+        std::mem::drop(guards);
+        std::mem::drop(locks);
+
+        for (key, waiter) in others_waiters {
+            // A key whose loader has not returned it, has panicked or has been
+            // aborted is treated as not loaded.
+            let value = match &*waiter.read().await {
+                WaiterValue::Ready(Ok(value)) => Some(value.clone()),
+                _ => None,
+            };
+            results.push((Arc::clone(key), value, false));
+        }
+        (results, loaded_waiters)
+    }
+
     /// # Panics
     /// Panics if the `init` future has been panicked.
     async fn do_try_init<'a, F, O, C, E>(
@@ -166,14 +315,28 @@ where
     {
         use futures_util::FutureExt;
         use std::panic::{resume_unwind, AssertUnwindSafe};
-        use InitResult::*;
 
-        const MAX_RETRIES: usize = 200;
         let mut retries = 0;
 
         loop {
+            // Wait for the existing waiter if any, before allocating our own. This
+            // way, the tasks contending on the same key share one waiter and wait
+            // on its lock, instead of each allocating a waiter that will be thrown
+            // away.
+            if let Some(res) = self.get_waiter(key, type_id) {
+                match Self::read_waiter_value(&res, &mut retries, MAX_RETRIES).await {
+                    Some(result) => return result,
+                    // Retry from the beginning.
+                    None => continue,
+                }
+            }
+
             let waiter = Arc::new(RwLock::new(WaiterValue::Computing));
-            let mut lock = waiter.write().await;
+            // Nobody else knows the new waiter yet, so we can take the write lock
+            // without waiting.
+            let mut lock = waiter
+                .try_write()
+                .expect("Failed to lock a new waiter. This might be a bug in Moka");
 
             match self.try_insert_waiter(key, type_id, &waiter) {
                 None => {
@@ -198,40 +361,70 @@ where
                     }
                 }
                 Some(res) => {
-                    // Somebody else's waiter already exists. Drop our write lock and wait
-                    // for a read lock to become available.
+                    // Somebody else's waiter has been inserted in the meantime. Drop
+                    // our write lock and wait for a read lock to become available.
                     std::mem::drop(lock);
-                    match &*res.read().await {
-                        WaiterValue::Ready(Ok(value)) => return ReadExisting(value.clone()),
-                        WaiterValue::Ready(Err(e)) => {
-                            return InitErr(Arc::clone(e).downcast().unwrap())
-                        }
-                        // Somebody else's init future has been panicked.
-                        WaiterValue::InitFuturePanicked => {
-                            retries += 1;
-                            panic_if_retry_exhausted_for_panicking(retries, MAX_RETRIES);
-                            // Retry from the beginning.
-                            continue;
-                        }
-                        // Somebody else (a future containing `get_or_insert_with`/
-                        // `get_or_try_insert_with`) has been aborted.
-                        WaiterValue::EnclosingFutureAborted => {
-                            retries += 1;
-                            panic_if_retry_exhausted_for_aborting(retries, MAX_RETRIES);
-                            // Retry from the beginning.
-                            continue;
-                        }
-                        // Unexpected state.
-                        WaiterValue::Computing => panic!(
-                            "Got unexpected state `Computing` after resolving `init` future. \
-                        This might be a bug in Moka"
-                        ),
+                    if let Some(result) =
+                        Self::read_waiter_value(&res, &mut retries, MAX_RETRIES).await
+                    {
+                        return result;
                     }
+                    // Retry from the beginning.
+                }
+            }
+        }
+    }
+
+    /// Waits for somebody else's init future to be resolved and returns its
+    /// result. Returns `None` if the caller should retry from the beginning.
+    async fn read_waiter_value<E>(
+        waiter: &Waiter<V>,
+        retries: &mut usize,
+        max_retries: usize,
+    ) -> Option<InitResult<V, E>>
+    where
+        E: Send + Sync + 'static,
+    {
+        use InitResult::*;
+
+        match &*waiter.read().await {
+            WaiterValue::Ready(Ok(value)) => Some(ReadExisting(value.clone())),
+            WaiterValue::Ready(Err(e)) => Some(InitErr(Arc::clone(e).downcast().unwrap())),
+            // Somebody else's init future has been panicked.
+            WaiterValue::InitFuturePanicked => {
+                *retries += 1;
+                if *retries >= max_retries {
+                    Some(OwnerPanicked)
+                } else {
+                    None
                 }
             }
+            // Somebody else (a future containing `get_or_insert_with`/
+            // `get_or_try_insert_with`) has been aborted.
+            WaiterValue::EnclosingFutureAborted => {
+                *retries += 1;
+                panic_if_retry_exhausted_for_aborting(*retries, max_retries);
+                None
+            }
+            // Unexpected state.
+            WaiterValue::Computing => panic!(
+                "Got unexpected state `Computing` after resolving `init` future. \
+            This might be a bug in Moka"
+            ),
         }
     }
 
+    #[inline]
+    fn get_waiter(&self, key: &Arc<K>, type_id: TypeId) -> Option<Waiter<V>> {
+        let key = Arc::clone(key);
+        self.waiters.get(&(key, type_id))
+    }
+
+    /// Returns true if a load of the key by `init_or_read` is in progress.
+    pub(crate) fn is_loading(&self, key: &Arc<K>) -> bool {
+        self.get_waiter(key, TypeId::of::<()>()).is_some()
+    }
+
     #[inline]
     pub(crate) fn remove_waiter(&self, key: &Arc<K>, type_id: TypeId) {
         let key = Arc::clone(key);
@@ -253,14 +446,12 @@ where
     }
 }
 
-fn panic_if_retry_exhausted_for_panicking(retries: usize, max: usize) {
-    if retries >= max {
-        panic!(
-            "Too many retries. Tried to read the return value from the `init` future \
+fn panic_for_retries_exhausted_for_panicking(retries: usize) -> ! {
+    panic!(
+        "Too many retries. Tried to read the return value from the `init` future \
     but failed {} times. Maybe the `init` kept panicking?",
-            retries
-        );
-    }
+        retries
+    );
 }
 
 fn panic_if_retry_exhausted_for_aborting(retries: usize, max: usize) {
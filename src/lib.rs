@@ -154,15 +154,59 @@
 #[cfg(feature = "future")]
 pub mod future;
 
+pub mod defaults;
+pub mod notification;
+pub mod policy;
 pub mod sync;
 pub mod unsync;
 
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
 pub(crate) mod common;
 
-pub use common::error::PredicateError;
+pub use common::entry::Entry;
+pub use common::error::{
+    BuildError, CasError, DeadlineError, DefaultsError, FallbackError, LoadError,
+    LoadSheddingError, OccupiedError, PredicateError,
+};
+pub use common::precomputed_hash::PrecomputedHashKey;
+pub use common::stats::{CacheStats, MaintenanceStats};
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    // Checks that the cache types have the same accessors returning the same
+    // values, so that they do not drift apart.
+    macro_rules! assert_accessor_parity {
+        ($builder:expr, $insert:ident) => {{
+            let cache = $builder
+                .name("parity")
+                .time_to_live(Duration::from_secs(60))
+                .time_to_idle(Duration::from_secs(30))
+                .build();
+            assert_eq!(cache.name(), Some("parity"));
+            assert_eq!(cache.time_to_live(), Some(Duration::from_secs(60)));
+            assert_eq!(cache.time_to_idle(), Some(Duration::from_secs(30)));
+            assert_eq!(cache.max_age(), None);
+            assert!(!cache.has_per_entry_expiration());
+
+            cache.$insert(1, 1);
+            assert!(!cache.has_per_entry_expiration());
+            assert!(cache.invalidate_after(&1, Duration::from_secs(10)));
+            assert!(cache.has_per_entry_expiration());
+        }};
+    }
+
+    #[test]
+    fn accessor_parity() {
+        assert_accessor_parity!(crate::sync::CacheBuilder::new(100), insert);
+        assert_accessor_parity!(crate::sync::CacheBuilder::new(100).segments(4), insert);
+        #[cfg(feature = "future")]
+        assert_accessor_parity!(crate::future::CacheBuilder::new(100), blocking_insert);
+    }
+
     // #[cfg(trybuild)]
     // #[test]
     // fn ui_trybuild() {
@@ -0,0 +1,238 @@
+//! Common data types for notifications.
+
+use std::{
+    collections::VecDeque,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+pub(crate) type BatchedListenerFn<K, V> =
+    Box<dyn Fn(&[(Arc<K>, V, RemovalCause)]) + Send + Sync + 'static>;
+
+/// The eviction listener of a cache. Only one of the kinds can be set.
+pub(crate) enum EvictionListener<K, V> {
+    PerEntry(Arc<dyn Fn(Arc<K>, V, RemovalCause) + Send + Sync + 'static>),
+    WithInfo(Arc<dyn Fn(Arc<K>, V, RemovalInfo) + Send + Sync + 'static>),
+    Batched(Arc<BatchedListener<K, V>>),
+}
+
+impl<K, V> Clone for EvictionListener<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::PerEntry(listener) => Self::PerEntry(Arc::clone(listener)),
+            Self::WithInfo(listener) => Self::WithInfo(Arc::clone(listener)),
+            Self::Batched(listener) => Self::Batched(Arc::clone(listener)),
+        }
+    }
+}
+
+/// Accumulates the removals, and delivers them to the listener in batches of up
+/// to `max_batch_size`, in the order they were made.
+pub(crate) struct BatchedListener<K, V> {
+    listener: BatchedListenerFn<K, V>,
+    max_batch_size: usize,
+    pending: Mutex<VecDeque<(Arc<K>, V, RemovalCause)>>,
+    // Held while taking a batch from `pending` and delivering it, so that the
+    // batches are delivered in order.
+    delivering: Mutex<()>,
+}
+
+impl<K, V> BatchedListener<K, V> {
+    pub(crate) fn new(listener: BatchedListenerFn<K, V>, max_batch_size: usize) -> Self {
+        Self {
+            listener,
+            max_batch_size,
+            pending: Mutex::new(VecDeque::new()),
+            delivering: Mutex::new(()),
+        }
+    }
+
+    fn push(&self, key: Arc<K>, value: V, cause: RemovalCause) {
+        let is_full = {
+            let mut pending = self.pending.lock();
+            pending.push_back((key, value, cause));
+            pending.len() >= self.max_batch_size
+        };
+        if is_full {
+            self.flush();
+        }
+    }
+
+    /// Delivers the pending removals. Returns without delivering if another
+    /// thread (or the listener itself, by touching the cache) is delivering, as
+    /// that thread will deliver them.
+    fn flush(&self) {
+        loop {
+            let _guard = match self.delivering.try_lock() {
+                Some(guard) => guard,
+                None => return,
+            };
+            let batch = {
+                let mut pending = self.pending.lock();
+                let len = pending.len().min(self.max_batch_size);
+                pending.drain(..len).collect::<Vec<_>>()
+            };
+            if batch.is_empty() {
+                return;
+            }
+            let _ = catch_unwind(AssertUnwindSafe(|| (self.listener)(&batch)));
+        }
+    }
+}
+
+pub(crate) type AccessListener<K, V> = Arc<dyn Fn(&K, &V) + Send + Sync + 'static>;
+
+/// Indicates the reason why a cached entry was removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalCause {
+    /// The entry's expiration timestamp has passed.
+    Expired,
+    /// The entry was manually removed by the user, e.g. by `invalidate`,
+    /// `invalidate_all` or `invalidate_entries_if`.
+    Explicit,
+    /// The entry itself was not actually removed, but its value was replaced by
+    /// the user.
+    Replaced,
+    /// The entry was evicted due to size constraints.
+    Size,
+    /// The entry was new, and was removed right after its insertion as the
+    /// admission policy found it less popular than the entries it would have
+    /// evicted.
+    ///
+    /// A new entry too large to fit in the cache at all is removed with `Size`.
+    NotAdmitted,
+    /// The cache was dropped while the entry was still in it. Only delivered when
+    /// the cache was built with `invoke_listener_on_drop(true)`.
+    Shutdown,
+    /// The value was a weak reference, and the object it pointed to has been
+    /// dropped. Only delivered by the caches built with `weak_values`. The value
+    /// given to the eviction listener is the `Weak` that no longer upgrades.
+    Collected,
+}
+
+/// The region of the cache where an entry was when it was evicted for the size.
+///
+/// The cache admits a new entry to the probation region, and promotes it to the
+/// protected region when it is accessed again. The eviction takes victims from
+/// the probation region first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheRegion {
+    /// The probation region of the main space, where the entries that have not
+    /// been accessed since their admission (or that have been demoted from the
+    /// protected region) are.
+    MainProbation,
+    /// The protected region of the main space, where the entries that have been
+    /// accessed after their admission are.
+    MainProtected,
+}
+
+/// The details of a removal given to the eviction listener set by
+/// `eviction_listener_with_info` of the cache builders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RemovalInfo {
+    cause: RemovalCause,
+    region: Option<CacheRegion>,
+}
+
+impl RemovalInfo {
+    pub(crate) fn new(cause: RemovalCause, region: Option<CacheRegion>) -> Self {
+        Self { cause, region }
+    }
+
+    /// Returns the reason why the entry was removed.
+    pub fn cause(&self) -> RemovalCause {
+        self.cause
+    }
+
+    /// Returns the region the entry was evicted from. Only a removal caused by
+    /// `RemovalCause::Size` has a region; it is `None` for the other causes.
+    pub fn region(&self) -> Option<CacheRegion> {
+        self.region
+    }
+}
+
+/// Where a cache drops the values it removes. Set by `drop_values_on` of the
+/// builders of [`sync::Cache`][sync-cache], [`sync::SegmentedCache`][seg-cache]
+/// and [`future::Cache`][future-cache].
+///
+/// [sync-cache]: ../sync/struct.Cache.html
+/// [seg-cache]: ../sync/struct.SegmentedCache.html
+/// [future-cache]: ../future/struct.Cache.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropContext {
+    /// The value is dropped on the thread that releases the last reference to it
+    /// held by the cache. This is the default.
+    ///
+    /// It is usually the thread running the maintenance (a housekeeper thread or
+    /// the caller of a cache method), or the caller of `insert` or `invalidate`.
+    /// As the internal concurrent hash table defers dropping removed entries, it
+    /// can also be any thread calling a method of the cache a little later.
+    Inline,
+    /// The value is dropped on the housekeeper threads, the threads that run the
+    /// maintenance and call the eviction listener. They are shared with the other
+    /// caches, so a slow drop delays the maintenance of all caches.
+    EvictionListener,
+    /// The value is dropped on a thread dedicated to the cache. The thread exits
+    /// after the cache and all its values have been dropped.
+    Worker,
+}
+
+impl RemovalCause {
+    /// Returns `true` if the entry was removed by the cache itself, rather than
+    /// by the user.
+    pub fn was_evicted(&self) -> bool {
+        matches!(
+            self,
+            Self::Expired | Self::Size | Self::NotAdmitted | Self::Collected
+        )
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Expired => "expired",
+            Self::Explicit => "invalidated",
+            Self::Replaced => "replaced",
+            Self::Size => "size",
+            Self::NotAdmitted => "not_admitted",
+            Self::Shutdown => "shutdown",
+            Self::Collected => "collected",
+        }
+    }
+}
+
+/// Calls the listener, and swallows a panic raised by it so that the cache stays
+/// usable (and so that a panic during drop does not abort the process). A batched
+/// listener is only called when the batch is full.
+pub(crate) fn notify<K, V>(
+    listener: &EvictionListener<K, V>,
+    key: Arc<K>,
+    value: V,
+    info: RemovalInfo,
+) {
+    match listener {
+        EvictionListener::PerEntry(listener) => {
+            let _ = catch_unwind(AssertUnwindSafe(|| listener(key, value, info.cause)));
+        }
+        EvictionListener::WithInfo(listener) => {
+            let _ = catch_unwind(AssertUnwindSafe(|| listener(key, value, info)));
+        }
+        EvictionListener::Batched(listener) => listener.push(key, value, info.cause),
+    }
+}
+
+/// Delivers the removals pending in a batched listener. Called at the end of a
+/// maintenance pass.
+pub(crate) fn flush<K, V>(listener: &EvictionListener<K, V>) {
+    if let EvictionListener::Batched(listener) = listener {
+        listener.flush();
+    }
+}
+
+/// Calls the access listener, and swallows a panic raised by it, so that the
+/// panic does not reach the caller of `get`.
+pub(crate) fn notify_access<K, V>(listener: &AccessListener<K, V>, key: &K, value: &V) {
+    let _ = catch_unwind(AssertUnwindSafe(|| listener(key, value)));
+}
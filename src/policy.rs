@@ -0,0 +1,162 @@
+//! Types to configure the policies of the caches.
+
+use crate::common::{self, frequency_sketch::FrequencySketch};
+
+use parking_lot::RwLock;
+use std::{collections::hash_map::RandomState, sync::Arc};
+
+/// A frequency sketch that can be shared by multiple caches, so that a new cache
+/// starts with the popularity of the keys learned by the others.
+///
+/// The admission policy of a cache compares the access frequencies of the keys
+/// recorded by its sketch. A cache normally starts with an empty sketch, so it
+/// makes poor admission decisions until it has seen enough accesses. When a
+/// dataset is split across caches whose keys are equally popular (e.g. a cache
+/// per data version), give them a shared sketch by
+/// [`CacheBuilder::shared_frequency_sketch`][builder-method], so that the
+/// accesses to the existing caches are already counted when a new cache is
+/// created.
+///
+/// The sketch is keyed by the hash of the keys, so the caches sharing it must
+/// hash the keys in the same way. The `build` method of the builder uses the
+/// hasher of the sketch for this. If you use `build_with_hasher` instead, pass
+/// clones of the same hasher to all caches sharing the sketch.
+///
+/// # Sizing and aging
+///
+/// The sketch does not grow with the caches. Give `new` the number of distinct
+/// keys the caches hold at the same time in total, which is usually the max
+/// capacity of one cache when the caches hold versions of the same keys.
+///
+/// All counts are halved after every `10 * estimated_entries` accesses, so that
+/// old popularity fades away. The accesses through all caches sharing the sketch
+/// count toward this window, so when the traffic moves from an old cache to a
+/// new one, the aging goes on at the same pace as with a single cache.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::{policy::SharedFrequencySketch, sync::Cache};
+///
+/// let sketch = SharedFrequencySketch::new(10_000);
+///
+/// let v1: Cache<u32, String> = Cache::builder()
+///     .max_capacity(10_000)
+///     .shared_frequency_sketch(sketch.clone())
+///     .build();
+///
+/// // Later, the cache for the next version starts with the popularity of the
+/// // keys read from `v1`.
+/// let v2: Cache<u32, String> = Cache::builder()
+///     .max_capacity(10_000)
+///     .shared_frequency_sketch(sketch)
+///     .build();
+/// # v1.insert(0, "zero".to_string());
+/// # v2.insert(0, "zero".to_string());
+/// ```
+///
+/// [builder-method]: ../sync/struct.CacheBuilder.html#method.shared_frequency_sketch
+#[derive(Clone)]
+pub struct SharedFrequencySketch {
+    sketch: Arc<RwLock<FrequencySketch>>,
+    build_hasher: RandomState,
+}
+
+impl SharedFrequencySketch {
+    /// Creates a sketch sized for `estimated_entries` distinct keys.
+    pub fn new(estimated_entries: u64) -> Self {
+        let mut sketch = FrequencySketch::default();
+        sketch.ensure_capacity(common::sketch_capacity(estimated_entries));
+        Self {
+            sketch: Arc::new(RwLock::new(sketch)),
+            build_hasher: RandomState::default(),
+        }
+    }
+
+    pub(crate) fn sketch(&self) -> &Arc<RwLock<FrequencySketch>> {
+        &self.sketch
+    }
+
+    pub(crate) fn build_hasher(&self) -> &RandomState {
+        &self.build_hasher
+    }
+}
+
+/// The state learned by the admission policy of a cache: the counters of its
+/// frequency sketch, and the keys in the protected region. Returned by
+/// `export_policy_state` of the caches, and given to `import_policy_state` to
+/// restart a cache with the popularity of the keys learned by its predecessor.
+///
+/// The state is opaque, and is meant to be serialized with `serde` to persist it
+/// across restarts. Importing it is best-effort; see
+/// [`Cache::import_policy_state`][import] for when it is ignored.
+///
+/// # Format compatibility
+///
+/// The serialized form holds a format version. A state exported by a version of
+/// this crate can be imported by the same or a later version with the same
+/// format version. A state in another format version is ignored on import, as
+/// if it did not match the cache.
+///
+/// [import]: ../sync/struct.Cache.html#method.import_policy_state
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PolicyState {
+    version: u32,
+    max_capacity: Option<u64>,
+    hasher_fingerprint: u64,
+    sketch_table: Vec<u64>,
+    sketch_size: u32,
+    sketch_sample_size: u32,
+    // The hashes of the keys in the protected region, from the least recently
+    // used.
+    protected: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl PolicyState {
+    /// The format version of the state. Bump it when the meaning of the fields
+    /// or the layout of the sketch changes.
+    pub(crate) const VERSION: u32 = 1;
+
+    pub(crate) fn new(
+        max_capacity: Option<u64>,
+        hasher_fingerprint: u64,
+        (sketch_table, sketch_size, sketch_sample_size): (Vec<u64>, u32, u32),
+        protected: Vec<u64>,
+    ) -> Self {
+        Self {
+            version: Self::VERSION,
+            max_capacity,
+            hasher_fingerprint,
+            sketch_table,
+            sketch_size,
+            sketch_sample_size,
+            protected,
+        }
+    }
+
+    /// Returns `true` if the state was exported by a cache with the same format
+    /// version, max capacity and hasher.
+    pub(crate) fn matches(&self, max_capacity: Option<u64>, hasher_fingerprint: u64) -> bool {
+        self.version == Self::VERSION
+            && self.max_capacity == max_capacity
+            && self.hasher_fingerprint == hasher_fingerprint
+    }
+
+    pub(crate) fn sketch_table(&self) -> &[u64] {
+        &self.sketch_table
+    }
+
+    pub(crate) fn sketch_size(&self) -> u32 {
+        self.sketch_size
+    }
+
+    pub(crate) fn sketch_sample_size(&self) -> u32 {
+        self.sketch_sample_size
+    }
+
+    pub(crate) fn protected(&self) -> &[u64] {
+        &self.protected
+    }
+}
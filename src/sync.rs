@@ -1,25 +1,53 @@
 //! Provides thread-safe, blocking cache implementations.
 
-use crate::common::{deque::DeqNode, time::Instant};
+use crate::{
+    common::{
+        deque::DeqNode,
+        time::{CheckedTimeOps, Instant},
+    },
+    notification::RemovalCause,
+};
 
 use parking_lot::Mutex;
-use std::{ptr::NonNull, sync::Arc};
+use std::{mem::ManuallyDrop, ptr::NonNull, sync::Arc, time::Duration};
 
 pub(crate) mod base_cache;
+mod batch;
 mod builder;
 mod cache;
+pub(crate) mod cache_config;
+mod coherence;
 mod deques;
 mod entry_info;
+pub(crate) mod frozen_view;
+mod group_index;
 pub(crate) mod housekeeper;
 mod invalidator;
+mod key_handle;
+mod key_lock;
+pub(crate) mod miss_claim;
+mod prefetcher;
 mod segment;
+mod snapshot_handle;
+mod swap_lock;
+mod thread_local_cache;
+mod tiered;
+pub(crate) mod value_dropper;
 mod value_initializer;
 
+pub use batch::BatchOps;
 pub use builder::CacheBuilder;
 pub use cache::Cache;
-pub use segment::SegmentedCache;
+pub use coherence::{CoherenceBus, PublishOn};
+pub use frozen_view::FrozenView;
+pub use key_handle::KeyHandle;
+pub use miss_claim::{MissClaim, MissResolution, MissToken, MissWaiter};
+pub use segment::{SegmentCleared, SegmentedCache};
+pub use snapshot_handle::SnapshotHandle;
+pub use tiered::TieredCache;
+pub use value_initializer::LoadRole;
 
-use self::entry_info::EntryInfo;
+use self::{entry_info::EntryInfo, value_dropper::ValueDropper};
 
 /// The type of the unique ID to identify a predicate used by
 /// [`Cache#invalidate_entries_if`][invalidate-if] method.
@@ -39,11 +67,138 @@ pub trait ConcurrentCacheExt<K, V> {
 
 pub(crate) type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> u32 + Send + Sync + 'static>;
 
+pub(crate) type EvictionFilter<K, V> =
+    Arc<dyn Fn(&K, &V, RemovalCause) -> bool + Send + Sync + 'static>;
+
+pub(crate) type Loader<K, V> = Arc<dyn Fn(&K) -> V + Send + Sync + 'static>;
+
+/// Returns the label of the key to emit the tracing events for, or `None` if the
+/// key is not traced.
+#[cfg(feature = "tracing")]
+pub(crate) type KeyTracer<K> = Arc<dyn Fn(&K) -> Option<String> + Send + Sync + 'static>;
+
+pub(crate) type KeyNormalizer<K> = Arc<dyn Fn(&K) -> K + Send + Sync + 'static>;
+
+/// Normalizes a key given to a method taking a borrowed form of the key. Returns
+/// `None` if no normalizer is set.
+pub(crate) fn normalize_borrowed_key<K, Q>(
+    normalizer: Option<&KeyNormalizer<K>>,
+    key: &Q,
+) -> Option<K>
+where
+    Q: BorrowedKey<K> + ?Sized,
+{
+    normalizer.map(|normalizer| normalizer(key.as_key()))
+}
+
+/// A borrowed form of the key type `K` given to the methods of the caches such
+/// as `get` and `invalidate`.
+///
+/// The caches store the keys as `Arc<K>`, which can be borrowed only as `K` and
+/// `Arc<K>`, so this trait is implemented for these two types. It gives the cache
+/// access to the key itself, e.g. to apply the
+/// [key normalizer](./struct.CacheBuilder.html#method.key_normalizer) to it.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait BorrowedKey<K>: sealed::Sealed<K> {
+    /// Returns the key.
+    fn as_key(&self) -> &K;
+}
+
+impl<K> BorrowedKey<K> for K {
+    #[inline]
+    fn as_key(&self) -> &K {
+        self
+    }
+}
+
+impl<K> BorrowedKey<K> for Arc<K> {
+    #[inline]
+    fn as_key(&self) -> &K {
+        self
+    }
+}
+
+mod sealed {
+    use std::sync::Arc;
+
+    pub trait Sealed<K> {}
+
+    impl<K> Sealed<K> for K {}
+    impl<K> Sealed<K> for Arc<K> {}
+}
+
+/// Determines what happens when an update increases the weight of an entry that
+/// has been already admitted to the cache.
+///
+/// See [`CacheBuilder::on_weight_increase`][on-weight-increase] for the details.
+///
+/// [on-weight-increase]: ./struct.CacheBuilder.html#method.on_weight_increase
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightIncreasePolicy {
+    /// Keeps the updated entry, and evicts other entries in LRU order to make room
+    /// for the increased weight. This is the default.
+    Admit,
+    /// Runs the admission policy for the updated entry again as if it were newly
+    /// inserted with the new weight. The entry is removed from the cache if it is
+    /// not admitted.
+    ReapplyAdmission,
+}
+
+/// The maximum age of the entries. See
+/// [`CacheBuilder::max_age`][max-age] for the details.
+///
+/// [max-age]: ./struct.CacheBuilder.html#method.max_age
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MaxAge {
+    pub(crate) duration: Duration,
+    pub(crate) spans_updates: bool,
+}
+
+impl MaxAge {
+    /// Returns the instant when the entry reaches the max age, or `None` if the
+    /// entry has not been applied by the housekeeper yet.
+    #[inline]
+    pub(crate) fn expiration(&self, info: &EntryInfo) -> Option<Instant> {
+        // `last_modified` is unset while an update is pending. Do not let the
+        // new value expire by the age of the old one.
+        let last_modified = info.last_modified()?;
+        let born = if self.spans_updates {
+            info.created_at().unwrap_or(last_modified)
+        } else {
+            last_modified
+        };
+        born.checked_add(self.duration)
+    }
+
+    #[inline]
+    pub(crate) fn is_expired(&self, info: &EntryInfo, now: Instant) -> bool {
+        matches!(self.expiration(info), Some(ts) if ts <= now)
+    }
+}
+
 pub(crate) trait AccessTime {
     fn last_accessed(&self) -> Option<Instant>;
     fn set_last_accessed(&self, timestamp: Instant);
     fn last_modified(&self) -> Option<Instant>;
     fn set_last_modified(&self, timestamp: Instant);
+
+    /// Returns the time-to-idle set for this entry, which overrides the one of the
+    /// cache.
+    fn time_to_idle(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns the time-to-live set for this entry, which overrides the one of the
+    /// cache.
+    fn time_to_live(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns when the entry is scheduled to be removed by `invalidate_after`.
+    fn removal_deadline(&self) -> Option<Instant> {
+        None
+    }
 }
 
 pub(crate) struct KeyHash<K> {
@@ -86,6 +241,10 @@ impl<K> KeyDate<K> {
     pub(crate) fn last_modified(&self) -> Option<Instant> {
         self.entry_info.last_modified()
     }
+
+    pub(crate) fn entry_info(&self) -> &EntryInfo {
+        &self.entry_info
+    }
 }
 
 pub(crate) struct KeyHashDate<K> {
@@ -143,6 +302,11 @@ impl<K> AccessTime for DeqNode<KeyDate<K>> {
     fn set_last_modified(&self, timestamp: Instant) {
         self.element.entry_info.set_last_modified(timestamp);
     }
+
+    #[inline]
+    fn time_to_live(&self) -> Option<Duration> {
+        self.element.entry_info.time_to_live()
+    }
 }
 
 impl<K> AccessTime for DeqNode<KeyHashDate<K>> {
@@ -165,6 +329,16 @@ impl<K> AccessTime for DeqNode<KeyHashDate<K>> {
     fn set_last_modified(&self, _timestamp: Instant) {
         unreachable!();
     }
+
+    #[inline]
+    fn time_to_idle(&self) -> Option<Duration> {
+        self.element.entry_info.time_to_idle()
+    }
+
+    #[inline]
+    fn removal_deadline(&self) -> Option<Instant> {
+        self.element.entry_info.removal_deadline()
+    }
 }
 
 // DeqNode for an access order queue.
@@ -182,46 +356,112 @@ struct DeqNodes<K> {
 unsafe impl<K> Send for DeqNodes<K> {}
 
 pub(crate) struct ValueEntry<K, V> {
-    pub(crate) value: V,
+    // Taken out in `drop` to be dropped in the configured `DropContext`.
+    value: ManuallyDrop<V>,
     info: EntryInfo,
+    // Unique within the cache, and larger than the versions of the entries
+    // created before. Zero until set by the cache.
+    version: u64,
     nodes: Mutex<DeqNodes<K>>,
+    dropper: Option<Arc<ValueDropper<V>>>,
+    #[cfg(feature = "zeroize")]
+    zeroizer: Option<fn(&mut V)>,
+}
+
+impl<K, V> Drop for ValueEntry<K, V> {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        {
+            if let Some(zeroize) = self.zeroizer {
+                zeroize(&mut self.value);
+            }
+        }
+        // SAFETY: `value` is not touched after this.
+        let value = unsafe { ManuallyDrop::take(&mut self.value) };
+        if let Some(dropper) = &self.dropper {
+            dropper.send(value);
+        }
+    }
 }
 
 impl<K, V> ValueEntry<K, V> {
     fn new(value: V, entry_info: EntryInfo) -> Self {
         Self {
-            value,
+            value: ManuallyDrop::new(value),
             info: entry_info,
+            version: 0,
             nodes: Mutex::new(DeqNodes {
                 access_order_q_node: None,
                 write_order_q_node: None,
             }),
+            dropper: None,
+            #[cfg(feature = "zeroize")]
+            zeroizer: None,
         }
     }
 
     fn new_from(value: V, entry_info: EntryInfo, other: &Self) -> Self {
-        let nodes = {
-            let other_nodes = other.nodes.lock();
-            DeqNodes {
-                access_order_q_node: other_nodes.access_order_q_node,
-                write_order_q_node: other_nodes.write_order_q_node,
-            }
-        };
         // To prevent this updated ValueEntry from being evicted by an expiration policy,
         // set the max value to the timestamps. They will be replaced with the real
         // timestamps when applying writes.
         entry_info.reset_timestamps();
         Self {
-            value,
+            value: ManuallyDrop::new(value),
             info: entry_info,
-            nodes: Mutex::new(nodes),
+            version: 0,
+            nodes: Mutex::new(other.copy_nodes()),
+            dropper: None,
+            #[cfg(feature = "zeroize")]
+            zeroizer: None,
         }
     }
 
+    fn new_transformed(value: V, other: &Self) -> Self {
+        Self {
+            value: ManuallyDrop::new(value),
+            info: other.info.clone(),
+            version: 0,
+            nodes: Mutex::new(other.copy_nodes()),
+            dropper: None,
+            #[cfg(feature = "zeroize")]
+            zeroizer: None,
+        }
+    }
+
+    fn copy_nodes(&self) -> DeqNodes<K> {
+        let nodes = self.nodes.lock();
+        DeqNodes {
+            access_order_q_node: nodes.access_order_q_node,
+            write_order_q_node: nodes.write_order_q_node,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub(crate) fn set_dropper(&mut self, dropper: Option<Arc<ValueDropper<V>>>) {
+        self.dropper = dropper;
+    }
+
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn set_zeroizer(&mut self, zeroizer: Option<fn(&mut V)>) {
+        self.zeroizer = zeroizer;
+    }
+
     pub(crate) fn entry_info(&self) -> &EntryInfo {
         &self.info
     }
 
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
     pub(crate) fn is_admitted(&self) -> bool {
         self.info.is_admitted()
     }
@@ -286,6 +526,21 @@ impl<K, V> AccessTime for Arc<ValueEntry<K, V>> {
     fn set_last_modified(&self, timestamp: Instant) {
         self.info.set_last_modified(timestamp);
     }
+
+    #[inline]
+    fn time_to_idle(&self) -> Option<Duration> {
+        self.info.time_to_idle()
+    }
+
+    #[inline]
+    fn time_to_live(&self) -> Option<Duration> {
+        self.info.time_to_live()
+    }
+
+    #[inline]
+    fn removal_deadline(&self) -> Option<Instant> {
+        self.info.removal_deadline()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -326,6 +581,16 @@ impl ValueEntryBuilder {
         info.set_policy_weight(policy_weight);
         ValueEntry::new_from(value, info, other)
     }
+
+    /// Builds a value entry that takes over the entry info and the deque nodes of
+    /// `other` as they are, i.e. without resetting the timestamps.
+    pub(crate) fn build_transformed<K, V>(
+        &self,
+        value: V,
+        other: &ValueEntry<K, V>,
+    ) -> ValueEntry<K, V> {
+        ValueEntry::new_transformed(value, other)
+    }
 }
 
 pub(crate) enum ReadOp<K, V> {
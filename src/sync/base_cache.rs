@@ -1,28 +1,51 @@
+#[cfg(feature = "tracing")]
+use super::KeyTracer;
 use super::{
+    cache_config::CacheConfig,
     deques::Deques,
+    group_index::GroupIndex,
     housekeeper::{Housekeeper, InnerSync, SyncPace},
     invalidator::{GetOrRemoveEntry, InvalidationResult, Invalidator, KeyDateLite, PredicateFun},
-    AccessTime, CacheFeatures, KeyDate, KeyHash, KeyHashDate, KvEntry, PredicateId, ReadOp,
-    ValueEntry, ValueEntryBuilder, Weigher, WriteOp,
+    value_dropper::ValueDropper,
+    AccessTime, BorrowedKey, CacheFeatures, EntryInfo, EvictionFilter, KeyDate, KeyHash,
+    KeyHashDate, KeyNormalizer, KvEntry, MaxAge, PredicateId, ReadOp, ValueEntry,
+    ValueEntryBuilder, Weigher, WeightIncreasePolicy, WriteOp,
 };
 use crate::{
     common::{
         self,
         atomic_time::AtomicInstant,
+        atomic_u64::AtomicU64,
         deque::{CacheRegion, DeqNode, Deque},
         frequency_sketch::FrequencySketch,
+        jitter::Jitter,
+        stats::{CacheStats, LoadTimer, MaintenancePass, OpKind, StatsCounter},
         time::{CheckedTimeOps, Clock, Instant},
     },
-    PredicateError,
+    notification::{self, AccessListener, EvictionListener, RemovalCause, RemovalInfo},
+    policy::SharedFrequencySketch,
+    CasError, LoadSheddingError, OccupiedError, PredicateError,
 };
+
+#[cfg(feature = "test-util")]
+use crate::common::time::Mock;
+
+#[cfg(feature = "future")]
+use crate::future::eviction_stream::{EvictionStream, EvictionStreams};
+#[cfg(feature = "serde")]
+use crate::policy::PolicyState;
+
 use crossbeam_channel::{Receiver, Sender, TrySendError};
 use crossbeam_utils::atomic::AtomicCell;
-use parking_lot::{Mutex, RwLock};
+use once_cell::sync::OnceCell;
+use parking_lot::{Mutex, MutexGuard, RwLock};
 use smallvec::SmallVec;
 use std::{
     borrow::Borrow,
-    collections::hash_map::RandomState,
-    hash::{BuildHasher, Hash, Hasher},
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut},
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe, RefUnwindSafe, UnwindSafe},
     ptr::NonNull,
     rc::Rc,
     sync::{
@@ -42,18 +65,22 @@ const WRITE_LOG_LOW_WATER_MARK: usize = WRITE_LOG_FLUSH_POINT / 2;
 // const WRITE_LOG_HIGH_WATER_MARK: usize = WRITE_LOG_FLUSH_POINT * (MAX_SYNC_REPEATS - 1);
 const WRITE_LOG_SIZE: usize = WRITE_LOG_FLUSH_POINT * (MAX_SYNC_REPEATS + 2);
 
+const HASH_MAP_NUM_SEGMENTS: usize = 64;
+
 pub(crate) const WRITE_RETRY_INTERVAL_MICROS: u64 = 50;
 
 pub(crate) const PERIODICAL_SYNC_INITIAL_DELAY_MILLIS: u64 = 500;
 pub(crate) const PERIODICAL_SYNC_NORMAL_PACE_MILLIS: u64 = 300;
 pub(crate) const PERIODICAL_SYNC_FAST_PACE_NANOS: u64 = 500;
 
+// A cache built with the `small` hint uses the plain LRU policy only when its max
+// capacity is below this number of entries.
+const SMALL_CACHE_THRESHOLD: u64 = 32;
+
 pub(crate) type HouseKeeperArc<K, V, S> = Arc<Housekeeper<Inner<K, V, S>>>;
 
 pub(crate) struct BaseCache<K, V, S = RandomState> {
     pub(crate) inner: Arc<Inner<K, V, S>>,
-    read_op_ch: Sender<ReadOp<K, V>>,
-    pub(crate) write_op_ch: Sender<WriteOp<K, V>>,
     pub(crate) housekeeper: Option<HouseKeeperArc<K, V, S>>,
 }
 
@@ -65,8 +92,6 @@ impl<K, V, S> Clone for BaseCache<K, V, S> {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
-            read_op_ch: self.read_op_ch.clone(),
-            write_op_ch: self.write_op_ch.clone(),
             housekeeper: self.housekeeper.as_ref().map(Arc::clone),
         }
     }
@@ -79,44 +104,65 @@ impl<K, V, S> Drop for BaseCache<K, V, S> {
     }
 }
 
+// The internals hold raw pointers, closures and locks, which are not unwind safe
+// by themselves. But a panic in a cache operation, e.g. from the hasher, the
+// weigher or the eviction filter, cannot leave them in a state that is unsafe to
+// use:
+//
+// - The deques are relinked only by code that does not call user code, and an
+//   entry gives up the pointer to its node before the node is unlinked.
+// - A maintenance pass applies all the write ops it has drained even if one of
+//   them panics, and stores its counters even when it unwinds (`CountersGuard`).
+// - The locks are released by their guards. The parking_lot locks do not poison.
+// - The hash table is lock-free, so an operation that stops halfway, e.g. while
+//   rehashing the keys, is no different from one preempted forever. The other
+//   threads finish its work.
+// - The housekeeper and the invalidator catch the panics of their passes instead
+//   of unwinding through the thread pool.
+//
+// At worst, the entry whose write op panicked stays untracked by the eviction
+// policy. This is also documented in the "Unwind Safety" section of `sync::Cache`.
+impl<K, V, S> UnwindSafe for BaseCache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
+impl<K, V, S> RefUnwindSafe for BaseCache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
 impl<K, V, S> BaseCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn new(
-        max_capacity: Option<u64>,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
-        weigher: Option<Weigher<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
-    ) -> Self {
-        let (r_snd, r_rcv) = crossbeam_channel::bounded(READ_LOG_SIZE);
-        let (w_snd, w_rcv) = crossbeam_channel::bounded(WRITE_LOG_SIZE);
-        let inner = Arc::new(Inner::new(
-            max_capacity,
-            initial_capacity,
-            build_hasher,
-            weigher,
-            r_rcv,
-            w_rcv,
-            time_to_live,
-            time_to_idle,
-            invalidator_enabled,
-        ));
-        if invalidator_enabled {
+    pub(crate) fn new(config: &CacheConfig<K, V, S>) -> Self {
+        let inner = Arc::new(Inner::new(config));
+        if config.invalidator_enabled {
             inner.set_invalidator(&inner);
         }
-        let housekeeper = Housekeeper::new(Arc::downgrade(&inner));
-        Self {
+        let housekeeper = Housekeeper::new(Arc::downgrade(&inner), config.maintenance_interval);
+        let base = Self {
             inner,
-            read_op_ch: r_snd,
-            write_op_ch: w_snd,
             housekeeper: Some(Arc::new(housekeeper)),
-        }
+        };
+        base.set_collection_check(config.collection_check);
+        base.set_value_dropper(config.value_dropper.as_ref().map(Arc::clone));
+        base.set_expiration_jitter(config.expiration_jitter);
+        base.set_reservation_high_water_mark(config.reservation_high_water_mark);
+        #[cfg(feature = "zeroize")]
+        base.set_value_zeroizer(config.value_zeroizer);
+        #[cfg(feature = "tracing")]
+        base.set_key_tracer(config.key_tracer.as_ref().map(Arc::clone));
+        base
     }
 
     #[inline]
@@ -128,50 +174,417 @@ where
         self.inner.hash(key)
     }
 
+    /// Returns the sender of the write op channel, creating the op channels if
+    /// this is the first write to the cache.
+    #[inline]
+    pub(crate) fn write_op_ch(&self) -> &Sender<WriteOp<K, V>> {
+        &self.inner.op_channels().write_snd
+    }
+
     pub(crate) fn get_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<V>
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        let record = |op| {
-            self.record_read_op(op).expect("Failed to record a get op");
-        };
+        self.get_key_value_with_hash(key, hash).map(|(_, v)| v)
+    }
+
+    pub(crate) fn get_key_value_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<(Arc<K>, V)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get_entry_with_hash(key, hash)
+            .map(|(k, entry)| (k, entry.value().clone()))
+    }
+
+    pub(crate) fn get_versioned_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<(V, u64)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get_entry_with_hash(key, hash)
+            .map(|(_, entry)| (entry.value().clone(), entry.version()))
+    }
+
+    /// Makes the versions of the entries of this cache continue from the ones of
+    /// `other`, so that a version is not reused by the cache rebuilt from `other`.
+    pub(crate) fn continue_versions_from(&self, other: &Self) {
+        let next = other.inner.next_version.load(Ordering::Relaxed);
+        self.inner.next_version.fetch_max(next, Ordering::Relaxed);
+    }
+
+    /// Looks up the live entry for the key, and records the read as a hit or a
+    /// miss.
+    pub(crate) fn get_entry_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<CacheEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get_entry_as_of(key, hash, self.inner.valid_after())
+    }
+
+    /// Runs `f` against the live value for the key without cloning it, and
+    /// returns its result.
+    pub(crate) fn read_with_hash<Q, R>(
+        &self,
+        key: &Q,
+        hash: u64,
+        f: impl FnOnce(&V) -> R,
+    ) -> Option<R>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let started_at = self.start_latency_timer(OpKind::Get);
+        let entry = self.get_entry_with_hash(key, hash);
+        self.record_latency(OpKind::Get, started_at);
+        // Holding the entry keeps the value alive and untouched (not even
+        // zeroized) while `f` runs, even if the entry is removed or replaced
+        // meanwhile.
+        entry.map(|(_, entry)| f(entry.value()))
+    }
+
+    /// Same as `get_entry_with_hash`, but the entry is checked against the given
+    /// `valid_after` instead of the current one.
+    pub(crate) fn get_entry_as_of<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        valid_after: Option<Instant>,
+    ) -> Option<CacheEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.lookup_entry(key, hash, valid_after, true)
+    }
+
+    /// Same as `get_with_hash`, but does not call the access listener. Used for
+    /// the reads made while holding a lock, which the listener might try to
+    /// take again. The caller should notify the hit after releasing the lock.
+    pub(crate) fn get_with_hash_unnotified<Q>(&self, key: &Q, hash: u64) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.lookup_entry(key, hash, self.inner.valid_after(), false)
+            .map(|(_, entry)| entry.value().clone())
+    }
+
+    fn lookup_entry<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        valid_after: Option<Instant>,
+        notify_access: bool,
+    ) -> Option<CacheEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        // Looking up an empty hash table segment allocates its buckets, so avoid
+        // touching the table of a cache that has never been written to.
+        if !self.inner.has_been_written() {
+            self.trace_read(key, None, "miss");
+            self.record_read(ReadOp::Miss(hash));
+            return None;
+        }
 
         match self.inner.get_key_value(key) {
             None => {
-                record(ReadOp::Miss(hash));
+                self.trace_read(key, None, "miss");
+                self.record_read(ReadOp::Miss(hash));
                 None
             }
             Some((arc_key, entry)) => {
-                let i = &self.inner;
-                let (ttl, tti, va) = (&i.time_to_live(), &i.time_to_idle(), &i.valid_after());
-                let now = i.current_time_from_expiration_clock();
+                let now = self.inner.current_time_from_expiration_clock();
 
-                if is_expired_entry_wo(ttl, va, &entry, now)
-                    || is_expired_entry_ao(tti, va, &entry, now)
-                    || self.inner.is_invalidated_entry(&arc_key, &entry)
+                if !self
+                    .inner
+                    .is_valid_entry_as_of(&arc_key, &entry, now, valid_after)
                 {
                     // Expired or invalidated entry. Record this access as a cache miss
                     // rather than a hit.
-                    record(ReadOp::Miss(hash));
+                    self.trace_read(&arc_key, Some(&entry), "stale");
+                    self.record_read(ReadOp::Miss(hash));
                     None
+                } else if valid_after != self.inner.valid_after()
+                    && !self.inner.is_valid_entry(&arc_key, &entry, now)
+                {
+                    // Valid only for a snapshot handle. Do not record the access
+                    // to the entry, so that the entry can be removed as soon as
+                    // the snapshot handles are dropped.
+                    self.trace_read(&arc_key, Some(&entry), "hit");
+                    self.record_hit();
+                    if notify_access {
+                        self.notify_access(&arc_key, entry.value());
+                    }
+                    Some((arc_key, entry))
                 } else {
                     // Valid entry.
-                    let v = entry.value.clone();
-                    record(ReadOp::Hit(hash, entry, now));
-                    Some(v)
+                    self.trace_read(&arc_key, Some(&entry), "hit");
+                    self.record_hit_on(hash, Arc::clone(&entry), now);
+                    if notify_access {
+                        self.notify_access(&arc_key, entry.value());
+                    }
+                    Some((arc_key, entry))
+                }
+            }
+        }
+    }
+
+    /// Reads the value of an entry obtained earlier by `get_entry_with_hash`,
+    /// without looking up the hash table. Returns `None` if the entry is no longer
+    /// live.
+    ///
+    /// The caller must ensure that the entry has not been replaced or removed
+    /// since then, e.g. by checking the write generation.
+    pub(crate) fn read_entry(
+        &self,
+        key: &Arc<K>,
+        hash: u64,
+        entry: Arc<ValueEntry<K, V>>,
+    ) -> Option<V> {
+        let now = self.inner.current_time_from_expiration_clock();
+        if !self.inner.is_valid_entry(key, &entry, now) {
+            self.trace_read(key, Some(&entry), "stale");
+            self.record_read(ReadOp::Miss(hash));
+            return None;
+        }
+        let v = entry.value().clone();
+        self.trace_read(key, Some(&entry), "hit");
+        self.notify_access(key, &v);
+        self.record_hit_on(hash, entry, now);
+        Some(v)
+    }
+
+    /// Records a hit on the entry. The access is not recorded for the access
+    /// order and the time to idle if the entry has been accessed within the
+    /// access record throttle.
+    #[inline]
+    fn record_hit_on(&self, hash: u64, entry: Arc<ValueEntry<K, V>>, now: Instant) {
+        if let Some(stats) = &self.inner.stats {
+            if entry.entry_info().take_is_prefetched() {
+                stats.record_prefetch_hit();
+            }
+        }
+        if self.inner.is_access_record_throttled(&entry, now) {
+            self.record_hit();
+        } else {
+            self.record_read(ReadOp::Hit(hash, entry, now));
+        }
+    }
+
+    #[inline]
+    fn record_read(&self, op: ReadOp<K, V>) {
+        if let Some(stats) = &self.inner.stats {
+            match op {
+                ReadOp::Hit(..) => stats.record_hit(),
+                ReadOp::Miss(_) => stats.record_miss(),
+            }
+        }
+        self.record_read_op(op).expect("Failed to record a get op");
+    }
+
+    /// Returns true if the hash table has an entry for the key, regardless of
+    /// whether it has expired or been invalidated. Does not record a read.
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.has_been_written() && self.inner.get_key_value(key).is_some()
+    }
+
+    /// Returns true if the key has a live entry. Unlike `get`, this does not
+    /// record a read.
+    pub(crate) fn contains_live_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.inner.has_been_written() {
+            return false;
+        }
+        let now = self.inner.current_time_from_expiration_clock();
+        matches!(self.inner.get_key_value(key), Some((k, entry)) if self.inner.is_valid_entry(&k, &entry, now))
+    }
+
+    /// Records a `prefetch` that starts a load.
+    pub(crate) fn record_prefetch(&self) {
+        if let Some(stats) = &self.inner.stats {
+            stats.record_prefetch();
+        }
+    }
+
+    /// Marks the entry of the key as loaded by `prefetch`, so that its first hit
+    /// is counted as a prefetch hit. Does nothing if the stats are not recorded.
+    pub(crate) fn mark_prefetched<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.inner.stats.is_some() {
+            if let Some((_, entry)) = self.inner.get_key_value(key) {
+                entry.entry_info().set_is_prefetched(true);
+            }
+        }
+    }
+
+    /// Records an access to the entry without reading its value, so that its
+    /// time-to-idle restarts. Returns false if the entry is absent, expired or
+    /// invalidated. Unlike `get`, this does not count as a hit nor a miss.
+    pub(crate) fn touch_with_hash<Q>(&self, key: &Q, hash: u64) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.inner.has_been_written() {
+            return false;
+        }
+        match self.inner.get_key_value(key) {
+            Some((arc_key, entry)) => {
+                let now = self.inner.current_time_from_expiration_clock();
+                if !self.inner.is_valid_entry(&arc_key, &entry, now) {
+                    return false;
                 }
+                self.record_read_op(ReadOp::Hit(hash, entry, now))
+                    .expect("Failed to record a touch op");
+                true
             }
+            None => false,
         }
     }
 
+    /// Rewrites the entry with its current value, so that both its time-to-live
+    /// and time-to-idle restart. Returns the `WriteOp` to schedule, or `None` if
+    /// the entry is absent, expired or invalidated.
+    pub(crate) fn do_rewrite_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<WriteOp<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.inner.has_been_written() {
+            return None;
+        }
+        let (arc_key, _) = self.inner.get_key_value(key)?;
+        let mut op = None;
+
+        // Unlike insert, modify() does nothing if the key has been removed in the
+        // meantime. The closure may be called more than once, and the last call
+        // decides the WriteOp.
+        self.inner
+            .cache
+            .modify(Arc::clone(&arc_key), |k, old_entry| {
+                op = None;
+                let now = self.inner.current_time_from_expiration_clock();
+                if !self.inner.is_valid_entry(k, old_entry, now) {
+                    return Arc::clone(old_entry);
+                }
+                let weight = old_entry.policy_weight();
+                // The value is unchanged, so keep the version.
+                let entry = self.new_value_entry_from(
+                    old_entry.value().clone(),
+                    weight,
+                    old_entry.version(),
+                    old_entry,
+                );
+                op = Some((
+                    Arc::clone(old_entry),
+                    WriteOp::Upsert {
+                        key_hash: KeyHash::new(Arc::clone(&arc_key), hash),
+                        value_entry: Arc::clone(&entry),
+                        old_weight: weight,
+                        new_weight: weight,
+                    },
+                ));
+                entry
+            })?;
+
+        let (old_entry, op) = op?;
+        old_entry.unset_q_nodes();
+        self.inner.bump_write_generation();
+        Some(op)
+    }
+
     #[inline]
     pub(crate) fn remove_entry<Q>(&self, key: &Q) -> Option<KvEntry<K, V>>
     where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.inner.remove_entry(key)
+        let kv = self.remove_entry_unnotified(key);
+        if let Some(kv) = &kv {
+            self.inner
+                .notify_removal(&kv.key, &kv.entry, RemovalCause::Explicit);
+        }
+        kv
+    }
+
+    /// Same as `remove_entry`, but does not notify the eviction listener, e.g.
+    /// because the entry is moved to another cache.
+    pub(crate) fn remove_entry_unnotified<Q>(&self, key: &Q) -> Option<KvEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.inner.has_been_written() {
+            return None;
+        }
+        let kv = self.inner.remove_entry(key);
+        if kv.is_some() {
+            self.inner.bump_write_generation();
+        }
+        kv
+    }
+
+    /// Schedules the removal of the entry for the key at `delay` from now, unless
+    /// an earlier removal has already been scheduled. Returns false if the key is
+    /// not in the cache.
+    pub(crate) fn invalidate_after<Q>(&self, key: &Q, delay: Duration) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.inner.has_been_written() {
+            return false;
+        }
+        let (key, entry) = match self.inner.get_key_value(key) {
+            Some(kv) => kv,
+            None => return false,
+        };
+        let now = self.inner.current_time_from_expiration_clock();
+        if !self.inner.is_valid_entry(&key, &entry, now) {
+            return false;
+        }
+        let info = entry.entry_info();
+        let deadline = now.checked_add(delay);
+        match (info.removal_deadline(), deadline) {
+            (Some(current), Some(dl)) if current <= dl => (),
+            (Some(_), None) => (),
+            (_, dl) => info.set_removal_deadline(dl),
+        }
+        self.inner
+            .has_removal_deadlines
+            .store(true, Ordering::Release);
+        true
+    }
+
+    /// Returns true if the removed entry had neither expired nor been
+    /// invalidated.
+    pub(crate) fn is_live(&self, kv: &KvEntry<K, V>) -> bool {
+        let now = self.inner.current_time_from_expiration_clock();
+        self.inner.is_valid_entry(&kv.key, &kv.entry, now)
+    }
+
+    /// Applies the pending writes in the current thread, unless a maintenance
+    /// pass is in progress. Called by a writer that has found the write op
+    /// channel full, so that it does not have to wait for the housekeeper, which
+    /// may be waiting for the writer itself, e.g. when the writer is an eviction
+    /// listener called by a housekeeper pass.
+    pub(crate) fn try_apply_writes(&self) {
+        self.inner.try_sync(MAX_SYNC_REPEATS);
     }
 
     #[inline]
@@ -188,9 +601,174 @@ where
         }
     }
 
-    pub(crate) fn invalidate_all(&self) {
+    /// Adds the current entry for the key to the group. Does nothing if the key
+    /// is not in the cache.
+    pub(crate) fn add_to_group(&self, group: String, key: &Arc<K>) {
+        self.inner.has_groups.store(true, Ordering::Release);
+        if let Some(entry) = self.inner.cache.get(key) {
+            let id = entry.entry_info().id();
+            self.inner.groups.lock().insert(group, Arc::clone(key), id);
+            // The entry may have been removed before it was added to the index.
+            if !matches!(self.inner.cache.get(key), Some(e) if e.entry_info().id() == id) {
+                self.inner.groups.lock().remove(key, id);
+            }
+        }
+    }
+
+    /// Removes the members of the group from the hash table, and returns them so
+    /// that the caller can schedule the `WriteOp::Remove`s.
+    pub(crate) fn remove_group(&self, group: &str) -> Vec<KvEntry<K, V>> {
+        if !self.inner.has_groups.load(Ordering::Acquire) {
+            return Vec::default();
+        }
+        let members = self.inner.groups.lock().take(group);
+        let removed = members
+            .into_iter()
+            .filter_map(|(key, id)| {
+                self.inner
+                    .cache
+                    .remove_entry_if(&key, |_, entry| entry.entry_info().id() == id)
+                    .map(|(key, entry)| KvEntry::new(key, entry))
+            })
+            .collect::<Vec<_>>();
+        if !removed.is_empty() {
+            self.inner.bump_write_generation();
+        }
+        for kv in &removed {
+            self.inner
+                .notify_removal(&kv.key, &kv.entry, RemovalCause::Explicit);
+        }
+        removed
+    }
+
+    pub(crate) fn invalidate_all(&self, notify: bool) {
         let now = self.inner.current_time_from_expiration_clock();
-        self.inner.set_valid_after(now);
+        if !notify {
+            self.inner.cleared_silently_before.set_instant(now);
+        }
+        self.inner.advance_valid_after(now);
+        self.inner.bump_write_generation();
+    }
+
+    /// Removes the entries for which `keep` returns `false` from the hash table,
+    /// and returns them so that the caller can schedule the `WriteOp::Remove`s.
+    ///
+    /// Only the admitted entries are visited; apply the pending writes before
+    /// calling this method to visit all entries. `keep` is called without holding
+    /// the lock on the deques.
+    pub(crate) fn remove_entries_except(
+        &self,
+        keep: impl Fn(&K, &V) -> bool,
+    ) -> Vec<KvEntry<K, V>> {
+        let keys = self.inner.admitted_keys();
+        let removed = keys
+            .iter()
+            .filter_map(|key| {
+                self.inner
+                    .cache
+                    .remove_entry_if(key, |k, entry| !keep(k, entry.value()))
+                    .map(|(key, entry)| KvEntry::new(key, entry))
+            })
+            .collect::<Vec<_>>();
+        if !removed.is_empty() {
+            self.inner.bump_write_generation();
+        }
+        for kv in &removed {
+            self.inner
+                .notify_removal(&kv.key, &kv.entry, RemovalCause::Explicit);
+        }
+        removed
+    }
+
+    /// Replaces the value of every live entry with a clone of it transformed by
+    /// `f`. The new value entry takes over the entry info and the deque nodes of
+    /// the old one, so the timestamps and the policy weight of the entry are kept
+    /// and no `WriteOp` is needed.
+    ///
+    /// The entries are swapped while holding the lock on the deques, so that the
+    /// housekeeper does not move or unlink the deque nodes while they are taken
+    /// over. An entry not admitted yet has a pending `WriteOp::Upsert`, which would
+    /// give the deque nodes to the replaced entry, so it is skipped as if it were
+    /// inserted concurrently.
+    ///
+    /// Only the admitted entries are visited; apply the pending writes before
+    /// calling this method to visit all entries. `f` is called without holding
+    /// the lock on the deques.
+    pub(crate) fn transform_values(&self, mut f: impl FnMut(&K, &mut V)) {
+        let keys = self.inner.admitted_keys();
+        for key in keys {
+            let mut current = match self.inner.cache.get(&key) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            loop {
+                let now = self.inner.current_time_from_expiration_clock();
+                if !current.is_admitted() || !self.inner.is_valid_entry(&key, &current, now) {
+                    break;
+                }
+                let mut value = current.value().clone();
+                f(&key, &mut value);
+
+                let deqs = self.inner.lock_deques();
+                let mut replaced = false;
+                let mut updated = None;
+                // moka-cht may call the closure more than once when the modification
+                // conflicts with a concurrent update. The last call decides the
+                // result.
+                self.inner.cache.modify(Arc::clone(&key), |_k, entry| {
+                    if !Arc::ptr_eq(entry, &current) {
+                        // Updated by another thread. Transform the updated value.
+                        replaced = false;
+                        updated = Some(Arc::clone(entry));
+                        return Arc::clone(entry);
+                    }
+                    updated = None;
+                    replaced = entry.is_admitted();
+                    if replaced {
+                        self.new_transformed_value_entry(value.clone(), entry)
+                    } else {
+                        Arc::clone(entry)
+                    }
+                });
+                if replaced {
+                    // The deque nodes now belong to the new entry.
+                    current.unset_q_nodes();
+                    std::mem::drop(deqs);
+                    self.inner.bump_write_generation();
+                    break;
+                }
+                std::mem::drop(deqs);
+                match updated {
+                    Some(entry) => current = entry,
+                    // Removed by another thread.
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Applies the pending reads and writes, and then evicts up to `n` admitted
+    /// entries with a non-zero weight, coldest first. Returns the number of
+    /// evicted entries.
+    pub(crate) fn evict_now(&self, n: usize) -> usize {
+        self.inner.sync(MAX_SYNC_REPEATS);
+        self.inner.evict_now(n)
+    }
+
+    /// Creates the op channels unless they exist, and then runs the maintenance
+    /// passes until there is nothing left to catch up on. Does nothing but the
+    /// former while the cache is frozen.
+    pub(crate) fn prepare(&self) {
+        let inner = &self.inner;
+        inner.op_channels();
+        loop {
+            let before = (inner.entry_count.load(), inner.weighted_size.load());
+            inner.sync(MAX_SYNC_REPEATS);
+            let after = (inner.entry_count.load(), inner.weighted_size.load());
+            if inner.is_frozen() || before == after {
+                break;
+            }
+        }
     }
 
     pub(crate) fn invalidate_entries_if(
@@ -198,13 +776,26 @@ where
         predicate: PredicateFun<K, V>,
     ) -> Result<PredicateId, PredicateError> {
         let now = self.inner.current_time_from_expiration_clock();
-        self.inner.register_invalidation_predicate(predicate, now)
+        let id = self.inner.register_invalidation_predicate(predicate, now)?;
+        self.inner.bump_write_generation();
+        Ok(id)
+    }
+
+    /// Returns the current write generation of the cache. It is advanced after
+    /// every insert, invalidation and eviction.
+    #[inline]
+    pub(crate) fn write_generation(&self) -> u64 {
+        self.inner.write_generation.load(Ordering::Acquire)
     }
 
     pub(crate) fn max_capacity(&self) -> Option<usize> {
         self.inner.max_capacity()
     }
 
+    pub(crate) fn initial_capacity(&self) -> Option<usize> {
+        self.inner.initial_capacity
+    }
+
     pub(crate) fn time_to_live(&self) -> Option<Duration> {
         self.inner.time_to_live()
     }
@@ -214,6 +805,485 @@ where
     }
 
     #[cfg(test)]
+    pub(crate) fn maintenance_interval(&self) -> Option<Duration> {
+        self.housekeeper
+            .as_ref()
+            .and_then(|h| h.maintenance_interval())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_small_cache(&self) -> bool {
+        self.inner.small_cache
+    }
+
+    pub(crate) fn has_per_entry_expiration(&self) -> bool {
+        self.inner.has_per_entry_tti.load(Ordering::Acquire) || self.inner.has_removal_deadlines()
+    }
+
+    pub(crate) fn max_age(&self) -> Option<MaxAge> {
+        self.inner.max_age
+    }
+
+    /// Inserts the entries by applying them directly to the internal data
+    /// structures, without going through the write op channel or touching the
+    /// frequency sketch.
+    ///
+    /// This must be called only on a newly created cache that has not been shared
+    /// yet; otherwise, the writes already in the channel could be applied after
+    /// the entries inserted by this method.
+    pub(crate) fn insert_all_cold(&self, entries: impl IntoIterator<Item = (K, V)>) {
+        let inner = &self.inner;
+        let mut deqs = inner.lock_deques();
+        let mut counters =
+            EvictionCounters::new(inner.entry_count.load(), inner.weighted_size.load());
+        let ts = inner.current_time_from_expiration_clock();
+
+        {
+            let freq = inner.frequency_sketch.read();
+            for (key, value) in entries {
+                let hash = self.hash(&key);
+                if let WriteOp::Upsert {
+                    key_hash,
+                    value_entry,
+                    old_weight,
+                    new_weight,
+                } = self.do_insert_with_hash(Arc::new(key), hash, value)
+                {
+                    inner.handle_upsert(
+                        key_hash,
+                        value_entry,
+                        old_weight,
+                        new_weight,
+                        ts,
+                        &mut deqs,
+                        &freq,
+                        &mut counters,
+                    );
+                }
+            }
+        }
+
+        inner.enable_frequency_sketch_if_needed(&counters);
+        inner.entry_count.store(counters.entry_count);
+        inner.weighted_size.store(counters.weighted_size);
+        std::mem::drop(deqs);
+        inner.deliver_deferred_removals();
+    }
+
+    /// Sets the function to zeroize a value when the `ValueEntry` holding it is
+    /// dropped. It only applies to the entries created after calling this method.
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn set_value_zeroizer(&self, zeroizer: Option<fn(&mut V)>) {
+        self.inner.value_zeroizer.store(zeroizer);
+    }
+
+    /// Sets the dropper of the values. It can be set only once, and only applies to
+    /// the entries created after calling this method.
+    pub(crate) fn set_value_dropper(&self, dropper: Option<Arc<ValueDropper<V>>>) {
+        if let Some(dropper) = dropper {
+            let _ = self.inner.value_dropper.set(dropper);
+        }
+    }
+
+    /// Sets the fraction to randomize the time-to-live of the inserted keys by.
+    /// Has no effect on a cache without a time-to-live.
+    pub(crate) fn set_expiration_jitter(&self, fraction: Option<f64>) {
+        if let (Some(fraction), Some(_)) = (fraction, self.inner.time_to_live) {
+            let _ = self.inner.expiration_jitter.set(Jitter::new(fraction));
+        }
+    }
+
+    pub(crate) fn set_reservation_high_water_mark(&self, high_water_mark: Option<u64>) {
+        if let Some(mark) = high_water_mark {
+            let _ = self.inner.reservation_high_water_mark.set(mark);
+        }
+    }
+
+    pub(crate) fn reservation_high_water_mark(&self) -> Option<u64> {
+        self.inner.reservation_high_water_mark.get().copied()
+    }
+
+    pub(crate) fn reserved_weight(&self) -> u64 {
+        self.inner.reserved_weight.load(Ordering::Acquire)
+    }
+
+    /// Reserves the weight for a load, unless the weighted size plus the weight
+    /// already reserved and this weight would exceed the reservation high-water
+    /// mark. The weight is released when the returned reservation is dropped.
+    pub(crate) fn try_reserve_weight(
+        &self,
+        weight: u32,
+    ) -> Result<WeightReservation<'_>, LoadSheddingError> {
+        let reserved_weight = &self.inner.reserved_weight;
+        let weight = u64::from(weight);
+        let mut reserved = reserved_weight.load(Ordering::Acquire);
+        loop {
+            if let Some(mark) = self.reservation_high_water_mark() {
+                let committed = self.inner.weighted_size.load().saturating_add(reserved);
+                if committed.saturating_add(weight) > mark {
+                    return Err(LoadSheddingError {
+                        committed_weight: committed,
+                        requested_weight: weight,
+                    });
+                }
+            }
+            match reserved_weight.compare_exchange_weak(
+                reserved,
+                reserved + weight,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    return Ok(WeightReservation {
+                        reserved_weight,
+                        weight,
+                    })
+                }
+                Err(current) => reserved = current,
+            }
+        }
+    }
+
+    /// Sets the function to tell whether a value is a weak reference whose
+    /// referent has been dropped. Such values are treated as absent, and removed
+    /// by the maintenance with `RemovalCause::Collected`.
+    pub(crate) fn set_collection_check(&self, check: Option<fn(&V) -> bool>) {
+        self.inner.collection_check.store(check);
+    }
+
+    /// Removes the entry for the key if its value has been collected, and
+    /// notifies the eviction listener with `RemovalCause::Collected`.
+    pub(crate) fn remove_if_collected<Q>(&self, key: &Q) -> Option<KvEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let check = self.inner.collection_check.load()?;
+        if !self.inner.has_been_written() {
+            return None;
+        }
+        let (key, entry) = self
+            .inner
+            .cache
+            .remove_entry_if(key, |_, entry| check(entry.value()))?;
+        self.inner.bump_write_generation();
+        self.inner
+            .notify_removal(&key, &entry, RemovalCause::Collected);
+        Some(KvEntry::new(key, entry))
+    }
+
+    /// Sets the function to select the keys to emit the tracing events for. It
+    /// can be set only once, when the cache is built.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn set_key_tracer(&self, tracer: Option<KeyTracer<K>>) {
+        if let Some(tracer) = tracer {
+            let _ = self.inner.key_tracer.set(tracer);
+        }
+    }
+
+    #[cfg(all(test, feature = "tracing"))]
+    pub(crate) fn key_tracer(&self) -> Option<&KeyTracer<K>> {
+        self.inner.key_tracer.get()
+    }
+
+    /// Replaces the expiration clock with a mock clock, which only advances by
+    /// `advance_mock_clock`.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn use_mock_clock(&self) {
+        let (clock, mock) = Clock::mock();
+        *self.inner.mock_clock.lock() = Some(mock);
+        *self.inner.expiration_clock.write() = Some(clock);
+        self.inner
+            .has_expiration_clock
+            .store(true, Ordering::SeqCst);
+    }
+
+    /// Advances the mock clock by the duration. Returns `false` if the cache does
+    /// not use a mock clock.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn advance_mock_clock(&self, duration: Duration) -> bool {
+        match &*self.inner.mock_clock.lock() {
+            Some(mock) => {
+                mock.increment(duration);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs the maintenance until the op channels are drained, no invalidation
+    /// predicate is left to apply, and a pass neither evicts nor admits any
+    /// entry. Returns early if the cache is frozen.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn run_until_quiescent(&self) {
+        let inner = &self.inner;
+        let pending_ops = || {
+            inner
+                .op_channels
+                .get()
+                .map_or(0, |ch| ch.read_rcv.len() + ch.write_rcv.len())
+        };
+        let has_predicates = || match &*inner.invalidator.read() {
+            Some(invalidator) => !invalidator.is_empty(),
+            None => false,
+        };
+        loop {
+            let before = (inner.entry_count.load(), inner.weighted_size.load());
+            if inner.sync(MAX_SYNC_REPEATS).is_none() {
+                break;
+            }
+            let after = (inner.entry_count.load(), inner.weighted_size.load());
+            if has_predicates() {
+                // The predicates are applied by a task on the thread pool.
+                std::thread::sleep(Duration::from_millis(1));
+            } else if pending_ops() == 0 && before == after {
+                break;
+            }
+        }
+    }
+
+    /// Emits an event for a read of the key if it is traced. `entry` is `None`
+    /// when the key was not found.
+    #[allow(unused_variables)]
+    #[inline]
+    pub(crate) fn trace_read<Q>(
+        &self,
+        key: &Q,
+        entry: Option<&ValueEntry<K, V>>,
+        outcome: &'static str,
+    ) where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(label) = self.inner.traced_key_label(key) {
+                tracing::debug!(
+                    cache = self.inner.name.as_deref(),
+                    key = %label,
+                    outcome,
+                    age = ?entry.and_then(|e| self.inner.entry_age(e)),
+                    "traced key read"
+                );
+            }
+        }
+    }
+
+    pub(crate) fn weigher(&self) -> Option<&Weigher<K, V>> {
+        self.inner.weigher.as_ref()
+    }
+
+    #[inline]
+    /// Returns the number of accesses to the key with the hash, as estimated by
+    /// the frequency sketch.
+    pub(crate) fn estimated_frequency(&self, hash: u64) -> u8 {
+        self.inner.frequency_sketch.read().frequency(hash)
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn export_policy_state(&self) -> PolicyState {
+        self.inner.export_policy_state()
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn import_policy_state(&self, state: &PolicyState) -> bool {
+        self.inner.import_policy_state(state)
+    }
+
+    #[inline]
+    pub(crate) fn normalize_key(&self, key: K) -> K {
+        match &self.inner.key_normalizer {
+            Some(normalizer) => normalizer(&key),
+            None => key,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn normalize_borrowed_key<Q>(&self, key: &Q) -> Option<K>
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        super::normalize_borrowed_key(self.inner.key_normalizer.as_ref(), key)
+    }
+
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    #[cfg(feature = "future")]
+    pub(crate) fn eviction_stream(&self) -> EvictionStream<K, V> {
+        self.inner.eviction_streams.subscribe()
+    }
+
+    pub(crate) fn access_listener(&self) -> Option<&AccessListener<K, V>> {
+        self.inner.access_listener.as_ref()
+    }
+
+    /// Calls the access listener, if any, for a hit. The caller must not hold
+    /// any lock of the cache.
+    #[inline]
+    pub(crate) fn notify_access(&self, key: &K, value: &V) {
+        if let Some(listener) = &self.inner.access_listener {
+            notification::notify_access(listener, key, value);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.inner
+            .stats
+            .as_ref()
+            .map(StatsCounter::snapshot)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn stats_and_reset(&self) -> CacheStats {
+        self.inner
+            .stats
+            .as_ref()
+            .map(StatsCounter::snapshot_and_reset)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn lock_contentions(&self) -> u64 {
+        self.inner
+            .stats
+            .as_ref()
+            .map_or(0, StatsCounter::lock_contentions)
+    }
+
+    #[inline]
+    pub(crate) fn record_lock_contention(&self) {
+        if let Some(stats) = &self.inner.stats {
+            stats.record_lock_contention();
+        }
+    }
+
+    /// Records a hit that was served without looking up the shared store, e.g. by
+    /// a thread local read cache.
+    #[inline]
+    pub(crate) fn record_hit(&self) {
+        if let Some(stats) = &self.inner.stats {
+            stats.record_hit();
+        }
+    }
+
+    /// Records the start of a load by `get_or_insert_with` and its variants, and
+    /// returns a timer to record its end. `None` if the stats are not recorded.
+    #[inline]
+    pub(crate) fn start_load(&self) -> Option<LoadTimer<'_>> {
+        self.inner.stats.as_ref().map(StatsCounter::start_load)
+    }
+
+    #[inline]
+    pub(crate) fn record_load_deduplicated(&self) {
+        if let Some(stats) = &self.inner.stats {
+            stats.record_load_deduplicated();
+        }
+    }
+
+    /// Returns the start time of an operation if its latency should be recorded.
+    #[inline]
+    pub(crate) fn start_latency_timer(&self, kind: OpKind) -> Option<std::time::Instant> {
+        self.inner
+            .stats
+            .as_ref()
+            .and_then(|stats| stats.start_timer(kind))
+    }
+
+    #[inline]
+    pub(crate) fn record_latency(&self, kind: OpKind, started_at: Option<std::time::Instant>) {
+        if let (Some(stats), Some(started_at)) = (&self.inner.stats, started_at) {
+            stats.record_latency(kind, started_at);
+        }
+    }
+
+    /// Applies the pending writes, and then pauses the maintenance until
+    /// `unfreeze` is called with the returned ID, or `max_duration` has passed.
+    /// Also returns the admitted entries at that point, which are read under the
+    /// deques lock, so they will not be changed by the writes made while the
+    /// maintenance is paused.
+    pub(crate) fn freeze(&self, max_duration: Duration) -> (u64, Vec<CacheEntry<K, V>>) {
+        let inner = &self.inner;
+        inner.sync(MAX_SYNC_REPEATS);
+        let id = inner.next_freeze_id.fetch_add(1, Ordering::Relaxed);
+        let now = std::time::Instant::now();
+        let deadline = now
+            .checked_add(max_duration)
+            .unwrap_or_else(|| now + MAX_FREEZE_DURATION_LIMIT);
+        {
+            let mut freezes = inner.freezes.lock();
+            freezes.push((id, deadline));
+            inner.has_freezes.store(true, Ordering::Release);
+        }
+        // This waits for the maintenance that may be in progress to finish.
+        let mut entries = Vec::with_capacity(inner.entry_count.load() as usize);
+        inner.visit_snapshot_chunk(&mut SnapshotCursor::default(), usize::MAX, |key, entry| {
+            entries.push((Arc::clone(key), entry));
+            true
+        });
+        (id, entries)
+    }
+
+    pub(crate) fn unfreeze(&self, id: u64) {
+        let inner = &self.inner;
+        let mut freezes = inner.freezes.lock();
+        freezes.retain(|(i, _)| *i != id);
+        inner
+            .has_freezes
+            .store(!freezes.is_empty(), Ordering::Release);
+    }
+
+    /// Pins the current `valid_after` for a snapshot handle, and returns the ID of
+    /// the pin and the pinned value.
+    pub(crate) fn pin_snapshot(&self) -> (u64, Option<Instant>) {
+        let inner = &self.inner;
+        let id = inner.next_snapshot_id.fetch_add(1, Ordering::Relaxed);
+        let mut pins = inner.snapshot_pins.lock();
+        let va = inner.valid_after();
+        pins.push((id, va));
+        (id, va)
+    }
+
+    pub(crate) fn unpin_snapshot(&self, id: u64) {
+        self.inner.snapshot_pins.lock().retain(|(i, _)| *i != id);
+    }
+
+    /// Returns true if the freeze with the given ID has not reached its deadline.
+    pub(crate) fn is_frozen_by(&self, id: u64) -> bool {
+        let now = std::time::Instant::now();
+        self.inner
+            .freezes
+            .lock()
+            .iter()
+            .any(|(i, deadline)| *i == id && *deadline > now)
+    }
+
+    pub(crate) fn entry_visitor(&self) -> EntryVisitor<'_, K, V, S> {
+        EntryVisitor {
+            base: self,
+            cursor: SnapshotCursor::default(),
+            is_done: false,
+        }
+    }
+
+    pub(crate) fn iter_by_expiration(&self) -> ExpirationIter<'_, K, V, S> {
+        ExpirationIter {
+            base: self,
+            cursor: None,
+            chunk: Vec::default().into_iter(),
+            is_done: false,
+        }
+    }
+
+    pub(crate) fn entries_by_expiration(&self) -> Vec<(Arc<K>, std::time::Instant)> {
+        let now = self.inner.current_time_from_expiration_clock();
+        let std_now = std::time::Instant::now();
+        self.inner
+            .entries_by_expiration(now)
+            .into_iter()
+            .map(|(k, ts)| (k, ts.to_std_instant(now, std_now)))
+            .collect()
+    }
+
     pub(crate) fn estimated_entry_count(&self) -> u64 {
         self.inner.estimated_entry_count()
     }
@@ -224,6 +1294,139 @@ where
     }
 }
 
+/// The number of nodes to visit while holding the deques lock at once.
+const EXPIRATION_ITER_CHUNK_SIZE: usize = 128;
+
+/// The weight reserved by `BaseCache::try_reserve_weight` for a load in progress.
+/// Releases the weight when dropped, including when the load panics.
+pub(crate) struct WeightReservation<'a> {
+    reserved_weight: &'a AtomicU64,
+    weight: u64,
+}
+
+impl Drop for WeightReservation<'_> {
+    fn drop(&mut self) {
+        self.reserved_weight
+            .fetch_sub(self.weight, Ordering::AcqRel);
+    }
+}
+const ENTRY_VISITOR_CHUNK_SIZE: usize = 128;
+
+/// Returns the version of the value entry written by an upsert `WriteOp`.
+fn upserted_version<K, V>(op: &WriteOp<K, V>) -> u64 {
+    match op {
+        WriteOp::Upsert { value_entry, .. } => value_entry.version(),
+        _ => unreachable!(),
+    }
+}
+
+/// Caps the duration of a freeze when the requested one overflows `Instant`.
+const MAX_FREEZE_DURATION_LIMIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The position in the access order deques of the last visited node.
+struct SnapshotCursor<K> {
+    deq_index: usize,
+    position: usize,
+    last_key: Option<Arc<K>>,
+}
+
+impl<K> Default for SnapshotCursor<K> {
+    fn default() -> Self {
+        Self {
+            deq_index: 0,
+            position: 0,
+            last_key: None,
+        }
+    }
+}
+
+/// Calls a closure with borrowed keys and values of the live entries in the
+/// access order deques, one chunk at a time. The deques lock is held only while
+/// visiting a chunk. Returned by `BaseCache::entry_visitor`.
+pub(crate) struct EntryVisitor<'a, K, V, S> {
+    base: &'a BaseCache<K, V, S>,
+    cursor: SnapshotCursor<K>,
+    is_done: bool,
+}
+
+impl<'a, K, V, S> EntryVisitor<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Visits the next chunk of nodes. Returns `false` if there is no more node
+    /// to visit.
+    pub(crate) fn visit_chunk(&mut self, f: &mut impl FnMut(&Arc<K>, &V)) -> bool {
+        if self.is_done {
+            return false;
+        }
+
+        let inner = &self.base.inner;
+        let now = inner.current_time_from_expiration_clock();
+        // Count expired and invalidated entries too, so that the number of
+        // entries checked while holding the lock is bounded.
+        let has_more =
+            inner.visit_snapshot_chunk(&mut self.cursor, ENTRY_VISITOR_CHUNK_SIZE, |key, entry| {
+                if inner.is_valid_entry(key, &entry, now) {
+                    f(key, entry.value());
+                }
+                true
+            });
+        self.is_done = !has_more;
+        has_more
+    }
+
+    pub(crate) fn visit_all(mut self, mut f: impl FnMut(&Arc<K>, &V)) {
+        while self.visit_chunk(&mut f) {}
+    }
+}
+
+/// An iterator over the entries of a cache in the order of their expiration.
+/// Returned by `BaseCache::iter_by_expiration`.
+pub(crate) struct ExpirationIter<'a, K, V, S> {
+    base: &'a BaseCache<K, V, S>,
+    cursor: Option<(Arc<K>, Instant)>,
+    chunk: std::vec::IntoIter<(Arc<K>, V, std::time::Instant)>,
+    is_done: bool,
+}
+
+impl<'a, K, V, S> Iterator for ExpirationIter<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V, std::time::Instant);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.chunk.next() {
+                return Some(item);
+            }
+            if self.is_done {
+                return None;
+            }
+
+            let inner = &self.base.inner;
+            let now = inner.current_time_from_expiration_clock();
+            let std_now = std::time::Instant::now();
+            match inner.next_expiration_chunk(&mut self.cursor, EXPIRATION_ITER_CHUNK_SIZE, now) {
+                Some(chunk) => {
+                    self.chunk = chunk
+                        .into_iter()
+                        .map(|(k, entry, ts)| {
+                            (k, entry.value().clone(), ts.to_std_instant(now, std_now))
+                        })
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                }
+                None => self.is_done = true,
+            }
+        }
+    }
+}
+
 //
 // private methods
 //
@@ -235,8 +1438,13 @@ where
 {
     #[inline]
     fn record_read_op(&self, op: ReadOp<K, V>) -> Result<(), TrySendError<ReadOp<K, V>>> {
-        self.apply_reads_if_needed();
-        let ch = &self.read_op_ch;
+        // Nothing has been written to the cache yet, so there is no entry whose
+        // access order to update. Do not create the op channels for reads.
+        let ch = match self.inner.op_channels.get() {
+            Some(channels) => &channels.read_snd,
+            None => return Ok(()),
+        };
+        self.apply_reads_if_needed(ch);
         match ch.try_send(op) {
             // Discard the ReadOp when the channel is full.
             Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
@@ -246,6 +1454,149 @@ where
 
     #[inline]
     pub(crate) fn do_insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) -> WriteOp<K, V> {
+        self.do_insert_with_hash_and_tti(key, hash, value, None)
+    }
+
+    /// Inserts the value with its own time-to-idle, which overrides the one of the
+    /// cache. Passing `None` makes the entry use the time-to-idle of the cache.
+    pub(crate) fn do_insert_with_hash_and_tti(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_idle: Option<Duration>,
+    ) -> WriteOp<K, V> {
+        if time_to_idle.is_some() {
+            self.inner.has_per_entry_tti.store(true, Ordering::Release);
+        }
+        match self.do_upsert_with_hash(key, hash, value, time_to_idle, false) {
+            Ok(op) => op,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Inserts the value only when the key is absent, or its entry has expired or
+    /// been invalidated. Otherwise, returns the existing value and the rejected
+    /// value, and leaves the entry untouched.
+    pub(crate) fn do_insert_if_absent_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<WriteOp<K, V>, OccupiedError<V>> {
+        self.do_upsert_with_hash(key, hash, value, None, true)
+            .map_err(|(existing, value)| OccupiedError {
+                existing: existing.value().clone(),
+                value,
+            })
+    }
+
+    /// Inserts the value only when the version of the live entry of the key is
+    /// `expected_version`, or when the key is absent and `expected_version` is
+    /// `None`. Returns the version of the inserted entry and the `WriteOp` to
+    /// schedule. Otherwise, returns the current value and version, if any, and
+    /// the rejected value, and leaves the entry untouched.
+    pub(crate) fn do_insert_if_version_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        expected_version: Option<u64>,
+    ) -> Result<(u64, WriteOp<K, V>), CasError<V>> {
+        let expected_version = match expected_version {
+            Some(version) => version,
+            None => {
+                return match self.do_upsert_with_hash(key, hash, value, None, true) {
+                    Ok(op) => Ok((upserted_version(&op), op)),
+                    Err((existing, value)) => Err(CasError {
+                        current: Some((existing.value().clone(), existing.version())),
+                        value,
+                    }),
+                }
+            }
+        };
+
+        if !self.inner.has_been_written() {
+            return Err(CasError {
+                current: None,
+                value,
+            });
+        }
+        let weight = self.inner.weigh(&key, &value);
+        let mut result = None;
+
+        // Unlike insert, modify() does nothing if the key is absent. The closure may
+        // be called more than once, and the last call decides the result.
+        self.inner.cache.modify(Arc::clone(&key), |k, old_entry| {
+            result = None;
+            let now = self.inner.current_time_from_expiration_clock();
+            if !self.inner.is_valid_entry(k, old_entry, now) {
+                return Arc::clone(old_entry);
+            }
+            if old_entry.version() != expected_version {
+                result = Some(Err(Arc::clone(old_entry)));
+                return Arc::clone(old_entry);
+            }
+            let old_weight = old_entry.policy_weight();
+            let version = self.inner.next_version();
+            let entry = self.new_value_entry_from(value.clone(), weight, version, old_entry);
+            entry.entry_info().set_time_to_idle(None);
+            entry.entry_info().set_removal_deadline(None);
+            entry.entry_info().set_is_prefetched(false);
+            result = Some(Ok((
+                Arc::clone(old_entry),
+                WriteOp::Upsert {
+                    key_hash: KeyHash::new(Arc::clone(&key), hash),
+                    value_entry: Arc::clone(&entry),
+                    old_weight,
+                    new_weight: weight,
+                },
+            )));
+            entry
+        });
+
+        #[cfg(feature = "tracing")]
+        {
+            let outcome = match &result {
+                Some(Ok(_)) => "replaced",
+                _ => "version mismatch",
+            };
+            self.inner.trace_write(&key, outcome);
+        }
+
+        match result {
+            Some(Ok((old_entry, op))) => {
+                self.inner.bump_write_generation();
+                old_entry.unset_q_nodes();
+                self.inner
+                    .notify_removal(&key, &old_entry, RemovalCause::Replaced);
+                Ok((upserted_version(&op), op))
+            }
+            Some(Err(current)) => Err(CasError {
+                current: Some((current.value().clone(), current.version())),
+                value,
+            }),
+            None => Err(CasError {
+                current: None,
+                value,
+            }),
+        }
+    }
+
+    /// Returns the `WriteOp` to schedule, or the existing entry and the rejected
+    /// value if `only_if_absent` is set and the key has a live entry.
+    #[allow(clippy::type_complexity)]
+    fn do_upsert_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_idle: Option<Duration>,
+        only_if_absent: bool,
+    ) -> Result<WriteOp<K, V>, (Arc<ValueEntry<K, V>>, V)> {
+        // Create the op channels before the entry becomes visible, so that
+        // `has_been_written` returns true once the entry is in the hash table.
+        self.inner.op_channels();
         let weight = self.inner.weigh(&key, &value);
         let op_cnt1 = Rc::new(AtomicU8::new(0));
         let op_cnt2 = Rc::clone(&op_cnt1);
@@ -265,6 +1616,7 @@ where
             // on_insert
             || {
                 let entry = self.new_value_entry(value.clone(), weight);
+                entry.entry_info().set_time_to_idle(time_to_idle);
                 let cnt = op_cnt1.fetch_add(1, Ordering::Relaxed);
                 op1 = Some((
                     cnt,
@@ -278,50 +1630,101 @@ where
                 entry
             },
             // on_modify
-            |_k, old_entry| {
+            |k, old_entry| {
+                if only_if_absent
+                    && self.inner.is_valid_entry(
+                        k,
+                        old_entry,
+                        self.inner.current_time_from_expiration_clock(),
+                    )
+                {
+                    // Keep the existing entry as is.
+                    let cnt = op_cnt2.fetch_add(1, Ordering::Relaxed);
+                    op2 = Some((cnt, Err(Arc::clone(old_entry))));
+                    return Arc::clone(old_entry);
+                }
                 // NOTES on `new_value_entry_from` method:
                 // 1. The internal EntryInfo will be shared between the old and new ValueEntries.
                 // 2. This method will set the last_accessed and last_modified to the max value to
                 //    prevent this new ValueEntry from being evicted by an expiration policy.
                 // 3. This method will update the policy_weight with the new weight.
                 let old_weight = old_entry.policy_weight();
-                let entry = self.new_value_entry_from(value.clone(), weight, old_entry);
+                let version = self.inner.next_version();
+                let entry = self.new_value_entry_from(value.clone(), weight, version, old_entry);
+                entry.entry_info().set_time_to_idle(time_to_idle);
+                // Replacing the value cancels the removal scheduled by
+                // `invalidate_after`.
+                entry.entry_info().set_removal_deadline(None);
+                entry.entry_info().set_is_prefetched(false);
                 let cnt = op_cnt2.fetch_add(1, Ordering::Relaxed);
                 op2 = Some((
                     cnt,
-                    Arc::clone(old_entry),
-                    WriteOp::Upsert {
-                        key_hash: KeyHash::new(Arc::clone(&key), hash),
-                        value_entry: Arc::clone(&entry),
-                        old_weight,
-                        new_weight: weight,
-                    },
+                    Ok((
+                        Arc::clone(old_entry),
+                        WriteOp::Upsert {
+                            key_hash: KeyHash::new(Arc::clone(&key), hash),
+                            value_entry: Arc::clone(&entry),
+                            old_weight,
+                            new_weight: weight,
+                        },
+                    )),
                 ));
                 entry
             },
         );
 
-        match (op1, op2) {
-            (Some((_cnt, ins_op)), None) => ins_op,
-            (None, Some((_cnt, old_entry, upd_op))) => {
-                old_entry.unset_q_nodes();
-                upd_op
-            }
-            (Some((cnt1, ins_op)), Some((cnt2, old_entry, upd_op))) => {
-                if cnt1 > cnt2 {
-                    ins_op
-                } else {
+        let last_op = match (op1, op2) {
+            (Some((_cnt, ins_op)), None) => Ok((None, ins_op)),
+            (None, Some((_cnt, upd_op))) => upd_op.map(|(old, op)| (Some(old), op)),
+            (Some((cnt1, ins_op)), Some((cnt2, upd_op))) => {
+                if cnt1 > cnt2 {
+                    Ok((None, ins_op))
+                } else {
+                    upd_op.map(|(old, op)| (Some(old), op))
+                }
+            }
+            (None, None) => unreachable!(),
+        };
+
+        #[cfg(feature = "tracing")]
+        {
+            let outcome = match &last_op {
+                Ok((None, _)) => "inserted",
+                Ok((Some(_), _)) => "replaced",
+                Err(_) => "occupied",
+            };
+            self.inner.trace_write(&key, outcome);
+        }
+
+        match last_op {
+            Ok((old_entry, op)) => {
+                self.inner.bump_write_generation();
+                if let Some(old_entry) = old_entry {
                     old_entry.unset_q_nodes();
-                    upd_op
+                    self.inner
+                        .notify_removal(&key, &old_entry, RemovalCause::Replaced);
                 }
+                Ok(op)
             }
-            (None, None) => unreachable!(),
+            Err(existing) => Err((existing, value)),
         }
     }
 
     #[inline]
     fn new_value_entry(&self, value: V, policy_weight: u32) -> Arc<ValueEntry<K, V>> {
-        Arc::new(self.inner.value_entry_builder.build(value, policy_weight))
+        let mut entry = self.inner.value_entry_builder.build(value, policy_weight);
+        entry.set_version(self.inner.next_version());
+        entry.set_dropper(self.inner.value_dropper.get().map(Arc::clone));
+        #[cfg(feature = "zeroize")]
+        entry.set_zeroizer(self.inner.value_zeroizer.load());
+        // The jitter is drawn once per key, so the updates do not move its
+        // expiration around.
+        if let (Some(jitter), Some(ttl)) =
+            (self.inner.expiration_jitter.get(), self.inner.time_to_live)
+        {
+            entry.entry_info().set_time_to_live(Some(jitter.apply(ttl)));
+        }
+        Arc::new(entry)
     }
 
     #[inline]
@@ -329,18 +1732,39 @@ where
         &self,
         value: V,
         policy_weight: u32,
+        version: u64,
         other: &ValueEntry<K, V>,
     ) -> Arc<ValueEntry<K, V>> {
-        Arc::new(
-            self.inner
-                .value_entry_builder
-                .build_from(value, policy_weight, other),
-        )
+        let mut entry = self
+            .inner
+            .value_entry_builder
+            .build_from(value, policy_weight, other);
+        entry.set_version(version);
+        entry.set_dropper(self.inner.value_dropper.get().map(Arc::clone));
+        #[cfg(feature = "zeroize")]
+        entry.set_zeroizer(self.inner.value_zeroizer.load());
+        Arc::new(entry)
+    }
+
+    fn new_transformed_value_entry(
+        &self,
+        value: V,
+        other: &ValueEntry<K, V>,
+    ) -> Arc<ValueEntry<K, V>> {
+        let mut entry = self
+            .inner
+            .value_entry_builder
+            .build_transformed(value, other);
+        entry.set_version(self.inner.next_version());
+        entry.set_dropper(self.inner.value_dropper.get().map(Arc::clone));
+        #[cfg(feature = "zeroize")]
+        entry.set_zeroizer(self.inner.value_zeroizer.load());
+        Arc::new(entry)
     }
 
     #[inline]
-    fn apply_reads_if_needed(&self) {
-        let len = self.read_op_ch.len();
+    fn apply_reads_if_needed(&self, ch: &Sender<ReadOp<K, V>>) {
+        let len = ch.len();
 
         if Self::should_apply_reads(len) {
             if let Some(h) = &self.housekeeper {
@@ -374,6 +1798,17 @@ where
         self.inner.invalidation_predicate_count()
     }
 
+    /// Returns the region of the access order queues where the entry is, or
+    /// `None` if the entry is not admitted yet.
+    pub(crate) fn region_of(&self, key: &K) -> Option<CacheRegion> {
+        let _deqs = self.inner.lock_deques();
+        self.inner
+            .cache
+            .get(key)
+            .and_then(|entry| entry.access_order_q_node())
+            .map(|node| unsafe { node.as_ref() }.region.clone())
+    }
+
     pub(crate) fn reconfigure_for_testing(&mut self) {
         // Stop the housekeeping job that may cause sync() method to return earlier.
         if let Some(housekeeper) = &self.housekeeper {
@@ -390,11 +1825,26 @@ where
     pub(crate) fn set_expiration_clock(&self, clock: Option<Clock>) {
         self.inner.set_expiration_clock(clock);
     }
+
+    pub(crate) fn has_op_channels(&self) -> bool {
+        self.inner.op_channels.get().is_some()
+    }
+
+    /// Returns the total number of buckets allocated by the hash map.
+    pub(crate) fn bucket_capacity(&self) -> usize {
+        let map = &self.inner.cache;
+        (0..map.num_segments())
+            .map(|i| map.segment_capacity(i))
+            .sum()
+    }
 }
 
 struct EvictionCounters {
     entry_count: u64,
     weighted_size: u64,
+    // The numbers of entries expired and evicted in the current maintenance pass.
+    expired: u64,
+    evicted: u64,
 }
 
 impl EvictionCounters {
@@ -403,6 +1853,17 @@ impl EvictionCounters {
         Self {
             entry_count,
             weighted_size,
+            expired: 0,
+            evicted: 0,
+        }
+    }
+
+    #[inline]
+    fn record_removal(&mut self, cause: RemovalCause) {
+        match cause {
+            RemovalCause::Expired => self.expired += 1,
+            RemovalCause::Size | RemovalCause::NotAdmitted => self.evicted += 1,
+            _ => (),
         }
     }
 
@@ -421,6 +1882,39 @@ impl EvictionCounters {
     }
 }
 
+/// Stores the counters of a maintenance pass to the cache if the pass unwinds,
+/// e.g. from a panic in the hasher of the keys, so that the entry count and the
+/// weighted size stay in step with the deques. A normal pass stores them itself
+/// before releasing the deques lock.
+struct CountersGuard<'a> {
+    stored_entry_count: &'a AtomicCell<u64>,
+    stored_weighted_size: &'a AtomicCell<u64>,
+    counters: EvictionCounters,
+}
+
+impl Deref for CountersGuard<'_> {
+    type Target = EvictionCounters;
+
+    fn deref(&self) -> &Self::Target {
+        &self.counters
+    }
+}
+
+impl DerefMut for CountersGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.counters
+    }
+}
+
+impl Drop for CountersGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.stored_entry_count.store(self.counters.entry_count);
+            self.stored_weighted_size.store(self.counters.weighted_size);
+        }
+    }
+}
+
 #[derive(Default)]
 struct EntrySizeAndFrequency {
     policy_weight: u64,
@@ -454,90 +1948,464 @@ enum AdmissionResult<K> {
     },
     Rejected {
         skipped_nodes: SmallVec<[AoqNode<K>; 4]>,
+        // The potential victims, which were more popular than the candidate.
+        #[cfg(feature = "tracing")]
+        victims: EntrySizeAndFrequency,
     },
 }
 
+/// The channels to send read and write ops from the cache operations to the
+/// maintenance. They are created on the first write to the cache, as the bounded
+/// channels allocate their buffers up front.
+struct OpChannels<K, V> {
+    read_snd: Sender<ReadOp<K, V>>,
+    read_rcv: Receiver<ReadOp<K, V>>,
+    write_snd: Sender<WriteOp<K, V>>,
+    write_rcv: Receiver<WriteOp<K, V>>,
+}
+
+impl<K, V> OpChannels<K, V> {
+    fn new() -> Self {
+        let (read_snd, read_rcv) = crossbeam_channel::bounded(READ_LOG_SIZE);
+        let (write_snd, write_rcv) = crossbeam_channel::bounded(WRITE_LOG_SIZE);
+        Self {
+            read_snd,
+            read_rcv,
+            write_snd,
+            write_rcv,
+        }
+    }
+}
+
 type CacheStore<K, V, S> = moka_cht::SegmentedHashMap<Arc<K>, Arc<ValueEntry<K, V>>, S>;
 
-type CacheEntry<K, V> = (Arc<K>, Arc<ValueEntry<K, V>>);
+pub(crate) type CacheEntry<K, V> = (Arc<K>, Arc<ValueEntry<K, V>>);
+
+/// Tells if the value has been collected. Set in the weak-value mode.
+type CollectionCheck<V> = Option<fn(&V) -> bool>;
 
 // type BoxedValueEntryBuilder<K, V> = Box<dyn ValueEntryBuilder<K, V> + Send + Sync + 'static>;
 
 pub(crate) struct Inner<K, V, S> {
     max_capacity: Option<u64>,
+    initial_capacity: Option<usize>,
     entry_count: AtomicCell<u64>,
     weighted_size: AtomicCell<u64>,
     cache: CacheStore<K, V, S>,
     build_hasher: S,
     value_entry_builder: ValueEntryBuilder,
     deques: Mutex<Deques<K>>,
-    frequency_sketch: RwLock<FrequencySketch>,
-    read_op_ch: Receiver<ReadOp<K, V>>,
-    write_op_ch: Receiver<WriteOp<K, V>>,
+    // Shared with other caches when `shared_frequency_sketch` is set.
+    frequency_sketch: Arc<RwLock<FrequencySketch>>,
+    shared_frequency_sketch: Option<SharedFrequencySketch>,
+    op_channels: OnceCell<OpChannels<K, V>>,
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
+    max_age: Option<MaxAge>,
+    // The resolution of the last access times. `None` if they are exact.
+    expiration_tick: Option<Duration>,
+    // Consulted before evicting an entry for the capacity.
+    eviction_filter: Option<EvictionFilter<K, V>>,
+    // Set when an entry has been inserted with its own time-to-idle.
+    has_per_entry_tti: AtomicBool,
+    // Set when the removal of an entry has been scheduled by `invalidate_after`.
+    has_removal_deadlines: AtomicBool,
+    groups: Mutex<GroupIndex<K>>,
+    // Set when an entry has been inserted into a group.
+    has_groups: AtomicBool,
     valid_after: AtomicInstant,
+    // The entries modified before this are removed without notifying the eviction
+    // listener, as they have been discarded by `clear`.
+    cleared_silently_before: AtomicInstant,
     weigher: Option<Weigher<K, V>>,
+    key_normalizer: Option<KeyNormalizer<K>>,
     invalidator_enabled: bool,
     invalidator: RwLock<Option<Invalidator<K, V, S>>>,
     has_expiration_clock: AtomicBool,
     expiration_clock: RwLock<Option<Clock>>,
+    write_generation: AtomicU64,
+    // The version of the next value entry. Starts from one.
+    next_version: AtomicU64,
+    // The IDs and deadlines of the live frozen views. Maintenance is paused while
+    // any of them has not reached its deadline.
+    freezes: Mutex<Vec<(u64, std::time::Instant)>>,
+    has_freezes: AtomicBool,
+    next_freeze_id: AtomicU64,
+    // The IDs of the live snapshot handles and the `valid_after` they have pinned.
+    snapshot_pins: Mutex<Vec<(u64, Option<Instant>)>>,
+    next_snapshot_id: AtomicU64,
+    #[cfg(feature = "zeroize")]
+    value_zeroizer: AtomicCell<Option<fn(&mut V)>>,
+    // Not set for `DropContext::Inline`.
+    value_dropper: OnceCell<Arc<ValueDropper<V>>>,
+    // Randomizes the time-to-live of every inserted key.
+    expiration_jitter: OnceCell<Jitter>,
+    // The weight reserved by the loads of `get_with_reservation` in progress.
+    reserved_weight: AtomicU64,
+    // The limit of the weighted size plus the reserved weight, above which the
+    // loads are shed.
+    reservation_high_water_mark: OnceCell<u64>,
+    // Returns true if the value is a weak reference whose referent has been
+    // dropped. Set for the caches built with `weak_values`.
+    collection_check: AtomicCell<CollectionCheck<V>>,
+    #[cfg(feature = "tracing")]
+    key_tracer: OnceCell<KeyTracer<K>>,
+    #[cfg(feature = "test-util")]
+    mock_clock: Mutex<Option<Arc<Mock>>>,
+    stats: Option<StatsCounter>,
+    // True when the cache uses the plain LRU policy without the frequency sketch.
+    small_cache: bool,
+    // The number of hits that promotes an entry from the probation region to the
+    // protected region. `None` when the protected region is not used.
+    promote_after_hits: Option<u8>,
+    weight_increase_policy: WeightIncreasePolicy,
+    name: Option<String>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    // The removals made while holding the deques lock, to be delivered to the
+    // eviction listener after releasing it.
+    deferred_removals: Mutex<Vec<(Arc<K>, V, RemovalInfo)>>,
+    #[cfg(feature = "future")]
+    eviction_streams: EvictionStreams<K, V>,
+    access_listener: Option<AccessListener<K, V>>,
+    access_record_throttle: Option<Duration>,
+    // Deliver `RemovalCause::Shutdown` for the remaining entries when dropped.
+    invoke_listener_on_drop: bool,
+    // Set to `Inner::remove_all_entries`. A `Drop` impl cannot have the trait
+    // bounds required to call it directly.
+    remove_all_entries_fn: fn(&mut Self),
+}
+
+impl<K, V, S> Drop for Inner<K, V, S> {
+    fn drop(&mut self) {
+        (self.remove_all_entries_fn)(self);
+    }
 }
 
 // functions/methods used by BaseCache
 impl<K, V, S> Inner<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone,
 {
-    // Disable a Clippy warning for having more than seven arguments.
-    // https://rust-lang.github.io/rust-clippy/master/index.html#too_many_arguments
-    #[allow(clippy::too_many_arguments)]
-    fn new(
-        max_capacity: Option<u64>,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
-        weigher: Option<Weigher<K, V>>,
-        read_op_ch: Receiver<ReadOp<K, V>>,
-        write_op_ch: Receiver<WriteOp<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
-    ) -> Self {
-        let initial_capacity = initial_capacity
-            .map(|cap| cap + WRITE_LOG_SIZE * 4)
+    fn new(config: &CacheConfig<K, V, S>) -> Self {
+        let max_capacity = config.max_capacity;
+        let weigher = config.weigher.as_ref().map(Arc::clone);
+        let eviction_listener = config.eviction_listener.clone();
+        let shared_frequency_sketch = config.shared_frequency_sketch.clone();
+
+        // The capacity given to the hash map is per segment of the map, so divide
+        // the total capacity across them.
+        let seg_capacity = config
+            .initial_capacity
+            .map(|cap| common::div_ceil(cap, HASH_MAP_NUM_SEGMENTS))
             .unwrap_or_default();
-        let num_segments = 64;
         let cache = moka_cht::SegmentedHashMap::with_num_segments_capacity_and_hasher(
-            num_segments,
-            initial_capacity,
-            build_hasher.clone(),
+            HASH_MAP_NUM_SEGMENTS,
+            seg_capacity,
+            config.build_hasher.clone(),
         );
 
+        let small_cache = config.small_cache_hint
+            && weigher.is_none()
+            && matches!(max_capacity, Some(cap) if cap < SMALL_CACHE_THRESHOLD);
         let features = CacheFeatures::new(weigher.is_some());
         let value_entry_builder = ValueEntryBuilder::new(features);
 
         Self {
-            max_capacity: max_capacity.map(|n| n as u64),
+            max_capacity,
+            initial_capacity: config.initial_capacity,
             entry_count: Default::default(),
             weighted_size: Default::default(),
             cache,
-            build_hasher,
+            build_hasher: config.build_hasher.clone(),
             value_entry_builder,
             deques: Mutex::new(Default::default()),
-            frequency_sketch: RwLock::new(Default::default()),
-            read_op_ch,
-            write_op_ch,
-            time_to_live,
-            time_to_idle,
+            frequency_sketch: shared_frequency_sketch
+                .as_ref()
+                .map_or_else(Default::default, |s| Arc::clone(s.sketch())),
+            shared_frequency_sketch,
+            op_channels: OnceCell::new(),
+            time_to_live: config.time_to_live,
+            time_to_idle: config.time_to_idle,
+            max_age: config.max_age,
+            expiration_tick: config
+                .expiration_tick
+                .filter(|tick| *tick > Duration::default()),
+            eviction_filter: config.eviction_filter.as_ref().map(Arc::clone),
+            has_per_entry_tti: AtomicBool::new(false),
+            has_removal_deadlines: AtomicBool::new(false),
+            groups: Mutex::new(GroupIndex::default()),
+            has_groups: AtomicBool::new(false),
             valid_after: Default::default(),
+            cleared_silently_before: Default::default(),
             weigher,
-            invalidator_enabled,
+            key_normalizer: config.key_normalizer.as_ref().map(Arc::clone),
+            invalidator_enabled: config.invalidator_enabled,
             // When enabled, this field will be set later via the set_invalidator method.
             invalidator: RwLock::new(None),
             has_expiration_clock: AtomicBool::new(false),
             expiration_clock: RwLock::new(None),
+            write_generation: AtomicU64::new(0),
+            next_version: AtomicU64::new(1),
+            freezes: Mutex::new(Vec::default()),
+            has_freezes: AtomicBool::new(false),
+            next_freeze_id: AtomicU64::new(0),
+            snapshot_pins: Mutex::new(Vec::default()),
+            next_snapshot_id: AtomicU64::new(0),
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: AtomicCell::new(None),
+            value_dropper: OnceCell::new(),
+            expiration_jitter: OnceCell::new(),
+            reserved_weight: AtomicU64::new(0),
+            reservation_high_water_mark: OnceCell::new(),
+            collection_check: AtomicCell::new(None),
+            #[cfg(feature = "tracing")]
+            key_tracer: OnceCell::new(),
+            #[cfg(feature = "test-util")]
+            mock_clock: Mutex::new(None),
+            stats: if config.stats_enabled {
+                Some(StatsCounter::default())
+            } else {
+                None
+            },
+            small_cache,
+            // A small cache uses a single LRU queue.
+            promote_after_hits: config.promote_after_hits.filter(|_| !small_cache),
+            weight_increase_policy: config.weight_increase_policy,
+            name: config.name.clone(),
+            invoke_listener_on_drop: config.invoke_listener_on_drop && eviction_listener.is_some(),
+            eviction_listener,
+            deferred_removals: Mutex::new(Vec::default()),
+            #[cfg(feature = "future")]
+            eviction_streams: Default::default(),
+            access_listener: config.access_listener.as_ref().map(Arc::clone),
+            access_record_throttle: config.access_record_throttle,
+            remove_all_entries_fn: Self::remove_all_entries,
+        }
+    }
+
+    /// Removes all entries from the hash table so that their values are dropped.
+    ///
+    /// moka-cht frees the buckets of a dropped hash table but does not drop the
+    /// values in them. Therefore this is called when `Inner` is being dropped.
+    fn remove_all_entries(&mut self) {
+        if self.invoke_listener_on_drop {
+            self.notify_shutdown();
+        }
+        // Do not lose the removals pending in a batched listener.
+        if let Some(listener) = &self.eviction_listener {
+            notification::flush(listener);
+        }
+        let cache = &self.cache;
+        let deqs = self.deques.get_mut();
+        for deq in &[&deqs.window, &deqs.probation, &deqs.protected] {
+            let mut next = deq.peek_front();
+            while let Some(node) = next {
+                next = node.next_node();
+                cache.remove(node.element.key());
+            }
+        }
+        // Entries that have not been added to the deques yet.
+        if let Some(channels) = self.op_channels.get() {
+            while let Ok(op) = channels.write_rcv.try_recv() {
+                if let WriteOp::Upsert { key_hash, .. } = op {
+                    cache.remove(&key_hash.key);
+                }
+            }
+        }
+    }
+
+    /// Removes all entries from the hash table, delivering `RemovalCause::Shutdown`
+    /// for each of them in the write order, oldest first.
+    fn notify_shutdown(&mut self) {
+        // The pending writes are newer than anything in the write order deque.
+        // Keep only the last write of each key.
+        let mut pending = Vec::new();
+        if let Some(channels) = self.op_channels.get() {
+            while let Ok(op) = channels.write_rcv.try_recv() {
+                if let WriteOp::Upsert { key_hash, .. } = op {
+                    pending.push(key_hash.key);
+                }
+            }
+        }
+        let mut last_writes = HashMap::with_capacity(pending.len());
+        for (i, key) in pending.iter().enumerate() {
+            last_writes.insert(Arc::clone(key), i);
+        }
+
+        let deqs = self.deques.get_mut();
+        let mut keys = Vec::with_capacity(deqs.write_order.len() + last_writes.len());
+        let mut next = deqs.write_order.peek_front();
+        while let Some(node) = next {
+            next = node.next_node();
+            if !last_writes.contains_key(&node.element.key) {
+                keys.push(Arc::clone(&node.element.key));
+            }
+        }
+        keys.extend(
+            pending
+                .into_iter()
+                .enumerate()
+                .filter(|(i, key)| last_writes.get(key) == Some(i))
+                .map(|(_, key)| key),
+        );
+
+        for key in keys {
+            if let Some((key, entry)) = self.cache.remove_entry(&key) {
+                self.notify_removal(&key, &entry, RemovalCause::Shutdown);
+            }
+        }
+    }
+
+    #[inline]
+    fn notify_removal(&self, key: &Arc<K>, entry: &ValueEntry<K, V>, cause: RemovalCause) {
+        self.notify_removal_from(key, entry, cause, None);
+    }
+
+    /// Same as `notify_removal`, but also tells the eviction listener the region
+    /// of the cache the entry was evicted from.
+    fn notify_removal_from(
+        &self,
+        key: &Arc<K>,
+        entry: &ValueEntry<K, V>,
+        cause: RemovalCause,
+        region: Option<notification::CacheRegion>,
+    ) {
+        if let (Some(listener), Some(info)) = (
+            &self.eviction_listener,
+            self.prepare_removal(key, entry, cause, region),
+        ) {
+            notification::notify(listener, Arc::clone(key), entry.value().clone(), info);
+        }
+    }
+
+    /// Same as `notify_removal_from`, but must be called while holding the deques
+    /// lock. The eviction listener is not called here, as it may use the cache
+    /// and wait for the lock. Instead, the removal is queued and delivered by
+    /// `deliver_deferred_removals` after the lock has been released.
+    fn defer_removal_from(
+        &self,
+        key: &Arc<K>,
+        entry: &ValueEntry<K, V>,
+        cause: RemovalCause,
+        region: Option<notification::CacheRegion>,
+    ) {
+        if let (Some(_), Some(info)) = (
+            &self.eviction_listener,
+            self.prepare_removal(key, entry, cause, region),
+        ) {
+            self.deferred_removals
+                .lock()
+                .push((Arc::clone(key), entry.value().clone(), info));
+        }
+    }
+
+    /// Delivers the removals queued by `defer_removal_from`, and those pending in
+    /// a batched listener, to the eviction listener. Must be called without
+    /// holding the deques lock.
+    fn deliver_deferred_removals(&self) {
+        if let Some(listener) = &self.eviction_listener {
+            let removals = std::mem::take(&mut *self.deferred_removals.lock());
+            for (key, value, info) in removals {
+                notification::notify(listener, key, value, info);
+            }
+            notification::flush(listener);
+        }
+    }
+
+    /// Does the bookkeeping of a removed entry and sends it to the eviction
+    /// streams. Returns the info to pass to the eviction listener, or `None` if
+    /// the listener should not be notified.
+    fn prepare_removal(
+        &self,
+        key: &Arc<K>,
+        entry: &ValueEntry<K, V>,
+        cause: RemovalCause,
+        region: Option<notification::CacheRegion>,
+    ) -> Option<RemovalInfo> {
+        // Every entry removed from the hash table comes here. A replaced entry
+        // shares the `EntryInfo` with its successor, so it stays in the group.
+        if cause != RemovalCause::Replaced && self.has_groups.load(Ordering::Acquire) {
+            self.groups.lock().remove(key, entry.entry_info().id());
+        }
+        #[cfg(feature = "tracing")]
+        {
+            // A replacement is traced as a write.
+            if cause != RemovalCause::Replaced {
+                if let Some(label) = self.traced_key_label(key) {
+                    tracing::debug!(
+                        cache = self.name.as_deref(),
+                        key = %label,
+                        cause = cause.as_str(),
+                        age = ?self.entry_age(entry),
+                        "traced key removed"
+                    );
+                }
+            }
+        }
+        #[cfg(feature = "future")]
+        let has_streams = !self.eviction_streams.is_empty();
+        #[cfg(not(feature = "future"))]
+        let has_streams = false;
+        if self.eviction_listener.is_none() && !has_streams {
+            return None;
+        }
+        if cause == RemovalCause::Explicit && self.is_cleared_silently(entry) {
+            return None;
+        }
+        #[cfg(feature = "future")]
+        if has_streams {
+            let dropped = self.eviction_streams.push(key, entry.value(), cause);
+            if let (Some(stats), true) = (&self.stats, dropped > 0) {
+                stats.record_dropped_eviction_events(dropped);
+            }
+        }
+        Some(RemovalInfo::new(cause, region))
+    }
+
+    /// Returns the label of the key if it is traced.
+    #[cfg(feature = "tracing")]
+    #[inline]
+    fn traced_key_label<Q>(&self, key: &Q) -> Option<String>
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        let tracer = self.key_tracer.get()?;
+        tracer(key.as_key())
+    }
+
+    /// Emits the write event for the key if it is traced.
+    #[cfg(feature = "tracing")]
+    fn trace_write(&self, key: &Arc<K>, outcome: &'static str) {
+        if let Some(label) = self.traced_key_label(key) {
+            tracing::debug!(
+                cache = self.name.as_deref(),
+                key = %label,
+                outcome,
+                "traced key written"
+            );
+        }
+    }
+
+    /// Returns the time elapsed since the entry was last written.
+    #[cfg(feature = "tracing")]
+    fn entry_age(&self, entry: &ValueEntry<K, V>) -> Option<Duration> {
+        let now = self.current_time_from_expiration_clock();
+        entry
+            .entry_info()
+            .last_modified()
+            .map(|lm| now.0.saturating_duration_since(lm.0))
+    }
+
+    #[inline]
+    fn is_cleared_silently(&self, entry: &ValueEntry<K, V>) -> bool {
+        match (
+            entry.entry_info().last_modified(),
+            self.cleared_silently_before.instant(),
+        ) {
+            (Some(ts), Some(cleared_at)) => ts < cleared_at,
+            _ => false,
         }
     }
 
@@ -551,9 +2419,7 @@ where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let mut hasher = self.build_hasher.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.build_hasher.hash_one(key)
     }
 
     #[inline]
@@ -580,6 +2446,32 @@ where
         self.max_capacity.map(|n| n as usize)
     }
 
+    /// Returns true if an entry has been (or is being) inserted into the cache.
+    #[inline]
+    fn has_been_written(&self) -> bool {
+        self.op_channels.get().is_some()
+    }
+
+    /// Returns the op channels, creating them if they have not been created yet.
+    /// Concurrent callers wait for the one creating them.
+    #[inline]
+    fn op_channels(&self) -> &OpChannels<K, V> {
+        self.op_channels.get_or_init(OpChannels::new)
+    }
+
+    fn admitted_keys(&self) -> Vec<Arc<K>> {
+        let deqs = self.lock_deques();
+        let mut keys = Vec::with_capacity(self.entry_count.load() as usize);
+        for deq in &[&deqs.window, &deqs.probation, &deqs.protected] {
+            let mut next = deq.peek_front();
+            while let Some(node) = next {
+                next = node.next_node();
+                keys.push(Arc::clone(node.element.key()));
+            }
+        }
+        keys
+    }
+
     #[inline]
     fn time_to_live(&self) -> Option<Duration> {
         self.time_to_live
@@ -590,7 +2482,6 @@ where
         self.time_to_idle
     }
 
-    #[cfg(test)]
     #[inline]
     fn estimated_entry_count(&self) -> u64 {
         self.entry_count.load()
@@ -602,14 +2493,100 @@ where
         self.weighted_size.load()
     }
 
+    /// Returns true if the maintenance is paused by a frozen view.
+    fn is_frozen(&self) -> bool {
+        if !self.has_freezes.load(Ordering::Acquire) {
+            return false;
+        }
+        let now = std::time::Instant::now();
+        let mut freezes = self.freezes.lock();
+        // Drop the freezes that have passed their deadlines, e.g. leaked views.
+        freezes.retain(|(_, deadline)| *deadline > now);
+        let is_frozen = !freezes.is_empty();
+        self.has_freezes.store(is_frozen, Ordering::Release);
+        is_frozen
+    }
+
     #[inline]
     fn has_expiry(&self) -> bool {
-        self.time_to_live.is_some() || self.time_to_idle.is_some()
+        self.time_to_live.is_some()
+            || self.has_time_to_idle()
+            || self.max_age.is_some()
+            || self.has_removal_deadlines()
+    }
+
+    /// Locks the deques, counting the contention if the lock is held by another
+    /// thread.
+    fn lock_deques(&self) -> MutexGuard<'_, Deques<K>> {
+        if let Some(deqs) = self.deques.try_lock() {
+            return deqs;
+        }
+        if let Some(stats) = &self.stats {
+            stats.record_lock_contention();
+        }
+        self.deques.lock()
+    }
+
+    /// Returns true if the removal of any entry has been scheduled.
+    #[inline]
+    fn has_removal_deadlines(&self) -> bool {
+        self.has_removal_deadlines.load(Ordering::Acquire)
+    }
+
+    /// Returns true if the entry has been accessed within the access record
+    /// throttle, so that recording this access is not needed.
+    #[inline]
+    fn is_access_record_throttled(&self, entry: &Arc<ValueEntry<K, V>>, now: Instant) -> bool {
+        match (self.access_record_throttle, entry.last_accessed()) {
+            (Some(throttle), Some(ts)) => {
+                matches!(ts.checked_add(throttle), Some(until) if now < until)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the cache or any entry has a time-to-idle.
+    #[inline]
+    fn has_time_to_idle(&self) -> bool {
+        self.time_to_idle.is_some() || self.has_per_entry_tti.load(Ordering::Acquire)
     }
 
     #[inline]
     fn is_write_order_queue_enabled(&self) -> bool {
-        self.time_to_live.is_some() || self.invalidator_enabled
+        // The write order is also needed to deliver the notifications on drop.
+        self.time_to_live.is_some()
+            || self.max_age.is_some()
+            || self.invalidator_enabled
+            || self.invoke_listener_on_drop
+    }
+
+    /// Rounds up the time of a read to the expiration tick. Rounding up (rather
+    /// than down) never makes an entry look accessed before `valid_after`, and
+    /// never expires an entry early by its time-to-idle.
+    #[inline]
+    fn round_up_to_tick(&self, timestamp: Instant) -> Instant {
+        match self.expiration_tick {
+            Some(tick) => timestamp.round_up_to(tick),
+            None => timestamp,
+        }
+    }
+
+    #[inline]
+    fn is_over_max_age(&self, info: &EntryInfo, now: Instant) -> bool {
+        matches!(&self.max_age, Some(ma) if ma.is_expired(info, now))
+    }
+
+    /// Tells whether a write at `timestamp` starts a new life of the key: the key
+    /// is new, or its previous life has reached the max age spanning updates.
+    #[inline]
+    fn starts_new_life(&self, info: &EntryInfo, timestamp: Instant) -> bool {
+        match (info.created_at(), &self.max_age) {
+            (None, _) => true,
+            (Some(created_at), Some(ma)) if ma.spans_updates => {
+                matches!(created_at.checked_add(ma.duration), Some(ts) if ts <= timestamp)
+            }
+            _ => false,
+        }
     }
 
     #[inline]
@@ -617,9 +2594,40 @@ where
         self.valid_after.instant()
     }
 
-    #[inline]
-    fn set_valid_after(&self, timestamp: Instant) {
-        self.valid_after.set_instant(timestamp);
+    /// Moves `valid_after` forward to the timestamp. It never moves backward, so
+    /// that a racing `invalidate_all` that read an older time does not revive the
+    /// entries invalidated by another one.
+    ///
+    /// This is done while holding the lock on the snapshot pins, so that each
+    /// `invalidate_all` happens either before or after a snapshot handle is taken.
+    fn advance_valid_after(&self, timestamp: Instant) {
+        let _pins = self.snapshot_pins.lock();
+        match self.valid_after() {
+            Some(va) if va >= timestamp => (),
+            _ => self.valid_after.set_instant(timestamp),
+        }
+    }
+
+    /// Returns the `valid_after` to remove the invalidated entries from the hash
+    /// table with. While snapshot handles are alive, the entries invalidated by
+    /// `invalidate_all` after the oldest of them was taken are kept, so that the
+    /// handles can still read them.
+    fn valid_after_for_removal(&self) -> Option<Instant> {
+        // Read the current value while holding the lock, so that a snapshot being
+        // taken concurrently cannot pin an older value than the one returned.
+        let pins = self.snapshot_pins.lock();
+        let mut oldest = match pins.first() {
+            Some((_, va)) => *va,
+            None => return self.valid_after(),
+        };
+        for (_, va) in pins.iter().skip(1) {
+            oldest = match (oldest, va) {
+                (Some(o), Some(va)) if *va < o => Some(*va),
+                (Some(_), None) => None,
+                (o, _) => o,
+            };
+        }
+        oldest
     }
 
     #[inline]
@@ -640,6 +2648,33 @@ where
         }
     }
 
+    /// Returns true if the entry has neither expired nor been invalidated.
+    fn is_valid_entry(&self, key: &Arc<K>, entry: &Arc<ValueEntry<K, V>>, now: Instant) -> bool {
+        self.is_valid_entry_as_of(key, entry, now, self.valid_after())
+    }
+
+    /// Same as `is_valid_entry`, but the entry is checked against the given
+    /// `valid_after` instead of the current one.
+    fn is_valid_entry_as_of(
+        &self,
+        key: &Arc<K>,
+        entry: &Arc<ValueEntry<K, V>>,
+        now: Instant,
+        valid_after: Option<Instant>,
+    ) -> bool {
+        let (ttl, tti, va) = (&self.time_to_live(), &self.time_to_idle(), &valid_after);
+        !(is_expired_entry_wo(ttl, va, entry, now)
+            || is_expired_entry_ao(tti, va, entry, now)
+            || self.is_over_max_age(entry.entry_info(), now)
+            || self.is_collected_entry(entry)
+            || self.is_invalidated_entry(key, entry))
+    }
+
+    #[inline]
+    fn is_collected_entry(&self, entry: &ValueEntry<K, V>) -> bool {
+        matches!(self.collection_check.load(), Some(check) if check(entry.value()))
+    }
+
     #[inline]
     fn is_invalidated_entry(&self, key: &Arc<K>, entry: &Arc<ValueEntry<K, V>>) -> bool {
         if self.invalidator_enabled {
@@ -650,6 +2685,16 @@ where
         false
     }
 
+    #[inline]
+    fn bump_write_generation(&self) {
+        self.write_generation.fetch_add(1, Ordering::Release);
+    }
+
+    #[inline]
+    fn next_version(&self) -> u64 {
+        self.next_version.fetch_add(1, Ordering::Relaxed)
+    }
+
     #[inline]
     fn weigh(&self, key: &K, value: &V) -> u32 {
         self.weigher.as_ref().map(|w| w(key, value)).unwrap_or(1)
@@ -697,43 +2742,98 @@ where
 impl<K, V, S> InnerSync for Inner<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     fn sync(&self, max_repeats: usize) -> Option<SyncPace> {
+        if self.is_frozen() {
+            return None;
+        }
+        let deqs = self.lock_deques();
+        self.sync_locked(deqs, max_repeats)
+    }
+}
+
+//
+// private methods
+//
+impl<K, V, S> Inner<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Same as `sync`, but does nothing if the deques are locked, e.g. by a
+    /// maintenance pass in progress.
+    fn try_sync(&self, max_repeats: usize) {
+        if self.is_frozen() {
+            return;
+        }
+        if let Some(deqs) = self.deques.try_lock() {
+            self.sync_locked(deqs, max_repeats);
+        }
+    }
+
+    fn sync_locked(
+        &self,
+        mut deqs: MutexGuard<'_, Deques<K>>,
+        max_repeats: usize,
+    ) -> Option<SyncPace> {
         const EVICTION_BATCH_SIZE: usize = 500;
         const INVALIDATION_BATCH_SIZE: usize = 500;
 
-        let mut deqs = self.deques.lock();
+        // The op channels do not exist until the first write to the cache.
+        let channels = self.op_channels.get();
+        let read_len = || channels.map_or(0, |ch| ch.read_rcv.len());
+        let write_len = || channels.map_or(0, |ch| ch.write_rcv.len());
+
+        // Time the pass only if it is going to be recorded.
+        let started_at =
+            (self.stats.is_some() || cfg!(feature = "tracing")).then(std::time::Instant::now);
+        let (mut drained_reads, mut drained_writes) = (0, 0);
+
         let mut calls = 0;
         let mut should_sync = true;
+        let mut has_applied_writes = false;
 
         let current_ec = self.entry_count.load();
         let current_ws = self.weighted_size.load();
-        let mut counters = EvictionCounters::new(current_ec, current_ws);
+        let mut counters = CountersGuard {
+            stored_entry_count: &self.entry_count,
+            stored_weighted_size: &self.weighted_size,
+            counters: EvictionCounters::new(current_ec, current_ws),
+        };
 
         while should_sync && calls <= max_repeats {
-            let r_len = self.read_op_ch.len();
+            let r_len = read_len();
             if r_len > 0 {
                 self.apply_reads(&mut deqs, r_len);
             }
 
-            let w_len = self.write_op_ch.len();
+            let w_len = write_len();
             if w_len > 0 {
                 self.apply_writes(&mut deqs, w_len, &mut counters);
+                has_applied_writes = true;
             }
 
+            drained_reads += r_len;
+            drained_writes += w_len;
+
             self.enable_frequency_sketch_if_needed(&counters);
 
             calls += 1;
-            should_sync = self.read_op_ch.len() >= READ_LOG_FLUSH_POINT
-                || self.write_op_ch.len() >= WRITE_LOG_FLUSH_POINT;
+            should_sync =
+                read_len() >= READ_LOG_FLUSH_POINT || write_len() >= WRITE_LOG_FLUSH_POINT;
         }
 
         if self.has_expiry() || self.has_valid_after() {
             self.evict_expired(&mut deqs, EVICTION_BATCH_SIZE, &mut counters);
         }
 
+        if let Some(check) = self.collection_check.load() {
+            self.remove_collected(&mut deqs, EVICTION_BATCH_SIZE, check, &mut counters);
+        }
+
         if self.invalidator_enabled {
             if let Some(invalidator) = &*self.invalidator.read() {
                 if !invalidator.is_empty() && !invalidator.is_task_running() {
@@ -758,37 +2858,81 @@ where
             );
         }
 
+        // Applying writes may have rejected some entries by the admission policy,
+        // and the steps above may have evicted others.
+        if has_applied_writes
+            || counters.entry_count != current_ec
+            || counters.weighted_size != current_ws
+        {
+            self.bump_write_generation();
+        }
+
         debug_assert_eq!(self.entry_count.load(), current_ec);
         debug_assert_eq!(self.weighted_size.load(), current_ws);
         self.entry_count.store(counters.entry_count);
         self.weighted_size.store(counters.weighted_size);
 
+        let elapsed = started_at.map(|t| t.elapsed());
+        if let (Some(stats), Some(elapsed)) = (&self.stats, elapsed) {
+            stats.record_maintenance(MaintenancePass {
+                elapsed,
+                reads_drained: drained_reads as u64,
+                writes_drained: drained_writes as u64,
+                entries_expired: counters.expired,
+                entries_evicted: counters.evicted,
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            cache = self.name.as_deref(),
+            drained_reads,
+            drained_writes,
+            expired = counters.expired,
+            evicted = counters.evicted,
+            entry_count = counters.entry_count,
+            weighted_size = counters.weighted_size,
+            duration = ?elapsed,
+            "maintenance pass done"
+        );
+
+        // Deliver the removals of this pass (and those made by the users since the
+        // last pass) after releasing the lock, so that the listener can use the
+        // cache.
+        std::mem::drop(deqs);
+        self.deliver_deferred_removals();
+
         if should_sync {
             Some(SyncPace::Fast)
-        } else if self.write_op_ch.len() <= WRITE_LOG_LOW_WATER_MARK {
+        } else if write_len() <= WRITE_LOG_LOW_WATER_MARK {
             Some(SyncPace::Normal)
         } else {
             // Keep the current pace.
             None
         }
     }
-}
 
-//
-// private methods
-//
-impl<K, V, S> Inner<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
     fn has_enough_capacity(&self, candidate_weight: u32, counters: &EvictionCounters) -> bool {
         self.max_capacity
             .map(|limit| counters.weighted_size + candidate_weight as u64 <= limit)
             .unwrap_or(true)
     }
 
+    /// Returns true if an update of an admitted entry from `old_weight` to
+    /// `new_weight` should go through the admission again, which is the case when
+    /// the policy says so and the weight increase does not fit in the cache.
+    #[inline]
+    fn should_reapply_admission(
+        &self,
+        old_weight: u32,
+        new_weight: u32,
+        counters: &EvictionCounters,
+    ) -> bool {
+        self.weight_increase_policy == WeightIncreasePolicy::ReapplyAdmission
+            && new_weight > old_weight
+            && !self.has_enough_capacity(new_weight - old_weight, counters)
+    }
+
     fn weights_to_evict(&self, counters: &EvictionCounters) -> u64 {
         self.max_capacity
             .map(|limit| counters.weighted_size.saturating_sub(limit))
@@ -797,6 +2941,9 @@ where
 
     #[inline]
     fn enable_frequency_sketch_if_needed(&self, counters: &EvictionCounters) {
+        if self.small_cache {
+            return;
+        }
         if let Some(max_cap) = self.max_capacity {
             if counters.weighted_size >= max_cap / 2 {
                 self.do_enable_frequency_sketch(counters.entry_count, max_cap);
@@ -804,15 +2951,87 @@ where
         }
     }
 
-    #[cfg(test)]
-    fn enable_frequency_sketch(&self) {
-        if let Some(max_cap) = self.max_capacity {
-            self.do_enable_frequency_sketch(self.entry_count.load(), max_cap);
+    #[cfg(test)]
+    fn enable_frequency_sketch(&self) {
+        if let Some(max_cap) = self.max_capacity {
+            self.do_enable_frequency_sketch(self.entry_count.load(), max_cap);
+        }
+    }
+
+    /// Returns a value that differs between the hashers that hash the keys
+    /// differently, so that a `PolicyState` keyed by the hashes of the keys is
+    /// only imported by a cache hashing them in the same way.
+    #[cfg(feature = "serde")]
+    fn hasher_fingerprint(&self) -> u64 {
+        self.build_hasher.hash_one("moka::policy::PolicyState")
+    }
+
+    #[cfg(feature = "serde")]
+    fn export_policy_state(&self) -> PolicyState {
+        let deqs = self.lock_deques();
+        let mut protected = Vec::with_capacity(deqs.protected.len());
+        let mut next = deqs.protected.peek_front();
+        while let Some(node) = next {
+            next = node.next_node();
+            protected.push(node.element.hash);
+        }
+        let sketch = self.frequency_sketch.read().export();
+        PolicyState::new(
+            self.max_capacity,
+            self.hasher_fingerprint(),
+            sketch,
+            protected,
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    fn import_policy_state(&self, state: &PolicyState) -> bool {
+        if self.small_cache || !state.matches(self.max_capacity, self.hasher_fingerprint()) {
+            return false;
+        }
+
+        // Lock the deques first as the maintenance does.
+        let mut deqs = self.lock_deques();
+        let restored = self.frequency_sketch.write().restore(
+            state.sketch_table(),
+            state.sketch_size(),
+            state.sketch_sample_size(),
+        );
+        if !restored {
+            return false;
+        }
+
+        // Promote the entries that were protected in the exported cache, if they
+        // are present already.
+        if self.promote_after_hits.is_some() && !state.protected().is_empty() {
+            let protected = state
+                .protected()
+                .iter()
+                .collect::<std::collections::HashSet<_>>();
+            let mut keys = Vec::new();
+            let mut next = deqs.probation.peek_front();
+            while let Some(node) = next {
+                next = node.next_node();
+                if protected.contains(&node.element.hash) {
+                    keys.push(Arc::clone(&node.element.key));
+                }
+            }
+            for key in keys {
+                if let Some(entry) = self.cache.get(&key) {
+                    self.promote(&mut deqs, &entry);
+                }
+            }
         }
+        true
     }
 
     #[inline]
     fn do_enable_frequency_sketch(&self, entry_count: u64, max_capacity: u64) {
+        // A shared sketch has been sized by its creator. Resizing would forget
+        // the counts of the other caches.
+        if self.shared_frequency_sketch.is_some() {
+            return;
+        }
         let num_entries = if self.weigher.is_some() {
             entry_count * 2
         } else {
@@ -824,43 +3043,148 @@ where
 
     fn apply_reads(&self, deqs: &mut Deques<K>, count: usize) {
         use ReadOp::*;
-        let mut freq = self.frequency_sketch.write();
-        let ch = &self.read_op_ch;
+        // A small cache has no frequency sketch to update.
+        let mut freq = if self.small_cache {
+            None
+        } else {
+            Some(self.frequency_sketch.write())
+        };
+        let ch = &self.op_channels().read_rcv;
         for _ in 0..count {
             match ch.try_recv() {
                 Ok(Hit(hash, entry, timestamp)) => {
-                    freq.increment(hash);
-                    entry.set_last_accessed(timestamp);
-                    deqs.move_to_back_ao(&entry)
+                    if let Some(freq) = freq.as_mut() {
+                        freq.increment(hash);
+                    }
+                    // With an expiration tick, the reads within the same tick
+                    // store the same time, so skip storing it again.
+                    let timestamp = self.round_up_to_tick(timestamp);
+                    if entry.last_accessed() != Some(timestamp) {
+                        entry.set_last_accessed(timestamp);
+                    }
+                    match self.promote_after_hits {
+                        Some(hits)
+                            if deqs.is_in_probation_ao(&entry)
+                                && entry.entry_info().increment_hits() >= hits =>
+                        {
+                            self.promote(deqs, &entry)
+                        }
+                        _ => deqs.move_to_back_ao(&entry),
+                    }
+                }
+                Ok(Miss(hash)) => {
+                    if let Some(freq) = freq.as_mut() {
+                        freq.increment(hash);
+                    }
                 }
-                Ok(Miss(hash)) => freq.increment(hash),
                 Err(_) => break,
             }
         }
     }
 
+    /// Promotes the entry to the protected region, and demotes the least recently
+    /// used protected entries to keep the region within 80% of the entries.
+    fn promote(&self, deqs: &mut Deques<K>, entry: &Arc<ValueEntry<K, V>>) {
+        deqs.promote_ao(entry);
+        let max_protected = self.entry_count.load() * 4 / 5;
+        while deqs.protected.len() as u64 > max_protected {
+            deqs.demote_front_ao();
+        }
+    }
+
     fn apply_writes(&self, deqs: &mut Deques<K>, count: usize, counters: &mut EvictionCounters) {
         use WriteOp::*;
         let freq = self.frequency_sketch.read();
-        let ch = &self.write_op_ch;
         let ts = self.current_time_from_expiration_clock();
 
-        for _ in 0..count {
-            match ch.try_recv() {
-                Ok(Upsert {
+        // Apply all the drained ops even if one of them panics, e.g. in the hasher,
+        // so that the others are not lost. Then resume the first panic.
+        let mut panic = None;
+        for op in self.drain_and_coalesce_writes(count) {
+            let result = catch_unwind(AssertUnwindSafe(|| match op {
+                Upsert {
                     key_hash: kh,
                     value_entry: entry,
                     old_weight,
                     new_weight,
-                }) => {
+                } => {
+                    #[cfg(feature = "hyperloglog")]
+                    if let Some(stats) = &self.stats {
+                        stats.record_key(kh.hash);
+                    }
                     self.handle_upsert(kh, entry, old_weight, new_weight, ts, deqs, &freq, counters)
                 }
-                Ok(Remove(KvEntry { key: _key, entry })) => {
-                    Self::handle_remove(deqs, entry, counters)
+                Remove(KvEntry { key: _key, entry }) => Self::handle_remove(deqs, entry, counters),
+            }));
+            if let Err(payload) = result {
+                panic.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = panic {
+            resume_unwind(payload);
+        }
+    }
+
+    /// Receives up to `count` write ops and coalesces the upserts to the same
+    /// entry into the last one of them.
+    ///
+    /// A key updated many times between two syncs will have only its final value
+    /// applied: the entry is moved in the deques once, and the weight delta is
+    /// computed from the weight before the first upsert to the weight after the
+    /// last one. Upserts are never coalesced across a `Remove` op for the same
+    /// entry, so an interleaved invalidation will be applied in order.
+    ///
+    /// Upserts to the same entry are identified by their shared `EntryInfo`.
+    /// Once a key has been removed, a later insert will get a new `EntryInfo`.
+    ///
+    /// Note that the superseded values are simply dropped; the cache does not
+    /// notify anybody about values replaced by an update.
+    fn drain_and_coalesce_writes(&self, count: usize) -> impl Iterator<Item = WriteOp<K, V>> {
+        use WriteOp::*;
+
+        let mut ops = self
+            .op_channels()
+            .write_rcv
+            .try_iter()
+            .take(count)
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        // Walk the ops backward, remembering the position of the last upsert to
+        // each entry.
+        let mut last_upserts = HashMap::with_capacity(ops.len());
+        for i in (0..ops.len()).rev() {
+            match &ops[i] {
+                Some(Upsert {
+                    value_entry,
+                    old_weight,
+                    ..
+                }) => {
+                    let id = value_entry.entry_info().id();
+                    if let Some(&last) = last_upserts.get(&id) {
+                        // Superseded by the later upsert. Carry our old weight over
+                        // to it.
+                        let old_weight = *old_weight;
+                        if let Some(Upsert {
+                            old_weight: last_old_weight,
+                            ..
+                        }) = &mut ops[last]
+                        {
+                            *last_old_weight = old_weight;
+                        }
+                        ops[i] = None;
+                    } else {
+                        last_upserts.insert(id, i);
+                    }
+                }
+                Some(Remove(KvEntry { entry, .. })) => {
+                    last_upserts.remove(&entry.entry_info().id());
                 }
-                Err(_) => break,
-            };
+                None => unreachable!(),
+            }
         }
+
+        ops.into_iter().flatten()
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -875,16 +3199,36 @@ where
         freq: &FrequencySketch,
         counters: &mut EvictionCounters,
     ) {
+        let is_new_life = self.starts_new_life(entry.entry_info(), timestamp);
+        if is_new_life {
+            entry.entry_info().set_created_at(timestamp);
+        }
         entry.set_last_accessed(timestamp);
         entry.set_last_modified(timestamp);
 
         if entry.is_admitted() {
-            // The entry has been already admitted, so treat this as an update.
-            counters.saturating_sub(0, old_weight);
-            counters.saturating_add(0, new_weight);
-            deqs.move_to_back_ao(&entry);
-            deqs.move_to_back_wo(&entry);
-            return;
+            if self.should_reapply_admission(old_weight, new_weight, counters) {
+                // Take the entry out of the deques and let it go through the
+                // admission below as if it were a new entry.
+                entry.set_is_admitted(false);
+                counters.saturating_sub(1, old_weight);
+                deqs.unlink_ao(&entry);
+                Deques::unlink_wo(&mut deqs.write_order, &entry);
+                entry.unset_q_nodes();
+            } else {
+                // The entry has been already admitted, so treat this as an update.
+                counters.saturating_sub(0, old_weight);
+                counters.saturating_add(0, new_weight);
+                deqs.move_to_back_ao(&entry);
+                // Without a time-to-live, keep the write order queue sorted by the
+                // creation time, so that the max age is enforced from its front.
+                let keeps_creation_order = self.time_to_live.is_none()
+                    && matches!(&self.max_age, Some(ma) if ma.spans_updates);
+                if is_new_life || !keeps_creation_order {
+                    deqs.move_to_back_wo(&entry);
+                }
+                return;
+            }
         }
 
         if self.has_enough_capacity(new_weight, counters) {
@@ -896,28 +3240,39 @@ where
 
         if let Some(max) = self.max_capacity {
             if new_weight as u64 > max {
-                // The candidate is too big to fit in the cache. Reject it.
-                self.cache.remove(&Arc::clone(&kh.key));
+                // The candidate is too big to fit in the cache. Reject it
+                // regardless of the eviction filter.
+                self.reject_candidate(kh, &entry, new_weight, RemovalCause::Size, deqs, counters);
                 return;
             }
         }
 
         let skipped_nodes;
+        let freq = if self.small_cache { None } else { Some(freq) };
         let mut candidate = EntrySizeAndFrequency::new(new_weight);
-        candidate.add_frequency(freq, kh.hash);
+        if let Some(freq) = freq {
+            candidate.add_frequency(freq, kh.hash);
+        }
 
         // Try to admit the candidate.
-        match Self::admit(&candidate, &self.cache, deqs, freq) {
+        match Self::admit(
+            &candidate,
+            &self.cache,
+            deqs,
+            freq,
+            self.eviction_filter.as_ref(),
+        ) {
             AdmissionResult::Admitted {
                 victim_nodes,
                 skipped_nodes: mut skipped,
             } => {
                 // Try to remove the victims from the cache (hash map).
                 for victim in victim_nodes {
-                    if let Some((_vic_key, vic_entry)) = self
+                    if let Some((vic_key, vic_entry)) = self
                         .cache
                         .remove_entry(unsafe { &victim.as_ref().element.key })
                     {
+                        self.handle_eviction(RemovalCause::Size, &vic_key, &vic_entry, counters);
                         // And then remove the victim from the deques.
                         Self::handle_remove(deqs, vic_entry, counters);
                     } else {
@@ -932,10 +3287,23 @@ where
                 // Add the candidate to the deques.
                 self.handle_admit(kh, &entry, new_weight, deqs, counters);
             }
-            AdmissionResult::Rejected { skipped_nodes: s } => {
+            AdmissionResult::Rejected {
+                skipped_nodes: s,
+                #[cfg(feature = "tracing")]
+                victims,
+            } => {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(
+                    cache = self.name.as_deref(),
+                    candidate_freq = candidate.freq,
+                    candidate_weight = candidate.policy_weight,
+                    victims_freq = victims.freq,
+                    victims_weight = victims.policy_weight,
+                    "admission rejected"
+                );
                 skipped_nodes = s;
-                // Remove the candidate from the cache (hash map).
-                self.cache.remove(&Arc::clone(&kh.key));
+                let cause = RemovalCause::NotAdmitted;
+                self.reject_candidate(kh, &entry, new_weight, cause, deqs, counters);
             }
         };
 
@@ -946,6 +3314,49 @@ where
         }
     }
 
+    /// Removes the candidate rejected by the admission from the cache (hash
+    /// map) with the `cause`. If the eviction filter vetoes the removal, admits
+    /// the candidate instead, letting the cache exceed its capacity. A candidate
+    /// heavier than the max capacity cannot be vetoed, as it would never fit.
+    fn reject_candidate(
+        &self,
+        kh: KeyHash<K>,
+        entry: &Arc<ValueEntry<K, V>>,
+        new_weight: u32,
+        cause: RemovalCause,
+        deqs: &mut Deques<K>,
+        counters: &mut EvictionCounters,
+    ) {
+        let fits = !matches!(self.max_capacity, Some(max) if u64::from(new_weight) > max);
+        let filter = self.eviction_filter.as_ref().filter(|_| fits);
+        if matches!(filter, Some(f) if !f(&kh.key, entry.value(), cause)) {
+            self.handle_admit(kh, entry, new_weight, deqs, counters);
+        } else if let Some(entry) = self.cache.remove(&Arc::clone(&kh.key)) {
+            self.record_removal(cause);
+            counters.record_removal(cause);
+            self.defer_removal_from(&kh.key, &entry, cause, None);
+        }
+    }
+
+    /// Returns `false` if the eviction filter vetoes evicting the entry for the
+    /// capacity.
+    #[inline]
+    fn is_evictable(&self, key: &K, entry: &ValueEntry<K, V>) -> bool {
+        Self::is_evictable_by(self.eviction_filter.as_ref(), key, entry)
+    }
+
+    #[inline]
+    fn is_evictable_by(
+        filter: Option<&EvictionFilter<K, V>>,
+        key: &K,
+        entry: &ValueEntry<K, V>,
+    ) -> bool {
+        match filter {
+            Some(f) => f(key, entry.value(), RemovalCause::Size),
+            None => true,
+        }
+    }
+
     /// Performs size-aware admission explained in the paper:
     /// [Lightweight Robust Size Aware Cache Management][size-aware-cache-paper]
     /// by Gil Einziger, Ohad Eytan, Roy Friedman, Ben Manes.
@@ -963,12 +3374,18 @@ where
     ///   different set of victims for the next candidate). We may implement the
     ///   paper's behavior later?
     ///
+    /// When `freq` is `None` (a small cache), the frequencies are not compared at
+    /// all, so the candidate is always admitted by evicting the LRU entries.
+    ///
+    /// Entries vetoed by the eviction `filter` are skipped as if they had been
+    /// invalidated, so that a different set of victims is selected.
     #[inline]
     fn admit(
         candidate: &EntrySizeAndFrequency,
         cache: &CacheStore<K, V, S>,
         deqs: &Deques<K>,
-        freq: &FrequencySketch,
+        freq: Option<&FrequencySketch>,
+        filter: Option<&EvictionFilter<K, V>>,
     ) -> AdmissionResult<K> {
         const MAX_CONSECUTIVE_RETRIES: usize = 5;
         let mut retries = 0;
@@ -988,14 +3405,20 @@ where
             if let Some(victim) = next_victim.take() {
                 next_victim = victim.next_node();
 
-                if let Some(vic_entry) = cache.get(&victim.element.key) {
+                let vic_entry = cache
+                    .get(&victim.element.key)
+                    .filter(|e| Self::is_evictable_by(filter, &victim.element.key, e));
+                if let Some(vic_entry) = vic_entry {
                     victims.add_policy_weight(vic_entry.policy_weight());
-                    victims.add_frequency(freq, victim.element.hash);
+                    if let Some(freq) = freq {
+                        victims.add_frequency(freq, victim.element.hash);
+                    }
                     victim_nodes.push(NonNull::from(victim));
                     retries = 0;
                 } else {
-                    // Could not get the victim from the cache (hash map). Skip this node
-                    // as its ValueEntry might have been invalidated.
+                    // Could not get the victim from the cache (hash map), or the
+                    // eviction filter vetoed it. Skip this node as its ValueEntry
+                    // might have been invalidated.
                     skipped_nodes.push(NonNull::from(victim));
 
                     retries += 1;
@@ -1014,14 +3437,242 @@ where
         // TODO: Implement some randomness to mitigate hash DoS attack.
         // See Caffeine's implementation.
 
-        if victims.policy_weight >= candidate.policy_weight && candidate.freq > victims.freq {
+        if victims.policy_weight >= candidate.policy_weight
+            && (freq.is_none() || candidate.freq > victims.freq)
+        {
             AdmissionResult::Admitted {
                 victim_nodes,
                 skipped_nodes,
             }
         } else {
-            AdmissionResult::Rejected { skipped_nodes }
+            AdmissionResult::Rejected {
+                skipped_nodes,
+                #[cfg(feature = "tracing")]
+                victims,
+            }
+        }
+    }
+
+    /// Returns the admitted keys with their expiration instants, sorted by the
+    /// instants. Keys that are already expired or invalidated are excluded.
+    fn entries_by_expiration(&self, now: Instant) -> Vec<(Arc<K>, Instant)> {
+        let (ttl, tti, ma) = (self.time_to_live(), self.time_to_idle(), self.max_age);
+        if ttl.is_none()
+            && ma.is_none()
+            && !self.has_time_to_idle()
+            && !self.has_removal_deadlines()
+        {
+            return Vec::default();
+        }
+        let va = self.valid_after();
+
+        let deqs = self.lock_deques();
+        let mut entries = Vec::with_capacity(self.entry_count.load() as usize);
+        for deq in &[&deqs.window, &deqs.probation, &deqs.protected] {
+            let mut next = deq.peek_front();
+            while let Some(node) = next {
+                next = node.next_node();
+                if let Some(expiry) =
+                    live_entry_expiration(node.element.entry_info(), ttl, tti, ma, va, now)
+                {
+                    entries.push((Arc::clone(node.element.key()), expiry));
+                }
+            }
+        }
+        std::mem::drop(deqs);
+
+        entries.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        entries
+    }
+
+    /// Calls `visit` with the admitted entries after the cursor while holding the
+    /// deques lock, until it has counted `chunk_size` of them. `visit` returns
+    /// whether the entry counts toward the chunk. An entry removed from the hash
+    /// table is skipped without calling `visit`.
+    ///
+    /// Returns `false` if the end of the deques has been reached.
+    fn visit_snapshot_chunk(
+        &self,
+        cursor: &mut SnapshotCursor<K>,
+        chunk_size: usize,
+        mut visit: impl FnMut(&Arc<K>, Arc<ValueEntry<K, V>>) -> bool,
+    ) -> bool {
+        let deqs = self.lock_deques();
+        let deq_list = [&deqs.window, &deqs.probation, &deqs.protected];
+        let mut visited = 0;
+
+        while let Some(deq) = deq_list.get(cursor.deq_index) {
+            // Resume from the node of the last visited key if it is still in the
+            // deque. Otherwise, walk from the front to the last position. The
+            // caller holds the deques lock, so the node will not be unlinked while
+            // we are reading it.
+            let resumed = cursor
+                .last_key
+                .as_ref()
+                .and_then(|key| self.cache.get(key))
+                .and_then(|entry| entry.access_order_q_node())
+                .map(|node| unsafe { &*node.as_ptr() })
+                .filter(|node| deq.contains(node));
+            let mut next = match resumed {
+                Some(node) => node.next_node(),
+                None => {
+                    let mut next = deq.peek_front();
+                    for _ in 0..cursor.position {
+                        next = next.and_then(|node| node.next_node());
+                    }
+                    next
+                }
+            };
+
+            while let Some(node) = next {
+                if visited >= chunk_size {
+                    return true;
+                }
+                next = node.next_node();
+                let key = node.element.key();
+                cursor.position += 1;
+                cursor.last_key = Some(Arc::clone(key));
+                if let Some(entry) = self.cache.get(key) {
+                    if visit(key, entry) {
+                        visited += 1;
+                    }
+                }
+            }
+
+            // Move on to the next deque.
+            cursor.deq_index += 1;
+            cursor.position = 0;
+            cursor.last_key = None;
+        }
+
+        false
+    }
+
+    /// Collects the next chunk of entries for `ExpirationIter`, resuming after the
+    /// `cursor` and visiting at most `chunk_size` nodes while holding the deques
+    /// lock. Returns `None` when the end of the queue has been reached.
+    ///
+    /// The write order queue is walked if `time_to_live` or `max_age` is set,
+    /// otherwise the access order queue is walked if `time_to_idle` is set.
+    #[allow(clippy::type_complexity)]
+    fn next_expiration_chunk(
+        &self,
+        cursor: &mut Option<(Arc<K>, Instant)>,
+        chunk_size: usize,
+        now: Instant,
+    ) -> Option<Vec<(Arc<K>, Arc<ValueEntry<K, V>>, Instant)>> {
+        if self.time_to_live().is_none() && self.promote_after_hits.is_some() {
+            // The access order is split between the probation and protected
+            // deques, so return a sorted snapshot of all entries as a single chunk.
+            if cursor.is_some() {
+                return None;
+            }
+            let entries = self
+                .entries_by_expiration(now)
+                .into_iter()
+                .filter_map(|(key, expiry)| {
+                    let entry = self.cache.get(&key)?;
+                    Some((key, entry, expiry))
+                })
+                .collect::<Vec<_>>();
+            *cursor = entries.last().map(|(key, _, ts)| (Arc::clone(key), *ts));
+            return if entries.is_empty() {
+                None
+            } else {
+                Some(entries)
+            };
+        }
+
+        let deqs = self.lock_deques();
+        if self.time_to_live().is_some() || self.max_age.is_some() {
+            self.collect_expiration_chunk(
+                &deqs.write_order,
+                cursor,
+                chunk_size,
+                now,
+                |entry| entry.write_order_q_node(),
+                |kd| (kd.key(), kd.last_modified()),
+            )
+        } else if self.has_time_to_idle() {
+            self.collect_expiration_chunk(
+                &deqs.probation,
+                cursor,
+                chunk_size,
+                now,
+                |entry| entry.access_order_q_node(),
+                |khd| (khd.key(), khd.entry_info().last_accessed()),
+            )
+        } else {
+            None
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn collect_expiration_chunk<T>(
+        &self,
+        deq: &Deque<T>,
+        cursor: &mut Option<(Arc<K>, Instant)>,
+        chunk_size: usize,
+        now: Instant,
+        node_of: impl Fn(&ValueEntry<K, V>) -> Option<NonNull<DeqNode<T>>>,
+        key_and_ts: impl Fn(&T) -> (&Arc<K>, Option<Instant>),
+    ) -> Option<Vec<(Arc<K>, Arc<ValueEntry<K, V>>, Instant)>> {
+        let mut next = match cursor {
+            None => deq.peek_front(),
+            Some((key, ts)) => {
+                // Resume from the node of the last visited key if it has not been
+                // moved since. The caller holds the deques lock, so the node will
+                // not be unlinked while we are reading it.
+                let resumed = self
+                    .cache
+                    .get(key)
+                    .and_then(|entry| node_of(&entry))
+                    .map(|node| unsafe { &*node.as_ptr() })
+                    .filter(|node| deq.contains(node) && key_and_ts(&node.element).1 == Some(*ts));
+                match resumed {
+                    Some(node) => node.next_node(),
+                    // The last visited key has been removed or updated. Skip the
+                    // nodes that are not newer than it.
+                    None => {
+                        let mut next = deq.peek_front();
+                        while let Some(node) = next {
+                            if matches!(key_and_ts(&node.element).1, Some(t) if t > *ts) {
+                                break;
+                            }
+                            next = node.next_node();
+                        }
+                        next
+                    }
+                }
+            }
+        };
+
+        next?;
+
+        let (ttl, tti, ma) = (self.time_to_live(), self.time_to_idle(), self.max_age);
+        let va = self.valid_after();
+        let mut entries = Vec::with_capacity(chunk_size);
+        let mut visited = 0;
+        while let Some(node) = next {
+            if visited >= chunk_size {
+                break;
+            }
+            visited += 1;
+            next = node.next_node();
+
+            let (key, ts) = key_and_ts(&node.element);
+            if let Some(ts) = ts {
+                *cursor = Some((Arc::clone(key), ts));
+            }
+            if let Some(entry) = self.cache.get(key) {
+                if let Some(expiry) =
+                    live_entry_expiration(entry.entry_info(), ttl, tti, ma, va, now)
+                {
+                    entries.push((Arc::clone(key), entry, expiry));
+                }
+            }
         }
+        Some(entries)
     }
 
     fn handle_admit(
@@ -1034,6 +3685,7 @@ where
     ) {
         let key = Arc::clone(&kh.key);
         counters.saturating_add(1, policy_weight);
+        entry.entry_info().reset_hits();
         deqs.push_back_ao(
             CacheRegion::MainProbation,
             KeyHashDate::new(kh, entry.entry_info()),
@@ -1045,6 +3697,64 @@ where
         entry.set_is_admitted(true);
     }
 
+    /// Counts an entry removed by the cache itself (not by `invalidate`) in the
+    /// statistics, queues the notification for the eviction listener, and emits an
+    /// event for it when tracing is enabled.
+    #[allow(unused_variables)]
+    fn handle_eviction(
+        &self,
+        cause: RemovalCause,
+        key: &Arc<K>,
+        entry: &Arc<ValueEntry<K, V>>,
+        counters: &mut EvictionCounters,
+    ) {
+        self.record_removal(cause);
+        counters.record_removal(cause);
+        let region = if cause == RemovalCause::Size {
+            Self::evicted_region(entry)
+        } else {
+            None
+        };
+        self.defer_removal_from(key, entry, cause, region);
+
+        #[cfg(feature = "tracing")]
+        {
+            tracing::trace!(
+                cache = self.name.as_deref(),
+                cause = cause.as_str(),
+                age = ?self.entry_age(entry),
+                weight = entry.policy_weight(),
+                "entry evicted"
+            );
+        }
+    }
+
+    /// Returns the region of the access order queues where the admitted entry
+    /// is. Must be called while holding the lock of the deques, before unlinking
+    /// the entry from them.
+    fn evicted_region(entry: &ValueEntry<K, V>) -> Option<notification::CacheRegion> {
+        if !entry.is_admitted() {
+            return None;
+        }
+        let node = entry.access_order_q_node()?;
+        match unsafe { &node.as_ref().region } {
+            CacheRegion::MainProbation => Some(notification::CacheRegion::MainProbation),
+            CacheRegion::MainProtected => Some(notification::CacheRegion::MainProtected),
+            CacheRegion::Window | CacheRegion::WriteOrder => None,
+        }
+    }
+
+    #[inline]
+    fn record_removal(&self, cause: RemovalCause) {
+        if let Some(stats) = &self.stats {
+            match cause {
+                RemovalCause::Expired => stats.record_expiration(),
+                RemovalCause::Size | RemovalCause::NotAdmitted => stats.record_eviction(),
+                _ => (),
+            }
+        }
+    }
+
     fn handle_remove(
         deqs: &mut Deques<K>,
         entry: Arc<ValueEntry<K, V>>,
@@ -1087,7 +3797,7 @@ where
             self.remove_expired_wo(deqs, batch_size, now, counters);
         }
 
-        if self.time_to_idle.is_some() || self.has_valid_after() {
+        if self.has_time_to_idle() || self.has_valid_after() || self.has_removal_deadlines() {
             let (window, probation, protected, wo) = (
                 &mut deqs.window,
                 &mut deqs.probation,
@@ -1115,11 +3825,11 @@ where
         counters: &mut EvictionCounters,
     ) {
         let tti = &self.time_to_idle;
-        let va = &self.valid_after();
+        let va = &self.valid_after_for_removal();
         for _ in 0..batch_size {
             // Peek the front node of the deque and check if it is expired.
             let key = deq.peek_front().and_then(|node| {
-                if is_expired_entry_ao(tti, va, &*node, now) {
+                if is_expired_entry_ao(tti, va, node, now) {
                     Some(Arc::clone(node.element.key()))
                 } else {
                     None
@@ -1141,11 +3851,98 @@ where
                 .remove_if(key, |_, v| is_expired_entry_ao(tti, va, v, now));
 
             if let Some(entry) = maybe_entry {
+                let cause = expired_or_invalidated(va, &entry, now);
+                self.handle_eviction(cause, key, &entry, counters);
                 Self::handle_remove_with_deques(deq_name, deq, write_order_deq, entry, counters);
             } else if !self.try_skip_updated_entry(key, deq_name, deq, write_order_deq) {
                 break;
             }
         }
+
+        if self.has_per_entry_tti.load(Ordering::Acquire) || self.has_removal_deadlines() {
+            self.scan_expired_ao(deq_name, deq, write_order_deq, batch_size, now, counters);
+        }
+    }
+
+    /// Entries with their own time-to-idle or a removal deadline do not expire in
+    /// the access order, so an expired entry may be behind the unexpired front of the deque. This
+    /// visits the next `batch_size` nodes from where the previous call stopped,
+    /// and starts over from the front after reaching the back.
+    fn scan_expired_ao(
+        &self,
+        deq_name: &str,
+        deq: &mut Deque<KeyHashDate<K>>,
+        write_order_deq: &mut Deque<KeyDate<K>>,
+        batch_size: usize,
+        now: Instant,
+        counters: &mut EvictionCounters,
+    ) {
+        let tti = &self.time_to_idle;
+        let va = &self.valid_after_for_removal();
+        let keys = (&mut *deq)
+            .take(batch_size)
+            .filter(|khd| is_expired_entry_ao(tti, va, khd.entry_info(), now))
+            .map(|khd| Arc::clone(khd.key()))
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            let maybe_entry = self
+                .cache
+                .remove_if(&key, |_, v| is_expired_entry_ao(tti, va, v, now));
+
+            if let Some(entry) = maybe_entry {
+                let cause = expired_or_invalidated(va, &entry, now);
+                self.handle_eviction(cause, &key, &entry, counters);
+                Self::handle_remove_with_deques(deq_name, deq, write_order_deq, entry, counters);
+            }
+        }
+    }
+
+    fn remove_collected(
+        &self,
+        deqs: &mut Deques<K>,
+        batch_size: usize,
+        check: fn(&V) -> bool,
+        counters: &mut EvictionCounters,
+    ) {
+        let (window, probation, protected, wo) = (
+            &mut deqs.window,
+            &mut deqs.probation,
+            &mut deqs.protected,
+            &mut deqs.write_order,
+        );
+
+        let mut rm_collected =
+            |name, deq| self.remove_collected_ao(name, deq, wo, batch_size, check, counters);
+
+        rm_collected("window", window);
+        rm_collected("probation", probation);
+        rm_collected("protected", protected);
+    }
+
+    /// Visits the next `batch_size` nodes of the deque from where the previous
+    /// call stopped, and removes the entries whose values have been collected.
+    fn remove_collected_ao(
+        &self,
+        deq_name: &str,
+        deq: &mut Deque<KeyHashDate<K>>,
+        write_order_deq: &mut Deque<KeyDate<K>>,
+        batch_size: usize,
+        check: fn(&V) -> bool,
+        counters: &mut EvictionCounters,
+    ) {
+        let keys = (&mut *deq)
+            .take(batch_size)
+            .map(|khd| Arc::clone(khd.key()))
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            let maybe_entry = self.cache.remove_if(&key, |_, v| check(v.value()));
+            if let Some(entry) = maybe_entry {
+                self.handle_eviction(RemovalCause::Collected, &key, &entry, counters);
+                Self::handle_remove_with_deques(deq_name, deq, write_order_deq, entry, counters);
+            }
+        }
     }
 
     #[inline]
@@ -1188,10 +3985,12 @@ where
         counters: &mut EvictionCounters,
     ) {
         let ttl = &self.time_to_live;
-        let va = &self.valid_after();
+        let va = &self.valid_after_for_removal();
         for _ in 0..batch_size {
             let key = deqs.write_order.peek_front().and_then(|node| {
-                if is_expired_entry_wo(ttl, va, &*node, now) {
+                if is_expired_entry_wo(ttl, va, node, now)
+                    || self.is_over_max_age(node.element.entry_info(), now)
+                {
                     Some(Arc::clone(node.element.key()))
                 } else {
                     None
@@ -1204,11 +4003,13 @@ where
 
             let key = key.as_ref().unwrap();
 
-            let maybe_entry = self
-                .cache
-                .remove_if(key, |_, v| is_expired_entry_wo(ttl, va, v, now));
+            let maybe_entry = self.cache.remove_if(key, |_, v| {
+                is_expired_entry_wo(ttl, va, v, now) || self.is_over_max_age(v.entry_info(), now)
+            });
 
             if let Some(entry) = maybe_entry {
+                let cause = expired_or_invalidated(va, &entry, now);
+                self.handle_eviction(cause, key, &entry, counters);
                 Self::handle_remove(deqs, entry, counters);
             } else if let Some(entry) = self.cache.get(key) {
                 if entry.last_modified().is_none() {
@@ -1253,7 +4054,8 @@ where
             is_done,
         }) = invalidator.task_result()
         {
-            for KvEntry { key: _, entry } in invalidated {
+            for KvEntry { key, entry } in invalidated {
+                self.handle_eviction(RemovalCause::Explicit, &key, &entry, counters);
                 Self::handle_remove(deqs, entry, counters);
             }
             if is_done {
@@ -1298,6 +4100,60 @@ where
         }
     }
 
+    fn evict_now(&self, n: usize) -> usize {
+        // Do not change the entries visible through a frozen view.
+        if n == 0 || self.is_frozen() {
+            return 0;
+        }
+
+        let mut deqs = self.lock_deques();
+
+        // The capacity-driven eviction takes victims from the front of the
+        // probation deque. Continue to the other deques if it runs short.
+        let mut victims = Vec::with_capacity(n);
+        'outer: for deq in &[&deqs.probation, &deqs.window, &deqs.protected] {
+            let mut next = deq.peek_front();
+            while let Some(node) = next {
+                if victims.len() >= n {
+                    break 'outer;
+                }
+                next = node.next_node();
+                let info = node.element.entry_info();
+                if info.policy_weight() == 0 {
+                    continue;
+                }
+                if let Some(ts) = info.last_modified() {
+                    victims.push((Arc::clone(node.element.key()), ts));
+                }
+            }
+        }
+
+        let mut counters =
+            EvictionCounters::new(self.entry_count.load(), self.weighted_size.load());
+        let mut evicted = 0;
+        for (key, ts) in victims {
+            // Skip the entry if it has been updated since we read its timestamp,
+            // or the eviction filter vetoes it.
+            let maybe_entry = self.cache.remove_if(&key, |k, v| {
+                v.last_modified() == Some(ts) && self.is_evictable(k, v)
+            });
+            if let Some(entry) = maybe_entry {
+                self.handle_eviction(RemovalCause::Size, &key, &entry, &mut counters);
+                Self::handle_remove(&mut deqs, entry, &mut counters);
+                evicted += 1;
+            }
+        }
+
+        if evicted > 0 {
+            self.entry_count.store(counters.entry_count);
+            self.weighted_size.store(counters.weighted_size);
+            self.bump_write_generation();
+        }
+        std::mem::drop(deqs);
+        self.deliver_deferred_removals();
+        evicted
+    }
+
     fn evict_lru_entries(
         &self,
         deqs: &mut Deques<K>,
@@ -1307,13 +4163,18 @@ where
     ) {
         const DEQ_NAME: &str = "probation";
         let mut evicted = 0u64;
-        let (deq, write_order_deq) = (&mut deqs.probation, &mut deqs.write_order);
 
         for _ in 0..batch_size {
             if evicted >= weights_to_evict {
                 break;
             }
 
+            // Evict the protected entries only when there is nothing else to evict.
+            if deqs.probation.peek_front().is_none() {
+                deqs.demote_front_ao();
+            }
+            let (deq, write_order_deq) = (&mut deqs.probation, &mut deqs.write_order);
+
             let maybe_key_and_ts = deq.peek_front().map(|node| {
                 (
                     Arc::clone(node.element.key()),
@@ -1333,18 +4194,31 @@ where
                 None => break,
             };
 
-            let maybe_entry = self.cache.remove_if(&key, |_, v| {
-                if let Some(lm) = v.last_modified() {
-                    lm == ts
-                } else {
+            let mut vetoed = false;
+            let maybe_entry = self.cache.remove_if(&key, |k, v| {
+                vetoed = false;
+                if v.last_modified() != Some(ts) {
+                    false
+                } else if !self.is_evictable(k, v) {
+                    vetoed = true;
                     false
+                } else {
+                    true
                 }
             });
 
             if let Some(entry) = maybe_entry {
                 let weight = entry.policy_weight();
+                self.handle_eviction(RemovalCause::Size, &key, &entry, counters);
                 Self::handle_remove_with_deques(DEQ_NAME, deq, write_order_deq, entry, counters);
                 evicted = evicted.saturating_add(weight as u64);
+            } else if vetoed {
+                // Keep the entry and try the next one. If every entry is vetoed,
+                // the cache stays over its capacity until a later maintenance.
+                if let Some(node) = deq.peek_front() {
+                    let node = NonNull::from(node);
+                    unsafe { deq.move_to_back(node) };
+                }
             } else if !self.try_skip_updated_entry(&key, DEQ_NAME, deq, write_order_deq) {
                 break;
             }
@@ -1385,19 +4259,63 @@ where
 // private free-standing functions
 //
 #[inline]
+/// Returns the instant when the entry will expire, or `None` if the entry never
+/// expires, or has already expired or been invalidated.
+fn live_entry_expiration(
+    info: &EntryInfo,
+    ttl: Option<Duration>,
+    tti: Option<Duration>,
+    max_age: Option<MaxAge>,
+    valid_after: Option<Instant>,
+    now: Instant,
+) -> Option<Instant> {
+    let expiry_ttl = info
+        .last_modified()
+        .zip(info.time_to_live().or(ttl))
+        .and_then(|(ts, ttl)| ts.checked_add(ttl));
+    let expiry_wo = match (expiry_ttl, max_age.and_then(|ma| ma.expiration(info))) {
+        (Some(ttl), Some(ma)) => Some(if ttl < ma { ttl } else { ma }),
+        (ttl, ma) => ttl.or(ma),
+    };
+    let expiry_ao = info
+        .last_accessed()
+        .zip(info.time_to_idle().or(tti))
+        .and_then(|(ts, tti)| ts.checked_add(tti));
+    let expiry = match (expiry_wo, expiry_ao) {
+        (Some(wo), Some(ao)) => Some(if wo < ao { wo } else { ao }),
+        (wo, ao) => wo.or(ao),
+    };
+    let expiry = match (expiry, info.removal_deadline()) {
+        (Some(ex), Some(dl)) => Some(if ex < dl { ex } else { dl }),
+        (ex, dl) => ex.or(dl),
+    }?;
+    let is_invalidated = match (valid_after, info.last_modified()) {
+        (Some(va), Some(ts)) => ts < va,
+        _ => false,
+    };
+    if expiry > now && !is_invalidated {
+        Some(expiry)
+    } else {
+        None
+    }
+}
+
 fn is_expired_entry_ao(
     time_to_idle: &Option<Duration>,
     valid_after: &Option<Instant>,
     entry: &impl AccessTime,
     now: Instant,
 ) -> bool {
+    if matches!(entry.removal_deadline(), Some(dl) if dl <= now) {
+        return true;
+    }
     if let Some(ts) = entry.last_accessed() {
         if let Some(va) = valid_after {
             if ts < *va {
                 return true;
             }
         }
-        if let Some(tti) = time_to_idle {
+        if let Some(tti) = entry.time_to_idle().as_ref().or(time_to_idle.as_ref()) {
             let checked_add = ts.checked_add(*tti);
             if checked_add.is_none() {
                 panic!("ttl overflow")
@@ -1408,6 +4326,24 @@ fn is_expired_entry_ao(
     false
 }
 
+/// Tells whether an entry removed for being expired has actually been
+/// invalidated by `invalidate_all` (i.e. modified before `valid_after`), or has
+/// reached the deadline set by `invalidate_after`.
+#[inline]
+fn expired_or_invalidated(
+    valid_after: &Option<Instant>,
+    entry: &impl AccessTime,
+    now: Instant,
+) -> RemovalCause {
+    if matches!(entry.removal_deadline(), Some(dl) if dl <= now) {
+        return RemovalCause::Explicit;
+    }
+    match (entry.last_modified(), valid_after) {
+        (Some(ts), Some(va)) if ts < *va => RemovalCause::Explicit,
+        _ => RemovalCause::Expired,
+    }
+}
+
 #[inline]
 fn is_expired_entry_wo(
     time_to_live: &Option<Duration>,
@@ -1421,7 +4357,7 @@ fn is_expired_entry_wo(
                 return true;
             }
         }
-        if let Some(ttl) = time_to_live {
+        if let Some(ttl) = entry.time_to_live().as_ref().or(time_to_live.as_ref()) {
             let checked_add = ts.checked_add(*ttl);
             if checked_add.is_none() {
                 panic!("ttl overflow");
@@ -1434,7 +4370,7 @@ fn is_expired_entry_wo(
 
 #[cfg(test)]
 mod tests {
-    use super::BaseCache;
+    use super::{BaseCache, CacheConfig};
 
     #[cfg_attr(target_pointer_width = "16", ignore)]
     #[test]
@@ -1445,15 +4381,10 @@ mod tests {
         let pot = |exp| 2u64.pow(exp);
 
         let ensure_sketch_len = |max_capacity, len, name| {
-            let cache = BaseCache::<u8, u8>::new(
-                Some(max_capacity),
-                None,
-                RandomState::default(),
-                None,
-                None,
-                None,
-                false,
-            );
+            let cache = BaseCache::<u8, u8>::new(&CacheConfig {
+                max_capacity: Some(max_capacity),
+                ..CacheConfig::new(RandomState::default())
+            });
             cache.inner.enable_frequency_sketch();
             assert_eq!(
                 cache.inner.frequency_sketch.read().table_len(),
@@ -0,0 +1,46 @@
+/// A buffered mutation of [`BatchOps`].
+pub(crate) enum BatchOp<K, V> {
+    Insert(K, V),
+    Invalidate(K),
+}
+
+/// The buffer of the inserts and invalidations given to the closure of
+/// [`Cache::batch`][batch].
+///
+/// Nothing is applied to the cache until the closure returns, so reading the
+/// cache from within the closure does not see the buffered operations.
+///
+/// [batch]: ./struct.Cache.html#method.batch
+pub struct BatchOps<K, V> {
+    ops: Vec<BatchOp<K, V>>,
+}
+
+impl<K, V> BatchOps<K, V> {
+    pub(crate) fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    /// Buffers the insert of the key-value pair.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.ops.push(BatchOp::Insert(key, value));
+    }
+
+    /// Buffers the invalidation of the key.
+    pub fn invalidate(&mut self, key: K) {
+        self.ops.push(BatchOp::Invalidate(key));
+    }
+
+    /// Returns the number of the buffered operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns `true` if no operation has been buffered.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub(crate) fn into_ops(self) -> Vec<BatchOp<K, V>> {
+        self.ops
+    }
+}
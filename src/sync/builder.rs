@@ -1,11 +1,26 @@
-use super::{Cache, SegmentedCache, Weigher};
-use crate::common::builder_utils;
+use super::{
+    cache_config::CacheConfig, coherence::Coherence, value_dropper::ValueDropper, Cache,
+    CoherenceBus, EvictionFilter, KeyNormalizer, Loader, PublishOn, SegmentedCache, Weigher,
+    WeightIncreasePolicy,
+};
+use crate::{
+    common::builder_utils,
+    defaults::{self, TtlCapPolicy},
+    notification::{
+        AccessListener, BatchedListener, DropContext, EvictionListener, RemovalCause, RemovalInfo,
+    },
+    policy::SharedFrequencySketch,
+    BuildError,
+};
+
+#[cfg(feature = "tracing")]
+use super::KeyTracer;
 
 use std::{
     collections::hash_map::RandomState,
     hash::{BuildHasher, Hash},
     marker::PhantomData,
-    sync::Arc,
+    sync::{Arc, Weak},
     time::Duration,
 };
 
@@ -44,12 +59,43 @@ use std::{
 #[must_use]
 pub struct CacheBuilder<K, V, C> {
     max_capacity: Option<u64>,
+    max_weighted_capacity: Option<u64>,
+    max_capacity_memory_fraction: Option<f64>,
     initial_capacity: Option<usize>,
     num_segments: Option<usize>,
     weigher: Option<Weigher<K, V>>,
+    key_normalizer: Option<KeyNormalizer<K>>,
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
+    max_allowed_ttl: Option<(Duration, TtlCapPolicy)>,
     invalidator_enabled: bool,
+    stats_enabled: bool,
+    small_cache_hint: bool,
+    promote_after_hits: Option<u8>,
+    weight_increase_policy: WeightIncreasePolicy,
+    name: Option<String>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    invoke_listener_on_drop: bool,
+    shared_frequency_sketch: Option<SharedFrequencySketch>,
+    max_age: Option<Duration>,
+    max_age_spans_updates: bool,
+    expiration_tick: Option<Duration>,
+    expiration_jitter: Option<f64>,
+    eviction_filter: Option<EvictionFilter<K, V>>,
+    maintenance_interval: Option<Duration>,
+    thread_local_cache_capacity: Option<usize>,
+    coherence: Option<Coherence<K>>,
+    segment_capacities: Option<Vec<u64>>,
+    loader: Option<Loader<K, V>>,
+    access_listener: Option<AccessListener<K, V>>,
+    access_record_throttle: Option<Duration>,
+    reservation_high_water_mark: Option<u64>,
+    collection_check: Option<fn(&V) -> bool>,
+    drop_context: DropContext,
+    #[cfg(feature = "zeroize")]
+    value_zeroizer: Option<fn(&mut V)>,
+    #[cfg(feature = "tracing")]
+    key_tracer: Option<KeyTracer<K>>,
     cache_type: PhantomData<C>,
 }
 
@@ -59,14 +105,46 @@ where
     V: Clone + Send + Sync + 'static,
 {
     fn default() -> Self {
+        let defaults = defaults::get();
         Self {
             max_capacity: None,
+            max_weighted_capacity: None,
+            max_capacity_memory_fraction: None,
             initial_capacity: None,
             num_segments: None,
             weigher: None,
+            key_normalizer: None,
             time_to_live: None,
             time_to_idle: None,
+            max_allowed_ttl: defaults.max_allowed_ttl(),
             invalidator_enabled: false,
+            stats_enabled: defaults.record_stats(),
+            small_cache_hint: false,
+            promote_after_hits: None,
+            weight_increase_policy: WeightIncreasePolicy::Admit,
+            name: None,
+            eviction_listener: None,
+            invoke_listener_on_drop: false,
+            shared_frequency_sketch: None,
+            max_age: None,
+            max_age_spans_updates: false,
+            expiration_tick: None,
+            expiration_jitter: None,
+            eviction_filter: None,
+            maintenance_interval: None,
+            thread_local_cache_capacity: None,
+            coherence: None,
+            segment_capacities: None,
+            loader: None,
+            access_listener: None,
+            access_record_throttle: None,
+            reservation_high_water_mark: None,
+            collection_check: None,
+            drop_context: DropContext::Inline,
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: None,
+            #[cfg(feature = "tracing")]
+            key_tracer: None,
             cache_type: Default::default(),
         }
     }
@@ -86,7 +164,25 @@ where
         }
     }
 
-    /// Sets the number of segments of the cache.
+    /// Sets the number of segments of the cache. It will be rounded up to the
+    /// next power of two.
+    ///
+    /// `segments(1)` is supported, and performs the same as a plain `Cache`: the
+    /// hash of the key is computed once and reused by the segment, and no
+    /// segment selection or parallel maintenance is done. So code that takes the
+    /// number of segments from its configuration can always use
+    /// `SegmentedCache`. More segments reduce the lock contention between
+    /// threads, at the cost of a little more work per operation and a less
+    /// precise eviction, as each segment evicts by its own share of the capacity.
+    ///
+    /// Results of `cargo bench --bench segments` on a single thread (insert /
+    /// get, ns per operation):
+    ///
+    /// | Cache                 | insert | get |
+    /// |-----------------------|--------|-----|
+    /// | `Cache`               | 899    | 414 |
+    /// | `SegmentedCache` (1)  | 852    | 402 |
+    /// | `SegmentedCache` (8)  | 1095   | 473 |
     ///
     /// # Panics
     ///
@@ -99,13 +195,44 @@ where
 
         CacheBuilder {
             max_capacity: self.max_capacity,
+            max_weighted_capacity: self.max_weighted_capacity,
+            max_capacity_memory_fraction: self.max_capacity_memory_fraction,
             initial_capacity: self.initial_capacity,
             num_segments: Some(num_segments),
-            weigher: None,
+            weigher: self.weigher,
+            key_normalizer: self.key_normalizer,
             time_to_live: self.time_to_live,
             time_to_idle: self.time_to_idle,
+            max_allowed_ttl: self.max_allowed_ttl,
             invalidator_enabled: self.invalidator_enabled,
-            cache_type: PhantomData::default(),
+            stats_enabled: self.stats_enabled,
+            small_cache_hint: self.small_cache_hint,
+            promote_after_hits: self.promote_after_hits,
+            weight_increase_policy: self.weight_increase_policy,
+            name: self.name,
+            eviction_listener: self.eviction_listener,
+            invoke_listener_on_drop: self.invoke_listener_on_drop,
+            shared_frequency_sketch: self.shared_frequency_sketch,
+            max_age: self.max_age,
+            max_age_spans_updates: self.max_age_spans_updates,
+            expiration_tick: self.expiration_tick,
+            expiration_jitter: self.expiration_jitter,
+            eviction_filter: self.eviction_filter,
+            maintenance_interval: self.maintenance_interval,
+            thread_local_cache_capacity: self.thread_local_cache_capacity,
+            coherence: self.coherence,
+            segment_capacities: self.segment_capacities,
+            loader: self.loader,
+            access_listener: self.access_listener,
+            access_record_throttle: self.access_record_throttle,
+            reservation_high_water_mark: self.reservation_high_water_mark,
+            collection_check: self.collection_check,
+            drop_context: self.drop_context,
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: self.value_zeroizer,
+            #[cfg(feature = "tracing")]
+            key_tracer: self.key_tracer,
+            cache_type: PhantomData,
         }
     }
 
@@ -119,18 +246,99 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
+    ///
+    /// Also panics if `max_capacity_memory_fraction` is set without a weigher, or
+    /// the total memory of the system is unknown.
+    ///
+    /// Also panics if the [process-level defaults][defaults] reject the
+    /// `time_to_live`.
+    ///
+    /// [defaults]: ../defaults/index.html
     pub fn build(self) -> Cache<K, V, RandomState> {
-        let build_hasher = RandomState::default();
-        builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        Cache::with_everything(
+        let build_hasher = self
+            .shared_frequency_sketch
+            .as_ref()
+            .map_or_else(RandomState::default, |s| s.build_hasher().clone());
+        Cache::with_config(self.into_config(build_hasher))
+    }
+
+    /// Builds a `Cache<K, V>` like `build`, after checking that the capacity
+    /// is given in the unit matching the weigher setting, and that the expiration
+    /// tick is short enough.
+    ///
+    /// # Errors
+    ///
+    /// - [`BuildError::EntryCapacityWithWeigher`][entry-with-weigher] if a
+    ///   weigher is set together with `max_capacity` (including the one given to
+    ///   `CacheBuilder::new`).
+    /// - [`BuildError::WeightedCapacityWithoutWeigher`][weighted-without-weigher]
+    ///   if `max_weighted_capacity` is set without a weigher.
+    /// - [`BuildError::ExpirationTickTooLong`][tick-too-long] if the
+    ///   `expiration_tick` is longer than half of `time_to_live` or
+    ///   `time_to_idle`.
+    /// - [`BuildError::TimeToLiveNotAllowed`][ttl-not-allowed] if the
+    ///   [process-level defaults][defaults] reject the `time_to_live`.
+    /// - [`BuildError::TtiExceedsTtl`][tti-exceeds-ttl] if the `time_to_idle` is
+    ///   longer than the `time_to_live`.
+    /// - [`BuildError::MemoryFractionWithoutWeigher`][memory-without-weigher] if
+    ///   `max_capacity_memory_fraction` is set without a weigher.
+    /// - [`BuildError::TotalMemoryUnknown`][memory-unknown] if
+    ///   `max_capacity_memory_fraction` is set and the total memory of the system
+    ///   is unknown.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `build`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::CacheBuilder;
+    ///
+    /// let cache = CacheBuilder::default()
+    ///     .weigher(|_key: &u32, value: &String| value.len() as u32)
+    ///     .max_weighted_capacity(1024 * 1024)
+    ///     .try_build()
+    ///     .unwrap();
+    /// # cache.insert(1, "one".to_string());
+    /// ```
+    ///
+    /// [entry-with-weigher]: ../enum.BuildError.html#variant.EntryCapacityWithWeigher
+    /// [weighted-without-weigher]: ../enum.BuildError.html#variant.WeightedCapacityWithoutWeigher
+    /// [tick-too-long]: ../enum.BuildError.html#variant.ExpirationTickTooLong
+    /// [ttl-not-allowed]: ../enum.BuildError.html#variant.TimeToLiveNotAllowed
+    /// [tti-exceeds-ttl]: ../enum.BuildError.html#variant.TtiExceedsTtl
+    /// [memory-without-weigher]: ../enum.BuildError.html#variant.MemoryFractionWithoutWeigher
+    /// [memory-unknown]: ../enum.BuildError.html#variant.TotalMemoryUnknown
+    /// [defaults]: ../defaults/index.html
+    pub fn try_build(self) -> Result<Cache<K, V, RandomState>, BuildError> {
+        builder_utils::ensure_capacity(
             self.max_capacity,
-            self.initial_capacity,
-            build_hasher,
-            self.weigher,
-            self.time_to_live,
+            self.max_weighted_capacity,
+            self.weigher.is_some(),
+        )?;
+        // Read the memory of the system only once.
+        let max_weighted_capacity = builder_utils::max_weighted_capacity(
+            self.max_weighted_capacity,
+            self.max_capacity_memory_fraction,
+            self.weigher.is_some(),
+        )?;
+        let time_to_live = builder_utils::time_to_live(self.time_to_live, self.max_allowed_ttl)?;
+        builder_utils::ensure_time_to_idle(time_to_live, self.time_to_idle)?;
+        builder_utils::ensure_expiration_tick(
+            self.expiration_tick,
+            time_to_live,
             self.time_to_idle,
-            self.invalidator_enabled,
-        )
+        )?;
+        Ok(Self {
+            max_weighted_capacity,
+            max_capacity_memory_fraction: None,
+            ..self
+        }
+        .build())
     }
 
     /// Builds a `Cache<K, V, S>`, with the given `hasher`.
@@ -143,20 +351,22 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
+    ///
+    /// Also panics if `max_capacity_memory_fraction` is set without a weigher, or
+    /// the total memory of the system is unknown.
+    ///
+    /// Also panics if the [process-level defaults][defaults] reject the
+    /// `time_to_live`.
+    ///
+    /// [defaults]: ../defaults/index.html
     pub fn build_with_hasher<S>(self, hasher: S) -> Cache<K, V, S>
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
-        builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        Cache::with_everything(
-            self.max_capacity,
-            self.initial_capacity,
-            hasher,
-            self.weigher,
-            self.time_to_live,
-            self.time_to_idle,
-            self.invalidator_enabled,
-        )
+        Cache::with_config(self.into_config(hasher))
     }
 }
 
@@ -165,6 +375,41 @@ where
     K: Eq + Hash + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
 {
+    /// Sets the max capacity of each segment, in the order of the segment
+    /// indices, instead of dividing the `max_capacity` evenly across them.
+    ///
+    /// This is useful when you place the keys to the segments with a custom
+    /// hasher, and some segments hold more hot keys than the others. The
+    /// capacities are in the same unit as the capacity of the cache: the number
+    /// of entries, or the total weight if a [`weigher`](#method.weigher) is set.
+    /// The `max_capacity` of the built cache is the sum of them.
+    ///
+    /// There must be exactly one capacity for each segment, i.e. the length of
+    /// `capacities` must be the `num_segments` given to `segments`, rounded up
+    /// to the next power of two. `try_build` returns an error otherwise, and
+    /// `build` panics.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::SegmentedCache;
+    ///
+    /// let cache: SegmentedCache<u64, String> = SegmentedCache::builder(4)
+    ///     // The segment 0 holds the hot keys.
+    ///     .segment_capacities(vec![700, 100, 100, 100])
+    ///     .try_build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(cache.max_capacity(), Some(1000));
+    /// assert_eq!(cache.segment_max_capacities()[0], Some(700));
+    /// ```
+    pub fn segment_capacities(self, capacities: Vec<u64>) -> Self {
+        Self {
+            segment_capacities: Some(capacities),
+            ..self
+        }
+    }
+
     /// Builds a `SegmentedCache<K, V>`.
     ///
     /// If you want to build a `Cache<K, V>`, do not call `segments` method before
@@ -175,19 +420,113 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
+    ///
+    /// Also panics if `max_capacity_memory_fraction` is set without a weigher, or
+    /// the total memory of the system is unknown.
+    ///
+    /// Also panics if the [process-level defaults][defaults] reject the
+    /// `time_to_live`.
+    ///
+    /// [defaults]: ../defaults/index.html
     pub fn build(self) -> SegmentedCache<K, V, RandomState> {
-        let build_hasher = RandomState::default();
-        builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        SegmentedCache::with_everything(
+        let build_hasher = self
+            .shared_frequency_sketch
+            .as_ref()
+            .map_or_else(RandomState::default, |s| s.build_hasher().clone());
+        let num_segments = self.num_segments.unwrap();
+        let segment_capacities = self.segment_capacities.clone();
+        SegmentedCache::with_config(
+            self.into_config(build_hasher),
+            num_segments,
+            segment_capacities,
+        )
+    }
+
+    /// Builds a `SegmentedCache<K, V>` like `build`, after checking that the capacity
+    /// is given in the unit matching the weigher setting, and that the expiration
+    /// tick is short enough.
+    ///
+    /// # Errors
+    ///
+    /// - [`BuildError::EntryCapacityWithWeigher`][entry-with-weigher] if a
+    ///   weigher is set together with `max_capacity` (including the one given to
+    ///   `CacheBuilder::new`).
+    /// - [`BuildError::WeightedCapacityWithoutWeigher`][weighted-without-weigher]
+    ///   if `max_weighted_capacity` is set without a weigher.
+    /// - [`BuildError::ExpirationTickTooLong`][tick-too-long] if the
+    ///   `expiration_tick` is longer than half of `time_to_live` or
+    ///   `time_to_idle`.
+    /// - [`BuildError::TimeToLiveNotAllowed`][ttl-not-allowed] if the
+    ///   [process-level defaults][defaults] reject the `time_to_live`.
+    /// - [`BuildError::TtiExceedsTtl`][tti-exceeds-ttl] if the `time_to_idle` is
+    ///   longer than the `time_to_live`.
+    /// - [`BuildError::MemoryFractionWithoutWeigher`][memory-without-weigher] if
+    ///   `max_capacity_memory_fraction` is set without a weigher.
+    /// - [`BuildError::TotalMemoryUnknown`][memory-unknown] if
+    ///   `max_capacity_memory_fraction` is set and the total memory of the system
+    ///   is unknown.
+    /// - [`BuildError::SegmentCapacitiesMismatch`][segment-capacities] if the
+    ///   number of the `segment_capacities` is not the number of the segments.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `build`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::CacheBuilder;
+    ///
+    /// let cache = CacheBuilder::default()
+    ///     .weigher(|_key: &u32, value: &String| value.len() as u32)
+    ///     .max_weighted_capacity(1024 * 1024)
+    ///     .segments(4)
+    ///     .try_build()
+    ///     .unwrap();
+    /// # cache.insert(1, "one".to_string());
+    /// ```
+    ///
+    /// [entry-with-weigher]: ../enum.BuildError.html#variant.EntryCapacityWithWeigher
+    /// [weighted-without-weigher]: ../enum.BuildError.html#variant.WeightedCapacityWithoutWeigher
+    /// [tick-too-long]: ../enum.BuildError.html#variant.ExpirationTickTooLong
+    /// [ttl-not-allowed]: ../enum.BuildError.html#variant.TimeToLiveNotAllowed
+    /// [tti-exceeds-ttl]: ../enum.BuildError.html#variant.TtiExceedsTtl
+    /// [memory-without-weigher]: ../enum.BuildError.html#variant.MemoryFractionWithoutWeigher
+    /// [memory-unknown]: ../enum.BuildError.html#variant.TotalMemoryUnknown
+    /// [segment-capacities]: ../enum.BuildError.html#variant.SegmentCapacitiesMismatch
+    /// [defaults]: ../defaults/index.html
+    pub fn try_build(self) -> Result<SegmentedCache<K, V, RandomState>, BuildError> {
+        builder_utils::ensure_capacity(
             self.max_capacity,
-            self.initial_capacity,
-            self.num_segments.unwrap(),
-            build_hasher,
-            self.weigher,
-            self.time_to_live,
+            self.max_weighted_capacity,
+            self.weigher.is_some(),
+        )?;
+        // Read the memory of the system only once.
+        let max_weighted_capacity = builder_utils::max_weighted_capacity(
+            self.max_weighted_capacity,
+            self.max_capacity_memory_fraction,
+            self.weigher.is_some(),
+        )?;
+        let time_to_live = builder_utils::time_to_live(self.time_to_live, self.max_allowed_ttl)?;
+        builder_utils::ensure_time_to_idle(time_to_live, self.time_to_idle)?;
+        builder_utils::ensure_expiration_tick(
+            self.expiration_tick,
+            time_to_live,
             self.time_to_idle,
-            self.invalidator_enabled,
-        )
+        )?;
+        builder_utils::ensure_segment_capacities(
+            self.segment_capacities.as_deref(),
+            self.num_segments.unwrap(),
+        )?;
+        Ok(Self {
+            max_weighted_capacity,
+            max_capacity_memory_fraction: None,
+            ..self
+        }
+        .build())
     }
 
     /// Builds a `SegmentedCache<K, V, S>`, with the given `hasher`.
@@ -200,26 +539,94 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
+    ///
+    /// Also panics if `max_capacity_memory_fraction` is set without a weigher, or
+    /// the total memory of the system is unknown.
+    ///
+    /// Also panics if the [process-level defaults][defaults] reject the
+    /// `time_to_live`.
+    ///
+    /// [defaults]: ../defaults/index.html
     pub fn build_with_hasher<S>(self, hasher: S) -> SegmentedCache<K, V, S>
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
-        builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        SegmentedCache::with_everything(
-            self.max_capacity,
-            self.initial_capacity,
-            self.num_segments.unwrap(),
-            hasher,
-            self.weigher,
-            self.time_to_live,
-            self.time_to_idle,
-            self.invalidator_enabled,
-        )
+        let num_segments = self.num_segments.unwrap();
+        let segment_capacities = self.segment_capacities.clone();
+        SegmentedCache::with_config(self.into_config(hasher), num_segments, segment_capacities)
+    }
+}
+
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Fills the configuration of the cache to build.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the cases described in `build`.
+    fn into_config<S>(self, build_hasher: S) -> CacheConfig<K, V, S> {
+        let time_to_live =
+            builder_utils::time_to_live_or_panic(self.time_to_live, self.max_allowed_ttl);
+        builder_utils::ensure_expirations_or_panic(time_to_live, self.time_to_idle);
+        CacheConfig {
+            max_capacity: builder_utils::max_capacity(
+                self.max_capacity,
+                builder_utils::max_weighted_capacity_or_panic(
+                    self.max_weighted_capacity,
+                    self.max_capacity_memory_fraction,
+                    self.weigher.is_some(),
+                ),
+                self.weigher.is_some(),
+            ),
+            initial_capacity: self.initial_capacity,
+            build_hasher,
+            weigher: self.weigher,
+            key_normalizer: self.key_normalizer,
+            time_to_live,
+            time_to_idle: self.time_to_idle,
+            invalidator_enabled: self.invalidator_enabled,
+            stats_enabled: self.stats_enabled,
+            small_cache_hint: self.small_cache_hint,
+            promote_after_hits: self.promote_after_hits,
+            weight_increase_policy: self.weight_increase_policy,
+            name: self.name,
+            eviction_listener: self.eviction_listener,
+            invoke_listener_on_drop: self.invoke_listener_on_drop,
+            shared_frequency_sketch: self.shared_frequency_sketch,
+            max_age: builder_utils::max_age(self.max_age, self.max_age_spans_updates),
+            expiration_tick: self.expiration_tick,
+            expiration_jitter: self.expiration_jitter,
+            eviction_filter: self.eviction_filter,
+            maintenance_interval: self.maintenance_interval,
+            access_listener: self.access_listener,
+            access_record_throttle: self.access_record_throttle,
+            reservation_high_water_mark: self.reservation_high_water_mark,
+            collection_check: self.collection_check,
+            value_dropper: ValueDropper::new(self.drop_context),
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: self.value_zeroizer,
+            #[cfg(feature = "tracing")]
+            key_tracer: self.key_tracer,
+            thread_local_cache_capacity: self.thread_local_cache_capacity,
+            coherence: self.coherence,
+            loader: self.loader,
+        }
     }
 }
 
 impl<K, V, C> CacheBuilder<K, V, C> {
-    /// Sets the max capacity of the cache.
+    /// Sets the max capacity of the cache as a number of entries.
+    ///
+    /// For compatibility, the `build` methods interpret it as the total weight of
+    /// the entries when a [`weigher`](#method.weigher) is set. `try_build`
+    /// rejects that combination; use
+    /// [`max_weighted_capacity`](#method.max_weighted_capacity) with a weigher.
     pub fn max_capacity(self, max_capacity: u64) -> Self {
         Self {
             max_capacity: Some(max_capacity),
@@ -227,7 +634,76 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the max capacity of the cache as the total weight of the entries
+    /// computed by the [`weigher`](#method.weigher).
+    ///
+    /// The `build` methods interpret `max_capacity` as the total weight too when
+    /// a weigher is set, while `try_build` requires this method to be used with a
+    /// weigher, to make the interpretation explicit.
+    pub fn max_weighted_capacity(self, max_weighted_capacity: u64) -> Self {
+        Self {
+            max_weighted_capacity: Some(max_weighted_capacity),
+            ..self
+        }
+    }
+
+    /// Sets the max capacity of the cache as a fraction of the total memory of
+    /// the system, e.g. `0.25` for a quarter of it. The cache must have a
+    /// [`weigher`](#method.weigher) returning the approximate size of the entries
+    /// in bytes.
+    ///
+    /// This sizes the cache relative to the machine it runs on, instead of a
+    /// byte limit hard-coded for one machine. On Linux, the total memory is the
+    /// `MemTotal` of `/proc/meminfo`, or the memory limit of the cgroup of the
+    /// process if it is lower (e.g. in a container). On the other platforms, the
+    /// total memory is unknown, and `try_build` returns
+    /// [`BuildError::TotalMemoryUnknown`][memory-unknown].
+    ///
+    /// The memory is read once by the `build` methods, and the capacity is not
+    /// changed afterwards. The cache does not adapt to the memory pressure at run
+    /// time; call [`evict_now`][evict-now] to shed entries under pressure.
+    ///
+    /// This takes precedence over
+    /// [`max_weighted_capacity`](#method.max_weighted_capacity).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::CacheBuilder;
+    ///
+    /// let builder = CacheBuilder::default()
+    ///     .weigher(|key: &String, value: &Vec<u8>| (key.len() + value.len()) as u32)
+    ///     .max_capacity_memory_fraction(0.1);
+    /// # if cfg!(target_os = "linux") {
+    /// let cache = builder.try_build().unwrap();
+    /// # cache.insert("a".to_string(), vec![0; 8]);
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not greater than `0.0` and less than `1.0`.
+    ///
+    /// The `build` methods panic if no weigher is set, or the total memory is
+    /// unknown.
+    ///
+    /// [memory-unknown]: ../enum.BuildError.html#variant.TotalMemoryUnknown
+    /// [evict-now]: ./struct.Cache.html#method.evict_now
+    pub fn max_capacity_memory_fraction(self, fraction: f64) -> Self {
+        assert!(
+            fraction > 0.0 && fraction < 1.0,
+            "max_capacity_memory_fraction must be greater than 0.0 and less than 1.0"
+        );
+        Self {
+            max_capacity_memory_fraction: Some(fraction),
+            ..self
+        }
+    }
+
     /// Sets the initial capacity (number of entries) of the cache.
+    ///
+    /// This is the total number of entries expected in the cache. For a
+    /// `SegmentedCache`, it is divided across the segments, rounding up.
     pub fn initial_capacity(self, number_of_entries: usize) -> Self {
         Self {
             initial_capacity: Some(number_of_entries),
@@ -235,6 +711,20 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the name of the cache. The name is used to tell caches apart in the
+    /// events emitted with the `tracing` crate feature.
+    ///
+    /// The `name_prefix` of the [process-level defaults][defaults] is added to
+    /// the name.
+    ///
+    /// [defaults]: ../defaults/index.html
+    pub fn name(self, name: &str) -> Self {
+        Self {
+            name: Some(defaults::get().prefixed_name(name)),
+            ..self
+        }
+    }
+
     /// Sets the weigher closure of the cache.
     ///
     /// The closure should take `&K` and `&V` as the arguments and returns a `u32`
@@ -246,6 +736,485 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the eviction listener closure of the cache.
+    ///
+    /// The closure is called with the key, the value and the
+    /// [`RemovalCause`][removal-cause] every time an entry is removed from the
+    /// cache, or its value is replaced by `insert`. It is called synchronously on
+    /// the thread that has removed the entry: the caller of `invalidate` or
+    /// `insert` for the explicit removals and replacements, and the thread running
+    /// the maintenance (usually the housekeeper) for the expired and evicted
+    /// entries. So it should return quickly. The maintenance calls it after
+    /// releasing its lock, so the closure may access the cache, e.g. to insert or
+    /// invalidate other entries.
+    ///
+    /// The entries invalidated by `invalidate_all` are notified with
+    /// `RemovalCause::Explicit` when the maintenance actually removes them.
+    ///
+    /// A panic in the closure is caught and discarded, so that it does not break
+    /// the cache.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::{notification::RemovalCause, sync::Cache};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let removed = Arc::new(Mutex::new(Vec::new()));
+    /// let removed1 = Arc::clone(&removed);
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .eviction_listener(move |key, _value, cause| {
+    ///         removed1.lock().unwrap().push((*key, cause));
+    ///     })
+    ///     .build();
+    ///
+    /// cache.insert(1, "one");
+    /// cache.insert(1, "uno");
+    /// cache.invalidate(&1);
+    ///
+    /// assert_eq!(
+    ///     *removed.lock().unwrap(),
+    ///     vec![(1, RemovalCause::Replaced), (1, RemovalCause::Explicit)]
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a [batched eviction listener](#method.eviction_listener_batched)
+    /// has been set.
+    ///
+    /// [removal-cause]: ../notification/enum.RemovalCause.html
+    pub fn eviction_listener(
+        self,
+        listener: impl Fn(Arc<K>, V, RemovalCause) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(
+            !matches!(self.eviction_listener, Some(EvictionListener::Batched(_))),
+            "eviction_listener cannot be used with eviction_listener_batched"
+        );
+        Self {
+            eviction_listener: Some(EvictionListener::PerEntry(Arc::new(listener))),
+            ..self
+        }
+    }
+
+    /// Sets the eviction listener closure of the cache, which receives a
+    /// [`RemovalInfo`][removal-info] instead of a `RemovalCause`. It replaces the
+    /// [eviction listener](#method.eviction_listener) if one has been set.
+    ///
+    /// Besides the cause, the `RemovalInfo` tells the region of the cache an entry
+    /// evicted for the size (`RemovalCause::Size`) was in. Together with
+    /// `RemovalCause::NotAdmitted`, which is given for a new entry rejected by
+    /// the admission policy, this tells apart the three outcomes of a full cache:
+    /// a new entry rejected as less popular than the incumbents, an incumbent
+    /// evicted from the probation region, and an incumbent evicted from the
+    /// protected region.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::{notification::RemovalCause, sync::Cache};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let removed = Arc::new(Mutex::new(Vec::new()));
+    /// let removed1 = Arc::clone(&removed);
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .eviction_listener_with_info(move |key, _value, info| {
+    ///         removed1.lock().unwrap().push((*key, info.cause(), info.region()));
+    ///     })
+    ///     .build();
+    ///
+    /// cache.insert(1, "one");
+    /// cache.invalidate(&1);
+    ///
+    /// assert_eq!(
+    ///     *removed.lock().unwrap(),
+    ///     vec![(1, RemovalCause::Explicit, None)]
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a [batched eviction listener](#method.eviction_listener_batched)
+    /// has been set.
+    ///
+    /// [removal-info]: ../notification/struct.RemovalInfo.html
+    pub fn eviction_listener_with_info(
+        self,
+        listener: impl Fn(Arc<K>, V, RemovalInfo) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(
+            !matches!(self.eviction_listener, Some(EvictionListener::Batched(_))),
+            "eviction_listener_with_info cannot be used with eviction_listener_batched"
+        );
+        Self {
+            eviction_listener: Some(EvictionListener::WithInfo(Arc::new(listener))),
+            ..self
+        }
+    }
+
+    /// Sets the eviction listener closure of the cache, which receives the
+    /// removals in batches of up to `max_batch_size` entries, instead of one by
+    /// one as the [eviction listener](#method.eviction_listener) does.
+    ///
+    /// The removals are accumulated and delivered at the end of each maintenance
+    /// pass, after the lock of the pass has been released, or as soon as
+    /// `max_batch_size` of them have accumulated. So the explicit removals and
+    /// replacements made by `invalidate` and `insert` are delivered later than
+    /// they would be to the eviction listener, but this costs one call per batch
+    /// rather than one call per entry.
+    ///
+    /// The entries in a batch are in the order they were removed, and the batches
+    /// are delivered one at a time in that order, so the removals of a key are
+    /// never delivered out of order. The pending removals are delivered when the
+    /// cache is dropped.
+    ///
+    /// A panic in the closure is caught and discarded, and the batch is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::{notification::RemovalCause, sync::Cache};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let batches = Arc::new(Mutex::new(Vec::new()));
+    /// let batches1 = Arc::clone(&batches);
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .eviction_listener_batched(2, move |batch| {
+    ///         let batch = batch.iter().map(|(k, v, cause)| (**k, *v, *cause));
+    ///         batches1.lock().unwrap().push(batch.collect::<Vec<_>>());
+    ///     })
+    ///     .build();
+    ///
+    /// cache.insert(1, "one");
+    /// cache.insert(1, "uno");
+    /// cache.invalidate(&1);
+    ///
+    /// assert_eq!(
+    ///     *batches.lock().unwrap(),
+    ///     vec![vec![(1, "one", RemovalCause::Replaced), (1, "uno", RemovalCause::Explicit)]]
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_batch_size` is zero, or an
+    /// [eviction listener](#method.eviction_listener) has been set.
+    pub fn eviction_listener_batched(
+        self,
+        max_batch_size: usize,
+        listener: impl Fn(&[(Arc<K>, V, RemovalCause)]) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(max_batch_size != 0, "max_batch_size must be greater than 0");
+        assert!(
+            !matches!(
+                self.eviction_listener,
+                Some(EvictionListener::PerEntry(_)) | Some(EvictionListener::WithInfo(_))
+            ),
+            "eviction_listener_batched cannot be used with eviction_listener"
+        );
+        let listener = BatchedListener::new(Box::new(listener), max_batch_size);
+        Self {
+            eviction_listener: Some(EvictionListener::Batched(Arc::new(listener))),
+            ..self
+        }
+    }
+
+    /// Replaces the eviction listener with the one returned by `f`, which is
+    /// given the current one.
+    pub(crate) fn wrap_eviction_listener(
+        self,
+        f: impl FnOnce(Option<EvictionListener<K, V>>) -> EvictionListener<K, V>,
+    ) -> Self {
+        Self {
+            eviction_listener: Some(f(self.eviction_listener)),
+            ..self
+        }
+    }
+
+    /// Sets the access listener closure of the cache.
+    ///
+    /// The closure is called with the key and the value every time a lookup finds
+    /// a live entry: `get` and its variants, including the hits of
+    /// `get_or_insert_with` and the hits served by the
+    /// [thread local cache](#method.thread_local_cache). It is not called for the
+    /// misses, nor by the methods that do not read a value for the caller, such as
+    /// `contains_key`, `touch` and `for_each_entry`. This can be used, for
+    /// example, to tell an upper tier of a tiered cache which keys are hit here.
+    ///
+    /// The closure is called synchronously on the thread calling `get`, after the
+    /// lookup, while holding no lock of the cache. So it may access the cache,
+    /// but it adds its own running time to every hit. Besides that, the overhead
+    /// is one dynamic call per hit, and one clone of the `Arc` of the key per hit
+    /// served by the thread local cache. When no access listener is set, the read
+    /// path is unchanged.
+    ///
+    /// A panic in the closure is caught and discarded, so that it does not break
+    /// the cache nor the caller of `get`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let hits = Arc::new(Mutex::new(Vec::new()));
+    /// let hits1 = Arc::clone(&hits);
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .access_listener(move |key: &u32, _value: &&str| {
+    ///         hits1.lock().unwrap().push(*key);
+    ///     })
+    ///     .build();
+    ///
+    /// cache.insert(1, "one");
+    /// cache.get(&1);
+    /// cache.get(&2); // A miss.
+    ///
+    /// assert_eq!(*hits.lock().unwrap(), vec![1]);
+    /// ```
+    pub fn access_listener(self, listener: impl Fn(&K, &V) + Send + Sync + 'static) -> Self {
+        Self {
+            access_listener: Some(Arc::new(listener)),
+            ..self
+        }
+    }
+
+    /// Sets whether to notify the [eviction listener](#method.eviction_listener)
+    /// of the entries remaining in the cache when the cache is dropped. The
+    /// default is `false`.
+    ///
+    /// When enabled, dropping the last handle of the cache (the last clone of a
+    /// `Cache`) calls the listener with `RemovalCause::Shutdown` for every
+    /// remaining entry, before the internal data structures are torn down. The
+    /// listener is called on the dropping thread, in the write order of the
+    /// entries, oldest first. For a `SegmentedCache`, the entries are notified
+    /// segment by segment, so the write order is kept only within a segment.
+    ///
+    /// Dropping the other clones of the cache does not call the listener. As with
+    /// the other notifications, a panic in the listener is caught, and the
+    /// remaining entries are still notified.
+    ///
+    /// This option has no effect without an eviction listener. Note that it makes
+    /// the cache keep the write order of the entries even if the cache has no
+    /// time-to-live, which costs a little memory and time per write.
+    pub fn invoke_listener_on_drop(self, enabled: bool) -> Self {
+        Self {
+            invoke_listener_on_drop: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the eviction filter closure of the cache.
+    ///
+    /// The closure is called with the key, the value and
+    /// `RemovalCause::Size` before an entry is evicted to keep the cache within
+    /// its max capacity. Returning `false` vetoes the eviction: the entry is kept
+    /// and a different victim is selected. A new entry that would be rejected by
+    /// the admission policy is also kept if the closure returns `false` for it;
+    /// the closure is called with `RemovalCause::NotAdmitted` for such an entry.
+    ///
+    /// Only the capacity-driven evictions can be vetoed. The expirations by the
+    /// time-to-live or time-to-idle and the explicit invalidations do not call
+    /// the closure.
+    ///
+    /// When all the candidates are vetoed, the cache temporarily exceeds its max
+    /// capacity, and the eviction is retried by a later maintenance.
+    ///
+    /// A new entry heavier than the max capacity is always rejected, without
+    /// calling the closure, as it would never fit in the cache.
+    ///
+    /// The closure is called by the thread running the maintenance while it
+    /// holds the internal lock, and may be called for an entry that is not
+    /// evicted in the end. So it should return quickly and not access the cache.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, ConcurrentCacheExt};
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(2)
+    ///     // Never evict the key 0.
+    ///     .eviction_filter(|key, _value, _cause| *key != 0)
+    ///     .build();
+    ///
+    /// for key in 0..10 {
+    ///     cache.insert(key, key);
+    ///     cache.sync();
+    /// }
+    ///
+    /// assert_eq!(cache.get(&0), Some(0));
+    /// ```
+    pub fn eviction_filter(
+        self,
+        filter: impl Fn(&K, &V, RemovalCause) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            eviction_filter: Some(Arc::new(filter)),
+            ..self
+        }
+    }
+
+    /// Sets the loader closure of the cache.
+    ///
+    /// The loader computes the value of a key that is not in the cache. It is
+    /// called by [`Cache::get_loading`][get-loading], which inserts the loaded
+    /// value and returns a clone of it. Like `get_or_insert_with`, concurrent
+    /// `get_loading` calls for the same key run the loader only once.
+    ///
+    /// If the cache has a [key normalizer](#method.key_normalizer), the loader
+    /// receives the normalized key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .loader(|key: &u32| key * 2)
+    ///     .build();
+    ///
+    /// assert_eq!(cache.get_loading(21), 42);
+    /// assert_eq!(cache.get(&21), Some(42));
+    /// ```
+    ///
+    /// [get-loading]: ./struct.Cache.html#method.get_loading
+    pub fn loader(self, loader: impl Fn(&K) -> V + Send + Sync + 'static) -> Self {
+        Self {
+            loader: Some(Arc::new(loader)),
+            ..self
+        }
+    }
+
+    /// Makes the cache record the access frequencies of the keys into the given
+    /// [`SharedFrequencySketch`][shared-sketch] instead of its own sketch, so that
+    /// the admission policy of the cache also knows the keys accessed through the
+    /// other caches sharing the sketch.
+    ///
+    /// `build` hashes the keys with the hasher of the sketch. If you use
+    /// `build_with_hasher`, give all caches sharing the sketch clones of the same
+    /// hasher, otherwise they will not see each other's counts.
+    ///
+    /// The shared sketch is used as it is sized by
+    /// [`SharedFrequencySketch::new`][shared-sketch-new]. A cache that is treated
+    /// as a small cache (see [`small`](#method.small)) does
+    /// not use a frequency sketch at all, so it neither reads nor updates the
+    /// shared sketch.
+    ///
+    /// [shared-sketch]: ../policy/struct.SharedFrequencySketch.html
+    /// [shared-sketch-new]: ../policy/struct.SharedFrequencySketch.html#method.new
+    pub fn shared_frequency_sketch(self, sketch: SharedFrequencySketch) -> Self {
+        Self {
+            shared_frequency_sketch: Some(sketch),
+            ..self
+        }
+    }
+
+    /// Sets a bus to publish the local modifications of the cache to the caches
+    /// in other processes, e.g. the other replicas of a service.
+    ///
+    /// The cache calls [`CoherenceBus::publish_invalidation`][publish] with the
+    /// key after each local modification chosen by `publish_on`:
+    ///
+    /// - `insert`: `insert`, `insert_with_tti`, a successful `try_insert`, and
+    ///   `insert_with`. The values loaded by `get_or_insert_with` and its
+    ///   variants are not published, as they are read from the source of truth.
+    /// - `invalidate`: `invalidate`, even if the key is not in the local cache,
+    ///   with either `&K` or `&Arc<K>`. The bulk invalidations (`invalidate_all`, `invalidate_all_except` and
+    ///   `invalidate_entries_if`), eviction and expiration are not published.
+    ///
+    /// The receiving side should call
+    /// [`Cache::apply_remote_invalidation`][apply-remote], which does not publish
+    /// the invalidation again.
+    ///
+    /// # Ordering
+    ///
+    /// The bus is called on the thread that has modified the cache, after the
+    /// modification has been made. So the invalidations of a key modified by a
+    /// single thread are published in the order of the modifications. There is no
+    /// ordering between the modifications of the same key by different threads,
+    /// just like the local cache. Keeping the order in transit is up to the
+    /// transport.
+    ///
+    /// [publish]: ./trait.CoherenceBus.html#tymethod.publish_invalidation
+    /// [apply-remote]: ./struct.Cache.html#method.apply_remote_invalidation
+    pub fn coherence_bus(self, bus: impl CoherenceBus<K>, publish_on: PublishOn) -> Self
+    where
+        K: 'static,
+    {
+        Self {
+            coherence: Some(Coherence::new(Arc::new(bus), publish_on)),
+            ..self
+        }
+    }
+
+    /// Sets the key normalizer closure of the cache.
+    ///
+    /// The cache applies the closure to every key before hashing or storing it,
+    /// so keys that normalize to the same key refer to the same entry. For
+    /// example, lowercasing keys makes the cache case-insensitive:
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .key_normalizer(|key: &String| key.to_lowercase())
+    ///     .build();
+    ///
+    /// cache.insert("Foo".to_string(), 1);
+    /// assert_eq!(cache.get(&"foo".to_string()), Some(1));
+    /// ```
+    ///
+    /// The normalizer is applied to the keys given to `insert`, `get`,
+    /// `invalidate`, `get_or_insert_with` and `get_or_try_insert_with`, and the
+    /// cache stores the normalized keys. Note that it runs on every one of these
+    /// calls, so it should be cheap.
+    ///
+    /// The normalizer must be idempotent, i.e. normalizing a normalized key must
+    /// return the same key. The methods taking a borrowed form of the key, `&K` or
+    /// `&Arc<K>`, apply the normalizer to the key as well.
+    pub fn key_normalizer(self, normalizer: impl Fn(&K) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            key_normalizer: Some(Arc::new(normalizer)),
+            ..self
+        }
+    }
+
+    /// Sets what happens when an update increases the weight of an entry that
+    /// has been already admitted to the cache. The default is
+    /// [`WeightIncreasePolicy::Admit`][admit].
+    ///
+    /// This matters when an entry is first inserted as a light placeholder, and
+    /// later replaced with the real value whose weight is only known then. With
+    /// the default policy, the updated entry is always kept, and the weight
+    /// increase is added to the weighted size of the cache, so that other entries
+    /// are evicted in LRU order to make room for it.
+    ///
+    /// With [`WeightIncreasePolicy::ReapplyAdmission`][reapply], if the increase
+    /// does not fit in the free space of the cache, the updated entry has to pass
+    /// the admission policy again as if it were newly inserted with the new
+    /// weight. It is removed from the cache when it is less popular than the
+    /// entries it would evict.
+    ///
+    /// [admit]: ./enum.WeightIncreasePolicy.html#variant.Admit
+    /// [reapply]: ./enum.WeightIncreasePolicy.html#variant.ReapplyAdmission
+    pub fn on_weight_increase(self, policy: WeightIncreasePolicy) -> Self {
+        Self {
+            weight_increase_policy: policy,
+            ..self
+        }
+    }
+
     /// Sets the time to live of the cache.
     ///
     /// A cached entry will be expired after the specified duration past from
@@ -280,6 +1249,359 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the max age of the cache, a hard cap on the lifetime of the entries.
+    ///
+    /// A cached entry will be expired after the specified duration past from the
+    /// `insert` that created it, regardless of the accesses to it. It applies on
+    /// top of `time_to_live` and `time_to_idle`: an entry expires when any of
+    /// them expires it, and a time-to-idle set for an individual entry (e.g. by
+    /// `insert_with_tti`) cannot extend its life beyond the max age.
+    ///
+    /// By default, replacing the value of an entry by `insert` starts a new life
+    /// of the entry, as with `time_to_live`. To measure the age from the first
+    /// `insert` of the key even across the updates, also call
+    /// [`max_age_spans_updates(true)`](#method.max_age_spans_updates).
+    ///
+    /// Expired entries are not returned by the read methods, and are removed by
+    /// the housekeeping.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// // Never keep a value for more than 6 hours, even if it is kept being
+    /// // updated and read.
+    /// let cache = Cache::builder()
+    ///     .max_capacity(10_000)
+    ///     .time_to_idle(Duration::from_secs(30 * 60))
+    ///     .max_age(Duration::from_secs(6 * 3600))
+    ///     .max_age_spans_updates(true)
+    ///     .build();
+    /// # cache.insert(0, "zero");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `duration` is longer
+    /// than 1000 years. This is done to protect against overflow when computing key
+    /// expiration.
+    pub fn max_age(self, duration: Duration) -> Self {
+        Self {
+            max_age: Some(duration),
+            ..self
+        }
+    }
+
+    /// Sets a sliding time to live of the cache, which every read extends, up to
+    /// a hard cap on the lifetime of the entries.
+    ///
+    /// A cached entry will be expired after `extend` past from the last `get`
+    /// or `insert`, or after `max_lifetime` past from the `insert` that created
+    /// it, whichever comes first. So an entry that is kept being read still
+    /// expires at `max_lifetime`, and an `extend` longer than `max_lifetime` is
+    /// effectively clamped to `max_lifetime`.
+    ///
+    /// This is a shorthand for [`time_to_idle(extend)`](#method.time_to_idle)
+    /// and [`max_age(max_lifetime)`](#method.max_age), and replaces them if they
+    /// have been set. Call
+    /// [`max_age_spans_updates(true)`](#method.max_age_spans_updates) too to
+    /// measure the lifetime from the first `insert` of the key even across the
+    /// updates.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// // Keep a session for 30 minutes after the last use, but not for more
+    /// // than 12 hours in total.
+    /// let cache = Cache::builder()
+    ///     .max_capacity(10_000)
+    ///     .sliding_ttl(Duration::from_secs(30 * 60), Duration::from_secs(12 * 3600))
+    ///     .build();
+    /// # cache.insert(0, "zero");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if either duration is longer
+    /// than 1000 years.
+    pub fn sliding_ttl(self, extend: Duration, max_lifetime: Duration) -> Self {
+        Self {
+            time_to_idle: Some(extend),
+            max_age: Some(max_lifetime),
+            ..self
+        }
+    }
+
+    /// Sets whether the [max age](#method.max_age) of an entry is measured from
+    /// the first `insert` of the key, rather than from the last one. The default
+    /// is `false`.
+    ///
+    /// When enabled, updating the value of an existing entry does not restart its
+    /// life. The first `insert` after the entry has reached the max age (or has
+    /// been removed) starts a new life.
+    ///
+    /// If the cache also has a `time_to_live`, the housekeeping removes the
+    /// entries in the order they were last inserted. An updated entry that has
+    /// reached its max age is no longer returned, but it may stay in the cache
+    /// until its time to live elapses.
+    ///
+    /// This option has no effect without a max age.
+    pub fn max_age_spans_updates(self, enabled: bool) -> Self {
+        Self {
+            max_age_spans_updates: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the resolution of the access times recorded for the time to idle. The
+    /// default is zero, which records the exact times.
+    ///
+    /// Every read of an entry records its access time, which costs an atomic
+    /// store per read. With a tick, the access times are rounded up to the next
+    /// multiple of the tick, so the reads of an entry within the same tick record
+    /// the time only once. In exchange, an entry may expire by `time_to_idle` up
+    /// to one tick later than it would without the tick. It never expires
+    /// earlier. The write times, and so `time_to_live` and `max_age`, are not
+    /// affected.
+    ///
+    /// The tick should be a small fraction of the time to idle.
+    /// [`try_build`](#method.try_build) returns
+    /// [`BuildError::ExpirationTickTooLong`][tick-too-long] if it is longer than
+    /// half of `time_to_live` or `time_to_idle`. Note that the time to idle of an
+    /// individual entry set by `insert_with_tti` is not checked.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// // Accept expiring idle entries up to 1 second late.
+    /// let cache = Cache::builder()
+    ///     .max_capacity(10_000)
+    ///     .time_to_idle(Duration::from_secs(5 * 60))
+    ///     .expiration_tick(Duration::from_secs(1))
+    ///     .try_build()
+    ///     .unwrap();
+    /// # cache.insert(0, "zero");
+    /// ```
+    ///
+    /// [tick-too-long]: ../enum.BuildError.html#variant.ExpirationTickTooLong
+    pub fn expiration_tick(self, tick: Duration) -> Self {
+        Self {
+            expiration_tick: Some(tick),
+            ..self
+        }
+    }
+
+    /// Randomizes the time to live of every entry by up to `fraction` of it, e.g.
+    /// `0.1` for ±10%. By default, this is disabled and all entries live exactly
+    /// for the time to live.
+    ///
+    /// Entries inserted at the same time, e.g. when warming the cache at startup,
+    /// otherwise all expire at the same time, and the resulting burst of misses
+    /// may overload the source of the values. With a jitter, their expirations
+    /// are spread over `(1 - fraction) × time_to_live` to
+    /// `(1 + fraction) × time_to_live` after the insert.
+    ///
+    /// The randomized time to live is drawn once when the key is inserted, and
+    /// kept by the updates of its value, so the entry has a stable spread over
+    /// its lifetime. It never exceeds 1000 years. The time to idle is not
+    /// randomized. [`entries_by_expiration`][entries-by-exp] returns the
+    /// instants when the entries will expire, including the jitter.
+    ///
+    /// The housekeeping removes the expired entries in the order of their last
+    /// insert. An entry that expires earlier than the ones inserted before it is
+    /// no longer returned by the read methods, but it may stay in the cache (and
+    /// count toward its capacity) until they expire, i.e. up to
+    /// `2 × fraction × time_to_live` later.
+    ///
+    /// This option has no effect without a time to live.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// // Expire the entries between 54 and 66 minutes after the insert.
+    /// let cache = Cache::builder()
+    ///     .max_capacity(10_000)
+    ///     .time_to_live(Duration::from_secs(3600))
+    ///     .expiration_jitter(0.1)
+    ///     .build();
+    /// # cache.insert(0, "zero");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not at least `0.0` and less than `1.0`.
+    ///
+    /// [entries-by-exp]: ./struct.Cache.html#method.entries_by_expiration
+    pub fn expiration_jitter(self, fraction: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&fraction),
+            "expiration_jitter must be at least 0.0 and less than 1.0"
+        );
+        Self {
+            expiration_jitter: Some(fraction),
+            ..self
+        }
+    }
+
+    /// Sets the minimum interval between the recorded accesses of an entry. By
+    /// default, this is disabled and every read is recorded.
+    ///
+    /// Every read of an entry pushes a record to the read buffer, which the
+    /// maintenance applies to the access order, the frequency sketch and the
+    /// access time used by `time_to_idle`. With a throttle, a read of an entry
+    /// whose last recorded access is within the throttle is not recorded. This
+    /// cuts the read overhead of the keys hit over and over, and it still counts
+    /// in the [statistics](#method.record_stats) and calls the
+    /// [access listener](#method.access_listener).
+    ///
+    /// In exchange, the tracking of the entries is coarser:
+    ///
+    /// - An entry may expire by `time_to_idle` up to one throttle _earlier_ than
+    ///   it would without the throttle, as its last reads may not be recorded.
+    ///   The throttle should be a small fraction of the time to idle.
+    /// - The recency and the frequency of a hot entry are underestimated, so the
+    ///   eviction may pick it a bit more readily than without the throttle.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// // Record at most one access per entry every 100 milliseconds.
+    /// let cache = Cache::builder()
+    ///     .max_capacity(10_000)
+    ///     .time_to_idle(Duration::from_secs(5 * 60))
+    ///     .access_record_throttle(Duration::from_millis(100))
+    ///     .build();
+    /// # cache.insert(0, "zero");
+    /// ```
+    pub fn access_record_throttle(self, throttle: Duration) -> Self {
+        Self {
+            access_record_throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Sets the limit of the weighted size of the cache plus the weight reserved
+    /// by the loads in progress, above which
+    /// [`get_with_reservation`][get-with-reservation] sheds new loads. By
+    /// default, there is no limit and loads are never shed.
+    ///
+    /// The mark is in the unit of the [weigher](#method.weigher), or a number of
+    /// entries for a cache without a weigher. It should be somewhat above the max
+    /// capacity, as a full cache is a normal state that the eviction keeps in
+    /// check; the mark bounds the transient excess of many large values arriving
+    /// at once. For a `SegmentedCache`, the mark is divided evenly across the
+    /// segments.
+    ///
+    /// [get-with-reservation]: ./struct.Cache.html#method.get_with_reservation
+    pub fn reservation_high_water_mark(self, high_water_mark: u64) -> Self {
+        Self {
+            reservation_high_water_mark: Some(high_water_mark),
+            ..self
+        }
+    }
+
+    /// Sets where the cache drops the values it removes. The default is
+    /// [`DropContext::Inline`][inline], which drops a value wherever the cache
+    /// releases it.
+    ///
+    /// With the default, a value whose `Drop` is slow (e.g. it closes a
+    /// connection or frees a large buffer) slows down whatever thread happens to
+    /// drop it: the caller of `insert` or `invalidate`, or the maintenance, which
+    /// may run on the caller of any cache method. Routing the drops elsewhere
+    /// keeps that cost off those threads:
+    ///
+    /// - [`DropContext::EvictionListener`][listener] drops the values on the
+    ///   housekeeper threads, along with the calls to the eviction listener.
+    /// - [`DropContext::Worker`][worker] drops the values on a thread dedicated to
+    ///   this cache.
+    ///
+    /// Either way, every dropped value is moved to the other thread, which costs
+    /// a boxed job (housekeeper) or a channel send (worker) per value, and the
+    /// values are dropped a little after they are removed. The queue to the other
+    /// thread is unbounded, so if the values are removed faster than they can be
+    /// dropped, they pile up in memory. Only choose these for values whose drop
+    /// is costly.
+    ///
+    /// This only applies to the values held by the cache, not to the clones
+    /// returned by `get`.
+    ///
+    /// [inline]: ../notification/enum.DropContext.html#variant.Inline
+    /// [listener]: ../notification/enum.DropContext.html#variant.EvictionListener
+    /// [worker]: ../notification/enum.DropContext.html#variant.Worker
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::{notification::DropContext, sync::Cache};
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .drop_values_on(DropContext::Worker)
+    ///     .build();
+    /// cache.insert(0, vec![0u8; 1024]);
+    /// ```
+    pub fn drop_values_on(self, context: DropContext) -> Self {
+        Self {
+            drop_context: context,
+            ..self
+        }
+    }
+
+    /// Sets the minimum interval between the maintenance passes run by the
+    /// housekeeper. The default is zero, which runs a pass as soon as enough
+    /// reads or writes have been recorded, and also every 0.3 seconds.
+    ///
+    /// A maintenance pass applies the recorded reads and writes to the eviction
+    /// policy, and removes the expired and evicted entries. Under a very high
+    /// access rate, the passes may run so often that they add a noticeable
+    /// overhead. With an interval, a pass does not start until the interval has
+    /// passed since the last pass started, regardless of the access rate, and
+    /// the work is batched into fewer passes.
+    ///
+    /// The tradeoff is latency: the evictions and the expirations may be applied
+    /// up to one interval late, so the cache may temporarily exceed its max
+    /// capacity. When the buffer of the recorded writes fills up before the
+    /// next pass, the writes wait for it. So keep the interval short, e.g. a few
+    /// milliseconds.
+    ///
+    /// Calling `sync` of [`ConcurrentCacheExt`][concurrent-cache-ext] runs a
+    /// pass immediately, regardless of the interval.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(10_000)
+    ///     .maintenance_interval(Duration::from_millis(5))
+    ///     .build();
+    /// # cache.insert(0, "zero");
+    /// ```
+    ///
+    /// [concurrent-cache-ext]: ./trait.ConcurrentCacheExt.html
+    pub fn maintenance_interval(self, interval: Duration) -> Self {
+        Self {
+            maintenance_interval: Some(interval),
+            ..self
+        }
+    }
+
     /// Enables support for [Cache::invalidate_entries_if][cache-invalidate-if]
     /// method.
     ///
@@ -293,6 +1615,244 @@ impl<K, V, C> CacheBuilder<K, V, C> {
             ..self
         }
     }
+
+    /// Enables recording of the cache statistics, which can be retrieved by
+    /// [`Cache::stats`][cache-stats] method.
+    ///
+    /// The hit and miss counts are always recorded. With the `hdrhistogram` crate
+    /// feature, the latencies of `get` and `insert` are also recorded to
+    /// histograms, so that you can see when the cache itself is slowed down by
+    /// lock contention or by the housekeeper.
+    ///
+    /// To keep the overhead low, only one in every 16 operations is timed. A
+    /// sample is also dropped if another thread is recording to the same
+    /// histogram at that moment, rather than making the cache operation wait for
+    /// it. The recorded latencies do not include the time spent in init closures
+    /// of `get_or_insert_with` and its variants.
+    ///
+    /// With the `hyperloglog` crate feature, the number of distinct keys inserted
+    /// to the cache is also estimated. See
+    /// [`CacheStats::distinct_keys_estimate`][distinct-keys].
+    ///
+    /// The contentions of the internal locks are also counted. Each lock is first
+    /// tried without blocking, and only a failed try, which is about to wait
+    /// anyway, increments a counter. See
+    /// [`SegmentedCache::contention_stats`][contention-stats].
+    ///
+    /// [cache-stats]: ./struct.Cache.html#method.stats
+    /// [contention-stats]: ./struct.SegmentedCache.html#method.contention_stats
+    /// [distinct-keys]: ../struct.CacheStats.html#method.distinct_keys_estimate
+    pub fn record_stats(self) -> Self {
+        Self {
+            stats_enabled: true,
+            ..self
+        }
+    }
+
+    /// Hints that the cache is small, so that it can use a simpler eviction
+    /// policy.
+    ///
+    /// If the max capacity is below 32 entries and no weigher is set, the cache
+    /// will track the entries with a single LRU (Least Recently Used) queue, and
+    /// will not allocate or update the frequency sketch of the TinyLFU admission
+    /// policy. A new entry is always admitted by evicting the least recently used
+    /// entry. This lowers the memory footprint and the per-operation overhead
+    /// for tiny caches, at the cost of a somewhat lower hit rate for workloads
+    /// where a frequently used entry is outlived by a burst of one-off entries.
+    ///
+    /// The hint is ignored when the max capacity is 32 or above, or when the
+    /// cache is unbounded or has a weigher. For a `SegmentedCache`, the threshold
+    /// applies to the max capacity of each segment.
+    pub fn small(self) -> Self {
+        Self {
+            small_cache_hint: true,
+            ..self
+        }
+    }
+
+    /// Makes the cache protect the entries that have been read `hits` times,
+    /// so that they are not evicted by a burst of new entries.
+    ///
+    /// By default, the cache keeps all admitted entries in a single LRU
+    /// (probation) queue, and evicts from its least recently used end. With this
+    /// option, an entry read `hits` times while in the probation queue is
+    /// promoted to a second (protected) queue. Eviction only takes from the
+    /// probation queue, so a promoted entry is not evicted until it is demoted
+    /// back. The protected queue holds up to 80% of the entries; when it grows
+    /// beyond that, its least recently used entries are demoted to the probation
+    /// queue and have to earn their `hits` again.
+    ///
+    /// A `hits` of 1 protects an entry on its first re-access. A larger value
+    /// keeps entries that are read only a few times in a row (e.g. by a scan)
+    /// from pushing the truly hot entries out of the protected queue.
+    ///
+    /// The option is ignored when the cache uses the plain LRU policy of
+    /// [`small`](#method.small).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hits` is 0.
+    pub fn promote_after_hits(self, hits: u8) -> Self {
+        assert!(hits != 0, "promote_after_hits must be greater than 0");
+        Self {
+            promote_after_hits: Some(hits),
+            ..self
+        }
+    }
+
+    /// Enables a small per-thread read cache holding up to `capacity` of the most
+    /// recently read entries.
+    ///
+    /// `get` will consult the read cache of the calling thread first, and fall
+    /// back to the shared cache only on a miss. This avoids the cost of probing
+    /// the shared hash table and recording the read, and will be effective for
+    /// read-dominated workloads where the same thread keeps reading a few hot
+    /// keys.
+    ///
+    /// # Coherence
+    ///
+    /// The cache maintains a write generation that is advanced by every `insert`,
+    /// `invalidate`, `invalidate_all`, `invalidate_entries_if`, and by eviction
+    /// and expiration performed by the housekeeper. A thread local read cache is
+    /// cleared as soon as it observes a new generation, so it never returns a
+    /// value that has been overwritten or invalidated.
+    ///
+    /// However, an entry that has passed its `time_to_live` or `time_to_idle` can
+    /// still be returned from a thread local read cache until the housekeeper
+    /// actually removes the entry, which happens within a fraction of a second.
+    /// Also, reads served by a thread local read cache are not recorded to the
+    /// shared cache, so they will neither extend the `time_to_idle` of the entry
+    /// nor increase its popularity for the eviction policy.
+    ///
+    /// Each thread allocates its read cache on the first `get`. A read cache of a
+    /// dropped `Cache` will be released when the thread ends or creates another
+    /// read cache.
+    pub fn thread_local_cache(self, capacity: usize) -> Self {
+        Self {
+            thread_local_cache_capacity: Some(capacity),
+            ..self
+        }
+    }
+}
+
+impl<K, T, C> CacheBuilder<K, Weak<T>, C> {
+    /// Makes the cache a non-owning index of objects held in `Arc`s, by caching
+    /// `Weak` references to them and forgetting the references whose objects have
+    /// been dropped.
+    ///
+    /// A `Weak` that no longer upgrades is treated as absent: `get` and its
+    /// variants miss it, and use [`Cache::get_upgraded`][get-upgraded] to get the
+    /// `Arc` directly and remove a dead entry at once. The maintenance visits a
+    /// batch of entries on every run, and removes the dead ones. The eviction
+    /// listener is notified of the removed entries with
+    /// `RemovalCause::Collected`, and given the dead `Weak` as the value, as the
+    /// object no longer exists. The weight of an entry is the one given by the
+    /// weigher when the `Weak` was inserted, until the entry is removed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::sync::Arc;
+    ///
+    /// let cache = Cache::builder().max_capacity(100).weak_values().build();
+    ///
+    /// let session = Arc::new("session");
+    /// cache.insert(1, Arc::downgrade(&session));
+    /// assert_eq!(cache.get_upgraded(&1), Some(Arc::clone(&session)));
+    ///
+    /// // The cache does not keep the session alive.
+    /// drop(session);
+    /// assert_eq!(cache.get_upgraded(&1), None);
+    /// ```
+    ///
+    /// [get-upgraded]: ./struct.Cache.html#method.get_upgraded
+    pub fn weak_values(self) -> Self {
+        Self {
+            collection_check: Some(|value| value.strong_count() == 0),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    V: zeroize::Zeroize,
+{
+    /// Makes the cache zeroize values when it drops them.
+    ///
+    /// A value is zeroized when its entry is physically dropped from the cache:
+    /// on eviction, expiration, `invalidate*` and replacement by `insert`, and
+    /// when the cache itself is dropped. Note that the internal concurrent hash
+    /// table defers dropping removed entries until no other thread can be reading
+    /// them, so a removed value may stay in memory for a short while.
+    ///
+    /// This does not cover the clones of the value returned by `get`, and the
+    /// clones held by the thread local read cache (see `thread_local_cache`).
+    /// Wrap the value in `zeroize::Zeroizing` or store `Arc`s to a zeroizing type
+    /// so that those clones are also wiped when they are dropped.
+    ///
+    /// Requires the `zeroize` crate feature.
+    pub fn zeroize_values_on_removal(self) -> Self {
+        Self {
+            value_zeroizer: Some(<V as zeroize::Zeroize>::zeroize),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    K: std::fmt::Debug,
+{
+    /// Makes the cache emit a `tracing` event at the `DEBUG` level for every
+    /// `get`, `insert` and removal of the keys matching the predicate. This is a
+    /// debugging aid to see why a particular key is or is not cached, without
+    /// tracing every operation of the cache.
+    ///
+    /// The events have the following messages and fields, in addition to the
+    /// `cache` name and the `key` formatted by `Debug`:
+    ///
+    /// - `traced key read`: `outcome` is `hit`, `miss` (not in the cache) or
+    ///   `stale` (expired or invalidated), and `age` is the time since the entry
+    ///   was last written.
+    /// - `traced key written`: `outcome` is `inserted`, `replaced` or `occupied`
+    ///   (`insert_if_absent` kept the existing value).
+    /// - `traced key removed`: `cause` is the [`RemovalCause`][removal-cause],
+    ///   e.g. `size` for an entry evicted or rejected by the admission policy.
+    ///
+    /// The predicate is called for every key read, written and removed, so it
+    /// should be cheap. The overhead of formatting and emitting the events is
+    /// proportional to the number of traced keys.
+    ///
+    /// Requires the `tracing` crate feature.
+    ///
+    /// [removal-cause]: ../notification/enum.RemovalCause.html
+    pub fn trace_keys(self, predicate: impl Fn(&K) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            key_tracer: Some(Arc::new(move |key: &K| {
+                if predicate(key) {
+                    Some(format!("{:?}", key))
+                } else {
+                    None
+                }
+            })),
+            ..self
+        }
+    }
+
+    /// Makes the cache emit `tracing` events for the key. A shorthand for
+    /// [`trace_keys`](#method.trace_keys) with an equality check.
+    ///
+    /// Requires the `tracing` crate feature.
+    pub fn trace_key(self, key: K) -> Self
+    where
+        K: Eq + Send + Sync + 'static,
+    {
+        self.trace_keys(move |k| *k == key)
+    }
 }
 
 #[cfg(test)]
@@ -310,6 +1870,7 @@ mod tests {
         assert_eq!(cache.time_to_live(), None);
         assert_eq!(cache.time_to_idle(), None);
         assert_eq!(cache.num_segments(), 1);
+        assert_eq!(cache.name(), None);
 
         cache.insert('a', "Alice");
         assert_eq!(cache.get(&'a'), Some("Alice"));
@@ -317,9 +1878,11 @@ mod tests {
         let cache = CacheBuilder::new(100)
             .time_to_live(Duration::from_secs(45 * 60))
             .time_to_idle(Duration::from_secs(15 * 60))
+            .name("sessions")
             .build();
 
         assert_eq!(cache.max_capacity(), Some(100));
+        assert_eq!(cache.name(), Some("sessions"));
         assert_eq!(cache.time_to_live(), Some(Duration::from_secs(45 * 60)));
         assert_eq!(cache.time_to_idle(), Some(Duration::from_secs(15 * 60)));
         assert_eq!(cache.num_segments(), 1);
@@ -356,6 +1919,208 @@ mod tests {
         assert_eq!(cache.get(&'b'), Some("Bob"));
     }
 
+    #[test]
+    fn max_capacity_memory_fraction() {
+        use crate::BuildError;
+
+        let result = CacheBuilder::<char, String, _>::default()
+            .max_capacity_memory_fraction(0.1)
+            .try_build();
+        assert_eq!(result.err(), Some(BuildError::MemoryFractionWithoutWeigher));
+
+        let result = CacheBuilder::default()
+            .weigher(|_k: &char, v: &String| v.len() as u32)
+            .max_capacity_memory_fraction(0.1)
+            .segments(2)
+            .try_build();
+        if cfg!(target_os = "linux") {
+            assert!(result.unwrap().max_capacity().unwrap() > 0);
+        } else {
+            assert_eq!(result.err(), Some(BuildError::TotalMemoryUnknown));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "max_capacity_memory_fraction must be greater than 0.0")]
+    fn max_capacity_memory_fraction_out_of_range() {
+        let _ = CacheBuilder::<char, String, _>::default().max_capacity_memory_fraction(1.0);
+    }
+
+    #[test]
+    fn max_allowed_ttl() {
+        use crate::{defaults::TtlCapPolicy, BuildError};
+
+        let hour = Duration::from_secs(3600);
+        // Set the field as the defaults would, without touching the process-level
+        // defaults shared by the other tests.
+        let builder = |ttl: Option<Duration>, policy| {
+            let builder = CacheBuilder::<char, String, _> {
+                max_allowed_ttl: Some((hour, policy)),
+                ..CacheBuilder::new(100)
+            };
+            match ttl {
+                Some(ttl) => builder.time_to_live(ttl),
+                None => builder,
+            }
+        };
+
+        let cache = builder(Some(hour * 2), TtlCapPolicy::Clamp).build();
+        assert_eq!(cache.time_to_live(), Some(hour));
+        let cache = builder(None, TtlCapPolicy::Clamp).segments(2).build();
+        assert_eq!(cache.time_to_live(), Some(hour));
+        let cache = builder(Some(hour / 2), TtlCapPolicy::Clamp).build();
+        assert_eq!(cache.time_to_live(), Some(hour / 2));
+
+        let cache = builder(Some(hour), TtlCapPolicy::Reject).try_build();
+        assert_eq!(cache.unwrap().time_to_live(), Some(hour));
+        let result = builder(Some(hour * 2), TtlCapPolicy::Reject).try_build();
+        assert_eq!(result.err(), Some(BuildError::TimeToLiveNotAllowed));
+        let result = builder(None, TtlCapPolicy::Reject).segments(2).try_build();
+        assert_eq!(result.err(), Some(BuildError::TimeToLiveNotAllowed));
+    }
+
+    #[test]
+    #[should_panic(expected = "time_to_live must be set")]
+    fn max_allowed_ttl_rejected_by_build() {
+        use crate::defaults::TtlCapPolicy;
+
+        let builder = CacheBuilder::<char, String, _> {
+            max_allowed_ttl: Some((Duration::from_secs(3600), TtlCapPolicy::Reject)),
+            ..CacheBuilder::new(100)
+        };
+        let _ = builder.build();
+    }
+
+    #[test]
+    fn try_build_cache() {
+        use crate::BuildError;
+
+        let weigher = |_k: &char, v: &String| v.len() as u32;
+
+        // Unbounded, with or without a weigher.
+        let cache = CacheBuilder::<char, String, _>::default()
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), None);
+        let cache = CacheBuilder::default()
+            .weigher(weigher)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), None);
+
+        // A number of entries without a weigher.
+        let cache = CacheBuilder::<char, String, _>::new(100)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), Some(100));
+
+        // A total weight with a weigher.
+        let cache = CacheBuilder::default()
+            .weigher(weigher)
+            .max_weighted_capacity(1000)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), Some(1000));
+        let cache = CacheBuilder::default()
+            .weigher(weigher)
+            .max_weighted_capacity(1000)
+            .segments(4)
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), Some(1000));
+
+        // Ambiguous combinations.
+        let result = CacheBuilder::new(100).weigher(weigher).try_build();
+        assert_eq!(result.err(), Some(BuildError::EntryCapacityWithWeigher));
+        let result = CacheBuilder::<char, String, _>::default()
+            .max_weighted_capacity(1000)
+            .segments(4)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuildError::WeightedCapacityWithoutWeigher)
+        );
+
+        // `build` keeps interpreting `max_capacity` as the total weight.
+        let cache = CacheBuilder::new(100).weigher(weigher).build();
+        assert_eq!(cache.max_capacity(), Some(100));
+
+        // An expiration tick up to half of the shortest expiration.
+        let builder = || {
+            CacheBuilder::<char, String, _>::new(100)
+                .time_to_live(Duration::from_secs(60))
+                .time_to_idle(Duration::from_secs(10))
+        };
+        let result = builder()
+            .expiration_tick(Duration::from_secs(5))
+            .try_build();
+        assert!(result.is_ok());
+        let result = builder()
+            .expiration_tick(Duration::from_millis(5001))
+            .segments(4)
+            .try_build();
+        assert_eq!(result.err(), Some(BuildError::ExpirationTickTooLong));
+
+        // A time to idle up to the time to live.
+        let builder = |tti| {
+            CacheBuilder::<char, String, _>::new(100)
+                .time_to_live(Duration::from_secs(60))
+                .time_to_idle(tti)
+        };
+        assert!(builder(Duration::from_secs(60)).try_build().is_ok());
+        let result = builder(Duration::from_secs(61)).try_build();
+        assert_eq!(result.err(), Some(BuildError::TtiExceedsTtl));
+        let result = builder(Duration::from_secs(61)).segments(4).try_build();
+        assert_eq!(result.err(), Some(BuildError::TtiExceedsTtl));
+    }
+
+    #[test]
+    fn segment_capacities() {
+        use crate::BuildError;
+
+        let cache = CacheBuilder::<char, String, _>::new(100)
+            .segments(4)
+            .segment_capacities(vec![70, 10, 10, 10])
+            .try_build()
+            .unwrap();
+        assert_eq!(cache.max_capacity(), Some(100));
+        assert_eq!(
+            cache.segment_max_capacities(),
+            vec![Some(70), Some(10), Some(10), Some(10)]
+        );
+
+        // Without them, the max capacity is divided evenly.
+        let cache = CacheBuilder::<char, String, _>::new(100)
+            .segments(4)
+            .build();
+        assert_eq!(cache.segment_max_capacities(), vec![Some(25); 4]);
+
+        // The number of the segments is rounded up to a power of two.
+        let result = CacheBuilder::<char, String, _>::default()
+            .segments(3)
+            .segment_capacities(vec![10, 10, 10])
+            .try_build();
+        assert_eq!(result.err(), Some(BuildError::SegmentCapacitiesMismatch));
+    }
+
+    #[test]
+    #[should_panic(expected = "segment_capacities must have one capacity per segment")]
+    fn build_cache_segment_capacities_mismatch() {
+        let _ = CacheBuilder::<char, String, _>::default()
+            .segments(2)
+            .segment_capacities(vec![10])
+            .build();
+    }
+
+    #[test]
+    #[should_panic(expected = "eviction_listener_batched cannot be used with eviction_listener")]
+    fn build_cache_with_both_eviction_listeners() {
+        let _ = CacheBuilder::<char, String, _>::new(100)
+            .eviction_listener(|_k, _v, _cause| ())
+            .eviction_listener_batched(10, |_batch| ())
+            .build();
+    }
+
     #[test]
     #[should_panic(expected = "time_to_live is longer than 1000 years")]
     fn build_cache_too_long_ttl() {
@@ -377,4 +2142,13 @@ mod tests {
             .time_to_idle(duration + Duration::from_secs(1))
             .build();
     }
+
+    #[test]
+    #[should_panic(expected = "time_to_idle must not be longer than time_to_live")]
+    fn build_cache_tti_exceeds_ttl() {
+        let _ = CacheBuilder::<char, String, _>::new(100)
+            .time_to_live(Duration::from_secs(60))
+            .time_to_idle(Duration::from_secs(61))
+            .build();
+    }
 }
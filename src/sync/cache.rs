@@ -1,18 +1,36 @@
 use super::{
     base_cache::{BaseCache, HouseKeeperArc, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS},
+    batch::{BatchOp, BatchOps},
+    cache_config::CacheConfig,
+    coherence::Coherence,
+    frozen_view::{FrozenView, DEFAULT_MAX_FREEZE_DURATION},
     housekeeper::InnerSync,
-    value_initializer::ValueInitializer,
-    CacheBuilder, ConcurrentCacheExt, PredicateId, Weigher, WriteOp,
+    key_handle::KeyHandle,
+    key_lock::KeyLockMap,
+    miss_claim::{Claim, ClaimState, MissClaim, MissClaims, MissToken, MissWaiter},
+    prefetcher::Prefetcher,
+    snapshot_handle::SnapshotHandle,
+    swap_lock::SwapLock,
+    thread_local_cache::ThreadLocalCache,
+    value_initializer::{LoadRole, ValueInitializer},
+    BorrowedKey, CacheBuilder, ConcurrentCacheExt, Loader, PredicateId, WriteOp,
 };
-use crate::{sync::value_initializer::InitResult, PredicateError};
+use crate::{
+    common::stats::OpKind, sync::value_initializer::InitResult, CacheStats, CasError, Entry,
+    FallbackError, LoadError, OccupiedError, PredicateError,
+};
+
+#[cfg(feature = "serde")]
+use crate::policy::PolicyState;
 
-use crossbeam_channel::{Sender, TrySendError};
+use crossbeam_channel::TrySendError;
 use std::{
     any::TypeId,
     borrow::Borrow,
     collections::hash_map::RandomState,
     hash::{BuildHasher, Hash},
-    sync::Arc,
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::{Arc, Weak},
     time::Duration,
 };
 
@@ -208,6 +226,23 @@ use std::{
 ///
 /// [once-cell-crate]: https://crates.io/crates/once_cell
 ///
+/// # Unwind Safety
+///
+/// `Cache<K, V, S>` implements `UnwindSafe` and `RefUnwindSafe` when `K`, `V` and
+/// `S` implement `RefUnwindSafe`, so it can be used across `catch_unwind` without
+/// `AssertUnwindSafe`.
+///
+/// A panic in a cache operation, e.g. from the hasher, the weigher or a closure
+/// given to the cache, does not leave the cache in an unsafe or unusable state.
+/// The locks taken by the operation are released, and a `get_or_insert_with`
+/// call waiting for the panicked one loads the value itself. However, if the
+/// panic happens while the maintenance applies an insert, the inserted entry may
+/// be left untracked by the eviction policy until it is updated or invalidated.
+/// Such an entry is not evicted to keep the cache within its max capacity, nor
+/// removed when it expires, though `get` does not return it after it expired. A
+/// panic in the housekeeper thread is caught and does not stop the future
+/// maintenance.
+///
 /// # Hashing Algorithm
 ///
 /// By default, `Cache` uses a hashing algorithm selected to provide resistance
@@ -227,10 +262,38 @@ use std::{
 /// [build-with-hasher-method]: ./struct.CacheBuilder.html#method.build_with_hasher
 /// [ahash-crate]: https://crates.io/crates/ahash
 ///
-#[derive(Clone)]
 pub struct Cache<K, V, S = RandomState> {
     base: BaseCache<K, V, S>,
     value_initializer: Arc<ValueInitializer<K, V, S>>,
+    miss_claims: Arc<MissClaims<K, Claim<V>, S>>,
+    key_locks: Arc<KeyLockMap<K, S>>,
+    swap_lock: Arc<SwapLock>,
+    thread_local_cache: Option<Arc<ThreadLocalCache<K, V>>>,
+    coherence: Option<Coherence<K>>,
+    loader: Option<Loader<K, V>>,
+    prefetcher: Arc<Prefetcher>,
+    config: Arc<CacheConfig<K, V, S>>,
+}
+
+impl<K, V, S> Clone for Cache<K, V, S> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+            value_initializer: Arc::clone(&self.value_initializer),
+            miss_claims: Arc::clone(&self.miss_claims),
+            key_locks: Arc::clone(&self.key_locks),
+            swap_lock: Arc::clone(&self.swap_lock),
+            thread_local_cache: self.thread_local_cache.as_ref().map(Arc::clone),
+            coherence: self.coherence.clone(),
+            loader: self.loader.as_ref().map(Arc::clone),
+            prefetcher: Arc::clone(&self.prefetcher),
+            config: Arc::clone(&self.config),
+        }
+    }
 }
 
 // TODO: https://github.com/moka-rs/moka/issues/54
@@ -251,6 +314,25 @@ where
 {
 }
 
+// See the unwind safety impls of `BaseCache`. The other parts release their
+// locks by guards, and resolve the claims and the loads left by a panicking
+// caller in `Drop`, so that the waiters get `LoadError::OwnerPanicked` or retry.
+impl<K, V, S> UnwindSafe for Cache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
+impl<K, V, S> RefUnwindSafe for Cache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
 impl<K, V> Cache<K, V, RandomState>
 where
     K: Hash + Eq + Send + Sync + 'static,
@@ -263,16 +345,10 @@ where
     ///
     /// [builder-struct]: ./struct.CacheBuilder.html
     pub fn new(max_capacity: u64) -> Self {
-        let build_hasher = RandomState::default();
-        Self::with_everything(
-            Some(max_capacity),
-            None,
-            build_hasher,
-            None,
-            None,
-            None,
-            false,
-        )
+        Self::with_config(CacheConfig {
+            max_capacity: Some(max_capacity),
+            ..CacheConfig::new(RandomState::default())
+        })
     }
 
     /// Returns a [`CacheBuilder`][builder-struct], which can builds a `Cache` or
@@ -290,29 +366,78 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn with_everything(
-        max_capacity: Option<u64>,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
-        weigher: Option<Weigher<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
-    ) -> Self {
+    pub(crate) fn with_config(config: CacheConfig<K, V, S>) -> Self {
+        let build_hasher = &config.build_hasher;
         Self {
-            base: BaseCache::new(
-                max_capacity,
-                initial_capacity,
-                build_hasher.clone(),
-                weigher,
-                time_to_live,
-                time_to_idle,
-                invalidator_enabled,
-            ),
-            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher)),
+            base: BaseCache::new(&config),
+            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher.clone())),
+            miss_claims: Arc::new(MissClaims::with_hasher(build_hasher.clone())),
+            key_locks: Arc::new(KeyLockMap::with_hasher(build_hasher.clone())),
+            swap_lock: Arc::new(SwapLock::default()),
+            thread_local_cache: config
+                .thread_local_cache_capacity
+                .filter(|cap| *cap > 0)
+                .map(|cap| Arc::new(ThreadLocalCache::new(cap))),
+            coherence: config.coherence.clone(),
+            loader: config.loader.as_ref().map(Arc::clone),
+            prefetcher: Arc::new(Prefetcher::default()),
+            config: Arc::new(config),
         }
     }
 
+    pub(crate) fn housekeeper(&self) -> Option<&HouseKeeperArc<K, V, S>> {
+        self.base.housekeeper.as_ref()
+    }
+
+    /// Creates a new, independent `Cache` with the same configuration as this
+    /// cache, and fills it with the given entries.
+    ///
+    /// This is intended for the "periodic full refresh" pattern, where a cache is
+    /// rebuilt from a snapshot in the background and then swapped in for the
+    /// readers at once. The returned cache is not shared with anybody yet, so this
+    /// method applies the entries directly to the internal data structures,
+    /// instead of going through the write buffer as `insert` does. The popularity
+    /// estimator is not touched by these inserts, so the new cache starts without
+    /// any access history.
+    ///
+    /// If the entries exceed the `max_capacity`, the earlier entries are kept and
+    /// the rest are rejected. If the iterator yields the same key more than once,
+    /// the last value wins.
+    ///
+    /// # Example
+    ///
+    /// The new cache can be swapped in with a lock, or without blocking the
+    /// readers by using a crate like [arc-swap][arc-swap-crate].
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::sync::{Arc, RwLock};
+    ///
+    /// let shared = RwLock::new(Arc::new(Cache::new(100)));
+    /// shared.read().unwrap().insert(1, "one");
+    ///
+    /// // Rebuild the cache from a snapshot and swap it in.
+    /// let snapshot = vec![(1, "uno"), (2, "dos")];
+    /// let rebuilt = shared.read().unwrap().rebuilt_from(snapshot);
+    /// *shared.write().unwrap() = Arc::new(rebuilt);
+    ///
+    /// let cache = Arc::clone(&shared.read().unwrap());
+    /// assert_eq!(cache.get(&1), Some("uno"));
+    /// assert_eq!(cache.get(&2), Some("dos"));
+    /// ```
+    ///
+    /// [arc-swap-crate]: https://crates.io/crates/arc-swap
+    pub fn rebuilt_from(&self, entries: impl IntoIterator<Item = (K, V)>) -> Self {
+        let entries = entries.into_iter();
+        let cache = Self::with_config(CacheConfig {
+            initial_capacity: Some(entries.size_hint().0),
+            ..CacheConfig::clone(&self.config)
+        });
+        cache.base.continue_versions_from(&self.base);
+        cache.base.insert_all_cold(entries);
+        cache
+    }
+
     /// Returns a _clone_ of the value corresponding to the key.
     ///
     /// If you want to store values that will be expensive to clone, wrap them by
@@ -322,21 +447,233 @@ where
     /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
     /// on the borrowed form _must_ match those for the key type.
     ///
+    /// If the cache was built with
+    /// [`CacheBuilder::thread_local_cache`][thread-local-cache], the value may be
+    /// returned from the thread local read cache of the calling thread.
+    ///
     /// [rustdoc-std-arc]: https://doc.rust-lang.org/stable/std/sync/struct.Arc.html
+    /// [thread-local-cache]: ./struct.CacheBuilder.html#method.thread_local_cache
     pub fn get<Q>(&self, key: &Q) -> Option<V>
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.base.normalize_borrowed_key(key) {
+            return self.get_with_hash::<K>(&key, self.base.hash::<K>(&key));
+        }
+        self.get_with_hash(key, self.base.hash(key))
+    }
+
+    /// Returns a copy of the value corresponding to the key.
+    ///
+    /// This is the same as [`get`](#method.get) for a `Copy` value type such as
+    /// `u64`. `get` does not clone the value through any hidden indirection:
+    /// the value is stored inline in the cache entry, and cloning a `Copy` value
+    /// is a plain copy without allocation. This method only makes it explicit at
+    /// the call site that the read is a copy.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let counters: Cache<&str, u64> = Cache::new(100);
+    /// counters.insert("requests", 42);
+    /// assert_eq!(counters.get_copied(&"requests"), Some(42));
+    /// ```
+    pub fn get_copied<Q>(&self, key: &Q) -> Option<V>
+    where
+        V: Copy,
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, like `map[key]`
+    /// of a `HashMap`.
+    ///
+    /// This is the same as [`get`](#method.get) followed by `expect`, for the
+    /// callers that have made sure the key is present. A cached value may still
+    /// be evicted, expire or be invalidated at any time, so prefer `get` or
+    /// [`fetch_or`](#method.fetch_or) unless the absence of the value is a bug.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache has no value for the key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("config", "v1");
+    /// assert_eq!(cache.fetch(&"config"), "v1");
+    /// ```
+    #[track_caller]
+    pub fn fetch<Q>(&self, key: &Q) -> V
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key)
+            .expect("Cache::fetch: The cache has no value for the key")
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or `default` if
+    /// the cache has no value for the key. The `default` is not inserted.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("retries", 5);
+    /// assert_eq!(cache.fetch_or(&"retries", 3), 5);
+    /// assert_eq!(cache.fetch_or(&"timeout", 30), 30);
+    /// ```
+    pub fn fetch_or<Q>(&self, key: &Q, default: V) -> V
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, together with the
+    /// version of the entry.
+    ///
+    /// Every entry has a version, which is replaced with a larger one whenever the
+    /// value of the key is replaced, e.g. by `insert`. The versions are never
+    /// reused, even after the key is invalidated and inserted again. So you can
+    /// pass the version to [`insert_if_version`](#method.insert_if_version) to
+    /// update the value only if nobody else has updated it since you read it.
+    ///
+    /// Unlike `get`, this method does not use the
+    /// [thread local cache][thread-local-cache].
+    ///
+    /// [thread-local-cache]: ./struct.CacheBuilder.html#method.thread_local_cache
+    pub fn get_versioned<Q>(&self, key: &Q) -> Option<(V, u64)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.base.normalize_borrowed_key(key) {
+            return self.get_versioned_with_hash::<K>(&key, self.base.hash::<K>(&key));
+        }
+        self.get_versioned_with_hash(key, self.base.hash(key))
+    }
+
+    /// Runs the closure `f` against the value corresponding to the key, and
+    /// returns its result. Returns `None` without calling `f` if the key is not
+    /// in the cache.
+    ///
+    /// Unlike [`get`](#method.get), this method does not clone the value. Use it
+    /// to read a few parts of a large value, e.g. to parse a few fields out of a
+    /// byte buffer. The value stays valid while `f` runs, even if the entry is
+    /// invalidated, evicted or replaced meanwhile; `f` then sees the value it
+    /// started with.
+    ///
+    /// `f` should return quickly and must not block, since a removed value is
+    /// not dropped until `f` returns. It may call the methods of this cache.
+    ///
+    /// The read counts as a `get` in the statistics and calls the access listener.
+    /// It does not use the [thread local cache][thread-local-cache].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache: Cache<&str, Vec<u8>> = Cache::new(100);
+    /// cache.insert("header", vec![0xCA, 0xFE, 0x00, 0x2A]);
+    ///
+    /// // Read the last two bytes as a big-endian u16 without cloning the buffer.
+    /// let len = cache.read_with(&"header", |bytes| u16::from_be_bytes([bytes[2], bytes[3]]));
+    /// assert_eq!(len, Some(42));
+    /// assert_eq!(cache.read_with(&"footer", |bytes| bytes.len()), None);
+    /// ```
+    ///
+    /// [thread-local-cache]: ./struct.CacheBuilder.html#method.thread_local_cache
+    pub fn read_with<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.base.normalize_borrowed_key(key) {
+            return self.read_with_hash::<K, R>(&key, self.base.hash::<K>(&key), f);
+        }
+        self.read_with_hash(key, self.base.hash(key), f)
+    }
+
+    pub(crate) fn read_with_hash<Q, R>(
+        &self,
+        key: &Q,
+        hash: u64,
+        f: impl FnOnce(&V) -> R,
+    ) -> Option<R>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.base.read_with_hash(key, hash, f)
+    }
+
+    pub(crate) fn get_versioned_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<(V, u64)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        self.base.get_with_hash(key, self.base.hash(key))
+        let started_at = self.base.start_latency_timer(OpKind::Get);
+        let v = self.base.get_versioned_with_hash(key, hash);
+        self.base.record_latency(OpKind::Get, started_at);
+        v
     }
 
     pub(crate) fn get_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<V>
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let started_at = self.base.start_latency_timer(OpKind::Get);
+        let v = self.get_with_hash_and_tlc(key, hash);
+        self.base.record_latency(OpKind::Get, started_at);
+        v
+    }
+
+    fn get_with_hash_and_tlc<Q>(&self, key: &Q, hash: u64) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        self.base.get_with_hash(key, hash)
+        if let Some(tlc) = &self.thread_local_cache {
+            // Read the generation before probing the shared store, so that a
+            // write racing with this read will always invalidate what we put
+            // into the thread local cache.
+            let generation = self.base.write_generation();
+            if self.base.access_listener().is_some() {
+                // Take the key out of the thread local cache, so that the listener
+                // is called after the slot has been released.
+                let hit = tlc.get_with(key, hash, generation, |k, v| (Arc::clone(k), v.clone()));
+                if let Some((k, v)) = hit {
+                    self.base.trace_read(key, None, "hit");
+                    self.base.record_hit();
+                    self.base.notify_access(&k, &v);
+                    return Some(v);
+                }
+            } else if let Some(v) = tlc.get(key, hash, generation) {
+                self.base.trace_read(key, None, "hit");
+                self.base.record_hit();
+                return Some(v);
+            }
+            let (k, v) = self.base.get_key_value_with_hash(key, hash)?;
+            tlc.put(k, hash, v.clone(), generation);
+            Some(v)
+        } else {
+            self.base.get_with_hash(key, hash)
+        }
     }
 
     /// Ensures the value of the key exists by inserting the result of the init
@@ -412,7 +749,12 @@ where
     /// progress (e.g. thread 0, 2 and 3 above), this method will restart and resolve
     /// one of the remaining `init` closure.
     ///
+    /// The same applies when cloning the value returned by `init` panics. In
+    /// either case, nothing is inserted for the key, and no other caller is left
+    /// waiting for the panicked one.
+    ///
     pub fn get_or_insert_with(&self, key: K, init: impl FnOnce() -> V) -> V {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
         self.get_or_insert_with_hash_and_fun(key, hash, init)
@@ -424,526 +766,6296 @@ where
         hash: u64,
         init: impl FnOnce() -> V,
     ) -> V {
-        if let Some(v) = self.get_with_hash(&key, hash) {
-            return v;
-        }
-
-        match self.value_initializer.init_or_read(Arc::clone(&key), init) {
-            InitResult::Initialized(v) => {
-                self.insert_with_hash(Arc::clone(&key), hash, v.clone());
-                self.value_initializer
-                    .remove_waiter(&key, TypeId::of::<()>());
-                v
-            }
-            InitResult::ReadExisting(v) => v,
-            InitResult::InitErr(_) => unreachable!(),
-        }
+        self.get_or_insert_with_role_hash_and_fun(key, hash, init).0
     }
 
-    /// Try to ensure the value of the key exists by inserting an `Ok` result of the
-    /// init closure if not exist, and returns a _clone_ of the value or the `Err`
-    /// returned by the closure.
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but the `init`
+    /// closure receives a reference to the key, so it does not have to capture a
+    /// clone of the key.
     ///
-    /// This method prevents to evaluate the init closure multiple times on the same
-    /// key even if the method is concurrently called by many threads; only one of
-    /// the calls evaluates its closure (as long as these closures return the same
-    /// error type), and other calls wait for that closure to complete.
+    /// If the cache has a [key normalizer][key-normalizer], the closure receives
+    /// the normalized key.
     ///
     /// # Example
     ///
     /// ```rust
     /// use moka::sync::Cache;
-    /// use std::{path::Path, time::Duration, thread};
     ///
-    /// /// This function tries to get the file size in bytes.
-    /// fn get_file_size(thread_id: u8, path: impl AsRef<Path>) -> Result<u64, std::io::Error> {
-    ///     println!("get_file_size() called by thread {}.", thread_id);
-    ///     Ok(std::fs::metadata(path)?.len())
+    /// fn load(id: &String) -> usize {
+    ///     id.len()
     /// }
     ///
     /// let cache = Cache::new(100);
+    /// let id = "user:1".to_string();
     ///
-    /// // Spawn four threads.
-    /// let threads: Vec<_> = (0..4_u8)
-    ///     .map(|thread_id| {
-    ///         let my_cache = cache.clone();
-    ///         thread::spawn(move || {
-    ///             println!("Thread {} started.", thread_id);
+    /// let value = cache.get_or_insert_with_key(id, load);
+    /// assert_eq!(value, 6);
+    /// ```
     ///
-    ///             // Try to insert and get the value for key1. Although all four
-    ///             // threads will call `get_or_try_insert_with` at the same time,
-    ///             // get_file_size() must be called only once.
-    ///             let value = my_cache.get_or_try_insert_with(
-    ///                 "key1",
-    ///                 || get_file_size(thread_id, "./Cargo.toml"),
-    ///             );
+    /// # Panics
     ///
-    ///             // Ensure the value exists now.
-    ///             assert!(value.is_ok());
-    ///             thread::sleep(Duration::from_millis(10));
-    ///             assert!(my_cache.get(&"key1").is_some());
+    /// Panics in the same way as `get_or_insert_with` when the `init` closure
+    /// panics.
     ///
-    ///             println!(
-    ///                 "Thread {} got the value. (len: {})",
-    ///                 thread_id,
-    ///                 value.unwrap()
-    ///             );
-    ///         })
-    ///     })
-    ///     .collect();
+    /// [key-normalizer]: ./struct.CacheBuilder.html#method.key_normalizer
+    pub fn get_or_insert_with_key(&self, key: K, init: impl FnOnce(&K) -> V) -> V {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init_key = Arc::clone(&key);
+        self.get_or_insert_with_hash_and_fun(key, hash, move || init(&init_key))
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key. If the key is not
+    /// in the cache, loads the value with the [loader][builder-loader] registered
+    /// to the builder, inserts it and returns a clone of it.
     ///
-    /// // Wait all threads to complete.
-    /// threads
-    ///     .into_iter()
-    ///     .for_each(|t| t.join().expect("Thread failed"));
+    /// As with [`get_or_insert_with`](#method.get_or_insert_with), concurrent calls
+    /// for the same key run the loader only once, and the other calls wait for it
+    /// and get the loaded value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::builder()
+    ///     .loader(|key: &String| key.len())
+    ///     .build();
+    ///
+    /// assert_eq!(cache.get_loading("key1".to_string()), 4);
+    /// assert_eq!(cache.get(&"key1".to_string()), Some(4));
     /// ```
     ///
-    /// **Result**
+    /// # Panics
     ///
-    /// - `get_file_size()` was called exactly once by thread 1.
-    /// - Other threads were blocked until thread 1 inserted the value.
+    /// Panics if the cache was built without a loader. Also panics in the same way
+    /// as `get_or_insert_with` when the loader panics.
     ///
-    /// ```console
-    /// Thread 1 started.
-    /// Thread 2 started.
-    /// get_file_size() called by thread 1.
-    /// Thread 3 started.
-    /// Thread 0 started.
-    /// Thread 2 got the value. (len: 1466)
-    /// Thread 0 got the value. (len: 1466)
-    /// Thread 1 got the value. (len: 1466)
-    /// Thread 3 got the value. (len: 1466)
+    /// [builder-loader]: ./struct.CacheBuilder.html#method.loader
+    pub fn get_loading(&self, key: K) -> V {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        self.get_loading_with_hash(Arc::new(key), hash)
+    }
+
+    pub(crate) fn get_loading_with_hash(&self, key: Arc<K>, hash: u64) -> V {
+        let loader = self
+            .loader
+            .as_ref()
+            .map(Arc::clone)
+            .expect("get_loading requires a loader set by CacheBuilder::loader");
+        let init_key = Arc::clone(&key);
+        self.get_or_insert_with_hash_and_fun(key, hash, move || loader(&init_key))
+    }
+
+    /// Starts loading the value of the key with the [loader][builder-loader] in
+    /// the background, if the key is neither in the cache nor being loaded.
+    /// Otherwise, does nothing.
+    ///
+    /// Call this as soon as you know that a key will be needed, e.g. when a request
+    /// header tells which record the request will read, so that the value is
+    /// loaded by the time you call [`get_loading`](#method.get_loading).
+    ///
+    /// This method never blocks: the loader runs on a background thread shared by
+    /// the caches. The load is coalesced with the other loads of the key like the
+    /// concurrent calls of `get_loading`, so a `get_loading` or
+    /// `get_or_insert_with` made while the prefetch is loading waits for it
+    /// instead of loading the value again. `get` does not wait, and misses until
+    /// the value is inserted.
+    ///
+    /// When the cache records the statistics, [`CacheStats::prefetches_issued`]
+    /// and [`CacheStats::prefetch_hits`] tell whether the prefetches pay off.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::{thread, time::Duration};
+    ///
+    /// let cache = Cache::builder()
+    ///     .loader(|key: &String| {
+    ///         // Some slow loading.
+    ///         thread::sleep(Duration::from_millis(10));
+    ///         key.len()
+    ///     })
+    ///     .build();
+    ///
+    /// // Returns immediately.
+    /// cache.prefetch("key1".to_string());
+    ///
+    /// // Waits for the prefetch (if still loading) instead of loading again.
+    /// assert_eq!(cache.get_loading("key1".to_string()), 4);
     /// ```
     ///
     /// # Panics
     ///
-    /// This method panics when the `init` closure has been panicked. When it
-    /// happens, only the caller whose `init` closure panicked will get the panic
-    /// (e.g. only thread 1 in the above sample). If there are other calls in
-    /// progress (e.g. thread 0, 2 and 3 above), this method will restart and resolve
-    /// one of the remaining `init` closure.
+    /// Panics if the cache was built without a loader.
     ///
-    pub fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
-    where
-        F: FnOnce() -> Result<V, E>,
-        E: Send + Sync + 'static,
-    {
+    /// [builder-loader]: ./struct.CacheBuilder.html#method.loader
+    /// [`CacheStats::prefetches_issued`]: ../struct.CacheStats.html#method.prefetches_issued
+    /// [`CacheStats::prefetch_hits`]: ../struct.CacheStats.html#method.prefetch_hits
+    pub fn prefetch(&self, key: K) {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
-        let key = Arc::new(key);
-        self.get_or_try_insert_with_hash_and_fun(key, hash, init)
+        self.prefetch_with_hash(Arc::new(key), hash)
     }
 
-    pub(crate) fn get_or_try_insert_with_hash_and_fun<F, E>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: F,
-    ) -> Result<V, Arc<E>>
-    where
-        F: FnOnce() -> Result<V, E>,
-        E: Send + Sync + 'static,
-    {
-        if let Some(v) = self.get_with_hash(&key, hash) {
-            return Ok(v);
+    pub(crate) fn prefetch_with_hash(&self, key: Arc<K>, hash: u64) {
+        let loader = self
+            .loader
+            .as_ref()
+            .map(Arc::clone)
+            .expect("prefetch requires a loader set by CacheBuilder::loader");
+        if self.base.contains_live_key(&key) || self.value_initializer.is_loading(&key) {
+            return;
         }
+        self.base.record_prefetch();
+        let cache = self.clone();
+        self.prefetcher
+            .execute(move || cache.load_prefetched(key, hash, loader));
+    }
 
-        match self
-            .value_initializer
-            .try_init_or_read(Arc::clone(&key), init)
-        {
-            InitResult::Initialized(v) => {
-                self.insert_with_hash(Arc::clone(&key), hash, v.clone());
-                self.value_initializer
-                    .remove_waiter(&key, TypeId::of::<E>());
-                Ok(v)
+    /// Loads the value for `prefetch`. Unlike `get_loading`, this does not
+    /// record a hit or a miss.
+    fn load_prefetched(&self, key: Arc<K>, hash: u64, loader: Loader<K, V>) {
+        // Another call may have inserted the key since the prefetch was issued.
+        if self.base.contains_live_key(&key) {
+            return;
+        }
+        let init = || {
+            let timer = self.base.start_load();
+            let v = loader(&key);
+            if let Some(timer) = timer {
+                timer.finish(true);
             }
-            InitResult::ReadExisting(v) => Ok(v),
-            InitResult::InitErr(e) => Err(e),
+            v
+        };
+        if let InitResult::Initialized(v) =
+            self.value_initializer.init_or_read(Arc::clone(&key), init)
+        {
+            self.insert_unpublished_with_hash(Arc::clone(&key), hash, v);
+            self.base.mark_prefetched(&key);
+            self.value_initializer
+                .remove_waiter(&key, TypeId::of::<()>());
         }
     }
 
-    /// Inserts a key-value pair into the cache.
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but also
+    /// returns whether this call has run its `init` closure.
     ///
-    /// If the cache has this key present, the value is updated.
-    pub fn insert(&self, key: K, value: V) {
+    /// Among the concurrent calls for the same key, exactly one gets
+    /// `LoadRole::Leader`, and the others get `LoadRole::Follower`, as well as any
+    /// call that finds the value already in the cache. So the leader can perform
+    /// the side effects that must happen once per load, e.g. notifying others that
+    /// the value has been populated.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, LoadRole};
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// let (v, role) = cache.get_or_insert_with_role("key1", || 1);
+    /// assert_eq!((v, role), (1, LoadRole::Leader));
+    ///
+    /// let (v, role) = cache.get_or_insert_with_role("key1", || unreachable!());
+    /// assert_eq!((v, role), (1, LoadRole::Follower));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same way as `get_or_insert_with` when the `init` closure
+    /// panics.
+    pub fn get_or_insert_with_role(&self, key: K, init: impl FnOnce() -> V) -> (V, LoadRole) {
+        let key = self.base.normalize_key(key);
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
-        self.insert_with_hash(key, hash, value)
+        self.get_or_insert_with_role_hash_and_fun(key, hash, init)
     }
 
-    pub(crate) fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
-        let op = self.base.do_insert_with_hash(key, hash, value);
-        let hk = self.base.housekeeper.as_ref();
-        Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to insert");
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but returns
+    /// the value in an [`Entry`][entry], which tells whether the value was
+    /// loaded by this call.
+    ///
+    /// [`Entry::is_fresh`][entry-is-fresh] returns `true` only for the call that
+    /// has run its `init` closure. It returns `false` for a call that found the
+    /// value in the cache or waited for a concurrent call to load it. This is the
+    /// same as `LoadRole::Leader` of
+    /// [`get_or_insert_with_role`](#method.get_or_insert_with_role).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// let entry = cache.get_or_insert_with_info("key1", || 1);
+    /// assert!(entry.is_fresh());
+    ///
+    /// let entry = cache.get_or_insert_with_info("key1", || unreachable!());
+    /// assert!(!entry.is_fresh());
+    /// assert_eq!(entry.into_value(), 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same way as `get_or_insert_with` when the `init` closure
+    /// panics.
+    ///
+    /// [entry]: ../struct.Entry.html
+    /// [entry-is-fresh]: ../struct.Entry.html#method.is_fresh
+    pub fn get_or_insert_with_info(&self, key: K, init: impl FnOnce() -> V) -> Entry<V> {
+        let (v, role) = self.get_or_insert_with_role(key, init);
+        Entry::new(v, role == LoadRole::Leader)
     }
 
-    /// Discards any cached value for the key.
+    pub(crate) fn get_or_insert_with_role_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> V,
+    ) -> (V, LoadRole) {
+        if let Some(v) = self.get_with_hash(&key, hash) {
+            return (v, LoadRole::Follower);
+        }
+
+        let init = || {
+            let timer = self.base.start_load();
+            let v = init();
+            if let Some(timer) = timer {
+                timer.finish(true);
+            }
+            v
+        };
+        match self.value_initializer.init_or_read(Arc::clone(&key), init) {
+            InitResult::Initialized(v) => {
+                self.insert_unpublished_with_hash(Arc::clone(&key), hash, v.clone());
+                self.value_initializer
+                    .remove_waiter(&key, TypeId::of::<()>());
+                (v, LoadRole::Leader)
+            }
+            InitResult::ReadExisting(v) => {
+                self.base.record_load_deduplicated();
+                (v, LoadRole::Follower)
+            }
+            InitResult::InitErr(_) | InitResult::OwnerPanicked => unreachable!(),
+        }
+    }
+
+    /// Looks up the key, and claims the load of its value if it is absent, so
+    /// that the concurrent misses of the key are collapsed into one load done
+    /// outside of the cache.
     ///
-    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
-    /// on the borrowed form _must_ match those for the key type.
-    pub fn invalidate<Q>(&self, key: &Q)
-    where
-        Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(kv) = self.base.remove_entry(key) {
-            let op = WriteOp::Remove(kv);
-            let hk = self.base.housekeeper.as_ref();
-            Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to remove");
+    /// This is a lower-level counterpart of
+    /// [`get_or_insert_with`](#method.get_or_insert_with) for when the load cannot
+    /// be expressed as a closure, e.g. when the value is loaded by another
+    /// subsystem that delivers it later. Returns:
+    ///
+    /// - `MissClaim::Hit` with the value if the key is in the cache.
+    /// - `MissClaim::Owner` with a [`MissToken`][miss-token] if the key is absent
+    ///   and not claimed. The caller should load the value and give it to
+    ///   [`complete`](#method.complete) with the token, or give up by
+    ///   [`abandon`](#method.abandon).
+    /// - `MissClaim::Pending` with a [`MissWaiter`][miss-waiter] if another caller
+    ///   owns the claim. Its `wait` method blocks until the owner completes the
+    ///   claim, returning the value, or abandons it, returning
+    ///   `MissResolution::Retry` to tell the caller to claim again.
+    ///
+    /// Dropping the token abandons the claim, so the waiters are woken up even
+    /// when the owner panics or its task is cancelled. The token can be sent to
+    /// another thread.
+    ///
+    /// The claims only collapse the calls of `claim_miss`. They are independent
+    /// of the loads by `get_or_insert_with` and its variants, which neither wait
+    /// for the claims nor are waited for by `claim_miss`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, MissClaim, MissResolution};
+    ///
+    /// let cache: Cache<u32, String> = Cache::new(100);
+    ///
+    /// let token = match cache.claim_miss(1) {
+    ///     MissClaim::Owner(token) => token,
+    ///     _ => unreachable!(),
+    /// };
+    ///
+    /// // The second caller finds the claim of the first one.
+    /// let waiter = match cache.claim_miss(1) {
+    ///     MissClaim::Pending(waiter) => waiter,
+    ///     _ => unreachable!(),
+    /// };
+    /// let handle = std::thread::spawn(move || waiter.wait());
+    ///
+    /// // The first caller loads the value in its own way, and delivers it.
+    /// cache.complete(token, "one".to_string());
+    ///
+    /// assert_eq!(
+    ///     handle.join().unwrap(),
+    ///     MissResolution::Completed("one".to_string())
+    /// );
+    /// assert!(matches!(cache.claim_miss(1), MissClaim::Hit(v) if v == "one"));
+    /// ```
+    ///
+    /// [miss-token]: ./struct.MissToken.html
+    /// [miss-waiter]: ./struct.MissWaiter.html
+    pub fn claim_miss(&self, key: K) -> MissClaim<K, V, S> {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        if let Some(v) = self.get_with_hash(&key, hash) {
+            return MissClaim::Hit(v);
+        }
+
+        let claim = Arc::new(Claim::default());
+        match self.miss_claims.try_insert(&key, &claim) {
+            None => {
+                let claims = Arc::clone(&self.miss_claims);
+                MissClaim::Owner(MissToken::new(key, hash, claim, claims))
+            }
+            Some(claim) => MissClaim::Pending(MissWaiter::new(claim)),
         }
     }
 
-    /// Discards all cached values.
+    /// Inserts the value of the key claimed by [`claim_miss`](#method.claim_miss),
+    /// and wakes up the callers waiting for the claim with clones of the value.
     ///
-    /// This method returns immediately and a background thread will evict all the
-    /// cached values inserted before the time when this method was called. It is
-    /// guaranteed that the `get` method must not return these invalidated values
-    /// even if they have not been evicted.
+    /// # Panics
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
-    pub fn invalidate_all(&self) {
-        self.base.invalidate_all();
+    /// Panics if the token was returned by another cache.
+    pub fn complete(&self, token: MissToken<K, V, S>, value: V) {
+        assert!(
+            token.is_claimed_from(&self.miss_claims),
+            "The miss was claimed from another cache"
+        );
+        let (key, hash) = token.key_and_hash();
+        self.insert_unpublished_with_hash(Arc::clone(key), hash, value.clone());
+        token.resolve(ClaimState::Completed(value));
     }
 
-    /// Discards cached values that satisfy a predicate.
+    /// Gives up the claim of the key claimed by
+    /// [`claim_miss`](#method.claim_miss), and wakes up the callers waiting for
+    /// the claim with `MissResolution::Retry`. Same as dropping the token.
+    pub fn abandon(&self, token: MissToken<K, V, S>) {
+        drop(token);
+    }
+
+    /// Try to ensure the value of the key exists by inserting an `Ok` result of the
+    /// init closure if not exist, and returns a _clone_ of the value or a
+    /// [`LoadError::Loader`][load-error] holding the `Err` returned by the closure.
     ///
-    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
-    /// method returns immediately and a background thread will apply the closure to
-    /// each cached value inserted before the time when `invalidate_entries_if` was
-    /// called. If the closure returns `true` on a value, that value will be evicted
-    /// from the cache.
+    /// This method prevents to evaluate the init closure multiple times on the same
+    /// key even if the method is concurrently called by many threads; only one of
+    /// the calls evaluates its closure (as long as these closures return the same
+    /// error type), and other calls wait for that closure to complete.
     ///
-    /// Also the `get` method will apply the closure to a value to determine if it
-    /// should have been invalidated. Therefore, it is guaranteed that the `get`
-    /// method must not return invalidated values.
+    /// # Example
     ///
-    /// Note that you must call
-    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
-    /// at the cache creation time as the cache needs to maintain additional internal
-    /// data structures to support this method. Otherwise, calling this method will
-    /// fail with a
-    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::{path::Path, time::Duration, thread};
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
+    /// /// This function tries to get the file size in bytes.
+    /// fn get_file_size(thread_id: u8, path: impl AsRef<Path>) -> Result<u64, std::io::Error> {
+    ///     println!("get_file_size() called by thread {}.", thread_id);
+    ///     Ok(std::fs::metadata(path)?.len())
+    /// }
     ///
-    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
-    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
-    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    /// let cache = Cache::new(100);
+    ///
+    /// // Spawn four threads.
+    /// let threads: Vec<_> = (0..4_u8)
+    ///     .map(|thread_id| {
+    ///         let my_cache = cache.clone();
+    ///         thread::spawn(move || {
+    ///             println!("Thread {} started.", thread_id);
+    ///
+    ///             // Try to insert and get the value for key1. Although all four
+    ///             // threads will call `get_or_try_insert_with` at the same time,
+    ///             // get_file_size() must be called only once.
+    ///             let value = my_cache.get_or_try_insert_with(
+    ///                 "key1",
+    ///                 || get_file_size(thread_id, "./Cargo.toml"),
+    ///             );
+    ///
+    ///             // Ensure the value exists now.
+    ///             assert!(value.is_ok());
+    ///             thread::sleep(Duration::from_millis(10));
+    ///             assert!(my_cache.get(&"key1").is_some());
+    ///
+    ///             println!(
+    ///                 "Thread {} got the value. (len: {})",
+    ///                 thread_id,
+    ///                 value.unwrap()
+    ///             );
+    ///         })
+    ///     })
+    ///     .collect();
+    ///
+    /// // Wait all threads to complete.
+    /// threads
+    ///     .into_iter()
+    ///     .for_each(|t| t.join().expect("Thread failed"));
+    /// ```
+    ///
+    /// **Result**
+    ///
+    /// - `get_file_size()` was called exactly once by thread 1.
+    /// - Other threads were blocked until thread 1 inserted the value.
+    ///
+    /// ```console
+    /// Thread 1 started.
+    /// Thread 2 started.
+    /// get_file_size() called by thread 1.
+    /// Thread 3 started.
+    /// Thread 0 started.
+    /// Thread 2 got the value. (len: 1466)
+    /// Thread 0 got the value. (len: 1466)
+    /// Thread 1 got the value. (len: 1466)
+    /// Thread 3 got the value. (len: 1466)
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the `init` closure has been panicked. When it
+    /// happens, only the caller whose `init` closure panicked will get the panic
+    /// (e.g. only thread 1 in the above sample). If there are other calls in
+    /// progress (e.g. thread 0, 2 and 3 above), this method will restart and resolve
+    /// one of the remaining `init` closure.
+    ///
+    /// A waiting call that keeps seeing the `init` closures of the other calls
+    /// panic gives up after a while, and returns `LoadError::OwnerPanicked`
+    /// instead of panicking.
+    ///
+    /// [load-error]: ../enum.LoadError.html
+    pub fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, LoadError<E>>
     where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+        F: FnOnce() -> Result<V, E>,
+        E: Send + Sync + 'static,
     {
-        self.base.invalidate_entries_if(Arc::new(predicate))
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_try_insert_with_hash_and_fun(key, hash, init)
     }
 
-    pub(crate) fn invalidate_entries_with_arc_fun<F>(
-        &self,
-        predicate: Arc<F>,
-    ) -> Result<PredicateId, PredicateError>
+    /// Same as [`get_or_try_insert_with`](#method.get_or_try_insert_with), but
+    /// the `init` closure receives a reference to the key, like
+    /// [`get_or_insert_with_key`](#method.get_or_insert_with_key).
+    pub fn get_or_try_insert_with_key<F, E>(&self, key: K, init: F) -> Result<V, LoadError<E>>
     where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+        F: FnOnce(&K) -> Result<V, E>,
+        E: Send + Sync + 'static,
     {
-        self.base.invalidate_entries_if(predicate)
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init_key = Arc::clone(&key);
+        self.get_or_try_insert_with_hash_and_fun(key, hash, move || init(&init_key))
     }
 
-    /// Returns the `max_capacity` of this cache.
-    pub fn max_capacity(&self) -> Option<usize> {
-        self.base.max_capacity()
+    /// Same as [`get_or_try_insert_with`](#method.get_or_try_insert_with), but
+    /// calls the `fallback` closure when the `primary` closure returns an `Err`,
+    /// e.g. to read the value from a replica when the primary store is down.
+    ///
+    /// Only an `Ok` result of either closure is inserted into the cache. When
+    /// both closures fail, the returned `LoadError::Loader` holds a
+    /// [`FallbackError`][fallback-error] with both errors.
+    ///
+    /// The whole chain is evaluated at most once at a time for the same key; the
+    /// concurrent calls wait for the chain of one of the calls to complete, and
+    /// get its value or its `FallbackError`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache: Cache<&str, String> = Cache::new(100);
+    ///
+    /// let value = cache.get_or_try_insert_with_fallback(
+    ///     "key1",
+    ///     || Err("primary is down"),
+    ///     || Ok::<_, &str>("from replica".to_string()),
+    /// );
+    /// assert_eq!(value.unwrap(), "from replica");
+    /// assert_eq!(cache.get(&"key1"), Some("from replica".to_string()));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics when the `primary` or `fallback` closure has panicked, like
+    /// `get_or_try_insert_with`.
+    ///
+    /// [fallback-error]: ../struct.FallbackError.html
+    pub fn get_or_try_insert_with_fallback<F1, F2, E1, E2>(
+        &self,
+        key: K,
+        primary: F1,
+        fallback: F2,
+    ) -> Result<V, LoadError<FallbackError<E1, E2>>>
+    where
+        F1: FnOnce() -> Result<V, E1>,
+        F2: FnOnce() -> Result<V, E2>,
+        E1: Send + Sync + 'static,
+        E2: Send + Sync + 'static,
+    {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init = || match primary() {
+            Ok(v) => Ok(v),
+            Err(primary) => fallback().map_err(|fallback| FallbackError { primary, fallback }),
+        };
+        self.get_or_try_insert_with_hash_and_fun(key, hash, init)
     }
 
-    /// Returns the `time_to_live` of this cache.
-    pub fn time_to_live(&self) -> Option<Duration> {
-        self.base.time_to_live()
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but reserves
+    /// the `estimated_weight` of the value before calling the `init` closure, or
+    /// returns a `LoadError::Shed` holding a [`LoadSheddingError`][shedding-error]
+    /// without calling it if the reservation would take the cache over its
+    /// [reservation high-water mark][high-water-mark].
+    ///
+    /// Without reservations, many concurrent misses loading large values may
+    /// add their values to the cache at about the same time, and take it far over
+    /// its capacity (and the process out of memory) before the eviction catches
+    /// up. With reservations, a load is only started if the weighted size of the
+    /// cache plus the weight reserved by the other loads in progress and the
+    /// `estimated_weight` fit in the high-water mark, so the excess loads fail
+    /// fast instead. Set the high-water mark somewhat above the max capacity, as
+    /// a full cache is a normal state that the eviction keeps in check.
+    ///
+    /// The `estimated_weight` is in the unit of the [weigher][weigher], or `1`
+    /// for a cache without a weigher. Only the call that runs its `init`
+    /// closure reserves the weight, and the weight is released when the closure
+    /// returns or panics. The calls waiting for it do not reserve anything, and
+    /// get the same `LoadSheddingError` if it is shed. The weight of the inserted
+    /// value is added to the weighted size by the next maintenance.
+    ///
+    /// A shed load is counted as a failed load by the [statistics][stats] and
+    /// by [`load_failure_count`](#method.load_failure_count). Without a
+    /// high-water mark, loads are never shed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::builder()
+    ///     .weigher(|_key: &u32, value: &Vec<u8>| value.len() as u32)
+    ///     .max_weighted_capacity(512 * 1024 * 1024)
+    ///     .reservation_high_water_mark(640 * 1024 * 1024)
+    ///     .build();
+    ///
+    /// let value = cache.get_with_reservation(1, 2 * 1024 * 1024, || vec![0; 2 * 1024 * 1024]);
+    /// assert!(value.is_ok());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics when the `init` closure has panicked, like `get_or_insert_with`.
+    ///
+    /// [shedding-error]: ../struct.LoadSheddingError.html
+    /// [high-water-mark]: ./struct.CacheBuilder.html#method.reservation_high_water_mark
+    /// [weigher]: ./struct.CacheBuilder.html#method.weigher
+    /// [stats]: #method.stats
+    pub fn get_with_reservation(
+        &self,
+        key: K,
+        estimated_weight: u32,
+        init: impl FnOnce() -> V,
+    ) -> Result<V, LoadError> {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_with_reservation_hash_and_fun(key, hash, estimated_weight, init)
     }
 
-    /// Returns the `time_to_idle` of this cache.
-    pub fn time_to_idle(&self) -> Option<Duration> {
-        self.base.time_to_idle()
+    pub(crate) fn get_with_reservation_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        estimated_weight: u32,
+        init: impl FnOnce() -> V,
+    ) -> Result<V, LoadError> {
+        let init = || {
+            let _reservation = self.base.try_reserve_weight(estimated_weight)?;
+            Ok(init())
+        };
+        self.get_or_try_insert_with_hash_and_fun(key, hash, init)
+            .map_err(LoadError::from_shed_load)
+    }
+
+    /// Returns the total weight reserved by the calls of
+    /// [`get_with_reservation`](#method.get_with_reservation) loading their
+    /// values.
+    pub fn reserved_weight(&self) -> u64 {
+        self.base.reserved_weight()
+    }
+
+    pub(crate) fn get_or_try_insert_with_hash_and_fun<F, E>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: F,
+    ) -> Result<V, LoadError<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+        E: Send + Sync + 'static,
+    {
+        if let Some(v) = self.get_with_hash(&key, hash) {
+            return Ok(v);
+        }
+
+        // A waiter may also get an `InitErr`, so remember whether this call has
+        // loaded the value.
+        let mut loaded = false;
+        let init = || {
+            loaded = true;
+            let timer = self.base.start_load();
+            let result = init();
+            if let Some(timer) = timer {
+                timer.finish(result.is_ok());
+            }
+            result
+        };
+        let result = self
+            .value_initializer
+            .try_init_or_read(Arc::clone(&key), init);
+        if !loaded {
+            self.base.record_load_deduplicated();
+        }
+        match result {
+            InitResult::Initialized(v) => {
+                self.insert_unpublished_with_hash(Arc::clone(&key), hash, v.clone());
+                self.value_initializer
+                    .remove_waiter(&key, TypeId::of::<E>());
+                Ok(v)
+            }
+            InitResult::ReadExisting(v) => Ok(v),
+            InitResult::InitErr(e) => Err(LoadError::Loader(e)),
+            InitResult::OwnerPanicked => Err(LoadError::OwnerPanicked),
+        }
+    }
+
+    /// Returns the number of consecutive failures of the `init` closure of
+    /// [`get_or_try_insert_with`](#method.get_or_try_insert_with) for the key.
+    ///
+    /// The count is incremented every time an `init` closure for the key returns
+    /// an `Err`, and reset to zero when one returns an `Ok`. Only the call that
+    /// actually evaluated its closure is counted; concurrent calls that received
+    /// the same error do not increment the count. You can use it to build a
+    /// circuit breaker, e.g. to stop calling a failing backend for a key after
+    /// some consecutive failures.
+    ///
+    /// Counts are only tracked for keys that have failed at least once, and a key
+    /// is forgotten when its `init` closure succeeds, or when it is invalidated by
+    /// `invalidate`, `invalidate_all` and the like. This method returns `0` for
+    /// other keys. Up to 4096 keys are tracked. Beyond that, the keys that have
+    /// not failed for the longest time are forgotten first.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn load_failure_count<Q>(&self, key: &Q) -> u32
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.base.normalize_borrowed_key(key) {
+            return self.value_initializer.load_failure_count::<K>(&key);
+        }
+        self.value_initializer.load_failure_count(key)
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub fn insert(&self, key: K, value: V) {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash(key, hash, value)
+    }
+
+    pub(crate) fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        self.insert_unpublished_with_hash(Arc::clone(&key), hash, value);
+        if let Some(coherence) = &self.coherence {
+            coherence.on_insert(&key);
+        }
+    }
+
+    /// Inserts without publishing to the coherence bus. Used to insert a value
+    /// loaded by `get_or_insert_with` and its variants.
+    fn insert_unpublished_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let op = self.base.do_insert_with_hash(key, hash, value);
+        Self::schedule_write_op(&self.base, op).expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+    }
+
+    /// Inserts a key-value pair into the cache with its own time-to-idle.
+    ///
+    /// The entry expires after it has not been read or written for `time_to_idle`,
+    /// regardless of the [time-to-idle of the cache][tti]. It also expires by the
+    /// time-to-live of the cache if that comes first. A later `insert` on the key
+    /// removes the override and makes the entry use the time-to-idle of the
+    /// cache.
+    ///
+    /// An entry is never returned once it is idle for its own duration, but it
+    /// may take a few maintenance passes before it is removed from the cache,
+    /// because entries with different durations do not expire in the order they
+    /// were accessed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::CacheBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let cache = CacheBuilder::new(100)
+    ///     .time_to_idle(Duration::from_secs(10 * 60))
+    ///     .build();
+    ///
+    /// // Expires after 10 minutes of no use.
+    /// cache.insert("session:1", "alice");
+    /// // Expires after 6 hours of no use.
+    /// cache.insert_with_tti("country:jp", "Japan", Duration::from_secs(6 * 3600));
+    /// ```
+    ///
+    /// [tti]: ./struct.CacheBuilder.html#method.time_to_idle
+    pub fn insert_with_tti(&self, key: K, value: V, time_to_idle: Duration) {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_tti(key, hash, value, time_to_idle)
+    }
+
+    pub(crate) fn insert_with_hash_and_tti(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_idle: Duration,
+    ) {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let op = self.base.do_insert_with_hash_and_tti(
+            Arc::clone(&key),
+            hash,
+            value,
+            Some(time_to_idle),
+        );
+        Self::schedule_write_op(&self.base, op).expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+        if let Some(coherence) = &self.coherence {
+            coherence.on_insert(&key);
+        }
+    }
+
+    /// Inserts a key-value pair into the cache, and adds the entry to the group
+    /// `group`. All entries in a group can be discarded at once by
+    /// [`invalidate_group`](#method.invalidate_group).
+    ///
+    /// An entry belongs to at most one group. Inserting the key into another
+    /// group moves the entry there, and it is no longer discarded with the first
+    /// group. A plain [`insert`](#method.insert) on the key replaces the value but
+    /// keeps the entry in its group. The entry leaves the group when it is
+    /// removed from the cache for any reason.
+    ///
+    /// The cache keeps an index from each group to the keys of its entries. It
+    /// takes a clone of the group name and a few words for each grouped entry,
+    /// and is guarded by a single lock shared by all groups, so a cache with many
+    /// grouped inserts from many threads may see some contention on it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert_in_group("user/1/profile", "alice", "user/1");
+    /// cache.insert_in_group("user/1/avatar", "alice.png", "user/1");
+    /// cache.insert_in_group("user/2/profile", "bob", "user/2");
+    ///
+    /// cache.invalidate_group("user/1");
+    ///
+    /// assert_eq!(cache.get(&"user/1/profile"), None);
+    /// assert_eq!(cache.get(&"user/1/avatar"), None);
+    /// assert_eq!(cache.get(&"user/2/profile"), Some("bob"));
+    /// ```
+    pub fn insert_in_group(&self, key: K, value: V, group: impl Into<String>) {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_in_group_with_hash(key, hash, value, group.into())
+    }
+
+    pub(crate) fn insert_in_group_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        group: String,
+    ) {
+        self.insert_with_hash(Arc::clone(&key), hash, value);
+        self.base.add_to_group(group, &key);
+    }
+
+    /// Inserts a key-value pair into the cache only if the key is not present.
+    ///
+    /// If the cache already has an unexpired value for the key, the cache is left
+    /// untouched and an [`OccupiedError`][occupied-error] holding a _clone_ of the
+    /// existing value and the given value is returned. An expired entry is
+    /// treated as absent and replaced. The check and the insertion are done
+    /// atomically, so only one of the concurrent calls on the same key will
+    /// succeed.
+    ///
+    /// Unlike `insert`, a rejected call does not update the entry, so it does not
+    /// reset the time-to-live of the entry.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// assert!(cache.try_insert("name", "alice").is_ok());
+    ///
+    /// let err = cache.try_insert("name", "bob").unwrap_err();
+    /// assert_eq!(err.existing, "alice");
+    /// assert_eq!(err.value, "bob");
+    /// assert_eq!(cache.get(&"name"), Some("alice"));
+    /// ```
+    ///
+    /// [occupied-error]: ../struct.OccupiedError.html
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), OccupiedError<V>> {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.try_insert_with_hash(key, hash, value)
+    }
+
+    pub(crate) fn try_insert_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<(), OccupiedError<V>> {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let op = self
+            .base
+            .do_insert_if_absent_with_hash(Arc::clone(&key), hash, value)?;
+        Self::schedule_write_op(&self.base, op).expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+        if let Some(coherence) = &self.coherence {
+            coherence.on_insert(&key);
+        }
+        Ok(())
+    }
+
+    /// Inserts a key-value pair into the cache only if the entry of the key still
+    /// has the expected version, and returns the version of the new entry.
+    ///
+    /// `expected_version` is a version returned by
+    /// [`get_versioned`](#method.get_versioned) or by a previous call to this
+    /// method. Pass `None` to insert only if the key is absent. An expired or
+    /// invalidated entry is treated as absent, so it never matches a version.
+    ///
+    /// If the version does not match, the cache is left untouched and a
+    /// [`CasError`][cas-error] holding a _clone_ of the current value and its
+    /// version, if any, and the given value is returned. The check and the
+    /// replacement are done atomically, so only one of the concurrent calls with
+    /// the same expected version will succeed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// let v1 = cache.insert_if_version("count", 1, None).unwrap();
+    ///
+    /// // Read-modify-write.
+    /// let (count, version) = cache.get_versioned(&"count").unwrap();
+    /// assert_eq!(version, v1);
+    /// let v2 = cache.insert_if_version("count", count + 1, Some(version)).unwrap();
+    /// assert!(v2 > v1);
+    ///
+    /// // A write with a stale version is rejected.
+    /// let err = cache.insert_if_version("count", 10, Some(v1)).unwrap_err();
+    /// assert_eq!(err.current, Some((2, v2)));
+    /// assert_eq!(err.value, 10);
+    /// ```
+    ///
+    /// [cas-error]: ../struct.CasError.html
+    pub fn insert_if_version(
+        &self,
+        key: K,
+        value: V,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CasError<V>> {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_if_version_with_hash(key, hash, value, expected_version)
+    }
+
+    pub(crate) fn insert_if_version_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CasError<V>> {
+        let started_at = self.base.start_latency_timer(OpKind::Insert);
+        let (version, op) = self.base.do_insert_if_version_with_hash(
+            Arc::clone(&key),
+            hash,
+            value,
+            expected_version,
+        )?;
+        Self::schedule_write_op(&self.base, op).expect("Failed to insert");
+        self.base.record_latency(OpKind::Insert, started_at);
+        if let Some(coherence) = &self.coherence {
+            coherence.on_insert(&key);
+        }
+        Ok(version)
+    }
+
+    /// Inserts the value returned by the closure for the key, and returns a
+    /// _clone_ of the value.
+    ///
+    /// The closure receives the current value of the key, or `None` if the key is
+    /// not present (or has expired), so that the new value can be built from the
+    /// old one, e.g. to merge two collections. Unlike `get` followed by `insert`,
+    /// the calls to `insert_with` on the same key are serialized by a per-key
+    /// lock, so that no update made by another `insert_with` call will be lost.
+    ///
+    /// Note that `insert`, `invalidate` and the other methods do not take the lock.
+    /// If they are called concurrently on the same key, the value passed to the
+    /// closure may have been replaced or removed by the time the new value is
+    /// inserted.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::collections::HashSet;
+    ///
+    /// let cache: Cache<&str, HashSet<u32>> = Cache::new(100);
+    ///
+    /// let merge = |new: &[u32]| {
+    ///     let new = new.iter().copied().collect::<HashSet<_>>();
+    ///     move |current: Option<&HashSet<u32>>| match current {
+    ///         Some(current) => current.union(&new).copied().collect(),
+    ///         None => new,
+    ///     }
+    /// };
+    ///
+    /// cache.insert_with("tags", merge(&[1, 2]));
+    /// let tags = cache.insert_with("tags", merge(&[2, 3]));
+    /// assert_eq!(tags, [1, 2, 3].iter().copied().collect());
+    /// ```
+    pub fn insert_with(&self, key: K, f: impl FnOnce(Option<&V>) -> V) -> V {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_fun(key, hash, f)
+    }
+
+    pub(crate) fn insert_with_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: impl FnOnce(Option<&V>) -> V,
+    ) -> V {
+        let (current, v) = {
+            let key_lock = self.key_locks.key_lock(&key);
+            let _guard = key_lock.lock(|| self.base.record_lock_contention());
+
+            let current = self.base.get_with_hash_unnotified(&key, hash);
+            let v = f(current.as_ref());
+            self.insert_with_hash(Arc::clone(&key), hash, v.clone());
+            (current, v)
+        };
+        // Call the access listener after releasing the key lock.
+        if let Some(current) = &current {
+            self.base.notify_access(&key, current);
+        }
+        v
+    }
+
+    /// Swaps the values of the two keys, and returns `true` if both keys had a
+    /// value. If either key has no value (or it has expired), nothing is changed
+    /// and `false` is returned.
+    ///
+    /// The two values are read and written while holding the per-key locks of
+    /// both keys, which are taken in a fixed order so that concurrent swaps of
+    /// the same keys cannot deadlock. So concurrent calls to `swap` and
+    /// [`insert_with`](#method.insert_with) on these keys are serialized, and
+    /// never lose or duplicate a value.
+    ///
+    /// The swap is atomic for [`get_pair`](#method.get_pair), which never sees
+    /// one key swapped and the other not yet. Two separate `get` calls may see
+    /// that, as a swap can run between them. This is not a transaction for the
+    /// other methods: `insert`, `invalidate` and the eviction do not wait for a
+    /// swap. Both entries are written as if inserted, so their time-to-live
+    /// restarts.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("active", "config-v1");
+    /// cache.insert("standby", "config-v2");
+    ///
+    /// assert!(cache.swap("active", "standby"));
+    /// assert_eq!(cache.get(&"active"), Some("config-v2"));
+    /// assert_eq!(cache.get(&"standby"), Some("config-v1"));
+    ///
+    /// assert!(!cache.swap("active", "missing"));
+    /// assert_eq!(cache.get(&"active"), Some("config-v2"));
+    /// ```
+    pub fn swap(&self, key_a: K, key_b: K) -> bool {
+        let key_a = Arc::new(self.base.normalize_key(key_a));
+        let hash_a = self.base.hash(&key_a);
+        let key_b = Arc::new(self.base.normalize_key(key_b));
+        let hash_b = self.base.hash(&key_b);
+
+        let lock_a = self.key_locks.key_lock(&key_a);
+        let lock_b = self.key_locks.key_lock(&key_b);
+        let _guards = lock_a.lock_with(&lock_b, || self.base.record_lock_contention());
+
+        let value_a = self.base.get_with_hash_unnotified(&key_a, hash_a);
+        let value_b = self.base.get_with_hash_unnotified(&key_b, hash_b);
+        match (value_a, value_b) {
+            (Some(value_a), Some(value_b)) => {
+                if key_a != key_b {
+                    self.swap_lock.write(|| {
+                        self.insert_with_hash(key_a, hash_a, value_b);
+                        self.insert_with_hash(key_b, hash_b, value_a);
+                    });
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns _clones_ of the values of the two keys, read atomically with respect
+    /// to [`swap`](#method.swap). Use it to read the two keys of a double buffer,
+    /// so that a concurrent swap is seen either entirely or not at all.
+    ///
+    /// If a swap runs while the values are read, they are read again.
+    ///
+    /// The keys may be any borrowed form of the cache's key type, but `Hash` and
+    /// `Eq` on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("active", "config-v1");
+    /// cache.insert("standby", "config-v2");
+    ///
+    /// cache.swap("active", "standby");
+    /// assert_eq!(
+    ///     cache.get_pair(&"active", &"standby"),
+    ///     (Some("config-v2"), Some("config-v1"))
+    /// );
+    /// ```
+    pub fn get_pair<Q>(&self, key_a: &Q, key_b: &Q) -> (Option<V>, Option<V>)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.swap_lock.read(|| (self.get(key_a), self.get(key_b)))
+    }
+
+    /// Marks the entry for the key as used without reading its value, e.g. to
+    /// keep a session alive on activity signaled out-of-band. Returns `true` if
+    /// the cache had a live (unexpired) entry for the key.
+    ///
+    /// The time-to-idle of the entry always restarts, as if the entry were read.
+    /// When `reset_ttl` is `true`, its time-to-live restarts too, as if the entry
+    /// were written again with the same value. When `reset_ttl` is `false`, the
+    /// time-to-live is left as is, so the entry still expires at the same time
+    /// if the cache has a time-to-live.
+    ///
+    /// Touching an entry does not count as a hit or a miss in the statistics.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::CacheBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let cache = CacheBuilder::new(100)
+    ///     .time_to_live(Duration::from_secs(30 * 60))
+    ///     .time_to_idle(Duration::from_secs(5 * 60))
+    ///     .build();
+    ///
+    /// cache.insert("session:1", "alice");
+    ///
+    /// // Extend both the idle and the maximum lifetime of the session.
+    /// assert!(cache.touch(&"session:1", true));
+    /// assert!(!cache.touch(&"session:2", true));
+    /// ```
+    pub fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.base.normalize_borrowed_key(key) {
+            return self.touch_with_hash::<K>(&key, self.base.hash::<K>(&key), reset_ttl);
+        }
+        self.touch_with_hash(key, self.base.hash(key), reset_ttl)
+    }
+
+    pub(crate) fn touch_with_hash<Q>(&self, key: &Q, hash: u64, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !reset_ttl {
+            return self.base.touch_with_hash(key, hash);
+        }
+        match self.base.do_rewrite_with_hash(key, hash) {
+            Some(op) => {
+                Self::schedule_write_op(&self.base, op).expect("Failed to touch");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.do_invalidate(key, true);
+    }
+
+    /// Discards any cached value for the key, and returns `true` if the key had a
+    /// value that had neither expired nor been invalidated.
+    ///
+    /// A value inserted just before this call counts as present, even if the
+    /// maintenance has not processed the insert yet. When the key is invalidated
+    /// concurrently by multiple threads, only one of them gets `true`.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert(1, "one");
+    ///
+    /// assert!(cache.invalidate_present(&1));
+    /// assert!(!cache.invalidate_present(&1));
+    /// ```
+    pub fn invalidate_present<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.do_invalidate(key, true)
+    }
+
+    /// Schedules the removal of the cached value for the key after the `delay`,
+    /// and returns `true` if the key had a value that had neither expired nor
+    /// been invalidated.
+    ///
+    /// The value stays readable until the delay elapses, and is then removed by
+    /// the same machinery as an expired value, except that the eviction listener
+    /// is notified with `RemovalCause::Explicit`. If the value is going to expire
+    /// earlier by the time-to-live or time-to-idle, it still does. Calling this
+    /// method again can only make the removal sooner, not later.
+    ///
+    /// Inserting a new value for the key cancels the scheduled removal.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert(1, "one");
+    /// cache.insert(2, "two");
+    ///
+    /// assert!(cache.invalidate_after(&1, Duration::from_millis(50)));
+    /// assert!(cache.invalidate_after(&2, Duration::from_millis(50)));
+    /// assert_eq!(cache.get(&1), Some("one"));
+    ///
+    /// // Re-inserting cancels the scheduled removal.
+    /// cache.insert(2, "deux");
+    ///
+    /// std::thread::sleep(Duration::from_millis(100));
+    /// assert_eq!(cache.get(&1), None);
+    /// assert_eq!(cache.get(&2), Some("deux"));
+    /// ```
+    pub fn invalidate_after<Q>(&self, key: &Q, delay: Duration) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.invalidate_after::<K>(&key, delay),
+            None => self.base.invalidate_after(key, delay),
+        }
+    }
+
+    /// Discards any cached value for the key, without publishing the invalidation
+    /// to the [coherence bus][coherence-bus].
+    ///
+    /// Call this method when an invalidation published by the cache of another
+    /// process has been received, so that the invalidation does not bounce back
+    /// and forth between the processes.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, CoherenceBus, PublishOn};
+    /// use std::sync::mpsc::{channel, Sender};
+    /// use std::sync::Mutex;
+    ///
+    /// // A bus for two caches in the same process. A real one will send the keys
+    /// // over the network to the other replicas.
+    /// struct ChannelBus(Mutex<Sender<u32>>);
+    ///
+    /// impl CoherenceBus<u32> for ChannelBus {
+    ///     fn publish_invalidation(&self, key: &u32) {
+    ///         self.0.lock().unwrap().send(*key).unwrap();
+    ///     }
+    /// }
+    ///
+    /// let (tx, rx) = channel();
+    /// let local = Cache::builder()
+    ///     .coherence_bus(ChannelBus(Mutex::new(tx)), PublishOn::InsertAndInvalidate)
+    ///     .build();
+    /// let remote = Cache::new(100);
+    /// remote.insert(1, "one");
+    ///
+    /// local.insert(1, "uno");
+    /// for key in rx.try_iter() {
+    ///     remote.apply_remote_invalidation(&key);
+    /// }
+    /// assert_eq!(remote.get(&1), None);
+    /// ```
+    ///
+    /// [coherence-bus]: ./struct.CacheBuilder.html#method.coherence_bus
+    pub fn apply_remote_invalidation<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.do_invalidate(key, false);
+    }
+
+    /// Returns true if a live entry has been removed.
+    pub(crate) fn do_invalidate<Q>(&self, key: &Q, publish: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let normalized = self.base.normalize_borrowed_key(key);
+        let removed = match &normalized {
+            Some(key) => {
+                self.value_initializer.forget_load_failures::<K>(key);
+                self.base.remove_entry::<K>(key)
+            }
+            None => {
+                self.value_initializer.forget_load_failures(key);
+                self.base.remove_entry(key)
+            }
+        };
+        if let Some(coherence) = self.coherence.as_ref().filter(|_| publish) {
+            // Publish even if the key was not cached locally, as other processes
+            // may have it.
+            coherence.on_invalidate(normalized.as_ref().unwrap_or_else(|| key.as_key()));
+        }
+        if let Some(kv) = removed {
+            let is_live = self.base.is_live(&kv);
+            let op = WriteOp::Remove(kv);
+            Self::schedule_write_op(&self.base, op).expect("Failed to remove");
+            is_live
+        } else {
+            false
+        }
+    }
+
+    /// Applies the inserts and invalidations buffered by the `ops` closure in
+    /// one pass, and returns the number of the operations that changed the cache:
+    /// all the inserts, and the invalidations of the keys that were in the cache.
+    /// The invalidations of absent keys are not counted.
+    ///
+    /// The operations are applied in the order they were buffered, after the
+    /// closure returns. Unlike calling `insert` and `invalidate` one by one, the
+    /// batch does not check whether the maintenance is needed after every
+    /// operation, and schedules one maintenance pass for the whole batch when it
+    /// is done. So the eviction and the expiration process the batch together.
+    ///
+    /// This is not a transaction. The other threads may see some of the
+    /// operations applied and the rest not yet, and their own writes may
+    /// interleave with the batch. The batch only makes that window shorter.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("user:1:name", "alice");
+    ///
+    /// let applied = cache.batch(|ops| {
+    ///     ops.insert("user:2:name", "bob");
+    ///     ops.insert("user:2:email", "bob@example.com");
+    ///     ops.invalidate("user:1:name");
+    /// });
+    /// assert_eq!(applied, 3);
+    /// assert_eq!(cache.get(&"user:2:name"), Some("bob"));
+    /// assert_eq!(cache.get(&"user:1:name"), None);
+    /// ```
+    pub fn batch(&self, ops: impl FnOnce(&mut BatchOps<K, V>)) -> usize {
+        let mut batch = BatchOps::new();
+        ops(&mut batch);
+        let mut applied = 0;
+
+        for op in batch.into_ops() {
+            let op = match op {
+                BatchOp::Insert(key, value) => {
+                    let key = self.base.normalize_key(key);
+                    let hash = self.base.hash(&key);
+                    let key = Arc::new(key);
+                    if let Some(coherence) = &self.coherence {
+                        coherence.on_insert(&key);
+                    }
+                    Some(self.base.do_insert_with_hash(key, hash, value))
+                }
+                BatchOp::Invalidate(key) => {
+                    let key = self.base.normalize_key(key);
+                    if let Some(coherence) = &self.coherence {
+                        coherence.on_invalidate(&key);
+                    }
+                    self.base.remove_entry::<K>(&key).map(WriteOp::Remove)
+                }
+            };
+            if let Some(op) = op {
+                Self::send_batched_write_op(&self.base, op).expect("Failed to apply a batch");
+                applied += 1;
+            }
+        }
+
+        if let Some(h) = &self.base.housekeeper {
+            h.try_schedule_sync();
+        }
+        applied
+    }
+
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately and a background thread will evict all the
+    /// cached values inserted before the time when this method was called. It is
+    /// guaranteed that the `get` method must not return these invalidated values
+    /// even if they have not been evicted.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    ///
+    /// The eviction listener is notified of the discarded entries with
+    /// `RemovalCause::Explicit`. This is the same as
+    /// [`clear_with(true)`](#method.clear_with).
+    pub fn invalidate_all(&self) {
+        self.clear_with(true);
+    }
+
+    /// Discards all cached values without notifying the eviction listener. This
+    /// is the same as [`clear_with(false)`](#method.clear_with).
+    pub fn clear(&self) {
+        self.clear_with(false);
+    }
+
+    /// Discards all cached values, and notifies the eviction listener of them
+    /// with `RemovalCause::Explicit` only when `notify` is `true`.
+    ///
+    /// In both modes, this method returns immediately, and the background thread
+    /// removes the discarded entries in batches, as described in
+    /// [`invalidate_all`](#method.invalidate_all). The `get` method never
+    /// returns them.
+    ///
+    /// The difference is the cost of the removal. With `notify`, the
+    /// background thread clones each value and calls the listener for it, so
+    /// clearing a large cache delays the other maintenance work by the time the
+    /// listener takes for all entries. Without `notify`, the entries are only
+    /// dropped, which is the fastest way to reclaim the memory. This makes no
+    /// difference when the cache has no eviction listener.
+    ///
+    /// Entries inserted after this call are not affected, and are notified as
+    /// usual when they are removed later.
+    pub fn clear_with(&self, notify: bool) {
+        self.value_initializer.forget_all_load_failures();
+        self.base.invalidate_all(notify);
+    }
+
+    /// Discards all cached values except the ones for which `keep` returns `true`.
+    ///
+    /// This is useful to clear a cache on a reload while keeping a few pinned
+    /// entries. Unlike [`invalidate_entries_if`](#method.invalidate_entries_if),
+    /// this method does not require the invalidation closures to be enabled, and
+    /// finishes the work before returning: it first applies the pending writes,
+    /// and then calls `keep` once for each cached entry. When it returns, the
+    /// `get` method will not return the discarded values.
+    ///
+    /// `keep` must not modify this cache. Entries inserted concurrently by other
+    /// threads may or may not be visited.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// for i in 0..10 {
+    ///     cache.insert(i, i);
+    /// }
+    ///
+    /// cache.invalidate_all_except(|k, _v| *k < 2);
+    ///
+    /// assert_eq!(cache.get(&0), Some(0));
+    /// assert_eq!(cache.get(&1), Some(1));
+    /// assert_eq!(cache.get(&2), None);
+    /// ```
+    pub fn invalidate_all_except<F>(&self, keep: F)
+    where
+        F: Fn(&K, &V) -> bool,
+    {
+        // Apply the pending writes so that all entries inserted by now are visited.
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+        for kv in self.base.remove_entries_except(keep) {
+            self.value_initializer.forget_load_failures(&kv.key);
+            Self::schedule_write_op(&self.base, WriteOp::Remove(kv)).expect("Failed to remove");
+        }
+    }
+
+    /// Discards all entries in the group `group`, which were inserted by
+    /// [`insert_in_group`](#method.insert_in_group).
+    ///
+    /// The entries are removed before this method returns, and the eviction
+    /// listener is notified of them with `RemovalCause::Explicit`. Entries that
+    /// have been moved to another group are not affected. Does nothing if the
+    /// group has no entries.
+    pub fn invalidate_group(&self, group: &str) {
+        for kv in self.base.remove_group(group) {
+            self.value_initializer.forget_load_failures(&kv.key);
+            if let Some(coherence) = &self.coherence {
+                coherence.on_invalidate(&kv.key);
+            }
+            Self::schedule_write_op(&self.base, WriteOp::Remove(kv)).expect("Failed to remove");
+        }
+    }
+
+    /// Evicts up to `n` entries right away, and returns the number of evicted
+    /// entries.
+    ///
+    /// The entries are chosen in the same order as the eviction driven by the max
+    /// capacity, i.e. the least recently used entries in the probation region
+    /// first, so that the coldest entries are evicted. You can use this method to
+    /// shed memory in response to a memory pressure signal without waiting for
+    /// the cache to reach its capacity.
+    ///
+    /// Entries weighted zero by the [weigher][weigher] are skipped, as evicting
+    /// them would not free any capacity. This method applies the pending writes
+    /// before choosing the entries, and evicts nothing while a
+    /// [`FrozenView`][frozen-view] of the cache is alive.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// for i in 0..10 {
+    ///     cache.insert(i, i);
+    /// }
+    ///
+    /// assert_eq!(cache.evict_now(3), 3);
+    /// // The oldest entries were the coldest.
+    /// assert_eq!(cache.get(&0), None);
+    /// assert_eq!(cache.get(&3), Some(3));
+    /// ```
+    ///
+    /// [weigher]: ./struct.CacheBuilder.html#method.weigher
+    /// [frozen-view]: ./struct.FrozenView.html
+    pub fn evict_now(&self, n: usize) -> usize {
+        self.base.evict_now(n)
+    }
+
+    /// Catches up on the deferred setup and maintenance of this cache, so that
+    /// the next operations do not pay for them.
+    ///
+    /// The buffers recording the reads and writes are allocated by the first
+    /// write to the cache, and the expired entries are removed by the
+    /// maintenance little by little. The maintenance runs on the housekeeping
+    /// threads, and the user operations never run it on their own thread. Call
+    /// this method at a moment of your own, e.g. at startup or when the
+    /// application is idle, to allocate the buffers, and to apply the pending
+    /// reads and writes and remove the expired entries and the entries over the
+    /// capacity at once. Does nothing but the allocation while a
+    /// [`FrozenView`][frozen-view] of the cache is alive.
+    ///
+    /// [frozen-view]: ./struct.FrozenView.html
+    pub fn prepare(&self) {
+        self.base.prepare();
+    }
+
+    /// Returns the popularity of the given keys as estimated by the cache, most
+    /// popular first.
+    ///
+    /// The estimate is the access count recorded by the frequency sketch that the
+    /// admission policy consults when the cache is full, so this shows how the
+    /// policy sees the keys. The sketch cannot enumerate the keys, so pass the
+    /// candidates to look up, e.g. the keys iterated over a
+    /// [`freeze`](#method.freeze)d view, or the keys you expected to be admitted.
+    /// The keys do not need to be in the cache.
+    ///
+    /// Each key is returned in its normalized form, with an estimate from `0` to
+    /// `15`. Keys with the same estimate keep the order of `sample_keys`.
+    ///
+    /// Note the following about the estimates:
+    ///
+    /// - They are approximate: the sketch may overestimate a key that shares
+    ///   counters with popular keys, and it halves all counts periodically so that
+    ///   old popularity fades away.
+    /// - They are all `0` until the cache is about half full, or when the cache
+    ///   was built with the [`small`][small] hint, as no sketch is kept then.
+    /// - Recent reads are recorded to the sketch by the maintenance, so they may
+    ///   not be counted yet.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, ConcurrentCacheExt};
+    ///
+    /// let cache = Cache::new(4);
+    /// for i in 0..4 {
+    ///     cache.insert(i, i);
+    /// }
+    /// cache.sync();
+    ///
+    /// for _ in 0..5 {
+    ///     cache.get(&2);
+    /// }
+    /// cache.sync();
+    ///
+    /// let freqs = cache.top_frequencies(&[0, 1, 2, 3]);
+    /// assert_eq!(freqs[0].0, 2);
+    /// ```
+    ///
+    /// [small]: ./struct.CacheBuilder.html#method.small
+    pub fn top_frequencies<'a>(&self, sample_keys: impl IntoIterator<Item = &'a K>) -> Vec<(K, u8)>
+    where
+        K: Clone + 'a,
+    {
+        let mut freqs = sample_keys
+            .into_iter()
+            .map(|key| {
+                let key = self.base.normalize_key(key.clone());
+                let freq = self.estimated_frequency_with_hash(self.base.hash(&key));
+                (key, freq)
+            })
+            .collect::<Vec<_>>();
+        freqs.sort_by_key(|(_, freq)| std::cmp::Reverse(*freq));
+        freqs
+    }
+
+    /// Returns the number of accesses to the key estimated by the frequency sketch
+    /// of the cache, from `0` to `15`.
+    ///
+    /// The admission policy compares these estimates to decide whether a new entry
+    /// replaces the entries it would evict, so this helps to understand why a key
+    /// was or was not admitted. The estimate is approximate: the sketch is a
+    /// count-min sketch, so a key sharing its counters with popular keys may be
+    /// overestimated, but never underestimated until the next aging. Every time
+    /// the sketch has recorded a certain number of accesses (ten times the
+    /// capacity of the cache), all counts are halved, so the estimate reflects
+    /// the accesses in the current aging period and, at a decreasing weight, the
+    /// earlier ones. See [`top_frequencies`](#method.top_frequencies) for when the
+    /// estimates are not recorded yet.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn estimated_frequency<Q>(&self, key: &Q) -> u8
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let hash = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.hash::<K>(&key),
+            None => self.base.hash(key),
+        };
+        self.estimated_frequency_with_hash(hash)
+    }
+
+    #[inline]
+    pub(crate) fn estimated_frequency_with_hash(&self, hash: u64) -> u8 {
+        self.base.estimated_frequency(hash)
+    }
+
+    /// Returns the state learned by the admission policy of the cache: the
+    /// counters of its frequency sketch, and which keys are in the protected
+    /// region. The entries themselves are not included.
+    ///
+    /// Serialize the state with `serde` and give it to
+    /// [`import_policy_state`](#method.import_policy_state) of the cache replacing
+    /// this one (e.g. after a restart), so that the new cache admits the entries
+    /// by the popularity learned here rather than by an empty sketch.
+    ///
+    /// # Example
+    ///
+    /// The sketch is keyed by the hashes of the keys, so both caches must hash
+    /// the keys in the same way, which the default `RandomState` does not do
+    /// across restarts.
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+    ///
+    /// type FixedState = BuildHasherDefault<DefaultHasher>;
+    ///
+    /// let cache: Cache<u32, String, FixedState> = Cache::builder()
+    ///     .max_capacity(1_000)
+    ///     .build_with_hasher(FixedState::default());
+    /// # cache.insert(0, "zero".to_string());
+    ///
+    /// let json = serde_json::to_string(&cache.export_policy_state()).unwrap();
+    ///
+    /// // After a restart.
+    /// let cache: Cache<u32, String, FixedState> = Cache::builder()
+    ///     .max_capacity(1_000)
+    ///     .build_with_hasher(FixedState::default());
+    /// assert!(cache.import_policy_state(&serde_json::from_str(&json).unwrap()));
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn export_policy_state(&self) -> PolicyState {
+        self.base.export_policy_state()
+    }
+
+    /// Replaces the state learned by the admission policy of the cache with the
+    /// one exported by [`export_policy_state`](#method.export_policy_state).
+    /// Returns `true` if the state has been imported.
+    ///
+    /// The frequency sketch takes the counters of the state. The entries present
+    /// in the cache that were in the protected region of the exporting cache are
+    /// promoted to the protected region, if this cache is built with
+    /// `promote_after_hits`. So import the state after restoring the entries, if
+    /// you restore them.
+    ///
+    /// The import is best-effort. The state is ignored, leaving the cache as it
+    /// is, and `false` is returned if:
+    ///
+    /// - the state is in another format version (see
+    ///   [`PolicyState`][policy-state]), or
+    /// - the max capacity of the cache differs from the one of the exporting
+    ///   cache, or the cache is small enough to use the plain LRU policy, or
+    /// - the hasher of the cache hashes the keys differently from the one of the
+    ///   exporting cache, or
+    /// - the sketch of the cache has already been sized differently, which can
+    ///   happen with a weigher as the sketch is then sized by the number of the
+    ///   entries.
+    ///
+    /// [policy-state]: ../policy/struct.PolicyState.html
+    #[cfg(feature = "serde")]
+    pub fn import_policy_state(&self, state: &PolicyState) -> bool {
+        self.base.import_policy_state(state)
+    }
+
+    /// Discards cached values that satisfy a predicate.
+    ///
+    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
+    /// method returns immediately and a background thread will apply the closure to
+    /// each cached value inserted before the time when `invalidate_entries_if` was
+    /// called. If the closure returns `true` on a value, that value will be evicted
+    /// from the cache.
+    ///
+    /// Also the `get` method will apply the closure to a value to determine if it
+    /// should have been invalidated. Therefore, it is guaranteed that the `get`
+    /// method must not return invalidated values.
+    ///
+    /// Note that you must call
+    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
+    /// at the cache creation time as the cache needs to maintain additional internal
+    /// data structures to support this method. Otherwise, calling this method will
+    /// fail with a
+    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    ///
+    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
+    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
+    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.base.invalidate_entries_if(Arc::new(predicate))
+    }
+
+    pub(crate) fn invalidate_entries_with_arc_fun<F>(
+        &self,
+        predicate: Arc<F>,
+    ) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.base.invalidate_entries_if(predicate)
+    }
+
+    /// Returns the name of this cache, if it has been set by the
+    /// `CacheBuilder::name` method.
+    pub fn name(&self) -> Option<&str> {
+        self.base.name()
+    }
+
+    /// Returns the `max_capacity` of this cache.
+    pub fn max_capacity(&self) -> Option<usize> {
+        self.base.max_capacity()
+    }
+
+    pub(crate) fn lock_contentions(&self) -> u64 {
+        self.base.lock_contentions()
+    }
+
+    /// Returns the weight that the [weigher][weigher] of this cache gives to the
+    /// key-value pair, or `None` if the cache was built without a weigher.
+    ///
+    /// Nothing is inserted. This lets you check whether a value is worth
+    /// inserting, e.g. against the [`max_capacity`](#method.max_capacity), with
+    /// the same weigher the cache uses, without repeating the closure.
+    ///
+    /// The weigher is your code. It is called on the calling thread, without
+    /// holding any lock of the cache.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::builder()
+    ///     .weigher(|_key: &&str, value: &Vec<u8>| value.len() as u32)
+    ///     .max_capacity(1024 * 1024)
+    ///     .build();
+    ///
+    /// let response = vec![0u8; 4 * 1024 * 1024];
+    /// let weight = cache.weigh(&"/large", &response).unwrap();
+    /// if (weight as usize) <= cache.max_capacity().unwrap() {
+    ///     cache.insert("/large", response);
+    /// }
+    /// assert_eq!(cache.get(&"/large"), None);
+    /// ```
+    ///
+    /// [weigher]: ./struct.CacheBuilder.html#method.weigher
+    pub fn weigh(&self, key: &K, value: &V) -> Option<u32> {
+        self.base.weigher().map(|w| w(key, value))
+    }
+
+    /// Returns the `initial_capacity` of this cache.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        self.base.initial_capacity()
+    }
+
+    /// Returns the `time_to_live` of this cache.
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.base.time_to_live()
+    }
+
+    /// Returns the `time_to_idle` of this cache.
+    pub fn time_to_idle(&self) -> Option<Duration> {
+        self.base.time_to_idle()
+    }
+
+    /// Returns `true` if any entry of this cache has been given its own
+    /// expiration, by [`insert_with_tti`](#method.insert_with_tti) or
+    /// [`invalidate_after`](#method.invalidate_after).
+    ///
+    /// It stays `true` after such entries have been removed.
+    pub fn has_per_entry_expiration(&self) -> bool {
+        self.base.has_per_entry_expiration()
+    }
+
+    /// Returns the `max_age` of this cache.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.base.max_age().map(|ma| ma.duration)
+    }
+
+    /// Returns the keys of this cache with the instants when they will expire,
+    /// sorted from the soonest to the latest.
+    ///
+    /// An entry expires at the earliest of `last insert + time_to_live`, `last
+    /// access + time_to_idle` and `creation + max_age`. If none of them is
+    /// configured, this method returns an empty `Vec`.
+    ///
+    /// This method is intended for diagnostics and is not cheap: it walks the
+    /// internal access-order queues while holding their lock, then sorts the
+    /// result in `O(n log n)`. The result is weakly consistent; it reflects the
+    /// state as of the last time the pending reads and writes were applied to the
+    /// queues, so recently inserted or read entries may be missing or have stale
+    /// instants. Entries that have already expired or been invalidated are
+    /// excluded.
+    pub fn entries_by_expiration(&self) -> Vec<(Arc<K>, std::time::Instant)> {
+        self.base.entries_by_expiration()
+    }
+
+    /// Returns an iterator over the entries of this cache in the order they will
+    /// expire, yielding the key, a _clone_ of the value, and the instant when the
+    /// entry will expire.
+    ///
+    /// If `time_to_live` is configured, the entries are visited in the order of
+    /// their last insert, which is exactly the expiration order. Otherwise, if
+    /// `time_to_idle` is configured, they are visited in the order of their last
+    /// access, which is again exactly the expiration order. If both are configured,
+    /// an entry can expire earlier by `time_to_idle` than the entries before it,
+    /// so the order is approximate. If neither is configured, the iterator yields
+    /// nothing.
+    ///
+    /// The iterator does not hold a lock for the whole iteration. It visits the
+    /// internal queue in chunks, taking the lock only while copying out a chunk.
+    /// Therefore the iteration is weakly consistent: an entry inserted or updated
+    /// during the iteration may or may not be yielded, and an updated entry may be
+    /// yielded twice. Like [`entries_by_expiration`](#method.entries_by_expiration),
+    /// it reflects the queue as of the last time the pending writes were applied.
+    /// Entries that have already expired or been invalidated are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let cache = Cache::builder()
+    ///     .time_to_live(Duration::from_secs(600))
+    ///     .build();
+    /// cache.insert("a", 1);
+    /// # use moka::sync::ConcurrentCacheExt;
+    /// # cache.sync();
+    ///
+    /// // What will fall out of the cache in the next 60 seconds?
+    /// let deadline = Instant::now() + Duration::from_secs(60);
+    /// let expiring = cache
+    ///     .iter_by_expiration()
+    ///     .take_while(|(_, _, expires_at)| *expires_at <= deadline)
+    ///     .count();
+    /// assert_eq!(expiring, 0);
+    /// ```
+    pub fn iter_by_expiration(&self) -> impl Iterator<Item = (Arc<K>, V, std::time::Instant)> + '_ {
+        self.base.iter_by_expiration()
+    }
+
+    /// Calls the closure with a reference to the key and the value of every entry
+    /// in this cache, without cloning the values.
+    ///
+    /// The entries are visited in chunks. The lock of the internal queues is held
+    /// while the closure is called for the entries in a chunk, and is released
+    /// between chunks. Therefore the closure must be fast, and it must not call
+    /// back into this cache (e.g. `insert` or `sync`); doing so may deadlock or
+    /// stall the maintenance. If you need to do more work per entry, copy out
+    /// what you need and process it after this method returns.
+    ///
+    /// Like [`iter_by_expiration`](#method.iter_by_expiration), the visit is
+    /// weakly consistent and reflects the queues as of the last time the pending
+    /// writes were applied, so a recently inserted entry may be missed until
+    /// [`sync`](./trait.ConcurrentCacheExt.html#tymethod.sync) is called. Entries
+    /// that have already expired or been invalidated are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, ConcurrentCacheExt};
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("a", vec![0u8; 1024]);
+    /// cache.insert("b", vec![0u8; 2048]);
+    /// cache.sync();
+    ///
+    /// let mut total_len = 0;
+    /// cache.for_each_entry(|_key, value| total_len += value.len());
+    /// assert_eq!(total_len, 3072);
+    /// ```
+    pub fn for_each_entry(&self, mut f: impl FnMut(&K, &V)) {
+        self.base.entry_visitor().visit_all(|k, v| f(k, v))
+    }
+
+    /// Returns the keys of the entries whose values satisfy the predicate.
+    ///
+    /// This is a convenience for reverse lookups, e.g. finding all entries in a
+    /// certain state. It scans all entries in `O(n)` time, but does not clone the
+    /// values nor record the accesses to the entries.
+    ///
+    /// Like [`for_each_entry`](#method.for_each_entry), the scan does not hold a
+    /// lock for the whole cache; the entries are visited in chunks, and the
+    /// predicate is called while holding the lock for a chunk. Therefore the
+    /// predicate must be fast and must not call back into this cache. The result
+    /// is weakly consistent, and a recently inserted entry may be missed until
+    /// [`sync`](./trait.ConcurrentCacheExt.html#tymethod.sync) is called.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::{Cache, ConcurrentCacheExt};
+    /// use std::sync::Arc;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("job1", "running");
+    /// cache.insert("job2", "done");
+    /// cache.insert("job3", "running");
+    /// cache.sync();
+    ///
+    /// let mut running = cache.keys_matching(|state| *state == "running");
+    /// running.sort();
+    /// assert_eq!(running, vec![Arc::new("job1"), Arc::new("job3")]);
+    /// ```
+    pub fn keys_matching(&self, predicate: impl Fn(&V) -> bool) -> Vec<Arc<K>> {
+        let mut keys = Vec::new();
+        self.base.entry_visitor().visit_all(|k, v| {
+            if predicate(v) {
+                keys.push(Arc::clone(k));
+            }
+        });
+        keys
+    }
+
+    /// Transforms the value of every entry in this cache in place, by calling the
+    /// closure with the key and a mutable reference to a clone of the value.
+    ///
+    /// This is an administrative tool for changes that must apply to all values
+    /// consistently, e.g. a schema migration. Each entry is swapped atomically
+    /// with the transformed one, so a reader sees either the old or the new value,
+    /// never a partially transformed one. If another thread updates an entry at
+    /// the same time, the closure is called again with the updated value, so the
+    /// update is not lost; therefore the closure may be called more than once for
+    /// an entry.
+    ///
+    /// This method first applies the pending writes, and then visits all entries
+    /// in a single pass, which takes `O(n)` time and can be long for a large
+    /// cache. Entries inserted concurrently by other threads may or may not be
+    /// visited, and expired or invalidated entries are skipped. The closure must
+    /// not modify this cache.
+    ///
+    /// A transformed entry keeps its timestamps for expiration and its weight,
+    /// and the eviction listener is not notified. To have the expiration or the
+    /// weight updated, re-insert the entry instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("alice", "Alice".to_string());
+    /// cache.insert("bob", "Bob".to_string());
+    ///
+    /// cache.transform_values(|_key, name| name.make_ascii_uppercase());
+    ///
+    /// assert_eq!(cache.get(&"alice"), Some("ALICE".to_string()));
+    /// assert_eq!(cache.get(&"bob"), Some("BOB".to_string()));
+    /// ```
+    pub fn transform_values(&self, f: impl FnMut(&K, &mut V)) {
+        // Apply the pending writes so that all entries inserted by now are visited.
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+        self.base.transform_values(f);
+    }
+
+    /// Creates a [`KeyHandle`][key-handle] to read the value of the key repeatedly
+    /// without looking up the hash table every time.
+    ///
+    /// This is useful for a hot key that is read much more often than the cache
+    /// is written. The handle bypasses the [thread local cache][thread-local-cache].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("config", 1);
+    ///
+    /// let mut handle = cache.handle("config");
+    /// assert_eq!(handle.get(), Some(1));
+    ///
+    /// cache.insert("config", 2);
+    /// assert_eq!(handle.get(), Some(2));
+    ///
+    /// cache.invalidate(&"config");
+    /// assert_eq!(handle.get(), None);
+    /// ```
+    ///
+    /// [key-handle]: ./struct.KeyHandle.html
+    /// [thread-local-cache]: ./struct.CacheBuilder.html#method.thread_local_cache
+    pub fn handle(&self, key: K) -> KeyHandle<'_, K, V, S> {
+        let key = self.base.normalize_key(key);
+        let hash = self.base.hash(&key);
+        self.handle_with_hash(Arc::new(key), hash)
+    }
+
+    pub(crate) fn handle_with_hash(&self, key: Arc<K>, hash: u64) -> KeyHandle<'_, K, V, S> {
+        KeyHandle::new(&self.base, key, hash)
+    }
+
+    /// Creates a [`SnapshotHandle`][snapshot-handle] to read this cache as if
+    /// [`invalidate_all`](#method.invalidate_all) had not been called after the
+    /// handle was created.
+    ///
+    /// This gives a weak snapshot isolation to a read path doing several `get`s:
+    /// the entries do not vanish midway because of a mass invalidation. It does
+    /// not prevent individual entries from being evicted, expiring or being
+    /// invalidated.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("a", 1);
+    /// # use moka::sync::ConcurrentCacheExt;
+    /// # cache.sync();
+    ///
+    /// let snapshot = cache.snapshot_handle();
+    /// cache.invalidate_all();
+    ///
+    /// assert_eq!(cache.get(&"a"), None);
+    /// assert_eq!(snapshot.get(&"a"), Some(1));
+    /// ```
+    ///
+    /// [snapshot-handle]: ./struct.SnapshotHandle.html
+    pub fn snapshot_handle(&self) -> SnapshotHandle<'_, K, V, S> {
+        SnapshotHandle::new(&self.base)
+    }
+
+    /// Creates a [`FrozenView`][frozen-view] of this cache, whose entry count and
+    /// entries correspond to the same point in time.
+    ///
+    /// The pending writes are applied first, and then the maintenance of the cache
+    /// is paused until the view is dropped, or five seconds have passed. Use
+    /// [`freeze_for`](#method.freeze_for) to specify a different maximum duration.
+    /// Keep the view short-lived, as nothing is evicted from the cache while it is
+    /// alive.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// for i in 0..10 {
+    ///     cache.insert(i, i % 3);
+    /// }
+    ///
+    /// let view = cache.freeze();
+    /// let zeros = view.iter().filter(|(_k, v)| *v == 0).count() as u64;
+    /// assert_eq!(view.entry_count(), 10);
+    /// assert_eq!(zeros, 4);
+    /// ```
+    ///
+    /// [frozen-view]: ./struct.FrozenView.html
+    pub fn freeze(&self) -> FrozenView<'_, K, V, S> {
+        self.freeze_for(DEFAULT_MAX_FREEZE_DURATION)
+    }
+
+    /// Creates a [`FrozenView`][frozen-view] of this cache, which pauses the
+    /// maintenance of the cache for up to `max_duration`.
+    ///
+    /// [frozen-view]: ./struct.FrozenView.html
+    pub fn freeze_for(&self, max_duration: Duration) -> FrozenView<'_, K, V, S> {
+        FrozenView::new(&self.base, max_duration)
+    }
+
+    /// Returns a snapshot of the statistics of this cache.
+    ///
+    /// The statistics are recorded only if the cache was built with
+    /// [`CacheBuilder::record_stats`][record-stats]. Otherwise, this method
+    /// returns a `CacheStats` with all counts set to zero.
+    ///
+    /// [record-stats]: ./struct.CacheBuilder.html#method.record_stats
+    pub fn stats(&self) -> CacheStats {
+        self.base.stats()
+    }
+
+    /// Returns a snapshot of the statistics of this cache, and resets the counts
+    /// to zero.
+    ///
+    /// This is intended for delta-based metric systems that export the counts
+    /// periodically. Reading with `stats` and then resetting separately would lose
+    /// the events recorded between the two calls. Here, each count is read and
+    /// zeroed by a single atomic swap without taking a lock, so every event is
+    /// counted by exactly one call. The counts are swapped one by one, so they
+    /// may not be consistent with each other, e.g. a hit may be in this snapshot
+    /// and its latency sample in the next.
+    ///
+    /// The `distinct_keys_estimate` is counted over the lifetime of the cache and
+    /// is not reset.
+    ///
+    /// [`CacheBuilder::record_stats`][record-stats] must be set for the counts to
+    /// be recorded.
+    ///
+    /// [record-stats]: ./struct.CacheBuilder.html#method.record_stats
+    pub fn stats_and_reset(&self) -> CacheStats {
+        self.base.stats_and_reset()
+    }
+
+    /// Returns the number of internal segments of this cache.
+    ///
+    /// `Cache` always returns `1`.
+    pub fn num_segments(&self) -> usize {
+        1
+    }
+
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.contains_key(key)
+    }
+
+    pub(crate) fn estimated_entry_count(&self) -> u64 {
+        self.base.estimated_entry_count()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn weighted_size(&self) -> u64 {
+        self.base.weighted_size()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn bucket_capacity(&self) -> usize {
+        self.base.bucket_capacity()
+    }
+}
+
+impl<K, V, S> ConcurrentCacheExt<K, V> for Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn sync(&self) {
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+    }
+}
+
+impl<K, T, S> Cache<K, Weak<T>, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Returns the object the cached `Weak` for the key points to, or `None` if
+    /// the key has no value or the object has been dropped. A dead entry found by
+    /// this method is removed at once, with `RemovalCause::Collected`.
+    ///
+    /// See [`CacheBuilder::weak_values`][weak-values] for the caches of `Weak`s.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// [weak-values]: ./struct.CacheBuilder.html#method.weak_values
+    pub fn get_upgraded<Q>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(value) = self.get(key).and_then(|weak| weak.upgrade()) {
+            return Some(value);
+        }
+        let removed = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.remove_if_collected::<K>(&key),
+            None => self.base.remove_if_collected(key),
+        };
+        if let Some(kv) = removed {
+            let op = WriteOp::Remove(kv);
+            Self::schedule_write_op(&self.base, op).expect("Failed to remove");
+        }
+        None
+    }
+}
+
+// Methods used by `TieredCache` to move entries between the tiers.
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Inserts the value, and returns the normalized key.
+    pub(crate) fn insert_returning_key(&self, key: K, value: V) -> Arc<K> {
+        let key = Arc::new(self.base.normalize_key(key));
+        let hash = self.base.hash::<K>(&key);
+        self.insert_with_hash(Arc::clone(&key), hash, value);
+        key
+    }
+
+    /// Inserts the value for a key normalized by another cache.
+    pub(crate) fn insert_normalized(&self, key: Arc<K>, value: V) {
+        let hash = self.base.hash::<K>(&key);
+        self.insert_with_hash(key, hash, value);
+    }
+
+    /// Inserts the value for a key normalized by another cache unless the key
+    /// already has a value, and returns the value the key has after the call.
+    pub(crate) fn insert_normalized_if_absent(&self, key: Arc<K>, value: V) -> V {
+        let hash = self.base.hash::<K>(&key);
+        match self.insert_if_version_with_hash(key, hash, value.clone(), None) {
+            Ok(_) => value,
+            Err(CasError {
+                current: Some((current, _)),
+                ..
+            }) => current,
+            Err(CasError { value, .. }) => value,
+        }
+    }
+
+    /// Removes the entry without notifying the eviction listener, and returns the
+    /// key and the value if the entry had neither expired nor been invalidated.
+    pub(crate) fn remove_unnotified<Q>(&self, key: &Q) -> Option<(Arc<K>, V)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let kv = match self.base.normalize_borrowed_key(key) {
+            Some(key) => self.base.remove_entry_unnotified::<K>(&key),
+            None => self.base.remove_entry_unnotified(key),
+        }?;
+        let live = if self.base.is_live(&kv) {
+            Some((Arc::clone(&kv.key), kv.entry.value().clone()))
+        } else {
+            None
+        };
+        let op = WriteOp::Remove(kv);
+        Self::schedule_write_op(&self.base, op).expect("Failed to remove");
+        live
+    }
+}
+
+// private methods
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    #[inline]
+    fn schedule_write_op(
+        base: &BaseCache<K, V, S>,
+        op: WriteOp<K, V>,
+    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let ch = base.write_op_ch();
+        let mut op = op;
+
+        // NOTES:
+        // - This will block when the channel is full.
+        // - We are doing a busy-loop here. We were originally calling `ch.send(op)?`,
+        //   but we got a notable performance degradation.
+        loop {
+            BaseCache::apply_reads_writes_if_needed(ch, base.housekeeper.as_ref());
+            match ch.try_send(op) {
+                Ok(()) => break,
+                Err(TrySendError::Full(op1)) => {
+                    op = op1;
+                    base.try_apply_writes();
+                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
+                }
+                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `schedule_write_op`, but only schedules the maintenance when the
+    /// channel is full, so that a batch schedules it once when it is done.
+    fn send_batched_write_op(
+        base: &BaseCache<K, V, S>,
+        op: WriteOp<K, V>,
+    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let ch = base.write_op_ch();
+        let mut op = op;
+        loop {
+            match ch.try_send(op) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(op1)) => {
+                    op = op1;
+                    if let Some(h) = &base.housekeeper {
+                        h.try_schedule_sync();
+                    }
+                    base.try_apply_writes();
+                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
+                }
+                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+            }
+        }
+    }
+}
+
+// For unit tests.
+#[cfg(test)]
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn is_table_empty(&self) -> bool {
+        self.estimated_entry_count() == 0
+    }
+
+    pub(crate) fn invalidation_predicate_count(&self) -> usize {
+        self.base.invalidation_predicate_count()
+    }
+
+    pub(crate) fn reconfigure_for_testing(&mut self) {
+        self.base.reconfigure_for_testing();
+    }
+
+    pub(crate) fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
+        self.base.set_expiration_clock(clock);
+    }
+}
+
+// For the `test_util` module.
+#[cfg(feature = "test-util")]
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn use_mock_clock(&self) {
+        self.base.use_mock_clock();
+    }
+
+    pub(crate) fn advance_mock_clock(&self, duration: Duration) -> bool {
+        self.base.advance_mock_clock(duration)
+    }
+
+    pub(crate) fn run_until_quiescent(&self) {
+        self.base.run_until_quiescent();
+    }
+}
+
+// To see the debug prints, run test as `cargo test -- --nocapture`
+#[cfg(test)]
+mod tests {
+    use super::{Cache, ConcurrentCacheExt};
+    use crate::{common::time::Clock, sync::CacheBuilder, LoadError};
+
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn basic_single_thread() {
+        let mut cache = Cache::new(3);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.sync();
+        // counts: a -> 1, b -> 1
+
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        // counts: a -> 1, b -> 1, c -> 1
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.sync();
+        // counts: a -> 2, b -> 2, c -> 1
+
+        // "d" should not be admitted because its frequency is too low.
+        cache.insert("d", "david"); //   count: d -> 0
+        cache.sync();
+        assert_eq!(cache.get(&"d"), None); //   d -> 1
+
+        cache.insert("d", "david");
+        cache.sync();
+        assert_eq!(cache.get(&"d"), None); //   d -> 2
+
+        // "d" should be admitted and "c" should be evicted
+        // because d's frequency is higher than c's.
+        cache.insert("d", "dennis");
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"d"), Some("dennis"));
+
+        cache.invalidate(&"b");
+        assert_eq!(cache.get(&"b"), None);
+    }
+
+    #[test]
+    fn evict_now() {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .weigher(|k: &u32, _v: &u32| if *k == 9 { 0 } else { 1 })
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+        cache.sync();
+        // Make 0 and 1 the most recently used ones.
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), Some(1));
+
+        // Pending reads are applied first.
+        assert_eq!(cache.evict_now(3), 3);
+        assert_eq!(cache.estimated_entry_count(), 7);
+        for i in 2..5 {
+            assert_eq!(cache.get(&i), None);
+        }
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&5), Some(5));
+
+        // Key 9 has zero weight and is never evicted.
+        assert_eq!(cache.evict_now(100), 6);
+        assert_eq!(cache.estimated_entry_count(), 1);
+        assert_eq!(cache.weighted_size(), 0);
+        assert_eq!(cache.get(&9), Some(9));
+        assert_eq!(cache.evict_now(1), 0);
+    }
+
+    #[test]
+    fn lazy_op_channels() {
+        let cache = Cache::new(100);
+        assert!(!cache.base.has_op_channels());
+
+        // Reading, invalidating and syncing a never-written cache do not create
+        // the op channels nor allocate the buckets of the hash table.
+        assert_eq!(cache.get(&1), None);
+        cache.invalidate(&1);
+        cache.sync();
+        assert!(!cache.base.has_op_channels());
+        assert_eq!(cache.bucket_capacity(), 0);
+
+        cache.insert(1, "alice");
+        assert!(cache.base.has_op_channels());
+        assert_eq!(cache.get(&1), Some("alice"));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+    }
+
+    #[test]
+    fn prepare() {
+        let cache = Cache::new(10);
+        cache.prepare();
+        assert!(cache.base.has_op_channels());
+        assert_eq!(cache.estimated_entry_count(), 0);
+
+        for i in 0..20 {
+            cache.insert(i, i);
+        }
+        // The pending writes are applied without waiting for the housekeeper.
+        cache.prepare();
+        assert_eq!(cache.base.write_op_ch().len(), 0);
+        assert_eq!(cache.estimated_entry_count(), 10);
+
+        // A frozen cache is left as it is.
+        let frozen = cache.freeze();
+        cache.insert(20, 20);
+        cache.prepare();
+        assert_eq!(cache.base.write_op_ch().len(), 1);
+        drop(frozen);
+        cache.prepare();
+        assert_eq!(cache.base.write_op_ch().len(), 0);
+    }
+
+    #[test]
+    fn key_normalizer() {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .key_normalizer(|key: &String| key.to_lowercase())
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("Alice".to_string(), 1);
+        assert_eq!(cache.get(&"alice".to_string()), Some(1));
+        assert_eq!(cache.get(&"ALICE".to_string()), Some(1));
+        assert_eq!(cache.get(&Arc::new("aLiCe".to_string())), Some(1));
+
+        // Updates the same entry.
+        cache.insert("ALICE".to_string(), 2);
+        cache.sync();
+        assert_eq!(cache.get(&"Alice".to_string()), Some(2));
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        assert_eq!(cache.get_or_insert_with("Bob".to_string(), || 3), 3);
+        assert_eq!(cache.get_or_insert_with("BOB".to_string(), || 4), 3);
+        assert_eq!(
+            cache.get_or_try_insert_with("bob".to_string(), || Ok(5)) as Result<_, LoadError>,
+            Ok(3)
+        );
+
+        cache.invalidate(&"aLICE".to_string());
+        assert_eq!(cache.get(&"alice".to_string()), None);
+        assert_eq!(cache.get(&"bob".to_string()), Some(3));
+
+        cache.invalidate(&Arc::new("BoB".to_string()));
+        assert_eq!(cache.get(&"bob".to_string()), None);
+    }
+
+    #[test]
+    fn small_cache() {
+        // 31 is the largest capacity to use the plain LRU policy.
+        for (capacity, is_small) in [(31, true), (32, false)] {
+            let mut cache = Cache::builder().max_capacity(capacity).small().build();
+            cache.reconfigure_for_testing();
+
+            // Make the cache exterior immutable.
+            let cache = cache;
+            assert_eq!(cache.base.is_small_cache(), is_small);
+
+            for i in 0..capacity {
+                cache.insert(i, i);
+            }
+            cache.sync();
+            for i in 0..capacity {
+                assert_eq!(cache.get(&i), Some(i));
+                assert_eq!(cache.get(&i), Some(i));
+            }
+            cache.sync();
+            // LRU -> MRU: 0, 1, ..., capacity - 1
+
+            cache.insert(capacity, capacity);
+            cache.sync();
+
+            if is_small {
+                // The new entry evicts the LRU entry regardless of frequencies.
+                assert_eq!(cache.get(&capacity), Some(capacity));
+                assert_eq!(cache.get(&0), None);
+            } else {
+                // TinyLFU rejects the new entry as it is less popular than the
+                // LRU entry.
+                assert_eq!(cache.get(&capacity), None);
+                assert_eq!(cache.get(&0), Some(0));
+            }
+        }
+
+        // Too large for the hint to take effect.
+        let cache: Cache<u64, u64> = Cache::builder().max_capacity(1000).small().build();
+        assert!(!cache.base.is_small_cache());
+    }
+
+    #[test]
+    fn weight_increase_of_pending_entry() {
+        use crate::sync::WeightIncreasePolicy;
+
+        #[derive(Clone, Debug, PartialEq)]
+        enum Value {
+            Pending,
+            Ready(u32),
+        }
+
+        let weigher = |_k: &&str, v: &Value| match v {
+            Value::Pending => 1,
+            Value::Ready(size) => *size,
+        };
+
+        for policy in [
+            WeightIncreasePolicy::Admit,
+            WeightIncreasePolicy::ReapplyAdmission,
+        ] {
+            let mut cache = Cache::builder()
+                .max_capacity(10)
+                .weigher(weigher)
+                .on_weight_increase(policy)
+                .build();
+            cache.reconfigure_for_testing();
+
+            // Make the cache exterior immutable.
+            let cache = cache;
+
+            cache.insert("a", Value::Ready(4));
+            cache.insert("b", Value::Ready(4));
+            cache.sync();
+            for _ in 0..2 {
+                assert_eq!(cache.get(&"a"), Some(Value::Ready(4)));
+                assert_eq!(cache.get(&"b"), Some(Value::Ready(4)));
+            }
+            cache.sync();
+            // order (LRU -> MRU) and counts: a -> 2, b -> 2
+
+            // The placeholder fits in the free space.
+            cache.insert("c", Value::Pending);
+            cache.sync();
+            assert_eq!(cache.base.weighted_size(), 9);
+
+            // The real payload of "c" no longer fits.
+            cache.insert("c", Value::Ready(5));
+            cache.sync();
+
+            match policy {
+                WeightIncreasePolicy::Admit => {
+                    // "a" was evicted to make room for the weight increase.
+                    assert_eq!(cache.base.weighted_size(), 9);
+                    assert_eq!(cache.get(&"a"), None);
+                    assert_eq!(cache.get(&"b"), Some(Value::Ready(4)));
+                    assert_eq!(cache.get(&"c"), Some(Value::Ready(5)));
+                }
+                WeightIncreasePolicy::ReapplyAdmission => {
+                    // "c" (count: 0) was rejected as it is less popular than
+                    // "a" and "b".
+                    assert_eq!(cache.base.weighted_size(), 8);
+                    assert_eq!(cache.get(&"a"), Some(Value::Ready(4)));
+                    assert_eq!(cache.get(&"b"), Some(Value::Ready(4)));
+                    assert_eq!(cache.get(&"c"), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weigh() {
+        let cache: Cache<&str, (&str, u32)> = Cache::new(100);
+        assert_eq!(cache.weigh(&"a", &("alice", 10)), None);
+
+        let cache = CacheBuilder::new(100)
+            .weigher(|_k: &&str, v: &(&str, u32)| v.1)
+            .build();
+        assert_eq!(cache.weigh(&"a", &("alice", 10)), Some(10));
+        // Nothing is inserted.
+        assert!(!cache.contains_key(&"a"));
+    }
+
+    #[test]
+    fn size_aware_eviction() {
+        let weigher = |_k: &&str, v: &(&str, u32)| v.1;
+
+        let alice = ("alice", 10);
+        let bob = ("bob", 15);
+        let bill = ("bill", 20);
+        let cindy = ("cindy", 5);
+        let david = ("david", 15);
+        let dennis = ("dennis", 15);
+
+        let mut cache = Cache::builder().max_capacity(31).weigher(weigher).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", alice);
+        cache.insert("b", bob);
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        cache.sync();
+        // order (LRU -> MRU) and counts: a -> 1, b -> 1
+
+        cache.insert("c", cindy);
+        assert_eq!(cache.get(&"c"), Some(cindy));
+        // order and counts: a -> 1, b -> 1, c -> 1
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        cache.sync();
+        // order and counts: c -> 1, a -> 2, b -> 2
+
+        // To enter "d" (weight: 15), it needs to evict "c" (w: 5) and "a" (w: 10).
+        // "d" must have higher count than 3, which is the aggregated count
+        // of "a" and "c".
+        cache.insert("d", david); //   count: d -> 0
+        cache.sync();
+        assert_eq!(cache.get(&"d"), None); //   d -> 1
+
+        cache.insert("d", david);
+        cache.sync();
+        assert_eq!(cache.get(&"d"), None); //   d -> 2
+
+        cache.insert("d", david);
+        cache.sync();
+        assert_eq!(cache.get(&"d"), None); //   d -> 3
+
+        cache.insert("d", david);
+        cache.sync();
+        assert_eq!(cache.get(&"d"), None); //   d -> 4
+
+        // Finally "d" should be admitted by evicting "c" and "a".
+        cache.insert("d", dennis);
+        cache.sync();
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(bob));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"d"), Some(dennis));
+
+        // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
+        cache.insert("b", bill);
+        cache.sync();
+        assert_eq!(cache.get(&"b"), Some(bill));
+        assert_eq!(cache.get(&"d"), None);
+
+        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
+        cache.insert("a", alice);
+        cache.insert("b", bob);
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        assert_eq!(cache.get(&"d"), None);
+
+        // Verify the sizes.
+        assert_eq!(cache.estimated_entry_count(), 2);
+        assert_eq!(cache.weighted_size(), 25);
+    }
+
+    #[test]
+    fn coalesce_writes_to_same_key() {
+        let weigher = |_k: &&str, v: &u32| *v;
+        let mut cache = Cache::builder().max_capacity(100).weigher(weigher).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for v in 1..=10 {
+            cache.insert("a", v);
+        }
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some(10));
+        assert_eq!(cache.estimated_entry_count(), 1);
+        assert_eq!(cache.weighted_size(), 10);
+
+        // Updates interleaved with an invalidation.
+        cache.insert("a", 20);
+        cache.insert("a", 30);
+        cache.invalidate(&"a");
+        cache.insert("a", 5);
+        cache.insert("a", 7);
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some(7));
+        assert_eq!(cache.estimated_entry_count(), 1);
+        assert_eq!(cache.weighted_size(), 7);
+
+        cache.insert("a", 8);
+        cache.invalidate(&"a");
+        cache.sync();
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.estimated_entry_count(), 0);
+        assert_eq!(cache.weighted_size(), 0);
+    }
+
+    #[test]
+    fn rebuilt_from() {
+        let mut cache = CacheBuilder::new(10)
+            .time_to_live(Duration::from_secs(60))
+            .build();
+        cache.reconfigure_for_testing();
+        cache.insert(0, 0);
+
+        let mut rebuilt = cache.rebuilt_from((1..=20).map(|i| (i, i * 10)));
+        rebuilt.reconfigure_for_testing();
+
+        // Make the caches exterior immutable.
+        let (cache, rebuilt) = (cache, rebuilt);
+
+        assert_eq!(rebuilt.max_capacity(), Some(10));
+        assert_eq!(rebuilt.time_to_live(), Some(Duration::from_secs(60)));
+
+        // The first 10 entries fit in the cache.
+        assert_eq!(rebuilt.estimated_entry_count(), 10);
+        assert_eq!(rebuilt.get(&1), Some(10));
+        assert_eq!(rebuilt.get(&10), Some(100));
+        assert_eq!(rebuilt.get(&11), None);
+        assert_eq!(rebuilt.get(&0), None);
+
+        // The original cache is not affected.
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), None);
+
+        rebuilt.insert(30, 300);
+        rebuilt.sync();
+        rebuilt.invalidate(&1);
+        assert_eq!(rebuilt.get(&1), None);
+        assert_eq!(rebuilt.get(&2), Some(20));
+    }
+
+    #[test]
+    fn record_stats() {
+        let mut cache = CacheBuilder::new(100)
+            .record_stats()
+            .thread_local_cache(4)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        // Served by the thread local read cache.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), None);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 2);
+        assert_eq!(stats.miss_count(), 1);
+        assert_eq!(stats.request_count(), 3);
+
+        #[cfg(feature = "hdrhistogram")]
+        {
+            // The first operations are always sampled.
+            assert!(stats.get_latency_percentile(50.0).is_some());
+            assert!(stats.insert_latency_percentile(99.9).is_some());
+        }
+
+        // Not recorded by default.
+        let cache = Cache::new(100);
+        cache.insert("a", "alice");
+        cache.get(&"a");
+        assert_eq!(cache.stats().request_count(), 0);
+    }
+
+    #[test]
+    fn stats_and_reset() {
+        let cache = CacheBuilder::new(100).record_stats().build();
+        cache.insert("a", "alice");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), None);
+
+        let stats = cache.stats_and_reset();
+        assert_eq!((stats.hit_count(), stats.miss_count()), (1, 1));
+
+        // The next read starts from zero.
+        let stats = cache.stats();
+        assert_eq!((stats.hit_count(), stats.miss_count()), (0, 0));
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        let stats = cache.stats_and_reset();
+        assert_eq!((stats.hit_count(), stats.miss_count()), (1, 0));
+        assert_eq!(cache.stats_and_reset().request_count(), 0);
+    }
+
+    #[test]
+    fn maintenance_stats() {
+        let mut cache = CacheBuilder::new(10)
+            .time_to_live(Duration::from_secs(10))
+            .record_stats()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..20u32 {
+            cache.insert(i, i);
+            cache.get(&i);
+        }
+        cache.sync();
+        let remaining = cache.estimated_entry_count();
+
+        mock.increment(Duration::from_secs(11));
+        cache.sync();
+        assert!(cache.is_table_empty());
+
+        let stats = cache.stats_and_reset();
+        let m = stats.maintenance();
+        assert_eq!(m.pass_count(), 2);
+        assert_eq!(m.duration_histogram().iter().sum::<u64>(), 2);
+        assert!(m.duration_percentile(100.0).is_some());
+        assert_eq!(m.writes_drained(), 20);
+        assert_eq!(m.reads_drained(), 20);
+        assert_eq!(m.entries_expired(), remaining);
+        assert_eq!(m.entries_evicted(), 20 - remaining);
+        assert_eq!(cache.stats().maintenance().pass_count(), 0);
+    }
+
+    #[cfg(feature = "hyperloglog")]
+    #[test]
+    fn distinct_keys_estimate() {
+        let mut cache = CacheBuilder::new(100).record_stats().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Insert far more distinct keys than the capacity, each of them twice.
+        for _ in 0..2 {
+            for i in 0..5_000u32 {
+                cache.insert(i, i);
+                if i % 500 == 499 {
+                    cache.sync();
+                }
+            }
+        }
+        cache.sync();
+        assert!(cache.estimated_entry_count() <= 100);
+
+        let estimate = cache.stats().distinct_keys_estimate() as f64;
+        assert!((estimate - 5_000.0).abs() < 500.0, "estimate: {}", estimate);
+
+        // Not recorded without record_stats.
+        let cache = Cache::new(100);
+        cache.insert(0, 0);
+        cache.sync();
+        assert_eq!(cache.stats().distinct_keys_estimate(), 0);
+    }
+
+    #[test]
+    fn load_stats() {
+        use std::sync::Barrier;
+
+        const NUM_THREADS: usize = 8;
+        const NUM_ROUNDS: u32 = 20;
+
+        let cache = CacheBuilder::new(100).record_stats().build();
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    for round in 0..NUM_ROUNDS {
+                        barrier.wait();
+                        // Every other round fails.
+                        let _ = cache.get_or_try_insert_with(round, || {
+                            std::thread::sleep(Duration::from_millis(5));
+                            if round % 2 == 0 {
+                                Ok(round)
+                            } else {
+                                Err(std::fmt::Error)
+                            }
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("Failed to join");
+        }
+
+        let stats = cache.stats();
+        assert!(stats.loads_started() >= u64::from(NUM_ROUNDS));
+        assert_eq!(
+            stats.loads_started(),
+            stats.loads_completed() + stats.loads_failed()
+        );
+        // Each successful round is loaded once.
+        assert_eq!(stats.loads_completed(), u64::from(NUM_ROUNDS / 2));
+        assert!(stats.loads_deduplicated() > 0);
+        // Every call either hits, loads, or waits for another call's load.
+        assert_eq!(
+            stats.hit_count() + stats.loads_started() + stats.loads_deduplicated(),
+            (NUM_THREADS as u64) * u64::from(NUM_ROUNDS)
+        );
+        assert!(stats.average_load_time() >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn claim_miss() {
+        use crate::sync::{MissClaim, MissResolution};
+
+        let cache = Cache::new(100);
+
+        let token = match cache.claim_miss(1) {
+            MissClaim::Owner(token) => token,
+            _ => panic!("The first claimant should own the claim"),
+        };
+        assert_eq!(token.key(), &1);
+
+        let waiters = (0..4)
+            .map(|_| match cache.claim_miss(1) {
+                MissClaim::Pending(waiter) => std::thread::spawn(move || waiter.wait()),
+                _ => panic!("The other claimants should wait"),
+            })
+            .collect::<Vec<_>>();
+
+        // The token can be completed by another thread.
+        let cache1 = cache.clone();
+        std::thread::spawn(move || cache1.complete(token, "one"))
+            .join()
+            .expect("Failed to join");
+
+        for waiter in waiters {
+            let resolution = waiter.join().expect("Failed to join");
+            assert_eq!(resolution, MissResolution::Completed("one"));
+        }
+        assert_eq!(cache.get(&1), Some("one"));
+        assert!(matches!(cache.claim_miss(1), MissClaim::Hit("one")));
+    }
+
+    #[test]
+    fn claim_miss_abandoned() {
+        use crate::sync::{MissClaim, MissResolution};
+
+        let cache: Cache<u32, &str> = Cache::new(100);
+
+        // Abandoned explicitly.
+        let token = match cache.claim_miss(1) {
+            MissClaim::Owner(token) => token,
+            _ => panic!("The first claimant should own the claim"),
+        };
+        let waiter = match cache.claim_miss(1) {
+            MissClaim::Pending(waiter) => std::thread::spawn(move || waiter.wait()),
+            _ => panic!("The second claimant should wait"),
+        };
+        cache.abandon(token);
+        assert_eq!(waiter.join().unwrap(), MissResolution::Retry);
+
+        // The owner panics while holding the token.
+        let token = match cache.claim_miss(1) {
+            MissClaim::Owner(token) => token,
+            _ => panic!("The key should be claimable again"),
+        };
+        let waiter = match cache.claim_miss(1) {
+            MissClaim::Pending(waiter) => waiter,
+            _ => panic!("The second claimant should wait"),
+        };
+        let owner = std::thread::spawn(move || {
+            let _token = token;
+            panic!("Panic while loading");
+        });
+        assert!(owner.join().is_err());
+        assert_eq!(waiter.wait(), MissResolution::Retry);
+
+        assert!(matches!(cache.claim_miss(1), MissClaim::Owner(_)));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn get_or_try_insert_with_fallback() {
+        use crate::FallbackError;
+
+        let cache = Cache::new(100);
+
+        // The primary fails and the fallback succeeds.
+        let value = cache.get_or_try_insert_with_fallback(
+            1,
+            || Err("primary failed"),
+            || Ok::<_, &str>("from fallback"),
+        );
+        assert_eq!(value, Ok("from fallback"));
+        assert_eq!(cache.get(&1), Some("from fallback"));
+
+        // The cached value is returned without calling the loaders.
+        let value = cache.get_or_try_insert_with_fallback(
+            1,
+            || -> Result<_, ()> { unreachable!() },
+            || -> Result<_, ()> { unreachable!() },
+        );
+        assert_eq!(value, Ok("from fallback"));
+
+        // The primary succeeds and the fallback is not called.
+        let value = cache.get_or_try_insert_with_fallback(
+            2,
+            || Ok::<_, &str>("from primary"),
+            || -> Result<_, &str> { unreachable!() },
+        );
+        assert_eq!(value, Ok("from primary"));
+
+        // Both fail, and nothing is cached.
+        let value = cache.get_or_try_insert_with_fallback(3, || Err("primary failed"), || Err(42));
+        assert_eq!(
+            value.unwrap_err().loader_error().map(AsRef::as_ref),
+            Some(&FallbackError {
+                primary: "primary failed",
+                fallback: 42,
+            })
+        );
+        assert!(!cache.contains_key(&3));
+    }
+
+    #[test]
+    fn load_error() {
+        use std::{error::Error, io};
+
+        let cache: Cache<u32, u32> = Cache::new(100);
+
+        let err = cache
+            .get_or_try_insert_with(1, || Err(io::Error::other("down")))
+            .unwrap_err();
+        assert!(matches!(err, LoadError::Loader(_)));
+        assert_eq!(
+            err.loader_error().map(|e| e.kind()),
+            Some(io::ErrorKind::Other)
+        );
+        let source = err.source().expect("The loader error should be the source");
+        assert_eq!(source.to_string(), "down");
+
+        // An error of the cache itself has no loader error.
+        assert_eq!(
+            LoadError::<io::Error>::OwnerPanicked
+                .loader_error()
+                .map(|_| ()),
+            None
+        );
+        assert!(LoadError::<io::Error>::OwnerPanicked.source().is_none());
+    }
+
+    #[test]
+    fn get_with_reservation() {
+        use crate::LoadSheddingError;
+        use std::sync::mpsc;
+
+        let cache = CacheBuilder::new(100)
+            .weigher(|_k: &u32, v: &u32| *v)
+            .reservation_high_water_mark(100)
+            .build();
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+
+        // The leader holds a reservation of 70 while its init closure is running.
+        let handle = {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                cache.get_with_reservation(1, 70, || {
+                    started_tx.send(()).unwrap();
+                    finish_rx.recv().unwrap();
+                    70
+                })
+            })
+        };
+        started_rx.recv().unwrap();
+        assert_eq!(cache.reserved_weight(), 70);
+
+        // 70 + 40 does not fit in the mark, so the load is shed.
+        let mut called = false;
+        let result = cache.get_with_reservation(2, 40, || {
+            called = true;
+            40
+        });
+        assert_eq!(
+            result,
+            Err(LoadError::Shed(LoadSheddingError {
+                committed_weight: 70,
+                requested_weight: 40,
+            }))
+        );
+        assert!(!called);
+        assert!(!cache.contains_key(&2));
+
+        // 70 + 30 fits.
+        assert_eq!(cache.get_with_reservation(3, 30, || 30), Ok(30));
+        assert_eq!(cache.reserved_weight(), 70);
+
+        finish_tx.send(()).unwrap();
+        assert_eq!(handle.join().expect("Failed to join"), Ok(70));
+        assert_eq!(cache.reserved_weight(), 0);
+
+        // The inserted values now count towards the mark.
+        cache.sync();
+        assert_eq!(cache.weighted_size(), 100);
+        assert!(cache.get_with_reservation(4, 1, || 1).is_err());
+        // A hit does not reserve anything.
+        assert_eq!(cache.get_with_reservation(3, 30, || unreachable!()), Ok(30));
+    }
+
+    #[test]
+    fn get_with_reservation_without_high_water_mark() {
+        let cache = CacheBuilder::new(100)
+            .weigher(|_k: &u32, v: &u32| *v)
+            .build();
+
+        assert_eq!(cache.get_with_reservation(1, u32::MAX, || 1), Ok(1));
+        assert_eq!(cache.reserved_weight(), 0);
+    }
+
+    #[test]
+    fn expired_and_evicted_counts() {
+        let mut cache = CacheBuilder::new(2)
+            .time_to_live(Duration::from_secs(10))
+            .record_stats()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.sync();
+        for _ in 0..3 {
+            cache.get(&"a");
+            cache.get(&"b");
+        }
+
+        // "c" is less popular than "a" and "b", so it will not be admitted.
+        cache.insert("c", "cindy");
+        cache.sync();
+        assert_eq!(cache.get(&"c"), None);
+        let stats = cache.stats();
+        assert_eq!((stats.expired_count(), stats.evicted_count()), (0, 1));
+
+        assert_eq!(cache.evict_now(1), 1);
+        let stats = cache.stats();
+        assert_eq!((stats.expired_count(), stats.evicted_count()), (0, 2));
+
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+        let stats = cache.stats();
+        assert_eq!((stats.expired_count(), stats.evicted_count()), (1, 2));
+
+        // Invalidated entries are counted by neither.
+        cache.insert("d", "david");
+        cache.sync();
+        cache.invalidate(&"d");
+        cache.sync();
+        let stats = cache.stats();
+        assert_eq!((stats.expired_count(), stats.evicted_count()), (1, 2));
+    }
+
+    #[test]
+    fn drop_values_on_cache_drop() {
+        let value = Arc::new(());
+        let cache = Cache::new(100);
+        cache.insert(0, Arc::clone(&value));
+        cache.sync();
+        // Not synced yet.
+        cache.insert(1, Arc::clone(&value));
+        assert_eq!(Arc::strong_count(&value), 3);
+
+        drop(cache);
+        // Run the deferred destructions of the hash table.
+        for _ in 0..1_000 {
+            crossbeam_epoch::pin().flush();
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize_values_on_removal() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        #[derive(Clone)]
+        struct Secret(u32, Arc<AtomicU32>);
+
+        impl zeroize::Zeroize for Secret {
+            fn zeroize(&mut self) {
+                self.0.zeroize();
+                self.1.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let zeroized = Arc::new(AtomicU32::default());
+        let secret = |n| Secret(n, Arc::clone(&zeroized));
+
+        // Removed entries are dropped by the epoch based garbage collector of the
+        // hash table, so flush its deferred functions until they are run.
+        let zeroized_count = || {
+            for _ in 0..1_000 {
+                crossbeam_epoch::pin().flush();
+            }
+            zeroized.load(Ordering::Relaxed)
+        };
+
+        let mut cache = CacheBuilder::new(100).zeroize_values_on_removal().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", secret(1));
+        cache.insert("b", secret(2));
+        cache.sync();
+        assert_eq!(zeroized_count(), 0);
+
+        // A clone returned by `get` is not zeroized.
+        let a = cache.get(&"a").unwrap();
+
+        // Replace.
+        cache.insert("a", secret(3));
+        cache.sync();
+        assert_eq!(zeroized_count(), 1);
+        assert_eq!(a.0, 1);
+
+        cache.invalidate(&"b");
+        cache.sync();
+        assert_eq!(zeroized_count(), 2);
+
+        drop(cache);
+        assert_eq!(zeroized_count(), 3);
+    }
+
+    #[test]
+    fn basic_multi_threads() {
+        let num_threads = 4;
+        let cache = Cache::new(100);
+
+        let handles = (0..num_threads)
+            .map(|id| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    cache.insert(10, format!("{}-100", id));
+                    cache.get(&10);
+                    cache.insert(20, format!("{}-200", id));
+                    cache.invalidate(&10);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+
+        assert!(cache.get(&10).is_none());
+        assert!(cache.get(&20).is_some());
+    }
+
+    #[test]
+    fn fetch_and_fetch_or() {
+        let cache = Cache::new(100);
+        cache.insert("a", "alice");
+
+        assert_eq!(cache.fetch(&"a"), "alice");
+        assert_eq!(cache.fetch_or(&"a", "anonymous"), "alice");
+        assert_eq!(cache.fetch_or(&"b", "anonymous"), "anonymous");
+        // The default is not inserted.
+        assert_eq!(cache.get(&"b"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The cache has no value for the key")]
+    fn fetch_absent_key() {
+        let cache: Cache<&str, &str> = Cache::new(100);
+        cache.fetch(&"a");
+    }
+
+    #[test]
+    fn get_copied() {
+        let mut cache: Cache<u64, u64> = CacheBuilder::new(100).record_stats().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i * 100);
+        }
+        cache.sync();
+
+        let mut counter = cache.get_copied(&3).unwrap();
+        counter += 1;
+        cache.insert(3, counter);
+        assert_eq!(cache.get_copied(&3), Some(301));
+        assert_eq!(cache.get_copied(&3), cache.get(&3));
+        assert_eq!(cache.get_copied(&10), None);
+
+        // Counted as the same requests as `get`.
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 4);
+        assert_eq!(stats.miss_count(), 1);
+    }
+
+    #[test]
+    fn thread_local_cache() {
+        let mut cache = CacheBuilder::new(100).thread_local_cache(4).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.sync();
+
+        // The first get fills the thread local cache and the second get hits it.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        // Writes must be visible to subsequent reads on this thread.
+        cache.insert("a", "anne");
+        assert_eq!(cache.get(&"a"), Some("anne"));
+        cache.invalidate(&"a");
+        assert_eq!(cache.get(&"a"), None);
+
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.invalidate_all();
+        assert_eq!(cache.get(&"b"), None);
+
+        // Writes from other threads must be visible too.
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        {
+            let cache = cache.clone();
+            std::thread::spawn(move || cache.insert("c", "carol"))
+                .join()
+                .expect("Failed");
+        }
+        assert_eq!(cache.get(&"c"), Some("carol"));
+    }
+
+    #[test]
+    fn invalidate_all() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        cache.sync();
+
+        cache.invalidate_all();
+        cache.sync();
+
+        cache.insert("d", "david");
+        cache.sync();
+
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+        assert_eq!(cache.get(&"d"), Some("david"));
+    }
+
+    #[test]
+    fn snapshot_handle() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.sync();
+
+        let snapshot = cache.snapshot_handle();
+        cache.invalidate_all();
+        cache.sync();
+
+        // The invalidated entries are kept for the snapshot.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(snapshot.get(&"a"), Some("alice"));
+        assert_eq!(snapshot.get(&"b"), Some("bob"));
+        assert_eq!(cache.estimated_entry_count(), 2);
+
+        // But individual invalidations and new entries are visible.
+        cache.invalidate(&"b");
+        cache.insert("c", "cindy");
+        cache.sync();
+        assert_eq!(snapshot.get(&"b"), None);
+        assert_eq!(snapshot.get(&"c"), Some("cindy"));
+
+        // A newer snapshot sees the invalidation.
+        let snapshot2 = cache.snapshot_handle();
+        assert_eq!(snapshot2.get(&"a"), None);
+        drop(snapshot2);
+
+        drop(snapshot);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+    }
+
+    #[test]
+    fn snapshot_handle_with_concurrent_invalidate_all() {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            thread::spawn,
+        };
+
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Pin a valid_after that is set, and keep the entries written after it.
+        cache.invalidate_all();
+        for i in 0..50u32 {
+            cache.insert(i, i * 10);
+        }
+        cache.sync();
+
+        let snapshot = cache.snapshot_handle();
+        let done = Arc::new(AtomicBool::new(false));
+        let (cache1, done1) = (cache.clone(), Arc::clone(&done));
+        let invalidator = spawn(move || {
+            for _ in 0..20 {
+                cache1.invalidate_all();
+                cache1.sync();
+            }
+            done1.store(true, Ordering::Release);
+        });
+
+        // The reads through the handle are not affected by the invalidations.
+        loop {
+            let finished = done.load(Ordering::Acquire);
+            for i in 0..50 {
+                assert_eq!(snapshot.get(&i), Some(i * 10));
+            }
+            if finished {
+                break;
+            }
+        }
+        invalidator.join().unwrap();
+
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(snapshot.get(&0), Some(0));
+        assert_eq!(cache.estimated_entry_count(), 50);
+
+        drop(snapshot);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+    }
+
+    #[test]
+    fn freeze() {
+        use std::collections::HashSet;
+
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i * 10);
+        }
+
+        {
+            let view = cache.freeze();
+            assert_eq!(view.entry_count(), 10);
+            assert_eq!(view.weighted_size(), 10);
+
+            // Inserts keep working, but are not applied while the view is alive.
+            for i in 10..20 {
+                cache.insert(i, i * 10);
+            }
+            cache.sync();
+            assert_eq!(cache.estimated_entry_count(), 10);
+            assert_eq!(cache.get(&15), Some(150));
+
+            let keys = view.iter().map(|(k, _v)| *k).collect::<HashSet<_>>();
+            assert_eq!(keys, (0..10).collect());
+            assert!(!view.is_expired());
+        }
+
+        // Dropping the view resumes the maintenance.
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 20);
+        assert_eq!(cache.freeze().iter().count(), 20);
+
+        // A leaked view stops pausing the maintenance after its maximum duration.
+        let view = cache.freeze_for(Duration::from_millis(10));
+        cache.insert(20, 200);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(view.is_expired());
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 21);
+        std::mem::forget(view);
+    }
+
+    #[test]
+    fn invalidate_while_frozen() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i * 10);
+        }
+
+        let view = cache.freeze();
+        cache.invalidate(&3);
+        cache.insert(4, 400);
+        cache.invalidate(&5);
+        cache.sync();
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.get(&4), Some(400));
+
+        // The view still shows the entries at the time it was created.
+        assert_eq!(view.entry_count(), 10);
+        assert_eq!(view.iter().count() as u64, view.entry_count());
+        let mut entries = view.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>();
+        entries.sort_unstable();
+        assert_eq!(entries, (0..10).map(|i| (i, i * 10)).collect::<Vec<_>>());
+        drop(view);
+
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 8);
+        let view = cache.freeze();
+        assert_eq!(view.entry_count(), 8);
+        assert_eq!(view.iter().count(), 8);
+        assert!(view.iter().any(|(k, v)| *k == 4 && v == 400));
+    }
+
+    #[test]
+    fn invalidate_all_except() {
+        let mut cache = Cache::new(200);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..100 {
+            cache.insert(i, i * 10);
+        }
+        // Do not sync here. The pending writes should be applied by the method.
+
+        let pinned = [7, 42, 99];
+        cache.invalidate_all_except(|k, _v| pinned.contains(k));
+        cache.sync();
+
+        assert_eq!(cache.estimated_entry_count(), 3);
+        for i in 0..100 {
+            if pinned.contains(&i) {
+                assert_eq!(cache.get(&i), Some(i * 10));
+            } else {
+                assert_eq!(cache.get(&i), None);
+            }
+        }
+    }
+
+    #[test]
+    fn maintenance_interval() {
+        const INTERVAL: Duration = Duration::from_millis(100);
+
+        // Keep the housekeeper, which counts the maintenance passes.
+        let cache = CacheBuilder::new(100)
+            .maintenance_interval(INTERVAL)
+            .build();
+        let housekeeper = cache.base.housekeeper.as_ref().expect("No housekeeper");
+
+        // The writes fill up the write log, which schedules the passes.
+        let started_at = std::time::Instant::now();
+        let mut i = 0u32;
+        while started_at.elapsed() < INTERVAL * 6 {
+            cache.insert(i % 1_000, i);
+            cache.get(&(i % 1_000));
+            i += 1;
+        }
+        let elapsed = started_at.elapsed();
+
+        let num_passes = housekeeper.num_passes();
+        let max_passes = (elapsed.as_millis() / INTERVAL.as_millis()) as usize + 1;
+        assert!(num_passes > 0);
+        assert!(
+            num_passes <= max_passes,
+            "{} passes in {:?}",
+            num_passes,
+            elapsed
+        );
+
+        // A rebuilt cache has the same interval.
+        let rebuilt = cache.rebuilt_from(std::iter::empty());
+        assert_eq!(rebuilt.base.maintenance_interval(), Some(INTERVAL));
+    }
+
+    #[test]
+    fn batch() {
+        // Keep the housekeeper, which counts the maintenance passes, but stop its
+        // periodical job, so that only the passes scheduled by the batch run.
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+        let housekeeper = cache.base.housekeeper.as_ref().expect("No housekeeper");
+        assert_eq!(housekeeper.num_passes(), 0);
+
+        let applied = cache.batch(|ops| {
+            for i in 0..50 {
+                ops.insert(i, i * 10);
+            }
+            for i in 0..10 {
+                ops.invalidate(i * 5);
+            }
+            assert_eq!(ops.len(), 60);
+        });
+        assert_eq!(applied, 60);
+
+        // The batch has scheduled one pass, which runs on the housekeeper.
+        let started_at = std::time::Instant::now();
+        while housekeeper.num_passes() == 0 {
+            assert!(started_at.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(housekeeper.num_passes(), 1);
+
+        for i in 0..50 {
+            let expected = if i % 5 == 0 { None } else { Some(i * 10) };
+            assert_eq!(cache.get(&i), expected);
+        }
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 40);
+
+        // An empty batch applies nothing.
+        assert_eq!(cache.batch(|_| ()), 0);
+
+        // The invalidations of absent keys are not counted.
+        let applied = cache.batch(|ops| {
+            ops.insert(100, 1000);
+            ops.invalidate(0);
+            ops.invalidate(1);
+        });
+        assert_eq!(applied, 2);
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn invalidate_present() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // The inserts have not been applied by the maintenance yet.
+        cache.insert('a', "alice");
+        cache.insert('a', "anna");
+        assert!(cache.invalidate_present(&'a'));
+        assert!(!cache.invalidate_present(&'a'));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+
+        cache.insert('b', "bob");
+        cache.sync();
+        assert!(cache.invalidate_present(&'b'));
+        assert!(!cache.invalidate_present(&'b'));
+
+        // An expired entry is not present.
+        cache.insert('c', "cindy");
+        cache.sync();
+        mock.increment(Duration::from_secs(10));
+        assert!(!cache.invalidate_present(&'c'));
+
+        // Nor an entry invalidated by invalidate_all.
+        cache.insert('d', "david");
+        cache.sync();
+        mock.increment(Duration::from_secs(1));
+        cache.invalidate_all();
+        assert!(!cache.invalidate_present(&'d'));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+    }
+
+    #[test]
+    fn invalidate_after() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener(move |k, v, cause| removed1.lock().unwrap().push((*k, v, cause)))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+        let take = || {
+            let mut removed = std::mem::take(&mut *removed.lock().unwrap());
+            removed.sort_unstable_by_key(|(k, _, _)| *k);
+            removed
+        };
+
+        cache.insert('a', "alice");
+        cache.insert('b', "bob");
+        cache.insert('c', "cindy");
+        cache.sync();
+
+        assert!(cache.invalidate_after(&'a', Duration::from_secs(3)));
+        // A later deadline does not postpone the removal.
+        assert!(cache.invalidate_after(&'a', Duration::from_secs(5)));
+        assert!(cache.invalidate_after(&'b', Duration::from_secs(3)));
+        // The time-to-live comes first.
+        assert!(cache.invalidate_after(&'c', Duration::from_secs(20)));
+        assert!(!cache.invalidate_after(&'d', Duration::from_secs(3)));
+
+        // Replacing the value cancels the removal.
+        cache.insert('b', "bill");
+        cache.sync();
+        assert_eq!(take(), vec![('b', "bob", RemovalCause::Replaced)]);
+
+        mock.increment(Duration::from_secs(2)); // 2 secs from the start.
+        cache.sync();
+        assert_eq!(cache.get(&'a'), Some("alice"));
+        assert!(take().is_empty());
+
+        mock.increment(Duration::from_secs(1)); // 3 secs.
+        assert_eq!(cache.get(&'a'), None);
+        cache.sync();
+        assert_eq!(take(), vec![('a', "alice", RemovalCause::Explicit)]);
+        assert_eq!(cache.get(&'b'), Some("bill"));
+        assert_eq!(cache.estimated_entry_count(), 2);
+
+        mock.increment(Duration::from_secs(7)); // 10 secs.
+        cache.sync();
+        assert_eq!(
+            take(),
+            vec![
+                ('b', "bill", RemovalCause::Expired),
+                ('c', "cindy", RemovalCause::Expired)
+            ]
+        );
+        assert!(cache.is_table_empty());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn trace_keys() {
+        let cache = CacheBuilder::new(100)
+            .trace_key("alice".to_string())
+            .build();
+        cache.insert("alice".to_string(), 1);
+
+        let tracer = cache.base.key_tracer().expect("No key tracer");
+        assert_eq!(tracer(&"alice".to_string()), Some(r#""alice""#.to_string()));
+        assert_eq!(tracer(&"bob".to_string()), None);
+
+        // A rebuilt cache traces the same keys.
+        let rebuilt = cache.rebuilt_from(vec![("bob".to_string(), 2)]);
+        let tracer = rebuilt.base.key_tracer().expect("No key tracer");
+        assert!(tracer(&"alice".to_string()).is_some());
+        assert_eq!(rebuilt.get(&"alice".to_string()), None);
+
+        let cache = CacheBuilder::new(100)
+            .trace_keys(|key: &u32| *key >= 2)
+            .build();
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        let tracer = cache.base.key_tracer().expect("No key tracer");
+        assert_eq!(tracer(&2), Some("2".to_string()));
+        assert_eq!(tracer(&1), None);
+        assert_eq!(cache.get(&2), Some(2));
+    }
+
+    #[test]
+    fn groups() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
+        let mut cache = CacheBuilder::new(100)
+            .eviction_listener(move |k, v, cause| removed1.lock().unwrap().push((*k, v, cause)))
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+        let take = || std::mem::take(&mut *removed.lock().unwrap());
+
+        cache.insert_in_group('a', "alice", "g1");
+        cache.insert_in_group('b', "bob", "g1");
+        cache.insert_in_group('c', "cindy", "g2");
+        // Moves 'b' to "g2".
+        cache.insert_in_group('b', "bill", "g2");
+        // Keeps 'a' in "g1".
+        cache.insert('a', "anna");
+        cache.sync();
+        take();
+
+        cache.invalidate_group("g1");
+        assert_eq!(take(), vec![('a', "anna", RemovalCause::Explicit)]);
+        assert_eq!(cache.get(&'a'), None);
+        assert_eq!(cache.get(&'b'), Some("bill"));
+
+        // A removed entry leaves its group, so a new entry for the key is not
+        // affected.
+        cache.invalidate(&'c');
+        cache.insert('c', "carol");
+        take();
+
+        cache.invalidate_group("g2");
+        assert_eq!(take(), vec![('b', "bill", RemovalCause::Explicit)]);
+        cache.invalidate_group("g2");
+        assert!(take().is_empty());
+
+        cache.sync();
+        assert_eq!(cache.get(&'c'), Some("carol"));
+        assert_eq!(cache.estimated_entry_count(), 1);
+    }
+
+    #[test]
+    fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        use std::collections::HashSet;
+
+        let mut cache = CacheBuilder::new(100)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, "alice");
+        cache.insert(1, "bob");
+        cache.insert(2, "alex");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.sync();
+
+        assert_eq!(cache.get(&0), Some("alice"));
+        assert_eq!(cache.get(&1), Some("bob"));
+        assert_eq!(cache.get(&2), Some("alex"));
+
+        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
+        cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
+        assert_eq!(cache.base.invalidation_predicate_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        cache.insert(3, "alice");
+
+        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
+        cache.sync(); // To submit the invalidation task.
+        std::thread::sleep(Duration::from_millis(200));
+        cache.sync(); // To process the task result.
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert!(cache.get(&0).is_none());
+        assert!(cache.get(&2).is_none());
+        assert_eq!(cache.get(&1), Some("bob"));
+        // This should survive as it was inserted after calling invalidate_entries_if.
+        assert_eq!(cache.get(&3), Some("alice"));
+        assert_eq!(cache.estimated_entry_count(), 2);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+
+        cache.invalidate_entries_if(|_k, &v| v == "alice")?;
+        cache.invalidate_entries_if(|_k, &v| v == "bob")?;
+        assert_eq!(cache.invalidation_predicate_count(), 2);
+
+        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
+        cache.sync(); // To submit the invalidation task.
+        std::thread::sleep(Duration::from_millis(200));
+        cache.sync(); // To process the task result.
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&3).is_none());
+        assert_eq!(cache.estimated_entry_count(), 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn time_to_live() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.sync();
+
+        cache.get(&"a");
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert!(cache.is_table_empty());
+
+        cache.insert("b", "bob");
+        cache.sync();
+
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
+        cache.sync();
+
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        cache.insert("b", "bill");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 20 secs
+        cache.sync();
+
+        assert_eq!(cache.get(&"b"), Some("bill"));
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 25 secs
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), None);
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn time_to_idle() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        cache.sync();
+
+        cache.insert("b", "bob");
+        cache.sync();
+
+        assert_eq!(cache.estimated_entry_count(), 2);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        mock.increment(Duration::from_secs(10)); // 25 secs
+        cache.sync();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), None);
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn per_entry_time_to_idle() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(30))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // "r" will be in front of "s" in the access order queue.
+        cache.insert_with_tti("r", "ref", Duration::from_secs(60));
+        cache.insert_with_tti("s", "session", Duration::from_secs(5));
+        cache.insert("c", "cache-wide");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(cache.get(&"s"), None);
+        cache.sync();
+        // "s" is removed although "r" in front of it has not expired.
+        assert_eq!(cache.estimated_entry_count(), 2);
+
+        mock.increment(Duration::from_secs(15)); // 20 secs.
+        assert_eq!(cache.get(&"r"), Some("ref"));
+        cache.sync();
+
+        mock.increment(Duration::from_secs(15)); // 35 secs.
+        cache.sync();
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"r"), Some("ref"));
+        assert_eq!(cache.estimated_entry_count(), 1);
+        cache.sync();
+
+        // Reading "r" at 35 secs has reset its idle clock.
+        mock.increment(Duration::from_secs(59)); // 94 secs.
+        assert_eq!(cache.get(&"r"), Some("ref"));
+        cache.sync();
+        mock.increment(Duration::from_secs(60)); // 154 secs.
+        assert_eq!(cache.get(&"r"), None);
+        cache.sync();
+        assert!(cache.is_table_empty());
+
+        // A plain insert removes the override.
+        cache.insert_with_tti("s", "session", Duration::from_secs(5));
+        cache.insert("s", "session");
+        cache.sync();
+        mock.increment(Duration::from_secs(10)); // 164 secs.
+        assert_eq!(cache.get(&"s"), Some("session"));
+    }
+
+    #[test]
+    fn max_age_with_time_to_idle() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .max_age(Duration::from_secs(30))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert_with_tti("a", "alice", Duration::from_secs(60));
+        cache.insert("b", "bob");
+        cache.sync();
+
+        // Keep reading "b" so that time to idle never expires it.
+        for _ in 0..5 {
+            mock.increment(Duration::from_secs(5)); // up to 25 secs.
+            assert_eq!(cache.get(&"b"), Some("bob"));
+            cache.sync();
+        }
+        assert_eq!(cache.estimated_entry_count(), 2);
+
+        // The reads and the longer per-entry time to idle do not extend the max age.
+        mock.increment(Duration::from_secs(5)); // 30 secs.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), None);
+        cache.sync();
+        assert!(cache.is_table_empty());
+
+        // Without `max_age_spans_updates`, an update starts a new life.
+        cache.insert("c", "cindy");
+        cache.sync();
+        mock.increment(Duration::from_secs(8)); // 38 secs.
+        cache.insert("c", "carol");
+        cache.sync();
+        mock.increment(Duration::from_secs(8)); // 46 secs.
+        assert_eq!(cache.get(&"c"), Some("carol"));
+        cache.sync();
+        mock.increment(Duration::from_secs(8)); // 54 secs.
+        assert_eq!(cache.get(&"c"), Some("carol"));
+        cache.sync();
+        mock.increment(Duration::from_secs(8)); // 62 secs.
+        assert_eq!(cache.get(&"c"), Some("carol"));
+        cache.sync();
+        mock.increment(Duration::from_secs(6)); // 68 secs, 30 secs from the update.
+        assert_eq!(cache.get(&"c"), None);
+        cache.sync();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn sliding_ttl() {
+        let mut cache = CacheBuilder::new(100)
+            .sliding_ttl(Duration::from_secs(10), Duration::from_secs(60))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.sync();
+
+        // Every read of "a" extends its life by 10 secs, and "b" is never read.
+        for _ in 0..11 {
+            mock.increment(Duration::from_secs(5)); // up to 55 secs.
+            assert_eq!(cache.get(&"a"), Some("alice"));
+            cache.sync();
+        }
+        assert_eq!(cache.get(&"b"), None);
+
+        // The reads do not extend the life beyond 60 secs from the insert.
+        mock.increment(Duration::from_secs(5)); // 60 secs.
+        assert_eq!(cache.get(&"a"), None);
+        cache.sync();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn sliding_ttl_extend_longer_than_max_lifetime() {
+        let mut cache = CacheBuilder::new(100)
+            .sliding_ttl(Duration::from_secs(120), Duration::from_secs(60))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.sync();
+
+        // The extend is clamped to the max lifetime.
+        mock.increment(Duration::from_secs(59));
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        mock.increment(Duration::from_secs(1)); // 60 secs.
+        assert_eq!(cache.get(&"a"), None);
+        cache.sync();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn max_age_spans_updates() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .max_age(Duration::from_secs(30))
+            .max_age_spans_updates(true)
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.sync();
+
+        // Keep updating "a" and reading "b".
+        for i in 0..5 {
+            mock.increment(Duration::from_secs(5)); // up to 25 secs.
+            cache.insert("a", if i % 2 == 0 { "anne" } else { "alice" });
+            assert_eq!(cache.get(&"b"), Some("bob"));
+            cache.sync();
+        }
+        cache.insert("c", "cindy");
+        cache.sync();
+
+        // "a" is expired by the age of its first insert.
+        mock.increment(Duration::from_secs(5)); // 30 secs.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), None);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+
+        // An insert after the max age starts a new life.
+        cache.insert("a", "amy");
+        cache.sync();
+        mock.increment(Duration::from_secs(5)); // 35 secs.
+        assert_eq!(cache.get(&"a"), Some("amy"));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 2);
+    }
+
+    #[test]
+    fn expiration_tick() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .expiration_tick(Duration::from_secs(1))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.sync();
+
+        // Both reads are within the tick ending at 1 sec, so "a" is recorded as
+        // accessed at 1 sec. "b" is read in the next tick, at 1.1 secs.
+        mock.increment(Duration::from_millis(100)); // 0.1 secs from the start.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        mock.increment(Duration::from_millis(800)); // 0.9 secs.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        mock.increment(Duration::from_millis(200)); // 1.1 secs.
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.sync();
+
+        // Without the tick, "a" would expire at 10.9 secs. It expires up to one
+        // tick later, but never earlier.
+        mock.increment(Duration::from_millis(9_800)); // 10.9 secs.
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 2);
+        mock.increment(Duration::from_millis(100)); // 11 secs.
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        // Without the tick, "b" would expire at 11.1 secs. It expires at 12 secs.
+        mock.increment(Duration::from_millis(999)); // 11.999 secs.
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+        mock.increment(Duration::from_millis(1)); // 12 secs.
+        assert_eq!(cache.get(&"b"), None);
+        cache.sync();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn drop_values_on() {
+        use crate::notification::DropContext;
+        use std::{
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Mutex,
+            },
+            thread,
+            time::{Duration, Instant},
+        };
+
+        type Dropped = Arc<Mutex<Vec<Option<String>>>>;
+
+        // The cache stores a clone of the inserted value. Only that first clone
+        // records the name of the thread dropping it; the inserted value and the
+        // other clones (e.g. the old value returned by an upsert) record nothing.
+        struct Value(Option<(Dropped, Arc<AtomicBool>)>, bool);
+
+        impl Value {
+            fn tracked(dropped: &Dropped) -> Self {
+                Self(Some((Arc::clone(dropped), Arc::default())), false)
+            }
+        }
+
+        impl Clone for Value {
+            fn clone(&self) -> Self {
+                let is_first =
+                    matches!(&self.0, Some((_, cloned)) if !cloned.swap(true, Ordering::AcqRel));
+                Self(self.0.clone(), is_first)
+            }
+        }
+
+        impl Drop for Value {
+            fn drop(&mut self) {
+                if let (Some((dropped, _)), true) = (&self.0, self.1) {
+                    let name = thread::current().name().map(ToString::to_string);
+                    dropped.lock().unwrap().push(name);
+                }
+            }
+        }
+
+        // Run the deferred destructions of the hash table until the value is
+        // dropped, and return the name of the thread that dropped it.
+        let wait_for_drop = |dropped: &Dropped| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while dropped.lock().unwrap().is_empty() && Instant::now() < deadline {
+                crossbeam_epoch::pin().flush();
+                thread::sleep(Duration::from_millis(1));
+            }
+            dropped.lock().unwrap().pop().flatten()
+        };
+
+        // The default drops the value where the cache releases it. It is usually
+        // this thread, but any thread may run the deferred destructions of the
+        // hash table, so only check that it is dropped.
+        let dropped = Dropped::default();
+        let cache = Cache::new(100);
+        cache.insert(0, Value::tracked(&dropped));
+        drop(cache);
+        assert!(wait_for_drop(&dropped).is_some());
+
+        for (context, thread_name) in &[
+            (DropContext::EvictionListener, "moka-housekeeper-"),
+            (DropContext::Worker, "moka-value-dropper"),
+        ] {
+            let dropped = Dropped::default();
+            let cache = Cache::builder()
+                .max_capacity(100)
+                .drop_values_on(*context)
+                .build();
+
+            // A replaced value.
+            cache.insert(0, Value::tracked(&dropped));
+            cache.insert(0, Value(None, false));
+            let name = wait_for_drop(&dropped).expect("not dropped");
+            assert!(name.starts_with(thread_name), "{:?}: {}", context, name);
+
+            // An invalidated value.
+            cache.insert(1, Value::tracked(&dropped));
+            cache.invalidate(&1);
+            cache.sync();
+            let name = wait_for_drop(&dropped).expect("not dropped");
+            assert!(name.starts_with(thread_name), "{:?}: {}", context, name);
+
+            // A value remaining when the cache is dropped.
+            cache.insert(2, Value::tracked(&dropped));
+            drop(cache);
+            let name = wait_for_drop(&dropped).expect("not dropped");
+            assert!(name.starts_with(thread_name), "{:?}: {}", context, name);
+        }
+    }
+
+    #[test]
+    fn access_record_throttle() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .access_record_throttle(Duration::from_secs(1))
+            .record_stats()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.sync();
+
+        // Only the read at 1.5 secs is recorded. The others are within one second
+        // from the last recorded access.
+        mock.increment(Duration::from_millis(500)); // 0.5 secs.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.sync();
+        mock.increment(Duration::from_millis(1_000)); // 1.5 secs.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.sync();
+        mock.increment(Duration::from_millis(500)); // 2 secs.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.sync();
+
+        // Without the throttle, "a" would expire at 12 secs.
+        mock.increment(Duration::from_millis(9_499)); // 11.499 secs.
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+        mock.increment(Duration::from_millis(1)); // 11.5 secs.
+        assert_eq!(cache.get(&"a"), None);
+
+        // The throttled reads are still counted.
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 3);
+        assert_eq!(stats.miss_count(), 1);
+    }
+
+    #[test]
+    fn touch() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(30))
+            .time_to_idle(Duration::from_secs(10))
+            .record_stats()
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert!(!cache.touch(&"a", false));
+        cache.insert("a", "alice");
+        cache.sync();
+
+        // Keep "a" from idling out without resetting its time-to-live.
+        for _ in 0..3 {
+            mock.increment(Duration::from_secs(8));
+            assert!(cache.touch(&"a", false));
+            cache.sync();
+        }
+        // 24 secs from the start.
+        assert_eq!(cache.estimated_entry_count(), 1);
+
+        mock.increment(Duration::from_secs(6)); // 30 secs.
+        assert!(!cache.touch(&"a", false));
+        cache.sync();
+        assert!(cache.is_table_empty());
+
+        cache.insert("b", "bob");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(8)); // 38 secs.
+        assert!(cache.touch(&"b", true));
+        cache.sync();
+
+        for _ in 0..3 {
+            mock.increment(Duration::from_secs(8));
+            assert!(cache.touch(&"b", false));
+            cache.sync();
+        }
+        // 62 secs. "b" would have expired at 60 secs without the reset.
+        mock.increment(Duration::from_secs(3)); // 65 secs.
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        mock.increment(Duration::from_secs(3)); // 68 secs.
+        assert_eq!(cache.get(&"b"), None);
+
+        // Touching is not counted as a hit or a miss.
+        assert_eq!(cache.stats().request_count(), 2);
+    }
+
+    #[test]
+    fn promote_after_hits() {
+        use crate::common::deque::CacheRegion::{MainProbation, MainProtected};
+
+        let mut cache = CacheBuilder::new(100).promote_after_hits(2).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in 0..10 {
+            cache.insert(key, key);
+        }
+        cache.sync();
+        assert_eq!(cache.base.region_of(&0), Some(MainProbation));
+
+        cache.get(&0);
+        cache.sync();
+        assert_eq!(cache.base.region_of(&0), Some(MainProbation));
+        cache.get(&0);
+        cache.sync();
+        assert_eq!(cache.base.region_of(&0), Some(MainProtected));
+
+        // The protected region holds up to 8 of the 10 entries. Promoting 8 more
+        // entries demotes 0, the least recently used one.
+        for key in 1..9 {
+            cache.get(&key);
+            cache.get(&key);
+        }
+        cache.sync();
+        assert_eq!(cache.base.region_of(&0), Some(MainProbation));
+        assert_eq!(cache.base.region_of(&1), Some(MainProtected));
+
+        // A demoted entry has to earn its hits again.
+        cache.get(&0);
+        cache.sync();
+        assert_eq!(cache.base.region_of(&0), Some(MainProbation));
+        cache.get(&0);
+        cache.sync();
+        assert_eq!(cache.base.region_of(&0), Some(MainProtected));
+    }
+
+    #[test]
+    fn promote_after_hits_trace() {
+        // Half of the accesses go to 80 hot keys, and the other half to one-off
+        // keys that are read twice in a row, like by a scan.
+        let mut seed = 12345u64;
+        let mut next_random = || {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) as u32
+        };
+        let mut trace = Vec::new();
+        let mut one_off_key = 1000;
+        for _ in 0..4000 {
+            if next_random() % 2 == 0 {
+                trace.push(next_random() % 80);
+            } else {
+                trace.push(one_off_key);
+                trace.push(one_off_key);
+                one_off_key += 1;
+            }
+        }
+
+        let hits = |promote_after_hits| {
+            let mut cache = CacheBuilder::new(100)
+                .promote_after_hits(promote_after_hits)
+                .build();
+            cache.reconfigure_for_testing();
+            let cache = cache;
+
+            let mut hits = 0;
+            for key in &trace {
+                if cache.get(key).is_some() {
+                    hits += 1;
+                } else {
+                    cache.insert(*key, *key);
+                }
+                cache.sync();
+            }
+            hits
+        };
+
+        // With 1, every one-off key is promoted by its second read, and pushes a
+        // hot key out of the protected region.
+        assert!(hits(2) > hits(1));
+    }
+
+    #[test]
+    fn key_handle() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .record_stats()
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let mut handle = cache.handle("a");
+        assert_eq!(handle.get(), None);
+
+        let value = Arc::new("alice");
+        cache.insert("a", Arc::clone(&value));
+        cache.sync();
+        for _ in 0..3 {
+            assert_eq!(handle.get(), Some(Arc::clone(&value)));
+        }
+        assert_eq!(cache.stats().hit_count(), 3);
+        assert_eq!(cache.stats().miss_count(), 1);
+
+        // Writes to other keys are detected too.
+        cache.insert("b", Arc::new("bob"));
+        assert_eq!(handle.get(), Some(Arc::clone(&value)));
+
+        // The handle does not return an expired entry.
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+        assert_eq!(handle.get(), None);
+
+        // The handle does not keep the entry from being removed.
+        cache.sync();
+        assert!(!cache.contains_key(&"a"));
+
+        cache.insert("a", Arc::new("amy"));
+        assert_eq!(handle.get().as_deref(), Some(&"amy"));
+        cache.invalidate(&"a");
+        assert_eq!(handle.get(), None);
+    }
+
+    #[test]
+    fn entries_by_expiration() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(30))
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.insert("b", "bob");
+        cache.sync();
+
+        mock.increment(Duration::from_secs(3)); // 8 secs.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.sync();
+
+        // a: min(0 + 30, 8 + 10) = 18 secs, b: min(5 + 30, 5 + 10) = 15 secs.
+        let entries = cache.entries_by_expiration();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(*entries[0].0, "b");
+        assert_eq!(*entries[1].0, "a");
+        assert_eq!(entries[1].1 - entries[0].1, Duration::from_secs(3));
+
+        cache.invalidate_all();
+        assert!(cache.entries_by_expiration().is_empty());
+    }
+
+    #[test]
+    fn expiration_jitter() {
+        let mut cache = CacheBuilder::new(1000)
+            .time_to_live(Duration::from_secs(100))
+            .expiration_jitter(0.2)
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..500 {
+            cache.insert(i, i);
+        }
+        cache.sync();
+
+        // All inserted at once, but expiring between 80 and 120 secs.
+        let entries = cache.entries_by_expiration();
+        assert_eq!(entries.len(), 500);
+        let (first, last) = (entries[0].1, entries[499].1);
+        assert!(last - first > Duration::from_secs(20));
+        assert!(last - first <= Duration::from_secs(40));
+
+        // Updating a value keeps its jitter.
+        let (key, gap) = (*entries[0].0, entries[1].1 - entries[0].1);
+        cache.insert(key, 0);
+        cache.sync();
+        let entries = cache.entries_by_expiration();
+        assert_eq!((*entries[0].0, entries[1].1 - entries[0].1), (key, gap));
+
+        mock.increment(Duration::from_secs(100));
+        let live = (0..500).filter(|i| cache.get(i).is_some()).count();
+        assert!(live > 0 && live < 500);
+
+        mock.increment(Duration::from_secs(20));
+        assert!((0..500).all(|i| cache.get(&i).is_none()));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "expiration_jitter must be at least 0.0 and less than 1.0")]
+    fn expiration_jitter_out_of_range() {
+        let _ = CacheBuilder::<u32, u32, _>::new(100).expiration_jitter(1.5);
+    }
+
+    #[test]
+    fn iter_by_expiration() {
+        let mut cache = CacheBuilder::new(1000)
+            .time_to_live(Duration::from_secs(1000))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..300 {
+            mock.increment(Duration::from_secs(1));
+            cache.insert(i, i * 10);
+            cache.sync();
+        }
+
+        let entries = cache.iter_by_expiration().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 300);
+        assert!(entries
+            .iter()
+            .enumerate()
+            .all(|(i, (k, v, _))| **k == i && *v == i * 10));
+        assert!(entries.windows(2).all(|w| w[0].2 < w[1].2));
+
+        // The iterator reads 128 entries at a time, so it has already copied out
+        // the entries up to 255 at this point.
+        let mut iter = cache.iter_by_expiration();
+        assert_eq!(iter.by_ref().take(200).count(), 200);
+
+        // Remove the last copied entry, and update an entry that has already been
+        // yielded.
+        cache.invalidate(&255);
+        mock.increment(Duration::from_secs(1));
+        cache.insert(10, 1);
+        cache.sync();
+
+        let rest = iter.map(|(k, v, _)| (*k, v)).collect::<Vec<_>>();
+        assert_eq!(rest.len(), 101);
+        assert_eq!(rest[0], (200, 2000));
+        assert_eq!(rest[99], (299, 2990));
+        assert_eq!(rest[100], (10, 1));
+
+        // With no expiration configured, nothing is yielded.
+        let cache = Cache::new(100);
+        cache.insert(0, 0);
+        cache.sync();
+        assert_eq!(cache.iter_by_expiration().count(), 0);
+    }
+
+    #[test]
+    fn for_each_entry() {
+        let mut cache = CacheBuilder::new(1000)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // More entries than a chunk, so that the lock is released in between.
+        for i in 0..150 {
+            cache.insert(i, i * 10);
+        }
+        cache.sync();
+        mock.increment(Duration::from_secs(5));
+        for i in 150..300 {
+            cache.insert(i, i * 10);
+        }
+        cache.sync();
+
+        let mut visited = Vec::new();
+        cache.for_each_entry(|k, v| visited.push((*k, *v)));
+        visited.sort_unstable();
+        assert_eq!(visited, (0..300).map(|i| (i, i * 10)).collect::<Vec<_>>());
+
+        // Expired and invalidated entries are skipped.
+        cache.invalidate(&200);
+        mock.increment(Duration::from_secs(5));
+
+        let mut keys = Vec::new();
+        cache.for_each_entry(|k, _| keys.push(*k));
+        keys.sort_unstable();
+        assert_eq!(keys, (150..300).filter(|k| *k != 200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keys_matching() {
+        let mut cache = Cache::new(1000);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..300 {
+            let state = if i % 3 == 0 { "running" } else { "done" };
+            cache.insert(i, state);
+        }
+        cache.sync();
+        cache.invalidate(&0);
+
+        let mut keys = cache
+            .keys_matching(|state| *state == "running")
+            .into_iter()
+            .map(|k| *k)
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, (3..300).step_by(3).collect::<Vec<_>>());
+        assert!(cache.keys_matching(|state| *state == "failed").is_empty());
+    }
+
+    #[test]
+    fn transform_values() {
+        let mut cache = CacheBuilder::new(1000)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..200 {
+            cache.insert(i, i * 10);
+        }
+        cache.sync();
+        mock.increment(Duration::from_secs(5));
+        // The pending insert is applied before the transformation.
+        cache.insert(200, 2000);
+
+        let mut calls = 0;
+        cache.transform_values(|k, v| {
+            calls += 1;
+            *v += k;
+        });
+        assert_eq!(calls, 201);
+        assert!((0..=200).all(|i| cache.get(&i) == Some(i * 11)));
+        assert_eq!(cache.estimated_entry_count(), 201);
+
+        // The transformation does not extend the time to live.
+        mock.increment(Duration::from_secs(5));
+        assert!((0..200).all(|i| cache.get(&i).is_none()));
+        assert_eq!(cache.get(&200), Some(2200));
+
+        // Expired entries are skipped.
+        cache.sync();
+        let mut keys = Vec::new();
+        cache.transform_values(|k, _| keys.push(*k));
+        assert_eq!(keys, vec![200]);
+    }
+
+    #[test]
+    fn transform_values_concurrently_with_invalidate() {
+        const NUM_KEYS: u32 = 200;
+        const NUM_ROUNDS: u32 = 50;
+
+        let cache = CacheBuilder::new(u64::from(NUM_KEYS)).build();
+        for key in 0..NUM_KEYS {
+            cache.insert(key, 0u32);
+        }
+        cache.sync();
+
+        let handles = (0..2)
+            .map(|t| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for round in 0..NUM_ROUNDS {
+                        for key in (t..NUM_KEYS).step_by(2) {
+                            cache.invalidate(&key);
+                            if round % 2 == 0 {
+                                cache.insert(key, 0);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..NUM_ROUNDS {
+            cache.transform_values(|_k, v| *v += 1);
+        }
+        for handle in handles {
+            handle.join().expect("Failed to join");
+        }
+        cache.sync();
+
+        // Every entry left in the cache is still tracked by the deques, so the
+        // invalidation of all entries empties the cache.
+        let count = (0..NUM_KEYS).filter(|k| cache.contains_key(k)).count();
+        assert_eq!(cache.estimated_entry_count(), count as u64);
+        cache.invalidate_all();
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+        assert_eq!(cache.weighted_size(), 0);
+
+        // The entries inserted after the invalidation are evicted as usual.
+        for key in 0..(NUM_KEYS * 2) {
+            cache.insert(key, 0);
+        }
+        cache.sync();
+        assert!(cache.estimated_entry_count() <= u64::from(NUM_KEYS));
+    }
+
+    #[test]
+    fn insert_if_version() {
+        use crate::CasError;
+
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // A version is expected, but the key is absent.
+        assert_eq!(
+            cache.insert_if_version("a", "alice", Some(1)),
+            Err(CasError {
+                current: None,
+                value: "alice"
+            })
+        );
+
+        let v1 = cache.insert_if_version("a", "alice", None).unwrap();
+        assert_eq!(cache.get_versioned(&"a"), Some(("alice", v1)));
+        assert_eq!(
+            cache.insert_if_version("a", "anna", None),
+            Err(CasError {
+                current: Some(("alice", v1)),
+                value: "anna"
+            })
+        );
+
+        let v2 = cache.insert_if_version("a", "anna", Some(v1)).unwrap();
+        assert!(v2 > v1);
+        assert_eq!(
+            cache.insert_if_version("a", "amy", Some(v1)),
+            Err(CasError {
+                current: Some(("anna", v2)),
+                value: "amy"
+            })
+        );
+        assert_eq!(cache.get_versioned(&"a"), Some(("anna", v2)));
+
+        // A plain insert also replaces the version.
+        cache.insert("a", "amy");
+        let (_, v3) = cache.get_versioned(&"a").unwrap();
+        assert!(v3 > v2);
+
+        // The version is not reused after the key is invalidated and inserted
+        // again.
+        cache.invalidate(&"a");
+        assert_eq!(cache.get_versioned(&"a"), None);
+        cache.insert("a", "alice");
+        let (_, v4) = cache.get_versioned(&"a").unwrap();
+        assert!(v4 > v3);
+
+        // An expired entry is treated as absent.
+        cache.sync();
+        mock.increment(Duration::from_secs(10));
+        assert_eq!(
+            cache.insert_if_version("a", "anna", Some(v4)),
+            Err(CasError {
+                current: None,
+                value: "anna"
+            })
+        );
+        let v5 = cache.insert_if_version("a", "anna", None).unwrap();
+        assert!(v5 > v4);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 1);
+    }
+
+    #[test]
+    fn insert_if_version_concurrently() {
+        const NUM_THREADS: usize = 4;
+        const NUM_INCREMENTS: usize = 100;
+
+        let cache = Cache::new(100);
+        cache.insert_if_version(0, 0, None).unwrap();
+
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..NUM_INCREMENTS {
+                        // Retry the read-modify-write until it succeeds.
+                        loop {
+                            let (count, version) = cache.get_versioned(&0).unwrap();
+                            if cache.insert_if_version(0, count + 1, Some(version)).is_ok() {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("Failed to join");
+        }
+
+        assert_eq!(cache.get(&0), Some(NUM_THREADS * NUM_INCREMENTS));
+    }
+
+    #[test]
+    fn coherence_bus() {
+        use crate::sync::{CoherenceBus, PublishOn};
+        use parking_lot::Mutex;
+
+        #[derive(Clone, Default)]
+        struct RecordingBus(Arc<Mutex<Vec<u32>>>);
+
+        impl CoherenceBus<u32> for RecordingBus {
+            fn publish_invalidation(&self, key: &u32) {
+                self.0.lock().push(*key);
+            }
+        }
+
+        impl RecordingBus {
+            fn take(&self) -> Vec<u32> {
+                std::mem::take(&mut *self.0.lock())
+            }
+        }
+
+        let bus = RecordingBus::default();
+        let cache = CacheBuilder::new(100)
+            .coherence_bus(bus.clone(), PublishOn::Invalidate)
+            .build();
+
+        cache.insert(1, "a");
+        assert!(bus.take().is_empty());
+        cache.invalidate(&1);
+        // Published even if the key is not in the local cache.
+        cache.invalidate(&2);
+        assert_eq!(bus.take(), vec![1, 2]);
+        cache.insert(3, "c");
+        cache.apply_remote_invalidation(&3);
+        assert_eq!(cache.get(&3), None);
+        assert!(bus.take().is_empty());
+
+        let bus = RecordingBus::default();
+        let cache = CacheBuilder::new(100)
+            .coherence_bus(bus.clone(), PublishOn::InsertAndInvalidate)
+            .build();
+
+        cache.insert(1, "a");
+        cache.insert_with_tti(2, "b", Duration::from_secs(60));
+        assert!(cache.try_insert(3, "c").is_ok());
+        assert!(cache.try_insert(3, "c").is_err());
+        cache.insert_with(4, |_| "d");
+        assert_eq!(bus.take(), vec![1, 2, 3, 4]);
+
+        // A loaded value is not published.
+        cache.get_or_insert_with(5, || "e");
+        assert!(bus.take().is_empty());
+
+        cache.invalidate(&5);
+        assert_eq!(bus.take(), vec![5]);
+    }
+
+    #[test]
+    fn coherence_bus_invalidates_remote_replica() {
+        use crate::sync::{CoherenceBus, PublishOn};
+
+        // Applies the published invalidations to the other replica.
+        struct ReplicaBus(Cache<u32, &'static str>);
+
+        impl CoherenceBus<u32> for ReplicaBus {
+            fn publish_invalidation(&self, key: &u32) {
+                self.0.apply_remote_invalidation(key);
+            }
+        }
+
+        let remote = Cache::new(100);
+        let local = CacheBuilder::new(100)
+            .coherence_bus(ReplicaBus(remote.clone()), PublishOn::Invalidate)
+            .build();
+
+        remote.insert(1, "a");
+        remote.insert(2, "b");
+        local.insert(2, "b");
+
+        // Key 1 is not in the local cache, but the remote replica drops it.
+        local.invalidate(&1);
+        assert_eq!(remote.get(&1), None);
+
+        // The same with `&Arc<K>`, for both a locally cached key and an
+        // uncached one.
+        remote.insert(1, "a");
+        local.invalidate(&Arc::new(1));
+        local.invalidate(&Arc::new(2));
+        assert_eq!(remote.get(&1), None);
+        assert_eq!(remote.get(&2), None);
+        assert_eq!(local.get(&2), None);
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        use std::thread::{sleep, spawn};
+
+        let cache = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // This test will run five threads:
+        //
+        // Thread1 will be the first thread to call `get_or_insert_with` for a key, so
+        // its async block will be evaluated and then a &str value "thread1" will be
+        // inserted to the cache.
+        let thread1 = {
+            let cache1 = cache.clone();
+            spawn(move || {
+                // Call `get_or_insert_with` immediately.
+                let v = cache1.get_or_insert_with(KEY, || {
+                    // Wait for 300 ms and return a &str value.
+                    sleep(Duration::from_millis(300));
+                    "thread1"
+                });
+                assert_eq!(v, "thread1");
+            })
+        };
+
+        // Thread2 will be the second thread to call `get_or_insert_with` for the same
+        // key, so its async block will not be evaluated. Once thread1's async block
+        // finishes, it will get the value inserted by thread1's async block.
+        let thread2 = {
+            let cache2 = cache.clone();
+            spawn(move || {
+                // Wait for 100 ms before calling `get_or_insert_with`.
+                sleep(Duration::from_millis(100));
+                let v = cache2.get_or_insert_with(KEY, || unreachable!());
+                assert_eq!(v, "thread1");
+            })
+        };
+
+        // Thread3 will be the third thread to call `get_or_insert_with` for the same
+        // key. By the time it calls, thread1's async block should have finished
+        // already and the value should be already inserted to the cache. So its
+        // async block will not be evaluated and will get the value insert by thread1's
+        // async block immediately.
+        let thread3 = {
+            let cache3 = cache.clone();
+            spawn(move || {
+                // Wait for 400 ms before calling `get_or_insert_with`.
+                sleep(Duration::from_millis(400));
+                let v = cache3.get_or_insert_with(KEY, || unreachable!());
+                assert_eq!(v, "thread1");
+            })
+        };
+
+        // Thread4 will call `get` for the same key. It will call when thread1's async
+        // block is still running, so it will get none for the key.
+        let thread4 = {
+            let cache4 = cache.clone();
+            spawn(move || {
+                // Wait for 200 ms before calling `get`.
+                sleep(Duration::from_millis(200));
+                let maybe_v = cache4.get(&KEY);
+                assert!(maybe_v.is_none());
+            })
+        };
+
+        // Thread5 will call `get` for the same key. It will call after thread1's async
+        // block finished, so it will get the value insert by thread1's async block.
+        let thread5 = {
+            let cache5 = cache.clone();
+            spawn(move || {
+                // Wait for 400 ms before calling `get`.
+                sleep(Duration::from_millis(400));
+                let maybe_v = cache5.get(&KEY);
+                assert_eq!(maybe_v, Some("thread1"));
+            })
+        };
+
+        for t in [thread1, thread2, thread3, thread4, thread5] {
+            t.join().expect("Failed to join");
+        }
+    }
+
+    #[test]
+    fn try_insert() {
+        use crate::OccupiedError;
+
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.try_insert("a", "alice"), Ok(()));
+        cache.sync();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(
+            cache.try_insert("a", "anna"),
+            Err(OccupiedError {
+                existing: "alice",
+                value: "anna"
+            })
+        );
+        cache.sync();
+
+        // The rejected call did not reset the time-to-live.
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        assert_eq!(cache.get(&"a"), None);
+
+        // The expired entry is still in the table, but it is treated as absent.
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.try_insert("a", "amy"), Ok(()));
+        cache.sync();
+        assert_eq!(cache.get(&"a"), Some("amy"));
+        assert_eq!(cache.estimated_entry_count(), 1);
+    }
+
+    #[test]
+    fn insert_with() {
+        use std::{collections::HashSet, thread::spawn};
+
+        let cache: Cache<u32, HashSet<u32>> = Cache::new(100);
+
+        let v = cache.insert_with(0, |current| {
+            assert!(current.is_none());
+            [1, 2].iter().copied().collect()
+        });
+        assert_eq!(v.len(), 2);
+
+        let v = cache.insert_with(0, |current| {
+            let mut set = current.cloned().expect("The value should exist");
+            set.extend([2, 3].iter().copied());
+            set
+        });
+        assert_eq!(v, [1, 2, 3].iter().copied().collect());
+        assert_eq!(cache.get(&0), Some(v));
+
+        // No update should be lost when many threads merge into the same value.
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let cache = cache.clone();
+                spawn(move || {
+                    for j in 0..50 {
+                        cache.insert_with(1, |current| {
+                            let mut set = current.cloned().unwrap_or_default();
+                            set.insert(i * 50 + j);
+                            set
+                        });
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().expect("Failed to join");
+        }
+        assert_eq!(cache.get(&1).map(|set| set.len()), Some(400));
+    }
+
+    #[test]
+    fn panic_injection() {
+        use std::{
+            collections::hash_map::{DefaultHasher, RandomState},
+            hash::{BuildHasher, Hasher},
+            panic::catch_unwind,
+            sync::atomic::{AtomicBool, AtomicU32, Ordering},
+        };
+
+        // Makes the hasher and the weigher panic at pseudo-random points.
+        #[derive(Default)]
+        struct Injector {
+            armed: AtomicBool,
+            state: AtomicU32,
+        }
+
+        impl Injector {
+            fn maybe_panic(&self, place: &str) {
+                if !self.armed.load(Ordering::Acquire) {
+                    return;
+                }
+                // xorshift32
+                let mut x = self.state.load(Ordering::Relaxed);
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                self.state.store(x, Ordering::Relaxed);
+                // Panics once in about 40 calls.
+                if x < u32::MAX / 40 {
+                    panic!("injected panic in the {}", place);
+                }
+            }
+        }
+
+        #[derive(Clone)]
+        struct PanickyState(RandomState, Arc<Injector>);
+
+        struct PanickyHasher(DefaultHasher, Arc<Injector>);
+
+        impl BuildHasher for PanickyState {
+            type Hasher = PanickyHasher;
+
+            fn build_hasher(&self) -> Self::Hasher {
+                PanickyHasher(self.0.build_hasher(), Arc::clone(&self.1))
+            }
+        }
+
+        impl Hasher for PanickyHasher {
+            fn finish(&self) -> u64 {
+                self.1.maybe_panic("hasher");
+                self.0.finish()
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                self.0.write(bytes)
+            }
+        }
+
+        let injector = Arc::new(Injector::default());
+        injector.state.store(0x2545_f491, Ordering::Relaxed);
+        let weigher_injector = Arc::clone(&injector);
+        let mut cache = CacheBuilder::new(32)
+            .weigher(move |k: &u32, _v: &u32| {
+                weigher_injector.maybe_panic("weigher");
+                k % 4 + 1
+            })
+            .build_with_hasher(PanickyState(RandomState::new(), Arc::clone(&injector)));
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        injector.armed.store(true, Ordering::Release);
+        let mut panics = 0;
+        for i in 0..2000u32 {
+            let key = i.wrapping_mul(2_654_435_761) % 64;
+            // This compiles without `AssertUnwindSafe` as `&Cache` is `UnwindSafe`.
+            let result = catch_unwind(|| match i % 5 {
+                0 | 1 => cache.insert(key, key * 10),
+                2 => {
+                    cache.get(&key);
+                }
+                3 => cache.invalidate(&key),
+                _ => cache.sync(),
+            });
+            if result.is_err() {
+                panics += 1;
+            }
+        }
+        injector.armed.store(false, Ordering::Release);
+        assert!(panics > 0);
+
+        // The cache returns no broken value.
+        cache.sync();
+        cache.for_each_entry(|k, v| assert_eq!(*v, *k * 10));
+
+        // The counts stay in step with the entries tracked by the policy.
+        for key in 0..64 {
+            cache.invalidate(&key);
+            assert_eq!(cache.get(&key), None);
+        }
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+        assert_eq!(cache.weighted_size(), 0);
+
+        // And the cache keeps working.
+        for key in 0..64 {
+            cache.insert(key, key * 10);
+            assert_eq!(cache.get(&key), Some(key * 10));
+        }
+    }
+
+    #[test]
+    fn swap() {
+        use std::{
+            collections::HashSet,
+            sync::atomic::{AtomicBool, Ordering},
+            thread::spawn,
+        };
+
+        let cache: Cache<&str, u32> = Cache::new(100);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+
+        assert!(cache.swap("a", "b"));
+        assert_eq!((cache.get(&"a"), cache.get(&"b")), (Some(2), Some(1)));
+        assert!(cache.swap("a", "a"));
+        assert_eq!(cache.get(&"a"), Some(2));
+        // Nothing changes unless both keys are present.
+        assert!(!cache.swap("a", "d"));
+        assert_eq!(cache.get(&"a"), Some(2));
+        assert_eq!(cache.get(&"d"), None);
+
+        // Concurrent swaps of overlapping pairs should neither lose nor
+        // duplicate a value, and the readers should never see a key without one,
+        // nor a pair with the same value.
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let cache = cache.clone();
+                let done = Arc::clone(&done);
+                spawn(move || {
+                    while !done.load(Ordering::Acquire) {
+                        for (x, y) in &[("a", "b"), ("b", "c"), ("c", "a")] {
+                            match cache.get_pair(x, y) {
+                                (Some(v), Some(w)) => {
+                                    assert!((1..=3).contains(&v) && (1..=3).contains(&w));
+                                    assert_ne!(v, w, "Saw a half-swapped pair");
+                                }
+                                pair => panic!("A key has no value: {:?}", pair),
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        let swappers: Vec<_> = [("a", "b"), ("b", "c"), ("c", "a"), ("b", "a")]
+            .iter()
+            .map(|&(x, y)| {
+                let cache = cache.clone();
+                spawn(move || {
+                    for _ in 0..500 {
+                        assert!(cache.swap(x, y));
+                    }
+                })
+            })
+            .collect();
+        for t in swappers {
+            t.join().expect("Failed to join");
+        }
+        done.store(true, Ordering::Release);
+        for t in readers {
+            t.join().expect("Failed to join");
+        }
+
+        let values = ["a", "b", "c"]
+            .iter()
+            .filter_map(|k| cache.get(k))
+            .collect::<HashSet<_>>();
+        assert_eq!(values, [1, 2, 3].iter().copied().collect());
+    }
+
+    #[test]
+    fn swap_is_atomic_for_get_pair() {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            thread::spawn,
+        };
+
+        let cache: Cache<&str, u32> = Cache::new(100);
+        cache.insert("active", 1);
+        cache.insert("standby", 2);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let done = Arc::clone(&done);
+                spawn(move || loop {
+                    let pair = cache.get_pair(&"active", &"standby");
+                    assert!(
+                        pair == (Some(1), Some(2)) || pair == (Some(2), Some(1)),
+                        "Saw a half-swapped pair: {:?}",
+                        pair
+                    );
+                    if done.load(Ordering::Acquire) {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..2000 {
+            assert!(cache.swap("active", "standby"));
+        }
+        done.store(true, Ordering::Release);
+        for t in readers {
+            t.join().expect("Failed to join");
+        }
+        // An even number of swaps.
+        assert_eq!(cache.get_pair(&"active", &"standby"), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn get_or_insert_with_role() {
+        use crate::sync::LoadRole;
+        use std::sync::Barrier;
+
+        const NUM_THREADS: usize = 8;
+        let cache = Cache::new(100);
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        // Each round is a load generation: the key is invalidated after every
+        // thread has got the value.
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    let mut leaders = Vec::new();
+                    for round in 0..10 {
+                        barrier.wait();
+                        let (v, role) = cache.get_or_insert_with_role(0, || {
+                            std::thread::sleep(Duration::from_millis(10));
+                            round
+                        });
+                        assert_eq!(v, round);
+                        if role == LoadRole::Leader {
+                            leaders.push(round);
+                        }
+                        if barrier.wait().is_leader() {
+                            cache.invalidate(&0);
+                        }
+                    }
+                    leaders
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut leaders = handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Failed to join"))
+            .collect::<Vec<_>>();
+        leaders.sort_unstable();
+        assert_eq!(leaders, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn get_or_insert_with_key() {
+        let cache = CacheBuilder::new(100)
+            .key_normalizer(|k: &String| k.to_lowercase())
+            .build();
+
+        // The closure receives the normalized key.
+        let v = cache.get_or_insert_with_key("Alice".to_string(), |k| format!("{}!", k));
+        assert_eq!(v, "alice!");
+        let v = cache.get_or_insert_with_key("ALICE".to_string(), |_| unreachable!());
+        assert_eq!(v, "alice!");
+
+        let v: Result<_, LoadError<std::num::ParseIntError>> = cache
+            .get_or_try_insert_with_key("42".to_string(), |k| {
+                k.parse::<u32>().map(|n| n.to_string())
+            });
+        assert_eq!(v.unwrap(), "42");
+        let v = cache.get_or_try_insert_with_key("x".to_string(), |k| {
+            k.parse::<u32>().map(|n| n.to_string())
+        });
+        assert!(v.is_err());
+        assert_eq!(cache.get(&"x".to_string()), None);
+    }
+
+    #[test]
+    fn get_loading() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Barrier,
+        };
+
+        const NUM_THREADS: usize = 8;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls1 = Arc::clone(&calls);
+        let cache = CacheBuilder::new(100)
+            .loader(move |k: &u32| {
+                calls1.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                k * 10
+            })
+            .build();
+
+        // Concurrent calls for the same key run the loader only once.
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cache.get_loading(1)
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), 10);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A cached value is returned without calling the loader.
+        assert_eq!(cache.get_loading(1), 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.get_loading(2), 20);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "get_loading requires a loader")]
+    fn get_loading_without_loader() {
+        let cache: Cache<u32, u32> = Cache::new(100);
+        cache.get_loading(1);
+    }
+
+    #[test]
+    fn prefetch() {
+        use std::{
+            sync::{
+                atomic::{AtomicU32, Ordering},
+                Barrier,
+            },
+            thread::{sleep, spawn},
+        };
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let barrier = Arc::new(Barrier::new(2));
+        let (calls1, barrier1) = (Arc::clone(&calls), Arc::clone(&barrier));
+        let cache = CacheBuilder::new(100)
+            .record_stats()
+            .loader(move |k: &u32| {
+                calls1.fetch_add(1, Ordering::SeqCst);
+                if *k == 1 {
+                    // Tell the test the load has started, and wait to be released.
+                    barrier1.wait();
+                    barrier1.wait();
+                }
+                *k * 10
+            })
+            .build();
+
+        // Returns while the loader is blocked.
+        cache.prefetch(1);
+        barrier.wait();
+        // Does nothing while the key is being loaded.
+        cache.prefetch(1);
+        assert_eq!(cache.stats().prefetches_issued(), 1);
+
+        // Waits for the prefetch instead of loading again.
+        let cache1 = cache.clone();
+        let getter = spawn(move || cache1.get_loading(1));
+        barrier.wait();
+        assert_eq!(getter.join().unwrap(), 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // The getter has either waited for the load, or hit the prefetched value.
+        let hits = cache.stats().prefetch_hits();
+        assert!(hits <= 1);
+
+        let wait_for_prefetch = |key: u32| {
+            let key = Arc::new(key);
+            while !cache.contains_key(&key) || cache.value_initializer.is_loading(&key) {
+                sleep(Duration::from_millis(1));
+            }
+        };
+
+        // Only the first hit on a prefetched value counts.
+        cache.prefetch(2);
+        wait_for_prefetch(2);
+        assert_eq!(cache.get(&2), Some(20));
+        assert_eq!(cache.get(&2), Some(20));
+        // Does nothing for a key in the cache.
+        cache.prefetch(2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // A replaced value is no longer a prefetched one.
+        cache.prefetch(3);
+        wait_for_prefetch(3);
+        cache.insert(3, 33);
+        assert_eq!(cache.get(&3), Some(33));
+
+        let stats = cache.stats();
+        assert_eq!(stats.prefetches_issued(), 3);
+        assert_eq!(stats.prefetch_hits(), hits + 1);
+        assert_eq!(stats.loads_started(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "prefetch requires a loader")]
+    fn prefetch_without_loader() {
+        let cache: Cache<u32, u32> = Cache::new(100);
+        cache.prefetch(1);
+    }
+
+    #[test]
+    fn get_or_insert_with_info() {
+        use std::sync::Barrier;
+
+        const NUM_THREADS: usize = 8;
+        let cache = Cache::new(100);
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        let handles = (0..NUM_THREADS)
+            .map(|i| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cache.get_or_insert_with_info(0, || {
+                        std::thread::sleep(Duration::from_millis(10));
+                        i
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let entries = handles
+            .into_iter()
+            .map(|h| h.join().expect("Failed to join"))
+            .collect::<Vec<_>>();
+
+        // Only the caller that ran the init closure gets a fresh entry, even
+        // though the waiters got the same new value.
+        let fresh = entries.iter().filter(|e| e.is_fresh()).collect::<Vec<_>>();
+        assert_eq!(fresh.len(), 1);
+        let value = *fresh[0].value();
+        assert!(entries.iter().all(|e| *e.value() == value));
+
+        let entry = cache.get_or_insert_with_info(0, || unreachable!());
+        assert!(!entry.is_fresh());
+        assert_eq!(entry.into_value(), value);
+    }
+
+    #[test]
+    fn get_or_try_insert_with() {
+        use std::thread::{sleep, spawn};
+
+        // Note that MyError does not implement std::error::Error trait
+        // like anyhow::Error.
+        #[derive(Debug)]
+        pub struct MyError(#[allow(dead_code)] String);
+
+        type MyResult<T> = Result<T, LoadError<MyError>>;
+
+        let cache = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // This test will run eight async threads:
+        //
+        // Thread1 will be the first thread to call `get_or_insert_with` for a key, so
+        // its async block will be evaluated and then an error will be returned.
+        // Nothing will be inserted to the cache.
+        let thread1 = {
+            let cache1 = cache.clone();
+            spawn(move || {
+                // Call `get_or_try_insert_with` immediately.
+                let v = cache1.get_or_try_insert_with(KEY, || {
+                    // Wait for 300 ms and return an error.
+                    sleep(Duration::from_millis(300));
+                    Err(MyError("thread1 error".into()))
+                });
+                assert!(v.is_err());
+            })
+        };
+
+        // Thread2 will be the second thread to call `get_or_insert_with` for the same
+        // key, so its async block will not be evaluated. Once thread1's async block
+        // finishes, it will get the same error value returned by thread1's async
+        // block.
+        let thread2 = {
+            let cache2 = cache.clone();
+            spawn(move || {
+                // Wait for 100 ms before calling `get_or_try_insert_with`.
+                sleep(Duration::from_millis(100));
+                let v: MyResult<_> = cache2.get_or_try_insert_with(KEY, || unreachable!());
+                assert!(v.is_err());
+            })
+        };
+
+        // Thread3 will be the third thread to call `get_or_insert_with` for the same
+        // key. By the time it calls, thread1's async block should have finished
+        // already, but the key still does not exist in the cache. So its async block
+        // will be evaluated and then an okay &str value will be returned. That value
+        // will be inserted to the cache.
+        let thread3 = {
+            let cache3 = cache.clone();
+            spawn(move || {
+                // Wait for 400 ms before calling `get_or_try_insert_with`.
+                sleep(Duration::from_millis(400));
+                let v: MyResult<_> = cache3.get_or_try_insert_with(KEY, || {
+                    // Wait for 300 ms and return an Ok(&str) value.
+                    sleep(Duration::from_millis(300));
+                    Ok("thread3")
+                });
+                assert_eq!(v.unwrap(), "thread3");
+            })
+        };
+
+        // thread4 will be the fourth thread to call `get_or_insert_with` for the same
+        // key. So its async block will not be evaluated. Once thread3's async block
+        // finishes, it will get the same okay &str value.
+        let thread4 = {
+            let cache4 = cache.clone();
+            spawn(move || {
+                // Wait for 500 ms before calling `get_or_try_insert_with`.
+                sleep(Duration::from_millis(500));
+                let v: MyResult<_> = cache4.get_or_try_insert_with(KEY, || unreachable!());
+                assert_eq!(v.unwrap(), "thread3");
+            })
+        };
+
+        // Thread5 will be the fifth thread to call `get_or_insert_with` for the same
+        // key. So its async block will not be evaluated. By the time it calls,
+        // thread3's async block should have finished already, so its async block will
+        // not be evaluated and will get the value insert by thread3's async block
+        // immediately.
+        let thread5 = {
+            let cache5 = cache.clone();
+            spawn(move || {
+                // Wait for 800 ms before calling `get_or_try_insert_with`.
+                sleep(Duration::from_millis(800));
+                let v: MyResult<_> = cache5.get_or_try_insert_with(KEY, || unreachable!());
+                assert_eq!(v.unwrap(), "thread3");
+            })
+        };
+
+        // Thread6 will call `get` for the same key. It will call when thread1's async
+        // block is still running, so it will get none for the key.
+        let thread6 = {
+            let cache6 = cache.clone();
+            spawn(move || {
+                // Wait for 200 ms before calling `get`.
+                sleep(Duration::from_millis(200));
+                let maybe_v = cache6.get(&KEY);
+                assert!(maybe_v.is_none());
+            })
+        };
+
+        // Thread7 will call `get` for the same key. It will call after thread1's async
+        // block finished with an error. So it will get none for the key.
+        let thread7 = {
+            let cache7 = cache.clone();
+            spawn(move || {
+                // Wait for 400 ms before calling `get`.
+                sleep(Duration::from_millis(400));
+                let maybe_v = cache7.get(&KEY);
+                assert!(maybe_v.is_none());
+            })
+        };
+
+        // Thread8 will call `get` for the same key. It will call after thread3's async
+        // block finished, so it will get the value insert by thread3's async block.
+        let thread8 = {
+            let cache8 = cache.clone();
+            spawn(move || {
+                // Wait for 800 ms before calling `get`.
+                sleep(Duration::from_millis(800));
+                let maybe_v = cache8.get(&KEY);
+                assert_eq!(maybe_v, Some("thread3"));
+            })
+        };
+
+        for t in [
+            thread1, thread2, thread3, thread4, thread5, thread6, thread7, thread8,
+        ] {
+            t.join().expect("Failed to join");
+        }
     }
 
-    /// Returns the number of internal segments of this cache.
-    ///
-    /// `Cache` always returns `1`.
-    pub fn num_segments(&self) -> usize {
-        1
-    }
+    #[test]
+    fn load_failure_count() {
+        let cache = Cache::new(100);
+        let fail = || Err("failed") as Result<&str, _>;
 
-    #[cfg(test)]
-    pub(crate) fn estimated_entry_count(&self) -> u64 {
-        self.base.estimated_entry_count()
-    }
+        assert_eq!(cache.load_failure_count(&"a"), 0);
 
-    #[cfg(test)]
-    pub(crate) fn weighted_size(&self) -> u64 {
-        self.base.weighted_size()
-    }
-}
+        assert!(cache.get_or_try_insert_with("a", fail).is_err());
+        assert!(cache.get_or_try_insert_with("a", fail).is_err());
+        assert_eq!(cache.load_failure_count(&"a"), 2);
+        assert_eq!(cache.load_failure_count(&"b"), 0);
 
-impl<K, V, S> ConcurrentCacheExt<K, V> for Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    fn sync(&self) {
-        self.base.inner.sync(MAX_SYNC_REPEATS);
+        assert_eq!(
+            cache.get_or_try_insert_with("a", || Ok("alice") as Result<_, &str>),
+            Ok("alice")
+        );
+        assert_eq!(cache.load_failure_count(&"a"), 0);
+
+        // Invalidating a key forgets its failures.
+        assert!(cache.get_or_try_insert_with("b", fail).is_err());
+        assert!(cache.get_or_try_insert_with("c", fail).is_err());
+        cache.invalidate(&"b");
+        assert_eq!(cache.load_failure_count(&"b"), 0);
+        assert_eq!(cache.load_failure_count(&"c"), 1);
+        cache.invalidate_all();
+        assert_eq!(cache.load_failure_count(&"c"), 0);
     }
-}
 
-// private methods
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    #[inline]
-    fn schedule_write_op(
-        ch: &Sender<WriteOp<K, V>>,
-        op: WriteOp<K, V>,
-        housekeeper: Option<&HouseKeeperArc<K, V, S>>,
-    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
-        let mut op = op;
+    #[test]
+    fn load_failure_count_is_bounded() {
+        use super::super::value_initializer::MAX_TRACKED_LOAD_FAILURES;
 
-        // NOTES:
-        // - This will block when the channel is full.
-        // - We are doing a busy-loop here. We were originally calling `ch.send(op)?`,
-        //   but we got a notable performance degradation.
-        loop {
-            BaseCache::apply_reads_writes_if_needed(ch, housekeeper);
-            match ch.try_send(op) {
-                Ok(()) => break,
-                Err(TrySendError::Full(op1)) => {
-                    op = op1;
-                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
-                }
-                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+        let cache = Cache::new(100);
+        let fail = || Err("failed") as Result<u32, _>;
+
+        // Key 0 keeps failing, while the other keys fail once and are never
+        // requested again.
+        let mut key0_failures = 0;
+        for key in 1..(MAX_TRACKED_LOAD_FAILURES * 3) {
+            if key % 100 == 1 {
+                assert!(cache.get_or_try_insert_with(0, fail).is_err());
+                key0_failures += 1;
             }
+            assert!(cache.get_or_try_insert_with(key, fail).is_err());
+            assert!(cache.value_initializer.tracked_load_failures() <= MAX_TRACKED_LOAD_FAILURES);
         }
-        Ok(())
-    }
-}
-
-// For unit tests.
-#[cfg(test)]
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    pub(crate) fn is_table_empty(&self) -> bool {
-        self.estimated_entry_count() == 0
+        // The recently failed key survives the aging.
+        assert_eq!(cache.load_failure_count(&0), key0_failures);
+        assert_eq!(cache.load_failure_count(&1), 0);
     }
 
-    pub(crate) fn invalidation_predicate_count(&self) -> usize {
-        self.base.invalidation_predicate_count()
-    }
+    #[test]
+    // https://github.com/moka-rs/moka/issues/43
+    fn handle_panic_in_get_or_insert_with() {
+        use std::{sync::Barrier, thread};
 
-    pub(crate) fn reconfigure_for_testing(&mut self) {
-        self.base.reconfigure_for_testing();
-    }
+        let cache = Cache::new(16);
+        let barrier = Arc::new(Barrier::new(2));
+        {
+            let cache_ref = cache.clone();
+            let barrier_ref = barrier.clone();
+            thread::spawn(move || {
+                let _ = cache_ref.get_or_insert_with(1, || {
+                    barrier_ref.wait();
+                    thread::sleep(Duration::from_millis(50));
+                    panic!("Panic during get_or_try_insert_with");
+                });
+            });
+        }
 
-    pub(crate) fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
-        self.base.set_expiration_clock(clock);
+        barrier.wait();
+        assert_eq!(cache.get_or_insert_with(1, || 5), 5);
     }
-}
-
-// To see the debug prints, run test as `cargo test -- --nocapture`
-#[cfg(test)]
-mod tests {
-    use super::{Cache, ConcurrentCacheExt};
-    use crate::{common::time::Clock, sync::CacheBuilder};
-
-    use std::{convert::Infallible, sync::Arc, time::Duration};
 
     #[test]
-    fn basic_single_thread() {
-        let mut cache = Cache::new(3);
-        cache.reconfigure_for_testing();
-
-        // Make the cache exterior immutable.
-        let cache = cache;
-
-        cache.insert("a", "alice");
-        cache.insert("b", "bob");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
-        // counts: a -> 1, b -> 1
+    fn get_or_insert_with_waiters_survive_panic() {
+        use std::{
+            sync::{mpsc, Barrier},
+            thread,
+        };
 
-        cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        // counts: a -> 1, b -> 1, c -> 1
-        cache.sync();
+        const NUM_WAITERS: usize = 4;
 
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
-        // counts: a -> 2, b -> 2, c -> 1
+        let cache = Cache::new(16);
+        let barrier = Arc::new(Barrier::new(NUM_WAITERS + 1));
+        let (tx, rx) = mpsc::channel();
 
-        // "d" should not be admitted because its frequency is too low.
-        cache.insert("d", "david"); //   count: d -> 0
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 1
+        // The first caller starts its loader, waits for the others to start, and
+        // then panics.
+        let panicking = {
+            let cache_ref = cache.clone();
+            let barrier_ref = barrier.clone();
+            thread::spawn(move || {
+                cache_ref.get_or_insert_with(1, || {
+                    barrier_ref.wait();
+                    thread::sleep(Duration::from_millis(100));
+                    panic!("Panic in the first loader");
+                })
+            })
+        };
 
-        cache.insert("d", "david");
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 2
+        // Wait until the first loader is running.
+        thread::sleep(Duration::from_millis(20));
 
-        // "d" should be admitted and "c" should be evicted
-        // because d's frequency is higher than c's.
-        cache.insert("d", "dennis");
-        cache.sync();
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), None);
-        assert_eq!(cache.get(&"d"), Some("dennis"));
+        let waiters = (0..NUM_WAITERS)
+            .map(|_| {
+                let cache_ref = cache.clone();
+                let barrier_ref = barrier.clone();
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    barrier_ref.wait();
+                    let v = cache_ref.get_or_insert_with(1, || 5);
+                    tx.send(v).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
 
-        cache.invalidate(&"b");
-        assert_eq!(cache.get(&"b"), None);
+        // Every waiter should get the value from the retried loader instead of
+        // waiting forever.
+        for _ in 0..NUM_WAITERS {
+            let v = rx
+                .recv_timeout(Duration::from_secs(10))
+                .expect("a waiter is deadlocked");
+            assert_eq!(v, 5);
+        }
+        waiters.into_iter().for_each(|t| t.join().unwrap());
+        assert!(panicking.join().is_err());
+        assert_eq!(cache.get(&1), Some(5));
     }
 
     #[test]
-    fn size_aware_eviction() {
-        let weigher = |_k: &&str, v: &(&str, u32)| v.1;
-
-        let alice = ("alice", 10);
-        let bob = ("bob", 15);
-        let bill = ("bill", 20);
-        let cindy = ("cindy", 5);
-        let david = ("david", 15);
-        let dennis = ("dennis", 15);
-
-        let mut cache = Cache::builder().max_capacity(31).weigher(weigher).build();
-        cache.reconfigure_for_testing();
-
-        // Make the cache exterior immutable.
-        let cache = cache;
-
-        cache.insert("a", alice);
-        cache.insert("b", bob);
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert_eq!(cache.get(&"b"), Some(bob));
-        cache.sync();
-        // order (LRU -> MRU) and counts: a -> 1, b -> 1
-
-        cache.insert("c", cindy);
-        assert_eq!(cache.get(&"c"), Some(cindy));
-        // order and counts: a -> 1, b -> 1, c -> 1
-        cache.sync();
+    fn get_or_insert_with_panic_in_value_clone() {
+        use std::sync::atomic::{AtomicBool, Ordering};
 
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert_eq!(cache.get(&"b"), Some(bob));
-        cache.sync();
-        // order and counts: c -> 1, a -> 2, b -> 2
+        static PANIC_ON_CLONE: AtomicBool = AtomicBool::new(true);
 
-        // To enter "d" (weight: 15), it needs to evict "c" (w: 5) and "a" (w: 10).
-        // "d" must have higher count than 3, which is the aggregated count
-        // of "a" and "c".
-        cache.insert("d", david); //   count: d -> 0
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 1
+        #[derive(Debug, PartialEq)]
+        struct Value(u32);
 
-        cache.insert("d", david);
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 2
+        impl Clone for Value {
+            fn clone(&self) -> Self {
+                if PANIC_ON_CLONE.swap(false, Ordering::AcqRel) {
+                    panic!("Panic in clone");
+                }
+                Self(self.0)
+            }
+        }
 
-        cache.insert("d", david);
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 3
+        let cache = Cache::new(16);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cache.get_or_insert_with(1, || Value(1))
+        }));
+        assert!(result.is_err());
+        assert_eq!(cache.get(&1), None);
+
+        // The waiter of the panicked call must have been removed, so this will
+        // resolve its own init closure.
+        assert_eq!(cache.get_or_insert_with(1, || Value(2)), Value(2));
+        assert_eq!(cache.get(&1), Some(Value(2)));
+    }
 
-        cache.insert("d", david);
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 4
+    #[test]
+    // https://github.com/moka-rs/moka/issues/43
+    fn handle_panic_in_get_or_try_insert_with() {
+        use std::{sync::Barrier, thread};
 
-        // Finally "d" should be admitted by evicting "c" and "a".
-        cache.insert("d", dennis);
-        cache.sync();
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), Some(bob));
-        assert_eq!(cache.get(&"c"), None);
-        assert_eq!(cache.get(&"d"), Some(dennis));
+        let cache = Cache::new(16);
+        let barrier = Arc::new(Barrier::new(2));
+        {
+            let cache_ref = cache.clone();
+            let barrier_ref = barrier.clone();
+            thread::spawn(move || {
+                let _ = cache_ref.get_or_try_insert_with(1, || {
+                    barrier_ref.wait();
+                    thread::sleep(Duration::from_millis(50));
+                    panic!("Panic during get_or_try_insert_with");
+                }) as Result<_, LoadError>;
+            });
+        }
 
-        // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
-        cache.insert("b", bill);
-        cache.sync();
-        assert_eq!(cache.get(&"b"), Some(bill));
-        assert_eq!(cache.get(&"d"), None);
+        barrier.wait();
+        assert_eq!(
+            cache.get_or_try_insert_with(1, || Ok(5)) as Result<_, LoadError>,
+            Ok(5)
+        );
+    }
 
-        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
-        cache.insert("a", alice);
-        cache.insert("b", bob);
-        cache.sync();
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert_eq!(cache.get(&"b"), Some(bob));
-        assert_eq!(cache.get(&"d"), None);
+    #[test]
+    fn access_listener() {
+        use std::sync::Mutex;
 
-        // Verify the sizes.
-        assert_eq!(cache.estimated_entry_count(), 2);
-        assert_eq!(cache.weighted_size(), 25);
-    }
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let hits1 = Arc::clone(&hits);
+        let mut cache = CacheBuilder::new(100)
+            .thread_local_cache(4)
+            .access_listener(move |k: &char, v: &&str| hits1.lock().unwrap().push((*k, *v)))
+            .build();
+        cache.reconfigure_for_testing();
 
-    #[test]
-    fn basic_multi_threads() {
-        let num_threads = 4;
-        let cache = Cache::new(100);
+        // Make the cache exterior immutable.
+        let cache = cache;
+        let take = || std::mem::take(&mut *hits.lock().unwrap());
 
-        let handles = (0..num_threads)
-            .map(|id| {
-                let cache = cache.clone();
-                std::thread::spawn(move || {
-                    cache.insert(10, format!("{}-100", id));
-                    cache.get(&10);
-                    cache.insert(20, format!("{}-200", id));
-                    cache.invalidate(&10);
-                })
+        cache.insert('a', "alice");
+        cache.sync();
+        assert_eq!(cache.get(&'a'), Some("alice"));
+        // Served by the thread local cache.
+        assert_eq!(cache.get(&'a'), Some("alice"));
+        assert_eq!(cache.get(&'b'), None);
+        assert!(cache.contains_key(&'a'));
+        assert_eq!(take(), vec![('a', "alice"), ('a', "alice")]);
+
+        assert_eq!(cache.get_or_insert_with('a', || unreachable!()), "alice");
+        cache.get_or_insert_with('b', || "bob");
+        assert_eq!(take(), vec![('a', "alice")]);
+
+        // The listener may access the cache, even when called by `insert_with`,
+        // which holds a lock of the key while reading the current value.
+        let reentered = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let slot = Arc::new(Mutex::new(None::<Cache<char, u32>>));
+        let slot1 = Arc::clone(&slot);
+        let cache = CacheBuilder::new(100)
+            .access_listener(move |k: &char, _v: &u32| {
+                if !reentered.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    let c = slot1.lock().unwrap().clone().unwrap();
+                    c.insert_with(*k, |v| v.map_or(0, |v| v + 10));
+                }
             })
-            .collect::<Vec<_>>();
-
-        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+            .build();
+        *slot.lock().unwrap() = Some(cache.clone());
+        cache.insert('a', 1);
+        assert_eq!(cache.insert_with('a', |v| v.map_or(0, |v| v + 1)), 2);
+        assert_eq!(cache.get(&'a'), Some(12));
+        // Break the reference cycle.
+        slot.lock().unwrap().take();
+    }
 
-        assert!(cache.get(&10).is_none());
-        assert!(cache.get(&20).is_some());
+    #[test]
+    fn access_listener_panic() {
+        let cache = CacheBuilder::new(100)
+            .access_listener(|_k: &u32, _v: &u32| panic!("Panic in the access listener"))
+            .build();
+        cache.insert(1, 1);
+        // The panic is not propagated to the caller.
+        assert_eq!(cache.get(&1), Some(1));
     }
 
     #[test]
-    fn invalidate_all() {
-        let mut cache = Cache::new(100);
+    fn eviction_listener() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
+        let mut cache = CacheBuilder::new(3)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener(move |k, v, cause| removed1.lock().unwrap().push((*k, v, cause)))
+            .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
+        let take = || std::mem::take(&mut *removed.lock().unwrap());
 
-        cache.insert("a", "alice");
-        cache.insert("b", "bob");
-        cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        cache.sync();
+        cache.insert('a', "alice");
+        cache.insert('a', "anna");
+        cache.invalidate(&'a');
+        assert_eq!(
+            take(),
+            vec![
+                ('a', "alice", RemovalCause::Replaced),
+                ('a', "anna", RemovalCause::Explicit)
+            ]
+        );
 
+        cache.insert('b', "bob");
+        cache.insert('c', "cindy");
+        cache.insert('d', "david");
+        cache.sync();
+        cache.insert('e', "emily");
+        cache.sync();
+        // Either 'e' was rejected, or a victim was evicted for it.
+        let evicted = take();
+        assert_eq!(evicted.len(), 1);
+        assert!(matches!(
+            evicted[0].2,
+            RemovalCause::Size | RemovalCause::NotAdmitted
+        ));
+
+        mock.increment(Duration::from_secs(5));
         cache.invalidate_all();
         cache.sync();
+        let invalidated = take();
+        assert_eq!(invalidated.len(), 3);
+        assert!(invalidated
+            .iter()
+            .all(|(_, _, cause)| *cause == RemovalCause::Explicit));
 
-        cache.insert("d", "david");
+        cache.insert('f', "fred");
         cache.sync();
-
-        assert!(cache.get(&"a").is_none());
-        assert!(cache.get(&"b").is_none());
-        assert!(cache.get(&"c").is_none());
-        assert_eq!(cache.get(&"d"), Some("david"));
+        mock.increment(Duration::from_secs(10));
+        cache.sync();
+        assert_eq!(take(), vec![('f', "fred", RemovalCause::Expired)]);
     }
 
     #[test]
-    fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
-        use std::collections::HashSet;
+    fn eviction_listener_batched() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
 
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let batches1 = Arc::clone(&batches);
         let mut cache = CacheBuilder::new(100)
-            .support_invalidation_closures()
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener_batched(2, move |batch| {
+                let batch = batch.iter().map(|(k, v, cause)| (**k, *v, *cause));
+                batches1.lock().unwrap().push(batch.collect::<Vec<_>>());
+            })
             .build();
         cache.reconfigure_for_testing();
 
@@ -952,429 +7064,587 @@ mod tests {
 
         // Make the cache exterior immutable.
         let cache = cache;
+        let take = || std::mem::take(&mut *batches.lock().unwrap());
 
-        cache.insert(0, "alice");
-        cache.insert(1, "bob");
-        cache.insert(2, "alex");
-        cache.sync();
+        // A full batch is delivered right away.
+        cache.insert('a', "alice");
+        cache.insert('a', "anna");
+        cache.invalidate(&'a');
+        assert_eq!(
+            take(),
+            vec![vec![
+                ('a', "alice", RemovalCause::Replaced),
+                ('a', "anna", RemovalCause::Explicit)
+            ]]
+        );
 
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        // Others wait for the next maintenance pass.
+        cache.insert('b', "bob");
+        cache.insert('b', "bill");
+        assert!(take().is_empty());
         cache.sync();
+        assert_eq!(take(), vec![vec![('b', "bob", RemovalCause::Replaced)]]);
 
-        assert_eq!(cache.get(&0), Some("alice"));
-        assert_eq!(cache.get(&1), Some("bob"));
-        assert_eq!(cache.get(&2), Some("alex"));
-
-        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
-        cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
-        assert_eq!(cache.base.invalidation_predicate_count(), 1);
-
-        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
-
-        cache.insert(3, "alice");
-
-        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
-        std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
-        std::thread::sleep(Duration::from_millis(200));
-
-        assert!(cache.get(&0).is_none());
-        assert!(cache.get(&2).is_none());
-        assert_eq!(cache.get(&1), Some("bob"));
-        // This should survive as it was inserted after calling invalidate_entries_if.
-        assert_eq!(cache.get(&3), Some("alice"));
-        assert_eq!(cache.estimated_entry_count(), 2);
-        assert_eq!(cache.invalidation_predicate_count(), 0);
+        cache.insert('c', "cindy");
+        cache.insert('d', "david");
+        cache.sync();
+        mock.increment(Duration::from_secs(10));
+        cache.sync();
+        assert_eq!(
+            take(),
+            vec![
+                vec![
+                    ('b', "bill", RemovalCause::Expired),
+                    ('c', "cindy", RemovalCause::Expired)
+                ],
+                vec![('d', "david", RemovalCause::Expired)]
+            ]
+        );
+    }
 
-        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+    #[test]
+    fn read_with() {
+        let mut cache = CacheBuilder::new(100).record_stats().build();
+        cache.reconfigure_for_testing();
 
-        cache.invalidate_entries_if(|_k, &v| v == "alice")?;
-        cache.invalidate_entries_if(|_k, &v| v == "bob")?;
-        assert_eq!(cache.invalidation_predicate_count(), 2);
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
-        std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
-        std::thread::sleep(Duration::from_millis(200));
+        cache.insert('a', Arc::new(vec![1u8, 2, 3]));
+        cache.sync();
 
-        assert!(cache.get(&1).is_none());
-        assert!(cache.get(&3).is_none());
-        assert_eq!(cache.estimated_entry_count(), 0);
-        assert_eq!(cache.invalidation_predicate_count(), 0);
+        // The value is not cloned.
+        assert_eq!(cache.read_with(&'a', Arc::strong_count), Some(1));
+
+        // The value stays valid while the closure runs, even if the entry is
+        // removed meanwhile.
+        let sum = cache.read_with(&'a', |v| {
+            cache.invalidate(&'a');
+            cache.sync();
+            v.iter().map(|b| *b as u32).sum::<u32>()
+        });
+        assert_eq!(sum, Some(6));
+        assert_eq!(cache.read_with(&'a', |v| v.len()), None);
+        assert!(cache.is_table_empty());
 
-        Ok(())
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 2);
+        assert_eq!(stats.miss_count(), 1);
     }
 
     #[test]
-    fn time_to_live() {
+    fn weak_values() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
         let mut cache = CacheBuilder::new(100)
-            .time_to_live(Duration::from_secs(10))
+            .weak_values()
+            .eviction_listener(move |k, v: std::sync::Weak<String>, cause| {
+                removed1.lock().unwrap().push((*k, v.upgrade(), cause))
+            })
             .build();
-
         cache.reconfigure_for_testing();
 
-        let (clock, mock) = Clock::mock();
-        cache.set_expiration_clock(Some(clock));
-
         // Make the cache exterior immutable.
         let cache = cache;
+        let take = || std::mem::take(&mut *removed.lock().unwrap());
 
-        cache.insert("a", "alice");
+        let values = ["alice", "bob", "cindy"]
+            .iter()
+            .map(|s| Arc::new(s.to_string()))
+            .collect::<Vec<_>>();
+        let mut values = values.into_iter();
+        let (a, b, c) = (
+            values.next().unwrap(),
+            values.next().unwrap(),
+            values.next().unwrap(),
+        );
+        cache.insert('a', Arc::downgrade(&a));
+        cache.insert('b', Arc::downgrade(&b));
+        cache.insert('c', Arc::downgrade(&c));
         cache.sync();
+        assert_eq!(cache.get_upgraded(&'a'), Some(Arc::clone(&a)));
 
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        // A dead entry is a miss, and removed by the maintenance.
+        drop(a);
+        assert!(cache.get(&'a').is_none());
+        assert!(take().is_empty());
         cache.sync();
+        assert_eq!(take(), vec![('a', None, RemovalCause::Collected)]);
 
-        cache.get(&"a");
-
-        mock.increment(Duration::from_secs(5)); // 10 secs.
+        // get_upgraded removes a dead entry at once.
+        drop(b);
+        assert_eq!(cache.get_upgraded(&'b'), None);
+        assert_eq!(take(), vec![('b', None, RemovalCause::Collected)]);
         cache.sync();
+        assert!(take().is_empty());
 
-        assert_eq!(cache.get(&"a"), None);
-        assert!(cache.is_table_empty());
+        assert_eq!(cache.get_upgraded(&'c'), Some(Arc::clone(&c)));
+        assert_eq!(cache.estimated_entry_count(), 1);
+    }
 
-        cache.insert("b", "bob");
-        cache.sync();
+    #[cfg(feature = "serde")]
+    #[test]
+    fn export_and_import_policy_state() {
+        use crate::{
+            common::deque::CacheRegion::{MainProbation, MainProtected},
+            policy::PolicyState,
+        };
+        use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
 
-        assert_eq!(cache.estimated_entry_count(), 1);
+        type FixedState = BuildHasherDefault<DefaultHasher>;
 
-        mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.sync();
+        let new_cache = |max_capacity| {
+            let mut cache = CacheBuilder::new(max_capacity)
+                .promote_after_hits(1)
+                .build_with_hasher(FixedState::default());
+            cache.reconfigure_for_testing();
+            for key in 0..10 {
+                cache.insert(key, key);
+            }
+            cache.sync();
+            cache
+        };
 
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.estimated_entry_count(), 1);
+        let cache1 = new_cache(100);
+        for _ in 0..5 {
+            cache1.get(&5);
+        }
+        cache1.sync();
+        assert_eq!(cache1.estimated_frequency(&5), 5);
+        assert_eq!(cache1.base.region_of(&5), Some(MainProtected));
+
+        let json = serde_json::to_string(&cache1.export_policy_state()).unwrap();
+        let state: PolicyState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state, cache1.export_policy_state());
+
+        let cache2 = new_cache(100);
+        assert_eq!(cache2.estimated_frequency(&5), 0);
+        assert_eq!(cache2.base.region_of(&5), Some(MainProbation));
+        assert!(cache2.import_policy_state(&state));
+        assert_eq!(cache2.estimated_frequency(&5), 5);
+        assert_eq!(cache2.base.region_of(&5), Some(MainProtected));
+        assert_eq!(cache2.base.region_of(&4), Some(MainProbation));
+
+        // A cache with another capacity ignores the state.
+        let cache3 = new_cache(200);
+        assert!(!cache3.import_policy_state(&state));
+        assert_eq!(cache3.estimated_frequency(&5), 0);
+
+        // So does a cache hashing the keys differently.
+        let mut cache4: Cache<i32, i32> = CacheBuilder::new(100).build();
+        cache4.reconfigure_for_testing();
+        assert!(!cache4.import_policy_state(&state));
+    }
 
-        cache.insert("b", "bill");
-        cache.sync();
+    #[test]
+    fn eviction_listener_reenters_cache() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Mutex,
+        };
 
-        mock.increment(Duration::from_secs(5)); // 20 secs
-        cache.sync();
+        // More than the capacity of the write buffer.
+        const NUM_WRITES: u32 = 5_000;
+
+        // The listener gets the cache from here, so that it does not keep the
+        // cache alive.
+        let slot: Arc<Mutex<Option<Cache<u32, u32>>>> = Arc::default();
+        let slot1 = Arc::clone(&slot);
+        let reentered = AtomicBool::new(false);
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let mut cache = CacheBuilder::new(10)
+            .eviction_listener(move |_k, _v, _cause| {
+                // Re-enter only once, as the writes below evict more entries.
+                if reentered.swap(true, Ordering::AcqRel) {
+                    return;
+                }
+                if let Some(cache) = slot1.lock().unwrap().clone() {
+                    // Fill the write buffer from the listener.
+                    for i in 0..NUM_WRITES {
+                        cache.insert(1_000 + i, i);
+                    }
+                    cache.invalidate(&1_000);
+                }
+                // The clone of the cache has been dropped here, so the cache will
+                // not be dropped by the housekeeper thread running this listener.
+                tx.lock().unwrap().send(()).unwrap();
+            })
+            .build();
+        cache.reconfigure_for_testing();
 
-        assert_eq!(cache.get(&"b"), Some("bill"));
-        assert_eq!(cache.estimated_entry_count(), 1);
+        // Make the cache exterior immutable.
+        let cache = cache;
+        *slot.lock().unwrap() = Some(cache.clone());
+
+        let writer = {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                for i in 0..20 {
+                    cache.insert(i, i);
+                }
+                cache.sync();
+            })
+        };
+        rx.recv_timeout(Duration::from_secs(30))
+            .expect("the eviction listener is deadlocked");
+        writer.join().unwrap();
 
-        mock.increment(Duration::from_secs(5)); // 25 secs
         cache.sync();
+        assert_eq!(cache.get(&1_000), None);
+        assert!(cache.estimated_entry_count() <= 10);
 
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), None);
-        assert!(cache.is_table_empty());
+        // Break the reference cycle.
+        *slot.lock().unwrap() = None;
     }
 
     #[test]
-    fn time_to_idle() {
-        let mut cache = CacheBuilder::new(100)
-            .time_to_idle(Duration::from_secs(10))
+    fn eviction_listener_with_info() {
+        use crate::{
+            common::deque::CacheRegion::{MainProbation, MainProtected},
+            notification::{CacheRegion, RemovalCause},
+        };
+        use std::sync::Mutex;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
+        let mut cache = CacheBuilder::new(3)
+            .promote_after_hits(1)
+            .eviction_listener_with_info(move |k, _v, info| {
+                removed1
+                    .lock()
+                    .unwrap()
+                    .push((*k, info.cause(), info.region()))
+            })
             .build();
-
         cache.reconfigure_for_testing();
 
-        let (clock, mock) = Clock::mock();
-        cache.set_expiration_clock(Some(clock));
-
         // Make the cache exterior immutable.
         let cache = cache;
+        let take = || std::mem::take(&mut *removed.lock().unwrap());
 
-        cache.insert("a", "alice");
+        cache.insert('a', "alice");
+        cache.insert('b', "bob");
+        cache.insert('c', "cindy");
         cache.sync();
-
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        for _ in 0..3 {
+            cache.get(&'a');
+            cache.get(&'b');
+            cache.get(&'c');
+        }
         cache.sync();
-
-        assert_eq!(cache.get(&"a"), Some("alice"));
-
-        mock.increment(Duration::from_secs(5)); // 10 secs.
+        // The protected region holds up to 80% of the entries, so the last
+        // promotions of 'b' and 'c' have demoted 'a' to the probation region.
+        assert_eq!(cache.base.region_of(&'a'), Some(MainProbation));
+        assert_eq!(cache.base.region_of(&'b'), Some(MainProtected));
+        assert_eq!(cache.base.region_of(&'c'), Some(MainProtected));
+
+        // 'd' (count: 0) loses the admission contest against the incumbents.
+        cache.insert('d', "david");
         cache.sync();
+        assert_eq!(take(), vec![('d', RemovalCause::NotAdmitted, None)]);
+        assert!(!cache.contains_key(&'d'));
 
-        cache.insert("b", "bob");
+        // 'e' (count: 6) wins against 'a' (count: 3), which is evicted from the
+        // probation region.
+        for _ in 0..6 {
+            cache.get(&'e');
+        }
         cache.sync();
-
-        assert_eq!(cache.estimated_entry_count(), 2);
-
-        mock.increment(Duration::from_secs(5)); // 15 secs.
+        cache.insert('e', "emily");
         cache.sync();
+        assert_eq!(
+            take(),
+            vec![('a', RemovalCause::Size, Some(CacheRegion::MainProbation))]
+        );
+        assert!(cache.contains_key(&'e'));
 
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.estimated_entry_count(), 1);
-
-        mock.increment(Duration::from_secs(10)); // 25 secs
-        cache.sync();
+        // 'e' is evicted from the probation region before 'b' from the protected
+        // region.
+        assert_eq!(cache.evict_now(2), 2);
+        assert_eq!(
+            take(),
+            vec![
+                ('e', RemovalCause::Size, Some(CacheRegion::MainProbation)),
+                ('b', RemovalCause::Size, Some(CacheRegion::MainProtected)),
+            ]
+        );
 
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), None);
-        assert!(cache.is_table_empty());
+        cache.invalidate(&'c');
+        assert_eq!(take(), vec![('c', RemovalCause::Explicit, None)]);
     }
 
     #[test]
-    fn get_or_insert_with() {
-        use std::thread::{sleep, spawn};
-
-        let cache = Cache::new(100);
-        const KEY: u32 = 0;
-
-        // This test will run five threads:
-        //
-        // Thread1 will be the first thread to call `get_or_insert_with` for a key, so
-        // its async block will be evaluated and then a &str value "thread1" will be
-        // inserted to the cache.
-        let thread1 = {
-            let cache1 = cache.clone();
-            spawn(move || {
-                // Call `get_or_insert_with` immediately.
-                let v = cache1.get_or_insert_with(KEY, || {
-                    // Wait for 300 ms and return a &str value.
-                    sleep(Duration::from_millis(300));
-                    "thread1"
-                });
-                assert_eq!(v, "thread1");
-            })
-        };
-
-        // Thread2 will be the second thread to call `get_or_insert_with` for the same
-        // key, so its async block will not be evaluated. Once thread1's async block
-        // finishes, it will get the value inserted by thread1's async block.
-        let thread2 = {
-            let cache2 = cache.clone();
-            spawn(move || {
-                // Wait for 100 ms before calling `get_or_insert_with`.
-                sleep(Duration::from_millis(100));
-                let v = cache2.get_or_insert_with(KEY, || unreachable!());
-                assert_eq!(v, "thread1");
-            })
-        };
-
-        // Thread3 will be the third thread to call `get_or_insert_with` for the same
-        // key. By the time it calls, thread1's async block should have finished
-        // already and the value should be already inserted to the cache. So its
-        // async block will not be evaluated and will get the value insert by thread1's
-        // async block immediately.
-        let thread3 = {
-            let cache3 = cache.clone();
-            spawn(move || {
-                // Wait for 400 ms before calling `get_or_insert_with`.
-                sleep(Duration::from_millis(400));
-                let v = cache3.get_or_insert_with(KEY, || unreachable!());
-                assert_eq!(v, "thread1");
-            })
-        };
+    fn eviction_filter() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
 
-        // Thread4 will call `get` for the same key. It will call when thread1's async
-        // block is still running, so it will get none for the key.
-        let thread4 = {
-            let cache4 = cache.clone();
-            spawn(move || {
-                // Wait for 200 ms before calling `get`.
-                sleep(Duration::from_millis(200));
-                let maybe_v = cache4.get(&KEY);
-                assert!(maybe_v.is_none());
+        let causes = Arc::new(Mutex::new(Vec::new()));
+        let causes1 = Arc::clone(&causes);
+        let mut cache = CacheBuilder::new(3)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_filter(move |k, _v, cause| {
+                causes1.lock().unwrap().push(cause);
+                *k != 'a'
             })
-        };
+            .build();
+        cache.reconfigure_for_testing();
 
-        // Thread5 will call `get` for the same key. It will call after thread1's async
-        // block finished, so it will get the value insert by thread1's async block.
-        let thread5 = {
-            let cache5 = cache.clone();
-            spawn(move || {
-                // Wait for 400 ms before calling `get`.
-                sleep(Duration::from_millis(400));
-                let maybe_v = cache5.get(&KEY);
-                assert_eq!(maybe_v, Some("thread1"));
-            })
-        };
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
 
-        for t in vec![thread1, thread2, thread3, thread4, thread5] {
-            t.join().expect("Failed to join");
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in "abcdefghij".chars() {
+            cache.insert(key, key);
+            cache.sync();
         }
+        // 'a' has been vetoed while the others are evicted or rejected.
+        assert!(cache.contains_key(&'a'));
+        assert_eq!(cache.estimated_entry_count(), 3);
+        let causes = std::mem::take(&mut *causes.lock().unwrap());
+        assert!(!causes.is_empty());
+        assert!(causes
+            .iter()
+            .all(|c| matches!(c, RemovalCause::Size | RemovalCause::NotAdmitted)));
+
+        // The expiration cannot be vetoed.
+        mock.increment(Duration::from_secs(10));
+        cache.sync();
+        assert!(!cache.contains_key(&'a'));
+        assert_eq!(cache.estimated_entry_count(), 0);
+
+        cache.insert('a', 'a');
+        cache.invalidate(&'a');
+        assert!(!cache.contains_key(&'a'));
     }
 
     #[test]
-    fn get_or_try_insert_with() {
-        use std::{
-            sync::Arc,
-            thread::{sleep, spawn},
-        };
+    fn eviction_filter_vetoes_all() {
+        let mut cache = CacheBuilder::new(3)
+            .eviction_filter(|_k, _v, _cause| false)
+            .build();
+        cache.reconfigure_for_testing();
 
-        // Note that MyError does not implement std::error::Error trait
-        // like anyhow::Error.
-        #[derive(Debug)]
-        pub struct MyError(String);
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        type MyResult<T> = Result<T, Arc<MyError>>;
+        for key in 0..10 {
+            cache.insert(key, key);
+            cache.sync();
+        }
+        // The cache exceeds its capacity rather than evicting any entry.
+        assert_eq!(cache.estimated_entry_count(), 10);
+        assert!((0..10).all(|key| cache.contains_key(&key)));
 
-        let cache = Cache::new(100);
-        const KEY: u32 = 0;
+        cache.invalidate_all();
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+    }
 
-        // This test will run eight async threads:
-        //
-        // Thread1 will be the first thread to call `get_or_insert_with` for a key, so
-        // its async block will be evaluated and then an error will be returned.
-        // Nothing will be inserted to the cache.
-        let thread1 = {
-            let cache1 = cache.clone();
-            spawn(move || {
-                // Call `get_or_try_insert_with` immediately.
-                let v = cache1.get_or_try_insert_with(KEY, || {
-                    // Wait for 300 ms and return an error.
-                    sleep(Duration::from_millis(300));
-                    Err(MyError("thread1 error".into()))
-                });
-                assert!(v.is_err());
-            })
-        };
+    #[test]
+    fn eviction_filter_cannot_admit_oversized_entry() {
+        let mut cache = CacheBuilder::new(10)
+            .weigher(|_k, v: &u32| *v)
+            .eviction_filter(|_k, _v, _cause| false)
+            .build();
+        cache.reconfigure_for_testing();
 
-        // Thread2 will be the second thread to call `get_or_insert_with` for the same
-        // key, so its async block will not be evaluated. Once thread1's async block
-        // finishes, it will get the same error value returned by thread1's async
-        // block.
-        let thread2 = {
-            let cache2 = cache.clone();
-            spawn(move || {
-                // Wait for 100 ms before calling `get_or_try_insert_with`.
-                sleep(Duration::from_millis(100));
-                let v: MyResult<_> = cache2.get_or_try_insert_with(KEY, || unreachable!());
-                assert!(v.is_err());
-            })
-        };
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        // Thread3 will be the third thread to call `get_or_insert_with` for the same
-        // key. By the time it calls, thread1's async block should have finished
-        // already, but the key still does not exist in the cache. So its async block
-        // will be evaluated and then an okay &str value will be returned. That value
-        // will be inserted to the cache.
-        let thread3 = {
-            let cache3 = cache.clone();
-            spawn(move || {
-                // Wait for 400 ms before calling `get_or_try_insert_with`.
-                sleep(Duration::from_millis(400));
-                let v: MyResult<_> = cache3.get_or_try_insert_with(KEY, || {
-                    // Wait for 300 ms and return an Ok(&str) value.
-                    sleep(Duration::from_millis(300));
-                    Ok("thread3")
-                });
-                assert_eq!(v.unwrap(), "thread3");
-            })
-        };
+        cache.insert('a', 4);
+        cache.sync();
+        // Heavier than the max capacity, so it is rejected despite the veto.
+        cache.insert('b', 11);
+        cache.sync();
 
-        // thread4 will be the fourth thread to call `get_or_insert_with` for the same
-        // key. So its async block will not be evaluated. Once thread3's async block
-        // finishes, it will get the same okay &str value.
-        let thread4 = {
-            let cache4 = cache.clone();
-            spawn(move || {
-                // Wait for 500 ms before calling `get_or_try_insert_with`.
-                sleep(Duration::from_millis(500));
-                let v: MyResult<_> = cache4.get_or_try_insert_with(KEY, || unreachable!());
-                assert_eq!(v.unwrap(), "thread3");
-            })
-        };
+        assert!(cache.contains_key(&'a'));
+        assert!(!cache.contains_key(&'b'));
+        assert_eq!(cache.weighted_size(), 4);
+    }
 
-        // Thread5 will be the fifth thread to call `get_or_insert_with` for the same
-        // key. So its async block will not be evaluated. By the time it calls,
-        // thread3's async block should have finished already, so its async block will
-        // not be evaluated and will get the value insert by thread3's async block
-        // immediately.
-        let thread5 = {
-            let cache5 = cache.clone();
-            spawn(move || {
-                // Wait for 800 ms before calling `get_or_try_insert_with`.
-                sleep(Duration::from_millis(800));
-                let v: MyResult<_> = cache5.get_or_try_insert_with(KEY, || unreachable!());
-                assert_eq!(v.unwrap(), "thread3");
+    #[test]
+    fn invoke_listener_on_drop() {
+        use crate::notification::RemovalCause;
+        use std::sync::Mutex;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
+        let cache = CacheBuilder::new(100)
+            .eviction_listener(move |k, _v, cause| {
+                assert_eq!(cause, RemovalCause::Shutdown);
+                if *k == 3 {
+                    panic!("Panic in the eviction listener");
+                }
+                removed1.lock().unwrap().push(*k);
             })
-        };
+            .invoke_listener_on_drop(true)
+            .build();
 
-        // Thread6 will call `get` for the same key. It will call when thread1's async
-        // block is still running, so it will get none for the key.
-        let thread6 = {
-            let cache6 = cache.clone();
-            spawn(move || {
-                // Wait for 200 ms before calling `get`.
-                sleep(Duration::from_millis(200));
-                let maybe_v = cache6.get(&KEY);
-                assert!(maybe_v.is_none());
-            })
-        };
+        for i in 0..5 {
+            cache.insert(i, i);
+        }
+        cache.sync();
+        // Updating an entry moves it to the back of the write order. Some writes
+        // are still pending when the cache is dropped.
+        cache.insert(1, 10);
+        cache.insert(5, 5);
+
+        // Dropping a clone does not notify.
+        std::mem::drop(cache.clone());
+        assert!(removed.lock().unwrap().is_empty());
+
+        // The panic for key 3 is caught, and the other keys are still notified.
+        std::mem::drop(cache);
+        assert_eq!(*removed.lock().unwrap(), vec![0, 2, 4, 1, 5]);
+    }
 
-        // Thread7 will call `get` for the same key. It will call after thread1's async
-        // block finished with an error. So it will get none for the key.
-        let thread7 = {
-            let cache7 = cache.clone();
-            spawn(move || {
-                // Wait for 400 ms before calling `get`.
-                sleep(Duration::from_millis(400));
-                let maybe_v = cache7.get(&KEY);
-                assert!(maybe_v.is_none());
-            })
-        };
+    #[test]
+    fn clear_with() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-        // Thread8 will call `get` for the same key. It will call after thread3's async
-        // block finished, so it will get the value insert by thread3's async block.
-        let thread8 = {
-            let cache8 = cache.clone();
-            spawn(move || {
-                // Wait for 800 ms before calling `get`.
-                sleep(Duration::from_millis(800));
-                let maybe_v = cache8.get(&KEY);
-                assert_eq!(maybe_v, Some("thread3"));
+        let notified = Arc::new(AtomicUsize::new(0));
+        let notified1 = Arc::clone(&notified);
+        let mut cache = CacheBuilder::new(100)
+            .eviction_listener(move |_k, _v, _cause| {
+                notified1.fetch_add(1, Ordering::Relaxed);
             })
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let fill = |range: std::ops::Range<u32>| {
+            for i in range {
+                cache.insert(i, i);
+            }
+            cache.sync();
+            mock.increment(Duration::from_secs(1));
         };
 
-        for t in vec![
-            thread1, thread2, thread3, thread4, thread5, thread6, thread7, thread8,
-        ] {
-            t.join().expect("Failed to join");
-        }
+        // clear() discards the entries without notifications.
+        fill(0..10);
+        cache.clear();
+        cache.sync();
+        assert_eq!(cache.get(&0), None);
+        assert!(cache.is_table_empty());
+        assert_eq!(notified.load(Ordering::Relaxed), 0);
+
+        // invalidate_all() notifies each entry.
+        fill(10..20);
+        cache.invalidate_all();
+        cache.sync();
+        assert!(cache.is_table_empty());
+        assert_eq!(notified.load(Ordering::Relaxed), 10);
+
+        // Entries inserted after clear() are notified as usual.
+        fill(20..25);
+        cache.clear_with(false);
+        fill(25..30);
+        cache.clear_with(true);
+        cache.sync();
+        assert!(cache.is_table_empty());
+        assert_eq!(notified.load(Ordering::Relaxed), 15);
     }
 
     #[test]
-    // https://github.com/moka-rs/moka/issues/43
-    fn handle_panic_in_get_or_insert_with() {
-        use std::{sync::Barrier, thread};
+    fn top_frequencies() {
+        let mut cache = CacheBuilder::new(10)
+            .key_normalizer(|k: &u32| k % 100)
+            .build();
+        cache.reconfigure_for_testing();
 
-        let cache = Cache::new(16);
-        let barrier = Arc::new(Barrier::new(2));
-        {
-            let cache_ref = cache.clone();
-            let barrier_ref = barrier.clone();
-            thread::spawn(move || {
-                let _ = cache_ref.get_or_insert_with(1, || {
-                    barrier_ref.wait();
-                    thread::sleep(Duration::from_millis(50));
-                    panic!("Panic during get_or_try_insert_with");
-                });
-            });
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+        cache.sync();
+        for (key, reads) in &[(3, 2), (5, 6), (7, 4)] {
+            for _ in 0..*reads {
+                cache.get(key);
+            }
         }
+        cache.sync();
 
-        barrier.wait();
-        assert_eq!(cache.get_or_insert_with(1, || 5), 5);
+        let freqs = cache.top_frequencies(&[1, 3, 105, 7, 42]);
+        let keys = freqs.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        // 105 is normalized to 5. 42 has never been accessed.
+        assert_eq!(keys, vec![5, 7, 3, 1, 42]);
+        assert!(freqs[0].1 >= 6);
+        assert_eq!(freqs[4].1, 0);
     }
 
     #[test]
-    // https://github.com/moka-rs/moka/issues/43
-    fn handle_panic_in_get_or_try_insert_with() {
-        use std::{sync::Barrier, thread};
+    fn estimated_frequency() {
+        let mut cache = CacheBuilder::new(10)
+            .key_normalizer(|k: &String| k.to_lowercase())
+            .build();
+        cache.reconfigure_for_testing();
 
-        let cache = Cache::new(16);
-        let barrier = Arc::new(Barrier::new(2));
-        {
-            let cache_ref = cache.clone();
-            let barrier_ref = barrier.clone();
-            thread::spawn(move || {
-                let _ = cache_ref.get_or_try_insert_with(1, || {
-                    barrier_ref.wait();
-                    thread::sleep(Duration::from_millis(50));
-                    panic!("Panic during get_or_try_insert_with");
-                }) as Result<_, Arc<Infallible>>;
-            });
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a".to_string(), 1);
+        cache.sync();
+        for _ in 0..3 {
+            cache.get(&"a".to_string());
         }
+        // Misses are counted too.
+        cache.get(&"b".to_string());
+        cache.sync();
 
-        barrier.wait();
-        assert_eq!(
-            cache.get_or_try_insert_with(1, || Ok(5)) as Result<_, Arc<Infallible>>,
-            Ok(5)
-        );
+        // Only the reads are counted.
+        assert!(cache.estimated_frequency(&"A".to_string()) >= 3);
+        assert!(cache.estimated_frequency(&"a".to_string()) >= 3);
+        assert!(cache.estimated_frequency(&"b".to_string()) >= 1);
+
+        // Saturates at 15.
+        for _ in 0..20 {
+            cache.get(&"a".to_string());
+        }
+        cache.sync();
+        assert_eq!(cache.estimated_frequency(&"a".to_string()), 15);
+    }
+
+    #[test]
+    fn shared_frequency_sketch() {
+        use crate::policy::SharedFrequencySketch;
+
+        let sketch = SharedFrequencySketch::new(100);
+        let cache1 = CacheBuilder::new(100)
+            .shared_frequency_sketch(sketch.clone())
+            .build();
+        let cache2: Cache<&str, i32> = CacheBuilder::new(100)
+            .shared_frequency_sketch(sketch)
+            .build();
+
+        cache1.insert("a", 1);
+        for _ in 0..3 {
+            cache1.get(&"a");
+        }
+        cache1.sync();
+
+        // The reads through cache1 are visible to cache2, even though cache2 is
+        // empty and has not enabled its own sketch yet.
+        assert_eq!(cache2.estimated_entry_count(), 0);
+        assert!(cache2.estimated_frequency(&"a") >= 3);
+        assert_eq!(cache2.estimated_frequency(&"b"), 0);
+
+        // And vice versa.
+        cache2.insert("a", 1);
+        cache2.get(&"a");
+        cache2.sync();
+        assert!(cache1.estimated_frequency(&"a") >= 4);
     }
 }
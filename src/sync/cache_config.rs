@@ -0,0 +1,137 @@
+use super::{
+    coherence::Coherence, value_dropper::ValueDropper, EvictionFilter, KeyNormalizer, Loader,
+    MaxAge, Weigher, WeightIncreasePolicy,
+};
+use crate::{
+    notification::{AccessListener, EvictionListener},
+    policy::SharedFrequencySketch,
+};
+
+#[cfg(feature = "tracing")]
+use super::KeyTracer;
+
+use std::{sync::Arc, time::Duration};
+
+/// The configuration of a cache. It is filled by the `CacheBuilder`s, and cloned
+/// by `Cache::rebuilt_from` to build another cache with the same configuration.
+///
+/// `thread_local_cache_capacity`, `coherence` and `loader` are only used by
+/// `sync::Cache` and `SegmentedCache`.
+pub(crate) struct CacheConfig<K, V, S> {
+    pub(crate) max_capacity: Option<u64>,
+    pub(crate) initial_capacity: Option<usize>,
+    pub(crate) build_hasher: S,
+    pub(crate) weigher: Option<Weigher<K, V>>,
+    pub(crate) key_normalizer: Option<KeyNormalizer<K>>,
+    pub(crate) time_to_live: Option<Duration>,
+    pub(crate) time_to_idle: Option<Duration>,
+    pub(crate) invalidator_enabled: bool,
+    pub(crate) stats_enabled: bool,
+    pub(crate) small_cache_hint: bool,
+    pub(crate) promote_after_hits: Option<u8>,
+    pub(crate) weight_increase_policy: WeightIncreasePolicy,
+    pub(crate) name: Option<String>,
+    pub(crate) eviction_listener: Option<EvictionListener<K, V>>,
+    pub(crate) invoke_listener_on_drop: bool,
+    pub(crate) shared_frequency_sketch: Option<SharedFrequencySketch>,
+    pub(crate) max_age: Option<MaxAge>,
+    pub(crate) expiration_tick: Option<Duration>,
+    pub(crate) expiration_jitter: Option<f64>,
+    pub(crate) eviction_filter: Option<EvictionFilter<K, V>>,
+    pub(crate) maintenance_interval: Option<Duration>,
+    pub(crate) access_listener: Option<AccessListener<K, V>>,
+    pub(crate) access_record_throttle: Option<Duration>,
+    pub(crate) reservation_high_water_mark: Option<u64>,
+    pub(crate) collection_check: Option<fn(&V) -> bool>,
+    pub(crate) value_dropper: Option<Arc<ValueDropper<V>>>,
+    #[cfg(feature = "zeroize")]
+    pub(crate) value_zeroizer: Option<fn(&mut V)>,
+    #[cfg(feature = "tracing")]
+    pub(crate) key_tracer: Option<KeyTracer<K>>,
+    pub(crate) thread_local_cache_capacity: Option<usize>,
+    pub(crate) coherence: Option<Coherence<K>>,
+    pub(crate) loader: Option<Loader<K, V>>,
+}
+
+impl<K, V, S> CacheConfig<K, V, S> {
+    /// Returns the configuration of an unbounded cache with no optional feature
+    /// enabled.
+    pub(crate) fn new(build_hasher: S) -> Self {
+        Self {
+            max_capacity: None,
+            initial_capacity: None,
+            build_hasher,
+            weigher: None,
+            key_normalizer: None,
+            time_to_live: None,
+            time_to_idle: None,
+            invalidator_enabled: false,
+            stats_enabled: false,
+            small_cache_hint: false,
+            promote_after_hits: None,
+            weight_increase_policy: WeightIncreasePolicy::Admit,
+            name: None,
+            eviction_listener: None,
+            invoke_listener_on_drop: false,
+            shared_frequency_sketch: None,
+            max_age: None,
+            expiration_tick: None,
+            expiration_jitter: None,
+            eviction_filter: None,
+            maintenance_interval: None,
+            access_listener: None,
+            access_record_throttle: None,
+            reservation_high_water_mark: None,
+            collection_check: None,
+            value_dropper: None,
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: None,
+            #[cfg(feature = "tracing")]
+            key_tracer: None,
+            thread_local_cache_capacity: None,
+            coherence: None,
+            loader: None,
+        }
+    }
+}
+
+// Not derived, as it would require `K` and `V` to be `Clone`.
+impl<K, V, S: Clone> Clone for CacheConfig<K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            max_capacity: self.max_capacity,
+            initial_capacity: self.initial_capacity,
+            build_hasher: self.build_hasher.clone(),
+            weigher: self.weigher.as_ref().map(Arc::clone),
+            key_normalizer: self.key_normalizer.as_ref().map(Arc::clone),
+            time_to_live: self.time_to_live,
+            time_to_idle: self.time_to_idle,
+            invalidator_enabled: self.invalidator_enabled,
+            stats_enabled: self.stats_enabled,
+            small_cache_hint: self.small_cache_hint,
+            promote_after_hits: self.promote_after_hits,
+            weight_increase_policy: self.weight_increase_policy,
+            name: self.name.clone(),
+            eviction_listener: self.eviction_listener.clone(),
+            invoke_listener_on_drop: self.invoke_listener_on_drop,
+            shared_frequency_sketch: self.shared_frequency_sketch.clone(),
+            max_age: self.max_age,
+            expiration_tick: self.expiration_tick,
+            expiration_jitter: self.expiration_jitter,
+            eviction_filter: self.eviction_filter.as_ref().map(Arc::clone),
+            maintenance_interval: self.maintenance_interval,
+            access_listener: self.access_listener.as_ref().map(Arc::clone),
+            access_record_throttle: self.access_record_throttle,
+            reservation_high_water_mark: self.reservation_high_water_mark,
+            collection_check: self.collection_check,
+            value_dropper: self.value_dropper.as_ref().map(Arc::clone),
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: self.value_zeroizer,
+            #[cfg(feature = "tracing")]
+            key_tracer: self.key_tracer.as_ref().map(Arc::clone),
+            thread_local_cache_capacity: self.thread_local_cache_capacity,
+            coherence: self.coherence.clone(),
+            loader: self.loader.as_ref().map(Arc::clone),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+/// A transport to tell the caches in other processes that a key has been
+/// modified locally, so that they can drop their copies of the entry.
+///
+/// Set it to a cache by [`CacheBuilder::coherence_bus`][coherence-bus], and call
+/// [`Cache::apply_remote_invalidation`][apply-remote] on the other side when an
+/// invalidation has been received.
+///
+/// [coherence-bus]: ./struct.CacheBuilder.html#method.coherence_bus
+/// [apply-remote]: ./struct.Cache.html#method.apply_remote_invalidation
+pub trait CoherenceBus<K>: Send + Sync + 'static {
+    /// Publishes an invalidation of the key to the other processes.
+    ///
+    /// This method is called on the thread that has modified the cache, right
+    /// after the modification. It should not block for long; hand the key over to
+    /// a background task if the transport can be slow.
+    fn publish_invalidation(&self, key: &K);
+}
+
+/// Determines which local modifications are published to a `CoherenceBus`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublishOn {
+    /// Publishes on `invalidate`.
+    Invalidate,
+    /// Publishes on `insert` and its variants.
+    Insert,
+    /// Publishes on both `insert` and `invalidate`.
+    InsertAndInvalidate,
+}
+
+pub(crate) struct Coherence<K> {
+    bus: Arc<dyn CoherenceBus<K>>,
+    publish_on: PublishOn,
+}
+
+impl<K> Clone for Coherence<K> {
+    fn clone(&self) -> Self {
+        Self {
+            bus: Arc::clone(&self.bus),
+            publish_on: self.publish_on,
+        }
+    }
+}
+
+impl<K: 'static> Coherence<K> {
+    pub(crate) fn new(bus: Arc<dyn CoherenceBus<K>>, publish_on: PublishOn) -> Self {
+        Self { bus, publish_on }
+    }
+
+    pub(crate) fn on_insert(&self, key: &K) {
+        if self.publish_on != PublishOn::Invalidate {
+            self.bus.publish_invalidation(key);
+        }
+    }
+
+    pub(crate) fn on_invalidate(&self, key: &K) {
+        if self.publish_on != PublishOn::Insert {
+            self.bus.publish_invalidation(key);
+        }
+    }
+}
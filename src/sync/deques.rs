@@ -6,7 +6,8 @@ use std::{ptr::NonNull, sync::Arc};
 pub(crate) struct Deques<K> {
     pub(crate) window: Deque<KeyHashDate<K>>, //    Not used yet.
     pub(crate) probation: Deque<KeyHashDate<K>>,
-    pub(crate) protected: Deque<KeyHashDate<K>>, // Not used yet.
+    // Only used when `promote_after_hits` is set.
+    pub(crate) protected: Deque<KeyHashDate<K>>,
     pub(crate) write_order: Deque<KeyDate<K>>,
 }
 
@@ -70,6 +71,48 @@ impl<K> Deques<K> {
         }
     }
 
+    pub(crate) fn is_in_probation_ao<V>(&self, entry: &Arc<ValueEntry<K, V>>) -> bool {
+        entry
+            .access_order_q_node()
+            .map(|node| self.probation.contains(unsafe { node.as_ref() }))
+            .unwrap_or_default()
+    }
+
+    /// Moves the node of the entry from the probation deque to the back of the
+    /// protected deque. Does nothing if the node is not in the probation deque.
+    pub(crate) fn promote_ao<V>(&mut self, entry: &Arc<ValueEntry<K, V>>) {
+        if let Some(node) = entry.access_order_q_node() {
+            let p = unsafe { node.as_ref() };
+            if self.probation.contains(p) {
+                unsafe { Self::relink_ao(&mut self.probation, &mut self.protected, node) };
+            }
+        }
+    }
+
+    /// Moves the node at the front of the protected deque to the back of the
+    /// probation deque, and resets its hit counter.
+    pub(crate) fn demote_front_ao(&mut self) {
+        if let Some(node) = self.protected.peek_front() {
+            node.element.entry_info().reset_hits();
+            let node = NonNull::from(node);
+            unsafe { Self::relink_ao(&mut self.protected, &mut self.probation, node) };
+        }
+    }
+
+    /// Moves the node to the back of another deque without reallocating it, so
+    /// that the pointer held by the `ValueEntry` stays valid.
+    unsafe fn relink_ao(
+        from: &mut Deque<KeyHashDate<K>>,
+        to: &mut Deque<KeyHashDate<K>>,
+        node: NonNull<DeqNode<KeyHashDate<K>>>,
+    ) {
+        from.unlink(node);
+        // Not creating a mutable reference overlapping `element`.
+        (*node.as_ptr()).region = to.region().clone();
+        let moved = to.push_back(Box::from_raw(node.as_ptr()));
+        debug_assert_eq!(moved, node);
+    }
+
     pub(crate) fn move_to_back_ao_in_deque<V>(
         deq_name: &str,
         deq: &mut Deque<KeyHashDate<K>>,
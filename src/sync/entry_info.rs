@@ -1,10 +1,13 @@
-use std::sync::{
-    atomic::{AtomicBool, AtomicU32, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use super::{AccessTime, CacheFeatures};
-use crate::common::{atomic_time::AtomicInstant, time::Instant};
+use crate::common::{atomic_time::AtomicInstant, atomic_u64::AtomicU64, time::Instant};
 
 // We use enum-based dynamic dispatch here, rather than using trait-object-based
 // dynamic dispatch. Our benchmark programs showed enum-based dispatch was slightly
@@ -18,23 +21,74 @@ pub(crate) enum EntryInfo {
 #[derive(Default)]
 pub(crate) struct Plain {
     is_admitted: AtomicBool,
+    // Set when the value has been loaded by `prefetch` and not read yet. Only
+    // used when the stats are recorded.
+    is_prefetched: AtomicBool,
     last_accessed: AtomicInstant,
     last_modified: AtomicInstant,
+    // When the current life of the key started, i.e. when the key was inserted
+    // while absent (or past its max age). Kept across updates.
+    created_at: AtomicInstant,
+    time_to_idle: DurationOverride,
+    // Set to the time-to-live randomized by the expiration jitter when the key is
+    // inserted. Kept across updates.
+    time_to_live: DurationOverride,
+    // When the entry is scheduled to be removed by `invalidate_after`. Cleared
+    // when the value is replaced.
+    removal_deadline: AtomicInstant,
+    // The number of hits while in the probation region. Only updated while
+    // holding the lock on the deques.
+    hits: AtomicU8,
 }
 
 pub(crate) struct Weighted {
     is_admitted: AtomicBool,
+    is_prefetched: AtomicBool,
     last_accessed: AtomicInstant,
     last_modified: AtomicInstant,
+    created_at: AtomicInstant,
+    time_to_idle: DurationOverride,
+    time_to_live: DurationOverride,
+    removal_deadline: AtomicInstant,
+    hits: AtomicU8,
     policy_weight: AtomicU32,
 }
 
+/// A per-entry time-to-idle or time-to-live, which overrides the one of the
+/// cache.
+///
+/// Stored as nanoseconds plus one, so that zero (the default) means no override.
+#[derive(Default)]
+struct DurationOverride(AtomicU64);
+
+impl DurationOverride {
+    #[inline]
+    fn get(&self) -> Option<Duration> {
+        match self.0.load(Ordering::Acquire) {
+            0 => None,
+            n => Some(Duration::from_nanos(n - 1)),
+        }
+    }
+
+    #[inline]
+    fn set(&self, duration: Option<Duration>) {
+        let v = duration.map_or(0, |d| d.as_nanos().min(u64::MAX as u128 - 1) as u64 + 1);
+        self.0.store(v, Ordering::Release);
+    }
+}
+
 impl Weighted {
     pub(crate) fn new(policy_weight: u32) -> Self {
         Self {
             is_admitted: Default::default(),
+            is_prefetched: Default::default(),
             last_accessed: Default::default(),
             last_modified: Default::default(),
+            created_at: Default::default(),
+            time_to_idle: Default::default(),
+            time_to_live: Default::default(),
+            removal_deadline: Default::default(),
+            hits: Default::default(),
             policy_weight: AtomicU32::new(policy_weight),
         }
     }
@@ -58,6 +112,16 @@ impl EntryInfo {
         }
     }
 
+    /// Returns an identifier of this `EntryInfo`, which is shared by all
+    /// `ValueEntry`s created for the same key until the key is removed.
+    #[inline]
+    pub(crate) fn id(&self) -> usize {
+        match self {
+            Self::Plain(ei) => Arc::as_ptr(ei) as usize,
+            Self::Weighted(ei) => Arc::as_ptr(ei) as usize,
+        }
+    }
+
     #[inline]
     pub(crate) fn is_admitted(&self) -> bool {
         let v = match self {
@@ -76,6 +140,26 @@ impl EntryInfo {
         v.store(value, Ordering::Release);
     }
 
+    #[inline]
+    pub(crate) fn set_is_prefetched(&self, value: bool) {
+        let v = match self {
+            Self::Plain(ei) => &ei.is_prefetched,
+            Self::Weighted(ei) => &ei.is_prefetched,
+        };
+        v.store(value, Ordering::Release);
+    }
+
+    /// Clears the prefetched flag, and returns true if it was set.
+    #[inline]
+    pub(crate) fn take_is_prefetched(&self) -> bool {
+        let v = match self {
+            Self::Plain(ei) => &ei.is_prefetched,
+            Self::Weighted(ei) => &ei.is_prefetched,
+        };
+        // Load first to avoid writing the cache line on every hit.
+        v.load(Ordering::Acquire) && v.swap(false, Ordering::AcqRel)
+    }
+
     #[inline]
     pub(crate) fn reset_timestamps(&self) {
         match self {
@@ -90,6 +174,38 @@ impl EntryInfo {
         }
     }
 
+    /// Returns when the current life of the key started. Unlike `last_modified`,
+    /// this is not updated when the value is replaced.
+    #[inline]
+    pub(crate) fn created_at(&self) -> Option<Instant> {
+        let v = match self {
+            Self::Plain(ei) => &ei.created_at,
+            Self::Weighted(ei) => &ei.created_at,
+        };
+        v.instant()
+    }
+
+    #[inline]
+    pub(crate) fn set_created_at(&self, timestamp: Instant) {
+        let v = match self {
+            Self::Plain(ei) => &ei.created_at,
+            Self::Weighted(ei) => &ei.created_at,
+        };
+        v.set_instant(timestamp);
+    }
+
+    #[inline]
+    pub(crate) fn set_removal_deadline(&self, deadline: Option<Instant>) {
+        let v = match self {
+            Self::Plain(ei) => &ei.removal_deadline,
+            Self::Weighted(ei) => &ei.removal_deadline,
+        };
+        match deadline {
+            Some(ts) => v.set_instant(ts),
+            None => v.reset(),
+        }
+    }
+
     #[inline]
     pub(crate) fn policy_weight(&self) -> u32 {
         match self {
@@ -104,6 +220,44 @@ impl EntryInfo {
             Self::Weighted(ei) => ei.policy_weight.store(size, Ordering::Release),
         }
     }
+
+    #[inline]
+    pub(crate) fn set_time_to_idle(&self, time_to_idle: Option<Duration>) {
+        match self {
+            Self::Plain(ei) => ei.time_to_idle.set(time_to_idle),
+            Self::Weighted(ei) => ei.time_to_idle.set(time_to_idle),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_time_to_live(&self, time_to_live: Option<Duration>) {
+        match self {
+            Self::Plain(ei) => ei.time_to_live.set(time_to_live),
+            Self::Weighted(ei) => ei.time_to_live.set(time_to_live),
+        }
+    }
+
+    /// Increments the hit counter, saturating at `u8::MAX`, and returns the new
+    /// count.
+    #[inline]
+    pub(crate) fn increment_hits(&self) -> u8 {
+        let v = match self {
+            Self::Plain(ei) => &ei.hits,
+            Self::Weighted(ei) => &ei.hits,
+        };
+        let hits = v.load(Ordering::Acquire).saturating_add(1);
+        v.store(hits, Ordering::Release);
+        hits
+    }
+
+    #[inline]
+    pub(crate) fn reset_hits(&self) {
+        let v = match self {
+            Self::Plain(ei) => &ei.hits,
+            Self::Weighted(ei) => &ei.hits,
+        };
+        v.store(0, Ordering::Release);
+    }
 }
 
 impl AccessTime for EntryInfo {
@@ -142,4 +296,29 @@ impl AccessTime for EntryInfo {
         };
         v.set_instant(timestamp);
     }
+
+    #[inline]
+    fn time_to_idle(&self) -> Option<Duration> {
+        match self {
+            Self::Plain(ei) => ei.time_to_idle.get(),
+            Self::Weighted(ei) => ei.time_to_idle.get(),
+        }
+    }
+
+    #[inline]
+    fn time_to_live(&self) -> Option<Duration> {
+        match self {
+            Self::Plain(ei) => ei.time_to_live.get(),
+            Self::Weighted(ei) => ei.time_to_live.get(),
+        }
+    }
+
+    #[inline]
+    fn removal_deadline(&self) -> Option<Instant> {
+        let v = match self {
+            Self::Plain(ei) => &ei.removal_deadline,
+            Self::Weighted(ei) => &ei.removal_deadline,
+        };
+        v.instant()
+    }
 }
@@ -0,0 +1,105 @@
+use super::base_cache::{BaseCache, CacheEntry};
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+    time::Duration,
+};
+
+/// The default maximum duration of a `FrozenView`.
+pub(crate) const DEFAULT_MAX_FREEZE_DURATION: Duration = Duration::from_secs(5);
+
+/// A short-lived view of a cache whose entry count and entries correspond to the
+/// same point in time.
+///
+/// A `FrozenView` is created by the `freeze` method of `Cache`. While the view is
+/// alive, the cache does not apply its write buffer: `insert` and `invalidate`
+/// keep working, but the entries inserted after the view was created are not
+/// counted by [`entry_count`](#method.entry_count) nor visited by
+/// [`iter`](#method.iter), and nothing is evicted or expired from the cache.
+/// Dropping the view resumes the maintenance.
+///
+/// Since the write buffer has a bounded capacity, `insert` may have to wait for
+/// the view to be dropped if many entries are written while it is alive. To
+/// protect the cache from a leaked view, the maintenance resumes anyway after the
+/// maximum duration of the view has passed. Then the view is no longer
+/// consistent; [`is_expired`](#method.is_expired) tells if this has happened.
+///
+/// The view holds its own snapshot of the entries, so an entry invalidated or
+/// updated while the view is alive is still visited by `iter` with the value it
+/// had when the view was created.
+///
+/// # Limitations
+///
+/// - The values of the entries are kept alive until the view is dropped.
+/// - Expired entries that have not been removed by the maintenance yet are
+///   counted and visited.
+pub struct FrozenView<'a, K, V, S = RandomState>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    base: &'a BaseCache<K, V, S>,
+    id: u64,
+    entries: Vec<CacheEntry<K, V>>,
+    weighted_size: u64,
+}
+
+impl<'a, K, V, S> FrozenView<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(base: &'a BaseCache<K, V, S>, max_duration: Duration) -> Self {
+        let (id, entries) = base.freeze(max_duration);
+        let weighted_size = entries
+            .iter()
+            .map(|(_k, entry)| entry.policy_weight() as u64)
+            .sum();
+        Self {
+            base,
+            id,
+            entries,
+            weighted_size,
+        }
+    }
+
+    /// Returns the number of entries in the cache when the view was created.
+    pub fn entry_count(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    /// Returns the total weighted size of the entries in the cache when the view
+    /// was created.
+    pub fn weighted_size(&self) -> u64 {
+        self.weighted_size
+    }
+
+    /// Creates an iterator visiting the entries in the cache when the view was
+    /// created, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (Arc<K>, V)> + '_ {
+        self.entries
+            .iter()
+            .map(|(k, entry)| (Arc::clone(k), entry.value().clone()))
+    }
+
+    /// Returns true if the view has outlived its maximum duration, so that the
+    /// cache may have resumed its maintenance.
+    pub fn is_expired(&self) -> bool {
+        !self.base.is_frozen_by(self.id)
+    }
+}
+
+impl<'a, K, V, S> Drop for FrozenView<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.base.unfreeze(self.id);
+    }
+}
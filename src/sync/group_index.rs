@@ -0,0 +1,84 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+    sync::Arc,
+};
+
+/// Maps a group to the keys of its entries, and an entry to its group.
+///
+/// An entry is identified by the ID of its `EntryInfo`, so that a stale
+/// membership of a removed entry does not affect a new entry for the same key.
+pub(crate) struct GroupIndex<K> {
+    // group -> (key -> entry ID)
+    members: HashMap<String, HashMap<Arc<K>, usize>>,
+    // entry ID -> group
+    groups: HashMap<usize, String>,
+}
+
+impl<K> Default for GroupIndex<K> {
+    fn default() -> Self {
+        Self {
+            members: HashMap::default(),
+            groups: HashMap::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> GroupIndex<K> {
+    /// Adds the entry to the group, removing it from its current group if any.
+    pub(crate) fn insert(&mut self, group: String, key: Arc<K>, entry_id: usize) {
+        self.remove(&key, entry_id);
+        self.members
+            .entry(group.clone())
+            .or_default()
+            .insert(key, entry_id);
+        self.groups.insert(entry_id, group);
+    }
+
+    /// Removes the entry from its group if it belongs to one.
+    pub(crate) fn remove(&mut self, key: &Arc<K>, entry_id: usize) {
+        if let Some(group) = self.groups.remove(&entry_id) {
+            if let Entry::Occupied(mut members) = self.members.entry(group) {
+                if members.get().get(key) == Some(&entry_id) {
+                    members.get_mut().remove(key);
+                }
+                if members.get().is_empty() {
+                    members.remove();
+                }
+            }
+        }
+    }
+
+    /// Removes the group and returns the keys and entry IDs of its members.
+    pub(crate) fn take(&mut self, group: &str) -> Vec<(Arc<K>, usize)> {
+        let members = self.members.remove(group).unwrap_or_default();
+        for entry_id in members.values() {
+            self.groups.remove(entry_id);
+        }
+        members.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GroupIndex;
+    use std::sync::Arc;
+
+    #[test]
+    fn move_between_groups() {
+        let mut index = GroupIndex::default();
+        let (a, b) = (Arc::new("a"), Arc::new("b"));
+        index.insert("g1".to_string(), Arc::clone(&a), 1);
+        index.insert("g1".to_string(), Arc::clone(&b), 2);
+        index.insert("g2".to_string(), Arc::clone(&a), 1);
+
+        assert_eq!(index.take("g1"), vec![(Arc::clone(&b), 2)]);
+        assert!(index.take("g1").is_empty());
+
+        // A stale ID does not remove the current membership.
+        index.remove(&a, 3);
+        assert_eq!(index.take("g2"), vec![(a, 1)]);
+        assert!(index.members.is_empty());
+        assert!(index.groups.is_empty());
+    }
+}
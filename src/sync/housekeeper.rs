@@ -11,6 +11,7 @@ use parking_lot::Mutex;
 use scheduled_thread_pool::JobHandle;
 use std::{
     marker::PhantomData,
+    panic::{catch_unwind, AssertUnwindSafe},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Weak,
@@ -38,6 +39,54 @@ pub(crate) trait InnerSync {
     fn sync(&self, max_sync_repeats: usize) -> Option<SyncPace>;
 }
 
+/// Keeps the maintenance passes run by the housekeeper at least `interval`
+/// apart. Does nothing when `interval` is `None`.
+struct Debounce {
+    interval: Option<Duration>,
+    last_started_at: Mutex<Option<std::time::Instant>>,
+    #[cfg(test)]
+    num_passes: std::sync::atomic::AtomicUsize,
+}
+
+impl Debounce {
+    fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval: interval.filter(|i| *i > Duration::default()),
+            last_started_at: Mutex::new(None),
+            #[cfg(test)]
+            num_passes: Default::default(),
+        }
+    }
+
+    /// Returns the time to wait until a pass may start.
+    fn remaining(&self) -> Option<Duration> {
+        let interval = self.interval?;
+        let last = (*self.last_started_at.lock())?;
+        Self::remaining_since(interval, last)
+    }
+
+    /// Marks the start of a pass. Returns the time to wait instead if the last
+    /// pass has started less than `interval` ago.
+    fn try_start(&self) -> Result<(), Duration> {
+        if let Some(interval) = self.interval {
+            let mut last = self.last_started_at.lock();
+            if let Some(remaining) = last.and_then(|l| Self::remaining_since(interval, l)) {
+                return Err(remaining);
+            }
+            *last = Some(std::time::Instant::now());
+        }
+        #[cfg(test)]
+        self.num_passes.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn remaining_since(interval: Duration, last: std::time::Instant) -> Option<Duration> {
+        interval
+            .checked_sub(last.elapsed())
+            .filter(|d| *d > Duration::default())
+    }
+}
+
 pub(crate) struct Housekeeper<T> {
     inner: Arc<Mutex<UnsafeWeakPointer>>,
     thread_pool: Arc<ThreadPool>,
@@ -45,6 +94,7 @@ pub(crate) struct Housekeeper<T> {
     periodical_sync_job: Mutex<Option<JobHandle>>,
     periodical_sync_running: Arc<Mutex<()>>,
     on_demand_sync_scheduled: Arc<AtomicBool>,
+    debounce: Arc<Debounce>,
     _marker: PhantomData<T>,
 }
 
@@ -79,19 +129,21 @@ impl<T> Drop for Housekeeper<T> {
 
 // functions/methods used by Cache
 impl<T: InnerSync> Housekeeper<T> {
-    pub(crate) fn new(inner: Weak<T>) -> Self {
+    pub(crate) fn new(inner: Weak<T>, maintenance_interval: Option<Duration>) -> Self {
         use crate::common::thread_pool::PoolName;
 
         let thread_pool = ThreadPoolRegistry::acquire_pool(PoolName::Housekeeper);
         let inner_ptr = Arc::new(Mutex::new(UnsafeWeakPointer::from_weak_arc(inner)));
         let is_shutting_down = Arc::new(AtomicBool::new(false));
         let periodical_sync_running = Arc::new(Mutex::new(()));
+        let debounce = Arc::new(Debounce::new(maintenance_interval));
 
         let sync_job = Self::start_periodical_sync_job(
             &thread_pool,
             Arc::clone(&inner_ptr),
             Arc::clone(&is_shutting_down),
             Arc::clone(&periodical_sync_running),
+            Arc::clone(&debounce),
         );
 
         Self {
@@ -101,7 +153,8 @@ impl<T: InnerSync> Housekeeper<T> {
             periodical_sync_job: Mutex::new(Some(sync_job)),
             periodical_sync_running,
             on_demand_sync_scheduled: Arc::new(AtomicBool::new(false)),
-            _marker: PhantomData::default(),
+            debounce,
+            _marker: PhantomData,
         }
     }
 
@@ -110,6 +163,7 @@ impl<T: InnerSync> Housekeeper<T> {
         unsafe_weak_ptr: Arc<Mutex<UnsafeWeakPointer>>,
         is_shutting_down: Arc<AtomicBool>,
         periodical_sync_running: Arc<Mutex<()>>,
+        debounce: Arc<Debounce>,
     ) -> JobHandle {
         let mut sync_pace = SyncPace::Normal;
 
@@ -117,6 +171,10 @@ impl<T: InnerSync> Housekeeper<T> {
             move || {
                 if !is_shutting_down.load(Ordering::Acquire) {
                     let _lock = periodical_sync_running.lock();
+                    if let Err(remaining) = debounce.try_start() {
+                        // Try again when the minimum interval has passed.
+                        return Some(remaining);
+                    }
                     if let Some(new_pace) = Self::call_sync(&unsafe_weak_ptr) {
                         if sync_pace != new_pace {
                             sync_pace = new_pace
@@ -154,17 +212,43 @@ impl<T: InnerSync> Housekeeper<T> {
             Ok(_) => {
                 let unsafe_weak_ptr = Arc::clone(&self.inner);
                 let sync_scheduled = Arc::clone(&self.on_demand_sync_scheduled);
-                // Execute a task in a worker thread.
-                self.thread_pool.pool.execute(move || {
-                    Self::call_sync(&unsafe_weak_ptr);
+                let debounce = Arc::clone(&self.debounce);
+                let job = move || {
+                    // If another pass has started in the meantime, skip this one.
+                    // The next call to this method will schedule a new one.
+                    if debounce.try_start().is_ok() {
+                        Self::call_sync(&unsafe_weak_ptr);
+                    }
                     sync_scheduled.store(false, Ordering::Release);
-                });
+                };
+                // Execute a task in a worker thread, after the minimum interval
+                // since the last pass.
+                match self.debounce.remaining() {
+                    Some(delay) => self.thread_pool.pool.execute_after(delay, job),
+                    None => self.thread_pool.pool.execute(job),
+                };
                 true
             }
             Err(_) => false,
         }
     }
 
+    /// Runs the job on a worker thread of the housekeeper thread pool.
+    pub(crate) fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        self.thread_pool.pool.execute(job);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn maintenance_interval(&self) -> Option<Duration> {
+        self.debounce.interval
+    }
+
+    /// Returns the number of maintenance passes started by the housekeeper.
+    #[cfg(test)]
+    pub(crate) fn num_passes(&self) -> usize {
+        self.debounce.num_passes.load(Ordering::Acquire)
+    }
+
     #[cfg(test)]
     pub(crate) fn periodical_sync_job(&self) -> &Mutex<Option<JobHandle>> {
         &self.periodical_sync_job
@@ -178,8 +262,13 @@ impl<T: InnerSync> Housekeeper<T> {
         // Restore the Weak pointer to Inner<K, V, S>.
         let weak = unsafe { lock.as_weak_arc::<T>() };
         if let Some(inner) = weak.upgrade() {
-            // TODO: Protect this call with catch_unwind().
-            let sync_pace = inner.sync(MAX_SYNC_REPEATS);
+            // Do not let a panic in the pass, e.g. from the hasher of the keys,
+            // unwind past here. It would drop the restored Weak pointer, and stop
+            // the periodical job and the on-demand passes for good. The cache
+            // stays consistent after such a panic, so the next pass can go on.
+            let sync_pace = catch_unwind(AssertUnwindSafe(|| inner.sync(MAX_SYNC_REPEATS)))
+                .ok()
+                .flatten();
             // Avoid to drop the Arc<Inner<K, V, S>>.
             UnsafeWeakPointer::forget_arc(inner);
             sync_pace
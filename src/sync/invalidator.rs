@@ -16,6 +16,7 @@ use std::{
     collections::HashMap,
     hash::{BuildHasher, Hash},
     marker::PhantomData,
+    panic::{catch_unwind, AssertUnwindSafe},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Weak,
@@ -165,7 +166,7 @@ impl<K, V, S> Invalidator<K, V, S> {
         if self.is_empty() {
             false
         } else if let Some(ts) = entry.last_modified() {
-            Self::do_apply_predicates(self.predicates.read().values(), key, &entry.value, ts)
+            Self::do_apply_predicates(self.predicates.read().values(), key, entry.value(), ts)
         } else {
             false
         }
@@ -295,7 +296,7 @@ impl<K, V, S> ScanContext<K, V, S> {
             result: Mutex::new(None),
             is_running: AtomicBool::new(false),
             is_shutting_down: AtomicBool::new(false),
-            _marker: PhantomData::default(),
+            _marker: PhantomData,
         }
     }
 }
@@ -367,7 +368,6 @@ where
         // Restore the Weak pointer to Inner<K, V, S>.
         let weak = unsafe { cache_lock.as_weak_arc::<Inner<K, V, S>>() };
         if let Some(inner_cache) = weak.upgrade() {
-            // TODO: Protect this call with catch_unwind().
             *self.scan_context.result.lock() = Some(self.do_execute(&inner_cache));
 
             // Change this flag here (before downgrading the Arc to a Weak) to avoid a (soft)
@@ -396,13 +396,21 @@ where
         for candidate in &self.candidates {
             let key = &candidate.key;
             let ts = candidate.timestamp;
-            if Self::apply(&predicates, cache, key, ts) {
-                if let Some(entry) = Self::invalidate(cache, key, ts) {
-                    invalidated.push(KvEntry {
-                        key: Arc::clone(key),
-                        entry,
-                    })
+            // Do not let a panicking predicate (or hasher) unwind past here. It
+            // would drop the restored Weak pointer in `execute`, and lose the
+            // entries invalidated so far. The entry is left in the cache instead.
+            let removed = catch_unwind(AssertUnwindSafe(|| {
+                if Self::apply(&predicates, cache, key, ts) {
+                    Self::invalidate(cache, key, ts)
+                } else {
+                    None
                 }
+            }));
+            if let Ok(Some(entry)) = removed {
+                invalidated.push(KvEntry {
+                    key: Arc::clone(key),
+                    entry,
+                })
             }
             newest_timestamp = Some(ts);
         }
@@ -424,7 +432,7 @@ where
                     return Invalidator::<_, _, S>::do_apply_predicates(
                         predicates.iter(),
                         key,
-                        &entry.value,
+                        entry.value(),
                         lm,
                     );
                 }
@@ -0,0 +1,111 @@
+use super::{base_cache::BaseCache, ValueEntry};
+use crate::common::stats::OpKind;
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::{Arc, Weak},
+};
+
+/// A handle to read the value of a single key without looking up the hash table
+/// every time.
+///
+/// A `KeyHandle` is created by the `handle` method of `Cache`. It remembers the
+/// entry it has read last, together with the write generation of the cache at
+/// that time. [`get`](#method.get) reads the remembered entry directly as long as
+/// nothing has been inserted, invalidated or evicted since then, and falls back
+/// to a regular lookup otherwise. So a handle pays off for a hot key of a cache
+/// that is read much more often than it is written.
+///
+/// `get` returns the same values as `Cache::get` would: an expired or invalidated
+/// entry is never returned, and reads are recorded for the eviction policy and
+/// the statistics. The handle does not keep the entry from being evicted, and
+/// does not keep its value alive after it has been removed from the cache.
+pub struct KeyHandle<'a, K, V, S = RandomState> {
+    base: &'a BaseCache<K, V, S>,
+    key: Arc<K>,
+    hash: u64,
+    // The write generation of the cache when the entry was read.
+    cached: Option<(u64, Weak<ValueEntry<K, V>>)>,
+}
+
+// The handle only reads the remembered entry. See the unwind safety impls of
+// `BaseCache`.
+impl<'a, K, V, S> UnwindSafe for KeyHandle<'a, K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
+impl<'a, K, V, S> RefUnwindSafe for KeyHandle<'a, K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
+impl<'a, K, V, S> KeyHandle<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(base: &'a BaseCache<K, V, S>, key: Arc<K>, hash: u64) -> Self {
+        Self {
+            base,
+            key,
+            hash,
+            cached: None,
+        }
+    }
+
+    /// Returns the key of this handle.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key.
+    pub fn get(&mut self) -> Option<V> {
+        let started_at = self.base.start_latency_timer(OpKind::Get);
+        // Read the generation before the entry, so that a write racing with this
+        // read will always be detected by the next call.
+        let generation = self.base.write_generation();
+
+        let cached_entry = match &self.cached {
+            Some((gen, entry)) if *gen == generation => entry.upgrade(),
+            _ => None,
+        };
+
+        let v = match cached_entry {
+            Some(entry) => self.base.read_entry(&self.key, self.hash, entry),
+            None => match self.base.get_entry_with_hash(&self.key, self.hash) {
+                Some((_, entry)) => {
+                    let v = entry.value().clone();
+                    self.cached = Some((generation, Arc::downgrade(&entry)));
+                    Some(v)
+                }
+                None => {
+                    self.cached = None;
+                    None
+                }
+            },
+        };
+        self.base.record_latency(OpKind::Get, started_at);
+        v
+    }
+}
+
+impl<'a, K, V, S> Clone for KeyHandle<'a, K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base,
+            key: Arc::clone(&self.key),
+            hash: self.hash,
+            cached: self.cached.clone(),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+type LockMap<K, S> = moka_cht::SegmentedHashMap<Arc<K>, Arc<Mutex<()>>, S>;
+
+/// A lock for a single key, obtained from a `KeyLockMap`.
+///
+/// The mutex is shared by all `KeyLock`s of the same key that are alive at the
+/// same time. The last one to be dropped removes the mutex from the map.
+pub(crate) struct KeyLock<'a, K, S>
+// NOTE: We usually do not attach trait bounds to here at the struct definition, but
+// the Drop trait requires these bounds here.
+where
+    Arc<K>: Eq + Hash,
+    S: BuildHasher,
+{
+    map: &'a LockMap<K, S>,
+    key: Arc<K>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl<'a, K, S> KeyLock<'a, K, S>
+where
+    Arc<K>: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Locks the key, calling `on_contention` if the lock is held by another
+    /// thread.
+    pub(crate) fn lock(&self, on_contention: impl Fn()) -> MutexGuard<'_, ()> {
+        self.lock.try_lock().unwrap_or_else(|| {
+            on_contention();
+            self.lock.lock()
+        })
+    }
+
+    /// Locks this and the other key. The locks are always taken in the same
+    /// order, so that two threads locking the same pair do not deadlock. Takes
+    /// the lock only once if the keys are the same.
+    pub(crate) fn lock_with<'b>(
+        &'b self,
+        other: &'b Self,
+        on_contention: impl Fn(),
+    ) -> (MutexGuard<'b, ()>, Option<MutexGuard<'b, ()>>) {
+        let (this, that) = (Arc::as_ptr(&self.lock), Arc::as_ptr(&other.lock));
+        if this == that {
+            (self.lock(on_contention), None)
+        } else if this < that {
+            let first = self.lock(&on_contention);
+            (first, Some(other.lock(on_contention)))
+        } else {
+            let first = other.lock(&on_contention);
+            (first, Some(self.lock(on_contention)))
+        }
+    }
+}
+
+impl<'a, K, S> Drop for KeyLock<'a, K, S>
+where
+    Arc<K>: Eq + Hash,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        // One reference is held by the map and the other by us.
+        if Arc::strong_count(&self.lock) <= 2 {
+            self.map.remove_if(&self.key, |_, v| {
+                Arc::ptr_eq(v, &self.lock) && Arc::strong_count(v) <= 2
+            });
+        }
+    }
+}
+
+/// Hands out a lock per key. Only the keys being locked occupy the map.
+pub(crate) struct KeyLockMap<K, S> {
+    locks: LockMap<K, S>,
+}
+
+impl<K, S> KeyLockMap<K, S>
+where
+    Arc<K>: Eq + Hash,
+    S: BuildHasher,
+{
+    pub(crate) fn with_hasher(hasher: S) -> Self {
+        Self {
+            locks: moka_cht::SegmentedHashMap::with_num_segments_and_hasher(16, hasher),
+        }
+    }
+
+    pub(crate) fn key_lock(&self, key: &Arc<K>) -> KeyLock<'_, K, S> {
+        let new_lock = Arc::new(Mutex::new(()));
+        let lock = match self.locks.insert_with_or_modify(
+            Arc::clone(key),
+            || Arc::clone(&new_lock),
+            |_, existing| Arc::clone(existing),
+        ) {
+            Some(existing) => existing,
+            None => new_lock,
+        };
+        KeyLock {
+            map: &self.locks,
+            key: Arc::clone(key),
+            lock,
+        }
+    }
+}
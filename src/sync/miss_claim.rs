@@ -0,0 +1,218 @@
+use parking_lot::{Condvar, Mutex};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::Arc,
+};
+
+/// The state of a claimed miss, resolved by the owner of the claim.
+pub(crate) enum ClaimState<V> {
+    Pending,
+    Completed(V),
+    Abandoned,
+}
+
+/// The claims of the missed keys. `C` is the type of a claim, which the sync and
+/// async caches wait on in their own ways.
+pub(crate) struct MissClaims<K, C, S> {
+    claims: moka_cht::SegmentedHashMap<Arc<K>, Arc<C>, S>,
+}
+
+impl<K, C, S> MissClaims<K, C, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub(crate) fn with_hasher(hasher: S) -> Self {
+        Self {
+            claims: moka_cht::SegmentedHashMap::with_num_segments_and_hasher(16, hasher),
+        }
+    }
+
+    /// Inserts the claim of the key unless the key has been claimed. Returns the
+    /// existing claim, or `None` if the given claim has been inserted.
+    pub(crate) fn try_insert(&self, key: &Arc<K>, claim: &Arc<C>) -> Option<Arc<C>> {
+        let claim = Arc::clone(claim);
+        self.claims
+            .insert_with_or_modify(Arc::clone(key), || claim, |_, c| Arc::clone(c))
+    }
+
+    /// Removes the claim of the key if it is the given one.
+    pub(crate) fn remove(&self, key: &Arc<K>, claim: &Arc<C>) {
+        self.claims.remove_if(key, |_, c| Arc::ptr_eq(c, claim));
+    }
+}
+
+/// A claimed miss of the sync caches, on which the waiters block.
+pub(crate) struct Claim<V> {
+    state: Mutex<ClaimState<V>>,
+    resolved: Condvar,
+}
+
+impl<V> Default for Claim<V> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(ClaimState::Pending),
+            resolved: Condvar::new(),
+        }
+    }
+}
+
+/// The result of [`Cache::claim_miss`][claim-miss].
+///
+/// [claim-miss]: ./struct.Cache.html#method.claim_miss
+pub enum MissClaim<K, V, S = RandomState>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// The key is in the cache. Holds a clone of the value.
+    Hit(V),
+    /// The key was absent and not claimed. The caller owns the claim, and is
+    /// expected to load the value and give it to the cache with the token.
+    Owner(MissToken<K, V, S>),
+    /// Another caller owns the claim of the key. The caller can wait for it to
+    /// be resolved.
+    Pending(MissWaiter<V>),
+}
+
+/// The token of a claimed miss, returned to the owner of the claim by
+/// [`Cache::claim_miss`][claim-miss].
+///
+/// Give it back to [`Cache::complete`][complete] with the loaded value, or to
+/// [`Cache::abandon`][abandon]. Dropping the token without doing so, e.g. when
+/// the owner panics, abandons the claim, so that the waiters do not wait
+/// forever.
+///
+/// [claim-miss]: ./struct.Cache.html#method.claim_miss
+/// [complete]: ./struct.Cache.html#method.complete
+/// [abandon]: ./struct.Cache.html#method.abandon
+pub struct MissToken<K, V, S = RandomState>
+// NOTE: We usually do not attach trait bounds to here at the struct definition, but
+// the Drop trait requires these bounds here.
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    key: Arc<K>,
+    hash: u64,
+    claim: Arc<Claim<V>>,
+    claims: Arc<MissClaims<K, Claim<V>, S>>,
+}
+
+impl<K, V, S> MissToken<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub(crate) fn new(
+        key: Arc<K>,
+        hash: u64,
+        claim: Arc<Claim<V>>,
+        claims: Arc<MissClaims<K, Claim<V>, S>>,
+    ) -> Self {
+        Self {
+            key,
+            hash,
+            claim,
+            claims,
+        }
+    }
+
+    /// Returns the claimed key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub(crate) fn key_and_hash(&self) -> (&Arc<K>, u64) {
+        (&self.key, self.hash)
+    }
+
+    pub(crate) fn is_claimed_from(&self, claims: &Arc<MissClaims<K, Claim<V>, S>>) -> bool {
+        Arc::ptr_eq(&self.claims, claims)
+    }
+
+    /// Resolves the claim unless it has been resolved, wakes up the waiters, and
+    /// removes the claim so that the key can be claimed again.
+    pub(crate) fn resolve(&self, resolution: ClaimState<V>) {
+        {
+            let mut state = self.claim.state.lock();
+            if matches!(*state, ClaimState::Pending) {
+                *state = resolution;
+            }
+        }
+        self.claim.resolved.notify_all();
+        self.claims.remove(&self.key, &self.claim);
+    }
+}
+
+impl<K, V, S> Drop for MissToken<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        self.resolve(ClaimState::Abandoned);
+    }
+}
+
+// A claim is resolved by a single assignment under its lock, and a token dropped
+// by a panicking owner abandons its claim, so the waiters never see a broken state.
+impl<K, V, S> UnwindSafe for MissToken<K, V, S>
+where
+    K: Hash + Eq + RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: BuildHasher + RefUnwindSafe,
+{
+}
+
+impl<K, V, S> RefUnwindSafe for MissToken<K, V, S>
+where
+    K: Hash + Eq + RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: BuildHasher + RefUnwindSafe,
+{
+}
+
+/// A handle to wait for a miss claimed by another caller of
+/// [`Cache::claim_miss`][claim-miss].
+///
+/// [claim-miss]: ./struct.Cache.html#method.claim_miss
+pub struct MissWaiter<V> {
+    claim: Arc<Claim<V>>,
+}
+
+impl<V: Clone> MissWaiter<V> {
+    pub(crate) fn new(claim: Arc<Claim<V>>) -> Self {
+        Self { claim }
+    }
+
+    /// Blocks the current thread until the owner of the claim completes or
+    /// abandons it.
+    pub fn wait(self) -> MissResolution<V> {
+        let mut state = self.claim.state.lock();
+        loop {
+            match &*state {
+                ClaimState::Pending => self.claim.resolved.wait(&mut state),
+                ClaimState::Completed(v) => return MissResolution::Completed(v.clone()),
+                ClaimState::Abandoned => return MissResolution::Retry,
+            }
+        }
+    }
+}
+
+impl<V> UnwindSafe for MissWaiter<V> where V: RefUnwindSafe {}
+
+impl<V> RefUnwindSafe for MissWaiter<V> where V: RefUnwindSafe {}
+
+/// How a claimed miss was resolved, returned by the `wait` method of the waiters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MissResolution<V> {
+    /// The owner of the claim has completed it. Holds a clone of the value
+    /// inserted to the cache.
+    Completed(V),
+    /// The owner of the claim has abandoned it, or dropped its token. Call
+    /// `claim_miss` again; one of the retrying callers will own the next claim.
+    Retry,
+}
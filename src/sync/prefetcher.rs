@@ -0,0 +1,29 @@
+use crate::common::thread_pool::{PoolName, ThreadPool, ThreadPoolRegistry};
+
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+
+/// Runs the loads started by `Cache::prefetch` in the background. The thread
+/// pool is acquired on the first prefetch, and released when the last clone of
+/// the cache (including those held by the running loads) is dropped.
+#[derive(Default)]
+pub(crate) struct Prefetcher {
+    thread_pool: OnceCell<Arc<ThreadPool>>,
+}
+
+impl Prefetcher {
+    pub(crate) fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        self.thread_pool
+            .get_or_init(|| ThreadPoolRegistry::acquire_pool(PoolName::Loader))
+            .pool
+            .execute(job);
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        if let Some(pool) = self.thread_pool.get() {
+            ThreadPoolRegistry::release_pool(pool);
+        }
+    }
+}
@@ -1,12 +1,22 @@
-use super::{cache::Cache, CacheBuilder, ConcurrentCacheExt, Weigher};
-use crate::PredicateError;
+use super::{
+    cache::Cache, cache_config::CacheConfig, BorrowedKey, CacheBuilder, ConcurrentCacheExt,
+    KeyHandle, KeyNormalizer, LoadRole,
+};
+use crate::{
+    common, CacheStats, CasError, Entry, FallbackError, LoadError, OccupiedError, PredicateError,
+};
 
+use parking_lot::{Condvar, Mutex};
 use std::{
     borrow::Borrow,
     collections::hash_map::RandomState,
     error::Error,
-    hash::{BuildHasher, Hash, Hasher},
-    sync::Arc,
+    hash::{BuildHasher, Hash},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
     time::Duration,
 };
 
@@ -42,6 +52,23 @@ where
 {
 }
 
+// See the unwind safety impls of `sync::Cache`.
+impl<K, V, S> UnwindSafe for SegmentedCache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
+impl<K, V, S> RefUnwindSafe for SegmentedCache<K, V, S>
+where
+    K: RefUnwindSafe,
+    V: RefUnwindSafe,
+    S: RefUnwindSafe,
+{
+}
+
 impl<K, V, S> Clone for SegmentedCache<K, V, S> {
     /// Makes a clone of this shared cache.
     ///
@@ -71,17 +98,11 @@ where
     ///
     /// Panics if `num_segments` is 0.
     pub fn new(max_capacity: u64, num_segments: usize) -> Self {
-        let build_hasher = RandomState::default();
-        Self::with_everything(
-            Some(max_capacity),
-            None,
-            num_segments,
-            build_hasher,
-            None,
-            None,
-            None,
-            false,
-        )
+        let config = CacheConfig {
+            max_capacity: Some(max_capacity),
+            ..CacheConfig::new(RandomState::default())
+        };
+        Self::with_config(config, num_segments, None)
     }
 
     /// Returns a [`CacheBuilder`][builder-struct], which can builds a
@@ -102,28 +123,13 @@ where
     /// # Panics
     ///
     /// Panics if `num_segments` is 0.
-    #[allow(clippy::too_many_arguments)]
-    pub(crate) fn with_everything(
-        max_capacity: Option<u64>,
-        initial_capacity: Option<usize>,
+    pub(crate) fn with_config(
+        config: CacheConfig<K, V, S>,
         num_segments: usize,
-        build_hasher: S,
-        weigher: Option<Weigher<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        segment_capacities: Option<Vec<u64>>,
     ) -> Self {
         Self {
-            inner: Arc::new(Inner::new(
-                max_capacity,
-                initial_capacity,
-                num_segments,
-                build_hasher,
-                weigher,
-                time_to_live,
-                time_to_idle,
-                invalidator_enabled,
-            )),
+            inner: Arc::new(Inner::new(config, num_segments, segment_capacities)),
         }
     }
 
@@ -140,10 +146,59 @@ where
     pub fn get<Q>(&self, key: &Q) -> Option<V>
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        let hash = self.inner.hash(key);
-        self.inner.select(hash).get_with_hash(key, hash)
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            let (hash, segment) = self.inner.select::<K>(&key);
+            return segment.get_with_hash::<K>(&key, hash);
+        }
+        let (hash, segment) = self.inner.select(key);
+        segment.get_with_hash(key, hash)
+    }
+
+    /// Runs the closure `f` against the value corresponding to the key without
+    /// cloning the value, and returns its result. See
+    /// [`Cache::read_with`](./struct.Cache.html#method.read_with) for details.
+    pub fn read_with<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            let (hash, segment) = self.inner.select::<K>(&key);
+            return segment.read_with_hash::<K, R>(&key, hash, f);
+        }
+        let (hash, segment) = self.inner.select(key);
+        segment.read_with_hash(key, hash, f)
+    }
+
+    /// Returns a copy of the value corresponding to the key. This is the same as
+    /// [`get`](#method.get) for a `Copy` value type. See
+    /// [`Cache::get_copied`](./struct.Cache.html#method.get_copied) for details.
+    pub fn get_copied<Q>(&self, key: &Q) -> Option<V>
+    where
+        V: Copy,
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, together with the
+    /// version of the entry. See
+    /// [`Cache::get_versioned`](./struct.Cache.html#method.get_versioned) for
+    /// details.
+    pub fn get_versioned<Q>(&self, key: &Q) -> Option<(V, u64)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            let (hash, segment) = self.inner.select::<K>(&key);
+            return segment.get_versioned_with_hash::<K>(&key, hash);
+        }
+        let (hash, segment) = self.inner.select(key);
+        segment.get_versioned_with_hash(key, hash)
     }
 
     /// Ensures the value of the key exists by inserting the result of the init
@@ -154,40 +209,296 @@ where
     /// the calls evaluates its closure, and other calls wait for that closure to
     /// complete.
     pub fn get_or_insert_with(&self, key: K, init: impl FnOnce() -> V) -> V {
-        let hash = self.inner.hash(&key);
-        let key = Arc::new(key);
-        self.inner
-            .select(hash)
-            .get_or_insert_with_hash_and_fun(key, hash, init)
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.get_or_insert_with_hash_and_fun(key, hash, init)
+    }
+
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but the `init`
+    /// closure receives a reference to the key.
+    ///
+    /// See [`Cache::get_or_insert_with_key`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.get_or_insert_with_key
+    pub fn get_or_insert_with_key(&self, key: K, init: impl FnOnce(&K) -> V) -> V {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        let init_key = Arc::clone(&key);
+        segment.get_or_insert_with_hash_and_fun(key, hash, move || init(&init_key))
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, loading it with
+    /// the loader registered to the builder if the key is not in the cache.
+    ///
+    /// See [`Cache::get_loading`][cache-method] for the details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache was built without a loader.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.get_loading
+    pub fn get_loading(&self, key: K) -> V {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.get_loading_with_hash(key, hash)
+    }
+
+    /// Starts loading the value of the key with the loader registered to the
+    /// builder in the background, if the key is neither in the cache nor being
+    /// loaded.
+    ///
+    /// See [`Cache::prefetch`][cache-method] for the details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cache was built without a loader.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.prefetch
+    pub fn prefetch(&self, key: K) {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.prefetch_with_hash(key, hash)
+    }
+
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but also
+    /// returns whether this call has run its `init` closure.
+    ///
+    /// See [`Cache::get_or_insert_with_role`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.get_or_insert_with_role
+    pub fn get_or_insert_with_role(&self, key: K, init: impl FnOnce() -> V) -> (V, LoadRole) {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.get_or_insert_with_role_hash_and_fun(key, hash, init)
+    }
+
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but returns
+    /// the value in an `Entry`, which tells whether the value was loaded by this
+    /// call.
+    ///
+    /// See [`Cache::get_or_insert_with_info`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.get_or_insert_with_info
+    pub fn get_or_insert_with_info(&self, key: K, init: impl FnOnce() -> V) -> Entry<V> {
+        let (v, role) = self.get_or_insert_with_role(key, init);
+        Entry::new(v, role == LoadRole::Leader)
     }
 
     /// Try to ensure the value of the key exists by inserting an `Ok` result of the
-    /// init closure if not exist, and returns a _clone_ of the value or the `Err`
-    /// returned by the closure.
+    /// init closure if not exist, and returns a _clone_ of the value or a
+    /// `LoadError::Loader` holding the `Err` returned by the closure.
     ///
     /// This method prevents to evaluate the init closure multiple times on the same
     /// key even if the method is concurrently called by many threads; only one of
     /// the calls evaluates its closure (as long as these closures return the same
     /// error type), and other calls wait for that closure to complete.
-    pub fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    pub fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, LoadError<E>>
     where
         F: FnOnce() -> Result<V, E>,
         E: Error + Send + Sync + 'static,
     {
-        let hash = self.inner.hash(&key);
-        let key = Arc::new(key);
-        self.inner
-            .select(hash)
-            .get_or_try_insert_with_hash_and_fun(key, hash, init)
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.get_or_try_insert_with_hash_and_fun(key, hash, init)
+    }
+
+    /// Same as [`get_or_try_insert_with`](#method.get_or_try_insert_with), but
+    /// the `init` closure receives a reference to the key.
+    pub fn get_or_try_insert_with_key<F, E>(&self, key: K, init: F) -> Result<V, LoadError<E>>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+        E: Error + Send + Sync + 'static,
+    {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        let init_key = Arc::clone(&key);
+        segment.get_or_try_insert_with_hash_and_fun(key, hash, move || init(&init_key))
+    }
+
+    /// Same as [`get_or_try_insert_with`](#method.get_or_try_insert_with), but
+    /// calls the `fallback` closure when the `primary` closure returns an `Err`.
+    ///
+    /// See [`Cache::get_or_try_insert_with_fallback`][cache-method] for the
+    /// details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.get_or_try_insert_with_fallback
+    pub fn get_or_try_insert_with_fallback<F1, F2, E1, E2>(
+        &self,
+        key: K,
+        primary: F1,
+        fallback: F2,
+    ) -> Result<V, LoadError<FallbackError<E1, E2>>>
+    where
+        F1: FnOnce() -> Result<V, E1>,
+        F2: FnOnce() -> Result<V, E2>,
+        E1: Error + Send + Sync + 'static,
+        E2: Error + Send + Sync + 'static,
+    {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        let init = || match primary() {
+            Ok(v) => Ok(v),
+            Err(primary) => fallback().map_err(|fallback| FallbackError { primary, fallback }),
+        };
+        segment.get_or_try_insert_with_hash_and_fun(key, hash, init)
+    }
+
+    /// Same as [`get_or_insert_with`](#method.get_or_insert_with), but reserves
+    /// the `estimated_weight` of the value before calling the `init` closure, or
+    /// returns a `LoadError::Shed` without calling it if the reservation would
+    /// take the segment over its share of the reservation high-water mark.
+    ///
+    /// The reservation high-water mark is divided evenly across the segments. See
+    /// [`Cache::get_with_reservation`][cache-get-with-reservation] for the
+    /// details.
+    ///
+    /// [cache-get-with-reservation]: ./struct.Cache.html#method.get_with_reservation
+    pub fn get_with_reservation(
+        &self,
+        key: K,
+        estimated_weight: u32,
+        init: impl FnOnce() -> V,
+    ) -> Result<V, LoadError> {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.get_with_reservation_hash_and_fun(key, hash, estimated_weight, init)
+    }
+
+    /// Returns the total weight reserved by the calls of
+    /// [`get_with_reservation`](#method.get_with_reservation) loading their
+    /// values.
+    pub fn reserved_weight(&self) -> u64 {
+        self.inner.segments.iter().map(Cache::reserved_weight).sum()
+    }
+
+    /// Returns the number of consecutive failures of the `init` closure of
+    /// [`get_or_try_insert_with`](#method.get_or_try_insert_with) for the key.
+    ///
+    /// The count is incremented every time an `init` closure for the key returns
+    /// an `Err`, and reset to zero when one returns an `Ok`. Only the call that
+    /// actually evaluated its closure is counted; concurrent calls that received
+    /// the same error do not increment the count. You can use it to build a
+    /// circuit breaker, e.g. to stop calling a failing backend for a key after
+    /// some consecutive failures.
+    ///
+    /// Counts are only tracked for keys that have failed at least once, and a key
+    /// is forgotten when its `init` closure succeeds, or when it is invalidated by
+    /// `invalidate`, `invalidate_all` and the like. This method returns `0` for
+    /// other keys. Up to 4096 keys are tracked per segment. Beyond that, the keys
+    /// that have not failed for the longest time are forgotten first.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn load_failure_count<Q>(&self, key: &Q) -> u32
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            let (_, segment) = self.inner.select::<K>(&key);
+            return segment.load_failure_count::<K>(&key);
+        }
+        let (_, segment) = self.inner.select(key);
+        segment.load_failure_count(key)
     }
 
     /// Inserts a key-value pair into the cache.
     ///
     /// If the cache has this key present, the value is updated.
     pub fn insert(&self, key: K, value: V) {
-        let hash = self.inner.hash(&key);
-        let key = Arc::new(key);
-        self.inner.select(hash).insert_with_hash(key, hash, value);
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.insert_with_hash(key, hash, value);
+    }
+
+    /// Inserts a key-value pair into the cache with its own time-to-idle, which
+    /// overrides the one of the cache. See
+    /// [`Cache::insert_with_tti`](./struct.Cache.html#method.insert_with_tti) for
+    /// details.
+    pub fn insert_with_tti(&self, key: K, value: V, time_to_idle: Duration) {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.insert_with_hash_and_tti(key, hash, value, time_to_idle);
+    }
+
+    /// Inserts a key-value pair into the cache, and adds the entry to the group
+    /// `group`. See
+    /// [`Cache::insert_in_group`](./struct.Cache.html#method.insert_in_group) for
+    /// details.
+    pub fn insert_in_group(&self, key: K, value: V, group: impl Into<String>) {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.insert_in_group_with_hash(key, hash, value, group.into());
+    }
+
+    /// Inserts a key-value pair into the cache only if the key is not present.
+    ///
+    /// If the cache already has an unexpired value for the key, the cache is left
+    /// untouched and an `OccupiedError` holding the existing value and the given
+    /// value is returned. See
+    /// [`Cache::try_insert`](./struct.Cache.html#method.try_insert) for details.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), OccupiedError<V>> {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.try_insert_with_hash(key, hash, value)
+    }
+
+    /// Inserts a key-value pair into the cache only if the entry of the key still
+    /// has the expected version, and returns the version of the new entry. See
+    /// [`Cache::insert_if_version`](./struct.Cache.html#method.insert_if_version)
+    /// for details.
+    pub fn insert_if_version(
+        &self,
+        key: K,
+        value: V,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CasError<V>> {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.insert_if_version_with_hash(key, hash, value, expected_version)
+    }
+
+    /// Inserts the value returned by the closure for the key, and returns a
+    /// _clone_ of the value.
+    ///
+    /// The closure receives the current value of the key, or `None` if the key is
+    /// not present. The calls to `insert_with` on the same key are serialized, but
+    /// `insert` and `invalidate` do not wait for them. See
+    /// [`Cache::insert_with`](./struct.Cache.html#method.insert_with) for details.
+    pub fn insert_with(&self, key: K, f: impl FnOnce(Option<&V>) -> V) -> V {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.insert_with_hash_and_fun(key, hash, f)
+    }
+
+    /// Marks the entry for the key as used without reading its value. Returns
+    /// `true` if the cache had a live entry for the key.
+    ///
+    /// The time-to-idle of the entry always restarts, and so does its
+    /// time-to-live when `reset_ttl` is `true`. See
+    /// [`Cache::touch`](./struct.Cache.html#method.touch) for details.
+    pub fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            let (hash, segment) = self.inner.select::<K>(&key);
+            return segment.touch_with_hash::<K>(&key, hash, reset_ttl);
+        }
+        let (hash, segment) = self.inner.select(key);
+        segment.touch_with_hash(key, hash, reset_ttl)
+    }
+
+    /// Creates a [`KeyHandle`](./struct.KeyHandle.html) to read the value of the
+    /// key repeatedly without looking up the hash table every time.
+    ///
+    /// The handle is bound to the segment of the key, so it is only affected by
+    /// the writes to that segment.
+    pub fn handle(&self, key: K) -> KeyHandle<'_, K, V, S> {
+        let key = Arc::new(self.inner.normalize_key(key));
+        let (hash, segment) = self.inner.select(&key);
+        segment.handle_with_hash(key, hash)
     }
 
     /// Discards any cached value for the key.
@@ -197,10 +508,69 @@ where
     pub fn invalidate<Q>(&self, key: &Q)
     where
         Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            self.inner.invalidate::<K>(&key, true);
+        } else {
+            self.inner.invalidate(key, true);
+        }
+    }
+
+    /// Discards any cached value for the key, and returns `true` if the key had a
+    /// value that had neither expired nor been invalidated.
+    ///
+    /// See [`Cache::invalidate_present`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.invalidate_present
+    pub fn invalidate_present<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            self.inner.invalidate::<K>(&key, true)
+        } else {
+            self.inner.invalidate(key, true)
+        }
+    }
+
+    /// Schedules the removal of the cached value for the key after the `delay`.
+    ///
+    /// See [`Cache::invalidate_after`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.invalidate_after
+    pub fn invalidate_after<Q>(&self, key: &Q, delay: Duration) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            self.inner
+                .select::<K>(&key)
+                .1
+                .invalidate_after::<K>(&key, delay)
+        } else {
+            self.inner.select(key).1.invalidate_after(key, delay)
+        }
+    }
+
+    /// Discards any cached value for the key, without publishing the invalidation
+    /// to the coherence bus.
+    ///
+    /// See [`Cache::apply_remote_invalidation`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.apply_remote_invalidation
+    pub fn apply_remote_invalidation<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
     {
-        let hash = self.inner.hash(key);
-        self.inner.select(hash).invalidate(key);
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            self.inner.invalidate::<K>(&key, false);
+        } else {
+            self.inner.invalidate(key, false);
+        }
     }
 
     /// Discards all cached values.
@@ -213,12 +583,178 @@ where
     /// Like the `invalidate` method, this method does not clear the historic
     /// popularity estimator of keys so that it retains the client activities of
     /// trying to retrieve an item.
+    ///
+    /// The eviction listener is notified of the discarded entries. This is the
+    /// same as [`clear_with(true)`](#method.clear_with).
     pub fn invalidate_all(&self) {
+        self.clear_with(true);
+    }
+
+    /// Discards all cached values without notifying the eviction listener. This
+    /// is the same as [`clear_with(false)`](#method.clear_with).
+    pub fn clear(&self) {
+        self.clear_with(false);
+    }
+
+    /// Discards all cached values, and notifies the eviction listener of them
+    /// only when `notify` is `true`.
+    ///
+    /// See [`Cache::clear_with`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.clear_with
+    pub fn clear_with(&self, notify: bool) {
+        for segment in self.inner.segments.iter() {
+            segment.clear_with(notify);
+        }
+    }
+
+    /// Discards all cached values segment by segment, and returns an iterator
+    /// that clears the next segment and reports it on every call to `next`.
+    ///
+    /// Unlike [`invalidate_all`](#method.invalidate_all), which returns
+    /// immediately and leaves the removal to the background threads, each step
+    /// removes the entries of its segment before it returns, so the progress of
+    /// clearing a large cache can be reported as it goes. A cleared segment
+    /// never returns the values discarded by the step, even to the concurrent
+    /// reads, while the remaining segments still return theirs.
+    ///
+    /// Dropping the iterator before it is exhausted cancels the remaining
+    /// segments, which are left as they are.
+    ///
+    /// The eviction listener is notified of the discarded entries, as
+    /// `invalidate_all` does.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::SegmentedCache;
+    ///
+    /// let cache = SegmentedCache::new(100, 4);
+    /// for i in 0..20 {
+    ///     cache.insert(i, i);
+    /// }
+    ///
+    /// let mut removed = 0;
+    /// for cleared in cache.invalidate_all_with_progress() {
+    ///     println!("Segment {} cleared.", cleared.index);
+    ///     removed += cleared.entries_removed;
+    /// }
+    /// assert_eq!(removed, 20);
+    /// ```
+    pub fn invalidate_all_with_progress(&self) -> impl Iterator<Item = SegmentCleared> + '_ {
+        self.inner
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                // Apply the pending writes so that they are counted.
+                segment.prepare();
+                let before = segment.estimated_entry_count();
+                segment.invalidate_all();
+                segment.prepare();
+                let after = segment.estimated_entry_count();
+                SegmentCleared {
+                    index,
+                    entries_removed: before.saturating_sub(after),
+                }
+            })
+    }
+
+    /// Discards all cached values except the ones for which `keep` returns `true`.
+    ///
+    /// See [`Cache::invalidate_all_except`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.invalidate_all_except
+    pub fn invalidate_all_except<F>(&self, keep: F)
+    where
+        F: Fn(&K, &V) -> bool,
+    {
         for segment in self.inner.segments.iter() {
-            segment.invalidate_all();
+            segment.invalidate_all_except(&keep);
         }
     }
 
+    /// Discards all entries in the group `group`.
+    ///
+    /// A group may have entries in any segment, so this method visits all of
+    /// them. See [`Cache::invalidate_group`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.invalidate_group
+    pub fn invalidate_group(&self, group: &str) {
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_group(group);
+        }
+    }
+
+    /// Evicts up to `n` entries right away, and returns the number of evicted
+    /// entries.
+    ///
+    /// The entries are evicted evenly from the segments as far as possible, and
+    /// coldest first within each segment. See [`Cache::evict_now`][cache-method]
+    /// for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.evict_now
+    pub fn evict_now(&self, n: usize) -> usize {
+        let segments = &self.inner.segments;
+        let mut evicted = 0;
+        // Ask every segment for its share first, and then take what is still
+        // missing from the segments that have more entries.
+        for (i, segment) in segments.iter().enumerate() {
+            let share = common::div_ceil(n - evicted, segments.len() - i);
+            evicted += segment.evict_now(share);
+        }
+        for segment in segments.iter() {
+            if evicted >= n {
+                break;
+            }
+            evicted += segment.evict_now(n - evicted);
+        }
+        evicted
+    }
+
+    /// Returns the number of accesses to the key estimated by the frequency sketch
+    /// of its segment, from `0` to `15`.
+    ///
+    /// See [`Cache::estimated_frequency`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.estimated_frequency
+    pub fn estimated_frequency<Q>(&self, key: &Q) -> u8
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let (hash, segment) = match self.inner.normalize_borrowed_key(key) {
+            Some(key) => self.inner.select::<K>(&key),
+            None => self.inner.select(key),
+        };
+        segment.estimated_frequency_with_hash(hash)
+    }
+
+    /// Returns the popularity of the given keys as estimated by the cache, most
+    /// popular first.
+    ///
+    /// Each segment keeps its own frequency sketch, and a key is looked up in the
+    /// sketch of its segment. See [`Cache::top_frequencies`][cache-method] for the
+    /// details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.top_frequencies
+    pub fn top_frequencies<'a>(&self, sample_keys: impl IntoIterator<Item = &'a K>) -> Vec<(K, u8)>
+    where
+        K: Clone + 'a,
+    {
+        let mut freqs = sample_keys
+            .into_iter()
+            .map(|key| {
+                let key = self.inner.normalize_key(key.clone());
+                let (hash, segment) = self.inner.select(&key);
+                let freq = segment.estimated_frequency_with_hash(hash);
+                (key, freq)
+            })
+            .collect::<Vec<_>>();
+        freqs.sort_by_key(|(_, freq)| std::cmp::Reverse(*freq));
+        freqs
+    }
+
     /// Discards cached values that satisfy a predicate.
     ///
     /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
@@ -255,26 +791,254 @@ where
         Ok(())
     }
 
+    /// Returns the name of this cache, if it has been set by the
+    /// `CacheBuilder::name` method.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.segments[0].name()
+    }
+
     /// Returns the `max_capacity` of this cache.
+    ///
+    /// If the capacities of the segments were set by
+    /// `CacheBuilder::segment_capacities`, this is the sum of them.
     pub fn max_capacity(&self) -> Option<u64> {
         self.inner.desired_capacity
     }
 
+    /// Returns the weight that the weigher of this cache gives to the key-value
+    /// pair, or `None` if the cache was built without a weigher.
+    ///
+    /// See [`Cache::weigh`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.weigh
+    pub fn weigh(&self, key: &K, value: &V) -> Option<u32> {
+        // The segments share the same weigher.
+        self.inner.segments[0].weigh(key, value)
+    }
+
+    /// Returns the max capacity of each segment, in the order of the segment
+    /// indices.
+    ///
+    /// The capacities are the ones set by `CacheBuilder::segment_capacities`,
+    /// or otherwise the `max_capacity` divided evenly across the segments.
+    pub fn segment_max_capacities(&self) -> Vec<Option<u64>> {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.max_capacity().map(|cap| cap as u64))
+            .collect()
+    }
+
+    /// Returns the `initial_capacity` applied to this cache, which is the sum of
+    /// the initial capacities of its segments.
+    ///
+    /// The `initial_capacity` set by the builder is divided across the segments,
+    /// rounding up, so this may be slightly larger than the one set.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        let segments = &self.inner.segments;
+        segments[0]
+            .initial_capacity()
+            .map(|cap| cap * segments.len())
+    }
+
     /// Returns the `time_to_live` of this cache.
     pub fn time_to_live(&self) -> Option<Duration> {
         self.inner.segments[0].time_to_live()
     }
 
-    /// Returns the `time_to_idle` of this cache.
-    pub fn time_to_idle(&self) -> Option<Duration> {
-        self.inner.segments[0].time_to_idle()
+    /// Returns the `time_to_idle` of this cache.
+    pub fn time_to_idle(&self) -> Option<Duration> {
+        self.inner.segments[0].time_to_idle()
+    }
+
+    /// Returns `true` if any entry of this cache has been given its own
+    /// expiration, in any segment.
+    pub fn has_per_entry_expiration(&self) -> bool {
+        self.inner
+            .segments
+            .iter()
+            .any(|seg| seg.has_per_entry_expiration())
+    }
+
+    /// Returns the `max_age` of this cache.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.inner.segments[0].max_age()
+    }
+
+    /// Returns the keys of all segments of this cache with the instants when they will expire,
+    /// sorted from the soonest to the latest.
+    ///
+    /// An entry expires at the earliest of `last insert + time_to_live`, `last
+    /// access + time_to_idle` and `creation + max_age`. If none of them is
+    /// configured, this method returns an empty `Vec`.
+    ///
+    /// This method is intended for diagnostics and is not cheap: it walks the
+    /// internal access-order queues while holding their lock, then sorts the
+    /// result in `O(n log n)`. The result is weakly consistent; it reflects the
+    /// state as of the last time the pending reads and writes were applied to the
+    /// queues, so recently inserted or read entries may be missing or have stale
+    /// instants. Entries that have already expired or been invalidated are
+    /// excluded.
+    pub fn entries_by_expiration(&self) -> Vec<(Arc<K>, std::time::Instant)> {
+        let mut entries = self
+            .inner
+            .segments
+            .iter()
+            .flat_map(|seg| seg.entries_by_expiration())
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(_, ts)| *ts);
+        entries
+    }
+
+    /// Returns an iterator over the entries of all segments of this cache in the
+    /// order they will expire.
+    ///
+    /// The iterators of the segments are merged by the expiration instants. See
+    /// [`Cache::iter_by_expiration`][cache-iter-by-exp] for the ordering and
+    /// consistency guarantees.
+    ///
+    /// [cache-iter-by-exp]: ./struct.Cache.html#method.iter_by_expiration
+    pub fn iter_by_expiration(&self) -> impl Iterator<Item = (Arc<K>, V, std::time::Instant)> + '_ {
+        let mut iters = self
+            .inner
+            .segments
+            .iter()
+            .map(|seg| seg.iter_by_expiration().peekable())
+            .collect::<Vec<_>>();
+        std::iter::from_fn(move || {
+            let (i, _) = iters
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(i, iter)| iter.peek().map(|(_, _, ts)| (i, *ts)))
+                .min_by_key(|(_, ts)| *ts)?;
+            iters[i].next()
+        })
+    }
+
+    /// Calls the closure with a reference to the key and the value of every entry
+    /// in this cache, without cloning the values.
+    ///
+    /// The segments are visited one after another, each in chunks. See
+    /// [`Cache::for_each_entry`][cache-for-each-entry] for the locking and
+    /// consistency guarantees. The closure must be fast and must not call back
+    /// into this cache.
+    ///
+    /// [cache-for-each-entry]: ./struct.Cache.html#method.for_each_entry
+    pub fn for_each_entry(&self, mut f: impl FnMut(&K, &V)) {
+        for seg in self.inner.segments.iter() {
+            seg.for_each_entry(&mut f);
+        }
+    }
+
+    /// Returns the keys of the entries whose values satisfy the predicate.
+    ///
+    /// The segments are scanned one after another. See
+    /// [`Cache::keys_matching`][cache-keys-matching] for the details.
+    ///
+    /// [cache-keys-matching]: ./struct.Cache.html#method.keys_matching
+    pub fn keys_matching(&self, predicate: impl Fn(&V) -> bool) -> Vec<Arc<K>> {
+        self.inner
+            .segments
+            .iter()
+            .flat_map(|seg| seg.keys_matching(&predicate))
+            .collect()
+    }
+
+    /// Transforms the value of every entry in this cache in place.
+    ///
+    /// The segments are transformed one after another. See
+    /// [`Cache::transform_values`][cache-transform-values] for the details.
+    ///
+    /// [cache-transform-values]: ./struct.Cache.html#method.transform_values
+    pub fn transform_values(&self, mut f: impl FnMut(&K, &mut V)) {
+        for seg in self.inner.segments.iter() {
+            seg.transform_values(&mut f);
+        }
+    }
+
+    /// Returns a snapshot of the statistics of this cache, aggregated over all
+    /// segments.
+    ///
+    /// The statistics are recorded only if the cache was built with
+    /// [`CacheBuilder::record_stats`][record-stats]. Otherwise, this method
+    /// returns a `CacheStats` with all counts set to zero.
+    ///
+    /// [record-stats]: ./struct.CacheBuilder.html#method.record_stats
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+        for segment in self.inner.segments.iter() {
+            stats.merge(&segment.stats());
+        }
+        stats
+    }
+
+    /// Returns the number of lock contentions of each segment, in the order of
+    /// the segment indices.
+    ///
+    /// The contentions are counted only if the cache was built with
+    /// [`CacheBuilder::record_stats`][record-stats], which opts in to their small
+    /// overhead. Otherwise, all counts are zero. See
+    /// [`CacheStats::lock_contentions`][lock-contentions] for what is counted.
+    ///
+    /// The counts are since the cache was built, or since the last
+    /// `stats_and_reset`. Contentions spread over all segments suggest adding
+    /// more segments, while contentions concentrated on a few segments suggest
+    /// a skew to hot keys, which more segments will not spread.
+    ///
+    /// [record-stats]: ./struct.CacheBuilder.html#method.record_stats
+    /// [lock-contentions]: ../struct.CacheStats.html#method.lock_contentions
+    pub fn contention_stats(&self) -> Vec<u64> {
+        self.inner
+            .segments
+            .iter()
+            .map(Cache::lock_contentions)
+            .collect()
+    }
+
+    /// Returns a snapshot of the statistics of this cache aggregated over all
+    /// segments, and resets the counts to zero.
+    ///
+    /// See [`Cache::stats_and_reset`][cache-stats-and-reset] for the details.
+    ///
+    /// [cache-stats-and-reset]: ./struct.Cache.html#method.stats_and_reset
+    pub fn stats_and_reset(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+        for segment in self.inner.segments.iter() {
+            stats.merge(&segment.stats_and_reset());
+        }
+        stats
     }
 
     /// Returns the number of internal segments of this cache.
+    ///
+    /// This is the `num_segments` given to the builder rounded up to the next
+    /// power of two.
     pub fn num_segments(&self) -> usize {
         self.inner.segments.len()
     }
 
+    /// Returns the index of the internal segment that the key maps to, which is
+    /// in the range of `0..num_segments()`.
+    ///
+    /// The index is computed by the same function that every operation of this
+    /// cache uses to select the segment of a key, so you can use it to shard
+    /// other data structures in the same way as this cache. The index depends on
+    /// the hasher of the cache, so it is stable only for the lifetime of this
+    /// cache unless the cache was built with a deterministic hasher.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn segment_index_for<Q>(&self, key: &Q) -> usize
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        match self.inner.normalize_borrowed_key(key) {
+            Some(key) => self.inner.segment_index_and_hash::<K>(&key).0,
+            None => self.inner.segment_index_and_hash(key).0,
+        }
+    }
+
     #[cfg(test)]
     fn estimated_entry_count(&self) -> u64 {
         self.inner
@@ -293,6 +1057,15 @@ where
             .sum()
     }
 
+    #[cfg(test)]
+    fn bucket_capacity(&self) -> usize {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.bucket_capacity())
+            .sum()
+    }
+
     // /// This is used by unit tests to get consistent result.
     // #[cfg(test)]
     // pub(crate) fn reconfigure_for_testing(&mut self) {
@@ -303,19 +1076,61 @@ where
     // }
 }
 
+impl<K, T, S> SegmentedCache<K, Weak<T>, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Returns the object the cached `Weak` for the key points to, or `None` if
+    /// the key has no value or the object has been dropped.
+    ///
+    /// See [`Cache::get_upgraded`][cache-method] for the details.
+    ///
+    /// [cache-method]: ./struct.Cache.html#method.get_upgraded
+    pub fn get_upgraded<Q>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(key) = self.inner.normalize_borrowed_key(key) {
+            self.inner.select::<K>(&key).1.get_upgraded::<K>(&key)
+        } else {
+            self.inner.select(key).1.get_upgraded(key)
+        }
+    }
+}
+
 impl<K, V, S> ConcurrentCacheExt<K, V> for SegmentedCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
+    /// Runs the pending maintenance tasks of the segments concurrently on the
+    /// housekeeper thread pool, together with the calling thread.
+    ///
+    /// The segments do not share their counters (the entry count, the weighted
+    /// size and the statistics), so their maintenance does not need any
+    /// coordination.
     fn sync(&self) {
-        for segment in self.inner.segments.iter() {
-            segment.sync();
-        }
+        Inner::sync_segments(&self.inner);
     }
 }
 
+/// The progress of [`SegmentedCache::invalidate_all_with_progress`][method],
+/// reported when a segment has been cleared.
+///
+/// [method]: ./struct.SegmentedCache.html#method.invalidate_all_with_progress
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentCleared {
+    /// The index of the cleared segment, in the range of `0..num_segments()`.
+    pub index: usize,
+    /// The number of the entries removed from the segment. Entries inserted
+    /// concurrently by other threads may make this inexact.
+    pub entries_removed: u64,
+}
+
 // For unit tests.
 #[cfg(test)]
 impl<K, V, S> SegmentedCache<K, V, S>
@@ -370,10 +1185,64 @@ impl MockExpirationClock {
     }
 }
 
+/// Distributes the maintenance of the segments between the calling thread and
+/// the helper jobs running on the housekeeper thread pool.
+#[derive(Default)]
+struct ParallelSync {
+    // The index of the next segment to sync.
+    next: AtomicUsize,
+    state: Mutex<ParallelSyncState>,
+    helper_finished: Condvar,
+}
+
+#[derive(Default)]
+struct ParallelSyncState {
+    // Set when no segment is left to claim. The helper jobs started after this
+    // do nothing.
+    closed: bool,
+    running_helpers: usize,
+}
+
+impl ParallelSync {
+    /// Registers a helper. Returns `false` if the sync has already been closed.
+    fn enter_helper(&self) -> bool {
+        let mut state = self.state.lock();
+        if !state.closed {
+            state.running_helpers += 1;
+        }
+        !state.closed
+    }
+
+    fn exit_helper(&self) {
+        self.state.lock().running_helpers -= 1;
+        self.helper_finished.notify_all();
+    }
+
+    /// Closes the sync and waits for the running helpers to finish their
+    /// segments.
+    fn close_and_wait(&self) {
+        let mut state = self.state.lock();
+        state.closed = true;
+        while state.running_helpers > 0 {
+            self.helper_finished.wait(&mut state);
+        }
+    }
+}
+
+/// Calls `ParallelSync::exit_helper` even if the sync of a segment panics.
+struct HelperGuard<'a>(&'a ParallelSync);
+
+impl Drop for HelperGuard<'_> {
+    fn drop(&mut self) {
+        self.0.exit_helper();
+    }
+}
+
 struct Inner<K, V, S> {
     desired_capacity: Option<u64>,
     segments: Box<[Cache<K, V, S>]>,
     build_hasher: S,
+    key_normalizer: Option<KeyNormalizer<K>>,
     segment_shift: u32,
 }
 
@@ -386,63 +1255,183 @@ where
     /// # Panics
     ///
     /// Panics if `num_segments` is 0.
-    #[allow(clippy::too_many_arguments)]
     fn new(
-        max_capacity: Option<u64>,
-        initial_capacity: Option<usize>,
+        config: CacheConfig<K, V, S>,
         num_segments: usize,
-        build_hasher: S,
-        weigher: Option<Weigher<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        segment_capacities: Option<Vec<u64>>,
     ) -> Self {
         assert!(num_segments > 0);
 
         let actual_num_segments = num_segments.next_power_of_two();
         let segment_shift = 64 - actual_num_segments.trailing_zeros();
-        // TODO: Round up.
-        let seg_max_capacity = max_capacity.map(|n| n / actual_num_segments as u64);
-        let seg_init_capacity = initial_capacity.map(|cap| cap / actual_num_segments);
+        let (desired_capacity, seg_max_capacities) = match segment_capacities {
+            Some(caps) => {
+                assert_eq!(
+                    caps.len(),
+                    actual_num_segments,
+                    "segment_capacities must have one capacity per segment"
+                );
+                let total = caps.iter().fold(0u64, |sum, cap| sum.saturating_add(*cap));
+                (Some(total), caps.into_iter().map(Some).collect())
+            }
+            None => {
+                // TODO: Round up.
+                let seg_max_capacity = config.max_capacity.map(|n| n / actual_num_segments as u64);
+                (
+                    config.max_capacity,
+                    vec![seg_max_capacity; actual_num_segments],
+                )
+            }
+        };
+        let seg_init_capacity = config
+            .initial_capacity
+            .map(|cap| common::div_ceil(cap, actual_num_segments));
+        let seg_reservation_high_water_mark = config
+            .reservation_high_water_mark
+            .map(|n| n / actual_num_segments as u64);
         // NOTE: We cannot initialize the segments as `vec![cache; actual_num_segments]`
         // because Cache::clone() does not clone its inner but shares the same inner.
-        let segments = (0..num_segments)
-            .map(|_| {
-                Cache::with_everything(
-                    seg_max_capacity,
-                    seg_init_capacity,
-                    build_hasher.clone(),
-                    weigher.as_ref().map(Arc::clone),
-                    time_to_live,
-                    time_to_idle,
-                    invalidator_enabled,
-                )
+        let segments = seg_max_capacities
+            .into_iter()
+            .map(|seg_max_capacity| {
+                // All segments share the value dropper, so `DropContext::Worker`
+                // uses a single thread.
+                Cache::with_config(CacheConfig {
+                    max_capacity: seg_max_capacity,
+                    initial_capacity: seg_init_capacity,
+                    // The segmented cache normalizes keys before selecting a segment.
+                    key_normalizer: None,
+                    reservation_high_water_mark: seg_reservation_high_water_mark,
+                    ..config.clone()
+                })
             })
             .collect::<Vec<_>>();
 
         Self {
-            desired_capacity: max_capacity,
+            desired_capacity,
             segments: segments.into_boxed_slice(),
-            build_hasher,
+            build_hasher: config.build_hasher,
+            key_normalizer: config.key_normalizer,
             segment_shift,
         }
     }
 
+    /// Syncs all segments. When the housekeeper thread pool is available, helper
+    /// jobs are submitted to it, and the segments are claimed one by one by the
+    /// calling thread and the helpers. A helper that starts after all segments
+    /// have been claimed does nothing, so the calling thread never waits for a
+    /// job that is not running yet.
+    fn sync_segments(this: &Arc<Self>) {
+        let segments = &this.segments;
+        if segments.len() == 1 {
+            segments[0].sync();
+            return;
+        }
+        let num_helpers = num_cpus::get().min(segments.len()).saturating_sub(1);
+        let housekeeper = match segments[0].housekeeper() {
+            Some(h) if num_helpers > 0 => h,
+            _ => {
+                for segment in segments.iter() {
+                    segment.sync();
+                }
+                return;
+            }
+        };
+
+        let progress = Arc::new(ParallelSync::default());
+        for _ in 0..num_helpers {
+            // Hold a weak pointer, so that a helper job started after the cache
+            // has been dropped does not keep it alive.
+            let weak = Arc::downgrade(this);
+            let progress = Arc::clone(&progress);
+            housekeeper.execute(move || {
+                if !progress.enter_helper() {
+                    return;
+                }
+                let _guard = HelperGuard(&progress);
+                if let Some(inner) = weak.upgrade() {
+                    inner.sync_claimed_segments(&progress);
+                }
+            });
+        }
+
+        this.sync_claimed_segments(&progress);
+        progress.close_and_wait();
+    }
+
+    fn sync_claimed_segments(&self, progress: &ParallelSync) {
+        loop {
+            let index = progress.next.fetch_add(1, Ordering::AcqRel);
+            match self.segments.get(index) {
+                Some(segment) => segment.sync(),
+                None => break,
+            }
+        }
+    }
+
+    #[inline]
+    fn normalize_key(&self, key: K) -> K {
+        match &self.key_normalizer {
+            Some(normalizer) => normalizer(&key),
+            None => key,
+        }
+    }
+
+    #[inline]
+    fn normalize_borrowed_key<Q>(&self, key: &Q) -> Option<K>
+    where
+        Q: BorrowedKey<K> + ?Sized,
+    {
+        super::normalize_borrowed_key(self.key_normalizer.as_ref(), key)
+    }
+
     #[inline]
     fn hash<Q>(&self, key: &Q) -> u64
     where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let mut hasher = self.build_hasher.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.build_hasher.hash_one(key)
+    }
+
+    /// Returns the index of the segment for the key and the hash of the key.
+    ///
+    /// Every operation on a key must select its segment through this method so
+    /// that they never disagree.
+    #[inline]
+    fn segment_index_and_hash<Q>(&self, key: &Q) -> (usize, u64)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash(key);
+        (self.segment_index_from_hash(hash), hash)
     }
 
     #[inline]
-    fn select(&self, hash: u64) -> &Cache<K, V, S> {
-        let index = self.segment_index_from_hash(hash);
-        &self.segments[index]
+    fn select<Q>(&self, key: &Q) -> (u64, &Cache<K, V, S>)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (index, hash) = self.segment_index_and_hash(key);
+        (hash, &self.segments[index])
+    }
+
+    fn invalidate<Q>(&self, key: &Q, publish: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let (index, _) = self.segment_index_and_hash(key);
+        debug_assert!(
+            self.segments
+                .iter()
+                .enumerate()
+                .all(|(i, segment)| i == index || !segment.contains_key(key)),
+            "The key is found in a segment other than the selected one"
+        );
+        self.segments[index].do_invalidate(key, publish)
     }
 
     #[inline]
@@ -458,7 +1447,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::{ConcurrentCacheExt, SegmentedCache};
-    use crate::sync::CacheBuilder;
+    use crate::{sync::CacheBuilder, LoadError};
     use std::time::Duration;
 
     #[test]
@@ -507,6 +1496,16 @@ mod tests {
         cache.invalidate(&"b");
     }
 
+    #[test]
+    fn weigh() {
+        let cache = CacheBuilder::new(100)
+            .segments(4)
+            .weigher(|_k: &&str, v: &(&str, u32)| v.1)
+            .build();
+        assert_eq!(cache.weigh(&"a", &("alice", 10)), Some(10));
+        assert_eq!(cache.get(&"a"), None);
+    }
+
     #[test]
     fn size_aware_eviction() {
         let weigher = |_k: &&str, v: &(&str, u32)| v.1;
@@ -621,6 +1620,185 @@ mod tests {
         assert!(cache.get(&20).is_some());
     }
 
+    #[test]
+    fn sync_segments_in_parallel() {
+        const NUM_THREADS: u32 = 8;
+        const NUM_KEYS: u32 = 1_000;
+
+        // Keep the housekeeper, which runs the helper jobs of the sync.
+        let cache = SegmentedCache::new(u64::MAX, 64);
+
+        let handles = (0..NUM_THREADS)
+            .map(|id| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for key in (id * NUM_KEYS)..((id + 1) * NUM_KEYS) {
+                        cache.insert(key, key);
+                        if key % 100 == 0 {
+                            cache.sync();
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+
+        cache.sync();
+
+        let num_entries = (NUM_THREADS * NUM_KEYS) as u64;
+        assert_eq!(cache.estimated_entry_count(), num_entries);
+        assert_eq!(cache.weighted_size(), num_entries);
+        assert!((0..NUM_THREADS * NUM_KEYS).all(|key| cache.get(&key) == Some(key)));
+    }
+
+    #[test]
+    fn invalidate_present() {
+        let mut cache = SegmentedCache::new(100, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // The inserts have not been applied by the maintenance yet.
+        for key in 0..8 {
+            cache.insert(key, key);
+        }
+        assert!((0..8).all(|key| cache.invalidate_present(&key)));
+        assert!((0..8).all(|key| !cache.invalidate_present(&key)));
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+    }
+
+    #[test]
+    fn for_each_entry() {
+        let mut cache = SegmentedCache::new(1000, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..300 {
+            cache.insert(i, i * 10);
+        }
+        cache.sync();
+        cache.invalidate(&0);
+
+        let mut visited = Vec::new();
+        cache.for_each_entry(|k, v| visited.push((*k, *v)));
+        visited.sort_unstable();
+        assert_eq!(visited, (1..300).map(|i| (i, i * 10)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn get_loading() {
+        let cache = CacheBuilder::new(100)
+            .segments(4)
+            .key_normalizer(|k: &String| k.to_lowercase())
+            .loader(|k: &String| format!("{}!", k))
+            .build();
+
+        // The loader receives the normalized key.
+        assert_eq!(cache.get_loading("Alice".to_string()), "alice!");
+        assert_eq!(cache.get(&"alice".to_string()), Some("alice!".to_string()));
+        assert_eq!(cache.get_loading("ALICE".to_string()), "alice!");
+    }
+
+    #[test]
+    fn transform_values() {
+        let mut cache = SegmentedCache::new(1000, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..300 {
+            cache.insert(i, i * 10);
+        }
+        cache.transform_values(|k, v| *v += k);
+        assert!((0..300).all(|i| cache.get(&i) == Some(i * 11)));
+    }
+
+    #[test]
+    fn insert_if_version() {
+        let mut cache = SegmentedCache::new(100, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let v1 = cache.insert_if_version("a", "alice", None).unwrap();
+        let v2 = cache.insert_if_version("a", "anna", Some(v1)).unwrap();
+        assert!(v2 > v1);
+        assert!(cache.insert_if_version("a", "amy", Some(v1)).is_err());
+        assert_eq!(cache.get_versioned(&"a"), Some(("anna", v2)));
+    }
+
+    #[test]
+    fn segment_capacities() {
+        let mut cache = CacheBuilder::default()
+            .segments(4)
+            .segment_capacities(vec![100, 10, 10, 10])
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..1000 {
+            cache.insert(i, i);
+        }
+        cache.sync();
+
+        let counts = cache
+            .inner
+            .segments
+            .iter()
+            .map(|seg| seg.estimated_entry_count())
+            .collect::<Vec<_>>();
+        assert!(counts[0] > 10 && counts[0] <= 100, "{:?}", counts);
+        assert!(counts[1..].iter().all(|n| *n <= 10), "{:?}", counts);
+    }
+
+    #[test]
+    fn contention_stats() {
+        let cache = CacheBuilder::new(100).segments(4).record_stats().build();
+        let busy = cache.segment_index_for(&0);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let cache2 = cache.clone();
+        let handle = std::thread::spawn(move || {
+            rx.recv().expect("Failed");
+            cache2.insert_with(0, |v| v.unwrap() + 1)
+        });
+        let v = cache.insert_with(0, |_| {
+            // The other thread waits for the lock of the key held by this closure.
+            tx.send(()).expect("Failed");
+            std::thread::sleep(Duration::from_millis(200));
+            1
+        });
+        assert_eq!(v, 1);
+        assert_eq!(handle.join().expect("Failed"), 2);
+
+        let contentions = cache.contention_stats();
+        assert_eq!(contentions.len(), 4);
+        for (i, n) in contentions.into_iter().enumerate() {
+            if i == busy {
+                assert!(n >= 1, "segment {}: {}", i, n);
+            } else {
+                assert_eq!(n, 0, "segment {}", i);
+            }
+        }
+
+        assert!(cache.stats_and_reset().lock_contentions() >= 1);
+        assert_eq!(cache.contention_stats(), vec![0; 4]);
+
+        // Nothing is counted without record_stats.
+        let cache: SegmentedCache<u32, u32> = SegmentedCache::new(100, 4);
+        cache.insert(0, 0);
+        assert_eq!(cache.contention_stats(), vec![0; 4]);
+    }
+
     #[test]
     fn invalidate_all() {
         let mut cache = SegmentedCache::new(100, 4);
@@ -649,6 +1827,50 @@ mod tests {
         assert_eq!(cache.get(&"d"), Some("david"));
     }
 
+    #[test]
+    fn invalidate_all_with_progress() {
+        let mut cache = SegmentedCache::new(1000, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..200u32 {
+            cache.insert(i, i);
+        }
+        let in_segment = |index: usize| {
+            (0..200u32)
+                .filter(|i| cache.segment_index_for(i) == index)
+                .collect::<Vec<_>>()
+        };
+
+        // Clear two segments, and cancel the rest.
+        let mut progress = cache.invalidate_all_with_progress();
+        for expected_index in 0..2 {
+            let cleared = progress.next().expect("No progress");
+            assert_eq!(cleared.index, expected_index);
+            assert_eq!(
+                cleared.entries_removed,
+                in_segment(expected_index).len() as u64
+            );
+        }
+        drop(progress);
+
+        for index in 0..4 {
+            for key in in_segment(index) {
+                let expected = if index < 2 { None } else { Some(key) };
+                assert_eq!(cache.get(&key), expected);
+            }
+        }
+
+        // Clear the remaining segments.
+        let cleared = cache.invalidate_all_with_progress().collect::<Vec<_>>();
+        assert_eq!(cleared.len(), 4);
+        let removed = cleared.iter().map(|c| c.entries_removed).sum::<u64>();
+        assert_eq!(removed, (in_segment(2).len() + in_segment(3).len()) as u64);
+        assert!((0..200u32).all(|i| cache.get(&i).is_none()));
+    }
+
     #[test]
     fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
         use std::collections::HashSet;
@@ -679,7 +1901,7 @@ mod tests {
 
         let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
         cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
-        assert_eq!(cache.invalidation_predicate_count(), SEGMENTS * 1);
+        assert_eq!(cache.invalidation_predicate_count(), SEGMENTS);
 
         mock.increment(Duration::from_secs(5)); // 10 secs from the start.
 
@@ -697,7 +1919,7 @@ mod tests {
         // This should survive as it was inserted after calling invalidate_entries_if.
         assert_eq!(cache.get(&3), Some("alice"));
         assert_eq!(cache.estimated_entry_count(), 2);
-        assert_eq!(cache.invalidation_predicate_count(), SEGMENTS * 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
 
         mock.increment(Duration::from_secs(5)); // 15 secs from the start.
 
@@ -714,7 +1936,7 @@ mod tests {
         assert!(cache.get(&1).is_none());
         assert!(cache.get(&3).is_none());
         assert_eq!(cache.estimated_entry_count(), 0);
-        assert_eq!(cache.invalidation_predicate_count(), SEGMENTS * 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
 
         Ok(())
     }
@@ -796,23 +2018,20 @@ mod tests {
             })
         };
 
-        for t in vec![thread1, thread2, thread3, thread4, thread5] {
+        for t in [thread1, thread2, thread3, thread4, thread5] {
             t.join().expect("Failed to join");
         }
     }
 
     #[test]
     fn get_or_try_insert_with() {
-        use std::{
-            sync::Arc,
-            thread::{sleep, spawn},
-        };
+        use std::thread::{sleep, spawn};
 
         #[derive(thiserror::Error, Debug)]
         #[error("{}", _0)]
         pub struct MyError(String);
 
-        type MyResult<T> = Result<T, Arc<MyError>>;
+        type MyResult<T> = Result<T, LoadError<MyError>>;
 
         let cache = SegmentedCache::new(100, 4);
         const KEY: u32 = 0;
@@ -932,10 +2151,125 @@ mod tests {
             })
         };
 
-        for t in vec![
+        for t in [
             thread1, thread2, thread3, thread4, thread5, thread6, thread7, thread8,
         ] {
             t.join().expect("Failed to join");
         }
     }
+
+    #[test]
+    fn segment_index_for() {
+        // Not a power of two.
+        let cache = SegmentedCache::new(100, 3);
+        assert_eq!(cache.num_segments(), 4);
+
+        for i in 0..100 {
+            cache.insert(i, i);
+        }
+        let mut used = [false; 4];
+        for i in 0..100 {
+            let index = cache.segment_index_for(&i);
+            assert!(cache.inner.segments[index].contains_key(&i));
+            used[index] = true;
+            cache.invalidate(&i);
+            assert_eq!(cache.get(&i), None);
+        }
+        assert!(used.iter().all(|u| *u));
+    }
+
+    #[test]
+    fn try_insert() {
+        use std::thread::spawn;
+
+        let cache = SegmentedCache::new(100, 4);
+
+        // Only one of the concurrent calls on the same key should win.
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let cache = cache.clone();
+                spawn(move || cache.try_insert("key", i).is_ok())
+            })
+            .collect();
+        let winners = threads
+            .into_iter()
+            .map(|t| t.join().expect("Failed to join"))
+            .filter(|won| *won)
+            .count();
+        assert_eq!(winners, 1);
+
+        let existing = cache.get(&"key").expect("The key should exist");
+        let err = cache.try_insert("key", 100).unwrap_err();
+        assert_eq!((err.existing, err.value), (existing, 100));
+    }
+
+    #[test]
+    fn evict_now() {
+        let cache = SegmentedCache::new(100, 4);
+        for i in 0..40 {
+            cache.insert(i, i);
+        }
+
+        assert_eq!(cache.evict_now(10), 10);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 30);
+
+        // Asks for more than the remaining entries.
+        assert_eq!(cache.evict_now(50), 30);
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 0);
+    }
+
+    #[test]
+    fn key_normalizer() {
+        let cache: SegmentedCache<String, u32> = CacheBuilder::new(100)
+            .key_normalizer(|key: &String| key.to_lowercase())
+            .segments(4)
+            .build();
+
+        for i in 0..20 {
+            cache.insert(format!("KEY-{}", i), i);
+        }
+        for i in 0..20 {
+            assert_eq!(cache.get(&format!("key-{}", i)), Some(i));
+        }
+        cache.sync();
+        assert_eq!(cache.estimated_entry_count(), 20);
+
+        cache.invalidate(&"Key-7".to_string());
+        assert_eq!(cache.get(&"key-7".to_string()), None);
+    }
+
+    #[test]
+    fn initial_capacity() {
+        const INITIAL_CAPACITY: usize = 1_000_000;
+
+        let single: SegmentedCache<u32, u32> = CacheBuilder::default()
+            .initial_capacity(INITIAL_CAPACITY)
+            .segments(1)
+            .build();
+        let segmented: SegmentedCache<u32, u32> = CacheBuilder::default()
+            .initial_capacity(INITIAL_CAPACITY)
+            .segments(64)
+            .build();
+
+        assert_eq!(single.initial_capacity(), Some(INITIAL_CAPACITY));
+        // 1_000_000 / 64 = 15625 per segment.
+        assert_eq!(segmented.initial_capacity(), Some(INITIAL_CAPACITY));
+
+        // Each segment should get its share of the initial capacity, not the whole
+        // of it.
+        let single_buckets = single.bucket_capacity();
+        let segmented_buckets = segmented.bucket_capacity();
+        assert!(single_buckets >= INITIAL_CAPACITY);
+        assert!(segmented_buckets >= INITIAL_CAPACITY);
+        assert!(segmented_buckets <= single_buckets * 2);
+
+        // Rounded up.
+        let cache: SegmentedCache<u32, u32> = CacheBuilder::default()
+            .initial_capacity(100)
+            .segments(8)
+            .build();
+        assert_eq!(cache.initial_capacity(), Some(104));
+    }
 }
@@ -0,0 +1,110 @@
+use super::{base_cache::BaseCache, BorrowedKey};
+use crate::common::{stats::OpKind, time::Instant};
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+/// A handle to read a cache as if `invalidate_all` had not been called after the
+/// handle was created.
+///
+/// A `SnapshotHandle` is created by the `snapshot_handle` method of `Cache`. It
+/// is meant for a read path that does several `get`s and must not see some of
+/// the entries vanish midway because another thread has called
+/// `invalidate_all`. While the handle is alive, the entries invalidated that way
+/// are kept in the cache, so that the handle can still read them. Other readers
+/// do not see them.
+///
+/// # Visibility
+///
+/// Each `invalidate_all` (or `clear`) call is ordered either before or after the
+/// creation of the handle, even when they run concurrently. A `get` through the
+/// handle returns an entry if and only if:
+///
+/// - the entry was last inserted or updated no earlier than the latest
+///   `invalidate_all` ordered before the handle, and
+/// - the entry is present and neither expired nor individually invalidated at
+///   the time of the `get`.
+///
+/// So the `invalidate_all` calls ordered after the handle, including those in
+/// progress while it reads, have no effect on what it returns until it is
+/// dropped. A plain `get` on the cache applies the latest `invalidate_all`
+/// instead.
+///
+/// # Limitations
+///
+/// The isolation is weak. It only covers the mass invalidation:
+///
+/// - Individual entries may still be evicted, expire, or be removed by
+///   `invalidate` and `invalidate_entries_if` while the handle is alive.
+/// - Entries inserted or updated after the handle was created are visible
+///   through it.
+///
+/// Keep the handle short-lived (e.g. one request), as it delays the removal of
+/// the invalidated entries, which still occupy the cache capacity.
+pub struct SnapshotHandle<'a, K, V, S = RandomState>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    base: &'a BaseCache<K, V, S>,
+    id: u64,
+    valid_after: Option<Instant>,
+}
+
+impl<'a, K, V, S> SnapshotHandle<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(base: &'a BaseCache<K, V, S>) -> Self {
+        let (id, valid_after) = base.pin_snapshot();
+        Self {
+            base,
+            id,
+            valid_after,
+        }
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, ignoring the
+    /// `invalidate_all` calls made after this handle was created.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        let started_at = self.base.start_latency_timer(OpKind::Get);
+        let entry = match self.base.normalize_borrowed_key(key) {
+            Some(key) => {
+                let hash = self.base.hash::<K>(&key);
+                self.base.get_entry_as_of::<K>(&key, hash, self.valid_after)
+            }
+            None => {
+                let hash = self.base.hash(key);
+                self.base.get_entry_as_of(key, hash, self.valid_after)
+            }
+        };
+        let v = entry.map(|(_, entry)| entry.value().clone());
+        self.base.record_latency(OpKind::Get, started_at);
+        v
+    }
+}
+
+impl<'a, K, V, S> Drop for SnapshotHandle<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.base.unpin_snapshot(self.id);
+    }
+}
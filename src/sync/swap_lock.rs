@@ -0,0 +1,69 @@
+use parking_lot::Mutex;
+use std::{
+    sync::atomic::{self, AtomicUsize, Ordering},
+    thread::{self, ThreadId},
+};
+
+/// A sequence lock to make `Cache::swap` atomic for `Cache::get_pair`.
+///
+/// The swaps are serialized by a mutex. A swap makes the sequence number odd
+/// before writing the two values, and even again after writing them. A reader
+/// reads the values when the number is even, and retries if the number has
+/// changed meanwhile, so it never sees the values of a swap in progress.
+///
+/// The eviction listener is called by the swapping thread when the swap replaces
+/// the values. If it swaps or reads a pair, it does so without waiting for the
+/// swap in progress, which would never finish otherwise.
+#[derive(Default)]
+pub(crate) struct SwapLock {
+    seq: AtomicUsize,
+    writer: Mutex<()>,
+    owner: Mutex<Option<ThreadId>>,
+}
+
+impl SwapLock {
+    pub(crate) fn write<R>(&self, f: impl FnOnce() -> R) -> R {
+        if self.is_writing_on_current_thread() {
+            return f();
+        }
+        let _writer = self.writer.lock();
+        *self.owner.lock() = Some(thread::current().id());
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        // Makes the number even again even if `f` panics, so that the readers
+        // will not spin forever.
+        let _guard = WriteGuard(self);
+        f()
+    }
+
+    pub(crate) fn read<R>(&self, mut f: impl FnMut() -> R) -> R {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq % 2 == 1 {
+                if self.is_writing_on_current_thread() {
+                    return f();
+                }
+                std::hint::spin_loop();
+                continue;
+            }
+            let r = f();
+            atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return r;
+            }
+        }
+    }
+
+    fn is_writing_on_current_thread(&self) -> bool {
+        *self.owner.lock() == Some(thread::current().id())
+    }
+}
+
+struct WriteGuard<'a>(&'a SwapLock);
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.0.seq.fetch_add(1, Ordering::Release);
+        *self.0.owner.lock() = None;
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    any::Any,
+    borrow::Borrow,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+};
+
+static NEXT_CACHE_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Maps the ID of a `ThreadLocalCache` to the slot of the current thread.
+    static SLOTS: RefCell<HashMap<usize, ErasedSlot>> = RefCell::new(HashMap::new());
+}
+
+struct ErasedSlot {
+    // Becomes dangling when the owning `ThreadLocalCache` has been dropped. We
+    // use it to purge slots of dropped caches from the thread local map.
+    owner: Weak<()>,
+    slot: Box<dyn Any>,
+}
+
+struct Slot<K, V> {
+    generation: u64,
+    // The most recently used entry is at the front.
+    entries: VecDeque<(u64, Arc<K>, V)>,
+}
+
+/// A small LRU of recently read entries, one per thread.
+///
+/// Every slot is tagged with the write generation of the cache at the time the
+/// entries were read from the shared store. A slot whose generation differs from
+/// the current write generation is cleared before it is consulted, so a value can
+/// only be returned from here if nothing has been written to (or evicted from) the
+/// cache since the value was read.
+pub(crate) struct ThreadLocalCache<K, V> {
+    id: usize,
+    capacity: usize,
+    owner: Arc<()>,
+    _marker: PhantomData<fn(K, V)>,
+}
+
+impl<K, V> ThreadLocalCache<K, V>
+where
+    K: Hash + Eq + 'static,
+    V: Clone + 'static,
+{
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            id: NEXT_CACHE_ID.fetch_add(1, Ordering::Relaxed),
+            capacity,
+            owner: Arc::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn get<Q>(&self, key: &Q, hash: u64, generation: u64) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_with(key, hash, generation, |_k, v| v.clone())
+    }
+
+    /// Same as `get`, but returns the result of `f` called with the key and the
+    /// value. `f` is called while the slot is borrowed, so it must not access
+    /// this `ThreadLocalCache`.
+    pub(crate) fn get_with<Q, R>(
+        &self,
+        key: &Q,
+        hash: u64,
+        generation: u64,
+        f: impl FnOnce(&Arc<K>, &V) -> R,
+    ) -> Option<R>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.with_slot(generation, |slot| {
+            let pos = slot
+                .entries
+                .iter()
+                .position(|(h, k, _)| *h == hash && k.borrow() == key)?;
+            if pos > 0 {
+                let entry = slot.entries.remove(pos).unwrap();
+                slot.entries.push_front(entry);
+            }
+            slot.entries.front().map(|(_, k, v)| f(k, v))
+        })
+        .flatten()
+    }
+
+    pub(crate) fn put(&self, key: Arc<K>, hash: u64, value: V, generation: u64) {
+        let capacity = self.capacity;
+        self.with_slot(generation, |slot| {
+            if let Some(pos) = slot
+                .entries
+                .iter()
+                .position(|(h, k, _)| *h == hash && *k == key)
+            {
+                slot.entries.remove(pos);
+            } else if slot.entries.len() >= capacity {
+                slot.entries.pop_back();
+            }
+            if capacity > 0 {
+                slot.entries.push_front((hash, key, value));
+            }
+        });
+    }
+
+    /// Runs `f` with the slot of the current thread. Returns `None` without
+    /// calling `f` when the slot is not accessible, e.g. during the thread
+    /// teardown or a re-entrant call from `V::clone`.
+    fn with_slot<F, R>(&self, generation: u64, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Slot<K, V>) -> R,
+    {
+        SLOTS
+            .try_with(|slots| {
+                let mut slots = slots.try_borrow_mut().ok()?;
+                if !slots.contains_key(&self.id) {
+                    // Take the chance to drop the slots of dropped caches.
+                    slots.retain(|_, s| s.owner.strong_count() > 0);
+                    slots.insert(
+                        self.id,
+                        ErasedSlot {
+                            owner: Arc::downgrade(&self.owner),
+                            slot: Box::new(Slot::<K, V> {
+                                generation,
+                                entries: VecDeque::with_capacity(self.capacity),
+                            }),
+                        },
+                    );
+                }
+                let slot = slots
+                    .get_mut(&self.id)
+                    .and_then(|s| s.slot.downcast_mut::<Slot<K, V>>())?;
+                if slot.generation != generation {
+                    slot.entries.clear();
+                    slot.generation = generation;
+                }
+                Some(f(slot))
+            })
+            .ok()
+            .flatten()
+    }
+}
+
+impl<K, V> Drop for ThreadLocalCache<K, V> {
+    fn drop(&mut self) {
+        // Slots held by other threads will be purged lazily.
+        let _ = SLOTS.try_with(|slots| {
+            if let Ok(mut slots) = slots.try_borrow_mut() {
+                slots.remove(&self.id);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThreadLocalCache;
+    use std::sync::Arc;
+
+    #[test]
+    fn lru_and_generation() {
+        let tlc = ThreadLocalCache::<u32, &str>::new(2);
+
+        tlc.put(Arc::new(1), 1, "a", 0);
+        tlc.put(Arc::new(2), 2, "b", 0);
+        assert_eq!(tlc.get(&1, 1, 0), Some("a"));
+
+        // 2 is the least recently used.
+        tlc.put(Arc::new(3), 3, "c", 0);
+        assert_eq!(tlc.get(&2, 2, 0), None);
+        assert_eq!(tlc.get(&1, 1, 0), Some("a"));
+        assert_eq!(tlc.get(&3, 3, 0), Some("c"));
+
+        // Same hash but different keys.
+        assert_eq!(tlc.get(&4, 3, 0), None);
+
+        // A new generation clears the slot.
+        assert_eq!(tlc.get(&1, 1, 1), None);
+        assert_eq!(tlc.get(&3, 3, 1), None);
+    }
+}
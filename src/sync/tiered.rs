@@ -0,0 +1,329 @@
+use super::{BorrowedKey, Cache, CacheBuilder, ConcurrentCacheExt};
+use crate::{
+    notification::{self, EvictionListener, RemovalCause},
+    CacheStats,
+};
+
+use std::{borrow::Borrow, collections::hash_map::RandomState, hash::Hash, sync::Arc};
+
+/// A two-tier cache composed of a small _near_ (L1) cache in front of a larger
+/// _far_ (L2) cache.
+///
+/// A lookup that misses the near tier looks up the far tier, and a value found
+/// there is promoted to the near tier. An entry evicted from the near tier for
+/// its size is demoted to the far tier. Inserts go to the near tier.
+///
+/// # Consistency between the tiers
+///
+/// The tiers are exclusive: a key lives in one of them. Inserting a value removes
+/// the key from the far tier, promoting a value moves it from the far tier to the
+/// near tier, and demoting moves it back. These moves are not atomic across the
+/// tiers, so for a moment a key may be in both of them, or (while being moved) in
+/// neither of them, in which case a concurrent `get` misses. When the key is in
+/// both tiers, the near tier wins, and a promotion never overwrites a value
+/// inserted into the near tier meanwhile. `invalidate` removes the key from both
+/// tiers.
+///
+/// The eviction listeners of the tiers are notified of the entries leaving the
+/// tiered cache, not of the moves between the tiers:
+///
+/// - The entries evicted from the near tier for the size (`RemovalCause::Size`),
+///   or not admitted to it (`RemovalCause::NotAdmitted`), are demoted without
+///   notifying the listener of the near tier.
+/// - Promoting a value, and inserting a value for a key that is in the far tier,
+///   remove the key from the far tier without notifying the listener of the far
+///   tier.
+/// - `invalidate` notifies the listener of only one tier: the near tier if it
+///   had the key, otherwise the far tier.
+///
+/// The near tier cannot have a
+/// [batched eviction listener][eviction-listener-batched]. The tiers should be
+/// built with the same key normalizer, if any.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::sync::{ConcurrentCacheExt, TieredCache};
+///
+/// let cache = TieredCache::new(1, 100);
+/// cache.insert("a", "alice");
+/// cache.insert("b", "bob");
+/// cache.sync();
+///
+/// // The near tier has room for one entry, so the other one has been demoted
+/// // (or rejected by the near tier, and moved to the far tier).
+/// assert_eq!(cache.get(&"a"), Some("alice"));
+/// assert_eq!(cache.get(&"b"), Some("bob"));
+/// ```
+///
+/// [eviction-listener-batched]: ./struct.CacheBuilder.html#method.eviction_listener_batched
+pub struct TieredCache<K, V> {
+    near: Arc<Cache<K, V, RandomState>>,
+    // Shared with the eviction listener of the near tier.
+    far: Arc<Cache<K, V, RandomState>>,
+}
+
+impl<K, V> Clone for TieredCache<K, V> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            near: Arc::clone(&self.near),
+            far: Arc::clone(&self.far),
+        }
+    }
+}
+
+impl<K, V> TieredCache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Constructs a new `TieredCache<K, V>` whose near and far tiers can hold up
+    /// to `near_capacity` and `far_capacity` entries respectively.
+    pub fn new(near_capacity: u64, far_capacity: u64) -> Self {
+        Self::from_builders(
+            Cache::builder().max_capacity(near_capacity),
+            Cache::builder().max_capacity(far_capacity),
+        )
+    }
+
+    /// Constructs a new `TieredCache<K, V>` with the tiers built by the given
+    /// builders.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `near` builder has a batched eviction listener, or if either
+    /// builder panics on `build`.
+    pub fn from_builders(
+        near: CacheBuilder<K, V, Cache<K, V, RandomState>>,
+        far: CacheBuilder<K, V, Cache<K, V, RandomState>>,
+    ) -> Self {
+        let far = Arc::new(far.build());
+        let far1 = Arc::clone(&far);
+        let near = near
+            .wrap_eviction_listener(move |listener| {
+                assert!(
+                    !matches!(listener, Some(EvictionListener::Batched(_))),
+                    "The near tier of a TieredCache cannot have eviction_listener_batched"
+                );
+                EvictionListener::WithInfo(Arc::new(move |key, value, info| {
+                    if matches!(info.cause(), RemovalCause::Size | RemovalCause::NotAdmitted) {
+                        far1.insert_normalized(key, value);
+                    } else if let Some(listener) = &listener {
+                        notification::notify(listener, key, value, info);
+                    }
+                }))
+            })
+            .build();
+        Self {
+            near: Arc::new(near),
+            far,
+        }
+    }
+
+    /// Returns a reference to the near (L1) tier.
+    pub fn near(&self) -> &Cache<K, V, RandomState> {
+        &self.near
+    }
+
+    /// Returns a reference to the far (L2) tier.
+    pub fn far(&self) -> &Cache<K, V, RandomState> {
+        &self.far
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key. If the value is
+    /// found in the far tier, it is promoted to the near tier.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if let Some(value) = self.near.get(key) {
+            return Some(value);
+        }
+        // Read it first, so that the far tier counts the hit.
+        let value = self.far.get(key)?;
+        match self.far.remove_unnotified(key) {
+            Some((key, value)) => Some(self.near.insert_normalized_if_absent(key, value)),
+            // Removed (or being promoted) by another thread after the read.
+            None => Some(value),
+        }
+    }
+
+    /// Inserts a key-value pair into the near tier, and removes the key from the
+    /// far tier.
+    pub fn insert(&self, key: K, value: V) {
+        let key = self.near.insert_returning_key(key, value);
+        self.far.remove_unnotified::<K>(&key);
+    }
+
+    /// Discards any cached value for the key from both tiers.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: BorrowedKey<K> + Hash + Eq + ?Sized,
+    {
+        if self.near.invalidate_present(key) {
+            self.far.remove_unnotified(key);
+        } else {
+            self.far.invalidate(key);
+        }
+    }
+
+    /// Discards all cached values from both tiers.
+    pub fn invalidate_all(&self) {
+        self.near.invalidate_all();
+        self.far.invalidate_all();
+    }
+
+    /// Returns the statistics of the tiered cache, combined from the tiers.
+    ///
+    /// A lookup counts as a hit if either tier has the value, and as a miss if
+    /// neither has it. The entries demoted from the near tier are not counted as
+    /// evicted. Statistics are recorded only by the tiers built with
+    /// `record_stats`.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats::merge_tiers(&self.near.stats(), &self.far.stats())
+    }
+
+    /// Returns the statistics of the tiered cache like `stats`, and resets the
+    /// counts of both tiers to zero.
+    ///
+    /// See [`Cache::stats_and_reset`][cache-stats-and-reset] for the details.
+    ///
+    /// [cache-stats-and-reset]: ./struct.Cache.html#method.stats_and_reset
+    pub fn stats_and_reset(&self) -> CacheStats {
+        CacheStats::merge_tiers(&self.near.stats_and_reset(), &self.far.stats_and_reset())
+    }
+}
+
+impl<K, V> ConcurrentCacheExt<K, V> for TieredCache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn sync(&self) {
+        // The near tier first, as it may demote the entries to the far tier.
+        self.near.sync();
+        self.far.sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TieredCache;
+    use crate::{
+        notification::RemovalCause,
+        sync::{Cache, ConcurrentCacheExt},
+    };
+
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[allow(clippy::type_complexity)]
+    fn tiered_cache(
+        near_capacity: u64,
+    ) -> (
+        TieredCache<char, &'static str>,
+        Arc<Mutex<Vec<(&'static str, char, RemovalCause)>>>,
+    ) {
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let (removed1, removed2) = (Arc::clone(&removed), Arc::clone(&removed));
+        let cache = TieredCache::from_builders(
+            Cache::builder()
+                .max_capacity(near_capacity)
+                .record_stats()
+                .eviction_listener(move |k, _v, cause| removed1.lock().push(("near", *k, cause))),
+            Cache::builder()
+                .max_capacity(100)
+                .record_stats()
+                .eviction_listener(move |k, _v, cause| removed2.lock().push(("far", *k, cause))),
+        );
+        (cache, removed)
+    }
+
+    #[test]
+    fn promote_and_demote() {
+        let (cache, removed) = tiered_cache(2);
+
+        cache.insert('a', "alice");
+        cache.insert('b', "bob");
+        cache.insert('c', "cindy");
+        cache.sync();
+
+        // Each key is in one of the tiers.
+        let in_near = |k| cache.near().contains_key(k);
+        let in_far = |k| cache.far().contains_key(k);
+        for k in &['a', 'b', 'c'] {
+            assert!(in_near(k) != in_far(k), "{}", k);
+        }
+        assert_eq!(['a', 'b', 'c'].iter().filter(|k| in_far(k)).count(), 1);
+        // The demotion is not notified.
+        assert!(removed.lock().is_empty());
+
+        let demoted = *['a', 'b', 'c'].iter().find(|k| in_far(k)).unwrap();
+        assert!(cache.get(&demoted).is_some());
+        cache.sync();
+        assert!(in_near(&demoted));
+        assert!(!in_far(&demoted));
+        assert!(removed.lock().is_empty());
+
+        // One miss in each tier makes one miss of the tiered cache.
+        assert_eq!(cache.get(&'z'), None);
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 1);
+        assert_eq!(stats.miss_count(), 1);
+        assert_eq!(stats.evicted_count(), 0);
+    }
+
+    #[test]
+    fn insert_and_invalidate() {
+        let (cache, removed) = tiered_cache(10);
+
+        // Inserting removes the key from the far tier without notification.
+        cache.far().insert('a', "alice");
+        cache.insert('a', "anna");
+        cache.sync();
+        assert!(!cache.far().contains_key(&'a'));
+        assert_eq!(cache.get(&'a'), Some("anna"));
+        assert!(removed.lock().is_empty());
+
+        // Invalidating notifies one tier.
+        cache.far().insert('b', "bob");
+        cache.near().insert('b', "bill");
+        cache.invalidate(&'a');
+        cache.invalidate(&'b');
+        cache.sync();
+        assert_eq!(
+            *removed.lock(),
+            vec![
+                ("near", 'a', RemovalCause::Explicit),
+                ("near", 'b', RemovalCause::Explicit)
+            ]
+        );
+        assert_eq!(cache.get(&'b'), None);
+
+        removed.lock().clear();
+        cache.far().insert('c', "cindy");
+        cache.invalidate(&'c');
+        assert_eq!(*removed.lock(), vec![("far", 'c', RemovalCause::Explicit)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot have eviction_listener_batched")]
+    fn batched_listener_on_near_tier() {
+        let _ = TieredCache::<char, &str>::from_builders(
+            Cache::builder().eviction_listener_batched(10, |_batch| ()),
+            Cache::builder(),
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    common::thread_pool::{PoolName, ThreadPool, ThreadPoolRegistry},
+    notification::DropContext,
+};
+
+use crossbeam_channel::Sender;
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+};
+
+/// Drops the values of a cache in the context given to `drop_values_on`. Every
+/// `ValueEntry` of the cache holds an `Arc` to it, so it lives until the last
+/// value has been dropped.
+pub(crate) enum ValueDropper<V> {
+    // The `fn` moves the value to a job of the pool. It is instantiated in `new`,
+    // where `V: Send + 'static`, so that `send` can be called from the `Drop` of
+    // `ValueEntry`, which cannot have those bounds.
+    Housekeeper(Arc<ThreadPool>, fn(&ThreadPool, V)),
+    Worker(Sender<V>),
+}
+
+impl<V: Send + 'static> ValueDropper<V> {
+    /// Returns `None` for `DropContext::Inline`, as the values are dropped in
+    /// place.
+    pub(crate) fn new(context: DropContext) -> Option<Arc<Self>> {
+        match context {
+            DropContext::Inline => None,
+            DropContext::EvictionListener => Some(Arc::new(Self::Housekeeper(
+                ThreadPoolRegistry::acquire_pool(PoolName::Housekeeper),
+                |pool, value| {
+                    pool.pool.execute(move || drop_value(value));
+                },
+            ))),
+            DropContext::Worker => {
+                let (tx, rx) = crossbeam_channel::unbounded::<V>();
+                // The loop ends when the last value has been dropped, as it drops
+                // the last `Sender`.
+                thread::Builder::new()
+                    .name("moka-value-dropper".to_string())
+                    .spawn(move || {
+                        for value in rx {
+                            drop_value(value);
+                        }
+                    })
+                    .expect("Failed to spawn the value dropper thread");
+                Some(Arc::new(Self::Worker(tx)))
+            }
+        }
+    }
+}
+
+impl<V> ValueDropper<V> {
+    pub(crate) fn send(&self, value: V) {
+        match self {
+            Self::Housekeeper(pool, execute) => execute(pool, value),
+            Self::Worker(tx) => {
+                // The worker only stops when all senders are gone, but drop the
+                // value here rather than leaking it if it ever did.
+                if let Err(e) = tx.send(value) {
+                    drop_value(e.into_inner());
+                }
+            }
+        }
+    }
+}
+
+impl<V> Drop for ValueDropper<V> {
+    fn drop(&mut self) {
+        if let Self::Housekeeper(pool, _) = self {
+            ThreadPoolRegistry::release_pool(pool);
+        }
+    }
+}
+
+/// Swallows a panic raised by the `Drop` of the value, so that it does not take
+/// down the thread.
+fn drop_value<V>(value: V) {
+    let _ = catch_unwind(AssertUnwindSafe(move || drop(value)));
+}
@@ -1,18 +1,111 @@
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::{
     any::{Any, TypeId},
+    borrow::Borrow,
+    collections::HashMap,
     hash::{BuildHasher, Hash},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 type ErrorObject = Arc<dyn Any + Send + Sync + 'static>;
 type WaiterValue<V> = Option<Result<V, ErrorObject>>;
 type Waiter<V> = Arc<RwLock<WaiterValue<V>>>;
 
+/// The number of times a call retries the load after the init closure of
+/// another call has panicked.
+const MAX_RETRIES: usize = 200;
+
+/// The maximum number of keys whose consecutive load failures are tracked. When
+/// a new key fails beyond it, the half of the keys that have not failed for the
+/// longest time are forgotten.
+pub(crate) const MAX_TRACKED_LOAD_FAILURES: usize = 4096;
+
+/// Tells whether a call of `get_or_insert_with_role` has run its `init` closure.
+///
+/// Returned by the `get_or_insert_with_role` method of `Cache` and
+/// `SegmentedCache`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadRole {
+    /// This call has run its `init` closure and inserted the value.
+    Leader,
+    /// This call has not run its `init` closure. It has either found the value in
+    /// the cache, or waited for another call to load it.
+    Follower,
+}
+
 pub(crate) enum InitResult<V, E> {
     Initialized(V),
     ReadExisting(V),
     InitErr(Arc<E>),
+    // The init closures of the other calls have kept panicking.
+    OwnerPanicked,
+}
+
+/// Ensures that a waiter is not left behind when the thread resolving its init
+/// closure panics.
+///
+/// Unless a value has been set, dropping the guard (e.g. while unwinding) sets
+/// the waiter value to `None`, which tells the other threads waiting on the
+/// waiter to retry from the beginning, and removes the waiter so that one of them
+/// can insert a new waiter.
+struct WaiterGuard<'a, K, V, S>
+// NOTE: We usually do not attach trait bounds to here at the struct definition, but
+// the Drop trait requires these bounds here.
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    is_waiter_value_set: bool,
+    key: &'a Arc<K>,
+    type_id: TypeId,
+    value_initializer: &'a ValueInitializer<K, V, S>,
+    write_lock: &'a mut WaiterValue<V>,
+}
+
+impl<'a, K, V, S> WaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    fn new(
+        key: &'a Arc<K>,
+        type_id: TypeId,
+        value_initializer: &'a ValueInitializer<K, V, S>,
+        write_lock: &'a mut WaiterValue<V>,
+    ) -> Self {
+        Self {
+            is_waiter_value_set: false,
+            key,
+            type_id,
+            value_initializer,
+            write_lock,
+        }
+    }
+
+    fn set_waiter_value(&mut self, v: WaiterValue<V>) {
+        *self.write_lock = v;
+        self.is_waiter_value_set = true;
+    }
+}
+
+impl<'a, K, V, S> Drop for WaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    fn drop(&mut self) {
+        if !self.is_waiter_value_set {
+            // The init closure (or cloning its value) has panicked.
+            *self.write_lock = None;
+            self.value_initializer.remove_waiter(self.key, self.type_id);
+        }
+    }
 }
 
 pub(crate) struct ValueInitializer<K, V, S> {
@@ -21,32 +114,142 @@ pub(crate) struct ValueInitializer<K, V, S> {
     // we can always downcast the trait object ErrorObject (in Waiter<V>) into
     // its concrete type.
     waiters: moka_cht::SegmentedHashMap<(Arc<K>, TypeId), Waiter<V>, S>,
+    // The numbers of consecutive failures of try_init_or_read(). A key is added on
+    // its first failure, and removed on its next success, when it is invalidated,
+    // or when it is aged out by MAX_TRACKED_LOAD_FAILURES.
+    load_failures: Mutex<LoadFailures<K, S>>,
+    // The number of keys in load_failures. Updated under its lock, and read
+    // without it, so that the successful loads and the invalidations will not
+    // take the lock while no failure is tracked.
+    num_load_failures: AtomicUsize,
+}
+
+struct LoadFailures<K, S> {
+    // The failure count and the sequence number of the last failure of each key.
+    counts: HashMap<Arc<K>, (u32, u64), S>,
+    next_seq: u64,
+}
+
+impl<K, S> LoadFailures<K, S>
+where
+    Arc<K>: Eq + Hash,
+    S: BuildHasher,
+{
+    fn record(&mut self, key: &Arc<K>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some((count, last_seq)) = self.counts.get_mut(key) {
+            *count = count.saturating_add(1);
+            *last_seq = seq;
+            return;
+        }
+        if self.counts.len() >= MAX_TRACKED_LOAD_FAILURES {
+            self.forget_older_half();
+        }
+        self.counts.insert(Arc::clone(key), (1, seq));
+    }
+
+    // Forgets the keys whose last failures are older than the median, so that the
+    // cost of aging is amortized over the next failures of new keys.
+    fn forget_older_half(&mut self) {
+        let mut seqs = self.counts.values().map(|(_, s)| *s).collect::<Vec<_>>();
+        let mid = seqs.len() / 2;
+        let (_, median, _) = seqs.select_nth_unstable(mid);
+        let median = *median;
+        self.counts.retain(|_, (_, s)| *s >= median);
+    }
 }
 
 impl<K, V, S> ValueInitializer<K, V, S>
 where
     Arc<K>: Eq + Hash,
     V: Clone,
-    S: BuildHasher,
+    S: BuildHasher + Clone,
 {
     pub(crate) fn with_hasher(hasher: S) -> Self {
         Self {
-            waiters: moka_cht::SegmentedHashMap::with_num_segments_and_hasher(16, hasher),
+            waiters: moka_cht::SegmentedHashMap::with_num_segments_and_hasher(16, hasher.clone()),
+            load_failures: Mutex::new(LoadFailures {
+                counts: HashMap::with_hasher(hasher),
+                next_seq: 0,
+            }),
+            num_load_failures: AtomicUsize::default(),
+        }
+    }
+
+    pub(crate) fn load_failure_count<Q>(&self, key: &Q) -> u32
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.num_load_failures.load(Ordering::Acquire) == 0 {
+            return 0;
+        }
+        let failures = self.load_failures.lock();
+        failures
+            .counts
+            .get(key)
+            .map(|(n, _)| *n)
+            .unwrap_or_default()
+    }
+
+    /// Forgets the consecutive load failures of the key.
+    pub(crate) fn forget_load_failures<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.num_load_failures.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut failures = self.load_failures.lock();
+        if failures.counts.remove(key).is_some() {
+            self.num_load_failures
+                .store(failures.counts.len(), Ordering::Release);
         }
     }
 
+    /// Forgets the consecutive load failures of all keys.
+    pub(crate) fn forget_all_load_failures(&self) {
+        if self.num_load_failures.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut failures = self.load_failures.lock();
+        failures.counts.clear();
+        self.num_load_failures.store(0, Ordering::Release);
+    }
+
+    fn record_load_failure(&self, key: &Arc<K>) {
+        let mut failures = self.load_failures.lock();
+        failures.record(key);
+        self.num_load_failures
+            .store(failures.counts.len(), Ordering::Release);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn tracked_load_failures(&self) -> usize {
+        self.load_failures.lock().counts.len()
+    }
+
     /// # Panics
     /// Panics if the `init` future has been panicked.
     pub(crate) fn init_or_read(&self, key: Arc<K>, init: impl FnOnce() -> V) -> InitResult<V, ()> {
         // This closure will be called after the init closure has returned a value.
         // It will convert the returned value (from init) into an InitResult.
-        let post_init = |_key, value: V, lock: &mut WaiterValue<V>| {
-            *lock = Some(Ok(value.clone()));
+        let post_init = |_key, value: V, mut guard: WaiterGuard<'_, K, V, S>| {
+            guard.set_waiter_value(Some(Ok(value.clone())));
             InitResult::Initialized(value)
         };
 
         let type_id = TypeId::of::<()>();
-        self.do_try_init(&key, type_id, init, post_init)
+        match self.do_try_init(&key, type_id, init, post_init) {
+            InitResult::OwnerPanicked => panic!(
+                "Too many retries. Tried to read the return value from the `init` \
+            closure but failed {} times. Maybe the `init` kept panicking?",
+                MAX_RETRIES
+            ),
+            result => result,
+        }
     }
 
     /// # Panics
@@ -60,18 +263,21 @@ where
 
         // This closure will be called after the init closure has returned a value.
         // It will convert the returned value (from init) into an InitResult.
-        let post_init = |key, value: Result<V, E>, lock: &mut WaiterValue<V>| match value {
-            Ok(value) => {
-                *lock = Some(Ok(value.clone()));
-                InitResult::Initialized(value)
-            }
-            Err(e) => {
-                let err: ErrorObject = Arc::new(e);
-                *lock = Some(Err(Arc::clone(&err)));
-                self.remove_waiter(key, type_id);
-                InitResult::InitErr(err.downcast().unwrap())
-            }
-        };
+        let post_init =
+            |key: &Arc<K>, value: Result<V, E>, mut guard: WaiterGuard<'_, K, V, S>| match value {
+                Ok(value) => {
+                    guard.set_waiter_value(Some(Ok(value.clone())));
+                    self.forget_load_failures(key);
+                    InitResult::Initialized(value)
+                }
+                Err(e) => {
+                    let err: ErrorObject = Arc::new(e);
+                    guard.set_waiter_value(Some(Err(Arc::clone(&err))));
+                    self.record_load_failure(key);
+                    self.remove_waiter(key, type_id);
+                    InitResult::InitErr(err.downcast().unwrap())
+                }
+            };
 
         self.do_try_init(&key, type_id, init, post_init)
     }
@@ -87,13 +293,11 @@ where
     ) -> InitResult<V, E>
     where
         F: FnOnce() -> O,
-        C: FnMut(&'a Arc<K>, O, &mut WaiterValue<V>) -> InitResult<V, E>,
+        C: FnMut(&'a Arc<K>, O, WaiterGuard<'_, K, V, S>) -> InitResult<V, E>,
         E: Send + Sync + 'static,
     {
-        use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
         use InitResult::*;
 
-        const MAX_RETRIES: usize = 200;
         let mut retries = 0;
 
         loop {
@@ -102,19 +306,15 @@ where
 
             match self.try_insert_waiter(key, type_id, &waiter) {
                 None => {
-                    // Our waiter was inserted. Let's resolve the init future.
-                    // Catching panic is safe here as we do not try to resolve the future again.
-                    match catch_unwind(AssertUnwindSafe(init)) {
-                        // Resolved.
-                        Ok(value) => return post_init(key, value, &mut lock),
-                        // Panicked.
-                        Err(payload) => {
-                            *lock = None;
-                            // Remove the waiter so that others can retry.
-                            self.remove_waiter(key, type_id);
-                            resume_unwind(payload);
-                        } // The write lock will be unlocked here.
-                    }
+                    // Our waiter was inserted. Let's resolve the init closure.
+                    //
+                    // If the init closure panics, the guard will be dropped while
+                    // unwinding, and it will reset our waiter and remove it so that
+                    // others can retry. The write lock is unlocked after that, as it
+                    // was declared before the guard.
+                    let waiter_guard = WaiterGuard::new(key, type_id, self, &mut lock);
+                    let value = init();
+                    return post_init(key, value, waiter_guard);
                 }
                 Some(res) => {
                     // Somebody else's waiter already exists. Drop our write lock and wait
@@ -130,11 +330,7 @@ where
                                 // Retry from the beginning.
                                 continue;
                             } else {
-                                panic!(
-                                    "Too many retries. Tried to read the return value from the `init` \
-                                closure but failed {} times. Maybe the `init` kept panicking?",
-                                    retries
-                                );
+                                return OwnerPanicked;
                             }
                         }
                     }
@@ -143,6 +339,13 @@ where
         }
     }
 
+    /// Returns true if a load of the key by `init_or_read` is in progress.
+    pub(crate) fn is_loading(&self, key: &Arc<K>) -> bool {
+        self.waiters
+            .get(&(Arc::clone(key), TypeId::of::<()>()))
+            .is_some()
+    }
+
     #[inline]
     pub(crate) fn remove_waiter(&self, key: &Arc<K>, type_id: TypeId) {
         let key = Arc::clone(key);
@@ -0,0 +1,212 @@
+//! Helpers to write deterministic tests against [`sync::Cache`][sync-cache].
+//!
+//! To use this module, enable a crate feature called "test-util".
+//!
+//! A cache applies the inserts, the evictions and the expirations in its
+//! maintenance, which runs in the background or when enough operations have been
+//! recorded. So a test that asserts on the entries of a cache must run the
+//! pending maintenance first, and a test of the expirations needs to control the
+//! time. The helpers in this module do both:
+//!
+//! - [`flush`](./fn.flush.html) runs the maintenance until there is nothing left
+//!   to do.
+//! - [`use_mock_clock`](./fn.use_mock_clock.html) and
+//!   [`advance_clock`](./fn.advance_clock.html) make the expirations of the
+//!   cache follow a clock that only advances when the test says so.
+//! - [`assert_contains_exactly`](./fn.assert_contains_exactly.html) flushes the
+//!   cache and checks its keys, listing the missing and the unexpected keys on
+//!   failure.
+//!
+//! # Example
+//!
+//! ```rust
+//! use moka::{sync::Cache, test_util};
+//! use std::time::Duration;
+//!
+//! let cache = Cache::builder()
+//!     .max_capacity(100)
+//!     .time_to_live(Duration::from_secs(30))
+//!     .build();
+//! // Install the mock clock before inserting any entry.
+//! test_util::use_mock_clock(&cache);
+//!
+//! cache.insert(1, "one");
+//! test_util::advance_clock(&cache, Duration::from_secs(20));
+//! cache.insert(2, "two");
+//! test_util::assert_contains_exactly(&cache, vec![1, 2]);
+//!
+//! // The entry 1 expires 30 seconds after it was inserted.
+//! test_util::advance_clock(&cache, Duration::from_secs(10));
+//! test_util::assert_contains_exactly(&cache, vec![2]);
+//! ```
+//!
+//! [sync-cache]: ../sync/struct.Cache.html
+
+use crate::sync::Cache;
+
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    hash::{BuildHasher, Hash},
+    time::Duration,
+};
+
+/// Runs the pending maintenance of the cache until it is quiescent.
+///
+/// Unlike [`ConcurrentCacheExt::sync`][sync-method], which runs a bounded
+/// amount of maintenance, this keeps going until all recorded reads and writes
+/// are applied, the entries over the max capacity and the expired entries are
+/// evicted, and the invalidation predicates have been applied. So after it
+/// returns, the entry count and the entries seen by `for_each_entry` reflect
+/// all operations made before the call.
+///
+/// It returns immediately while the cache is frozen by a `FrozenView`.
+///
+/// [sync-method]: ../sync/trait.ConcurrentCacheExt.html#tymethod.sync
+pub fn flush<K, V, S>(cache: &Cache<K, V, S>)
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    cache.run_until_quiescent();
+}
+
+/// Makes the cache use a mock clock for the expirations. The mock clock starts
+/// at zero and only advances by [`advance_clock`](./fn.advance_clock.html).
+///
+/// Call this right after building the cache, before inserting any entry. The
+/// entries inserted before the call have timestamps of the real clock, so their
+/// expirations will not follow the mock clock.
+pub fn use_mock_clock<K, V, S>(cache: &Cache<K, V, S>)
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    cache.use_mock_clock();
+}
+
+/// Advances the mock clock of the cache by the duration, and runs the
+/// maintenance by [`flush`](./fn.flush.html) so that the entries expired by the
+/// advance are evicted.
+///
+/// The cache is also flushed before advancing the clock, because the recorded
+/// reads and writes get their timestamps when they are applied by the
+/// maintenance. This way they are timestamped with the time before the advance.
+///
+/// # Panics
+///
+/// Panics if [`use_mock_clock`](./fn.use_mock_clock.html) has not been called
+/// for the cache.
+pub fn advance_clock<K, V, S>(cache: &Cache<K, V, S>, duration: Duration)
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    flush(cache);
+    if !cache.advance_mock_clock(duration) {
+        panic!("advance_clock requires a mock clock installed by use_mock_clock");
+    }
+    flush(cache);
+}
+
+/// Flushes the cache by [`flush`](./fn.flush.html), and asserts that the cache
+/// contains exactly the given keys.
+///
+/// # Panics
+///
+/// Panics if the keys of the cache differ from the given keys. The message lists
+/// the keys missing from the cache and the keys the cache has but were not
+/// expected, each sorted by their `Debug` representation.
+pub fn assert_contains_exactly<K, V, S>(cache: &Cache<K, V, S>, keys: impl IntoIterator<Item = K>)
+where
+    K: Debug + Clone + Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    flush(cache);
+
+    let expected = keys.into_iter().collect::<HashSet<_>>();
+    let mut actual = HashSet::new();
+    cache.for_each_entry(|k, _v| {
+        actual.insert(k.clone());
+    });
+
+    let missing = sorted_debug(expected.difference(&actual));
+    let unexpected = sorted_debug(actual.difference(&expected));
+    if !missing.is_empty() || !unexpected.is_empty() {
+        panic!(
+            "the cache does not contain exactly the expected keys\n  \
+             missing:    [{}]\n  \
+             unexpected: [{}]",
+            missing.join(", "),
+            unexpected.join(", ")
+        );
+    }
+}
+
+fn sorted_debug<'a, K: Debug + 'a>(keys: impl Iterator<Item = &'a K>) -> Vec<String> {
+    let mut keys = keys.map(|k| format!("{:?}", k)).collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{advance_clock, assert_contains_exactly, flush, use_mock_clock};
+    use crate::sync::Cache;
+    use std::time::Duration;
+
+    #[test]
+    fn flush_applies_evictions() {
+        let cache = Cache::new(10);
+        for i in 0..2000u32 {
+            cache.insert(i, i);
+        }
+        flush(&cache);
+        assert!(cache.estimated_entry_count() <= 10);
+
+        let mut count = 0;
+        cache.for_each_entry(|_k, _v| count += 1);
+        assert_eq!(count as u64, cache.estimated_entry_count());
+    }
+
+    #[test]
+    fn advance_clock_expires_entries() {
+        let cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+        use_mock_clock(&cache);
+
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        advance_clock(&cache, Duration::from_secs(5));
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        advance_clock(&cache, Duration::from_secs(5));
+        assert_contains_exactly(&cache, vec!["a"]);
+
+        advance_clock(&cache, Duration::from_secs(5));
+        assert_contains_exactly(&cache, vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "requires a mock clock")]
+    fn advance_clock_without_mock_clock() {
+        let cache: Cache<u32, u32> = Cache::new(100);
+        advance_clock(&cache, Duration::from_secs(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "missing:    [3]\n  unexpected: [0, 1]")]
+    fn assert_contains_exactly_reports_diff() {
+        let cache = Cache::new(100);
+        for i in 0..3u32 {
+            cache.insert(i, i);
+        }
+        assert_contains_exactly(&cache, vec![2, 3]);
+    }
+}
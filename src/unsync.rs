@@ -62,6 +62,17 @@ struct EntryInfo<K> {
 pub(crate) struct ValueEntry<K, V> {
     pub(crate) value: V,
     info: EntryInfo<K>,
+    #[cfg(feature = "zeroize")]
+    zeroizer: Option<fn(&mut V)>,
+}
+
+#[cfg(feature = "zeroize")]
+impl<K, V> Drop for ValueEntry<K, V> {
+    fn drop(&mut self) {
+        if let Some(zeroize) = self.zeroizer {
+            zeroize(&mut self.value);
+        }
+    }
 }
 
 impl<K, V> ValueEntry<K, V> {
@@ -73,9 +84,16 @@ impl<K, V> ValueEntry<K, V> {
                 write_order_q_node: None,
                 policy_weight,
             },
+            #[cfg(feature = "zeroize")]
+            zeroizer: None,
         }
     }
 
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn set_zeroizer(&mut self, zeroizer: Option<fn(&mut V)>) {
+        self.zeroizer = zeroizer;
+    }
+
     #[inline]
     pub(crate) fn replace_deq_nodes_with(&mut self, mut other: Self) {
         self.info.access_order_q_node = other.info.access_order_q_node.take();
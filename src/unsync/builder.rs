@@ -45,6 +45,9 @@ pub struct CacheBuilder<K, V, C> {
     weigher: Option<Weigher<K, V>>,
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
+    maintenance_budget: Option<usize>,
+    #[cfg(feature = "zeroize")]
+    value_zeroizer: Option<fn(&mut V)>,
     cache_type: PhantomData<C>,
 }
 
@@ -59,6 +62,9 @@ where
             weigher: None,
             time_to_live: None,
             time_to_idle: None,
+            maintenance_budget: None,
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: None,
             cache_type: Default::default(),
         }
     }
@@ -84,10 +90,13 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
     pub fn build(self) -> Cache<K, V, RandomState> {
         let build_hasher = RandomState::default();
         builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        Cache::with_everything(
+        let cache = Cache::with_everything(
             self.max_capacity,
             self.initial_capacity,
             build_hasher,
@@ -95,6 +104,10 @@ where
             self.time_to_live,
             self.time_to_idle,
         )
+        .with_maintenance_budget(self.maintenance_budget);
+        #[cfg(feature = "zeroize")]
+        let cache = cache.with_value_zeroizer(self.value_zeroizer);
+        cache
     }
 
     /// Builds a `Cache<K, V, S>`, with the given `hasher`.
@@ -104,12 +117,15 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if `time_to_idle` is longer than `time_to_live`, as such a
+    /// `time_to_idle` would never expire an entry.
     pub fn build_with_hasher<S>(self, hasher: S) -> Cache<K, V, S>
     where
         S: BuildHasher + Clone,
     {
         builder_utils::ensure_expirations_or_panic(self.time_to_live, self.time_to_idle);
-        Cache::with_everything(
+        let cache = Cache::with_everything(
             self.max_capacity,
             self.initial_capacity,
             hasher,
@@ -117,6 +133,10 @@ where
             self.time_to_live,
             self.time_to_idle,
         )
+        .with_maintenance_budget(self.maintenance_budget);
+        #[cfg(feature = "zeroize")]
+        let cache = cache.with_value_zeroizer(self.value_zeroizer);
+        cache
     }
 }
 
@@ -181,6 +201,54 @@ impl<K, V, C> CacheBuilder<K, V, C> {
             ..self
         }
     }
+
+    /// Sets the maximum number of entries a single `get`, `insert` or
+    /// `invalidate` call removes while catching up on the maintenance of the
+    /// cache, i.e. removing the expired entries and evicting the entries over
+    /// the capacity. Defaults to 100.
+    ///
+    /// The rest is left to the next calls, so a call after a long idle period
+    /// does not pay for the whole backlog. A smaller budget bounds the latency
+    /// of each call more tightly, but the cache may hold the expired entries, or
+    /// exceed its capacity, for longer. Call [`Cache::prepare`][prepare] to
+    /// catch up all at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_entries` is zero.
+    ///
+    /// [prepare]: ./struct.Cache.html#method.prepare
+    pub fn maintenance_budget(self, max_entries: usize) -> Self {
+        assert!(
+            max_entries > 0,
+            "maintenance_budget must be greater than zero"
+        );
+        Self {
+            maintenance_budget: Some(max_entries),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<K, V, C> CacheBuilder<K, V, C>
+where
+    V: zeroize::Zeroize,
+{
+    /// Makes the cache zeroize values when it drops them, i.e. on eviction,
+    /// expiration, invalidation, replacement by `insert`, and when the cache
+    /// itself is dropped.
+    ///
+    /// References returned by `get` are not affected as they borrow the value
+    /// stored in the cache.
+    ///
+    /// Requires the `zeroize` crate feature.
+    pub fn zeroize_values_on_removal(self) -> Self {
+        Self {
+            value_zeroizer: Some(<V as zeroize::Zeroize>::zeroize),
+            ..self
+        }
+    }
 }
 
 #[cfg(test)]
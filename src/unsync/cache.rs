@@ -1,6 +1,6 @@
 use super::{deques::Deques, AccessTime, CacheBuilder, KeyDate, KeyHashDate, ValueEntry, Weigher};
 use crate::common::{
-    self,
+    self, builder_utils,
     deque::{CacheRegion, DeqNode, Deque},
     frequency_sketch::FrequencySketch,
     time::{CheckedTimeOps, Clock, Instant},
@@ -10,7 +10,7 @@ use smallvec::SmallVec;
 use std::{
     borrow::Borrow,
     collections::{hash_map::RandomState, HashMap},
-    hash::{BuildHasher, Hash, Hasher},
+    hash::{BuildHasher, Hash},
     ptr::NonNull,
     rc::Rc,
     time::Duration,
@@ -167,6 +167,9 @@ pub struct Cache<K, V, S = RandomState> {
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
     expiration_clock: Option<Clock>,
+    maintenance_budget: usize,
+    #[cfg(feature = "zeroize")]
+    value_zeroizer: Option<fn(&mut V)>,
 }
 
 impl<K, V> Cache<K, V, RandomState>
@@ -191,6 +194,41 @@ where
     pub fn builder() -> CacheBuilder<K, V, Cache<K, V, RandomState>> {
         CacheBuilder::default()
     }
+
+    /// Constructs a new unbounded `Cache<K, V>` whose entries will expire after the
+    /// given `time_to_live` past from `insert`.
+    ///
+    /// This is meant for a memoization table that only needs to forget old
+    /// results. As the cache has no capacity limit, it does not maintain the
+    /// popularity estimator or the access-order queue used by the eviction policy;
+    /// only the hash map and the write-order queue are kept. Expired entries are
+    /// removed lazily by `get`, `insert` and `invalidate`, or explicitly by
+    /// [`evict_expired`](#method.evict_expired).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time_to_live` is longer than 1000 years.
+    pub fn with_ttl(time_to_live: Duration) -> Self {
+        builder_utils::ensure_expirations_or_panic(Some(time_to_live), None);
+        let build_hasher = RandomState::default();
+        Self::with_everything(None, None, build_hasher, None, Some(time_to_live), None)
+    }
+
+    /// Constructs a new unbounded `Cache<K, V>` whose entries will expire after the
+    /// given `time_to_idle` past from `get` or `insert`.
+    ///
+    /// Like [`with_ttl`](#method.with_ttl), the cache does not maintain the
+    /// popularity estimator. It does maintain the access-order queue, as it is
+    /// needed to find idle entries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time_to_idle` is longer than 1000 years.
+    pub fn with_tti(time_to_idle: Duration) -> Self {
+        builder_utils::ensure_expirations_or_panic(None, Some(time_to_idle));
+        let build_hasher = RandomState::default();
+        Self::with_everything(None, None, build_hasher, None, None, Some(time_to_idle))
+    }
 }
 
 //
@@ -215,7 +253,7 @@ where
         );
 
         Self {
-            max_capacity: max_capacity.map(|n| n as u64),
+            max_capacity,
             entry_count: 0,
             weighted_size: 0,
             cache,
@@ -226,6 +264,24 @@ where
             time_to_live,
             time_to_idle,
             expiration_clock: None,
+            maintenance_budget: EVICTION_BATCH_SIZE,
+            #[cfg(feature = "zeroize")]
+            value_zeroizer: None,
+        }
+    }
+
+    pub(crate) fn with_maintenance_budget(self, budget: Option<usize>) -> Self {
+        Self {
+            maintenance_budget: budget.unwrap_or(EVICTION_BATCH_SIZE),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn with_value_zeroizer(self, zeroizer: Option<fn(&mut V)>) -> Self {
+        Self {
+            value_zeroizer: zeroizer,
+            ..self
         }
     }
 
@@ -240,9 +296,10 @@ where
         Rc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let timestamp = self.evict_expired_if_needed();
-        self.evict_lru_entries();
-        self.frequency_sketch.increment(self.hash(key));
+        let timestamp = self.run_maintenance();
+        if self.max_capacity.is_some() {
+            self.frequency_sketch.increment(self.hash(key));
+        }
 
         match (self.cache.get_mut(key), timestamp, &mut self.deques) {
             // Value not found.
@@ -270,11 +327,13 @@ where
     ///
     /// If the cache has this key present, the value is updated.
     pub fn insert(&mut self, key: K, value: V) {
-        let timestamp = self.evict_expired_if_needed();
-        self.evict_lru_entries();
+        let timestamp = self.run_maintenance();
         let policy_weight = weigh(&mut self.weigher, &key, &value);
         let key = Rc::new(key);
-        let entry = ValueEntry::new(value, policy_weight);
+        #[allow(unused_mut)]
+        let mut entry = ValueEntry::new(value, policy_weight);
+        #[cfg(feature = "zeroize")]
+        entry.set_zeroizer(self.value_zeroizer);
 
         if let Some(old_entry) = self.cache.insert(Rc::clone(&key), entry) {
             self.handle_update(key, timestamp, policy_weight, old_entry);
@@ -293,14 +352,36 @@ where
         Rc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.evict_expired_if_needed();
-        self.evict_lru_entries();
+        self.invalidate_present(key);
+    }
+
+    /// Discards any cached value for the key, and returns `true` if the key had a
+    /// value that had not expired.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate_present<Q>(&mut self, key: &Q) -> bool
+    where
+        Rc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let timestamp = self.run_maintenance();
 
         if let Some(mut entry) = self.cache.remove(key) {
             let weight = entry.policy_weight();
             self.deques.unlink_ao(&mut entry);
             Deques::unlink_wo(&mut self.deques.write_order, &mut entry);
+            self.entry_count -= 1;
             self.saturating_sub_from_total_weight(weight as u64);
+            match timestamp {
+                Some(ts) => {
+                    !(Self::is_expired_entry_wo(&self.time_to_live, &entry, ts)
+                        || Self::is_expired_entry_ao(&self.time_to_idle, &entry, ts))
+                }
+                None => true,
+            }
+        } else {
+            false
         }
     }
 
@@ -312,6 +393,7 @@ where
     pub fn invalidate_all(&mut self) {
         self.cache.clear();
         self.deques.clear();
+        self.entry_count = 0;
         self.weighted_size = 0;
     }
 
@@ -324,7 +406,7 @@ where
     /// Like the `invalidate` method, this method does not clear the historic
     /// popularity estimator of keys so that it retains the client activities of
     /// trying to retrieve an item.
-
+    //
     // We need this #[allow(...)] to avoid a false Clippy warning about needless
     // collect to create keys_to_invalidate.
     // clippy 0.1.52 (9a1dfd2dc5c 2021-04-30) in Rust 1.52.0-beta.7
@@ -343,6 +425,7 @@ where
             .map(|(key, _)| Rc::clone(key))
             .collect::<Vec<_>>();
 
+        let mut invalidated_count = 0u64;
         let mut invalidated = 0u64;
 
         keys_to_invalidate.into_iter().for_each(|k| {
@@ -350,17 +433,120 @@ where
                 let weight = entry.policy_weight();
                 deques.unlink_ao(&mut entry);
                 Deques::unlink_wo(&mut deques.write_order, &mut entry);
-                invalidated = invalidated.saturating_sub(weight as u64);
+                invalidated_count += 1;
+                invalidated = invalidated.saturating_add(weight as u64);
             }
         });
+        self.entry_count -= invalidated_count;
         self.saturating_sub_from_total_weight(invalidated);
     }
 
+    /// Removes all entries that have expired by `time_to_live` or `time_to_idle`.
+    ///
+    /// Expired entries are never returned by `get`, and they are also removed
+    /// little by little when the cache is accessed. Call this method to remove all
+    /// of them at once, e.g. to release memory after a burst of inserts.
+    pub fn evict_expired(&mut self) {
+        if self.has_expiry() {
+            let now = self.current_time_from_expiration_clock();
+            self.evict_expired_entries(now, usize::MAX);
+        }
+    }
+
+    /// Catches up on the maintenance of this cache, so that the next operations
+    /// do not pay for it.
+    ///
+    /// `get`, `insert` and `invalidate` remove the expired entries and evict the
+    /// entries over the capacity little by little, up to the
+    /// [`maintenance_budget`][maintenance-budget] per call. So after a long
+    /// idle period, the backlog of expired entries is spread over the following
+    /// operations. Call this method at a moment of your own, e.g. at startup or
+    /// when the application is idle, to remove all of them at once, and to
+    /// allocate the popularity estimator if it is due.
+    ///
+    /// [maintenance-budget]: ./struct.CacheBuilder.html#method.maintenance_budget
+    pub fn prepare(&mut self) {
+        self.evict_expired();
+        self.evict_lru_entries(usize::MAX);
+        if self.should_enable_frequency_sketch() {
+            self.enable_frequency_sketch();
+        }
+    }
+
+    /// Returns the number of entries in this cache.
+    ///
+    /// As the cache has no background maintenance, this method first removes the
+    /// expired entries found at the front of the expiration queues, up to a small
+    /// batch per call to keep the latency bounded. So after a burst of expired
+    /// entries, the count may include some of them until this cache has been
+    /// accessed a few more times. Call [`evict_expired`](#method.evict_expired)
+    /// first to get the exact count.
+    pub fn entry_count(&mut self) -> u64 {
+        self.evict_expired_if_needed();
+        self.entry_count
+    }
+
+    /// Returns the total weighted size of the entries in this cache.
+    ///
+    /// Like [`entry_count`](#method.entry_count), this method removes a batch of
+    /// expired entries first.
+    pub fn weighted_size(&mut self) -> u64 {
+        self.evict_expired_if_needed();
+        self.weighted_size
+    }
+
+    /// Returns an iterator over the key-value pairs in this cache, in arbitrary
+    /// order.
+    ///
+    /// Like [`entry_count`](#method.entry_count), this method removes a batch of
+    /// expired entries first. The iterator skips the expired entries that are
+    /// still left in the cache. Unlike `get`, iterating does not count as an
+    /// access to the entries, so it neither resets their time-to-idle nor
+    /// affects the eviction order.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::unsync::Cache;
+    ///
+    /// let mut cache = Cache::new(100);
+    /// cache.insert("a", 1);
+    /// cache.insert("b", 2);
+    ///
+    /// let mut pairs = cache.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+    /// pairs.sort_unstable();
+    /// assert_eq!(pairs, vec![("a", 1), ("b", 2)]);
+    /// ```
+    pub fn iter(&mut self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        let now = self.evict_expired_if_needed();
+        let (ttl, tti) = (&self.time_to_live, &self.time_to_idle);
+        self.cache.iter().filter_map(move |(key, entry)| {
+            if let Some(now) = now {
+                if Self::is_expired_entry_wo(ttl, entry, now)
+                    || Self::is_expired_entry_ao(tti, entry, now)
+                {
+                    return None;
+                }
+            }
+            Some((key.as_ref(), &entry.value))
+        })
+    }
+
     /// Returns the `max_capacity` of this cache.
     pub fn max_capacity(&self) -> Option<usize> {
         self.max_capacity.map(|n| n as usize)
     }
 
+    /// Returns the weight that the weigher of this cache gives to the key-value
+    /// pair, or `None` if the cache was built without a weigher.
+    ///
+    /// Nothing is inserted. This lets you check whether a value is worth
+    /// inserting with the same weigher the cache uses. It takes `&mut self` as
+    /// the weigher of this cache is an `FnMut`.
+    pub fn weigh(&mut self, key: &K, value: &V) -> Option<u32> {
+        self.weigher.as_mut().map(|w| w(key, value))
+    }
+
     /// Returns the `time_to_live` of this cache.
     pub fn time_to_live(&self) -> Option<Duration> {
         self.time_to_live
@@ -386,9 +572,7 @@ where
         Rc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let mut hasher = self.build_hasher.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.build_hasher.hash_one(key)
     }
 
     #[inline]
@@ -396,17 +580,41 @@ where
         self.time_to_live.is_some() || self.time_to_idle.is_some()
     }
 
+    /// The access-order queue is only needed by the eviction policy of a bounded
+    /// cache and to find idle entries.
+    #[inline]
+    fn is_access_order_queue_enabled(&self) -> bool {
+        self.max_capacity.is_some() || self.time_to_idle.is_some()
+    }
+
     #[inline]
     fn evict_expired_if_needed(&mut self) -> Option<Instant> {
+        self.evict_expired_within_budget().0
+    }
+
+    /// Removes the expired entries up to the maintenance budget. Returns the
+    /// current time if this cache has expiry, and the number of removed entries.
+    #[inline]
+    fn evict_expired_within_budget(&mut self) -> (Option<Instant>, usize) {
         if self.has_expiry() {
             let ts = self.current_time_from_expiration_clock();
-            self.evict_expired(ts);
-            Some(ts)
+            let removed = self.evict_expired_entries(ts, self.maintenance_budget);
+            (Some(ts), removed)
         } else {
-            None
+            (None, 0)
         }
     }
 
+    /// Removes the expired entries, and then evicts the entries over the
+    /// capacity, up to the maintenance budget in total. The rest is left to the
+    /// next calls.
+    #[inline]
+    fn run_maintenance(&mut self) -> Option<Instant> {
+        let (timestamp, removed) = self.evict_expired_within_budget();
+        self.evict_lru_entries(self.maintenance_budget - removed);
+        timestamp
+    }
+
     #[inline]
     fn current_time_from_expiration_clock(&self) -> Instant {
         if let Some(clock) = &self.expiration_clock {
@@ -491,12 +699,12 @@ where
 
     fn saturating_add_to_total_weight(&mut self, weight: u64) {
         let total = &mut self.weighted_size;
-        *total = total.saturating_add(weight as u64);
+        *total = total.saturating_add(weight);
     }
 
     fn saturating_sub_from_total_weight(&mut self, weight: u64) {
         let total = &mut self.weighted_size;
-        *total = total.saturating_sub(weight as u64);
+        *total = total.saturating_sub(weight);
     }
 
     #[inline]
@@ -508,17 +716,20 @@ where
         timestamp: Option<Instant>,
     ) {
         let has_free_space = self.has_enough_capacity(policy_weight, self.weighted_size);
+        let is_ao_enabled = self.is_access_order_queue_enabled();
         let (cache, deqs, freq) = (&mut self.cache, &mut self.deques, &self.frequency_sketch);
 
         if has_free_space {
             // Add the candidate to the deque.
             let key = Rc::clone(&key);
             let entry = cache.get_mut(&key).unwrap();
-            deqs.push_back_ao(
-                CacheRegion::MainProbation,
-                KeyHashDate::new(Rc::clone(&key), hash, timestamp),
-                entry,
-            );
+            if is_ao_enabled {
+                deqs.push_back_ao(
+                    CacheRegion::MainProbation,
+                    KeyHashDate::new(Rc::clone(&key), hash, timestamp),
+                    entry,
+                );
+            }
             if self.time_to_live.is_some() {
                 deqs.push_back_wo(KeyDate::new(key, timestamp), entry);
             }
@@ -680,11 +891,16 @@ where
         self.saturating_add_to_total_weight(policy_weight as u64);
     }
 
-    fn evict_expired(&mut self, now: Instant) {
+    /// Removes up to `batch_size` expired entries in total. Returns the number of
+    /// removed entries.
+    fn evict_expired_entries(&mut self, now: Instant, batch_size: usize) -> usize {
+        let mut removed = 0;
+
         if self.time_to_live.is_some() {
-            let (count, weight) = self.remove_expired_wo(EVICTION_BATCH_SIZE, now);
+            let (count, weight) = self.remove_expired_wo(batch_size, now);
             self.entry_count -= count;
             self.saturating_sub_from_total_weight(weight);
+            removed += count as usize;
         }
 
         if self.time_to_idle.is_some() {
@@ -698,27 +914,25 @@ where
                 &self.time_to_idle,
             );
 
-            let mut rm_expired_ao = |name, deq| {
-                Self::remove_expired_ao(
-                    name,
-                    deq,
-                    wo,
-                    cache,
-                    time_to_idle,
-                    EVICTION_BATCH_SIZE,
-                    now,
-                )
+            let mut rm_expired_ao = |name, deq, removed: usize| {
+                let batch_size = batch_size - removed;
+                let (count, weight) =
+                    Self::remove_expired_ao(name, deq, wo, cache, time_to_idle, batch_size, now);
+                (removed + count as usize, weight)
             };
 
-            let (count1, weight1) = rm_expired_ao("window", window);
-            let (count2, weight2) = rm_expired_ao("probation", probation);
-            let (count3, weight3) = rm_expired_ao("protected", protected);
+            let (removed1, weight1) = rm_expired_ao("window", window, removed);
+            let (removed2, weight2) = rm_expired_ao("probation", probation, removed1);
+            let (removed3, weight3) = rm_expired_ao("protected", protected, removed2);
 
-            self.entry_count -= count1 + count2 + count3;
+            self.entry_count -= (removed3 - removed) as u64;
             self.saturating_sub_from_total_weight(weight1);
             self.saturating_sub_from_total_weight(weight2);
             self.saturating_sub_from_total_weight(weight3);
+            removed = removed3;
         }
+
+        removed
     }
 
     // Returns (u64, u64) where (evicted_entry_count, evicted_policy_weight).
@@ -739,7 +953,7 @@ where
             let key = deq
                 .peek_front()
                 .and_then(|node| {
-                    if Self::is_expired_entry_ao(time_to_idle, &*node, now) {
+                    if Self::is_expired_entry_ao(time_to_idle, node, now) {
                         Some(Some(Rc::clone(&node.element.key)))
                     } else {
                         None
@@ -780,7 +994,7 @@ where
                 .write_order
                 .peek_front()
                 .and_then(|node| {
-                    if Self::is_expired_entry_wo(time_to_live, &*node, now) {
+                    if Self::is_expired_entry_wo(time_to_live, node, now) {
                         Some(Some(Rc::clone(&node.element.key)))
                     } else {
                         None
@@ -799,7 +1013,7 @@ where
                 self.deques.unlink_ao(&mut entry);
                 Deques::unlink_wo(&mut self.deques.write_order, &mut entry);
                 evicted_entry_count += 1;
-                evicted_policy_weight = evicted_policy_weight.saturating_sub(weight as u64);
+                evicted_policy_weight = evicted_policy_weight.saturating_add(weight as u64);
             } else {
                 self.deques.write_order.pop_front();
             }
@@ -808,8 +1022,10 @@ where
         (evicted_entry_count, evicted_policy_weight)
     }
 
+    /// Evicts up to `batch_size` entries over the capacity, least recently used
+    /// first.
     #[inline]
-    fn evict_lru_entries(&mut self) {
+    fn evict_lru_entries(&mut self, batch_size: usize) {
         const DEQ_NAME: &str = "probation";
 
         let weights_to_evict = self.weights_to_evict();
@@ -821,7 +1037,7 @@ where
             let (probation, wo, cache) =
                 (&mut deqs.probation, &mut deqs.write_order, &mut self.cache);
 
-            for _ in 0..EVICTION_BATCH_SIZE {
+            for _ in 0..batch_size {
                 if evicted_policy_weight >= weights_to_evict {
                     break;
                 }
@@ -954,6 +1170,19 @@ mod tests {
         assert_eq!(cache.get(&"b"), None);
     }
 
+    #[test]
+    fn weigh() {
+        let mut cache: Cache<&str, (&str, u32)> = Cache::new(100);
+        assert_eq!(cache.weigh(&"a", &("alice", 10)), None);
+
+        let mut cache = CacheBuilder::new(100)
+            .weigher(|_k: &&str, v: &(&str, u32)| v.1)
+            .build();
+        assert_eq!(cache.weigh(&"a", &("alice", 10)), Some(10));
+        // Nothing is inserted.
+        assert_eq!(cache.get(&"a"), None);
+    }
+
     #[test]
     fn size_aware_eviction() {
         let weigher = |_k: &&str, v: &(&str, u32)| v.1;
@@ -1087,6 +1316,27 @@ mod tests {
         assert_eq!(cache.cache.len(), 0);
     }
 
+    #[test]
+    fn invalidate_present() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.enable_frequency_sketch();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        cache.insert("a", "alice");
+        assert!(cache.invalidate_present(&"a"));
+        assert!(!cache.invalidate_present(&"a"));
+
+        // An expired entry is not present.
+        cache.insert("b", "bob");
+        mock.increment(Duration::from_secs(10));
+        assert!(!cache.invalidate_present(&"b"));
+        assert!(cache.cache.is_empty());
+    }
+
     #[test]
     fn time_to_live() {
         let mut cache = CacheBuilder::new(100)
@@ -1131,6 +1381,32 @@ mod tests {
         assert!(cache.cache.is_empty());
     }
 
+    #[test]
+    fn time_to_live_with_weigher() {
+        let mut cache = CacheBuilder::new(100)
+            .weigher(|_k: &&str, v: &(&str, u32)| v.1)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.enable_frequency_sketch();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        cache.insert("a", ("alice", 10));
+        cache.insert("b", ("bob", 15));
+        assert_eq!(cache.weighted_size, 25);
+
+        mock.increment(Duration::from_secs(10)); // 10 secs.
+
+        // The expired entries should give their weights back.
+        assert_eq!(cache.get(&"a"), None);
+        assert!(cache.cache.is_empty());
+        assert_eq!(cache.weighted_size, 0);
+
+        cache.insert("c", ("cindy", 5));
+        assert_eq!(cache.weighted_size, 5);
+    }
+
     #[test]
     fn time_to_idle() {
         let mut cache = CacheBuilder::new(100)
@@ -1166,6 +1442,162 @@ mod tests {
         assert!(cache.cache.is_empty());
     }
 
+    #[test]
+    fn with_ttl() {
+        let mut cache = Cache::with_ttl(Duration::from_secs(10));
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        cache.insert("a", "alice");
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.insert("b", "bob");
+
+        assert_eq!(cache.get(&"a"), Some(&"alice"));
+        assert_eq!(cache.cache.len(), 2);
+        // The access-order queue is not used.
+        assert_eq!(cache.deques.probation.len(), 0);
+        assert_eq!(cache.deques.write_order.len(), 2);
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        cache.evict_expired();
+        assert_eq!(cache.cache.len(), 1);
+        assert_eq!(cache.deques.write_order.len(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
+        cache.evict_expired();
+        assert!(cache.cache.is_empty());
+        assert_eq!(cache.deques.write_order.len(), 0);
+    }
+
+    #[test]
+    fn entry_count_and_iter_purge_expired() {
+        use super::EVICTION_BATCH_SIZE;
+
+        let mut cache = Cache::with_ttl(Duration::from_secs(10));
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        let burst = EVICTION_BATCH_SIZE as u32 * 2 + 10;
+        for i in 0..burst {
+            cache.insert(i, i);
+        }
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.insert(burst, burst);
+        assert_eq!(cache.entry_count(), burst as u64 + 1);
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+
+        // Each call removes one batch of the expired entries.
+        assert_eq!(
+            cache.entry_count(),
+            burst as u64 + 1 - EVICTION_BATCH_SIZE as u64
+        );
+        // The iterator removes another batch, and skips the expired entries left.
+        assert_eq!(
+            cache.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            vec![(burst, burst)]
+        );
+        assert_eq!(cache.cache.len(), 11);
+
+        cache.evict_expired();
+        assert_eq!(cache.entry_count(), 1);
+        assert_eq!(cache.weighted_size(), 1);
+        assert_eq!(cache.cache.len(), 1);
+        assert_eq!(cache.deques.write_order.len(), 1);
+
+        cache.invalidate(&burst);
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.weighted_size(), 0);
+    }
+
+    #[test]
+    fn maintenance_budget_and_prepare() {
+        let mut cache = CacheBuilder::new(10_000)
+            .time_to_live(Duration::from_secs(60))
+            .maintenance_budget(50)
+            .build();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        for i in 0..1000 {
+            cache.insert(i, i);
+        }
+
+        // After idling for 10 minutes, all entries have expired. The first insert
+        // only removes as many of them as the budget allows.
+        mock.increment(Duration::from_secs(10 * 60));
+        cache.insert(1000, 1000);
+        assert_eq!(cache.cache.len(), 1001 - 50);
+
+        // The next operations carry on with the rest, one budget at a time.
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.cache.len(), 1001 - 100);
+
+        // Preparing the cache removes the rest at once.
+        cache.prepare();
+        assert_eq!(cache.cache.len(), 1);
+        assert_eq!(cache.entry_count(), 1);
+        assert_eq!(cache.weighted_size(), 1);
+        assert_eq!(cache.get(&1000), Some(&1000));
+    }
+
+    #[test]
+    fn with_tti() {
+        let mut cache = Cache::with_tti(Duration::from_secs(10));
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(cache.get(&"a"), Some(&"alice"));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        cache.evict_expired();
+        assert_eq!(cache.cache.len(), 1);
+        assert_eq!(cache.get(&"a"), Some(&"alice"));
+
+        mock.increment(Duration::from_secs(10)); // 20 secs.
+        cache.evict_expired();
+        assert!(cache.cache.is_empty());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize_values_on_removal() {
+        use zeroize::Zeroize;
+
+        struct Secret<'a>(u32, &'a std::cell::Cell<u32>);
+
+        impl Zeroize for Secret<'_> {
+            fn zeroize(&mut self) {
+                self.0.zeroize();
+                self.1.set(self.1.get() + 1);
+            }
+        }
+
+        let zeroized = std::cell::Cell::new(0);
+        let mut cache = CacheBuilder::new(100).zeroize_values_on_removal().build();
+
+        cache.insert("a", Secret(1, &zeroized));
+        cache.insert("b", Secret(2, &zeroized));
+        assert_eq!(zeroized.get(), 0);
+
+        cache.insert("a", Secret(3, &zeroized));
+        assert_eq!(zeroized.get(), 1);
+
+        cache.invalidate(&"b");
+        assert_eq!(zeroized.get(), 2);
+
+        drop(cache);
+        assert_eq!(zeroized.get(), 3);
+    }
+
     #[cfg_attr(target_pointer_width = "16", ignore)]
     #[test]
     fn test_skt_capacity_will_not_overflow() {
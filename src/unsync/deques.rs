@@ -54,17 +54,19 @@ impl<K> Deques<K> {
 
     pub(crate) fn move_to_back_ao<V>(&mut self, entry: &ValueEntry<K, V>) {
         use CacheRegion::*;
-        let node = entry.access_order_q_node().unwrap();
-        let p = unsafe { node.as_ref() };
-        match &p.region {
-            Window if self.window.contains(p) => unsafe { self.window.move_to_back(node) },
-            MainProbation if self.probation.contains(p) => unsafe {
-                self.probation.move_to_back(node)
-            },
-            MainProtected if self.protected.contains(p) => unsafe {
-                self.protected.move_to_back(node)
-            },
-            _ => {}
+        // The node will be `None` when the access-order queue is not enabled.
+        if let Some(node) = entry.access_order_q_node() {
+            let p = unsafe { node.as_ref() };
+            match &p.region {
+                Window if self.window.contains(p) => unsafe { self.window.move_to_back(node) },
+                MainProbation if self.probation.contains(p) => unsafe {
+                    self.probation.move_to_back(node)
+                },
+                MainProtected if self.protected.contains(p) => unsafe {
+                    self.protected.move_to_back(node)
+                },
+                _ => {}
+            }
         }
     }
 